@@ -1,5 +1,5 @@
-use bustubx::{pretty_format_tuples, Database};
-use clap::Parser;
+use bustubx::{pretty_format_tuples, BustubxError, Database, ReplayReport, SharedDatabase};
+use clap::{Parser, Subcommand};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 
@@ -8,11 +8,38 @@ use rustyline::DefaultEditor;
 struct Args {
     #[clap(short = 'f', long, help = "Path to your database file")]
     file: Option<String>,
+    #[clap(long, help = "Append the statements run to this capture file")]
+    capture: Option<String>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+enum Command {
+    /// Run the statements of a capture file against a database and compare the results
+    Replay {
+        capture: String,
+        target: String,
+        #[clap(
+            long,
+            help = "Wait between statements as long as the capture did, divided by N"
+        )]
+        speed: Option<f64>,
+    },
 }
 
 fn main() {
     env_logger::init();
     let args = Args::parse();
+    if let Some(Command::Replay {
+        capture,
+        target,
+        speed,
+    }) = args.command
+    {
+        replay(&capture, &target, speed);
+        return;
+    }
 
     let mut db = if let Some(path) = args.file {
         Database::new_on_disk(path.as_str())
@@ -20,6 +47,10 @@ fn main() {
     } else {
         Database::new_temp().expect("fail to open temp database")
     };
+    if let Some(path) = args.capture {
+        db.start_capture(&path)
+            .unwrap_or_else(|e| panic!("fail to capture to {} file, err: {}", path, e));
+    }
 
     println!(":) Welcome to the bustubx, please input sql.");
     let mut rl = DefaultEditor::new().expect("created editor");
@@ -34,14 +65,15 @@ fn main() {
                     println!("bye!");
                     break;
                 }
-                let result = db.run(&line);
-                match result {
-                    Ok(tuples) => {
-                        if !tuples.is_empty() {
-                            println!("{}", pretty_format_tuples(&tuples))
+                for outcome in db.execute_script(&line) {
+                    match outcome.result {
+                        Ok(tuples) => {
+                            if !tuples.is_empty() {
+                                println!("{}", pretty_format_tuples(&tuples))
+                            }
                         }
+                        Err(e) => print_error(&line, &e),
                     }
-                    Err(e) => println!("{}", e),
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -58,7 +90,54 @@ fn main() {
             }
         }
     }
-    db.flush().unwrap();
+    db.close().unwrap();
 
     rl.save_history(".history").ok();
 }
+
+// Parser errors point at the offending token of the input line with a caret
+fn print_error(input: &str, e: &BustubxError) {
+    println!("{}", e);
+    if let BustubxError::ParserAt { line, column, .. } = e {
+        if let Some(source_line) = input.lines().nth(line - 1) {
+            println!("{}", source_line);
+            println!("{:>width$}", "^", width = column);
+        }
+    }
+}
+
+fn replay(capture: &str, target: &str, speed: Option<f64>) {
+    let db = Database::new_on_disk(target)
+        .unwrap_or_else(|e| panic!("fail to open {} file, err: {}", target, e));
+    let db = SharedDatabase::new(db);
+    let report = db
+        .replay(capture, speed)
+        .unwrap_or_else(|e| panic!("fail to replay {} file, err: {}", capture, e));
+    print_report(&report);
+    db.close().unwrap();
+}
+
+// One line per statement with the rows and time of the capture and of the replay, the
+// statements whose rows differ are marked
+fn print_report(report: &ReplayReport) {
+    let rows = |rows: Option<usize>| rows.map_or("error".to_string(), |rows| rows.to_string());
+    for statement in &report.statements {
+        println!(
+            "{} session {:>3} rows {:>6} / {:<6} time {:>10.3?} / {:<10.3?} {}",
+            if statement.rows_match() { " " } else { "!" },
+            statement.captured.session,
+            rows(statement.captured.rows),
+            rows(statement.rows),
+            statement.captured.elapsed(),
+            statement.elapsed,
+            statement.captured.sql
+        );
+    }
+    println!(
+        "{} statements, {} with different rows, captured {:.3?}, replayed {:.3?}",
+        report.statements.len(),
+        report.mismatches().count(),
+        report.captured_elapsed(),
+        report.replayed_elapsed()
+    );
+}
@@ -0,0 +1,54 @@
+//! Filter query over a 100k row table in row and in batch execution.
+//!
+//! ```text
+//! cargo bench -p bustubx --bench batch_filter
+//! ```
+
+use std::time::{Duration, Instant};
+
+use bustubx::{Database, Tuple};
+
+const ROWS: usize = 100_000;
+const RUNS: usize = 5;
+const QUERY: &str = "select a, c from t1 where a >= 1000 and b < 50";
+
+fn run(db: &mut Database) -> (Duration, Vec<Tuple>) {
+    let mut best = Duration::MAX;
+    let mut rows = vec![];
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        rows = db.run(QUERY).unwrap();
+        best = best.min(start.elapsed());
+    }
+    (best, rows)
+}
+
+fn main() {
+    let mut db = Database::new_temp().unwrap();
+    db.run("create table t1 (a int, b int, c bigint, d varchar(32))")
+        .unwrap();
+    for start in (0..ROWS).step_by(1000) {
+        let values = (start..start + 1000)
+            .map(|i| format!("({i}, {}, {}, 'padding {i}')", i % 100, i * 7))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+    }
+    // the plan cache keeps both modes on the same plan
+    db.run(QUERY).unwrap();
+
+    db.set_batch_execution(false, 1024).unwrap();
+    let (row_time, row_rows) = run(&mut db);
+    db.set_batch_execution(true, 1024).unwrap();
+    let (batch_time, batch_rows) = run(&mut db);
+
+    assert_eq!(row_rows, batch_rows, "batch execution changed the result");
+    println!("{QUERY}");
+    println!("  rows returned      {}", row_rows.len());
+    println!("  row execution      {row_time:?}");
+    println!("  batch execution    {batch_time:?}");
+    println!(
+        "  speedup            {:.2}x",
+        row_time.as_secs_f64() / batch_time.as_secs_f64()
+    );
+}
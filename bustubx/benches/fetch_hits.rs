@@ -0,0 +1,57 @@
+//! Fetches of resident pages from 1 to 8 threads. A hit pins its frame with an atomic
+//! and only locks the replacer shard of the frame, so throughput should grow about
+//! linearly with the threads as long as there are cores for them.
+//!
+//! ```text
+//! cargo bench -p bustubx --bench fetch_hits
+//! ```
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bustubx::{BufferPoolManager, DiskManager};
+
+const PAGES: usize = 1000;
+const FETCHES_PER_THREAD: usize = 1_000_000;
+
+fn run(buffer_pool: &Arc<BufferPoolManager>, page_ids: &Arc<Vec<u32>>, threads: usize) -> Duration {
+    let start = Instant::now();
+    let workers = (0..threads)
+        .map(|t| {
+            let buffer_pool = buffer_pool.clone();
+            let page_ids = page_ids.clone();
+            thread::spawn(move || {
+                for i in 0..FETCHES_PER_THREAD {
+                    let page_id = page_ids[(i * 7 + t * 131) % page_ids.len()];
+                    drop(buffer_pool.fetch_page(page_id).unwrap());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let disk_manager = DiskManager::try_new(temp_dir.path().join("bench.db")).unwrap();
+    let buffer_pool = Arc::new(BufferPoolManager::new(PAGES, Arc::new(disk_manager)));
+    let page_ids = Arc::new(
+        (0..PAGES)
+            .map(|_| buffer_pool.new_page().unwrap().read().unwrap().page_id)
+            .collect::<Vec<_>>(),
+    );
+
+    println!("{FETCHES_PER_THREAD} fetch hits per thread over {PAGES} resident pages");
+    let mut single = None;
+    for threads in [1, 2, 4, 8] {
+        let elapsed = run(&buffer_pool, &page_ids, threads);
+        let rate = (threads * FETCHES_PER_THREAD) as f64 / elapsed.as_secs_f64();
+        let scaling = rate / *single.get_or_insert(rate);
+        println!("  {threads} threads  {rate:>12.0} fetches/s  {scaling:.1}x");
+    }
+    assert_eq!(buffer_pool.stats().misses, 0);
+}
@@ -0,0 +1,43 @@
+//! Inserts and deletes of narrow rows, many of them on every heap page. Both patch the
+//! page in place instead of decoding and encoding it again.
+//!
+//! ```text
+//! cargo bench -p bustubx --bench heap_writes
+//! ```
+
+use std::time::{Duration, Instant};
+
+use bustubx::Database;
+
+const ROWS: usize = 100_000;
+const ROWS_PER_INSERT: usize = 1000;
+
+fn throughput(rows: usize, elapsed: Duration) -> String {
+    format!("{:.0} rows/s", rows as f64 / elapsed.as_secs_f64())
+}
+
+fn main() {
+    let mut db = Database::new_temp().unwrap();
+    db.run("create table t1 (a int, b smallint)").unwrap();
+
+    let start = Instant::now();
+    for chunk_start in (0..ROWS).step_by(ROWS_PER_INSERT) {
+        let values = (chunk_start..chunk_start + ROWS_PER_INSERT)
+            .map(|i| format!("({i}, {})", i % 100))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+    }
+    let insert_time = start.elapsed();
+
+    // every deleted row updates the meta of its slot
+    let start = Instant::now();
+    db.run("delete from t1 where b < 50").unwrap();
+    let delete_time = start.elapsed();
+    let remaining = db.run("select a from t1").unwrap().len();
+    assert_eq!(remaining, ROWS / 2);
+
+    println!("{ROWS} rows of (int, smallint)");
+    println!("  insert             {}", throughput(ROWS, insert_time));
+    println!("  delete             {}", throughput(ROWS / 2, delete_time));
+}
@@ -0,0 +1,42 @@
+//! CREATE INDEX on a filled table, by every number of workers sorting the heap pages.
+//! Zero workers builds on the calling thread.
+//!
+//! ```text
+//! cargo bench -p bustubx --bench index_build
+//! ```
+
+use std::time::{Duration, Instant};
+
+use bustubx::Database;
+
+const ROWS: usize = 100_000;
+const ROWS_PER_INSERT: usize = 1000;
+const WORKERS: [usize; 4] = [0, 2, 4, 8];
+
+fn throughput(rows: usize, elapsed: Duration) -> String {
+    format!("{:.0} rows/s", rows as f64 / elapsed.as_secs_f64())
+}
+
+fn main() {
+    let mut db = Database::new_temp().unwrap();
+    db.run("create table t1 (a int, b int)").unwrap();
+    for chunk_start in (0..ROWS).step_by(ROWS_PER_INSERT) {
+        let values = (chunk_start..chunk_start + ROWS_PER_INSERT)
+            .map(|i| format!("({i}, {})", (i * 7919) % ROWS))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+    }
+    db.run("set parallel_scan_min_pages = 1").unwrap();
+
+    println!("{ROWS} rows of (int, int), index on the unordered column");
+    for workers in WORKERS {
+        db.run(&format!("set parallel_scan_workers = {workers}"))
+            .unwrap();
+        let start = Instant::now();
+        db.run(&format!("create index t1_b_{workers} on t1 (b)"))
+            .unwrap();
+        let elapsed = start.elapsed();
+        println!("  {workers} workers          {}", throughput(ROWS, elapsed));
+    }
+}
@@ -0,0 +1,398 @@
+//! Threads a database runs beside its statements, e.g. the buffer pool flusher and auto
+//! analyze. They are spawned by the [`BackgroundTaskRegistry`] of the database, which stops
+//! and joins them before the buffer pool and the db file they hold go away, and keeps the
+//! panic of a task for [`crate::Database::background_tasks`] instead of losing it with the
+//! thread.
+
+use log::warn;
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BustubxError, BustubxResult};
+
+/// How long closing a database waits for its background tasks to stop.
+pub const DEFAULT_TASK_JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub type TaskId = u64;
+
+/// Set when the task should stop. A task waits on it between rounds of work and checks it
+/// in long ones.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownToken {
+    pub fn is_shutdown(&self) -> bool {
+        *self.stop.0.lock().unwrap()
+    }
+
+    /// Wait `timeout` or until shut down, whichever comes first. Returns whether shut down.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (stopped, signal) = &*self.stop;
+        *signal
+            .wait_timeout_while(stopped.lock().unwrap(), timeout, |stopped| !*stopped)
+            .unwrap()
+            .0
+    }
+
+    fn shut_down(&self) {
+        let (stopped, signal) = &*self.stop;
+        *stopped.lock().unwrap() = true;
+        signal.notify_all();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskHealth {
+    Running,
+    Finished,
+    // the panic message of the task
+    Panicked(String),
+}
+
+impl Display for TaskHealth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskHealth::Running => write!(f, "running"),
+            TaskHealth::Finished => write!(f, "finished"),
+            TaskHealth::Panicked(message) => write!(f, "panicked: {message}"),
+        }
+    }
+}
+
+/// A background task as listed by [`BackgroundTaskRegistry::health`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskStatus {
+    pub id: TaskId,
+    pub name: String,
+    pub health: TaskHealth,
+}
+
+#[derive(Debug)]
+struct Task {
+    id: TaskId,
+    name: String,
+    token: ShutdownToken,
+    // health of the task, signaled once it is no longer running
+    health: Arc<(Mutex<TaskHealth>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Task {
+    fn health(&self) -> TaskHealth {
+        self.health.0.lock().unwrap().clone()
+    }
+
+    // whether the task stopped running before `deadline`
+    fn wait_until(&self, deadline: Option<Instant>) -> bool {
+        let (health, signal) = &*self.health;
+        let mut guard = health.lock().unwrap();
+        while *guard == TaskHealth::Running {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    guard = signal.wait_timeout(guard, deadline - now).unwrap().0;
+                }
+                None => guard = signal.wait(guard).unwrap(),
+            }
+        }
+        true
+    }
+
+    fn join(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            // the panic is caught in the thread, the join cannot fail
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Spawns and tracks the background tasks of a database. [`Self::shutdown`] shuts down the
+/// token of every task and joins them, a task still running after the join timeout is left
+/// behind and reported as an error.
+#[derive(Debug)]
+pub struct BackgroundTaskRegistry {
+    tasks: Vec<Task>,
+    next_id: TaskId,
+    join_timeout: Duration,
+    shut_down: bool,
+}
+
+impl Default for BackgroundTaskRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_TASK_JOIN_TIMEOUT)
+    }
+}
+
+impl BackgroundTaskRegistry {
+    pub fn new(join_timeout: Duration) -> Self {
+        Self {
+            tasks: vec![],
+            next_id: 0,
+            join_timeout,
+            shut_down: false,
+        }
+    }
+
+    /// Run `task` on a thread of its own. It is given the token it stops on.
+    pub fn spawn(
+        &mut self,
+        name: impl Into<String>,
+        task: impl FnOnce(ShutdownToken) + Send + 'static,
+    ) -> BustubxResult<TaskId> {
+        let name = name.into();
+        if self.shut_down {
+            return Err(BustubxError::Internal(format!(
+                "cannot start background task {name}, the background tasks are shut down"
+            )));
+        }
+        // finished tasks are forgotten, panicked ones stay listed
+        for task in self.tasks.iter_mut() {
+            if task.health() == TaskHealth::Finished {
+                task.join();
+            }
+        }
+        self.tasks
+            .retain(|task| task.health() != TaskHealth::Finished);
+
+        let token = ShutdownToken::default();
+        let health = Arc::new((Mutex::new(TaskHealth::Running), Condvar::new()));
+        let thread_token = token.clone();
+        let thread_health = health.clone();
+        let thread_name = name.clone();
+        let handle = thread::Builder::new()
+            .name(format!("bustubx-{name}"))
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| task(thread_token)));
+                let outcome = match result {
+                    Ok(()) => TaskHealth::Finished,
+                    Err(payload) => {
+                        let message = panic_message(payload.as_ref());
+                        warn!("background task {} panicked: {}", thread_name, message);
+                        TaskHealth::Panicked(message)
+                    }
+                };
+                let (health, signal) = &*thread_health;
+                *health.lock().unwrap() = outcome;
+                signal.notify_all();
+            })?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id,
+            name,
+            token,
+            health,
+            handle: Some(handle),
+        });
+        Ok(id)
+    }
+
+    /// Wait for task `id` to finish on its own, its token is not shut down.
+    pub fn join(&mut self, id: TaskId) -> TaskHealth {
+        let Some(position) = self.tasks.iter().position(|task| task.id == id) else {
+            return TaskHealth::Finished;
+        };
+        self.tasks[position].wait_until(None);
+        self.remove(position)
+    }
+
+    /// Shut down the token of task `id` and join it, see [`Self::shutdown`].
+    pub fn stop(&mut self, id: TaskId) -> BustubxResult<TaskHealth> {
+        let Some(position) = self.tasks.iter().position(|task| task.id == id) else {
+            return Ok(TaskHealth::Finished);
+        };
+        let task = &self.tasks[position];
+        task.token.shut_down();
+        if !task.wait_until(Some(Instant::now() + self.join_timeout)) {
+            let task = self.tasks.remove(position);
+            return Err(self.abandoned(vec![task.name]));
+        }
+        Ok(self.remove(position))
+    }
+
+    // a stopped task, joined. A panicked one stays listed
+    fn remove(&mut self, position: usize) -> TaskHealth {
+        self.tasks[position].join();
+        let health = self.tasks[position].health();
+        if !matches!(health, TaskHealth::Panicked(_)) {
+            self.tasks.remove(position);
+        }
+        health
+    }
+
+    /// Whether task `id` is running or its end was not seen yet.
+    pub fn contains(&self, id: TaskId) -> bool {
+        self.tasks.iter().any(|task| task.id == id)
+    }
+
+    /// Every task not joined yet, a panicked one is listed until the registry shuts down.
+    pub fn health(&self) -> Vec<TaskStatus> {
+        self.tasks
+            .iter()
+            .map(|task| TaskStatus {
+                id: task.id,
+                name: task.name.clone(),
+                health: task.health(),
+            })
+            .collect()
+    }
+
+    /// Shut down the token of every task and join them, no task is started after. The ones
+    /// still running after the join timeout are left running without their handle and
+    /// named in the error, they hold on to what they share with the database.
+    pub fn shutdown(&mut self) -> BustubxResult<()> {
+        self.shut_down = true;
+        for task in self.tasks.iter() {
+            task.token.shut_down();
+        }
+        let deadline = Instant::now() + self.join_timeout;
+        let mut abandoned = vec![];
+        for mut task in self.tasks.drain(..) {
+            if task.wait_until(Some(deadline)) {
+                task.join();
+            } else {
+                abandoned.push(task.name);
+            }
+        }
+        if abandoned.is_empty() {
+            Ok(())
+        } else {
+            Err(self.abandoned(abandoned))
+        }
+    }
+
+    fn abandoned(&self, names: Vec<String>) -> BustubxError {
+        BustubxError::Internal(format!(
+            "background tasks {} did not stop within {:?}, they were abandoned",
+            names.join(", "),
+            self.join_timeout
+        ))
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{BackgroundTaskRegistry, TaskHealth};
+    use crate::BustubxError;
+
+    #[test]
+    pub fn test_tasks_exit_on_shutdown() {
+        let mut registry = BackgroundTaskRegistry::new(Duration::from_secs(5));
+        let rounds = Arc::new(AtomicUsize::new(0));
+        for i in 0..4 {
+            let rounds = rounds.clone();
+            registry
+                .spawn(format!("worker-{i}"), move |token| {
+                    while !token.wait_timeout(Duration::from_millis(1)) {
+                        rounds.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+                .unwrap();
+        }
+        // a task done on its own is forgotten by the next spawn
+        let once = registry.spawn("once", |_| {}).unwrap();
+        assert_eq!(registry.join(once), TaskHealth::Finished);
+        assert!(registry
+            .health()
+            .iter()
+            .all(|task| task.health == TaskHealth::Running));
+
+        let started = Instant::now();
+        registry.shutdown().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(registry.health().is_empty());
+        let after = rounds.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(rounds.load(Ordering::Relaxed), after);
+        assert!(matches!(
+            registry.spawn("late", |_| {}),
+            Err(BustubxError::Internal(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_panicking_task_is_unhealthy() {
+        let mut registry = BackgroundTaskRegistry::default();
+        let running = registry
+            .spawn("steady", |token| {
+                while !token.wait_timeout(Duration::from_millis(1)) {}
+            })
+            .unwrap();
+        let panicking = registry
+            .spawn("faulty", |_| panic!("page 7 is gone"))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let health = registry.health();
+            let faulty = health.iter().find(|task| task.id == panicking).unwrap();
+            if faulty.health != TaskHealth::Running {
+                assert_eq!(
+                    faulty.health,
+                    TaskHealth::Panicked("page 7 is gone".to_string())
+                );
+                break;
+            }
+            assert!(Instant::now() < deadline, "the task did not panic");
+            thread::sleep(Duration::from_millis(1));
+        }
+        // the panic stays listed beside the healthy task
+        registry.spawn("other", |_| {}).unwrap();
+        let health = registry.health();
+        assert!(health.iter().any(|task| task.id == panicking));
+        assert_eq!(
+            health
+                .iter()
+                .find(|task| task.id == running)
+                .unwrap()
+                .health,
+            TaskHealth::Running
+        );
+        registry.shutdown().unwrap();
+    }
+
+    #[test]
+    pub fn test_shutdown_abandons_stuck_task() {
+        let mut registry = BackgroundTaskRegistry::new(Duration::from_millis(20));
+        registry
+            .spawn("stuck", |_| thread::sleep(Duration::from_millis(500)))
+            .unwrap();
+        registry
+            .spawn("steady", |token| {
+                while !token.wait_timeout(Duration::from_millis(1)) {}
+            })
+            .unwrap();
+        let started = Instant::now();
+        let err = registry.shutdown().unwrap_err();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        assert!(
+            matches!(&err, BustubxError::Internal(message) if message.contains("stuck") && !message.contains("steady"))
+        );
+    }
+}
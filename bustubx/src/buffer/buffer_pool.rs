@@ -1,59 +1,280 @@
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::sync::RwLock;
+use log::warn;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 use std::{collections::VecDeque, sync::Arc};
 
 use crate::buffer::page::{Page, PageId};
 
-use crate::buffer::PageRef;
+use crate::buffer::{PageRef, PinTracker};
 use crate::catalog::SchemaRef;
+use crate::config::BufferPoolOptions;
 use crate::storage::codec::{
-    BPlusTreeInternalPageCodec, BPlusTreeLeafPageCodec, BPlusTreePageCodec, TablePageCodec,
+    BPlusTreeInternalPageCodec, BPlusTreeLeafPageCodec, BPlusTreePageCodec, PageCodec,
+    TablePageCodec,
 };
 use crate::storage::{
     BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage, DiskManager, TablePage,
 };
 use crate::{BustubxError, BustubxResult};
 
-use super::replacer::LRUKReplacer;
+use super::replacer::ShardedReplacer;
 
 pub type FrameId = usize;
 
 pub const BUFFER_POOL_SIZE: usize = 1000;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Unknown,
+    // Scans touching each page once (e.g. index dumps), they should not evict the working set
+    Sequential,
+}
+
+/// How a traced page access found its page, see [`BufferPoolManager::start_trace`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageAccessKind {
+    // the page was resident
+    Hit,
+    // the page was read from disk
+    Miss,
+    // the page was allocated
+    New,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageAccess {
+    pub page_id: PageId,
+    pub kind: PageAccessKind,
+}
+
+/// State of one frame, see [`BufferPoolManager::frames`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInfo {
+    pub frame_id: FrameId,
+    // None for a free frame
+    pub page_id: Option<PageId>,
+    pub pin_count: u32,
+    pub is_dirty: bool,
+    // logical time of the latest access counted by the replacer
+    pub last_access: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    // fetches served by a resident frame
+    pub hits: u64,
+    // fetches that read the page from disk
+    pub misses: u64,
+    // frames taken from another page, see `evictions` of `BufferPoolManager`
+    pub evictions: u64,
+    // dirty pages written back to disk, on eviction or by a flush
+    pub dirty_writes: u64,
+    // evictions that wrote their dirty page back before the frame could be reused
+    pub dirty_evictions: u64,
+    // evictions that took a clean page instead of the dirty LRU-K victim
+    pub dirty_evictions_avoided: u64,
+    // pin count overflows and underflows detected, each one is a bug of a page user
+    pub pin_anomalies: u64,
+}
+
+impl BufferPoolStats {
+    pub fn fetches(&self) -> u64 {
+        self.hits + self.misses
+    }
+}
+
+/// Outcome of [`BufferPoolManager::flush_all_pages`], both lists in page id order.
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    pub flushed: Vec<PageId>,
+    pub failed: Vec<(PageId, BustubxError)>,
+}
+
+impl FlushReport {
+    /// The error of the first page that could not be written, for callers that cannot go
+    /// on after a partial flush. The other failures are only logged.
+    pub fn into_result(self) -> BustubxResult<()> {
+        let mut failed = self.failed.into_iter();
+        let Some((_, first)) = failed.next() else {
+            return Ok(());
+        };
+        for (page_id, e) in failed {
+            warn!("failed to flush page {}: {}", page_id, e);
+        }
+        Err(first)
+    }
+}
+
 #[derive(Debug)]
 pub struct BufferPoolManager {
     pool: Vec<Arc<RwLock<Page>>>,
-    // LRU-K replacement algorithm
-    pub replacer: Arc<RwLock<LRUKReplacer>>,
+    // LRU-K replacement algorithm, also keeps the pin counts of the frames
+    pub replacer: Arc<ShardedReplacer>,
     pub disk_manager: Arc<DiskManager>,
     // Mapping between page IDs and frame IDs in the buffer pool
     page_table: Arc<DashMap<PageId, FrameId>>,
     // Free frames in the buffer pool
     free_list: Arc<RwLock<VecDeque<FrameId>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    dirty_writes: AtomicU64,
+    dirty_evictions: AtomicU64,
+    dirty_evictions_avoided: AtomicU64,
+    // see `BufferPoolOptions::clean_eviction_window`
+    clean_eviction_window: usize,
+    // page accesses in order while `tracing` is set
+    tracing: AtomicBool,
+    trace: Mutex<Vec<PageAccess>>,
+    // pins of the frames per thread, signalled whenever a page is unpinned
+    pins: Arc<PinTracker>,
+    frame_wait_timeout: Duration,
+    #[cfg(feature = "debug-history")]
+    history: Arc<super::PageHistory>,
 }
 impl BufferPoolManager {
     pub fn new(num_pages: usize, disk_manager: Arc<DiskManager>) -> Self {
+        Self::new_with_options(
+            BufferPoolOptions {
+                pool_size: num_pages,
+                ..Default::default()
+            },
+            disk_manager,
+        )
+    }
+
+    pub fn new_with_options(options: BufferPoolOptions, disk_manager: Arc<DiskManager>) -> Self {
+        let num_pages = options.pool_size;
         let mut free_list = VecDeque::with_capacity(num_pages);
         let mut pool = vec![];
+        #[cfg(feature = "debug-history")]
+        let history = Arc::new(super::PageHistory::new(
+            super::history::PAGE_HISTORY_CAPACITY,
+        ));
         for i in 0..num_pages {
             free_list.push_back(i);
-            pool.push(Arc::new(RwLock::new(Page::empty())));
+            #[allow(unused_mut)]
+            let mut page = Page::empty();
+            #[cfg(feature = "debug-history")]
+            page.set_history(history.clone());
+            pool.push(Arc::new(RwLock::new(page)));
         }
 
         Self {
             pool,
-            replacer: Arc::new(RwLock::new(LRUKReplacer::new(num_pages, 2))),
+            replacer: Arc::new(ShardedReplacer::new(num_pages, options.replacer_k)),
             disk_manager,
             page_table: Arc::new(DashMap::new()),
             free_list: Arc::new(RwLock::new(free_list)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            dirty_writes: AtomicU64::new(0),
+            dirty_evictions: AtomicU64::new(0),
+            dirty_evictions_avoided: AtomicU64::new(0),
+            clean_eviction_window: options.clean_eviction_window,
+            tracing: AtomicBool::new(false),
+            trace: Mutex::new(vec![]),
+            pins: Arc::new(PinTracker::default()),
+            frame_wait_timeout: options.frame_wait_timeout,
+            #[cfg(feature = "debug-history")]
+            history,
+        }
+    }
+
+    /// Page counters since the buffer pool was created or [`Self::reset_stats`]
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            dirty_writes: self.dirty_writes.load(Ordering::Relaxed),
+            dirty_evictions: self.dirty_evictions.load(Ordering::Relaxed),
+            dirty_evictions_avoided: self.dirty_evictions_avoided.load(Ordering::Relaxed),
+            pin_anomalies: self.pins.anomalies(),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        for counter in [
+            &self.hits,
+            &self.misses,
+            &self.evictions,
+            &self.dirty_writes,
+            &self.dirty_evictions,
+            &self.dirty_evictions_avoided,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.pins.reset_anomalies();
+    }
+
+    /// Record every page fetch and allocation from now on until [`Self::take_trace`],
+    /// restarting a trace already running. The accesses of every thread are recorded,
+    /// the workers of a parallel scan included.
+    pub fn start_trace(&self) {
+        self.trace.lock().unwrap().clear();
+        self.tracing.store(true, Ordering::SeqCst);
+    }
+
+    /// Stop recording and return the accesses since [`Self::start_trace`] in order.
+    pub fn take_trace(&self) -> Vec<PageAccess> {
+        self.tracing.store(false, Ordering::SeqCst);
+        std::mem::take(&mut *self.trace.lock().unwrap())
+    }
+
+    fn trace_access(&self, page_id: PageId, kind: PageAccessKind) {
+        if self.tracing.load(Ordering::Relaxed) {
+            self.trace
+                .lock()
+                .unwrap()
+                .push(PageAccess { page_id, kind });
+        }
+    }
+
+    /// Snapshot of every frame. No lock is held across frames, each frame is read on its
+    /// own so the result may mix states of slightly different moments.
+    pub fn frames(&self) -> Vec<FrameInfo> {
+        let mut page_ids = vec![None; self.pool.len()];
+        for entry in self.page_table.iter() {
+            page_ids[*entry.value()] = Some(*entry.key());
         }
+        page_ids
+            .into_iter()
+            .enumerate()
+            .map(|(frame_id, page_id)| {
+                let (pin_count, is_dirty) = match page_id {
+                    Some(_) => (
+                        self.replacer.pin_count(frame_id),
+                        self.pool[frame_id].read().unwrap().is_dirty,
+                    ),
+                    None => (0, false),
+                };
+                FrameInfo {
+                    frame_id,
+                    page_id,
+                    pin_count,
+                    is_dirty,
+                    last_access: self.replacer.last_access(frame_id),
+                }
+            })
+            .collect()
+    }
+
+    /// Recent writes of the page in this buffer pool, oldest first
+    #[cfg(feature = "debug-history")]
+    pub fn page_history(&self, page_id: PageId) -> Vec<super::PageWriteRecord> {
+        self.history.page_history(page_id)
     }
 
     // Create a new page in the buffer pool
     pub fn new_page(&self) -> BustubxResult<PageRef> {
         // Buffer pool is full and no page can be replaced
-        if self.free_list.read().unwrap().is_empty() && self.replacer.read().unwrap().size() == 0 {
-            return Err(BustubxError::Storage(
+        if self.free_list.read().unwrap().is_empty() && self.replacer.size() == 0 {
+            return Err(BustubxError::BufferPoolFull(
                 "Cannot new page because buffer pool is full and no page to evict".to_string(),
             ));
         }
@@ -61,71 +282,144 @@ impl BufferPoolManager {
         // Allocate a frame
         let frame_id = self.allocate_frame()?;
 
-        // Allocate a page from disk
-        let new_page_id = self.disk_manager.allocate_page().unwrap();
+        // Allocate a page from disk, the frame goes back to the free list if the disk is full
+        let new_page_id = match self.disk_manager.allocate_page() {
+            Ok(page_id) => page_id,
+            Err(e) => {
+                self.free_frame(frame_id);
+                return Err(e);
+            }
+        };
         self.page_table.insert(new_page_id, frame_id);
-        let new_page = Page::new(new_page_id).with_pin_count(1u32);
-        self.pool[frame_id].write().unwrap().replace(new_page);
-
-        self.replacer.write().unwrap().record_access(frame_id)?;
-        self.replacer
+        self.trace_access(new_page_id, PageAccessKind::New);
+        self.pool[frame_id]
             .write()
             .unwrap()
-            .set_evictable(frame_id, false)?;
+            .replace(Page::new(new_page_id));
+
+        self.replacer.record_access(frame_id, AccessType::Unknown)?;
+        self.replacer.release(frame_id, 1);
 
-        Ok(PageRef {
-            page: self.pool[frame_id].clone(),
-            page_table: self.page_table.clone(),
-            replacer: self.replacer.clone(),
-        })
+        Ok(self.page_ref(frame_id))
+    }
+
+    /// [`Self::new_page`] that waits up to `timeout` for another thread to unpin a frame
+    /// while every frame is pinned, instead of failing right away.
+    pub fn new_page_blocking(&self, timeout: Duration) -> BustubxResult<PageRef> {
+        self.wait_for_frame(timeout, || self.new_page())
     }
 
     pub fn fetch_page(&self, page_id: PageId) -> BustubxResult<PageRef> {
-        if let Some(frame_id) = self.page_table.get(&page_id) {
-            let page = self.pool[*frame_id].clone();
-            page.write().unwrap().pin_count += 1;
-            self.replacer
-                .write()
-                .unwrap()
-                .set_evictable(*frame_id, false)?;
-            Ok(PageRef {
-                page,
-                page_table: self.page_table.clone(),
-                replacer: self.replacer.clone(),
-            })
-        } else {
+        self.fetch_page_with_access_type(page_id, AccessType::Unknown)
+    }
+
+    /// [`Self::fetch_page`] that waits for a frame like [`Self::new_page_blocking`].
+    pub fn fetch_page_blocking(
+        &self,
+        page_id: PageId,
+        timeout: Duration,
+    ) -> BustubxResult<PageRef> {
+        self.wait_for_frame(timeout, || self.fetch_page(page_id))
+    }
+
+    pub fn fetch_page_with_access_type(
+        &self,
+        page_id: PageId,
+        access_type: AccessType,
+    ) -> BustubxResult<PageRef> {
+        loop {
+            if let Some(mapped) = self.page_table.get(&page_id) {
+                if self.pins.is_poisoned(*mapped) {
+                    return Err(BustubxError::Internal(format!(
+                        "page {} is in frame {} poisoned by a pin count underflow",
+                        page_id, *mapped
+                    )));
+                }
+                // pinned while the page table entry is held, the frame cannot be given to
+                // another page meanwhile
+                let frame_id = match self.replacer.pin(*mapped) {
+                    Ok(true) => *mapped,
+                    // being evicted or loaded, look the page up again
+                    Ok(false) => {
+                        drop(mapped);
+                        std::thread::yield_now();
+                        continue;
+                    }
+                    Err(_) => {
+                        self.pins.record_anomaly();
+                        return Err(BustubxError::Internal(format!(
+                            "pin count of page {} overflows",
+                            page_id
+                        )));
+                    }
+                };
+                drop(mapped);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                self.trace_access(page_id, PageAccessKind::Hit);
+                let page = self.page_ref(frame_id);
+                self.replacer.record_access(frame_id, access_type)?;
+                return Ok(page);
+            }
+
             // Allocate a frame
             let frame_id = self.allocate_frame()?;
 
-            // Read page from disk
-            self.page_table.insert(page_id, frame_id);
-            let new_page = Page::new(page_id)
-                .with_pin_count(1u32)
-                .with_data(self.disk_manager.read_page(page_id)?);
-            self.pool[frame_id].write().unwrap().replace(new_page);
+            // Claim the page, fetches of the page wait until the frame is released. Another
+            // thread may have loaded it since the lookup, then that frame is used instead.
+            let claimed = match self.page_table.entry(page_id) {
+                Entry::Occupied(_) => false,
+                Entry::Vacant(entry) => {
+                    entry.insert(frame_id);
+                    true
+                }
+            };
+            // the entry is released first, evictions lock the free list before the page table
+            if !claimed {
+                self.free_frame(frame_id);
+                continue;
+            }
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            self.trace_access(page_id, PageAccessKind::Miss);
 
-            self.replacer.write().unwrap().record_access(frame_id)?;
-            self.replacer
+            // Read page from disk
+            let data = match self.disk_manager.read_page(page_id) {
+                Ok(data) => data,
+                Err(e) => {
+                    self.page_table
+                        .remove_if(&page_id, |_, mapped| *mapped == frame_id);
+                    self.free_frame(frame_id);
+                    return Err(e);
+                }
+            };
+            self.pool[frame_id]
                 .write()
                 .unwrap()
-                .set_evictable(frame_id, false)?;
+                .replace(Page::new(page_id).with_data(data));
 
-            Ok(PageRef {
-                page: self.pool[frame_id].clone(),
-                page_table: self.page_table.clone(),
-                replacer: self.replacer.clone(),
-            })
+            self.replacer.record_access(frame_id, access_type)?;
+            self.replacer.release(frame_id, 1);
+
+            return Ok(self.page_ref(frame_id));
         }
     }
 
+    /// Fetch the page and decode it with the codec `C`.
+    pub fn fetch_decoded_page<C: PageCodec>(
+        &self,
+        page_id: PageId,
+        context: C::Context,
+    ) -> BustubxResult<(PageRef, C::Page)> {
+        let page = self.fetch_page(page_id)?;
+        let (decoded, _) = C::decode(page.read().unwrap().data(), context)?;
+        Ok((page, decoded))
+    }
+
     pub fn fetch_table_page(
         &self,
         page_id: PageId,
         schema: SchemaRef,
     ) -> BustubxResult<(PageRef, TablePage)> {
-        let page = self.fetch_page(page_id)?;
-        let (table_page, _) = TablePageCodec::decode(page.read().unwrap().data(), schema.clone())?;
-        Ok((page, table_page))
+        self.fetch_decoded_page::<TablePageCodec>(page_id, schema)
     }
 
     pub fn fetch_tree_page(
@@ -133,10 +427,7 @@ impl BufferPoolManager {
         page_id: PageId,
         key_schema: SchemaRef,
     ) -> BustubxResult<(PageRef, BPlusTreePage)> {
-        let page = self.fetch_page(page_id)?;
-        let (tree_page, _) =
-            BPlusTreePageCodec::decode(page.read().unwrap().data(), key_schema.clone())?;
-        Ok((page, tree_page))
+        self.fetch_decoded_page::<BPlusTreePageCodec>(page_id, key_schema)
     }
 
     pub fn fetch_tree_internal_page(
@@ -144,10 +435,7 @@ impl BufferPoolManager {
         page_id: PageId,
         key_schema: SchemaRef,
     ) -> BustubxResult<(PageRef, BPlusTreeInternalPage)> {
-        let page = self.fetch_page(page_id)?;
-        let (tree_internal_page, _) =
-            BPlusTreeInternalPageCodec::decode(page.read().unwrap().data(), key_schema.clone())?;
-        Ok((page, tree_internal_page))
+        self.fetch_decoded_page::<BPlusTreeInternalPageCodec>(page_id, key_schema)
     }
 
     pub fn fetch_tree_leaf_page(
@@ -155,10 +443,7 @@ impl BufferPoolManager {
         page_id: PageId,
         key_schema: SchemaRef,
     ) -> BustubxResult<(PageRef, BPlusTreeLeafPage)> {
-        let page = self.fetch_page(page_id)?;
-        let (tree_leaf_page, _) =
-            BPlusTreeLeafPageCodec::decode(page.read().unwrap().data(), key_schema.clone())?;
-        Ok((page, tree_leaf_page))
+        self.fetch_decoded_page::<BPlusTreeLeafPageCodec>(page_id, key_schema)
     }
 
     // Write the specified page in the buffer pool back to disk
@@ -167,72 +452,221 @@ impl BufferPoolManager {
             let page = self.pool[*frame_id].clone();
             self.disk_manager
                 .write_page(page_id, page.read().unwrap().data())?;
-            page.write().unwrap().is_dirty = false;
+            if std::mem::take(&mut page.write().unwrap().is_dirty) {
+                self.dirty_writes.fetch_add(1, Ordering::Relaxed);
+            }
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
-    // Write all pages in the buffer pool back to disk
-    pub fn flush_all_pages(&self) -> BustubxResult<()> {
-        let page_ids: Vec<PageId> = self.page_table.iter().map(|e| *e.key()).collect();
+    /// Write the dirty pages of the buffer pool back to disk in page id order, then sync.
+    ///
+    /// A page failing to write stays dirty and is listed in the report, the pages after it
+    /// are still written. Only a failing sync is returned as an error.
+    pub fn flush_all_pages(&self) -> BustubxResult<FlushReport> {
+        let mut page_ids: Vec<PageId> = self
+            .page_table
+            .iter()
+            .filter(|e| self.pool[*e.value()].read().unwrap().is_dirty)
+            .map(|e| *e.key())
+            .collect();
+        page_ids.sort_unstable();
+
+        let mut report = FlushReport::default();
         for page_id in page_ids {
-            self.flush_page(page_id)?;
+            match self.flush_page(page_id) {
+                Ok(true) => report.flushed.push(page_id),
+                // evicted meanwhile, and written by the eviction
+                Ok(false) => {}
+                Err(e) => report.failed.push((page_id, e)),
+            }
         }
-        Ok(())
+        self.disk_manager.sync()?;
+        Ok(report)
+    }
+
+    /// Write the dirty pages nobody has pinned back to disk without syncing, returns how
+    /// many were written. Run by the [`super::start_flusher`] task, so evictions find clean
+    /// pages instead of writing on the fetch path.
+    pub fn flush_unpinned_pages(&self) -> BustubxResult<usize> {
+        let frames: Vec<(PageId, FrameId)> = self
+            .page_table
+            .iter()
+            .map(|e| (*e.key(), *e.value()))
+            .collect();
+        let mut written = 0;
+        for (page_id, frame_id) in frames {
+            let dirty = self.pool[frame_id]
+                .try_read()
+                .is_ok_and(|page| page.is_dirty);
+            if !dirty || self.replacer.pin_count(frame_id) != 0 {
+                continue;
+            }
+            // pinned while the page table entry is held, like a fetch hit, so the page
+            // is neither evicted nor replaced while it is written
+            let page_ref = {
+                let Some(entry) = self.page_table.get(&page_id) else {
+                    continue;
+                };
+                if *entry != frame_id || !matches!(self.replacer.pin(frame_id), Ok(true)) {
+                    continue;
+                }
+                self.page_ref(frame_id)
+            };
+            let mut page = page_ref.write().unwrap();
+            if !page.is_dirty {
+                continue;
+            }
+            self.disk_manager.write_page(page_id, page.data())?;
+            page.is_dirty = false;
+            self.dirty_writes.fetch_add(1, Ordering::Relaxed);
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn frame_wait_timeout(&self) -> Duration {
+        self.frame_wait_timeout
     }
 
     // Delete a page from the buffer pool
     pub fn delete_page(&self, page_id: PageId) -> BustubxResult<bool> {
         if let Some(frame_id_lock) = self.page_table.get(&page_id) {
             let frame_id = *frame_id_lock;
-            drop(frame_id_lock);
-
-            let page = self.pool[frame_id].clone();
-            if page.read().unwrap().pin_count > 0 {
+            if !self.replacer.claim_unpinned(frame_id) {
                 // Page is pinned, cannot delete
                 return Ok(false);
             }
+            drop(frame_id_lock);
 
-            // Remove from buffer pool
-            page.write().unwrap().destroy();
+            // Remove from buffer pool, a poisoned frame stays claimed and is retired
+            // instead of reused
+            self.pool[frame_id].write().unwrap().destroy();
             self.page_table.remove(&page_id);
-            self.free_list.write().unwrap().push_back(frame_id);
-            self.replacer.write().unwrap().remove(frame_id);
+            if !self.pins.is_poisoned(frame_id) {
+                self.replacer.remove(frame_id);
+                self.free_frame(frame_id);
+            }
 
             // Delete from disk
             self.disk_manager.deallocate_page(page_id)?;
             Ok(true)
         } else {
+            // an evicted page only lives on disk
+            self.disk_manager.deallocate_page(page_id)?;
             Ok(true)
         }
     }
 
+    // A frame claimed for a new page, see `ShardedReplacer`
     fn allocate_frame(&self) -> BustubxResult<FrameId> {
         if let Some(frame_id) = self.free_list.write().unwrap().pop_front() {
+            self.replacer.claim_free(frame_id);
             Ok(frame_id)
-        } else if let Some(frame_id) = self.replacer.write().unwrap().evict() {
+        } else if let Some((frame_id, passed_over)) =
+            self.replacer
+                .evict_preferring(self.clean_eviction_window, |frame_id| {
+                    // a page locked right now is not known to be clean
+                    self.pool[frame_id]
+                        .try_read()
+                        .is_ok_and(|page| !page.is_dirty)
+                })
+        {
+            if passed_over {
+                self.dirty_evictions_avoided.fetch_add(1, Ordering::Relaxed);
+            }
             let evicted_page = self.pool[frame_id].clone();
             let evicted_page_id = evicted_page.read().unwrap().page_id;
             let is_dirty = evicted_page.read().unwrap().is_dirty;
             if is_dirty {
-                self.flush_page(evicted_page_id)?;
+                if let Err(e) = self.flush_page(evicted_page_id) {
+                    // the page stays, evictable again
+                    self.replacer.record_access(frame_id, AccessType::Unknown)?;
+                    self.replacer.release(frame_id, 0);
+                    return Err(e);
+                }
+                self.dirty_evictions.fetch_add(1, Ordering::Relaxed);
             }
-            self.page_table.remove(&evicted_page_id);
+            // only the mapping to this frame, the page may be mapped again once removed
+            self.page_table
+                .remove_if(&evicted_page_id, |_, mapped| *mapped == frame_id);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
             Ok(frame_id)
         } else {
-            Err(BustubxError::Storage(
+            Err(BustubxError::BufferPoolFull(
                 "Cannot allocate free frame".to_string(),
             ))
         }
     }
+
+    // Give a claimed frame without a page back to the free list
+    fn free_frame(&self, frame_id: FrameId) {
+        self.replacer.release(frame_id, 0);
+        self.free_list.write().unwrap().push_back(frame_id);
+    }
+
+    // Ref of the page in a frame already pinned for it
+    fn page_ref(&self, frame_id: FrameId) -> PageRef {
+        PageRef::new(
+            self.pool[frame_id].clone(),
+            frame_id,
+            self.replacer.clone(),
+            self.pins.clone(),
+        )
+    }
+
+    // Retry `request` after every unpin until it gets a frame or `timeout` passed. A thread
+    // holding most of the pins would mostly wait for itself, so it fails right away.
+    fn wait_for_frame<T>(
+        &self,
+        timeout: Duration,
+        request: impl Fn() -> BustubxResult<T>,
+    ) -> BustubxResult<T> {
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            // read before the request, so an unpin racing with it is not missed
+            let unpins = self.pins.unpins();
+            match request() {
+                Err(BustubxError::BufferPoolFull(message)) => {
+                    if timeout.is_zero() {
+                        return Err(BustubxError::BufferPoolFull(message));
+                    }
+                    let (held, total) = self.pins.held_by_current_thread();
+                    if held * 2 > total {
+                        return Err(BustubxError::BufferPoolFull(format!(
+                            "{message}, the waiting thread holds {held} of the {total} pins itself"
+                        )));
+                    }
+                    if !self.pins.wait_for_unpin(unpins, deadline) {
+                        return Err(BustubxError::BufferPoolFull(format!(
+                            "{message}, no frame was unpinned within {timeout:?}"
+                        )));
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{buffer::BufferPoolManager, storage::DiskManager};
-    use std::sync::Arc;
+    use crate::background::BackgroundTaskRegistry;
+    use crate::buffer::buffer_pool::BufferPoolStats;
+    use crate::buffer::{
+        start_flusher, BufferPoolManager, PageAccess, PageAccessKind, PageId, BUSTUBX_PAGE_SIZE,
+    };
+    use crate::config::BufferPoolOptions;
+    use crate::{storage::DiskManager, BustubxError};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
     use tempfile::TempDir;
 
     #[test]
@@ -253,7 +687,7 @@ mod tests {
             0
         );
         assert_eq!(buffer_pool.free_list.read().unwrap().len(), 2);
-        assert_eq!(buffer_pool.replacer.read().unwrap().size(), 0);
+        assert_eq!(buffer_pool.replacer.size(), 0);
 
         let page2 = buffer_pool.new_page().unwrap();
         let page2_id = page2.read().unwrap().page_id;
@@ -320,7 +754,38 @@ mod tests {
         assert_eq!(page.read().unwrap().page_id, page2_id);
         drop(page);
 
-        assert_eq!(buffer_pool.replacer.read().unwrap().size(), 3);
+        assert_eq!(buffer_pool.replacer.size(), 3);
+    }
+
+    #[test]
+    pub fn test_page_trace() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = BufferPoolManager::new(2, Arc::new(disk_manager));
+        let page1_id = buffer_pool.new_page().unwrap().read().unwrap().page_id;
+
+        buffer_pool.start_trace();
+        let page2_id = buffer_pool.new_page().unwrap().read().unwrap().page_id;
+        drop(buffer_pool.fetch_page(page2_id).unwrap());
+        // page 1 is evicted for page 3
+        let page3_id = buffer_pool.new_page().unwrap().read().unwrap().page_id;
+        drop(buffer_pool.fetch_page(page1_id).unwrap());
+        let access = |page_id, kind| PageAccess { page_id, kind };
+        assert_eq!(
+            buffer_pool.take_trace(),
+            vec![
+                access(page2_id, PageAccessKind::New),
+                access(page2_id, PageAccessKind::Hit),
+                access(page3_id, PageAccessKind::New),
+                access(page1_id, PageAccessKind::Miss),
+            ]
+        );
+
+        // nothing is recorded once the trace was taken
+        drop(buffer_pool.fetch_page(page1_id).unwrap());
+        assert!(buffer_pool.take_trace().is_empty());
     }
 
     #[test]
@@ -347,10 +812,351 @@ mod tests {
         assert!(res);
         assert_eq!(buffer_pool.pool.len(), 3);
         assert_eq!(buffer_pool.free_list.read().unwrap().len(), 1);
-        assert_eq!(buffer_pool.replacer.read().unwrap().size(), 2);
+        assert_eq!(buffer_pool.replacer.size(), 2);
         assert_eq!(buffer_pool.page_table.len(), 2);
 
         let page = buffer_pool.fetch_page(page1_id).unwrap();
         assert_eq!(page.read().unwrap().page_id, page1_id);
     }
+
+    #[test]
+    pub fn test_buffer_pool_manager_delete_evicted_page() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = BufferPoolManager::new(3, Arc::new(disk_manager));
+
+        let page_ids = (0..4)
+            .map(|_| buffer_pool.new_page().unwrap().read().unwrap().page_id)
+            .collect::<Vec<_>>();
+        assert!(!buffer_pool.page_table.contains_key(&page_ids[0]));
+        assert!(buffer_pool.delete_page(page_ids[0]).unwrap());
+        assert_eq!(buffer_pool.disk_manager.free_page_count().unwrap(), 1);
+        assert!(!buffer_pool
+            .disk_manager
+            .allocated_page_ids()
+            .unwrap()
+            .contains(&page_ids[0]));
+    }
+
+    #[test]
+    pub fn test_flush_all_pages_reports_failed_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = Arc::new(DiskManager::try_new(temp_path).unwrap());
+        let buffer_pool = BufferPoolManager::new(5, disk_manager.clone());
+        let pages = (0..5)
+            .map(|i| {
+                let page = buffer_pool.new_page().unwrap();
+                page.write()
+                    .unwrap()
+                    .set_data([i as u8 + 1; BUSTUBX_PAGE_SIZE]);
+                page
+            })
+            .collect::<Vec<_>>();
+        let page_ids = pages
+            .iter()
+            .map(|page| page.read().unwrap().page_id)
+            .collect::<Vec<_>>();
+        // a clean page is not written at all
+        buffer_pool.flush_page(page_ids[4]).unwrap();
+        disk_manager.fail_writes_to([page_ids[1], page_ids[3]]);
+
+        let report = buffer_pool.flush_all_pages().unwrap();
+        assert_eq!(report.flushed, vec![page_ids[0], page_ids[2]]);
+        assert_eq!(
+            report
+                .failed
+                .iter()
+                .map(|(page_id, _)| *page_id)
+                .collect::<Vec<_>>(),
+            vec![page_ids[1], page_ids[3]]
+        );
+        assert!(report
+            .failed
+            .iter()
+            .all(|(_, e)| matches!(e, BustubxError::Storage(_))));
+
+        for (i, page) in pages.iter().enumerate() {
+            let failed = i == 1 || i == 3;
+            assert_eq!(page.read().unwrap().is_dirty, failed);
+            let on_disk = disk_manager.read_page(page_ids[i]).unwrap();
+            assert_eq!(on_disk == [i as u8 + 1; BUSTUBX_PAGE_SIZE], !failed);
+        }
+        assert!(matches!(
+            buffer_pool.flush_all_pages().unwrap().into_result(),
+            Err(BustubxError::Storage(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_pin_count_underflow_poisons_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = BufferPoolManager::new(3, Arc::new(disk_manager));
+        let page1 = buffer_pool.new_page().unwrap();
+        let page1_id = page1.read().unwrap().page_id;
+        let frame_id = *buffer_pool.page_table.get(&page1_id).unwrap();
+        // a guard of the page that was never pinned for it, so the page is unpinned twice
+        let faulty_guard = buffer_pool.page_ref(frame_id);
+
+        drop(page1);
+        assert_eq!(buffer_pool.stats().pin_anomalies, 0);
+        drop(faulty_guard);
+        assert_eq!(buffer_pool.stats().pin_anomalies, 1);
+        assert_eq!(buffer_pool.replacer.pin_count(frame_id), 0);
+        assert_eq!(buffer_pool.replacer.size(), 0);
+        assert!(matches!(
+            buffer_pool.fetch_page(page1_id),
+            Err(BustubxError::Internal(_))
+        ));
+
+        // the poisoned frame is retired rather than reused
+        assert!(buffer_pool.delete_page(page1_id).unwrap());
+        assert!(!buffer_pool.free_list.read().unwrap().contains(&frame_id));
+        let _page2 = buffer_pool.new_page().unwrap();
+        let _page3 = buffer_pool.new_page().unwrap();
+        assert!(matches!(
+            buffer_pool.new_page(),
+            Err(BustubxError::BufferPoolFull(_))
+        ));
+
+        buffer_pool.reset_stats();
+        assert_eq!(buffer_pool.stats().pin_anomalies, 0);
+    }
+
+    // Threads fetching more pages than fit race hits with evictions of the same frames,
+    // every fetch has to see its own page and no pin may be lost.
+    #[test]
+    pub fn test_concurrent_fetches_with_evictions() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(128, Arc::new(disk_manager)));
+        let page_ids = (0..200)
+            .map(|i| {
+                let page = buffer_pool.new_page().unwrap();
+                let mut page = page.write().unwrap();
+                page.set_data([i as u8; BUSTUBX_PAGE_SIZE]);
+                page.page_id
+            })
+            .collect::<Vec<_>>();
+
+        let workers = (0..8u64)
+            .map(|seed| {
+                let buffer_pool = buffer_pool.clone();
+                let page_ids = page_ids.clone();
+                thread::spawn(move || {
+                    let mut state = seed + 1;
+                    for _ in 0..2000 {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        let i = state as usize % page_ids.len();
+                        let page = buffer_pool
+                            .fetch_page_blocking(page_ids[i], Duration::from_secs(10))
+                            .unwrap();
+                        let page = page.read().unwrap();
+                        assert_eq!(page.page_id, page_ids[i]);
+                        assert_eq!(page.data()[0], i as u8);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(buffer_pool
+            .frames()
+            .iter()
+            .all(|frame| frame.pin_count == 0));
+        assert_eq!(buffer_pool.replacer.size(), 128);
+        assert_eq!(buffer_pool.stats().pin_anomalies, 0);
+    }
+
+    // Threads missing the same page at once must load it into a single frame.
+    #[test]
+    pub fn test_concurrent_misses_share_frame() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(16, Arc::new(disk_manager)));
+        let page_ids = (0..64)
+            .map(|i| {
+                let page = buffer_pool.new_page().unwrap();
+                let mut page = page.write().unwrap();
+                page.set_data([i as u8; BUSTUBX_PAGE_SIZE]);
+                page.page_id
+            })
+            .collect::<Vec<_>>();
+
+        // each evicted by the pages created after it
+        for (round, &page_id) in page_ids.iter().enumerate().take(20) {
+            let barrier = Arc::new(std::sync::Barrier::new(8));
+            let workers = (0..8)
+                .map(|_| {
+                    let buffer_pool = buffer_pool.clone();
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        let page = buffer_pool
+                            .fetch_page_blocking(page_id, Duration::from_secs(10))
+                            .unwrap();
+                        assert_eq!(page.read().unwrap().data()[0], round as u8);
+                        // held until every thread fetched it
+                        barrier.wait();
+                        page.frame_id
+                    })
+                })
+                .collect::<Vec<_>>();
+            let frame_ids = workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>();
+            assert!(frame_ids.iter().all(|frame_id| *frame_id == frame_ids[0]));
+            let frames = buffer_pool.frames();
+            assert_eq!(
+                frames
+                    .iter()
+                    .filter(|frame| frame.page_id == Some(page_id))
+                    .count(),
+                1
+            );
+            assert!(frames.iter().all(|frame| frame.pin_count == 0));
+        }
+        assert_eq!(buffer_pool.stats().pin_anomalies, 0);
+    }
+
+    #[test]
+    pub fn test_new_page_blocking_waits_for_unpin() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(3, Arc::new(disk_manager)));
+
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let holder = {
+            let buffer_pool = buffer_pool.clone();
+            thread::spawn(move || {
+                let pages = (0..3)
+                    .map(|_| buffer_pool.new_page().unwrap())
+                    .collect::<Vec<_>>();
+                pinned_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(100));
+                drop(pages);
+            })
+        };
+        pinned_rx.recv().unwrap();
+
+        assert!(matches!(
+            buffer_pool.new_page(),
+            Err(BustubxError::BufferPoolFull(_))
+        ));
+        let page = buffer_pool
+            .new_page_blocking(Duration::from_secs(10))
+            .unwrap();
+        let page_id = page.read().unwrap().page_id;
+        drop(page);
+        assert!(buffer_pool
+            .fetch_page_blocking(page_id, Duration::ZERO)
+            .is_ok());
+        holder.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_new_page_blocking_detects_self_deadlock() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = BufferPoolManager::new(3, Arc::new(disk_manager));
+
+        // only this thread could unpin a frame, waiting would never end
+        let _pages = (0..3)
+            .map(|_| buffer_pool.new_page().unwrap())
+            .collect::<Vec<_>>();
+        let start = Instant::now();
+        let result = buffer_pool.new_page_blocking(Duration::from_secs(60));
+        assert!(matches!(result, Err(BustubxError::BufferPoolFull(_))));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    // Rounds of changing a few pages, then reading more pages than fit. With a flusher the
+    // changed pages are written back before the reads.
+    fn dirty_then_read(clean_eviction_window: usize, flush: bool) -> BufferPoolStats {
+        const ROUNDS: usize = 10;
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new_with_options(
+            BufferPoolOptions {
+                pool_size: 16,
+                clean_eviction_window,
+                ..Default::default()
+            },
+            Arc::new(disk_manager),
+        ));
+        let page_ids = (0..64)
+            .map(|_| buffer_pool.new_page().unwrap().read().unwrap().page_id)
+            .collect::<Vec<PageId>>();
+        buffer_pool.flush_all_pages().unwrap();
+        buffer_pool.reset_stats();
+        let mut registry = BackgroundTaskRegistry::default();
+        let flusher = flush.then(|| {
+            start_flusher(&mut registry, buffer_pool.clone(), Duration::from_millis(1)).unwrap()
+        });
+
+        for round in 0..ROUNDS {
+            for i in 0..4 {
+                let page = buffer_pool
+                    .fetch_page(page_ids[(round * 4 + i) % 32])
+                    .unwrap();
+                page.write()
+                    .unwrap()
+                    .set_data([round as u8; BUSTUBX_PAGE_SIZE]);
+            }
+            if flusher.is_some() {
+                let deadline = Instant::now() + Duration::from_secs(10);
+                while buffer_pool.frames().iter().any(|frame| frame.is_dirty) {
+                    assert!(Instant::now() < deadline, "the flusher did not write back");
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            for page_id in &page_ids[32..] {
+                buffer_pool.fetch_page(*page_id).unwrap();
+            }
+        }
+        registry.shutdown().unwrap();
+        // the changes survive whichever way they were written back
+        buffer_pool.flush_all_pages().unwrap();
+        for round in ROUNDS - 2..ROUNDS {
+            let page_id = page_ids[(round * 4) % 32];
+            let data = buffer_pool.disk_manager.read_page(page_id).unwrap();
+            assert_eq!(data[0], round as u8);
+        }
+        buffer_pool.stats()
+    }
+
+    #[test]
+    pub fn test_background_flusher_avoids_dirty_evictions() {
+        // plain LRU-K writes every changed page back when evicting it
+        let stats = dirty_then_read(0, false);
+        assert_eq!(stats.dirty_evictions, 40);
+        assert_eq!(stats.dirty_evictions_avoided, 0);
+
+        // clean pages are evicted first, the changed ones pile up in the pool
+        let stats = dirty_then_read(8, false);
+        assert!(stats.dirty_evictions_avoided > 0, "{stats:?}");
+        assert!(stats.dirty_evictions < 40, "{stats:?}");
+
+        // the flusher wrote the changed pages back before they were evicted
+        let stats = dirty_then_read(8, true);
+        assert_eq!(stats.dirty_evictions, 0, "{stats:?}");
+        assert!(stats.dirty_writes >= 40, "{stats:?}");
+        assert!(stats.evictions >= 320, "{stats:?}");
+    }
 }
@@ -0,0 +1,25 @@
+use log::warn;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::background::{BackgroundTaskRegistry, TaskId};
+use crate::buffer::BufferPoolManager;
+use crate::BustubxResult;
+
+/// Start the task writing the dirty unpinned pages of `buffer_pool` back every `interval`,
+/// see [`BufferPoolManager::flush_unpinned_pages`]. Evictions then mostly find clean pages
+/// and do not write on the fetch path. Stops with its token.
+pub fn start_flusher(
+    registry: &mut BackgroundTaskRegistry,
+    buffer_pool: Arc<BufferPoolManager>,
+    interval: Duration,
+) -> BustubxResult<TaskId> {
+    registry.spawn("flusher", move |token| {
+        while !token.wait_timeout(interval) {
+            // a page failing to write stays dirty, the eviction tries again
+            if let Err(e) = buffer_pool.flush_unpinned_pages() {
+                warn!("background flush failed: {}", e);
+            }
+        }
+    })
+}
@@ -0,0 +1,111 @@
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::buffer::PageId;
+
+pub const PAGE_HISTORY_CAPACITY: usize = 4096;
+
+thread_local! {
+    static CURRENT_OPERATION: Cell<&'static str> = const { Cell::new("unknown") };
+}
+
+/// Tag page writes of the current thread with `operation` until the returned scope is dropped.
+pub fn operation_scope(operation: &'static str) -> OperationScope {
+    let previous = CURRENT_OPERATION.with(|op| op.replace(operation));
+    OperationScope { previous }
+}
+
+pub struct OperationScope {
+    previous: &'static str,
+}
+
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        CURRENT_OPERATION.with(|op| op.set(self.previous));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageWriteRecord {
+    pub page_id: PageId,
+    pub seq: u64,
+    pub operation: &'static str,
+    pub content_hash: u64,
+}
+
+/// Bounded ring buffer of the most recent page writes of a buffer pool.
+#[derive(Debug)]
+pub struct PageHistory {
+    records: Mutex<VecDeque<PageWriteRecord>>,
+    capacity: usize,
+    next_seq: AtomicU64,
+}
+
+impl PageHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, page_id: PageId, data: &[u8]) {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let record = PageWriteRecord {
+            page_id,
+            seq: self.next_seq.fetch_add(1, Ordering::SeqCst),
+            operation: CURRENT_OPERATION.with(|op| op.get()),
+            content_hash: hasher.finish(),
+        };
+
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Recent writes of the page, oldest first
+    pub fn page_history(&self, page_id: PageId) -> Vec<PageWriteRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.page_id == page_id)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::history::{operation_scope, PageHistory};
+
+    #[test]
+    pub fn test_page_history_ring_buffer() {
+        let history = PageHistory::new(3);
+        {
+            let _op = operation_scope("outer");
+            history.record(1, &[1]);
+            {
+                let _op = operation_scope("inner");
+                history.record(1, &[2]);
+            }
+            history.record(2, &[3]);
+        }
+        history.record(1, &[4]);
+
+        let records = history.page_history(1);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].operation, "inner");
+        assert_eq!(records[1].operation, "unknown");
+        assert!(records[0].seq < records[1].seq);
+        assert_eq!(history.page_history(2)[0].operation, "outer");
+    }
+}
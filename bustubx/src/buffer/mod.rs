@@ -1,6 +1,24 @@
 mod buffer_pool;
+mod flusher;
+#[cfg(feature = "debug-history")]
+mod history;
 mod page;
 mod replacer;
 
-pub use buffer_pool::{BufferPoolManager, BUFFER_POOL_SIZE};
+pub use buffer_pool::{
+    AccessType, BufferPoolManager, PageAccess, PageAccessKind, BUFFER_POOL_SIZE,
+};
+pub use flusher::start_flusher;
+#[cfg(feature = "debug-history")]
+pub use history::{operation_scope, PageHistory, PageWriteRecord};
 pub use page::*;
+
+#[cfg(not(feature = "debug-history"))]
+pub struct OperationScope;
+
+/// Tag page writes of the current thread, only recorded with the `debug-history` feature.
+#[cfg(not(feature = "debug-history"))]
+#[inline(always)]
+pub fn operation_scope(_operation: &'static str) -> OperationScope {
+    OperationScope
+}
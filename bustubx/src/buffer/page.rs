@@ -1,11 +1,15 @@
 use crate::buffer::buffer_pool::FrameId;
-use crate::buffer::replacer::LRUKReplacer;
+use crate::buffer::replacer::ShardedReplacer;
+use crate::storage::codec::PageCodec;
 use dashmap::DashMap;
 use derive_with::With;
 use log::error;
+use std::collections::HashSet;
 use std::ops::Deref;
-use std::sync::atomic::AtomicU32;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, ThreadId};
+use std::time::Instant;
 
 pub type PageId = u32;
 pub type AtomicPageId = AtomicU32;
@@ -17,10 +21,10 @@ pub const BUSTUBX_PAGE_SIZE: usize = 4096;
 pub struct Page {
     pub page_id: PageId,
     data: [u8; BUSTUBX_PAGE_SIZE],
-    // Reference count
-    pub pin_count: u32,
     // Whether it has been written to
     pub is_dirty: bool,
+    #[cfg(feature = "debug-history")]
+    history: Option<Arc<super::PageHistory>>,
 }
 
 impl Page {
@@ -31,20 +35,57 @@ impl Page {
         Self {
             page_id,
             data: [0; BUSTUBX_PAGE_SIZE],
-            pin_count: 0,
             is_dirty: false,
+            #[cfg(feature = "debug-history")]
+            history: None,
         }
     }
+
+    #[cfg(feature = "debug-history")]
+    pub(crate) fn set_history(&mut self, history: Arc<super::PageHistory>) {
+        self.history = Some(history);
+    }
     pub fn destroy(&mut self) {
         self.page_id = 0;
         self.data = [0; BUSTUBX_PAGE_SIZE];
-        self.pin_count = 0;
         self.is_dirty = false;
     }
 
     pub fn set_data(&mut self, data: [u8; BUSTUBX_PAGE_SIZE]) {
         self.data = data;
         self.is_dirty = true;
+        #[cfg(feature = "debug-history")]
+        if let Some(history) = &self.history {
+            history.record(self.page_id, &self.data);
+        }
+    }
+
+    /// Let `encode` write the page straight into the frame, e.g. with the `encode_into` of a
+    /// page codec, instead of encoding to a buffer that [`Page::set_data`] copies.
+    pub fn encode_with(&mut self, encode: impl FnOnce(&mut [u8; BUSTUBX_PAGE_SIZE])) {
+        encode(&mut self.data);
+        self.is_dirty = true;
+        #[cfg(feature = "debug-history")]
+        if let Some(history) = &self.history {
+            history.record(self.page_id, &self.data);
+        }
+    }
+
+    /// Encode `page` straight into the frame with the codec `C`.
+    pub fn encode_page<C: PageCodec>(&mut self, page: &C::Page) {
+        self.encode_with(|data| C::encode_into(page, data));
+    }
+
+    /// Overwrite the byte ranges of the patch, the rest of the page is left as is.
+    pub fn apply_patch(&mut self, patch: &[(usize, Vec<u8>)]) {
+        for (offset, bytes) in patch {
+            self.data[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        self.is_dirty = true;
+        #[cfg(feature = "debug-history")]
+        if let Some(history) = &self.history {
+            history.record(self.page_id, &self.data);
+        }
     }
 
     pub fn data(&self) -> &[u8] {
@@ -54,15 +95,160 @@ impl Page {
     pub fn replace(&mut self, other: Page) {
         self.page_id = other.page_id;
         self.data = other.data;
-        self.pin_count = other.pin_count;
         self.is_dirty = other.is_dirty;
     }
 }
 
+/// Pins of a buffer pool per thread, and a signal for the threads waiting for a page
+/// to be unpinned. Also keeps the frames whose pin count went wrong.
+#[derive(Debug, Default)]
+pub struct PinTracker {
+    by_thread: DashMap<ThreadId, usize>,
+    // bumped whenever a page is unpinned
+    unpins: AtomicU64,
+    // threads in `wait_for_unpin`, unpins only lock `wait_lock` to wake them
+    waiters: AtomicUsize,
+    wait_lock: Mutex<()>,
+    unpinned: Condvar,
+    // pin count overflows and underflows detected
+    anomalies: AtomicU64,
+    // frames retired after an underflow, they are neither evicted nor reused
+    poisoned: Mutex<HashSet<FrameId>>,
+    // whether `poisoned` is not empty, spares fetches the lock
+    any_poisoned: AtomicBool,
+}
+
+impl PinTracker {
+    /// Count a pin of the calling thread, returns the thread as the owner of the pin.
+    pub fn pin(&self) -> ThreadId {
+        let owner = thread::current().id();
+        *self.by_thread.entry(owner).or_insert(0) += 1;
+        owner
+    }
+
+    pub fn unpin(&self, owner: ThreadId) {
+        if let Some(mut pins) = self.by_thread.get_mut(&owner) {
+            *pins = pins.saturating_sub(1);
+        }
+        self.by_thread.remove_if(&owner, |_, pins| *pins == 0);
+        self.unpins.fetch_add(1, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            // a waiter holds the lock until it waits, the notification cannot slip in
+            // between its check and its wait
+            let _guard = self.wait_lock.lock().unwrap();
+            self.unpinned.notify_all();
+        }
+    }
+
+    /// (pins held by the calling thread, pins held by every thread)
+    pub fn held_by_current_thread(&self) -> (usize, usize) {
+        let held = self
+            .by_thread
+            .get(&thread::current().id())
+            .map_or(0, |pins| *pins);
+        let total = self.by_thread.iter().map(|pins| *pins.value()).sum();
+        (held, total)
+    }
+
+    pub fn unpins(&self) -> u64 {
+        self.unpins.load(Ordering::SeqCst)
+    }
+
+    pub fn record_anomaly(&self) {
+        self.anomalies.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn anomalies(&self) -> u64 {
+        self.anomalies.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_anomalies(&self) {
+        self.anomalies.store(0, Ordering::Relaxed);
+    }
+
+    pub fn poison(&self, frame_id: FrameId) {
+        self.poisoned.lock().unwrap().insert(frame_id);
+        self.any_poisoned.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_poisoned(&self, frame_id: FrameId) -> bool {
+        self.any_poisoned.load(Ordering::SeqCst)
+            && self.poisoned.lock().unwrap().contains(&frame_id)
+    }
+
+    /// Wait until a page is unpinned after `seen` unpins, false once `deadline` passed
+    /// first. No deadline waits forever.
+    pub fn wait_for_unpin(&self, seen: u64, deadline: Option<Instant>) -> bool {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let mut guard = self.wait_lock.lock().unwrap();
+        let mut unpinned = true;
+        while self.unpins.load(Ordering::SeqCst) == seen {
+            match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        unpinned = false;
+                        break;
+                    }
+                    guard = self.unpinned.wait_timeout(guard, deadline - now).unwrap().0;
+                }
+                None => guard = self.unpinned.wait(guard).unwrap(),
+            }
+        }
+        drop(guard);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        unpinned
+    }
+}
+
 pub struct PageRef {
     pub page: Arc<RwLock<Page>>,
-    pub page_table: Arc<DashMap<PageId, FrameId>>,
-    pub replacer: Arc<RwLock<LRUKReplacer>>,
+    pub frame_id: FrameId,
+    pub replacer: Arc<ShardedReplacer>,
+    pub pins: Arc<PinTracker>,
+    // thread the pin is counted for
+    pub owner: ThreadId,
+}
+
+impl PageRef {
+    /// Ref of a page already pinned for it, the pin is counted for the calling thread.
+    pub fn new(
+        page: Arc<RwLock<Page>>,
+        frame_id: FrameId,
+        replacer: Arc<ShardedReplacer>,
+        pins: Arc<PinTracker>,
+    ) -> Self {
+        let owner = pins.pin();
+        Self {
+            page,
+            frame_id,
+            replacer,
+            pins,
+            owner,
+        }
+    }
+
+    /// Pins of the page, this one included.
+    pub fn pin_count(&self) -> u32 {
+        self.replacer.pin_count(self.frame_id)
+    }
+
+    fn unpin_frame(&self) {
+        // checked in every build, a wrapped count would let the frame be evicted or deleted
+        // while it is in use
+        if self.replacer.unpin(self.frame_id).is_none() {
+            error!(
+                "Page id {} in frame {} unpinned more often than pinned, frame poisoned",
+                self.page.read().unwrap().page_id,
+                self.frame_id
+            );
+            self.pins.record_anomaly();
+            self.pins.poison(self.frame_id);
+            // out of the replacer the frame is never evicted, the page stays readable
+            // for flushing
+            self.replacer.remove(self.frame_id);
+        }
+    }
 }
 
 impl Deref for PageRef {
@@ -75,52 +261,35 @@ impl Deref for PageRef {
 
 impl Drop for PageRef {
     fn drop(&mut self) {
-        if self.page.read().unwrap().pin_count == 0 {
-            return;
-        }
-        let page_id = self.page.read().unwrap().page_id;
-        if let Some(frame_id) = self.page_table.get(&page_id) {
-            self.page.write().unwrap().pin_count -= 1;
-            if self.page.read().unwrap().pin_count == 0 {
-                if let Err(e) = self
-                    .replacer
-                    .write()
-                    .unwrap()
-                    .set_evictable(*frame_id, true)
-                {
-                    panic!(
-                        "Failed to set evictable to frame {}, err: {:?}",
-                        *frame_id, e
-                    );
-                }
-            }
-        } else {
-            error!("Cannot unpin page id {} as it is not in the pool", page_id);
-        }
+        self.unpin_frame();
+        // waiters are woken once the frame is evictable
+        self.pins.unpin(self.owner);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::buffer::replacer::LRUKReplacer;
-    use crate::buffer::{Page, PageRef};
-    use dashmap::DashMap;
+    use crate::buffer::replacer::ShardedReplacer;
+    use crate::buffer::{Page, PageRef, PinTracker};
     use std::sync::{Arc, RwLock};
 
     #[test]
     fn page_ref() {
         let page = Arc::new(RwLock::new(Page::new(1)));
-        let page_table = Arc::new(DashMap::new());
-        let replacer = Arc::new(RwLock::new(LRUKReplacer::new(10, 2)));
+        let replacer = Arc::new(ShardedReplacer::new(10, 2));
 
-        let page_ref = PageRef {
-            page: page.clone(),
-            page_table,
-            replacer,
-        };
+        let pins = Arc::new(PinTracker::default());
+
+        assert!(replacer.pin(0).unwrap());
+        let page_ref = PageRef::new(page.clone(), 0, replacer.clone(), pins.clone());
         assert_eq!(Arc::strong_count(&page), 2);
         assert_eq!(page_ref.read().unwrap().page_id, 1);
+        assert_eq!(page_ref.pin_count(), 1);
+        assert_eq!(pins.held_by_current_thread(), (1, 1));
         drop(page_ref);
         assert_eq!(Arc::strong_count(&page), 1);
+        assert_eq!(replacer.pin_count(0), 0);
+        assert_eq!(pins.held_by_current_thread(), (0, 0));
+        assert_eq!(pins.unpins(), 1);
     }
 }
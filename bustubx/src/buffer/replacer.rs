@@ -1,7 +1,10 @@
 use crate::{BustubxError, BustubxResult};
+use std::cmp::Reverse;
 use std::collections::{HashMap, LinkedList};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
 
-use super::buffer_pool::FrameId;
+use super::buffer_pool::{AccessType, FrameId};
 
 #[derive(Debug)]
 struct LRUKNode {
@@ -50,31 +53,71 @@ impl LRUKReplacer {
     }
 
     // Evict the evictable frame with the maximum k-distance
+    #[cfg(test)]
     pub fn evict(&mut self) -> Option<FrameId> {
-        let mut max_k_distance = 0;
-        let mut result = None;
-        for (frame_id, node) in self.node_store.iter() {
-            if !node.is_evictable {
-                continue;
-            }
-            let k_distance = if node.history.len() < self.k {
-                u64::MAX - node.history.front().unwrap()
-            } else {
-                self.current_timestamp - node.history.front().unwrap()
-            };
-            if k_distance > max_k_distance {
-                max_k_distance = k_distance;
-                result = Some(*frame_id);
-            }
-        }
-        if let Some(frame_id) = result {
-            self.remove(frame_id);
+        self.evict_where(|_| true)
+    }
+
+    // Evict the evictable frame with the maximum k-distance `claim` accepts, it is asked
+    // for the frames in eviction order until it accepts one
+    #[cfg(test)]
+    pub fn evict_where(&mut self, mut claim: impl FnMut(FrameId) -> bool) -> Option<FrameId> {
+        let frame_id = self
+            .eviction_order()
+            .into_iter()
+            .find(|frame_id| claim(*frame_id))?;
+        self.remove(frame_id);
+        Some(frame_id)
+    }
+
+    // Evictable frames, the one with the maximum k-distance first
+    fn eviction_order(&self) -> Vec<FrameId> {
+        let mut candidates = self
+            .node_store
+            .iter()
+            .filter(|(_, node)| node.is_evictable)
+            .map(|(frame_id, node)| (*frame_id, self.k_distance(node)))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, k_distance)| Reverse(*k_distance));
+        candidates
+            .into_iter()
+            .map(|(frame_id, _)| frame_id)
+            .collect()
+    }
+
+    fn k_distance(&self, node: &LRUKNode) -> u64 {
+        match node.history.front() {
+            // Frames only touched by sequential accesses have no history
+            None => u64::MAX,
+            Some(first) if node.history.len() < self.k => u64::MAX - 1 - first,
+            Some(first) => self.current_timestamp - first,
         }
-        result
     }
 
     // Record frame access
+    #[cfg(test)]
     pub fn record_access(&mut self, frame_id: FrameId) -> BustubxResult<()> {
+        self.record_access_with_type(frame_id, AccessType::Unknown)
+    }
+
+    // Record frame access with an access hint. Sequential accesses never promote a frame,
+    // and frames first touched by them are the first candidates for eviction.
+    pub fn record_access_with_type(
+        &mut self,
+        frame_id: FrameId,
+        access_type: AccessType,
+    ) -> BustubxResult<()> {
+        if access_type == AccessType::Sequential {
+            if !self.node_store.contains_key(&frame_id) {
+                if self.node_store.len() >= self.replacer_size {
+                    return Err(BustubxError::Internal(
+                        "frame size exceeds the limit".to_string(),
+                    ));
+                }
+                self.node_store.insert(frame_id, LRUKNode::new(self.k));
+            }
+            return Ok(());
+        }
         if let Some(node) = self.node_store.get_mut(&frame_id) {
             node.record_access(self.current_timestamp);
             self.current_timestamp += 1;
@@ -118,15 +161,212 @@ impl LRUKReplacer {
         }
     }
 
+    // Timestamp of the latest access of the frame, None if it is not tracked
+    pub fn last_access(&self, frame_id: FrameId) -> Option<u64> {
+        self.node_store
+            .get(&frame_id)
+            .and_then(|node| node.history.back().copied())
+    }
+
     // Get the current number of evictable frames
+    #[cfg(test)]
     pub fn size(&self) -> usize {
         self.current_size
     }
+
+    // Frames tracked by the replacer, evictable or not
+    pub fn frame_ids(&self) -> impl Iterator<Item = FrameId> + '_ {
+        self.node_store.keys().copied()
+    }
+}
+
+/// [`LRUKReplacer`] split into shards, frame `i` belongs to shard `i % shards`, so a
+/// fetch hit only locks the shard of its frame to record the access.
+///
+/// Whether a tracked frame can be evicted is its pin count, an atomic changed without
+/// any lock, pinning a resident page does not touch the shard at all. The evictor skips
+/// pinned frames and claims the victim by swapping its pin count from 0 to
+/// [`FRAME_CLAIMED`], a fetch racing with the eviction either pins the frame first or
+/// finds it claimed. Evictions take the victim of the shards in turn, so they are
+/// LRU-K within a shard and approximately LRU-K across the pool. Small pools have a
+/// single shard and evict in exact LRU-K order.
+#[derive(Debug)]
+pub struct ShardedReplacer {
+    shards: Vec<Mutex<LRUKReplacer>>,
+    pin_counts: Vec<AtomicU32>,
+    // shard the next eviction starts at
+    next_shard: AtomicUsize,
+}
+
+/// Pin count of a frame that is being evicted, deleted or loaded with a new page.
+pub const FRAME_CLAIMED: u32 = u32::MAX;
+
+// a shard has at least this many frames
+const MIN_FRAMES_PER_SHARD: usize = 64;
+const MAX_REPLACER_SHARDS: usize = 16;
+
+impl ShardedReplacer {
+    pub fn new(num_frames: usize, k: usize) -> Self {
+        let num_shards = (num_frames / MIN_FRAMES_PER_SHARD).clamp(1, MAX_REPLACER_SHARDS);
+        Self {
+            shards: (0..num_shards)
+                .map(|_| Mutex::new(LRUKReplacer::new(num_frames.div_ceil(num_shards), k)))
+                .collect(),
+            pin_counts: (0..num_frames).map(|_| AtomicU32::new(0)).collect(),
+            next_shard: AtomicUsize::new(0),
+        }
+    }
+
+    fn shard(&self, frame_id: FrameId) -> MutexGuard<'_, LRUKReplacer> {
+        self.shards[frame_id % self.shards.len()].lock().unwrap()
+    }
+
+    /// Pin a frame holding a page, false if the frame is claimed and has to be looked up
+    /// again. Fails once the pin count would overflow.
+    pub fn pin(&self, frame_id: FrameId) -> BustubxResult<bool> {
+        let pin_count = &self.pin_counts[frame_id];
+        let mut current = pin_count.load(Ordering::Relaxed);
+        loop {
+            if current == FRAME_CLAIMED {
+                return Ok(false);
+            }
+            debug_assert!(
+                current < FRAME_CLAIMED - 1,
+                "pin count of frame {frame_id} overflows"
+            );
+            if current == FRAME_CLAIMED - 1 {
+                return Err(BustubxError::Internal(format!(
+                    "pin count of frame {} overflows",
+                    frame_id
+                )));
+            }
+            match pin_count.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(true),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Unpin a frame, returns the pin count left. None if the frame was not pinned.
+    pub fn unpin(&self, frame_id: FrameId) -> Option<u32> {
+        self.pin_counts[frame_id]
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |current| {
+                (current != 0 && current != FRAME_CLAIMED).then(|| current - 1)
+            })
+            .ok()
+            .map(|previous| previous - 1)
+    }
+
+    /// Pin count of the frame, 0 while it is claimed.
+    pub fn pin_count(&self, frame_id: FrameId) -> u32 {
+        match self.pin_counts[frame_id].load(Ordering::Relaxed) {
+            FRAME_CLAIMED => 0,
+            pin_count => pin_count,
+        }
+    }
+
+    /// Claim a frame no page is in, taken from the free list.
+    pub fn claim_free(&self, frame_id: FrameId) {
+        self.pin_counts[frame_id].store(FRAME_CLAIMED, Ordering::Relaxed);
+    }
+
+    /// Claim an unpinned frame to delete its page, false if it is pinned.
+    pub fn claim_unpinned(&self, frame_id: FrameId) -> bool {
+        self.pin_counts[frame_id]
+            .compare_exchange(0, FRAME_CLAIMED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// End the claim of a frame, pinned `pin_count` times by the thread that claimed it.
+    pub fn release(&self, frame_id: FrameId, pin_count: u32) {
+        self.pin_counts[frame_id].store(pin_count, Ordering::Release);
+    }
+
+    /// Claim the unpinned frame to evict next, None if every tracked frame is pinned.
+    #[cfg(test)]
+    pub fn evict(&self) -> Option<FrameId> {
+        self.evict_preferring(0, |_| false)
+            .map(|(frame_id, _)| frame_id)
+    }
+
+    /// [`Self::evict`] that takes the first unpinned frame `prefer` accepts among the next
+    /// `window` victims of a shard over the victim itself. Also returns whether a victim
+    /// `prefer` refused was passed over that way.
+    pub fn evict_preferring(
+        &self,
+        window: usize,
+        prefer: impl Fn(FrameId) -> bool,
+    ) -> Option<(FrameId, bool)> {
+        let num_shards = self.shards.len();
+        let start = self.next_shard.load(Ordering::Relaxed);
+        (0..num_shards).find_map(|i| {
+            let shard = (start + i) % num_shards;
+            let mut replacer = self.shards[shard].lock().unwrap();
+            let order = replacer.eviction_order();
+            let window_frames = order
+                .iter()
+                .copied()
+                .filter(|frame_id| self.pin_counts[*frame_id].load(Ordering::Relaxed) == 0)
+                .take(window)
+                .map(|frame_id| (frame_id, prefer(frame_id)))
+                .collect::<Vec<_>>();
+            // the frames may be pinned meanwhile, the next ones are tried then
+            let frame_id = window_frames
+                .iter()
+                .filter(|(_, preferred)| *preferred)
+                .map(|(frame_id, _)| *frame_id)
+                .chain(order.iter().copied())
+                .find(|frame_id| self.claim_unpinned(*frame_id))?;
+            replacer.remove(frame_id);
+            self.next_shard.store(shard + 1, Ordering::Relaxed);
+            let passed_over = window_frames
+                .first()
+                .is_some_and(|(victim, preferred)| !preferred && *victim != frame_id);
+            Some((frame_id, passed_over))
+        })
+    }
+
+    pub fn record_access(&self, frame_id: FrameId, access_type: AccessType) -> BustubxResult<()> {
+        let mut shard = self.shard(frame_id);
+        shard.record_access_with_type(frame_id, access_type)?;
+        // pinned frames are evictable to the shard, the evictor checks the pin count
+        shard.set_evictable(frame_id, true)
+    }
+
+    /// Stop tracking a frame, it is not evicted until it is accessed again.
+    pub fn remove(&self, frame_id: FrameId) {
+        self.shard(frame_id).remove(frame_id);
+    }
+
+    pub fn last_access(&self, frame_id: FrameId) -> Option<u64> {
+        self.shard(frame_id).last_access(frame_id)
+    }
+
+    /// Number of tracked frames that are not pinned
+    pub fn size(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .lock()
+                    .unwrap()
+                    .frame_ids()
+                    .filter(|frame_id| self.pin_counts[*frame_id].load(Ordering::Relaxed) == 0)
+                    .count()
+            })
+            .sum()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::LRUKReplacer;
+    use super::{LRUKReplacer, ShardedReplacer};
+    use crate::buffer::AccessType;
 
     #[test]
     pub fn test_lru_k_set_evictable() {
@@ -248,4 +488,54 @@ mod tests {
         lru_replacer.remove(1);
         assert_eq!(0, lru_replacer.size());
     }
+
+    #[test]
+    pub fn test_lru_k_sequential_access_evicted_first() {
+        let mut replacer = LRUKReplacer::new(3, 2);
+        replacer.record_access(1).unwrap();
+        replacer.record_access(2).unwrap();
+        replacer
+            .record_access_with_type(3, AccessType::Sequential)
+            .unwrap();
+        replacer.set_evictable(1, true).unwrap();
+        replacer.set_evictable(2, true).unwrap();
+        replacer.set_evictable(3, true).unwrap();
+        assert_eq!(replacer.evict(), Some(3));
+        assert_eq!(replacer.evict(), Some(1));
+    }
+
+    #[test]
+    pub fn test_sharded_replacer_evicts_unpinned_frames() {
+        let replacer = ShardedReplacer::new(256, 2);
+        assert_eq!(replacer.shards.len(), 4);
+        for frame_id in 0..256 {
+            replacer.claim_free(frame_id);
+            replacer
+                .record_access(frame_id, AccessType::Unknown)
+                .unwrap();
+            replacer.release(frame_id, 1);
+        }
+        // frames 0, 4, 8, .. stay pinned, the rest are unpinned
+        for frame_id in (0..256).filter(|frame_id| frame_id % 4 != 0) {
+            assert_eq!(replacer.unpin(frame_id), Some(0));
+        }
+        assert_eq!(replacer.size(), 192);
+        assert_eq!(replacer.unpin(1), None);
+
+        let mut evicted = (0..192)
+            .map(|_| replacer.evict().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(replacer.evict(), None);
+        assert_eq!(replacer.size(), 0);
+        // the shards took turns, the oldest frames of each went first
+        assert_eq!(evicted[..3], [1, 2, 3]);
+        evicted.sort();
+        assert!(evicted.iter().all(|frame_id| frame_id % 4 != 0));
+        assert!(!replacer.pin(1).unwrap());
+
+        replacer.release(1, 0);
+        assert!(replacer.pin(1).unwrap());
+        assert_eq!(replacer.unpin(4), Some(0));
+        assert_eq!(replacer.evict(), Some(4));
+    }
 }
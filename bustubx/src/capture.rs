@@ -0,0 +1,476 @@
+//! Statement capture and workload replay. A capture file holds one JSON object per
+//! executed statement, see [`CapturedStatement`], replaying it runs the statements against
+//! another database to compare their results and timing, e.g. before and after an
+//! optimizer or storage change.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::SessionSettings;
+use crate::transaction::TransactionId;
+use crate::{BustubxError, BustubxResult, Database, Session, SharedDatabase, Tuple};
+
+/// Session id of the statements run on the [`Database`] itself instead of a [`Session`].
+pub const DATABASE_SESSION_ID: u64 = 0;
+
+/// One line of a capture file. Statements carry their values inline, the database has no
+/// bound parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CapturedStatement {
+    pub session: u64,
+    // transaction of `Session::run_transaction` the statement ran in, its statements are
+    // replayed as one transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionId>,
+    pub sql: String,
+    // wall clock time the statement started at, since the unix epoch
+    pub started_at_micros: u64,
+    pub elapsed_micros: u64,
+    // rows of the result, none if the statement failed
+    pub rows: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CapturedStatement {
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_micros(self.elapsed_micros)
+    }
+}
+
+/// Capture file statements are appended to.
+pub(crate) struct StatementCapture {
+    path: PathBuf,
+    file: File,
+}
+
+impl StatementCapture {
+    pub(crate) fn open(path: impl AsRef<Path>) -> BustubxResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(BustubxError::Io)?;
+        Ok(Self { path, file })
+    }
+
+    // one write per statement, so statements appended by several sessions do not interleave
+    fn write(&mut self, statement: &CapturedStatement) {
+        let mut line = serde_json::to_string(statement).expect("captured statement serializes");
+        line.push('\n');
+        // capturing must not fail a statement that already ran
+        if let Err(e) = self.file.write_all(line.as_bytes()) {
+            warn!(
+                "failed to capture a statement to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Id and capture file of the session statements are run by.
+pub(crate) struct CaptureSource {
+    pub(crate) session_id: u64,
+    pub(crate) capture: Option<StatementCapture>,
+}
+
+impl CaptureSource {
+    pub(crate) fn new(session_id: u64) -> Self {
+        Self {
+            session_id,
+            capture: None,
+        }
+    }
+}
+
+/// Start time of a statement being captured.
+pub(crate) struct StatementTimer {
+    started_at: SystemTime,
+    start: Instant,
+}
+
+impl StatementTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            started_at: SystemTime::now(),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(
+        self,
+        session: u64,
+        transaction: Option<TransactionId>,
+        sql: &str,
+        result: &BustubxResult<Vec<Tuple>>,
+    ) -> CapturedStatement {
+        CapturedStatement {
+            session,
+            transaction,
+            sql: sql.trim().to_string(),
+            started_at_micros: self
+                .started_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as u64,
+            elapsed_micros: self.start.elapsed().as_micros() as u64,
+            rows: result.as_ref().ok().map(|rows| rows.len()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+impl Database {
+    /// Append every statement run from now on, by the database or any of its sessions, to
+    /// the capture file at `path`, created if missing. Replaces the capture started
+    /// before, see [`SharedDatabase::replay`].
+    pub fn start_capture(&mut self, path: impl AsRef<Path>) -> BustubxResult<()> {
+        self.capture = Some(StatementCapture::open(path)?);
+        Ok(())
+    }
+
+    pub fn stop_capture(&mut self) {
+        self.capture = None;
+    }
+
+    pub(crate) fn capturing(&self, source: &CaptureSource) -> bool {
+        self.capture.is_some() || source.capture.is_some()
+    }
+
+    // `run_in_session` recorded to the capture of the database and the one of the session
+    pub(crate) fn run_captured(
+        &mut self,
+        sql: &str,
+        session: Option<&mut SessionSettings>,
+        source: &mut CaptureSource,
+    ) -> BustubxResult<Vec<Tuple>> {
+        if !self.capturing(source) {
+            return self.run_in_session(sql, session);
+        }
+        let timer = StatementTimer::start();
+        let result = self.run_in_session(sql, session);
+        let statement = timer.finish(source.session_id, None, sql, &result);
+        self.record_statements(&[statement], source);
+        result
+    }
+
+    pub(crate) fn record_statements(
+        &mut self,
+        statements: &[CapturedStatement],
+        source: &mut CaptureSource,
+    ) {
+        for capture in self.capture.iter_mut().chain(source.capture.as_mut()) {
+            for statement in statements {
+                capture.write(statement);
+            }
+        }
+    }
+}
+
+impl Session {
+    /// Append the statements of this session to the capture file at `path`, on top of the
+    /// capture of the database, see [`Database::start_capture`].
+    pub fn start_capture(&mut self, path: impl AsRef<Path>) -> BustubxResult<()> {
+        self.capture.capture = Some(StatementCapture::open(path)?);
+        Ok(())
+    }
+
+    pub fn stop_capture(&mut self) {
+        self.capture.capture = None;
+    }
+}
+
+/// Statements of a capture file in the order they were appended.
+pub fn read_capture(path: impl AsRef<Path>) -> BustubxResult<Vec<CapturedStatement>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(BustubxError::Io)?;
+    let mut statements = vec![];
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(BustubxError::Io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let statement = serde_json::from_str(&line).map_err(|e| {
+            BustubxError::Storage(format!(
+                "line {} of capture {} is no statement: {}",
+                number + 1,
+                path.display(),
+                e
+            ))
+        })?;
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+/// Statement of a capture run again by [`SharedDatabase::replay`].
+#[derive(Debug, Clone)]
+pub struct ReplayedStatement {
+    pub captured: CapturedStatement,
+    // rows of the result when replayed, none if the statement failed
+    pub rows: Option<usize>,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+}
+
+impl ReplayedStatement {
+    /// Whether the replayed statement returned as many rows as the captured one, or both
+    /// failed.
+    pub fn rows_match(&self) -> bool {
+        self.rows == self.captured.rows
+    }
+}
+
+/// Outcome of [`SharedDatabase::replay`], the statements in the order they were replayed.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    pub statements: Vec<ReplayedStatement>,
+}
+
+impl ReplayReport {
+    /// Statements whose row count or failure differs from the capture.
+    pub fn mismatches(&self) -> impl Iterator<Item = &ReplayedStatement> {
+        self.statements.iter().filter(|s| !s.rows_match())
+    }
+
+    /// Time the captured statements took together.
+    pub fn captured_elapsed(&self) -> Duration {
+        self.statements.iter().map(|s| s.captured.elapsed()).sum()
+    }
+
+    /// Time the replayed statements took together, without waiting in between.
+    pub fn replayed_elapsed(&self) -> Duration {
+        self.statements.iter().map(|s| s.elapsed).sum()
+    }
+
+    fn push(
+        &mut self,
+        captured: CapturedStatement,
+        start: Instant,
+        result: &BustubxResult<Vec<Tuple>>,
+    ) {
+        self.statements.push(ReplayedStatement {
+            captured,
+            rows: result.as_ref().ok().map(|rows| rows.len()),
+            elapsed: start.elapsed(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+}
+
+impl SharedDatabase {
+    /// Run the statements of the capture file at `path` against this database.
+    ///
+    /// Statements run one at a time in the order they started, so the statements of every
+    /// session keep their order, each captured session replays on a session of its own.
+    /// The statements of a captured transaction replay in one transaction. With a `speed`
+    /// the replay waits between statements as long as the capture did, divided by the
+    /// speed, without one they run back to back. Failing statements are part of the
+    /// report, only failing to read the capture or to open a session is an error.
+    pub fn replay(
+        self: &Arc<Self>,
+        path: impl AsRef<Path>,
+        speed: Option<f64>,
+    ) -> BustubxResult<ReplayReport> {
+        if speed.is_some_and(|speed| !(speed > 0.0 && speed.is_finite())) {
+            return Err(BustubxError::Config(format!(
+                "replay speed {} is not a positive number",
+                speed.unwrap()
+            )));
+        }
+        let captured = read_capture(path)?;
+        // statements of a transaction were appended together when it committed
+        let mut groups: Vec<Vec<CapturedStatement>> = vec![];
+        for statement in captured {
+            match groups.last_mut() {
+                Some(group)
+                    if statement.transaction.is_some()
+                        && group[0].session == statement.session
+                        && group[0].transaction == statement.transaction =>
+                {
+                    group.push(statement)
+                }
+                _ => groups.push(vec![statement]),
+            }
+        }
+        groups.sort_by_key(|group| group[0].started_at_micros);
+
+        let first_start = groups.first().map_or(0, |group| group[0].started_at_micros);
+        let replay_start = Instant::now();
+        let mut sessions: HashMap<u64, Session> = HashMap::new();
+        let mut report = ReplayReport::default();
+        for group in groups {
+            if let Some(speed) = speed {
+                let offset = Duration::from_micros(group[0].started_at_micros - first_start);
+                let due = replay_start + offset.div_f64(speed);
+                std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            }
+            let session_id = group[0].session;
+            if session_id == DATABASE_SESSION_ID {
+                let mut db = self.lock()?;
+                for statement in group {
+                    let start = Instant::now();
+                    let result = db.run(&statement.sql);
+                    report.push(statement, start, &result);
+                }
+                continue;
+            }
+            let session = sessions.entry(session_id).or_insert_with(|| self.session());
+            if group[0].transaction.is_none() {
+                let statement = group.into_iter().next().unwrap();
+                let start = Instant::now();
+                let result = session.run(&statement.sql);
+                report.push(statement, start, &result);
+                continue;
+            }
+            // single threaded, the transaction does not conflict and runs once
+            let mut replayed = vec![];
+            session.run_transaction(|txn| {
+                replayed.clear();
+                for statement in group.iter() {
+                    let start = Instant::now();
+                    let result = txn.run(&statement.sql);
+                    replayed.push((start.elapsed(), result));
+                }
+                Ok(())
+            })?;
+            for (statement, (elapsed, result)) in group.into_iter().zip(replayed) {
+                report.statements.push(ReplayedStatement {
+                    captured: statement,
+                    rows: result.as_ref().ok().map(|rows| rows.len()),
+                    elapsed,
+                    error: result.err().map(|e| e.to_string()),
+                });
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use crate::capture::{read_capture, DATABASE_SESSION_ID};
+    use crate::{BustubxError, Database, SharedDatabase, Tuple};
+
+    fn contents(db: &SharedDatabase) -> Vec<Vec<Tuple>> {
+        let mut db = db.lock().unwrap();
+        vec![
+            db.run("select * from accounts order by id").unwrap(),
+            db.run("select * from audit order by id").unwrap(),
+        ]
+    }
+
+    #[test]
+    pub fn test_replay_captured_workload() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("capture.log");
+
+        let mut db = Database::new_temp().unwrap();
+        db.start_capture(&path).unwrap();
+        db.run("create table accounts (id int not null, balance int)")
+            .unwrap();
+        db.run("create index accounts_id on accounts (id)").unwrap();
+        db.execute_script(
+            "create table audit (id int, note varchar(20));
+             insert into accounts values (1, 100), (2, 50), (3, 0);",
+        );
+        let db = SharedDatabase::new(db);
+        let mut alice = db.session();
+        let mut bob = db.session();
+        alice
+            .run("update accounts set balance = balance - 30 where id = 1")
+            .unwrap();
+        bob.run("insert into audit values (1, 'withdraw')").unwrap();
+        assert!(bob.run("insert into missing values (1)").is_err());
+        alice
+            .run_transaction(|txn| {
+                txn.run("update accounts set balance = balance + 30 where id = 3")?;
+                txn.run("insert into audit values (2, 'deposit')")?;
+                Ok(())
+            })
+            .unwrap();
+        // rolled back, nothing to replay
+        let _ = bob.run_transaction(|txn| {
+            txn.run("delete from accounts")?;
+            Err::<(), _>(BustubxError::Execution("give up".to_string()))
+        });
+        bob.run("delete from accounts where id = 2").unwrap();
+        assert_eq!(alice.run("select * from accounts").unwrap().len(), 2);
+        db.lock().unwrap().stop_capture();
+
+        let captured = read_capture(&path).unwrap();
+        assert_eq!(captured.len(), 11);
+        assert_eq!(captured[0].session, DATABASE_SESSION_ID);
+        assert_eq!(
+            captured[3].sql,
+            "insert into accounts values (1, 100), (2, 50), (3, 0)"
+        );
+        assert!(captured[6].error.is_some() && captured[6].rows.is_none());
+        assert_eq!(captured[7].transaction, captured[8].transaction);
+        assert!(captured[7].transaction.is_some());
+        assert_ne!(captured[4].session, captured[5].session);
+
+        let replica = SharedDatabase::new(Database::new_temp().unwrap());
+        let report = replica.replay(&path, None).unwrap();
+        assert_eq!(report.statements.len(), 11);
+        assert_eq!(report.mismatches().count(), 0);
+        assert!(report.statements[6].error.is_some());
+        assert_eq!(report.statements[10].rows, Some(2));
+        assert!(report.captured_elapsed() > Duration::ZERO);
+        assert_eq!(contents(&replica), contents(&db));
+    }
+
+    #[test]
+    pub fn test_replay_session_capture_with_timing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("session.log");
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut captured = db.session();
+        let mut other = db.session();
+        captured.start_capture(&path).unwrap();
+        captured.run("create table t (a int)").unwrap();
+        other.run("create table u (a int)").unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        captured.run("insert into t values (1), (2)").unwrap();
+        captured.stop_capture();
+        captured.run("insert into t values (3)").unwrap();
+
+        let statements = read_capture(&path).unwrap();
+        assert_eq!(statements.len(), 2);
+        assert!(statements.iter().all(|s| s.session != DATABASE_SESSION_ID));
+
+        let replica = SharedDatabase::new(Database::new_temp().unwrap());
+        let start = std::time::Instant::now();
+        let report = replica.replay(&path, Some(2.0)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(report.mismatches().count(), 0);
+        assert_eq!(
+            replica
+                .lock()
+                .unwrap()
+                .run("select * from t")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(replica.lock().unwrap().run("select * from u").is_err());
+
+        assert!(matches!(
+            replica.replay(&path, Some(0.0)),
+            Err(BustubxError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+// next to the db file, written by CHECKPOINT and a clean close
+pub const ACCESS_STATS_FILE_SUFFIX: &str = ".stats";
+
+/// Usage counters of a table and its indexes, bumped by the executors and listed by
+/// `information_schema.statistics`. Counting starts at the creation of the table or the
+/// last `RESET STATS`, the counts saved by the last checkpoint are loaded on open.
+#[derive(Debug, Default)]
+pub struct TableAccessStats {
+    // sequential scans started and the rows they returned
+    pub seq_scans: AtomicU64,
+    pub seq_tuples_read: AtomicU64,
+    pub tuples_inserted: AtomicU64,
+    pub tuples_updated: AtomicU64,
+    pub tuples_deleted: AtomicU64,
+    // by index name, an index is added on its first scan
+    indexes: Mutex<HashMap<String, Arc<IndexAccessStats>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexAccessStats {
+    // index scans started and the rows they returned
+    pub scans: AtomicU64,
+    pub tuples_fetched: AtomicU64,
+}
+
+impl TableAccessStats {
+    /// Counters of the index named `index_name`, an index scan keeps them for its rows.
+    pub fn index(&self, index_name: &str) -> Arc<IndexAccessStats> {
+        self.indexes
+            .lock()
+            .unwrap()
+            .entry(index_name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    pub fn counts(&self, index_names: &[&String]) -> TableAccessCounts {
+        let indexes = self.indexes.lock().unwrap();
+        TableAccessCounts {
+            seq_scans: self.seq_scans.load(Ordering::Relaxed),
+            seq_tuples_read: self.seq_tuples_read.load(Ordering::Relaxed),
+            tuples_inserted: self.tuples_inserted.load(Ordering::Relaxed),
+            tuples_updated: self.tuples_updated.load(Ordering::Relaxed),
+            tuples_deleted: self.tuples_deleted.load(Ordering::Relaxed),
+            indexes: index_names
+                .iter()
+                .map(|name| {
+                    // an index never scanned has no counters yet
+                    let index = indexes.get(*name);
+                    let counts = IndexAccessCounts {
+                        scans: index.map_or(0, |index| index.scans.load(Ordering::Relaxed)),
+                        tuples_fetched: index
+                            .map_or(0, |index| index.tuples_fetched.load(Ordering::Relaxed)),
+                    };
+                    (name.to_string(), counts)
+                })
+                .collect(),
+        }
+    }
+
+    /// Set the counters to the ones saved by a checkpoint.
+    pub fn restore(&self, counts: &TableAccessCounts) {
+        self.seq_scans.store(counts.seq_scans, Ordering::Relaxed);
+        self.seq_tuples_read
+            .store(counts.seq_tuples_read, Ordering::Relaxed);
+        self.tuples_inserted
+            .store(counts.tuples_inserted, Ordering::Relaxed);
+        self.tuples_updated
+            .store(counts.tuples_updated, Ordering::Relaxed);
+        self.tuples_deleted
+            .store(counts.tuples_deleted, Ordering::Relaxed);
+        for (name, index_counts) in counts.indexes.iter() {
+            let index = self.index(name);
+            index.scans.store(index_counts.scans, Ordering::Relaxed);
+            index
+                .tuples_fetched
+                .store(index_counts.tuples_fetched, Ordering::Relaxed);
+        }
+    }
+
+    pub fn reset(&self) {
+        self.restore(&TableAccessCounts::default());
+        for index in self.indexes.lock().unwrap().values() {
+            index.scans.store(0, Ordering::Relaxed);
+            index.tuples_fetched.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Values of the counters of a table, as saved next to the db file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableAccessCounts {
+    pub seq_scans: u64,
+    pub seq_tuples_read: u64,
+    pub tuples_inserted: u64,
+    pub tuples_updated: u64,
+    pub tuples_deleted: u64,
+    // by index name, in the order of the names
+    pub indexes: Vec<(String, IndexAccessCounts)>,
+}
+
+impl TableAccessCounts {
+    /// Index scans of the table and the rows they returned, summed over its indexes.
+    pub fn index_totals(&self) -> (u64, u64) {
+        self.indexes
+            .iter()
+            .fold((0, 0), |(scans, fetched), (_, index)| {
+                (scans + index.scans, fetched + index.tuples_fetched)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexAccessCounts {
+    pub scans: u64,
+    pub tuples_fetched: u64,
+}
+
+/// Counters of every table by its qualified name, the content of the stats file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedAccessStats {
+    pub tables: Vec<(String, TableAccessCounts)>,
+}
+
+pub fn access_stats_path(db_path: &std::path::Path) -> Option<PathBuf> {
+    let mut file_name = db_path.file_name()?.to_os_string();
+    file_name.push(ACCESS_STATS_FILE_SUFFIX);
+    Some(db_path.with_file_name(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{IndexAccessCounts, TableAccessCounts};
+    use crate::common::ScalarValue;
+    use crate::{Database, DatabaseOptions};
+
+    fn counters(db: &mut Database, relation_name: &str) -> Vec<Option<u64>> {
+        let sql = format!(
+            "select seq_scans, seq_tuples_read, index_scans, index_tuples_fetched, \
+             tuples_inserted, tuples_updated, tuples_deleted \
+             from information_schema.statistics where relation_name = '{relation_name}'"
+        );
+        db.run(&sql).unwrap()[0]
+            .data
+            .iter()
+            .map(|value| match value {
+                ScalarValue::UInt64(v) => *v,
+                v => panic!("unexpected value {v}"),
+            })
+            .collect()
+    }
+
+    fn fixture(db: &mut Database) {
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create table t2 (c int)").unwrap();
+        db.run("create table t3 (d int)").unwrap();
+        db.run("create index idx_d on t3 (d)").unwrap();
+        let values = (0..10)
+            .map(|a| format!("({a}, {})", a * 10))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+        db.run("insert into t2 values (1), (2), (3), (4), (5)")
+            .unwrap();
+        db.run("insert into t3 values (1), (2), (3)").unwrap();
+    }
+
+    #[test]
+    pub fn test_access_stats_counters() {
+        let mut db = Database::new_temp().unwrap();
+        fixture(&mut db);
+        assert_eq!(
+            counters(&mut db, "t1"),
+            [0u64, 0, 0, 0, 10, 0, 0].map(Some).to_vec()
+        );
+        db.run("reset stats").unwrap();
+
+        // t2 has no index, t1 is read through its only one
+        assert_eq!(db.run("select * from t2").unwrap().len(), 5);
+        assert_eq!(db.run("select * from t2 where c > 2").unwrap().len(), 3);
+        assert_eq!(db.run("select * from t1").unwrap().len(), 10);
+        db.run("update t1 set b = 0 where a < 4").unwrap();
+        db.run("delete from t2 where c = 1").unwrap();
+        db.run("insert into t2 values (7), (8)").unwrap();
+        // the oldest two by the index
+        db.run("delete from t1 order by a limit 2").unwrap();
+
+        assert_eq!(
+            counters(&mut db, "t1"),
+            [0u64, 0, 2, 12, 0, 4, 2].map(Some).to_vec()
+        );
+        assert_eq!(
+            counters(&mut db, "idx_a"),
+            vec![None, None, Some(2), Some(12), None, None, None]
+        );
+        assert_eq!(
+            counters(&mut db, "t2"),
+            [2u64, 10, 0, 0, 2, 0, 1].map(Some).to_vec()
+        );
+        assert_eq!(counters(&mut db, "t3"), [0u64; 7].map(Some).to_vec());
+        // never probed, listed all the same
+        assert_eq!(
+            counters(&mut db, "idx_d"),
+            vec![None, None, Some(0), Some(0), None, None, None]
+        );
+
+        db.run("reset stats").unwrap();
+        for relation_name in ["t1", "t2", "t3"] {
+            assert_eq!(
+                counters(&mut db, relation_name),
+                [0u64; 7].map(Some).to_vec()
+            );
+        }
+        assert_eq!(
+            counters(&mut db, "idx_a"),
+            vec![None, None, Some(0), Some(0), None, None, None]
+        );
+    }
+
+    #[test]
+    pub fn test_access_stats_survive_clean_restart() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        fixture(&mut db);
+        db.run("select * from t1").unwrap();
+        db.run("select * from t2").unwrap();
+        db.run("checkpoint").unwrap();
+        let stats = db.access_stats().unwrap();
+        assert_eq!(
+            stats[0].1,
+            TableAccessCounts {
+                tuples_inserted: 10,
+                indexes: vec![(
+                    "idx_a".to_string(),
+                    IndexAccessCounts {
+                        scans: 1,
+                        tuples_fetched: 10,
+                    },
+                )],
+                ..Default::default()
+            }
+        );
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(db.access_stats().unwrap(), stats);
+        db.run("select * from t2").unwrap();
+        assert_eq!(counters(&mut db, "t2")[..2], [Some(2), Some(10)]);
+        db.close().unwrap();
+
+        // a damaged stats file leaves the counters at zero
+        let mut stats_path = db_path.clone().into_os_string();
+        stats_path.push(super::ACCESS_STATS_FILE_SUFFIX);
+        std::fs::write(&stats_path, b"not json").unwrap();
+        let db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert!(db
+            .access_stats()
+            .unwrap()
+            .iter()
+            .all(|(_, counts)| counts.seq_scans == 0 && counts.tuples_inserted == 0));
+        db.close().unwrap();
+    }
+}
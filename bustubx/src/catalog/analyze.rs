@@ -0,0 +1,441 @@
+//! ANALYZE split in the part reading the table, which runs without the catalog so auto
+//! analyze can run it on a thread of its own, and the part installing its result. Tables
+//! count the rows written since their last ANALYZE, which decides when the statistics are
+//! stale and when auto analyze starts.
+
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::background::{BackgroundTaskRegistry, TaskId};
+use crate::catalog::{
+    DistinctSketch, IndexSize, TableStatistics, INDEX_BLOAT_WARNING_THRESHOLD,
+    STALE_STATISTICS_FRACTION,
+};
+use crate::common::TableReference;
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::TableHeap;
+use crate::transaction::{lock_key, TableLocks};
+use crate::{BustubxError, BustubxResult, Tuple};
+
+/// Rows written to a table since its last ANALYZE, and the distinct value sketches of its
+/// indexed columns kept up by those writes. Bumped by the DML executors.
+#[derive(Debug, Default)]
+pub struct TableModifications {
+    modified: AtomicU64,
+    inserted: AtomicU64,
+    deleted: AtomicU64,
+    // set while auto analyze runs on the table, so it is started once at a time
+    analyzing: AtomicBool,
+    // column position in the row and the sketch of its values, by column name. Built by an
+    // ANALYZE with `distinct_sketches`, empty otherwise
+    sketches: Mutex<HashMap<String, (usize, DistinctSketch)>>,
+}
+
+/// Counts of [`TableModifications`] taken when an ANALYZE started, the rows written
+/// while it ran still count once it is installed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModificationCounts {
+    // inserted, updated and deleted rows
+    pub modified: u64,
+    pub inserted: u64,
+    pub deleted: u64,
+}
+
+impl TableModifications {
+    pub fn row_inserted(&self, tuple: &Tuple) {
+        self.inserted.fetch_add(1, Ordering::Relaxed);
+        self.row_written(Some(tuple));
+    }
+
+    // the new values of the row are added, the old ones stay in the sketches
+    pub fn row_updated(&self, tuple: &Tuple) {
+        self.row_written(Some(tuple));
+    }
+
+    pub fn row_deleted(&self) {
+        self.deleted.fetch_add(1, Ordering::Relaxed);
+        self.row_written(None);
+    }
+
+    fn row_written(&self, tuple: Option<&Tuple>) {
+        self.modified.fetch_add(1, Ordering::Relaxed);
+        let Some(tuple) = tuple else {
+            return;
+        };
+        let mut sketches = self.sketches.lock().unwrap();
+        for (position, sketch) in sketches.values_mut() {
+            if let Some(value) = tuple.data.get(*position) {
+                sketch.add(value);
+            }
+        }
+    }
+
+    pub fn counts(&self) -> ModificationCounts {
+        ModificationCounts {
+            modified: self.modified.load(Ordering::Relaxed),
+            inserted: self.inserted.load(Ordering::Relaxed),
+            deleted: self.deleted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Whether the distinct values of `column` are sketched.
+    pub fn has_sketch(&self, column: &str) -> bool {
+        self.sketches.lock().unwrap().contains_key(column)
+    }
+
+    // an ANALYZE that started at `counts` finished, its sketches replace the ones kept.
+    // An explicit ANALYZE may have finished meanwhile and taken the counts already
+    fn analyzed(
+        &self,
+        counts: ModificationCounts,
+        sketches: HashMap<String, (usize, DistinctSketch)>,
+    ) {
+        for (counter, count) in [
+            (&self.modified, counts.modified),
+            (&self.inserted, counts.inserted),
+            (&self.deleted, counts.deleted),
+        ] {
+            let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                Some(current.saturating_sub(count))
+            });
+        }
+        *self.sketches.lock().unwrap() = sketches;
+    }
+
+    /// `statistics` as the planner sees them. Once stale the row count is moved by the rows
+    /// inserted and deleted since, and the sketched columns take their distinct values from
+    /// the sketches.
+    pub fn refresh(&self, statistics: &Arc<TableStatistics>) -> Arc<TableStatistics> {
+        let counts = self.counts();
+        let stale_above = statistics.row_count.max(1) as f64 * STALE_STATISTICS_FRACTION;
+        if counts.modified as f64 <= stale_above {
+            return statistics.clone();
+        }
+        let mut refreshed = statistics.as_ref().clone();
+        refreshed.row_count =
+            (refreshed.row_count as u64 + counts.inserted).saturating_sub(counts.deleted) as usize;
+        for (column, (_, sketch)) in self.sketches.lock().unwrap().iter() {
+            if let Some(column_statistics) = refreshed.columns.get_mut(column) {
+                column_statistics.ndv = sketch.estimate().min(refreshed.row_count);
+            }
+        }
+        Arc::new(refreshed)
+    }
+}
+
+/// Reads a table for ANALYZE, see [`crate::catalog::Catalog::analyze_job`].
+#[derive(Debug)]
+pub struct AnalyzeJob {
+    pub table_ref: TableReference,
+    pub(crate) heap: Arc<TableHeap>,
+    pub(crate) indexes: Vec<(String, Arc<BPlusTreeIndex>)>,
+    // name and position of the columns to sketch, none without `distinct_sketches`
+    pub(crate) sketch_columns: Vec<(String, usize)>,
+    pub(crate) bucket_count: usize,
+    pub(crate) sample_size: usize,
+    pub(crate) modifications: Arc<TableModifications>,
+    pub(crate) counts: ModificationCounts,
+    // lock of the table held until the job is done, taken by auto analyze
+    pub(crate) table_locks: Option<TableLocks>,
+    // set by a statement dropping or rewriting the table, see `AutoAnalyzer::cancel`
+    pub(crate) cancelled: Arc<AtomicBool>,
+}
+
+/// Result of an [`AnalyzeJob`], installed by
+/// [`crate::catalog::Catalog::install_analyzed`].
+#[derive(Debug)]
+pub struct AnalyzedTable {
+    pub table_ref: TableReference,
+    pub(crate) heap: Arc<TableHeap>,
+    pub statistics: TableStatistics,
+    pub(crate) sketches: HashMap<String, (usize, DistinctSketch)>,
+    pub(crate) modifications: Arc<TableModifications>,
+    pub(crate) counts: ModificationCounts,
+}
+
+impl AnalyzeJob {
+    /// Hold `table_locks` while the job reads the table, released once it is done.
+    pub fn with_table_locks(mut self, table_locks: TableLocks) -> Self {
+        self.table_locks = Some(table_locks);
+        self
+    }
+
+    pub fn run(self) -> BustubxResult<AnalyzedTable> {
+        self.check_cancelled()?;
+        let mut statistics =
+            TableStatistics::analyze(&self.heap, self.bucket_count, self.sample_size)?;
+        for (index_name, index) in self.indexes.iter() {
+            self.check_cancelled()?;
+            let bloat = IndexSize::measure(index_name, index)?.bloat();
+            if bloat > INDEX_BLOAT_WARNING_THRESHOLD {
+                warn!(
+                    "index {} of table {} is {:.0}% bloated, REINDEX would shrink it",
+                    index_name,
+                    self.table_ref,
+                    bloat * 100.0
+                );
+            }
+            statistics.index_bloat.insert(index_name.clone(), bloat);
+        }
+        Ok(AnalyzedTable {
+            sketches: self.sketch_live_rows()?,
+            table_ref: self.table_ref,
+            heap: self.heap,
+            statistics,
+            modifications: self.modifications,
+            counts: self.counts,
+        })
+    }
+
+    // every live row, a sample would miss most of the distinct values
+    fn sketch_live_rows(&self) -> BustubxResult<HashMap<String, (usize, DistinctSketch)>> {
+        let mut sketches = self
+            .sketch_columns
+            .iter()
+            .map(|(name, position)| (name.clone(), (*position, DistinctSketch::default())))
+            .collect::<HashMap<_, _>>();
+        if sketches.is_empty() {
+            return Ok(sketches);
+        }
+        for page_id in self.heap.page_ids()? {
+            self.check_cancelled()?;
+            for (_, meta, tuple) in self.heap.page_tuples(page_id)? {
+                if meta.is_deleted {
+                    continue;
+                }
+                for (position, sketch) in sketches.values_mut() {
+                    sketch.add(&tuple.data[*position]);
+                }
+            }
+        }
+        Ok(sketches)
+    }
+
+    fn check_cancelled(&self) -> BustubxResult<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(BustubxError::Execution(format!(
+                "analyze of {} cancelled",
+                self.table_ref
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl AnalyzedTable {
+    // the counts and sketches of the table, its statistics are left to the catalog
+    pub(crate) fn install(self) -> TableStatistics {
+        self.modifications.analyzed(self.counts, self.sketches);
+        self.modifications.analyzing.store(false, Ordering::Relaxed);
+        self.statistics
+    }
+}
+
+/// Runs the ANALYZE of tables written more than `auto_analyze_threshold` rows since their
+/// last one, each as a task of its own. The statistics are installed by the next statement
+/// after they are done. A job holds the ACCESS SHARE lock of its table while it runs, so
+/// statements dropping or rewriting the table cancel it and wait for it to let go.
+#[derive(Debug)]
+pub struct AutoAnalyzer {
+    sender: Sender<BustubxResult<AnalyzedTable>>,
+    // behind a mutex for the database to stay Sync
+    receiver: Mutex<Receiver<BustubxResult<AnalyzedTable>>>,
+    // the table of each task and its cancel flag
+    tasks: Vec<(TaskId, TableReference, Arc<AtomicBool>)>,
+    // analyses started, listed by `SHOW STATS`
+    runs: u64,
+}
+
+impl Default for AutoAnalyzer {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            tasks: vec![],
+            runs: 0,
+        }
+    }
+}
+
+impl AutoAnalyzer {
+    /// Start `job` unless the table is analyzed already.
+    pub fn start(
+        &mut self,
+        registry: &mut BackgroundTaskRegistry,
+        job: AnalyzeJob,
+    ) -> BustubxResult<()> {
+        if job.modifications.analyzing.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        debug!(
+            "auto analyze of {} after {} rows written",
+            job.table_ref, job.counts.modified
+        );
+        self.tasks.retain(|(id, _, _)| registry.contains(*id));
+        let table_ref = lock_key(&job.table_ref);
+        let cancelled = job.cancelled.clone();
+        let modifications = job.modifications.clone();
+        let sender = self.sender.clone();
+        let spawned = registry.spawn("auto-analyze", move |token| {
+            // the database is closing, the table is analyzed after it opens again
+            if token.is_shutdown() {
+                return;
+            }
+            let modifications = job.modifications.clone();
+            let cancelled = job.cancelled.clone();
+            let result = job.run();
+            if result.is_err() {
+                modifications.analyzing.store(false, Ordering::Relaxed);
+                // the table is gone or rewritten, nothing to report
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+            }
+            let _ = sender.send(result);
+        });
+        match spawned {
+            Ok(id) => {
+                self.runs += 1;
+                self.tasks.push((id, table_ref, cancelled));
+                Ok(())
+            }
+            Err(e) => {
+                modifications.analyzing.store(false, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop the analyses of `table_ref` still running, which give up its lock as soon as
+    /// they notice.
+    pub fn cancel(&mut self, table_ref: &TableReference) {
+        let table_ref = lock_key(table_ref);
+        for (_, table, cancelled) in self.tasks.iter() {
+            if *table == table_ref {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Analyses done so far, without waiting for the others.
+    pub fn finished(&mut self) -> Vec<BustubxResult<AnalyzedTable>> {
+        self.receiver.lock().unwrap().try_iter().collect()
+    }
+
+    /// Wait for every analysis started, then return the ones not taken yet.
+    pub fn wait(
+        &mut self,
+        registry: &mut BackgroundTaskRegistry,
+    ) -> Vec<BustubxResult<AnalyzedTable>> {
+        for (id, _, _) in self.tasks.drain(..) {
+            registry.join(id);
+        }
+        self.finished()
+    }
+
+    pub fn runs(&self) -> u64 {
+        self.runs
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.runs = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TableReference;
+    use crate::transaction::TableLockMode;
+    use crate::Database;
+
+    #[test]
+    pub fn test_auto_analyze_after_threshold() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("set auto_analyze_threshold = 500").unwrap();
+        let insert = |db: &mut Database, rows: std::ops::Range<i32>| {
+            let values = rows.map(|i| format!("({i})")).collect::<Vec<_>>();
+            db.run(&format!("insert into t1 values {}", values.join(", ")))
+                .unwrap();
+        };
+        let table_ref = TableReference::bare("t1");
+
+        insert(&mut db, 0..400);
+        assert_eq!(db.runtime_stats().auto_analyze_runs, 0);
+        // the rows deleted count as well
+        db.run("delete from t1 where a < 100").unwrap();
+        assert_eq!(db.runtime_stats().auto_analyze_runs, 1);
+        db.wait_for_auto_analyze().unwrap();
+        let stats = db.catalog.table_statistics(&table_ref).unwrap();
+        assert_eq!(stats.row_count, 300);
+
+        // counting starts over after the analyze
+        insert(&mut db, 400..800);
+        assert_eq!(db.runtime_stats().auto_analyze_runs, 1);
+        insert(&mut db, 800..900);
+        assert_eq!(db.runtime_stats().auto_analyze_runs, 2);
+        db.wait_for_auto_analyze().unwrap();
+        assert_eq!(
+            db.catalog.table_statistics(&table_ref).unwrap().row_count,
+            800
+        );
+        assert_eq!(
+            db.catalog
+                .catalog_table(&table_ref)
+                .unwrap()
+                .modifications
+                .counts()
+                .modified,
+            0
+        );
+
+        db.run("set auto_analyze_threshold = 0").unwrap();
+        insert(&mut db, 900..1500);
+        assert_eq!(db.runtime_stats().auto_analyze_runs, 2);
+    }
+
+    // Tables dropped right after the write starting their analyze. The job holds the
+    // ACCESS SHARE lock of the table, the DROP cancels it and waits for the lock.
+    #[test]
+    pub fn test_drop_table_during_auto_analyze() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("set auto_analyze_threshold = 10").unwrap();
+        db.run("set lock_timeout = '10s'").unwrap();
+        let values = (0..2000)
+            .map(|i| format!("({i}, {})", i % 7))
+            .collect::<Vec<_>>();
+        let table_ref = TableReference::bare("t1");
+        for round in 0..10 {
+            db.run("create table t1 (a int, b int)").unwrap();
+            db.run("create index idx_b on t1 (b)").unwrap();
+            db.run(&format!("insert into t1 values {}", values.join(", ")))
+                .unwrap();
+            assert_eq!(db.runtime_stats().auto_analyze_runs, round + 1);
+            // held until the job is done
+            assert!(db
+                .lock_manager()
+                .table_locks()
+                .iter()
+                .all(|lock| lock.mode == TableLockMode::AccessShare && lock.granted));
+            db.run("drop table t1").unwrap();
+            assert!(db.lock_manager().table_locks().is_empty());
+        }
+        db.wait_for_auto_analyze().unwrap();
+        assert!(db.lock_manager().table_locks().is_empty());
+        assert!(db.catalog.table_statistics(&table_ref).is_none());
+
+        // the table is analyzed again once it is written to
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run(&format!("insert into t1 values {}", values.join(", ")))
+            .unwrap();
+        db.wait_for_auto_analyze().unwrap();
+        assert_eq!(
+            db.catalog.table_statistics(&table_ref).unwrap().row_count,
+            2000
+        );
+    }
+}
@@ -1,17 +1,35 @@
-use std::collections::HashMap;
-use std::sync::atomic::Ordering;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 
+use crate::buffer::PageId;
+use crate::catalog::index_build::build_index;
 use crate::catalog::{
-    key_schema_to_varchar, SchemaRef, COLUMNS_SCHMEA, INDEXES_SCHMEA, INFORMATION_SCHEMA_COLUMNS,
-    INFORMATION_SCHEMA_INDEXES, INFORMATION_SCHEMA_NAME, INFORMATION_SCHEMA_SCHEMAS,
-    INFORMATION_SCHEMA_TABLES, SCHEMAS_SCHMEA, TABLES_SCHMEA,
+    access_stats_path, foreign_keys_to_varchar, key_columns_to_varchar, key_schema_to_varchar,
+    IndexSize, PageOwner, SchemaRef, TableSize, TableStatistics, COLUMNS_SCHMEA, INDEXES_SCHMEA,
+    INFORMATION_SCHEMA_COLUMNS, INFORMATION_SCHEMA_INDEXES, INFORMATION_SCHEMA_NAME,
+    INFORMATION_SCHEMA_SCHEMAS, INFORMATION_SCHEMA_TABLES, SCHEMAS_SCHMEA, TABLES_SCHMEA,
+};
+use crate::catalog::{
+    AnalyzeJob, AnalyzedTable, ForeignKey, IndexBuild, KeyPart, KeyProjection, SavedAccessStats,
+    TableAccessCounts, TableAccessStats, TableModifications,
+};
+use crate::common::value_ord::Collation;
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::{TriggerEvent, TriggerFn, Triggers};
+use crate::function::FunctionRegistry;
+use crate::storage::{
+    Finding, LeafKV, RecordId, RowIdMap, Severity, TableIterator, TupleMeta,
+    BPLUS_INTERNAL_PAGE_MAX_SIZE, BPLUS_LEAF_PAGE_MAX_SIZE, EMPTY_TUPLE_META, ROW_ID_INDEX_NAME,
+    ROW_ID_KEY_SCHEMA,
 };
-use crate::common::TableReference;
-use crate::storage::{BPLUS_INTERNAL_PAGE_MAX_SIZE, BPLUS_LEAF_PAGE_MAX_SIZE, EMPTY_TUPLE_META};
 use crate::{
     buffer::BufferPoolManager,
-    storage::{index::BPlusTreeIndex, TableHeap},
+    storage::{
+        index::{BPlusTreeIndex, ConstraintTiming, TreeIndexIterator},
+        TableHeap,
+    },
     BustubxError, BustubxResult, Tuple,
 };
 
@@ -22,6 +40,10 @@ pub static DEFAULT_SCHEMA_NAME: &str = "public";
 pub struct Catalog {
     pub schemas: HashMap<String, CatalogSchema>,
     pub buffer_pool: Arc<BufferPoolManager>,
+    // index entries skipped by scans because their row is gone, reset by `reset_stats`
+    dangling_index_entries: AtomicU64,
+    // registered with `Database::register_function`, resolved by the binder
+    pub functions: FunctionRegistry,
 }
 
 #[derive(Debug)]
@@ -44,6 +66,55 @@ pub struct CatalogTable {
     pub name: String,
     pub table: Arc<TableHeap>,
     pub indexes: HashMap<String, Arc<BPlusTreeIndex>>,
+    // by index name, derives the keys of each index from the rows of the table
+    key_projections: HashMap<String, Arc<KeyProjection>>,
+    // expression indexes whose key calls a function not registered yet, by index name with
+    // the function, they have no key projection until it is registered
+    unresolved_indexes: HashMap<String, String>,
+    // collected by analyze, None until the table is analyzed
+    pub statistics: Option<Arc<TableStatistics>>,
+    // rows written since the last analyze, see `Catalog::table_statistics`
+    pub modifications: Arc<TableModifications>,
+    // rows whose value in this column is before the current time are expired
+    pub ttl_column: Option<String>,
+    // set for a table created with logical row ids, its indexes then store row ids
+    pub row_ids: Option<Arc<RowIdMap>>,
+    // bumped by every change of the indexes or statistics, plans made before are stale
+    pub version: u64,
+    // set for a table partitioned by range and for each of its partitions
+    pub partitioning: Option<Partitioning>,
+    pub access_stats: Arc<TableAccessStats>,
+    // set by `ALTER TABLE ... SET read_only`, statements writing the table fail to plan
+    pub read_only: bool,
+    // index whose key order the heap was rewritten in by the last `CLUSTER`
+    pub clustered_index: Option<String>,
+    // registered with `Database::register_trigger`, fired by the DML executors
+    pub triggers: Triggers,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+/// Place of a table in range partitioning, parent and partitions are in the same schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Partitioning {
+    /// `PARTITION BY RANGE (column)`, the rows are stored in the partitions and the heap of
+    /// the table itself stays empty.
+    Range { column: String },
+    /// `PARTITION OF parent FOR VALUES FROM (from) TO (to)`, holds the rows whose `column`
+    /// is in `[from, to)`.
+    Partition {
+        parent: String,
+        column: String,
+        from: ScalarValue,
+        to: ScalarValue,
+    },
+}
+
+impl Partitioning {
+    pub fn column(&self) -> &str {
+        match self {
+            Partitioning::Range { column } | Partitioning::Partition { column, .. } => column,
+        }
+    }
 }
 
 impl CatalogTable {
@@ -52,8 +123,263 @@ impl CatalogTable {
             name: name.into(),
             table,
             indexes: HashMap::new(),
+            key_projections: HashMap::new(),
+            unresolved_indexes: HashMap::new(),
+            statistics: None,
+            modifications: Arc::new(TableModifications::default()),
+            ttl_column: None,
+            row_ids: None,
+            version: 0,
+            partitioning: None,
+            access_stats: Arc::new(TableAccessStats::default()),
+            read_only: false,
+            clustered_index: None,
+            triggers: Triggers::default(),
+            foreign_keys: vec![],
+        }
+    }
+
+    /// Register an index of the table, its key columns must be columns of the table.
+    pub fn add_index(
+        &mut self,
+        index_name: String,
+        index: Arc<BPlusTreeIndex>,
+    ) -> BustubxResult<()> {
+        let projection = KeyProjection::try_new(&self.table.schema, index.key_schema.clone())?;
+        self.key_projections
+            .insert(index_name.clone(), Arc::new(projection));
+        self.indexes.insert(index_name, index);
+        Ok(())
+    }
+
+    /// Register an index whose keys are derived by `parts`, e.g. with an expression key.
+    pub fn add_index_with_parts(
+        &mut self,
+        index_name: String,
+        index: Arc<BPlusTreeIndex>,
+        parts: Vec<KeyPart>,
+    ) -> BustubxResult<()> {
+        let projection =
+            KeyProjection::try_new_with_parts(&self.table.schema, index.key_schema.clone(), parts)?;
+        self.unresolved_indexes.remove(&index_name);
+        self.key_projections
+            .insert(index_name.clone(), Arc::new(projection));
+        self.indexes.insert(index_name, index);
+        Ok(())
+    }
+
+    /// Register an expression index whose key calls `function`, which is not registered.
+    /// Maintaining the index fails until [`CatalogTable::add_index_with_parts`] resolves it.
+    pub fn add_unresolved_index(
+        &mut self,
+        index_name: String,
+        index: Arc<BPlusTreeIndex>,
+        function: String,
+    ) {
+        self.key_projections.remove(&index_name);
+        self.unresolved_indexes.insert(index_name.clone(), function);
+        self.indexes.insert(index_name, index);
+    }
+
+    /// Names of the indexes added by [`CatalogTable::add_unresolved_index`] and not
+    /// resolved since.
+    pub fn unresolved_indexes(&self) -> impl Iterator<Item = &String> {
+        self.unresolved_indexes.keys()
+    }
+
+    /// The projection deriving the keys of the index from rows of the table, shared by
+    /// every statement maintaining the index.
+    pub fn key_projection(&self, index_name: &str) -> BustubxResult<Arc<KeyProjection>> {
+        if let Some(function) = self.unresolved_indexes.get(index_name) {
+            return Err(BustubxError::UnresolvedIndexKey {
+                table: self.name.clone(),
+                index: index_name.to_string(),
+                function: function.clone(),
+            });
+        }
+        self.key_projections
+            .get(index_name)
+            .cloned()
+            .ok_or_else(|| {
+                BustubxError::Internal(format!(
+                    "index {} of table {} has no key projection",
+                    index_name, self.name
+                ))
+            })
+    }
+
+    pub fn with_ttl_column(mut self, ttl_column: Option<String>) -> Self {
+        self.ttl_column = ttl_column;
+        self
+    }
+
+    pub fn with_partitioning(mut self, partitioning: Option<Partitioning>) -> Self {
+        self.partitioning = partitioning;
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_clustered_index(mut self, clustered_index: Option<String>) -> Self {
+        self.clustered_index = clustered_index;
+        self
+    }
+
+    pub fn with_foreign_keys(mut self, foreign_keys: Vec<ForeignKey>) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    /// Whether the table is partitioned by range, its rows are in its partitions.
+    pub fn is_partitioned(&self) -> bool {
+        matches!(self.partitioning, Some(Partitioning::Range { .. }))
+    }
+
+    /// Fails for a row of a partition whose key is outside the range of the partition.
+    pub fn check_partition_key(&self, tuple: &Tuple) -> BustubxResult<()> {
+        let Some(Partitioning::Partition {
+            column, from, to, ..
+        }) = &self.partitioning
+        else {
+            return Ok(());
+        };
+        let key = tuple.value_by_name(None, column)?;
+        if !partition_contains(from, to, key) {
+            return Err(BustubxError::Execution(format!(
+                "{} = {} is outside the range [{}, {}) of partition {}",
+                column, key, from, to, self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Insert a new row, it gets the next logical row id when the table has them.
+    pub fn insert_tuple(&self, tuple: &Tuple) -> BustubxResult<RecordId> {
+        let Some(row_ids) = &self.row_ids else {
+            return self.table.insert_tuple(&EMPTY_TUPLE_META, tuple);
+        };
+        let row_id = row_ids.allocate();
+        let meta = TupleMeta {
+            row_id: Some(row_id),
+            ..EMPTY_TUPLE_META
+        };
+        let rid = self.table.insert_tuple(&meta, tuple)?;
+        row_ids.map(row_id, rid)?;
+        Ok(rid)
+    }
+
+    /// Value the indexes of the table store for the row at `rid`.
+    pub fn index_entry(&self, rid: RecordId) -> BustubxResult<RecordId> {
+        if self.row_ids.is_none() {
+            return Ok(rid);
+        }
+        match self.table.tuple_meta(rid)?.row_id {
+            Some(row_id) => Ok(RowIdMap::index_entry(row_id)),
+            None => Err(BustubxError::Internal(format!(
+                "row {:?} of table {} has no row id",
+                rid, self.name
+            ))),
+        }
+    }
+
+    /// Record id of the row an index entry of the table points to, None if it is gone.
+    pub fn resolve_index_entry(&self, entry: RecordId) -> BustubxResult<Option<RecordId>> {
+        match &self.row_ids {
+            Some(row_ids) => row_ids.resolve(RowIdMap::row_id_of(entry)),
+            None => Ok(Some(entry)),
+        }
+    }
+
+    /// Whether the ttl column of the row is before `now`, rows with a null ttl never expire.
+    pub fn is_expired(&self, tuple: &Tuple, now: i64) -> BustubxResult<bool> {
+        let Some(ttl_column) = &self.ttl_column else {
+            return Ok(false);
+        };
+        match tuple.value_by_name(None, ttl_column)? {
+            ScalarValue::Int64(Some(expires_at)) => Ok(*expires_at < now),
+            _ => Ok(false),
+        }
+    }
+}
+
+// Every live row must have the entry of its key in the index and every entry must point
+// at a live row holding its key. An entry of a deleted row is only a warning, scans skip
+// it and REINDEX removes it.
+fn check_index_entries(
+    catalog_table: &CatalogTable,
+    index_name: &str,
+    rows: &[(RecordId, RecordId, Tuple)],
+    entries: Vec<(PageId, LeafKV)>,
+) -> BustubxResult<Vec<Finding>> {
+    let mut findings = vec![];
+    // leaf page and key of the entries, by the index entry they store
+    let mut unmatched: HashMap<RecordId, Vec<(PageId, Tuple)>> = HashMap::new();
+    for (page_id, (key, entry)) in entries {
+        unmatched.entry(entry).or_default().push((page_id, key));
+    }
+    let projection = catalog_table.key_projection(index_name)?;
+    let mut live_rids = HashSet::new();
+    for (rid, entry, tuple) in rows {
+        live_rids.insert(*rid);
+        let key = projection.project(tuple)?;
+        let matched = unmatched.get_mut(entry).and_then(|keys| {
+            keys.iter()
+                .position(|(_, k)| *k == key)
+                .map(|i| keys.remove(i))
+        });
+        if matched.is_none() {
+            findings.push(Finding::error(
+                index_name,
+                Some(rid.page_id),
+                format!("row in slot {} of the heap page has no entry", rid.slot_num),
+            ));
         }
     }
+
+    let mut unmatched = unmatched
+        .into_iter()
+        .flat_map(|(entry, keys)| keys.into_iter().map(move |(page_id, _)| (page_id, entry)))
+        .collect::<Vec<_>>();
+    unmatched.sort_by_key(|(page_id, entry)| (*page_id, entry.page_id, entry.slot_num));
+    for (page_id, entry) in unmatched {
+        let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+            findings.push(Finding::error(
+                index_name,
+                Some(page_id),
+                format!(
+                    "entry points at row id {} mapped to no row",
+                    RowIdMap::row_id_of(entry)
+                ),
+            ));
+            continue;
+        };
+        let target = format!("slot {} of heap page {}", rid.slot_num, rid.page_id);
+        let finding = if live_rids.contains(&rid) {
+            Finding::error(
+                index_name,
+                Some(page_id),
+                format!("entry points at {target} holding another key"),
+            )
+        } else {
+            match catalog_table.table.tuple_meta(rid) {
+                Ok(meta) if meta.is_deleted => Finding::warning(
+                    index_name,
+                    Some(page_id),
+                    format!("entry points at deleted row in {target}, REINDEX removes it"),
+                ),
+                _ => Finding::error(
+                    index_name,
+                    Some(page_id),
+                    format!("entry points at {target} holding no row"),
+                ),
+            }
+        };
+        findings.push(finding);
+    }
+    Ok(findings)
 }
 
 impl Catalog {
@@ -61,7 +387,83 @@ impl Catalog {
         Self {
             schemas: HashMap::new(),
             buffer_pool,
+            dangling_index_entries: AtomicU64::new(0),
+            functions: FunctionRegistry::default(),
+        }
+    }
+
+    /// An index scan found an entry whose row is deleted or whose page was reused.
+    pub fn record_dangling_index_entry(&self) {
+        self.dangling_index_entries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dangling_index_entries(&self) -> u64 {
+        self.dangling_index_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_stats(&self) {
+        self.dangling_index_entries.store(0, Ordering::Relaxed);
+        for catalog_schema in self.schemas.values() {
+            for catalog_table in catalog_schema.tables.values() {
+                catalog_table.access_stats.reset();
+            }
+        }
+    }
+
+    /// Access counters of every table outside information_schema and of its indexes,
+    /// ordered by schema and table name, see `information_schema.statistics`.
+    pub fn access_stats(&self) -> BustubxResult<Vec<(TableReference, TableAccessCounts)>> {
+        let mut stats = vec![];
+        for table_ref in self.user_tables() {
+            let catalog_table = self.catalog_table(&table_ref)?;
+            let mut index_names = catalog_table.indexes.keys().collect::<Vec<_>>();
+            index_names.sort();
+            stats.push((table_ref, catalog_table.access_stats.counts(&index_names)));
+        }
+        Ok(stats)
+    }
+
+    /// Write the access counters next to the db file, replacing the ones saved before.
+    pub fn save_access_stats(&self) -> BustubxResult<()> {
+        let Some(path) = access_stats_path(self.buffer_pool.disk_manager.path()) else {
+            return Ok(());
+        };
+        let saved = SavedAccessStats {
+            tables: self
+                .access_stats()?
+                .into_iter()
+                .map(|(table_ref, counts)| (qualified_table_name(&table_ref), counts))
+                .collect(),
+        };
+        let json = serde_json::to_vec(&saved)
+            .map_err(|e| BustubxError::Internal(format!("cannot encode access stats: {e}")))?;
+        let mut temp_name = path.as_os_str().to_os_string();
+        temp_name.push(".tmp");
+        std::fs::write(&temp_name, json)?;
+        std::fs::rename(&temp_name, &path)?;
+        Ok(())
+    }
+
+    /// Set the access counters to the ones saved last, counters of tables dropped since
+    /// are ignored.
+    pub fn load_access_stats(&self) -> BustubxResult<()> {
+        let Some(path) = access_stats_path(self.buffer_pool.disk_manager.path()) else {
+            return Ok(());
+        };
+        let json = match std::fs::read(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        let saved: SavedAccessStats = serde_json::from_slice(&json)
+            .map_err(|e| BustubxError::Storage(format!("cannot decode {}: {e}", path.display())))?;
+        let saved = saved.tables.into_iter().collect::<HashMap<_, _>>();
+        for table_ref in self.user_tables() {
+            if let Some(counts) = saved.get(&qualified_table_name(&table_ref)) {
+                self.catalog_table(&table_ref)?.access_stats.restore(counts);
+            }
         }
+        Ok(())
     }
 
     pub fn create_schema(&mut self, schema_name: impl Into<String>) -> BustubxResult<()> {
@@ -106,6 +508,9 @@ impl Catalog {
         &mut self,
         table_ref: TableReference,
         schema: SchemaRef,
+        ttl_column: Option<String>,
+        logical_row_ids: bool,
+        partitioning: Option<Partitioning>,
     ) -> BustubxResult<Arc<TableHeap>> {
         let catalog_name = table_ref
             .catalog()
@@ -128,14 +533,47 @@ impl Catalog {
                 "Cannot create duplicated table".to_string(),
             ));
         }
+        if let Some(Partitioning::Partition {
+            parent, from, to, ..
+        }) = &partitioning
+        {
+            check_partition_bound(catalog_schema, parent, from, to)?;
+            if let Some(parent_table) = catalog_schema.tables.get_mut(parent) {
+                parent_table.version += 1;
+            }
+        }
         let table_heap = Arc::new(TableHeap::try_new(
             schema.clone(),
             self.buffer_pool.clone(),
         )?);
+        let row_ids = logical_row_ids.then(|| {
+            Arc::new(RowIdMap::new(
+                Arc::new(BPlusTreeIndex::new(
+                    ROW_ID_KEY_SCHEMA.clone(),
+                    self.buffer_pool.clone(),
+                    BPLUS_INTERNAL_PAGE_MAX_SIZE as u32,
+                    BPLUS_LEAF_PAGE_MAX_SIZE as u32,
+                )),
+                0,
+            ))
+        });
         let catalog_table = CatalogTable {
             name: table_name.clone(),
             table: table_heap.clone(),
             indexes: HashMap::new(),
+            key_projections: HashMap::new(),
+            unresolved_indexes: HashMap::new(),
+            statistics: None,
+            modifications: Arc::new(TableModifications::default()),
+            ttl_column: ttl_column.clone(),
+            row_ids: row_ids.clone(),
+            version: 0,
+            partitioning: partitioning.clone(),
+            access_stats: Arc::new(TableAccessStats::default()),
+            read_only: false,
+            clustered_index: None,
+            triggers: Triggers::default(),
+            foreign_keys: vec![],
         };
         catalog_schema
             .tables
@@ -161,6 +599,23 @@ impl Catalog {
                 catalog_schema_name.clone().into(),
                 table_name.clone().into(),
                 (table_heap.first_page_id.load(Ordering::SeqCst)).into(),
+                ScalarValue::Varchar(ttl_column),
+                ScalarValue::Varchar(partitioning.as_ref().map(|p| p.column().to_string())),
+                ScalarValue::Varchar(match &partitioning {
+                    Some(Partitioning::Partition { parent, .. }) => Some(parent.clone()),
+                    _ => None,
+                }),
+                ScalarValue::Varchar(match &partitioning {
+                    Some(Partitioning::Partition { from, .. }) => Some(format!("{from}")),
+                    _ => None,
+                }),
+                ScalarValue::Varchar(match &partitioning {
+                    Some(Partitioning::Partition { to, .. }) => Some(format!("{to}")),
+                    _ => None,
+                }),
+                false.into(),
+                ScalarValue::Varchar(None),
+                ScalarValue::Varchar(None),
             ],
         );
         tables_table.table.insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
@@ -185,6 +640,13 @@ impl Catalog {
                     format!("{sql_type}").into(),
                     col.nullable.into(),
                     format!("{}", col.default).into(),
+                    col.default_expr
+                        .as_ref()
+                        .map(|default_expr| default_expr.sql.clone())
+                        .into(),
+                    (col.collation != Collation::Binary)
+                        .then(|| col.collation.to_string())
+                        .into(),
                 ],
             );
             columns_table
@@ -192,140 +654,107 @@ impl Catalog {
                 .insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
         }
 
+        // the mapping is recorded like an index of the table
+        if let Some(row_ids) = row_ids {
+            let Some(indexes_table) = information_schema
+                .tables
+                .get_mut(INFORMATION_SCHEMA_INDEXES)
+            else {
+                return Err(BustubxError::Internal(
+                    "table information_schema.indexes not created yet".to_string(),
+                ));
+            };
+            let tuple = Tuple::new(
+                INDEXES_SCHMEA.clone(),
+                vec![
+                    catalog_name.clone().into(),
+                    catalog_schema_name.clone().into(),
+                    table_name.clone().into(),
+                    ROW_ID_INDEX_NAME.to_string().into(),
+                    key_schema_to_varchar(&row_ids.index.key_schema).into(),
+                    row_ids.index.internal_max_size.into(),
+                    row_ids.index.leaf_max_size.into(),
+                    row_ids.index.root_page_id.load(Ordering::SeqCst).into(),
+                    true.into(),
+                    ScalarValue::Varchar(None),
+                    ScalarValue::Varchar(None),
+                ],
+            );
+            indexes_table
+                .table
+                .insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+        }
+
         Ok(table_heap)
     }
 
-    pub fn table_heap(&self, table_ref: &TableReference) -> BustubxResult<Arc<TableHeap>> {
-        let catalog_schema_name = table_ref
-            .schema()
-            .unwrap_or(DEFAULT_SCHEMA_NAME)
-            .to_string();
-        let table_name = table_ref.table().to_string();
+    pub fn catalog_table(&self, table_ref: &TableReference) -> BustubxResult<&CatalogTable> {
+        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let table_name = table_ref.table();
 
-        let Some(catalog_schema) = self.schemas.get(&catalog_schema_name) else {
+        let Some(catalog_schema) = self.schemas.get(catalog_schema_name) else {
             return Err(BustubxError::Storage(format!(
                 "catalog schema {} not created yet",
                 catalog_schema_name
             )));
         };
-        let Some(catalog_table) = catalog_schema.tables.get(&table_name) else {
+        let Some(catalog_table) = catalog_schema.tables.get(table_name) else {
             return Err(BustubxError::Storage(format!(
                 "table {} not created yet",
                 table_name
             )));
         };
-        Ok(catalog_table.table.clone())
+        Ok(catalog_table)
     }
 
-    pub fn table_indexes(
-        &self,
+    pub fn catalog_table_mut(
+        &mut self,
         table_ref: &TableReference,
-    ) -> BustubxResult<Vec<Arc<BPlusTreeIndex>>> {
-        let catalog_schema_name = table_ref
-            .schema()
-            .unwrap_or(DEFAULT_SCHEMA_NAME)
-            .to_string();
-        let table_name = table_ref.table().to_string();
+    ) -> BustubxResult<&mut CatalogTable> {
+        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let table_name = table_ref.table();
 
-        let Some(catalog_schema) = self.schemas.get(&catalog_schema_name) else {
+        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
             return Err(BustubxError::Storage(format!(
                 "catalog schema {} not created yet",
                 catalog_schema_name
             )));
         };
-        let Some(catalog_table) = catalog_schema.tables.get(&table_name) else {
+        let Some(catalog_table) = catalog_schema.tables.get_mut(table_name) else {
             return Err(BustubxError::Storage(format!(
                 "table {} not created yet",
                 table_name
             )));
         };
-        Ok(catalog_table.indexes.values().cloned().collect())
+        Ok(catalog_table)
     }
 
-    pub fn create_index(
-        &mut self,
-        index_name: String,
-        table_ref: &TableReference,
-        key_schema: SchemaRef,
-    ) -> BustubxResult<Arc<BPlusTreeIndex>> {
-        let catalog_name = table_ref
-            .catalog()
-            .unwrap_or(DEFAULT_CATALOG_NAME)
-            .to_string();
+    pub fn table_heap(&self, table_ref: &TableReference) -> BustubxResult<Arc<TableHeap>> {
         let catalog_schema_name = table_ref
             .schema()
             .unwrap_or(DEFAULT_SCHEMA_NAME)
             .to_string();
         let table_name = table_ref.table().to_string();
 
-        let Some(catalog_schema) = self.schemas.get_mut(&catalog_schema_name) else {
+        let Some(catalog_schema) = self.schemas.get(&catalog_schema_name) else {
             return Err(BustubxError::Storage(format!(
                 "catalog schema {} not created yet",
                 catalog_schema_name
             )));
         };
-        let Some(catalog_table) = catalog_schema.tables.get_mut(&table_name) else {
+        let Some(catalog_table) = catalog_schema.tables.get(&table_name) else {
             return Err(BustubxError::Storage(format!(
                 "table {} not created yet",
                 table_name
             )));
         };
-        if catalog_table.indexes.contains_key(&index_name) {
-            return Err(BustubxError::Storage(
-                "Cannot create duplicated index".to_string(),
-            ));
-        }
-
-        let b_plus_tree_index = Arc::new(BPlusTreeIndex::new(
-            key_schema.clone(),
-            self.buffer_pool.clone(),
-            BPLUS_INTERNAL_PAGE_MAX_SIZE as u32,
-            BPLUS_LEAF_PAGE_MAX_SIZE as u32,
-        ));
-        catalog_table
-            .indexes
-            .insert(index_name.clone(), b_plus_tree_index.clone());
-
-        // update system table
-        let Some(information_schema) = self.schemas.get_mut(INFORMATION_SCHEMA_NAME) else {
-            return Err(BustubxError::Internal(
-                "catalog schema information_schema not created yet".to_string(),
-            ));
-        };
-        let Some(indexes_table) = information_schema
-            .tables
-            .get_mut(INFORMATION_SCHEMA_INDEXES)
-        else {
-            return Err(BustubxError::Internal(
-                "table information_schema.indexes not created yet".to_string(),
-            ));
-        };
-
-        let tuple = Tuple::new(
-            INDEXES_SCHMEA.clone(),
-            vec![
-                catalog_name.clone().into(),
-                catalog_schema_name.clone().into(),
-                table_name.clone().into(),
-                index_name.clone().into(),
-                key_schema_to_varchar(&b_plus_tree_index.key_schema).into(),
-                b_plus_tree_index.internal_max_size.into(),
-                b_plus_tree_index.leaf_max_size.into(),
-                b_plus_tree_index.root_page_id.load(Ordering::SeqCst).into(),
-            ],
-        );
-        indexes_table
-            .table
-            .insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
-
-        Ok(b_plus_tree_index)
+        Ok(catalog_table.table.clone())
     }
 
-    pub fn index(
+    pub fn table_indexes(
         &self,
         table_ref: &TableReference,
-        index_name: &str,
-    ) -> BustubxResult<Option<Arc<BPlusTreeIndex>>> {
+    ) -> BustubxResult<Vec<Arc<BPlusTreeIndex>>> {
         let catalog_schema_name = table_ref
             .schema()
             .unwrap_or(DEFAULT_SCHEMA_NAME)
@@ -344,63 +773,1317 @@ impl Catalog {
                 table_name
             )));
         };
-        Ok(catalog_table.indexes.get(index_name).cloned())
+        Ok(catalog_table.indexes.values().cloned().collect())
     }
 
-    pub fn load_schema(&mut self, name: impl Into<String>, schema: CatalogSchema) {
-        self.schemas.insert(name.into(), schema);
+    /// Names of the table indexes whose key includes `column` or an expression reading it,
+    /// sorted. DDL dropping or changing the column would leave their keys undecodable.
+    pub fn dependent_indexes(
+        &self,
+        table_ref: &TableReference,
+        column: &str,
+    ) -> BustubxResult<Vec<String>> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let mut index_names = catalog_table
+            .indexes
+            .iter()
+            .filter(|(index_name, index)| {
+                let expr_reads =
+                    catalog_table
+                        .key_projections
+                        .get(*index_name)
+                        .is_some_and(|projection| {
+                            projection.parts().iter().any(|part| {
+                            matches!(part, KeyPart::Expr(expr) if expr.references_column(column))
+                        })
+                        });
+                expr_reads
+                    || index
+                        .key_schema
+                        .columns
+                        .iter()
+                        .any(|key| key.name == column)
+            })
+            .map(|(index_name, _)| index_name.clone())
+            .collect::<Vec<_>>();
+        index_names.sort();
+        Ok(index_names)
     }
 
-    pub fn load_table(
+    pub fn analyze_table(
         &mut self,
-        table_ref: TableReference,
-        table: CatalogTable,
-    ) -> BustubxResult<()> {
-        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
-        let table_name = table_ref.table().to_string();
-        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
-            return Err(BustubxError::Storage(format!(
-                "catalog schema {} not created yet",
-                catalog_schema_name
-            )));
+        table_ref: &TableReference,
+        bucket_count: usize,
+        sample_size: usize,
+    ) -> BustubxResult<Arc<TableStatistics>> {
+        let job = self.analyze_job(table_ref, bucket_count, sample_size, false)?;
+        self.install_analyzed(job.run()?)?.ok_or_else(|| {
+            BustubxError::Internal(format!("table {} changed while analyzed", table_ref))
+        })
+    }
+
+    /// ANALYZE of the table, to run without the catalog. With `sketches` it also sketches
+    /// the distinct values of every column an index has as key column, see
+    /// [`TableModifications`].
+    pub fn analyze_job(
+        &self,
+        table_ref: &TableReference,
+        bucket_count: usize,
+        sample_size: usize,
+        sketches: bool,
+    ) -> BustubxResult<AnalyzeJob> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let mut indexes = catalog_table
+            .indexes
+            .iter()
+            .map(|(name, index)| (name.clone(), index.clone()))
+            .collect::<Vec<_>>();
+        indexes.sort_by(|a, b| a.0.cmp(&b.0));
+        let schema = &catalog_table.table.schema;
+        let mut sketch_columns = vec![];
+        if sketches {
+            for (_, index) in indexes.iter() {
+                for key in index.key_schema.columns.iter() {
+                    if let Ok(position) = schema.index_of(None, &key.name) {
+                        sketch_columns.push((key.name.clone(), position));
+                    }
+                }
+            }
+            sketch_columns.sort();
+            sketch_columns.dedup();
+        }
+        Ok(AnalyzeJob {
+            table_ref: table_ref.clone(),
+            heap: catalog_table.table.clone(),
+            indexes,
+            sketch_columns,
+            bucket_count,
+            sample_size,
+            modifications: catalog_table.modifications.clone(),
+            counts: catalog_table.modifications.counts(),
+            table_locks: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Install the statistics of a finished [`AnalyzeJob`], None when the table was
+    /// dropped since the job started.
+    pub fn install_analyzed(
+        &mut self,
+        analyzed: AnalyzedTable,
+    ) -> BustubxResult<Option<Arc<TableStatistics>>> {
+        let table_ref = analyzed.table_ref.clone();
+        let Ok(catalog_table) = self.catalog_table_mut(&table_ref) else {
+            return Ok(None);
         };
-        catalog_schema.tables.insert(table_name, table);
-        Ok(())
+        if !Arc::ptr_eq(&catalog_table.table, &analyzed.heap) {
+            return Ok(None);
+        }
+        let statistics = Arc::new(analyzed.install());
+        catalog_table.statistics = Some(statistics.clone());
+        catalog_table.version += 1;
+        Ok(Some(statistics))
     }
 
-    pub fn load_index(
+    /// Tables written `threshold` rows or more since their last ANALYZE.
+    pub fn tables_to_analyze(&self, threshold: u64) -> Vec<TableReference> {
+        let mut tables = vec![];
+        for catalog_schema in self.schemas.values() {
+            for catalog_table in catalog_schema.tables.values() {
+                if catalog_table.modifications.counts().modified >= threshold {
+                    tables.push(TableReference::full(
+                        DEFAULT_CATALOG_NAME,
+                        &catalog_schema.name,
+                        &catalog_table.name,
+                    ));
+                }
+            }
+        }
+        tables.sort_by_key(|table| table.to_string());
+        tables
+    }
+
+    /// Fails with [`BustubxError::TableReadOnly`] when the table or one of its partitions is
+    /// read only, checked by the planner for every statement writing or dropping the table.
+    pub fn check_table_writable(&self, table_ref: &TableReference) -> BustubxResult<()> {
+        if self.catalog_table(table_ref)?.read_only {
+            return Err(BustubxError::TableReadOnly {
+                table: table_ref.clone(),
+            });
+        }
+        // rows written to a partitioned table end up in its partitions
+        match self
+            .partitions(table_ref)
+            .into_iter()
+            .find(|(_, partition)| partition.read_only)
+        {
+            Some((partition_ref, _)) => Err(BustubxError::TableReadOnly {
+                table: partition_ref,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// `ALTER TABLE ... SET read_only` or `SET read_write`, the flag is stored in the row of
+    /// the table in information_schema.tables.
+    pub fn set_table_read_only(
         &mut self,
-        table_ref: TableReference,
-        index_name: impl Into<String>,
-        index: Arc<BPlusTreeIndex>,
+        table_ref: &TableReference,
+        read_only: bool,
     ) -> BustubxResult<()> {
-        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
-        let table_name = table_ref.table().to_string();
-        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
+        let schema_name = table_ref
+            .schema()
+            .unwrap_or(DEFAULT_SCHEMA_NAME)
+            .to_string();
+        if schema_name == INFORMATION_SCHEMA_NAME {
+            return Err(BustubxError::NotSupport(format!(
+                "table {} of {} has no access flag",
+                table_ref.table(),
+                INFORMATION_SCHEMA_NAME
+            )));
+        }
+        let Some(catalog_table) = self
+            .schemas
+            .get_mut(&schema_name)
+            .and_then(|catalog_schema| catalog_schema.tables.get_mut(table_ref.table()))
+        else {
             return Err(BustubxError::Storage(format!(
-                "catalog schema {} not created yet",
-                catalog_schema_name
+                "table {} not created yet",
+                table_ref
             )));
         };
-        let Some(catalog_table) = catalog_schema.tables.get_mut(&table_name) else {
-            return Err(BustubxError::Storage(format!(
+        if catalog_table.read_only == read_only {
+            return Ok(());
+        }
+        catalog_table.read_only = read_only;
+        // plans cached before skipped the check
+        catalog_table.version += 1;
+        self.update_information_tables_row(table_ref, |tuple| {
+            tuple.data[9] = read_only.into();
+        })
+    }
+
+    /// Replace the foreign keys of the table, stored in its row of information_schema.tables.
+    pub fn set_foreign_keys(
+        &mut self,
+        table_ref: &TableReference,
+        foreign_keys: Vec<ForeignKey>,
+    ) -> BustubxResult<()> {
+        let saved = foreign_keys_to_varchar(&foreign_keys)?;
+        let catalog_table = self.catalog_table_mut(table_ref)?;
+        catalog_table.foreign_keys = foreign_keys;
+        catalog_table.version += 1;
+        self.update_information_tables_row(table_ref, |tuple| {
+            tuple.data[11] = ScalarValue::Varchar(saved.clone());
+        })
+    }
+
+    // Apply `update` to the row of the table in information_schema.tables
+    fn update_information_tables_row(
+        &self,
+        table_ref: &TableReference,
+        update: impl Fn(&mut Tuple),
+    ) -> BustubxResult<()> {
+        let schema_name = ScalarValue::from(
+            table_ref
+                .schema()
+                .unwrap_or(DEFAULT_SCHEMA_NAME)
+                .to_string(),
+        );
+        let table_name = ScalarValue::from(table_ref.table().to_string());
+        let heap = self.table_heap(&TableReference::partial(
+            INFORMATION_SCHEMA_NAME,
+            INFORMATION_SCHEMA_TABLES,
+        ))?;
+        let mut rows = vec![];
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            if tuple.value(1)? == &schema_name && tuple.value(2)? == &table_name {
+                rows.push((rid, tuple));
+            }
+        }
+        for (rid, mut tuple) in rows {
+            update(&mut tuple);
+            heap.update_tuple(rid, tuple)?;
+        }
+        Ok(())
+    }
+
+    /// Fire `trigger` on `event` for every row of the table, after the ones registered before.
+    pub fn register_trigger(
+        &mut self,
+        table_ref: &TableReference,
+        event: TriggerEvent,
+        trigger: TriggerFn,
+    ) -> BustubxResult<()> {
+        let Some(catalog_table) = self
+            .schemas
+            .get_mut(table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME))
+            .and_then(|catalog_schema| catalog_schema.tables.get_mut(table_ref.table()))
+        else {
+            return Err(BustubxError::Storage(format!(
+                "table {} not created yet",
+                table_ref
+            )));
+        };
+        catalog_table.triggers.add(event, trigger);
+        Ok(())
+    }
+
+    /// See [`CatalogTable::version`]
+    pub fn table_version(&self, table_ref: &TableReference) -> BustubxResult<u64> {
+        Ok(self.catalog_table(table_ref)?.version)
+    }
+
+    /// Statistics of the last ANALYZE, refreshed by the distinct value sketches once stale,
+    /// see [`TableModifications::refresh`].
+    pub fn table_statistics(&self, table_ref: &TableReference) -> Option<Arc<TableStatistics>> {
+        let catalog_table = self
+            .schemas
+            .get(table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME))?
+            .tables
+            .get(table_ref.table())?;
+        let statistics = catalog_table.statistics.as_ref()?;
+        Some(catalog_table.modifications.refresh(statistics))
+    }
+
+    /// Size of every table and its indexes, ordered by schema and table name.
+    pub fn relation_sizes(&self) -> BustubxResult<Vec<TableSize>> {
+        let mut schema_names = self.schemas.keys().collect::<Vec<_>>();
+        schema_names.sort();
+        let mut sizes = vec![];
+        for schema_name in schema_names {
+            let catalog_schema = &self.schemas[schema_name];
+            let mut table_names = catalog_schema.tables.keys().collect::<Vec<_>>();
+            table_names.sort();
+            for table_name in table_names {
+                sizes.push(TableSize::measure(
+                    TableReference::full(DEFAULT_CATALOG_NAME, schema_name, table_name),
+                    &catalog_schema.tables[table_name],
+                )?);
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Relation of every page of the heaps and index trees of the catalog, information_schema
+    /// included. Pages of no relation, like the free pages, are left out.
+    pub fn page_owners(&self) -> BustubxResult<HashMap<PageId, PageOwner>> {
+        let mut owners = HashMap::new();
+        for (schema_name, catalog_schema) in self.schemas.iter() {
+            for (table_name, catalog_table) in catalog_schema.tables.iter() {
+                let table = TableReference::full(DEFAULT_CATALOG_NAME, schema_name, table_name);
+                let mut relations = vec![(None, catalog_table.table.page_ids()?)];
+                for (index_name, index) in catalog_table.indexes.iter() {
+                    relations.push((Some(index_name.clone()), index.page_ids()?));
+                }
+                if let Some(row_ids) = &catalog_table.row_ids {
+                    relations.push((
+                        Some(ROW_ID_INDEX_NAME.to_string()),
+                        row_ids.index.page_ids()?,
+                    ));
+                }
+                for (index, page_ids) in relations {
+                    for page_id in page_ids {
+                        owners.insert(
+                            page_id,
+                            PageOwner {
+                                table: table.clone(),
+                                index: index.clone(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Mark the row deleted and remove its index entries and logical row id. Returns the
+    /// name, key and value of every index entry removed.
+    pub fn delete_tuple(
+        &self,
+        table_ref: &TableReference,
+        rid: RecordId,
+    ) -> BustubxResult<Vec<(String, Tuple, RecordId)>> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let (mut meta, tuple) = catalog_table.table.full_tuple(rid)?;
+        if meta.is_deleted {
+            return Ok(vec![]);
+        }
+        // other rows may share the key of this one in an index that is not unique
+        let entry = catalog_table.index_entry(rid)?;
+        let mut removed = vec![];
+        for (index_name, index) in catalog_table.indexes.iter() {
+            let key = catalog_table.key_projection(index_name)?.project(&tuple)?;
+            if index.delete_entry(&key, entry)? {
+                removed.push((index_name.clone(), key, entry));
+            }
+        }
+        if let (Some(row_ids), Some(row_id)) = (&catalog_table.row_ids, meta.row_id) {
+            row_ids.unmap(row_id)?;
+        }
+        meta.is_deleted = true;
+        catalog_table.table.update_tuple_meta(meta, rid)?;
+        Ok(removed)
+    }
+
+    /// Every table outside information_schema, ordered by schema and table name.
+    pub fn user_tables(&self) -> Vec<TableReference> {
+        let mut table_refs = self
+            .schemas
+            .iter()
+            .filter(|(schema_name, _)| schema_name.as_str() != INFORMATION_SCHEMA_NAME)
+            .flat_map(|(schema_name, catalog_schema)| {
+                catalog_schema.tables.keys().map(move |table_name| {
+                    TableReference::full(DEFAULT_CATALOG_NAME, schema_name, table_name)
+                })
+            })
+            .collect::<Vec<_>>();
+        table_refs.sort();
+        table_refs
+    }
+
+    /// Partitions of the table partitioned by range at `table_ref`, ordered by their range.
+    pub fn partitions(&self, table_ref: &TableReference) -> Vec<(TableReference, &CatalogTable)> {
+        let schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let Some(catalog_schema) = self.schemas.get(schema_name) else {
+            return vec![];
+        };
+        let mut partitions = catalog_schema
+            .tables
+            .values()
+            .filter_map(|catalog_table| match &catalog_table.partitioning {
+                Some(Partitioning::Partition { parent, from, .. })
+                    if parent == table_ref.table() =>
+                {
+                    Some((from, catalog_table))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        partitions.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        partitions
+            .into_iter()
+            .map(|(_, catalog_table)| {
+                (
+                    TableReference::full(DEFAULT_CATALOG_NAME, schema_name, &catalog_table.name),
+                    catalog_table,
+                )
+            })
+            .collect()
+    }
+
+    /// Table a row inserted into `table_ref` is stored in, the partition whose range holds
+    /// the key of the row when the table is partitioned. Fails for a key outside the range
+    /// of every partition, or outside the range of the partition inserted into.
+    pub fn insert_target(
+        &self,
+        table_ref: &TableReference,
+        tuple: &Tuple,
+    ) -> BustubxResult<TableReference> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        match &catalog_table.partitioning {
+            None => Ok(table_ref.clone()),
+            Some(Partitioning::Range { column }) => {
+                let key = tuple.value_by_name(None, column)?;
+                self.partitions(table_ref)
+                    .into_iter()
+                    .find(|(_, partition)| {
+                        matches!(&partition.partitioning,
+                            Some(Partitioning::Partition { from, to, .. })
+                                if partition_contains(from, to, key))
+                    })
+                    .map(|(partition_ref, _)| partition_ref)
+                    .ok_or_else(|| {
+                        BustubxError::Execution(format!(
+                            "no partition of table {} for {} = {}",
+                            table_ref, column, key
+                        ))
+                    })
+            }
+            Some(Partitioning::Partition { .. }) => {
+                catalog_table.check_partition_key(tuple)?;
+                Ok(table_ref.clone())
+            }
+        }
+    }
+
+    /// Remove the table and its partitions from the catalog and free their heap pages.
+    /// The pages of their indexes are left behind, nothing frees b+ tree pages yet.
+    pub fn drop_table(&mut self, table_ref: &TableReference) -> BustubxResult<()> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let partitions = self
+            .partitions(table_ref)
+            .into_iter()
+            .map(|(partition_ref, _)| partition_ref)
+            .collect::<Vec<_>>();
+        let parent = match &catalog_table.partitioning {
+            Some(Partitioning::Partition { parent, .. }) => Some(parent.clone()),
+            _ => None,
+        };
+        for partition_ref in partitions {
+            self.drop_table(&partition_ref)?;
+        }
+
+        let schema_name = table_ref
+            .schema()
+            .unwrap_or(DEFAULT_SCHEMA_NAME)
+            .to_string();
+        for information_table in [
+            INFORMATION_SCHEMA_TABLES,
+            INFORMATION_SCHEMA_COLUMNS,
+            INFORMATION_SCHEMA_INDEXES,
+        ] {
+            let heap = self.table_heap(&TableReference::partial(
+                INFORMATION_SCHEMA_NAME,
+                information_table,
+            ))?;
+            let mut rids = vec![];
+            let mut iterator = TableIterator::new(heap.clone(), ..);
+            while let Some((rid, tuple)) = iterator.next()? {
+                if tuple.value(1)? == &ScalarValue::from(schema_name.clone())
+                    && tuple.value(2)? == &ScalarValue::from(table_ref.table().to_string())
+                {
+                    rids.push(rid);
+                }
+            }
+            for rid in rids {
+                let mut meta = heap.tuple_meta(rid)?;
+                meta.is_deleted = true;
+                heap.update_tuple_meta(meta, rid)?;
+            }
+        }
+
+        let Some(catalog_schema) = self.schemas.get_mut(&schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                schema_name
+            )));
+        };
+        if let Some(catalog_table) = catalog_schema.tables.remove(table_ref.table()) {
+            catalog_table.table.destroy()?;
+            for index in catalog_table.indexes.values() {
+                index.destroy()?;
+            }
+            if let Some(row_ids) = &catalog_table.row_ids {
+                row_ids.index.destroy()?;
+            }
+        }
+        if let Some(parent_table) = parent.and_then(|parent| catalog_schema.tables.get_mut(&parent))
+        {
+            parent_table.version += 1;
+        }
+        Ok(())
+    }
+
+    /// Remove the schema and drop every table in it, see [`Catalog::drop_table`].
+    pub fn drop_schema(&mut self, schema_name: &str) -> BustubxResult<()> {
+        let Some(catalog_schema) = self.schemas.get(schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                schema_name
+            )));
+        };
+        let table_names = catalog_schema.tables.keys().cloned().collect::<Vec<_>>();
+        for table_name in table_names {
+            // partitions are gone once their table is dropped
+            if self.schemas[schema_name].tables.contains_key(&table_name) {
+                self.drop_table(&TableReference::partial(schema_name, table_name))?;
+            }
+        }
+
+        let heap = self.table_heap(&TableReference::partial(
+            INFORMATION_SCHEMA_NAME,
+            INFORMATION_SCHEMA_SCHEMAS,
+        ))?;
+        let mut rids = vec![];
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            if tuple.value(1)? == &ScalarValue::from(schema_name.to_string()) {
+                rids.push(rid);
+            }
+        }
+        for rid in rids {
+            let mut meta = heap.tuple_meta(rid)?;
+            meta.is_deleted = true;
+            heap.update_tuple_meta(meta, rid)?;
+        }
+        self.schemas.remove(schema_name);
+        Ok(())
+    }
+
+    /// Reclaim the heap pages of the table holding only deleted rows, or with `full` rewrite
+    /// the heap without deleted rows and rebuild its indexes. A table with logical row ids
+    /// only rebuilds the mapping of its row ids. Index entries still pointing at the
+    /// reclaimed rows are removed first, the record ids are reused by later pages.
+    /// Returns (pages reclaimed, dead tuples removed).
+    pub fn vacuum_table(
+        &self,
+        table_ref: &TableReference,
+        full: bool,
+    ) -> BustubxResult<(usize, usize)> {
+        let table_heap = self.table_heap(table_ref)?;
+        if !full {
+            let removed = self.remove_index_entries(
+                self.catalog_table(table_ref)?,
+                table_heap.reclaimable_tuples()?,
+            )?;
+            if removed > 0 {
+                warn!(
+                    "removed {} index entries of {} pointing at deleted rows",
+                    removed, table_ref
+                );
+            }
+            return table_heap.vacuum();
+        }
+        let reclaimed = table_heap.vacuum_full()?;
+        match &self.catalog_table(table_ref)?.row_ids {
+            Some(row_ids) => self.rebuild_row_ids(&table_heap, row_ids)?,
+            None => self.rebuild_indexes(table_ref)?,
+        }
+        Ok(reclaimed)
+    }
+
+    /// `CLUSTER table USING index`: rewrite the live rows of the table into a fresh page
+    /// chain in the key order of the index, rows without an entry follow in heap order. The
+    /// old chain is freed, the indexes, or the mapping of the logical row ids, are rebuilt
+    /// and the index is recorded as the clustered index of the table.
+    /// Returns (rows, pages before, pages after).
+    pub fn cluster_table(
+        &mut self,
+        table_ref: &TableReference,
+        index_name: &str,
+    ) -> BustubxResult<(usize, usize, usize)> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        if catalog_table.is_partitioned() {
+            return Err(BustubxError::NotSupport(format!(
+                "table {} is partitioned, cluster its partitions",
+                table_ref
+            )));
+        }
+        let Some(index) = catalog_table.indexes.get(index_name) else {
+            return Err(BustubxError::Storage(format!(
+                "index {} of table {} not created yet",
+                index_name, table_ref
+            )));
+        };
+        // the record ids change, an index left out of the rebuild would point nowhere
+        for unresolved in catalog_table.unresolved_indexes() {
+            catalog_table.key_projection(unresolved)?;
+        }
+        let table_heap = catalog_table.table.clone();
+        let pages_before = table_heap.page_ids()?.len();
+
+        let mut visited = HashSet::new();
+        let mut rows = vec![];
+        let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+        while let Some(entry) = iterator.next()? {
+            let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                continue;
+            };
+            if !visited.insert(rid) {
+                continue;
+            }
+            let (meta, tuple) = table_heap.full_tuple(rid)?;
+            if !meta.is_deleted {
+                rows.push((meta, tuple));
+            }
+        }
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            if visited.contains(&rid) {
+                continue;
+            }
+            let meta = table_heap.tuple_meta(rid)?;
+            if !meta.is_deleted {
+                rows.push((meta, tuple));
+            }
+        }
+
+        let items = rows
+            .iter()
+            .map(|(meta, tuple)| (*meta, tuple))
+            .collect::<Vec<_>>();
+        table_heap.rewrite(&items)?;
+        match &catalog_table.row_ids {
+            Some(row_ids) => self.rebuild_row_ids(&table_heap, row_ids)?,
+            None => self.rebuild_indexes(table_ref)?,
+        }
+        let pages_after = table_heap.page_ids()?.len();
+
+        let first_page_id = table_heap.first_page_id.load(Ordering::SeqCst);
+        self.update_information_tables_row(table_ref, |tuple| {
+            tuple.data[3] = first_page_id.into();
+            tuple.data[10] = index_name.to_string().into();
+        })?;
+        let catalog_table = self.catalog_table_mut(table_ref)?;
+        catalog_table.clustered_index = Some(index_name.to_string());
+        // plans cached before estimated the correlation of the index without it
+        catalog_table.version += 1;
+        Ok((rows.len(), pages_before, pages_after))
+    }
+
+    /// Bulk load every index of the table, and the mapping of its logical row ids, from its
+    /// live rows in a single heap scan. Unresolved expression indexes are left out, they are
+    /// bulk loaded once their function is registered.
+    pub fn rebuild_indexes(&self, table_ref: &TableReference) -> BustubxResult<()> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let indexes = catalog_table
+            .indexes
+            .iter()
+            .filter(|(index_name, _)| !catalog_table.unresolved_indexes.contains_key(*index_name))
+            .collect::<Vec<_>>();
+        self.bulk_load_indexes(catalog_table, indexes, true)
+    }
+
+    /// Rebuild the indexes of every user table from its heap, the index pages written
+    /// before a crash may not match the rows that reached the disk.
+    /// Returns the number of tables recovered.
+    pub fn recover_indexes(&self) -> BustubxResult<usize> {
+        let table_refs = self.user_tables();
+        for table_ref in table_refs.iter() {
+            self.rebuild_indexes(table_ref)?;
+        }
+        Ok(table_refs.len())
+    }
+
+    /// Bulk load the index `index_name` of the table, or all of its indexes, from the live
+    /// rows. Every tree stays readable until its new root is swapped in, the pages of the
+    /// old tree are freed after.
+    /// Returns (indexes rebuilt, pages before, pages after).
+    pub fn reindex(
+        &self,
+        table_ref: &TableReference,
+        index_name: Option<&str>,
+    ) -> BustubxResult<(usize, usize, usize)> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let indexes = match index_name {
+            Some(index_name) => {
+                let Some(index) = catalog_table.indexes.get_key_value(index_name) else {
+                    return Err(BustubxError::Storage(format!(
+                        "index {} of table {} not created yet",
+                        index_name, table_ref
+                    )));
+                };
+                vec![index]
+            }
+            None => catalog_table.indexes.iter().collect(),
+        };
+        let pages = |indexes: &[(&String, &Arc<BPlusTreeIndex>)]| -> BustubxResult<usize> {
+            let mut pages = 0;
+            for (_, index) in indexes {
+                pages += IndexSize::measure("", index)?.pages();
+            }
+            Ok(pages)
+        };
+        let pages_before = pages(&indexes)?;
+        self.bulk_load_indexes(catalog_table, indexes.clone(), false)?;
+        Ok((indexes.len(), pages_before, pages(&indexes)?))
+    }
+
+    /// Integrity findings of the heap of the table and, `with_indexes`, of the structure of
+    /// its indexes and of whether their entries match the live rows one to one. Empty for a
+    /// healthy table. With the `debug-history` feature a finding carries the recent writes
+    /// of its page.
+    pub fn check_table(
+        &self,
+        table_ref: &TableReference,
+        with_indexes: bool,
+    ) -> BustubxResult<Vec<Finding>> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let mut findings = catalog_table.table.check(&table_ref.to_string());
+        if !with_indexes {
+            return Ok(findings);
+        }
+        // rows of a damaged heap are not cross-checked, its findings tell what is wrong
+        let heap_damaged = findings.iter().any(|f| f.severity == Severity::Error);
+        let live_rows = if heap_damaged {
+            None
+        } else {
+            let mut rows = vec![];
+            let mut iterator = TableIterator::new(catalog_table.table.clone(), ..);
+            while let Some((rid, tuple)) = iterator.next()? {
+                let meta = catalog_table.table.tuple_meta(rid)?;
+                if !meta.is_deleted {
+                    let entry = meta.row_id.map_or(rid, RowIdMap::index_entry);
+                    rows.push((rid, entry, tuple));
+                }
+            }
+            Some(rows)
+        };
+
+        let mut index_names = catalog_table.indexes.keys().collect::<Vec<_>>();
+        index_names.sort();
+        for index_name in index_names {
+            let index = &catalog_table.indexes[index_name];
+            let (index_findings, entries) = index.check(index_name);
+            let index_damaged = index_findings.iter().any(|f| f.severity == Severity::Error);
+            findings.extend(index_findings);
+            if let (Some(rows), false) = (&live_rows, index_damaged) {
+                findings.extend(check_index_entries(
+                    catalog_table,
+                    index_name,
+                    rows,
+                    entries,
+                )?);
+            }
+        }
+        #[cfg(feature = "debug-history")]
+        let findings = findings
+            .into_iter()
+            .map(|finding| finding.with_page_history(&self.buffer_pool))
+            .collect();
+        Ok(findings)
+    }
+
+    /// Tables of the schema having an index called `index_name`.
+    pub fn tables_with_index(&self, schema_name: &str, index_name: &str) -> Vec<TableReference> {
+        let Some(catalog_schema) = self.schemas.get(schema_name) else {
+            return vec![];
+        };
+        let mut table_refs = catalog_schema
+            .tables
+            .iter()
+            .filter(|(_, catalog_table)| catalog_table.indexes.contains_key(index_name))
+            .map(|(table_name, _)| {
+                TableReference::full(DEFAULT_CATALOG_NAME, schema_name, table_name)
+            })
+            .collect::<Vec<_>>();
+        table_refs.sort();
+        table_refs
+    }
+
+    // one heap scan feeding `indexes`, and with `row_ids` the mapping of the logical row ids
+    fn bulk_load_indexes(
+        &self,
+        catalog_table: &CatalogTable,
+        indexes: Vec<(&String, &Arc<BPlusTreeIndex>)>,
+        row_ids: bool,
+    ) -> BustubxResult<()> {
+        let row_ids = catalog_table.row_ids.as_ref().filter(|_| row_ids);
+        if indexes.is_empty() && row_ids.is_none() {
+            return Ok(());
+        }
+        let projections = indexes
+            .iter()
+            .map(|(index_name, _)| catalog_table.key_projection(index_name))
+            .collect::<BustubxResult<Vec<_>>>()?;
+        let mut entries = vec![vec![]; indexes.len()];
+        let mut row_id_entries = vec![];
+        let mut iterator = TableIterator::new(catalog_table.table.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            let meta = catalog_table.table.tuple_meta(rid)?;
+            if meta.is_deleted {
+                continue;
+            }
+            let entry = match meta.row_id {
+                Some(row_id) => {
+                    row_id_entries.push((row_id, rid));
+                    RowIdMap::index_entry(row_id)
+                }
+                None => rid,
+            };
+            for (projection, index_entries) in projections.iter().zip(entries.iter_mut()) {
+                index_entries.push((projection.project(&tuple)?, entry));
+            }
+        }
+        for ((_, index), index_entries) in indexes.into_iter().zip(entries) {
+            index.rebuild(index_entries)?;
+        }
+        if let Some(row_ids) = row_ids {
+            row_ids.rebuild(row_id_entries)?;
+        }
+        Ok(())
+    }
+
+    // Remove the entries of `tuples` from every index of the table, and their row ids from
+    // the mapping, returns the number of entries removed. Entries are removed per index in
+    // key order, so consecutive deletes mostly land on the same leaf.
+    fn remove_index_entries(
+        &self,
+        catalog_table: &CatalogTable,
+        tuples: Vec<(RecordId, TupleMeta, Tuple)>,
+    ) -> BustubxResult<usize> {
+        let mut removed = 0;
+        let entries = tuples
+            .iter()
+            .map(|(rid, meta, _)| match meta.row_id {
+                Some(row_id) => RowIdMap::index_entry(row_id),
+                None => *rid,
+            })
+            .collect::<Vec<_>>();
+        for (index_name, index) in catalog_table.indexes.iter() {
+            let projection = catalog_table.key_projection(index_name)?;
+            let mut keys = vec![];
+            for ((_, _, tuple), entry) in tuples.iter().zip(entries.iter()) {
+                keys.push((projection.project(tuple)?, *entry));
+            }
+            keys.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            for (key, entry) in keys {
+                if index.delete_entry(&key, entry)? {
+                    removed += 1;
+                }
+            }
+        }
+        if let Some(row_ids) = &catalog_table.row_ids {
+            for (rid, meta, _) in tuples.iter() {
+                if let Some(row_id) = meta.row_id {
+                    if row_ids.resolve(row_id)? == Some(*rid) {
+                        row_ids.unmap(row_id)?;
+                        removed += 1;
+                    }
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    // map the row ids of the live rows to where the rows are now
+    fn rebuild_row_ids(
+        &self,
+        table_heap: &Arc<TableHeap>,
+        row_ids: &RowIdMap,
+    ) -> BustubxResult<()> {
+        let mut entries = vec![];
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        while let Some((rid, _)) = iterator.next()? {
+            let meta = table_heap.tuple_meta(rid)?;
+            if let (false, Some(row_id)) = (meta.is_deleted, meta.row_id) {
+                entries.push((row_id, rid));
+            }
+        }
+        row_ids.rebuild(entries)
+    }
+
+    /// Delete the rows of every table with a ttl column whose ttl is before `now`,
+    /// returns the number of deleted rows per table. Read only tables keep their expired
+    /// rows until they are writable again, rows a foreign key references until it does not.
+    pub fn expire_rows(&self, now: i64) -> BustubxResult<Vec<(TableReference, usize)>> {
+        let mut table_refs = vec![];
+        for (schema_name, catalog_schema) in self.schemas.iter() {
+            for (table_name, catalog_table) in catalog_schema.tables.iter() {
+                if catalog_table.ttl_column.is_some() {
+                    table_refs.push(TableReference::full(
+                        DEFAULT_CATALOG_NAME,
+                        schema_name.clone(),
+                        table_name.clone(),
+                    ));
+                }
+            }
+        }
+        table_refs.sort();
+        let mut writable = vec![];
+        for table_ref in table_refs {
+            match self.check_table_writable(&table_ref) {
+                Ok(()) => writable.push(table_ref),
+                Err(BustubxError::TableReadOnly { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let table_refs = writable;
+
+        let mut deleted_rows = vec![];
+        for table_ref in table_refs {
+            let catalog_table = self.catalog_table(&table_ref)?;
+            let mut expired = vec![];
+            let mut iterator = TableIterator::new(catalog_table.table.clone(), ..);
+            while let Some((rid, tuple)) = iterator.next()? {
+                if catalog_table.is_expired(&tuple, now)?
+                    && !catalog_table.table.tuple_meta(rid)?.is_deleted
+                    && !self.is_key_referenced(&table_ref, &tuple)?
+                {
+                    expired.push(rid);
+                }
+            }
+            for rid in expired.iter() {
+                self.delete_tuple(&table_ref, *rid)?;
+            }
+            deleted_rows.push((table_ref, expired.len()));
+        }
+        Ok(deleted_rows)
+    }
+
+    /// Record the root page id of every index whose tree grew or shrank at the root in
+    /// information_schema.indexes, so opening the file again finds the current root.
+    pub fn persist_index_roots(&mut self) -> BustubxResult<()> {
+        let indexes_table = self.table_heap(&TableReference::partial(
+            INFORMATION_SCHEMA_NAME,
+            INFORMATION_SCHEMA_INDEXES,
+        ))?;
+        let mut moved = vec![];
+        let mut iterator = TableIterator::new(indexes_table.clone(), ..);
+        while let Some((rid, mut tuple)) = iterator.next()? {
+            if indexes_table.tuple_meta(rid)?.is_deleted {
+                continue;
+            }
+            let (
+                ScalarValue::Varchar(Some(schema_name)),
+                ScalarValue::Varchar(Some(table_name)),
+                ScalarValue::Varchar(Some(index_name)),
+                ScalarValue::UInt32(Some(root_page_id)),
+            ) = (
+                tuple.value(1)?,
+                tuple.value(2)?,
+                tuple.value(3)?,
+                tuple.value(7)?,
+            )
+            else {
+                return Err(BustubxError::Internal(format!(
+                    "Failed to decode index tuple: {:?}",
+                    tuple
+                )));
+            };
+            let table_ref = TableReference::partial(schema_name.clone(), table_name.clone());
+            let index = if index_name == ROW_ID_INDEX_NAME {
+                self.catalog_table(&table_ref)?
+                    .row_ids
+                    .as_ref()
+                    .map(|row_ids| row_ids.index.clone())
+            } else {
+                self.index(&table_ref, index_name)?
+            };
+            let Some(index) = index else {
+                continue;
+            };
+            let current_root_page_id = index.root_page_id.load(Ordering::SeqCst);
+            if current_root_page_id != *root_page_id {
+                tuple.data[7] = current_root_page_id.into();
+                moved.push((rid, tuple));
+            }
+        }
+        for (rid, tuple) in moved {
+            indexes_table.update_tuple(rid, tuple)?;
+        }
+        Ok(())
+    }
+
+    pub fn create_index(
+        &mut self,
+        index_name: String,
+        table_ref: &TableReference,
+        key_schema: SchemaRef,
+        unique: bool,
+    ) -> BustubxResult<Arc<BPlusTreeIndex>> {
+        self.create_index_with_parts(index_name, table_ref, key_schema, None, unique)
+    }
+
+    /// Create an index whose keys are derived by `parts`, without them the key columns are
+    /// looked up by name among the columns of the table. An index with an expression part
+    /// also records its key columns with their types, the expressions are bound again from
+    /// the names of their key columns on open.
+    pub fn create_index_with_parts(
+        &mut self,
+        index_name: String,
+        table_ref: &TableReference,
+        key_schema: SchemaRef,
+        parts: Option<Vec<KeyPart>>,
+        unique: bool,
+    ) -> BustubxResult<Arc<BPlusTreeIndex>> {
+        self.create_index_with_build(
+            index_name,
+            table_ref,
+            key_schema,
+            parts,
+            unique,
+            None,
+            &IndexBuild::default(),
+        )
+    }
+
+    /// Create an index and bulk load it from the rows of the table as `build` says. A
+    /// unique index over duplicate keys fails with [`BustubxError::DuplicateKey`] and is not
+    /// created, also a deferrable one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_index_with_build(
+        &mut self,
+        index_name: String,
+        table_ref: &TableReference,
+        key_schema: SchemaRef,
+        parts: Option<Vec<KeyPart>>,
+        unique: bool,
+        deferrable: Option<ConstraintTiming>,
+        build: &IndexBuild,
+    ) -> BustubxResult<Arc<BPlusTreeIndex>> {
+        let catalog_name = table_ref
+            .catalog()
+            .unwrap_or(DEFAULT_CATALOG_NAME)
+            .to_string();
+        let catalog_schema_name = table_ref
+            .schema()
+            .unwrap_or(DEFAULT_SCHEMA_NAME)
+            .to_string();
+        let table_name = table_ref.table().to_string();
+
+        let Some(catalog_schema) = self.schemas.get_mut(&catalog_schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                catalog_schema_name
+            )));
+        };
+        let Some(catalog_table) = catalog_schema.tables.get_mut(&table_name) else {
+            return Err(BustubxError::Storage(format!(
+                "table {} not created yet",
+                table_name
+            )));
+        };
+        if catalog_table.indexes.contains_key(&index_name) {
+            return Err(BustubxError::Storage(
+                "Cannot create duplicated index".to_string(),
+            ));
+        }
+        if index_name == ROW_ID_INDEX_NAME {
+            return Err(BustubxError::Storage(format!(
+                "index name {} is reserved",
+                index_name
+            )));
+        }
+
+        let b_plus_tree_index = Arc::new(
+            BPlusTreeIndex::new(
+                key_schema.clone(),
+                self.buffer_pool.clone(),
+                BPLUS_INTERNAL_PAGE_MAX_SIZE as u32,
+                BPLUS_LEAF_PAGE_MAX_SIZE as u32,
+            )
+            .with_unique(unique)
+            .with_deferrable(deferrable),
+        );
+        let mut key_columns = ScalarValue::Varchar(None);
+        match parts {
+            Some(parts) => {
+                if parts.iter().any(|part| matches!(part, KeyPart::Expr(_))) {
+                    key_columns = key_columns_to_varchar(&key_schema)?.into();
+                }
+                catalog_table.add_index_with_parts(
+                    index_name.clone(),
+                    b_plus_tree_index.clone(),
+                    parts,
+                )?
+            }
+            None => catalog_table.add_index(index_name.clone(), b_plus_tree_index.clone())?,
+        }
+        let projection = catalog_table.key_projection(&index_name)?;
+        if let Err(e) = build_index(&catalog_table.table, &projection, &b_plus_tree_index, build) {
+            catalog_table.indexes.remove(&index_name);
+            catalog_table.key_projections.remove(&index_name);
+            b_plus_tree_index.destroy()?;
+            return Err(e);
+        }
+        catalog_table.version += 1;
+
+        // update system table
+        let Some(information_schema) = self.schemas.get_mut(INFORMATION_SCHEMA_NAME) else {
+            return Err(BustubxError::Internal(
+                "catalog schema information_schema not created yet".to_string(),
+            ));
+        };
+        let Some(indexes_table) = information_schema
+            .tables
+            .get_mut(INFORMATION_SCHEMA_INDEXES)
+        else {
+            return Err(BustubxError::Internal(
+                "table information_schema.indexes not created yet".to_string(),
+            ));
+        };
+
+        let tuple = Tuple::new(
+            INDEXES_SCHMEA.clone(),
+            vec![
+                catalog_name.clone().into(),
+                catalog_schema_name.clone().into(),
+                table_name.clone().into(),
+                index_name.clone().into(),
+                key_schema_to_varchar(&b_plus_tree_index.key_schema).into(),
+                b_plus_tree_index.internal_max_size.into(),
+                b_plus_tree_index.leaf_max_size.into(),
+                b_plus_tree_index.root_page_id.load(Ordering::SeqCst).into(),
+                unique.into(),
+                key_columns,
+                ScalarValue::Varchar(deferrable.map(|timing| timing.to_string())),
+            ],
+        );
+        indexes_table
+            .table
+            .insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+
+        Ok(b_plus_tree_index)
+    }
+
+    pub fn index(
+        &self,
+        table_ref: &TableReference,
+        index_name: &str,
+    ) -> BustubxResult<Option<Arc<BPlusTreeIndex>>> {
+        let catalog_schema_name = table_ref
+            .schema()
+            .unwrap_or(DEFAULT_SCHEMA_NAME)
+            .to_string();
+        let table_name = table_ref.table().to_string();
+
+        let Some(catalog_schema) = self.schemas.get(&catalog_schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                catalog_schema_name
+            )));
+        };
+        let Some(catalog_table) = catalog_schema.tables.get(&table_name) else {
+            return Err(BustubxError::Storage(format!(
+                "table {} not created yet",
+                table_name
+            )));
+        };
+        Ok(catalog_table.indexes.get(index_name).cloned())
+    }
+
+    pub fn load_schema(&mut self, name: impl Into<String>, schema: CatalogSchema) {
+        self.schemas.insert(name.into(), schema);
+    }
+
+    pub fn load_table(
+        &mut self,
+        table_ref: TableReference,
+        table: CatalogTable,
+    ) -> BustubxResult<()> {
+        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let table_name = table_ref.table().to_string();
+        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                catalog_schema_name
+            )));
+        };
+        catalog_schema.tables.insert(table_name, table);
+        Ok(())
+    }
+
+    pub fn load_index(
+        &mut self,
+        table_ref: TableReference,
+        index_name: impl Into<String>,
+        index: Arc<BPlusTreeIndex>,
+    ) -> BustubxResult<()> {
+        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let table_name = table_ref.table().to_string();
+        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                catalog_schema_name
+            )));
+        };
+        let Some(catalog_table) = catalog_schema.tables.get_mut(&table_name) else {
+            return Err(BustubxError::Storage(format!(
                 "catalog table {} not created yet",
                 table_name
             )));
         };
-        catalog_table.indexes.insert(index_name.into(), index);
+        catalog_table.add_index(index_name.into(), index)
+    }
+
+    /// Attach the logical row id mapping stored in `index` to the table.
+    pub fn load_row_ids(
+        &mut self,
+        table_ref: TableReference,
+        index: Arc<BPlusTreeIndex>,
+    ) -> BustubxResult<()> {
+        let catalog_schema_name = table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let table_name = table_ref.table().to_string();
+        let Some(catalog_schema) = self.schemas.get_mut(catalog_schema_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog schema {} not created yet",
+                catalog_schema_name
+            )));
+        };
+        let Some(catalog_table) = catalog_schema.tables.get_mut(&table_name) else {
+            return Err(BustubxError::Storage(format!(
+                "catalog table {} not created yet",
+                table_name
+            )));
+        };
+        catalog_table.row_ids = Some(Arc::new(RowIdMap::open(
+            index,
+            catalog_table.table.clone(),
+        )?));
         Ok(())
     }
 }
 
+// keys are in `[from, to)`, a null key is in no partition
+// schema.table, the key of the counters of a table in the stats file
+fn qualified_table_name(table_ref: &TableReference) -> String {
+    format!(
+        "{}.{}",
+        table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME),
+        table_ref.table()
+    )
+}
+
+fn partition_contains(from: &ScalarValue, to: &ScalarValue, key: &ScalarValue) -> bool {
+    !key.is_null()
+        && matches!(
+            key.partial_cmp(from),
+            Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)
+        )
+        && key.partial_cmp(to) == Some(std::cmp::Ordering::Less)
+}
+
+// the range of a new partition of `parent` must be non empty and overlap no other partition
+fn check_partition_bound(
+    catalog_schema: &CatalogSchema,
+    parent: &str,
+    from: &ScalarValue,
+    to: &ScalarValue,
+) -> BustubxResult<()> {
+    let Some(parent_table) = catalog_schema.tables.get(parent) else {
+        return Err(BustubxError::Storage(format!(
+            "table {} not created yet",
+            parent
+        )));
+    };
+    if !parent_table.is_partitioned() {
+        return Err(BustubxError::Plan(format!(
+            "table {} is not partitioned",
+            parent
+        )));
+    }
+    if from.partial_cmp(to) != Some(std::cmp::Ordering::Less) {
+        return Err(BustubxError::Plan(format!(
+            "empty partition range [{}, {})",
+            from, to
+        )));
+    }
+    for catalog_table in catalog_schema.tables.values() {
+        if let Some(Partitioning::Partition {
+            parent: other_parent,
+            from: other_from,
+            to: other_to,
+            ..
+        }) = &catalog_table.partitioning
+        {
+            if other_parent == parent
+                && from.partial_cmp(other_to) == Some(std::cmp::Ordering::Less)
+                && other_from.partial_cmp(to) == Some(std::cmp::Ordering::Less)
+            {
+                return Err(BustubxError::Plan(format!(
+                    "range [{}, {}) overlaps partition {}",
+                    from, to, catalog_table.name
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
-    use crate::common::TableReference;
+    use crate::common::page_leaks::assert_no_page_leaks;
+    use crate::common::test_util::text_rows;
+    use crate::common::{MockClock, ScalarValue, TableReference};
     use crate::{
         catalog::{Column, DataType, Schema},
-        Database,
+        BustubxError, Database, DatabaseOptions, SettingValue, Tuple,
     };
 
     #[test]
@@ -415,7 +2098,7 @@ mod tests {
         ]));
         let table_info = db
             .catalog
-            .create_table(table_ref1.clone(), schema.clone())
+            .create_table(table_ref1.clone(), schema.clone(), None, false, None)
             .unwrap();
         assert_eq!(table_info.schema, schema);
 
@@ -427,7 +2110,7 @@ mod tests {
         ]));
         let table_info = db
             .catalog
-            .create_table(table_ref2.clone(), schema.clone())
+            .create_table(table_ref2.clone(), schema.clone(), None, false, None)
             .unwrap();
         assert_eq!(table_info.schema, schema);
 
@@ -448,13 +2131,15 @@ mod tests {
             Column::new("b", DataType::Int16, true),
             Column::new("c", DataType::Int32, true),
         ]));
-        let _ = db.catalog.create_table(table_ref.clone(), schema.clone());
+        let _ = db
+            .catalog
+            .create_table(table_ref.clone(), schema.clone(), None, false, None);
 
         let index_name1 = "test_index1".to_string();
         let key_schema1 = Arc::new(schema.project(&[0, 2]).unwrap());
         let index1 = db
             .catalog
-            .create_index(index_name1.clone(), &table_ref, key_schema1.clone())
+            .create_index(index_name1.clone(), &table_ref, key_schema1.clone(), false)
             .unwrap();
         assert_eq!(index1.key_schema, key_schema1);
 
@@ -462,9 +2147,11 @@ mod tests {
         let key_schema2 = Arc::new(schema.project(&[1]).unwrap());
         let index2 = db
             .catalog
-            .create_index(index_name2.clone(), &table_ref, key_schema2.clone())
+            .create_index(index_name2.clone(), &table_ref, key_schema2.clone(), true)
             .unwrap();
         assert_eq!(index2.key_schema, key_schema2);
+        assert!(!index1.unique);
+        assert!(index2.unique);
 
         let index3 = db
             .catalog
@@ -473,4 +2160,522 @@ mod tests {
             .unwrap();
         assert_eq!(index3.key_schema, key_schema1);
     }
+
+    #[test]
+    pub fn test_catalog_expire_rows() {
+        let clock = Arc::new(MockClock::new(1000));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        db.run(
+            "create table cache (k int, v int, expires_at bigint) with (ttl_column = 'expires_at')",
+        )
+        .unwrap();
+        db.run("create index cache_k on cache (k)").unwrap();
+        db.run("create table plain (a int)").unwrap();
+        // 4102444800 is 2100-01-01
+        db.run("insert into cache values (1, 10, 100), (2, 20, 200), (3, 30, 4102444800), (4, 40, 4102444800)")
+            .unwrap();
+        db.run("insert into plain values (1)").unwrap();
+
+        let keys = |db: &mut Database, sql: &str| {
+            db.run(sql)
+                .unwrap()
+                .into_iter()
+                .map(|tuple| tuple.data[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let ints = |values: &[i32]| {
+            values
+                .iter()
+                .map(|v| ScalarValue::Int32(Some(*v)))
+                .collect::<Vec<_>>()
+        };
+
+        // expired rows stay visible until collected unless the session hides them
+        assert_eq!(keys(&mut db, "select k from cache"), ints(&[1, 2, 3, 4]));
+        db.set_hide_expired_rows(true);
+        assert_eq!(keys(&mut db, "select k from cache"), ints(&[3, 4]));
+        assert_eq!(keys(&mut db, "select k from cache where k = 2"), ints(&[]));
+        db.set_hide_expired_rows(false);
+
+        let cache_ref = TableReference::full("bustubx", "public", "cache");
+        clock.set(150);
+        assert_eq!(db.expire_rows().unwrap(), vec![(cache_ref.clone(), 1)]);
+        assert_eq!(keys(&mut db, "select k from cache"), ints(&[2, 3, 4]));
+        assert_eq!(keys(&mut db, "select a from plain"), ints(&[1]));
+
+        let index = db.catalog.index(&cache_ref, "cache_k").unwrap().unwrap();
+        let key = |k: i32| Tuple::new(index.key_schema.clone(), vec![ScalarValue::Int32(Some(k))]);
+        assert_eq!(index.get(&key(1)).unwrap(), None);
+        assert!(index.get(&key(2)).unwrap().is_some());
+
+        assert_eq!(db.expire_rows().unwrap(), vec![(cache_ref.clone(), 0)]);
+        clock.set(1000);
+        assert_eq!(db.expire_rows().unwrap(), vec![(cache_ref, 1)]);
+        assert_eq!(keys(&mut db, "select k from cache"), ints(&[3, 4]));
+        assert_eq!(index.get(&key(2)).unwrap(), None);
+        assert!(index.get(&key(3)).unwrap().is_some());
+
+        assert!(matches!(
+            db.run("create table t1 (a int) with (ttl_column = 'a')"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("create table t1 (a bigint) with (ttl_column = 'b')"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("create table t1 (a bigint) with (fillfactor = 70)"),
+            Err(BustubxError::NotSupport(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_catalog_partitioned_table() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table events (ts bigint, v int) partition by range (ts)")
+            .unwrap();
+        db.run("create table events_1 partition of events for values from (1000) to (2000)")
+            .unwrap();
+        db.run("create table events_2 partition of events for values from (2000) to (3000)")
+            .unwrap();
+        assert!(matches!(
+            db.run("create table events_x partition of events for values from (1500) to (2500)"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("create table events_x partition of events_1 for values from (0) to (1)"),
+            Err(BustubxError::Plan(_))
+        ));
+
+        // every row goes to the partition holding its key
+        db.run("insert into events values (1000, 1), (1999, 2), (2000, 3), (2999, 4)")
+            .unwrap();
+        assert_eq!(text_rows(&mut db, "select v from events_1"), vec!["1", "2"]);
+        assert_eq!(text_rows(&mut db, "select v from events_2"), vec!["3", "4"]);
+        assert_eq!(
+            text_rows(&mut db, "select count(*), avg(v), avg(ts) from events"),
+            vec!["4 2.5 1999.5"]
+        );
+        assert_eq!(
+            text_rows(
+                &mut db,
+                "select v from events where ts >= 1999 order by v desc"
+            ),
+            vec!["4", "3", "2"]
+        );
+
+        // no partition for the key, the rows routed before are rolled back
+        assert!(matches!(
+            db.run("insert into events values (2500, 5), (3000, 6)"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            db.run("insert into events_1 values (2500, 5)"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            db.run("update events_1 set ts = 2500"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            db.run("delete from events"),
+            Err(BustubxError::NotSupport(_))
+        ));
+        assert_eq!(text_rows(&mut db, "select count(*) from events"), vec!["4"]);
+
+        // the partitions and their ranges are loaded again
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("insert into events values (2500, 5)").unwrap();
+        assert_eq!(
+            text_rows(&mut db, "select v from events_2"),
+            vec!["3", "4", "5"]
+        );
+        assert!(text_rows(&mut db, "show create table events_2")[0]
+            .contains("PARTITION OF events FOR VALUES FROM (2000) TO (3000)"));
+
+        // dropping a partition removes its range
+        assert_no_page_leaks(&mut db, |db| db.run("drop table events_1").unwrap());
+        assert_eq!(
+            text_rows(&mut db, "select v from events"),
+            vec!["3", "4", "5"]
+        );
+        assert!(db.run("select v from events_1").is_err());
+        assert!(matches!(
+            db.run("insert into events values (1500, 1)"),
+            Err(BustubxError::Execution(_))
+        ));
+        db.run("create table events_1 partition of events for values from (1000) to (2000)")
+            .unwrap();
+        db.run("insert into events values (1500, 1)").unwrap();
+        assert_eq!(
+            text_rows(&mut db, "select count(*), avg(v) from events"),
+            vec!["4 3.25"]
+        );
+
+        // a partitioned table is dropped with its partitions
+        assert_no_page_leaks(&mut db, |db| db.run("drop table events").unwrap());
+        assert!(db.run("select v from events_2").is_err());
+        assert!(db.run("drop table events").is_err());
+        db.run("drop table if exists events").unwrap();
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert!(db.run("select v from events").is_err());
+        assert!(db.run("select v from events_1").is_err());
+    }
+
+    #[test]
+    pub fn test_catalog_alter_table_dependent_indexes() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int, c int)").unwrap();
+        db.run("create index idx_ab on t1 (a, b)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        db.run("insert into t1 values (1, 2, 3), (4, 5, 6)")
+            .unwrap();
+
+        let table_ref = TableReference::full("bustubx", "public", "t1");
+        assert_eq!(
+            db.catalog.dependent_indexes(&table_ref, "b").unwrap(),
+            vec!["idx_ab".to_string(), "idx_b".to_string()]
+        );
+        assert!(db
+            .catalog
+            .dependent_indexes(&table_ref, "c")
+            .unwrap()
+            .is_empty());
+
+        for sql in [
+            "alter table t1 drop column b",
+            "alter table t1 drop column b cascade",
+            "alter table t1 alter column b set data type bigint",
+        ] {
+            match db.run(sql) {
+                Err(BustubxError::Plan(msg)) => assert!(msg.contains("idx_ab, idx_b"), "{msg}"),
+                result => panic!("{sql} should be rejected, got {result:?}"),
+            }
+        }
+        assert!(matches!(
+            db.run("alter table t1 drop column c"),
+            Err(BustubxError::NotSupport(_))
+        ));
+
+        // nothing changed, the index still decodes
+        let rows = db.run("select c from t1 where b = 5").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(6))]);
+    }
+
+    // every row as its values separated by spaces
+
+    #[test]
+    pub fn test_catalog_schema_search_path() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create schema app").unwrap();
+        assert!(matches!(
+            db.run("create schema app"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("create schema if not exists app").unwrap();
+        assert!(db.run("create table missing.users (id int)").is_err());
+
+        // same named tables in two schemas
+        db.run("create table users (id int, name varchar(10))")
+            .unwrap();
+        db.run("create table app.users (id int, name varchar(10))")
+            .unwrap();
+        db.run("create table app.orders (id int)").unwrap();
+        db.run("insert into users values (1, 'public')").unwrap();
+        db.run("insert into app.users values (1, 'app'), (2, 'app')")
+            .unwrap();
+        assert_eq!(text_rows(&mut db, "select name from users"), vec!["public"]);
+        assert_eq!(
+            text_rows(&mut db, "select name from app.users"),
+            vec!["app", "app"]
+        );
+        assert!(db.run("select id from orders").is_err());
+        assert_eq!(
+            text_rows(
+                &mut db,
+                "select table_schema, table_name from information_schema.tables \
+                 where table_name = 'users' order by table_schema"
+            ),
+            vec!["app users", "public users"]
+        );
+
+        // the first schema of the path holding the table wins, qualified names ignore it
+        db.run("set search_path = app, public").unwrap();
+        assert_eq!(
+            db.setting("search_path").unwrap(),
+            SettingValue::Names(vec!["app".to_string(), "public".to_string()])
+        );
+        assert_eq!(
+            text_rows(&mut db, "select name from users"),
+            vec!["app", "app"]
+        );
+        assert_eq!(
+            text_rows(&mut db, "select name from public.users"),
+            vec!["public"]
+        );
+        db.run("insert into orders values (7)").unwrap();
+        db.run("update users set name = 'changed' where id = 2")
+            .unwrap();
+        assert_eq!(
+            text_rows(&mut db, "select name from app.users"),
+            vec!["app", "changed"]
+        );
+        // new tables go to the first schema of the path
+        db.run("create table items (id int)").unwrap();
+        assert!(db.run("select id from app.items").is_ok());
+        assert!(db.run("select id from public.items").is_err());
+
+        db.run("set search_path = public, app").unwrap();
+        assert_eq!(text_rows(&mut db, "select name from users"), vec!["public"]);
+        assert_eq!(text_rows(&mut db, "select id from orders"), vec!["7"]);
+        db.run("set search_path = missing").unwrap();
+        assert!(matches!(
+            db.run("create table t1 (id int)"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("set search_path = default").unwrap();
+
+        // schemas and their tables are loaded again
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(text_rows(&mut db, "select name from users"), vec!["public"]);
+        assert_eq!(
+            text_rows(&mut db, "select name from app.users"),
+            vec!["app", "changed"]
+        );
+        assert_eq!(text_rows(&mut db, "select id from app.orders"), vec!["7"]);
+    }
+
+    #[test]
+    pub fn test_catalog_drop_schema_cascade() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create schema app").unwrap();
+        db.run("create table app.t1 (a int, b varchar(100))")
+            .unwrap();
+        db.run("create index idx_a on app.t1 (a)").unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        let values = (0..200)
+            .map(|i| format!("({i}, 'row {i} of a table spanning a few pages')"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into app.t1 values {values}"))
+            .unwrap();
+        db.run("insert into t1 values (1)").unwrap();
+
+        assert!(matches!(
+            db.run("drop schema app"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("drop schema information_schema cascade"),
+            Err(BustubxError::Plan(_))
+        ));
+        let heap_pages = db
+            .relation_sizes()
+            .unwrap()
+            .into_iter()
+            .find(|size| size.table.schema() == Some("app"))
+            .unwrap()
+            .heap_pages as u64;
+        assert!(heap_pages > 1);
+        let pages_freed = db.runtime_stats().pages_freed;
+
+        // the tables go with the schema, their heap and index pages are freed
+        assert_no_page_leaks(&mut db, |db| db.run("drop schema app cascade").unwrap());
+        assert!(db.runtime_stats().pages_freed - pages_freed >= heap_pages);
+        assert!(!db.catalog.schemas.contains_key("app"));
+        assert!(db.run("select a from app.t1").is_err());
+        assert!(text_rows(
+            &mut db,
+            "select table_name from information_schema.tables where table_schema = 'app'"
+        )
+        .is_empty());
+        assert!(
+            !text_rows(&mut db, "select * from information_schema.schemas")
+                .iter()
+                .any(|row| row.ends_with(" app"))
+        );
+        assert_eq!(text_rows(&mut db, "select a from t1"), vec!["1"]);
+
+        assert!(matches!(
+            db.run("drop schema app"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("drop schema if exists app").unwrap();
+        // an empty schema needs no CASCADE
+        db.run("create schema app").unwrap();
+        db.run("drop schema app").unwrap();
+        db.run("create schema app").unwrap();
+        db.run("create table app.t1 (a int)").unwrap();
+        assert!(text_rows(&mut db, "select a from app.t1").is_empty());
+    }
+
+    fn assert_read_only(result: crate::BustubxResult<Vec<Tuple>>, table: &str) {
+        match result {
+            Err(BustubxError::TableReadOnly { table: table_ref }) => {
+                assert_eq!(table_ref.table(), table)
+            }
+            result => panic!("write to {table} should be rejected, got {result:?}"),
+        }
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_table() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20)").unwrap();
+        // planned and cached while the table was writable
+        db.run("update t1 set b = 0 where a = 3").unwrap();
+        db.run("alter table t1 set read_only").unwrap();
+
+        for sql in [
+            "insert into t1 values (3, 30)",
+            "update t1 set b = 0 where a = 3",
+            "delete from t1 where a = 1",
+            "delete from t1 order by a limit 1",
+            "update t1 set b = 0 limit 1",
+            "truncate t1",
+            "drop table t1",
+            "alter table t1 drop column b",
+        ] {
+            assert_read_only(db.run(sql), "t1");
+        }
+        assert_read_only(db.run("drop table if exists t1"), "t1");
+        // reads and maintenance leave the rows alone
+        assert_eq!(
+            text_rows(&mut db, "select a, b from t1 where a = 2"),
+            vec!["2 20"]
+        );
+        db.run("analyze t1").unwrap();
+        db.run("check table t1 with indexes").unwrap();
+        assert_eq!(
+            text_rows(
+                &mut db,
+                "select read_only from information_schema.tables where table_name = 't1'"
+            ),
+            vec!["true"]
+        );
+
+        // a schema holding a read only table cannot be dropped either
+        db.run("create schema app").unwrap();
+        db.run("create table app.t2 (c int)").unwrap();
+        db.run("alter table app.t2 set read_only").unwrap();
+        assert_read_only(db.run("drop schema app cascade"), "t2");
+        assert!(db.catalog.schemas.contains_key("app"));
+        assert!(matches!(
+            db.run("alter table information_schema.tables set read_only"),
+            Err(BustubxError::NotSupport(_))
+        ));
+
+        db.run("alter table t1 set read_write").unwrap();
+        db.run("insert into t1 values (3, 30)").unwrap();
+        db.run("update t1 set b = 0 where a = 3").unwrap();
+        db.run("delete from t1 where a = 1").unwrap();
+        assert_eq!(
+            text_rows(&mut db, "select a, b from t1"),
+            vec!["2 20", "3 0"]
+        );
+        db.run("drop table t1").unwrap();
+        db.run("alter table app.t2 set read_write").unwrap();
+        db.run("drop schema app cascade").unwrap();
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_survives_reopen() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("create table t2 (a int)").unwrap();
+        db.run("insert into t1 values (1)").unwrap();
+        db.run("alter table t1 set read_only").unwrap();
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_read_only(db.run("insert into t1 values (2)"), "t1");
+        db.run("insert into t2 values (2)").unwrap();
+        assert_eq!(
+            text_rows(
+                &mut db,
+                "select table_name, read_only from information_schema.tables \
+                 where table_schema = 'public'"
+            ),
+            vec!["t1 true", "t2 false"]
+        );
+        db.run("alter table t1 set read_write").unwrap();
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("insert into t1 values (2)").unwrap();
+        assert_eq!(text_rows(&mut db, "select a from t1"), vec!["1", "2"]);
+        db.close().unwrap();
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_create_index() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("alter table t1 set read_only").unwrap();
+        assert_read_only(db.run("create index idx_a on t1 (a)"), "t1");
+        let t1 = TableReference::bare("t1");
+        assert!(db.catalog.index(&t1, "idx_a").unwrap().is_none());
+        db.run("alter table t1 set read_write").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_reindex() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("alter table t1 set read_only").unwrap();
+        assert_read_only(db.run("reindex table t1"), "t1");
+        assert_read_only(db.run("reindex index idx_a"), "t1");
+        db.run("alter table t1 set read_write").unwrap();
+        db.run("reindex index idx_a").unwrap();
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_cluster() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("insert into t1 values (2), (1)").unwrap();
+        db.run("alter table t1 set read_only").unwrap();
+        assert_read_only(db.run("cluster t1 using idx_a"), "t1");
+        db.run("alter table t1 set read_write").unwrap();
+        db.run("cluster t1 using idx_a").unwrap();
+    }
+
+    #[test]
+    pub fn test_catalog_read_only_keeps_expired_rows() {
+        let clock = Arc::new(MockClock::new(1000));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        db.run("create table cache (k int, expires_at bigint) with (ttl_column = 'expires_at')")
+            .unwrap();
+        db.run("insert into cache values (1, 100), (2, 4102444800)")
+            .unwrap();
+        db.run("alter table cache set read_only").unwrap();
+        clock.set(150);
+        assert_eq!(db.expire_rows().unwrap(), vec![]);
+        assert_eq!(text_rows(&mut db, "select k from cache"), vec!["1", "2"]);
+
+        db.run("alter table cache set read_write").unwrap();
+        let cache_ref = TableReference::full("bustubx", "public", "cache");
+        assert_eq!(db.expire_rows().unwrap(), vec![(cache_ref, 1)]);
+        assert_eq!(text_rows(&mut db, "select k from cache"), vec!["2"]);
+    }
 }
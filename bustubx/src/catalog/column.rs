@@ -2,7 +2,9 @@ use derive_with::With;
 use std::sync::Arc;
 
 use crate::catalog::DataType;
+use crate::common::value_ord::Collation;
 use crate::common::{ScalarValue, TableReference};
+use crate::expression::Expr;
 
 pub type ColumnRef = Arc<Column>;
 
@@ -13,6 +15,10 @@ pub struct Column {
     pub data_type: DataType,
     pub nullable: bool,
     pub default: ScalarValue,
+    // volatile default evaluated for every inserted row, `default` is null then
+    pub default_expr: Option<DefaultExpr>,
+    // how strings of the column compare, binary for a column of another type
+    pub collation: Collation,
 }
 
 impl PartialEq for Column {
@@ -31,6 +37,22 @@ impl Column {
             data_type,
             nullable,
             default: ScalarValue::new_empty(data_type),
+            default_expr: None,
+            collation: Collation::Binary,
         }
     }
 }
+
+/// Non-constant column default such as `DEFAULT now()`.
+#[derive(Debug, Clone)]
+pub struct DefaultExpr {
+    /// SQL text the default was bound from, stored in the catalog
+    pub sql: String,
+    pub expr: Expr,
+}
+
+impl std::fmt::Display for DefaultExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.sql)
+    }
+}
@@ -1,5 +1,4 @@
 use crate::error::BustubxError;
-use crate::BustubxResult;
 use sqlparser::dialect::PostgreSqlDialect;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,56 +15,8 @@ pub enum DataType {
     Float32,
     Float64,
     Varchar(Option<usize>),
-}
-
-impl DataType {
-    /// Coerce `lhs_type` and `rhs_type` to a common type for the purposes of a comparison operation
-    /// where one both are numeric
-    pub fn comparison_numeric_coercion(l: &DataType, r: &DataType) -> BustubxResult<DataType> {
-        use super::DataType::*;
-        if l == r {
-            return Ok(*l);
-        }
-        match (l, r) {
-            (Float64, _) | (_, Float64) => Ok(Float64),
-            (_, Float32) | (Float32, _) => Ok(Float32),
-            // The following match arms encode the following logic: Given the two
-            // integral types, we choose the narrowest possible integral type that
-            // accommodates all values of both types. Note that some information
-            // loss is inevitable when we have a signed type and a `UInt64`, in
-            // which case we use `Int64`;i.e. the widest signed integral type.
-            (Int64, _)
-            | (_, Int64)
-            | (UInt64, Int8)
-            | (Int8, UInt64)
-            | (UInt64, Int16)
-            | (Int16, UInt64)
-            | (UInt64, Int32)
-            | (Int32, UInt64)
-            | (UInt32, Int8)
-            | (Int8, UInt32)
-            | (UInt32, Int16)
-            | (Int16, UInt32)
-            | (UInt32, Int32)
-            | (Int32, UInt32) => Ok(Int64),
-            (UInt64, _) | (_, UInt64) => Ok(UInt64),
-            (Int32, _)
-            | (_, Int32)
-            | (UInt16, Int16)
-            | (Int16, UInt16)
-            | (UInt16, Int8)
-            | (Int8, UInt16) => Ok(Int32),
-            (UInt32, _) | (_, UInt32) => Ok(UInt32),
-            (Int16, _) | (_, Int16) | (Int8, UInt8) | (UInt8, Int8) => Ok(Int16),
-            (UInt16, _) | (_, UInt16) => Ok(UInt16),
-            (Int8, _) | (_, Int8) => Ok(Int8),
-            (UInt8, _) | (_, UInt8) => Ok(UInt8),
-            _ => Err(BustubxError::Internal(format!(
-                "Cannot coerce {} and {} for comparison",
-                l, r
-            ))),
-        }
-    }
+    // arbitrary byte string
+    Bytea,
 }
 
 impl TryFrom<&sqlparser::ast::DataType> for DataType {
@@ -86,7 +37,7 @@ impl TryFrom<&sqlparser::ast::DataType> for DataType {
             | sqlparser::ast::DataType::UnsignedInteger(_) => Ok(DataType::UInt32),
             sqlparser::ast::DataType::UnsignedBigInt(_) => Ok(DataType::UInt64),
             sqlparser::ast::DataType::Float(_) => Ok(DataType::Float32),
-            sqlparser::ast::DataType::Double => Ok(DataType::Float32),
+            sqlparser::ast::DataType::Double => Ok(DataType::Float64),
             sqlparser::ast::DataType::Varchar(len) => {
                 Ok(DataType::Varchar(len.map(|l| l.length as usize)))
             }
@@ -96,6 +47,9 @@ impl TryFrom<&sqlparser::ast::DataType> for DataType {
             sqlparser::ast::DataType::CharacterVarying(len) => {
                 Ok(DataType::Varchar(len.map(|l| l.length as usize)))
             }
+            sqlparser::ast::DataType::Bytea | sqlparser::ast::DataType::Blob(_) => {
+                Ok(DataType::Bytea)
+            }
             _ => Err(BustubxError::NotSupport(format!(
                 "Not support datatype {}",
                 value
@@ -124,6 +78,7 @@ impl From<&DataType> for sqlparser::ast::DataType {
                     unit: None,
                 }))
             }
+            DataType::Bytea => sqlparser::ast::DataType::Bytea,
         }
     }
 }
@@ -141,7 +96,8 @@ impl std::fmt::Display for DataType {
             | DataType::UInt32
             | DataType::UInt64
             | DataType::Float32
-            | DataType::Float64 => write!(f, "{self:?}"),
+            | DataType::Float64
+            | DataType::Bytea => write!(f, "{self:?}"),
             DataType::Varchar(len_opt) => {
                 if let Some(len) = len_opt {
                     write!(f, "Varchar({len})")
@@ -181,5 +137,16 @@ mod tests {
             DataType::try_from(format!("{sql_type}").as_str()).unwrap(),
             DataType::Varchar(Some(100))
         );
+
+        let sql_type: sqlparser::ast::DataType = (&DataType::Bytea).into();
+        assert_eq!(
+            DataType::try_from(format!("{sql_type}").as_str()).unwrap(),
+            DataType::Bytea
+        );
+        assert_eq!(DataType::try_from("blob").unwrap(), DataType::Bytea);
+        assert_eq!(
+            DataType::try_from(format!("{}", DataType::Bytea).as_str()).unwrap(),
+            DataType::Bytea
+        );
     }
 }
@@ -0,0 +1,300 @@
+use std::io::Write;
+
+use crate::catalog::{Catalog, Partitioning, DEFAULT_SCHEMA_NAME, INFORMATION_SCHEMA_NAME};
+use crate::common::{ScalarValue, TableReference};
+use crate::planner::{create_index_sql, create_table_sql, sql_literal};
+use crate::storage::TableIterator;
+use crate::{BustubxError, BustubxResult};
+
+// rows per INSERT statement of a dump
+pub const DUMP_INSERT_BATCH_ROWS: usize = 100;
+
+/// Write the user schemas and tables of the catalog as a SQL script that rebuilds them:
+/// `CREATE SCHEMA`, `CREATE TABLE` and `CREATE INDEX` statements, the live rows of every
+/// table as batched `INSERT`s, then the read only flags. Partitions follow the other tables
+/// and hold the rows of their partitioned table, tables follow the tables their foreign keys
+/// reference.
+///
+/// Nothing writes to the catalog while it is borrowed, so the rows of all tables are those
+/// of one point in time. Rows hidden as expired by their ttl column are dumped too.
+/// Returns the number of rows written.
+pub fn dump_catalog(catalog: &Catalog, writer: &mut dyn Write) -> BustubxResult<usize> {
+    let mut schema_names = catalog
+        .schemas
+        .keys()
+        .filter(|name| *name != INFORMATION_SCHEMA_NAME && *name != DEFAULT_SCHEMA_NAME)
+        .collect::<Vec<_>>();
+    schema_names.sort();
+    for schema_name in schema_names {
+        writeln!(writer, "CREATE SCHEMA {schema_name};")?;
+    }
+
+    let mut tables = vec![];
+    for table_ref in catalog.user_tables() {
+        let table_ref = TableReference::partial(
+            table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME),
+            table_ref.table(),
+        );
+        let catalog_table = catalog.catalog_table(&table_ref)?;
+        let depth = reference_depth(catalog, &table_ref)?;
+        tables.push((table_ref, catalog_table, depth));
+    }
+    // a partition is created once its partitioned table exists, a table once the tables
+    // it references exist with their unique indexes and rows
+    tables.sort_by_key(|(_, catalog_table, depth)| {
+        (
+            matches!(
+                catalog_table.partitioning,
+                Some(Partitioning::Partition { .. })
+            ),
+            *depth,
+        )
+    });
+    let tables = tables
+        .into_iter()
+        .map(|(table_ref, catalog_table, _)| (table_ref, catalog_table))
+        .collect::<Vec<_>>();
+
+    // CREATE INDEX leaves out the rows already in the table, the inserts fill the indexes
+    for (table_ref, catalog_table) in tables.iter() {
+        writeln!(writer, "{};", create_table_sql(table_ref, catalog_table))?;
+        for create_index in create_index_sql(table_ref, catalog_table) {
+            writeln!(writer, "{create_index};")?;
+        }
+    }
+
+    let mut dumped_rows = 0;
+    for (table_ref, catalog_table) in tables.iter() {
+        let heap = catalog_table.table.clone();
+        let mut rows = vec![];
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        loop {
+            let next = iterator.next()?;
+            if let Some((rid, tuple)) = &next {
+                if heap.tuple_meta(*rid)?.is_deleted {
+                    continue;
+                }
+                let values = tuple
+                    .data
+                    .iter()
+                    .map(dump_literal)
+                    .collect::<BustubxResult<Vec<_>>>()?;
+                rows.push(format!("({})", values.join(", ")));
+            }
+            if rows.len() == DUMP_INSERT_BATCH_ROWS || (next.is_none() && !rows.is_empty()) {
+                writeln!(
+                    writer,
+                    "INSERT INTO {} VALUES {};",
+                    table_ref,
+                    rows.join(", ")
+                )?;
+                dumped_rows += rows.len();
+                rows.clear();
+            }
+            if next.is_none() {
+                break;
+            }
+        }
+    }
+
+    for (table_ref, catalog_table) in tables.iter() {
+        if catalog_table.read_only {
+            writeln!(writer, "ALTER TABLE {table_ref} SET read_only;")?;
+        }
+    }
+    writer.flush()?;
+    Ok(dumped_rows)
+}
+
+// longest chain of foreign keys from the table to a table referencing none, which cannot
+// loop as a foreign key only references a table created before
+fn reference_depth(catalog: &Catalog, table_ref: &TableReference) -> BustubxResult<usize> {
+    let mut depth = 0;
+    for foreign_key in catalog.catalog_table(table_ref)?.foreign_keys.iter() {
+        depth = depth.max(reference_depth(catalog, &foreign_key.parent)? + 1);
+    }
+    Ok(depth)
+}
+
+// NaN and infinities have no literal the parser reads back
+fn dump_literal(value: &ScalarValue) -> BustubxResult<String> {
+    let finite = match value {
+        ScalarValue::Float32(Some(v)) => v.is_finite(),
+        ScalarValue::Float64(Some(v)) => v.is_finite(),
+        _ => true,
+    };
+    if !finite {
+        return Err(BustubxError::NotSupport(format!(
+            "value {} cannot be dumped",
+            value
+        )));
+    }
+    Ok(sql_literal(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database, DatabaseOptions};
+
+    fn dump(db: &Database) -> String {
+        let mut script = vec![];
+        db.dump(&mut script).unwrap();
+        String::from_utf8(script).unwrap()
+    }
+
+    fn fixture(db: &mut Database) {
+        db.run("create schema app").unwrap();
+        db.run(
+            "create table app.users (id int not null, name varchar(100), active boolean, \
+             score double default 1.5, avatar bytea, created bigint default now())",
+        )
+        .unwrap();
+        db.run("create unique index idx_id on app.users (id)")
+            .unwrap();
+        db.run("create index idx_name_active on app.users (name, active)")
+            .unwrap();
+        db.run(
+            "create table sessions (id bigint, expires_at bigint) with (ttl_column = 'expires_at')",
+        )
+        .unwrap();
+        db.run("create table events (id int, ts int) partition by range (ts)")
+            .unwrap();
+        db.run("create table events_1 partition of events for values from (0) to (100)")
+            .unwrap();
+        db.run("create table events_2 partition of events for values from (100) to (200)")
+            .unwrap();
+        db.run("create table codes (code varchar(10))").unwrap();
+        db.run("create unique index idx_code on codes (code) deferrable initially deferred")
+            .unwrap();
+        // created before the table it references in the order of the table names
+        db.run(
+            "create table app.orders (id int, user_id int references app.users (id) \
+             deferrable initially deferred, code varchar(10), \
+             constraint orders_code foreign key (code) references codes (code))",
+        )
+        .unwrap();
+
+        db.run(
+            "insert into app.users values \
+             (1, 'O''Brien; said \"hi\"', true, 0.25, x'00ff10', 7), \
+             (2, null, false, -3.5, null, 8), \
+             (3, 'line\nbreak', null, 1e300, x'', 9)",
+        )
+        .unwrap();
+        db.run("insert into app.users (id, name) values (4, 'defaults')")
+            .unwrap();
+        let values = (0..250)
+            .map(|i| format!("({i}, {})", 4_000_000_000i64 + i))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into sessions values {values}"))
+            .unwrap();
+        db.run("delete from sessions where id >= 240").unwrap();
+        db.run("insert into events values (1, 5), (2, 150), (-3, 99)")
+            .unwrap();
+        db.run("insert into codes values ('a'), ('b')").unwrap();
+        db.run("insert into app.orders values (1, 1, 'a'), (2, 4, null), (3, null, 'b')")
+            .unwrap();
+        db.run("alter table codes set read_only").unwrap();
+    }
+
+    fn contents(db: &mut Database) -> Vec<Vec<Vec<ScalarValue>>> {
+        [
+            // an indexed table may be read in the order of any of its indexes
+            "select * from app.users order by id",
+            "select * from sessions order by id",
+            "select * from events_1 order by id",
+            "select * from events_2 order by id",
+            "select * from codes order by code",
+            "select * from app.orders order by id",
+        ]
+        .iter()
+        .map(|sql| {
+            db.run(sql)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.data)
+                .collect()
+        })
+        .collect()
+    }
+
+    #[test]
+    pub fn test_dump_and_restore() {
+        let mut db = Database::new_temp().unwrap();
+        fixture(&mut db);
+        let script = dump(&db);
+        assert!(script.contains(
+            "INSERT INTO app.users VALUES (1, 'O''Brien; said \"hi\"', TRUE, 0.25, x'00ff10', 7)"
+        ));
+        assert!(script.contains("-3.5"));
+        assert!(script.contains(
+            "CREATE UNIQUE INDEX idx_code ON public.codes (code) DEFERRABLE INITIALLY DEFERRED"
+        ));
+        // 240 live rows in three statements
+        assert_eq!(script.matches("INSERT INTO public.sessions").count(), 3);
+        assert!(!script.contains("INSERT INTO public.events "));
+        assert!(script.contains(
+            "CONSTRAINT orders_user_id_fkey FOREIGN KEY (user_id) REFERENCES app.users (id) \
+             DEFERRABLE INITIALLY DEFERRED"
+        ));
+        assert!(script.find("INSERT INTO public.codes") < script.find("INSERT INTO app.orders"));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("restored.db");
+        let mut restored =
+            Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        restored.restore_script(script.as_bytes()).unwrap();
+        assert_eq!(contents(&mut restored), contents(&mut db));
+        for table in [
+            "app.users",
+            "sessions",
+            "events",
+            "events_1",
+            "codes",
+            "app.orders",
+        ] {
+            let sql = format!("show create table {table}");
+            assert_eq!(restored.run(&sql).unwrap(), db.run(&sql).unwrap());
+        }
+        // the whole catalog and every row, also across a restart
+        assert_eq!(dump(&restored), script);
+        restored.close().unwrap();
+        let mut restored =
+            Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(dump(&restored), script);
+        assert!(matches!(
+            restored.run("insert into app.users (id) values (1)"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            restored.run("insert into codes values ('c')"),
+            Err(BustubxError::TableReadOnly { .. })
+        ));
+        assert_eq!(
+            restored
+                .run("select id from app.users where name = 'defaults'")
+                .unwrap()[0]
+                .data,
+            vec![ScalarValue::Int32(Some(4))]
+        );
+    }
+
+    #[test]
+    pub fn test_restore_script_stops_at_error() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a double)").unwrap();
+        db.run("insert into t1 values (1.5)").unwrap();
+        let script = dump(&db);
+
+        let mut restored = Database::new_temp().unwrap();
+        restored.run("create table t1 (a int)").unwrap();
+        assert!(restored.restore_script(script.as_bytes()).is_err());
+
+        assert!(matches!(
+            super::dump_literal(&ScalarValue::Float64(Some(f64::INFINITY))),
+            Err(BustubxError::NotSupport(_))
+        ));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::catalog::{Catalog, CatalogTable, DEFAULT_SCHEMA_NAME, INFORMATION_SCHEMA_NAME};
+use crate::common::{ScalarValue, TableReference};
+use crate::storage::index::{BPlusTreeIndex, ConstraintTiming, TreeIndexIterator};
+use crate::storage::{TableIterator, Tuple};
+use crate::{BustubxError, BustubxResult};
+use std::sync::Arc;
+
+/// `FOREIGN KEY (columns) REFERENCES parent (parent_columns)` of a table. A row whose key
+/// columns are all non null needs a live row of the parent holding the same values, which
+/// the parent has a unique index on. Checked when the statement ends or, if deferred, when
+/// the transaction commits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub name: String,
+    pub columns: Vec<String>,
+    // qualified with its schema
+    pub parent: TableReference,
+    pub parent_columns: Vec<String>,
+    // INITIALLY timing of a deferrable foreign key, None for one checked by every statement
+    pub deferrable: Option<ConstraintTiming>,
+}
+
+impl ForeignKey {
+    /// Name Postgres gives a foreign key declared without one.
+    pub fn default_name(table: &str, columns: &[String]) -> String {
+        format!("{}_{}_fkey", table, columns.join("_"))
+    }
+}
+
+/// Values of `columns` in the row, None when one of them is null, as a null key references
+/// nothing.
+pub fn foreign_key_values(
+    tuple: &Tuple,
+    columns: &[String],
+) -> BustubxResult<Option<Vec<ScalarValue>>> {
+    let mut values = Vec::with_capacity(columns.len());
+    for column in columns {
+        let value = tuple.value_by_name(None, column)?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        values.push(value.clone());
+    }
+    Ok(Some(values))
+}
+
+impl Catalog {
+    pub fn has_foreign_keys(&self) -> bool {
+        self.schemas.values().any(|catalog_schema| {
+            catalog_schema
+                .tables
+                .values()
+                .any(|catalog_table| !catalog_table.foreign_keys.is_empty())
+        })
+    }
+
+    /// The foreign keys referencing the table, with the tables holding them.
+    pub fn referencing_foreign_keys(
+        &self,
+        parent: &TableReference,
+    ) -> Vec<(TableReference, &ForeignKey)> {
+        let parent_schema = parent.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+        let mut referencing = vec![];
+        for (schema_name, catalog_schema) in self.schemas.iter() {
+            if schema_name == INFORMATION_SCHEMA_NAME {
+                continue;
+            }
+            for (table_name, catalog_table) in catalog_schema.tables.iter() {
+                for foreign_key in catalog_table.foreign_keys.iter() {
+                    if foreign_key.parent.table() == parent.table()
+                        && foreign_key.parent.schema().unwrap_or(DEFAULT_SCHEMA_NAME)
+                            == parent_schema
+                    {
+                        referencing.push((
+                            TableReference::partial(schema_name, table_name),
+                            foreign_key,
+                        ));
+                    }
+                }
+            }
+        }
+        referencing.sort_by(|a, b| (&a.0, &a.1.name).cmp(&(&b.0, &b.1.name)));
+        referencing
+    }
+
+    /// Fails if a table outside of `dropped` has a foreign key referencing one of them.
+    pub fn check_drop_unreferenced(&self, dropped: &[TableReference]) -> BustubxResult<()> {
+        let qualified = |table_ref: &TableReference| {
+            (
+                table_ref
+                    .schema()
+                    .unwrap_or(DEFAULT_SCHEMA_NAME)
+                    .to_string(),
+                table_ref.table().to_string(),
+            )
+        };
+        let dropped = dropped.iter().map(qualified).collect::<Vec<_>>();
+        for parent in dropped.iter() {
+            let parent_ref = TableReference::partial(parent.0.as_str(), parent.1.as_str());
+            for (child, foreign_key) in self.referencing_foreign_keys(&parent_ref) {
+                if !dropped.contains(&qualified(&child)) {
+                    return Err(BustubxError::Plan(format!(
+                        "cannot drop table {} because foreign key {} of table {} references it",
+                        parent_ref, foreign_key.name, child
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a live row of the table holds `values` in `columns`, found through an index
+    /// on exactly those columns if the table has one.
+    pub fn holds_key(
+        &self,
+        table_ref: &TableReference,
+        columns: &[String],
+        values: &[ScalarValue],
+    ) -> BustubxResult<bool> {
+        let catalog_table = self.catalog_table(table_ref)?;
+        let holds = |tuple: &Tuple| -> BustubxResult<bool> {
+            for (column, value) in columns.iter().zip(values) {
+                if tuple.value_by_name(None, column)? != value {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        let heap = catalog_table.table.clone();
+        if let Some(index) = key_index(catalog_table, columns) {
+            let key = Tuple::new(index.key_schema.clone(), values.to_vec());
+            let mut entries = TreeIndexIterator::new(index, key.clone()..=key);
+            while let Some(entry) = entries.next()? {
+                let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                    continue;
+                };
+                // an entry whose row moved or changed since is stale
+                if let Some(tuple) = heap.live_tuple(rid)? {
+                    if holds(&tuple)? {
+                        return Ok(true);
+                    }
+                }
+            }
+            return Ok(false);
+        }
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            if !heap.tuple_meta(rid)?.is_deleted && holds(&tuple)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Whether a foreign key references the key the row of `parent` holds.
+    pub fn is_key_referenced(&self, parent: &TableReference, tuple: &Tuple) -> BustubxResult<bool> {
+        for (child, foreign_key) in self.referencing_foreign_keys(parent) {
+            if let Some(values) = foreign_key_values(tuple, &foreign_key.parent_columns)? {
+                if self.holds_key(&child, &foreign_key.columns, &values)? {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Fails if the row of `parent` that held `tuple` was the last one holding a key that a
+    /// foreign key `checked` picks by its timing still references.
+    pub fn check_key_unreferenced(
+        &self,
+        parent: &TableReference,
+        tuple: &Tuple,
+        checked: impl Fn(Option<ConstraintTiming>) -> bool,
+    ) -> BustubxResult<()> {
+        for (child, foreign_key) in self.referencing_foreign_keys(parent) {
+            if !checked(foreign_key.deferrable) {
+                continue;
+            }
+            let Some(values) = foreign_key_values(tuple, &foreign_key.parent_columns)? else {
+                continue;
+            };
+            if !self.holds_key(parent, &foreign_key.parent_columns, &values)?
+                && self.holds_key(&child, &foreign_key.columns, &values)?
+            {
+                return Err(BustubxError::Execution(format!(
+                    "update or delete on table {} violates foreign key {} of table {}",
+                    parent.table(),
+                    foreign_key.name,
+                    child
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the row of `table_ref` references a key no live row of the parent holds,
+    /// by one of its foreign keys `checked` picks by their timing.
+    pub fn check_key_referenced(
+        &self,
+        table_ref: &TableReference,
+        tuple: &Tuple,
+        checked: impl Fn(Option<ConstraintTiming>) -> bool,
+    ) -> BustubxResult<()> {
+        for foreign_key in self.catalog_table(table_ref)?.foreign_keys.iter() {
+            if !checked(foreign_key.deferrable) {
+                continue;
+            }
+            let Some(values) = foreign_key_values(tuple, &foreign_key.columns)? else {
+                continue;
+            };
+            if !self.holds_key(&foreign_key.parent, &foreign_key.parent_columns, &values)? {
+                return Err(BustubxError::Execution(format!(
+                    "insert or update on table {} violates foreign key {}",
+                    table_ref.table(),
+                    foreign_key.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Index of the table whose key columns are `columns` in order, a unique one first.
+pub fn key_index(catalog_table: &CatalogTable, columns: &[String]) -> Option<Arc<BPlusTreeIndex>> {
+    let mut indexes = catalog_table
+        .indexes
+        .iter()
+        .filter(|(name, index)| {
+            catalog_table.key_projection(name).is_ok()
+                && index.key_schema.columns.len() == columns.len()
+                && index
+                    .key_schema
+                    .columns
+                    .iter()
+                    .zip(columns)
+                    .all(|(key_column, column)| &key_column.name == column)
+        })
+        .collect::<Vec<_>>();
+    indexes.sort_by_key(|(name, index)| (!index.unique, *name));
+    indexes.first().map(|(_, index)| (*index).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::common::{MockClock, TableReference};
+    use crate::{BustubxError, Database, DatabaseOptions};
+
+    fn create_tables(db: &mut Database) {
+        db.run("create schema app").unwrap();
+        db.run(
+            "create table app.users (id int, expires_at bigint) with (ttl_column = 'expires_at')",
+        )
+        .unwrap();
+        db.run("create unique index idx_id on app.users (id)")
+            .unwrap();
+        db.run(
+            "create table orders (id int, user_id int, \
+             constraint orders_user foreign key (user_id) references app.users (id) \
+             deferrable initially deferred)",
+        )
+        .unwrap();
+    }
+
+    fn violates(result: crate::BustubxResult<Vec<crate::Tuple>>, name: &str) -> bool {
+        matches!(result, Err(BustubxError::Execution(msg)) if msg.contains(name))
+    }
+
+    #[test]
+    pub fn test_foreign_key_survives_reopen() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        create_tables(&mut db);
+        let create_orders = db.run("show create table orders").unwrap();
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(db.run("show create table orders").unwrap(), create_orders);
+        assert!(violates(
+            db.run("insert into orders values (1, 1)"),
+            "orders_user"
+        ));
+        db.run("insert into app.users values (1, 4102444800)")
+            .unwrap();
+        db.run("insert into orders values (1, 1)").unwrap();
+        db.close().unwrap();
+    }
+
+    #[test]
+    pub fn test_foreign_key_parent_drop() {
+        let mut db = Database::new_temp().unwrap();
+        create_tables(&mut db);
+        assert!(matches!(
+            db.run("drop table app.users"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("drop schema app cascade"),
+            Err(BustubxError::Plan(_))
+        ));
+        // along with the tables referencing it
+        db.run("drop table orders, app.users").unwrap();
+        db.run("drop schema app cascade").unwrap();
+    }
+
+    #[test]
+    pub fn test_foreign_key_keeps_referenced_expired_rows() {
+        let clock = Arc::new(MockClock::new(1000));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        create_tables(&mut db);
+        db.run("insert into app.users values (1, 100), (2, 100)")
+            .unwrap();
+        db.run("insert into orders values (1, 1)").unwrap();
+        clock.set(150);
+        let users_ref = TableReference::full("bustubx", "app", "users");
+        assert_eq!(db.expire_rows().unwrap(), vec![(users_ref.clone(), 1)]);
+
+        db.run("delete from orders").unwrap();
+        assert_eq!(db.expire_rows().unwrap(), vec![(users_ref, 1)]);
+    }
+}
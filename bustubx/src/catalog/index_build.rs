@@ -0,0 +1,449 @@
+//! Bulk load of a new index from the rows already in its table. Large tables are scanned
+//! by worker threads, each one projecting the keys of a run of consecutive heap pages and
+//! sorting them, the sorted entries of all workers are merged in one thread feeding the
+//! bulk loader. The entries come out in the order of a serial build, ties in heap order,
+//! so every number of workers makes the same tree.
+
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use crate::buffer::{BufferPoolManager, PageId};
+use crate::catalog::{Column, DataType, KeyProjection, Schema, SchemaRef};
+use crate::common::ScalarValue;
+use crate::config::{
+    ExecutionOptions, DEFAULT_PARALLEL_SCAN_MIN_PAGES, DEFAULT_SORT_MEMORY_BUDGET,
+};
+use crate::execution::physical_plan::{tuple_size_estimate, SortRun};
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::{LeafKV, RecordId, RowIdMap, TableHeap, Tuple};
+use crate::{BustubxError, BustubxResult};
+
+// the input position of an entry is the worker in the high bits and the position within
+// the pages of the worker in the low ones, ordering them as the heap does
+const WORKER_SEQ_SHIFT: u32 = 40;
+
+/// How CREATE INDEX fills the new index from the rows of its table, taken from the
+/// parallel scan and sort settings of the statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexBuild {
+    /// Threads scanning a run of heap pages each, 0 and 1 scan serially
+    pub workers: usize,
+    /// Tables of fewer pages are scanned serially
+    pub min_pages: usize,
+    /// Bytes of entries the workers together keep in memory, beyond that every worker
+    /// writes its sorted entries out as a run
+    pub memory_budget: usize,
+}
+
+impl Default for IndexBuild {
+    fn default() -> Self {
+        Self {
+            workers: 0,
+            min_pages: DEFAULT_PARALLEL_SCAN_MIN_PAGES,
+            memory_budget: DEFAULT_SORT_MEMORY_BUDGET,
+        }
+    }
+}
+
+impl IndexBuild {
+    pub fn from_options(options: &ExecutionOptions) -> Self {
+        Self {
+            workers: options.parallel_scan_workers,
+            min_pages: options.parallel_scan_min_pages,
+            memory_budget: options.sort_memory_budget,
+        }
+    }
+}
+
+/// Fill the empty `index` with the keys of the live rows of `heap`, scanned by one thread
+/// when the build has fewer than 2 workers or the heap fewer than `min_pages` pages. A
+/// unique index fails with [`BustubxError::DuplicateKey`] when two rows share a key without
+/// NULLs, leaving the index empty.
+pub(crate) fn build_index(
+    heap: &Arc<TableHeap>,
+    projection: &KeyProjection,
+    index: &BPlusTreeIndex,
+    build: &IndexBuild,
+) -> BustubxResult<()> {
+    let mut partitions = heap.partition_pages(build.workers.max(1))?;
+    if partitions.iter().map(Vec::len).sum::<usize>() < build.min_pages {
+        partitions = vec![partitions.concat()];
+    }
+    let memory_budget = build.memory_budget / partitions.len().max(1);
+    // the first worker failing stops the others
+    let cancel = AtomicBool::new(false);
+    let sorted = match partitions.len() {
+        0 => vec![],
+        1 => sort_pages(
+            heap,
+            projection,
+            &index.key_schema,
+            partitions.concat(),
+            0,
+            memory_budget,
+            &index.buffer_pool,
+            &cancel,
+        )?,
+        _ => thread::scope(|scope| {
+            let handles = partitions
+                .into_iter()
+                .enumerate()
+                .map(|(worker, page_ids)| {
+                    let cancel = &cancel;
+                    scope.spawn(move || {
+                        let result = sort_pages(
+                            heap,
+                            projection,
+                            &index.key_schema,
+                            page_ids,
+                            (worker as u64) << WORKER_SEQ_SHIFT,
+                            memory_budget,
+                            &index.buffer_pool,
+                            cancel,
+                        );
+                        if result.is_err() {
+                            cancel.store(true, Ordering::SeqCst);
+                        }
+                        result
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut sorted = vec![];
+            let mut error = None;
+            for handle in handles {
+                match handle.join() {
+                    Ok(Ok(entries)) => sorted.extend(entries),
+                    Ok(Err(e)) => {
+                        error.get_or_insert(e);
+                    }
+                    Err(_) => {
+                        error.get_or_insert(BustubxError::Internal(
+                            "an index build worker panicked".to_string(),
+                        ));
+                    }
+                }
+            }
+            match error {
+                Some(e) => Err(e),
+                None => Ok(sorted),
+            }
+        })?,
+    };
+    let entries = merge(sorted)?;
+    if index.unique {
+        check_unique(&entries)?;
+    }
+    index.rebuild(entries)
+}
+
+// Project and sort the keys of the live rows of `page_ids`, entries over the memory
+// budget are written out as sorted runs
+#[allow(clippy::too_many_arguments)]
+fn sort_pages(
+    heap: &TableHeap,
+    projection: &KeyProjection,
+    key_schema: &SchemaRef,
+    page_ids: Vec<PageId>,
+    mut seq: u64,
+    memory_budget: usize,
+    buffer_pool: &Arc<BufferPoolManager>,
+    cancel: &AtomicBool,
+) -> BustubxResult<Vec<SortedEntries>> {
+    let mut sorted = vec![];
+    let mut entries = vec![];
+    let mut memory_used = 0;
+    for page_id in page_ids {
+        if cancel.load(Ordering::SeqCst) {
+            return Ok(vec![]);
+        }
+        for (rid, meta, tuple) in heap.page_tuples(page_id)? {
+            if meta.is_deleted {
+                continue;
+            }
+            let entry = meta.row_id.map_or(rid, RowIdMap::index_entry);
+            let key = projection.project(&tuple)?;
+            memory_used += tuple_size_estimate(&key) + std::mem::size_of::<(u64, LeafKV)>();
+            entries.push((seq, (key, entry)));
+            seq += 1;
+        }
+        if memory_used > memory_budget {
+            sorted.push(SortedEntries::spill(
+                std::mem::take(&mut entries),
+                key_schema,
+                buffer_pool,
+            )?);
+            memory_used = 0;
+        }
+    }
+    sorted.push(SortedEntries::in_memory(entries));
+    Ok(sorted)
+}
+
+// Entries of one worker sorted by key, ties in heap order
+#[derive(Debug)]
+struct SortedEntries {
+    source: EntrySource,
+    // next entry with its input position
+    head: Option<(u64, LeafKV)>,
+}
+
+#[derive(Debug)]
+enum EntrySource {
+    InMemory(std::vec::IntoIter<(u64, LeafKV)>),
+    // rows of the key columns followed by the page id and slot of the entry
+    Spilled { run: SortRun, key_schema: SchemaRef },
+}
+
+impl SortedEntries {
+    fn in_memory(mut entries: Vec<(u64, LeafKV)>) -> Self {
+        entries.sort_by(|a, b| compare_keys(&a.1 .0, &b.1 .0));
+        let mut entries = entries.into_iter();
+        Self {
+            head: entries.next(),
+            source: EntrySource::InMemory(entries),
+        }
+    }
+
+    fn spill(
+        mut entries: Vec<(u64, LeafKV)>,
+        key_schema: &SchemaRef,
+        buffer_pool: &Arc<BufferPoolManager>,
+    ) -> BustubxResult<Self> {
+        entries.sort_by(|a, b| compare_keys(&a.1 .0, &b.1 .0));
+        let row_schema = Arc::new(Schema::try_merge([
+            key_schema.as_ref().clone(),
+            Schema::new(vec![
+                Column::new("__index_build_page", DataType::UInt32, false),
+                Column::new("__index_build_slot", DataType::UInt32, false),
+            ]),
+        ])?);
+        let rows = entries
+            .into_iter()
+            .map(|(seq, (key, rid))| {
+                let mut data = key.data;
+                data.push(ScalarValue::UInt32(Some(rid.page_id)));
+                data.push(ScalarValue::UInt32(Some(rid.slot_num)));
+                (seq, Tuple::new(row_schema.clone(), data))
+            })
+            .collect();
+        let run = SortRun::write(rows, row_schema, buffer_pool)?;
+        let mut sorted = Self {
+            source: EntrySource::Spilled {
+                run,
+                key_schema: key_schema.clone(),
+            },
+            head: None,
+        };
+        sorted.advance()?;
+        Ok(sorted)
+    }
+
+    fn advance(&mut self) -> BustubxResult<()> {
+        self.head = match &mut self.source {
+            EntrySource::InMemory(entries) => entries.next(),
+            EntrySource::Spilled { run, key_schema } => match run.head.take() {
+                None => None,
+                Some((seq, row)) => {
+                    run.advance()?;
+                    let mut data = row.data;
+                    let (
+                        Some(ScalarValue::UInt32(Some(slot_num))),
+                        Some(ScalarValue::UInt32(Some(page_id))),
+                    ) = (data.pop(), data.pop())
+                    else {
+                        return Err(BustubxError::Internal(
+                            "index build run row without record id".to_string(),
+                        ));
+                    };
+                    let key = Tuple::new(key_schema.clone(), data);
+                    Some((seq, (key, RecordId::new(page_id, slot_num))))
+                }
+            },
+        };
+        Ok(())
+    }
+}
+
+// Merge the sorted entries of the workers, equal keys in order of their input position
+fn merge(mut sorted: Vec<SortedEntries>) -> BustubxResult<Vec<LeafKV>> {
+    let mut entries = vec![];
+    // smallest head first, the runs of a spilling build are many
+    let mut heads = BinaryHeap::new();
+    for (input, sorted) in sorted.iter_mut().enumerate() {
+        if let Some((seq, entry)) = sorted.head.take() {
+            heads.push(Reverse(MergeHead { entry, seq, input }));
+        }
+    }
+    while let Some(Reverse(MergeHead { entry, input, .. })) = heads.pop() {
+        entries.push(entry);
+        sorted[input].advance()?;
+        if let Some((seq, entry)) = sorted[input].head.take() {
+            heads.push(Reverse(MergeHead { entry, seq, input }));
+        }
+    }
+    Ok(entries)
+}
+
+// next entry of one of the merged inputs, ordered by key and input position
+#[derive(Debug)]
+struct MergeHead {
+    entry: LeafKV,
+    seq: u64,
+    input: usize,
+}
+
+impl Ord for MergeHead {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        compare_keys(&self.entry.0, &other.entry.0).then(self.seq.cmp(&other.seq))
+    }
+}
+
+impl PartialOrd for MergeHead {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for MergeHead {}
+
+fn compare_keys(a: &Tuple, b: &Tuple) -> CmpOrdering {
+    a.partial_cmp(b).unwrap_or(CmpOrdering::Equal)
+}
+
+// keys with a NULL never conflict
+fn check_unique(entries: &[LeafKV]) -> BustubxResult<()> {
+    let duplicate = entries.windows(2).find(|pair| {
+        !pair[0].0.data.iter().any(|v| v.is_null())
+            && compare_keys(&pair[0].0, &pair[1].0) == CmpOrdering::Equal
+    });
+    match duplicate {
+        Some(pair) => Err(BustubxError::DuplicateKey { rid: pair[0].1 }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TableReference;
+    use crate::storage::{LeafKV, Tuple, TupleMeta, EMPTY_TUPLE_META};
+    use crate::Database;
+
+    const ROWS: i32 = 30_000;
+
+    // rows of (a, b) with every b on about 100 rows, a tenth of them deleted, written to the
+    // heap directly since inserting them one statement at a time takes long
+    fn fixture() -> Database {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        let table = db
+            .catalog
+            .catalog_table(&TableReference::bare("t1"))
+            .unwrap()
+            .table
+            .clone();
+        for i in 0..ROWS {
+            let meta = TupleMeta {
+                is_deleted: i % 10 == 3,
+                ..EMPTY_TUPLE_META
+            };
+            let tuple = Tuple::new(table.schema.clone(), vec![i.into(), (i % 997).into()]);
+            table.insert_tuple(&meta, &tuple).unwrap();
+        }
+        db
+    }
+
+    // the entries of the index in scan order, and the bytes of its pages
+    fn index_contents(db: &Database, index_name: &str) -> (Vec<LeafKV>, Vec<Vec<u8>>) {
+        let index = db
+            .catalog
+            .index(&TableReference::bare("t1"), index_name)
+            .unwrap()
+            .unwrap();
+        let (findings, entries) = index.check(index_name);
+        assert!(findings.is_empty(), "{findings:?}");
+        let pages = index
+            .page_ids()
+            .unwrap()
+            .into_iter()
+            .map(|page_id| {
+                let page = db.buffer_pool.fetch_page(page_id).unwrap();
+                let bytes = page.read().unwrap().data().to_vec();
+                bytes
+            })
+            .collect();
+        (entries.into_iter().map(|(_, entry)| entry).collect(), pages)
+    }
+
+    #[test]
+    pub fn test_parallel_build_matches_serial() {
+        let mut serial = fixture();
+        serial.run("create index t1_b on t1 (b)").unwrap();
+        let (entries, pages) = index_contents(&serial, "t1_b");
+        assert_eq!(entries.len(), ROWS as usize / 10 * 9);
+        // equal keys in heap order
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0
+            || pair[0].0 == pair[1].0
+                && (pair[0].1.page_id, pair[0].1.slot_num)
+                    < (pair[1].1.page_id, pair[1].1.slot_num)));
+
+        let mut parallel = fixture();
+        parallel.run("set parallel_scan_workers = 4").unwrap();
+        parallel.run("set parallel_scan_min_pages = 1").unwrap();
+        parallel.run("create index t1_b on t1 (b)").unwrap();
+        assert_eq!(index_contents(&parallel, "t1_b"), (entries.clone(), pages));
+
+        // workers spilling their sorted entries, and the serial rebuild of REINDEX after
+        parallel.run("set sort_memory_budget = 65536").unwrap();
+        parallel.run("create index t1_b2 on t1 (b)").unwrap();
+        assert_eq!(index_contents(&parallel, "t1_b2").0, entries);
+        parallel.run("reindex t1_b2").unwrap();
+        assert_eq!(index_contents(&parallel, "t1_b2").0, entries);
+        assert_eq!(
+            parallel.run("select a from t1 where b = 5").unwrap(),
+            serial.run("select a from t1 where b = 5").unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_unique_build_rejects_duplicates() {
+        for workers in [0, 4] {
+            let mut db = Database::new_temp().unwrap();
+            db.run(&format!("set parallel_scan_workers = {workers}"))
+                .unwrap();
+            db.run("set parallel_scan_min_pages = 1").unwrap();
+            db.run("create table t1 (a int, b int)").unwrap();
+            let values = (0..2000)
+                .map(|i| format!("({i}, {})", i % 1000))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!(
+                "insert into t1 values {values}, (null, 1000), (null, 1001)"
+            ))
+            .unwrap();
+            db.run("create unique index t1_a on t1 (a)").unwrap();
+            assert!(db.run("insert into t1 values (7, 0)").is_err());
+
+            let err = db.run("create unique index t1_b on t1 (b)").unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("could not create unique index t1_b"),
+                "{err}"
+            );
+            let table = TableReference::bare("t1");
+            assert!(db.catalog.index(&table, "t1_b").unwrap().is_none());
+            // the name is free again
+            db.run("delete from t1 where a >= 1000").unwrap();
+            db.run("create unique index t1_b on t1 (b)").unwrap();
+            assert!(db.run("check table t1 with indexes").unwrap().is_empty());
+        }
+    }
+}
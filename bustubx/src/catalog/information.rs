@@ -1,11 +1,18 @@
-use crate::buffer::{AtomicPageId, PageId, INVALID_PAGE_ID};
-use crate::catalog::catalog::{CatalogSchema, CatalogTable};
-use crate::catalog::{Catalog, Column, DataType, Schema, SchemaRef, DEFAULT_SCHEMA_NAME};
+use crate::buffer::{PageId, INVALID_PAGE_ID};
+use crate::catalog::catalog::{CatalogSchema, CatalogTable, Partitioning};
+use crate::catalog::{
+    Catalog, Column, DataType, ForeignKey, KeyPart, Schema, SchemaRef, DEFAULT_SCHEMA_NAME,
+};
+use crate::common::value_ord::Collation;
 use crate::common::{ScalarValue, TableReference};
-use crate::storage::TableHeap;
+use crate::parser::parse_expr;
+use crate::planner::{LogicalPlanner, PlannerContext};
+use crate::storage::{TableHeap, ROW_ID_INDEX_NAME, ROW_ID_KEY_SCHEMA};
 use crate::{BustubxError, BustubxResult, Database};
 
-use crate::storage::index::BPlusTreeIndex;
+use crate::storage::index::{BPlusTreeIndex, ConstraintTiming};
+use log::debug;
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, LazyLock};
 
 pub static INFORMATION_SCHEMA_NAME: &str = "information_schema";
@@ -13,6 +20,10 @@ pub static INFORMATION_SCHEMA_SCHEMAS: &str = "schemas";
 pub static INFORMATION_SCHEMA_TABLES: &str = "tables";
 pub static INFORMATION_SCHEMA_COLUMNS: &str = "columns";
 pub static INFORMATION_SCHEMA_INDEXES: &str = "indexes";
+// virtual table computed from the catalog when queried, see `Catalog::relation_sizes`
+pub static INFORMATION_SCHEMA_RELATION_SIZES: &str = "relation_sizes";
+// virtual table of the access counters, see `Catalog::access_stats`
+pub static INFORMATION_SCHEMA_STATISTICS: &str = "statistics";
 
 pub static SCHEMAS_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
     Arc::new(Schema::new(vec![
@@ -27,6 +38,18 @@ pub static TABLES_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
         Column::new("table_schema", DataType::Varchar(None), false),
         Column::new("table_name", DataType::Varchar(None), false),
         Column::new("first_page_id", DataType::UInt32, false),
+        Column::new("ttl_column", DataType::Varchar(None), true),
+        // key column of a table partitioned by range and of its partitions
+        Column::new("partition_column", DataType::Varchar(None), true),
+        // parent and range of a partition, the bounds in their text form
+        Column::new("partition_of", DataType::Varchar(None), true),
+        Column::new("partition_from", DataType::Varchar(None), true),
+        Column::new("partition_to", DataType::Varchar(None), true),
+        Column::new("read_only", DataType::Boolean, false),
+        // index the heap was last clustered by
+        Column::new("clustered_index", DataType::Varchar(None), true),
+        // foreign keys of the table as JSON, see `foreign_keys_to_varchar`
+        Column::new("foreign_keys", DataType::Varchar(None), true),
     ]))
 });
 
@@ -39,6 +62,9 @@ pub static COLUMNS_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
         Column::new("data_type", DataType::Varchar(None), false),
         Column::new("nullable", DataType::Boolean, false),
         Column::new("default", DataType::Varchar(None), false),
+        Column::new("default_expr", DataType::Varchar(None), true),
+        // null for a binary collation
+        Column::new("collation", DataType::Varchar(None), true),
     ]))
 });
 
@@ -52,6 +78,48 @@ pub static INDEXES_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
         Column::new("internal_max_size", DataType::UInt32, false),
         Column::new("leaf_max_size", DataType::UInt32, false),
         Column::new("root_page_id", DataType::UInt32, false),
+        Column::new("unique", DataType::Boolean, false),
+        // key columns of an index with an expression key as JSON, null for an index of
+        // table columns, see `key_columns_to_varchar`
+        Column::new("key_columns", DataType::Varchar(None), true),
+        // INITIALLY timing of a deferrable unique index, null for an index checked per row
+        Column::new("deferrable", DataType::Varchar(None), true),
+    ]))
+});
+
+// one row per table followed by its indexes, the tuple counts of an index are null
+// and so is the fill of a table, the bloat of an index is the estimate of its last analyze
+pub static RELATION_SIZES_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("table_schema", DataType::Varchar(None), false),
+        Column::new("table_name", DataType::Varchar(None), false),
+        Column::new("relation_name", DataType::Varchar(None), false),
+        Column::new("relation_kind", DataType::Varchar(None), false),
+        Column::new("pages", DataType::UInt64, false),
+        Column::new("live_tuples", DataType::UInt64, true),
+        Column::new("dead_tuples", DataType::UInt64, true),
+        Column::new("avg_fill", DataType::Float64, true),
+        Column::new("estimated_bloat", DataType::Float64, true),
+        Column::new("size_bytes", DataType::UInt64, false),
+        Column::new("total_bytes", DataType::UInt64, false),
+    ]))
+});
+
+// one row per table followed by its indexes, the index counts of a table are summed over
+// its indexes and its scan and row counts are null for an index
+pub static STATISTICS_SCHMEA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("table_schema", DataType::Varchar(None), false),
+        Column::new("table_name", DataType::Varchar(None), false),
+        Column::new("relation_name", DataType::Varchar(None), false),
+        Column::new("relation_kind", DataType::Varchar(None), false),
+        Column::new("seq_scans", DataType::UInt64, true),
+        Column::new("seq_tuples_read", DataType::UInt64, true),
+        Column::new("index_scans", DataType::UInt64, false),
+        Column::new("index_tuples_fetched", DataType::UInt64, false),
+        Column::new("tuples_inserted", DataType::UInt64, true),
+        Column::new("tuples_updated", DataType::UInt64, true),
+        Column::new("tuples_deleted", DataType::UInt64, true),
     ]))
 });
 
@@ -103,45 +171,45 @@ fn load_information_schema(catalog: &mut Catalog) -> BustubxResult<()> {
 
     let mut information_schema = CatalogSchema::new(INFORMATION_SCHEMA_NAME);
 
-    let schemas_table = TableHeap {
-        schema: SCHEMAS_SCHMEA.clone(),
-        buffer_pool: catalog.buffer_pool.clone(),
-        first_page_id: AtomicPageId::new(information_schema_schemas_first_page_id),
-        last_page_id: AtomicPageId::new(information_schema_schemas_last_page_id),
-    };
+    let schemas_table = TableHeap::open(
+        SCHEMAS_SCHMEA.clone(),
+        catalog.buffer_pool.clone(),
+        information_schema_schemas_first_page_id,
+        information_schema_schemas_last_page_id,
+    );
     information_schema.tables.insert(
         INFORMATION_SCHEMA_SCHEMAS.to_string(),
         CatalogTable::new(INFORMATION_SCHEMA_SCHEMAS, Arc::new(schemas_table)),
     );
 
-    let tables_table = TableHeap {
-        schema: TABLES_SCHMEA.clone(),
-        buffer_pool: catalog.buffer_pool.clone(),
-        first_page_id: AtomicPageId::new(information_schema_tables_first_page_id),
-        last_page_id: AtomicPageId::new(information_schema_tables_last_page_id),
-    };
+    let tables_table = TableHeap::open(
+        TABLES_SCHMEA.clone(),
+        catalog.buffer_pool.clone(),
+        information_schema_tables_first_page_id,
+        information_schema_tables_last_page_id,
+    );
     information_schema.tables.insert(
         INFORMATION_SCHEMA_TABLES.to_string(),
         CatalogTable::new(INFORMATION_SCHEMA_TABLES, Arc::new(tables_table)),
     );
 
-    let columns_table = TableHeap {
-        schema: COLUMNS_SCHMEA.clone(),
-        buffer_pool: catalog.buffer_pool.clone(),
-        first_page_id: AtomicPageId::new(information_schema_columns_first_page_id),
-        last_page_id: AtomicPageId::new(information_schema_columns_last_page_id),
-    };
+    let columns_table = TableHeap::open(
+        COLUMNS_SCHMEA.clone(),
+        catalog.buffer_pool.clone(),
+        information_schema_columns_first_page_id,
+        information_schema_columns_last_page_id,
+    );
     information_schema.tables.insert(
         INFORMATION_SCHEMA_COLUMNS.to_string(),
         CatalogTable::new(INFORMATION_SCHEMA_COLUMNS, Arc::new(columns_table)),
     );
 
-    let indexes_table = TableHeap {
-        schema: INDEXES_SCHMEA.clone(),
-        buffer_pool: catalog.buffer_pool.clone(),
-        first_page_id: AtomicPageId::new(information_schema_indexes_first_page_id),
-        last_page_id: AtomicPageId::new(information_schema_indexes_last_page_id),
-    };
+    let indexes_table = TableHeap::open(
+        INDEXES_SCHMEA.clone(),
+        catalog.buffer_pool.clone(),
+        information_schema_indexes_first_page_id,
+        information_schema_indexes_last_page_id,
+    );
     information_schema.tables.insert(
         INFORMATION_SCHEMA_INDEXES.to_string(),
         CatalogTable::new(INFORMATION_SCHEMA_INDEXES, Arc::new(indexes_table)),
@@ -195,7 +263,36 @@ fn load_user_tables(db: &mut Database) -> BustubxResult<()> {
         let ScalarValue::UInt32(Some(first_page_id)) = table_tuple.value(3)? else {
             return error;
         };
+        let ScalarValue::Varchar(ttl_column) = table_tuple.value(4)? else {
+            return error;
+        };
+        let (
+            ScalarValue::Varchar(partition_column),
+            ScalarValue::Varchar(partition_of),
+            ScalarValue::Varchar(partition_from),
+            ScalarValue::Varchar(partition_to),
+        ) = (
+            table_tuple.value(5)?,
+            table_tuple.value(6)?,
+            table_tuple.value(7)?,
+            table_tuple.value(8)?,
+        )
+        else {
+            return error;
+        };
+        let ScalarValue::Boolean(Some(read_only)) = table_tuple.value(9)? else {
+            return error;
+        };
+        let ScalarValue::Varchar(clustered_index) = table_tuple.value(10)? else {
+            return error;
+        };
+        let foreign_keys = match table_tuple.value(11)? {
+            ScalarValue::Varchar(Some(saved)) => parse_foreign_keys_from_varchar(saved)?,
+            ScalarValue::Varchar(None) => vec![],
+            _ => return error,
+        };
 
+        let table_ref = TableReference::full(catalog, table_schema, table_name);
         let column_tuples = db.run(&format!("select * from {}.{} where table_catalog = '{}' and table_schema = '{}' and table_name = '{}'",
                                             INFORMATION_SCHEMA_NAME, INFORMATION_SCHEMA_COLUMNS, catalog, table_schema, table_name))?;
         let mut columns = vec![];
@@ -216,25 +313,72 @@ fn load_user_tables(db: &mut Database) -> BustubxResult<()> {
             let ScalarValue::Varchar(Some(default)) = column_tuple.value(6)? else {
                 return error;
             };
+            let ScalarValue::Varchar(default_expr) = column_tuple.value(7)? else {
+                return error;
+            };
+            let ScalarValue::Varchar(collation) = column_tuple.value(8)? else {
+                return error;
+            };
             let data_type: DataType = data_type_str.as_str().try_into()?;
-            let default = ScalarValue::from_string(default, data_type)?;
-            columns
-                .push(Column::new(column_name.clone(), data_type, *nullable).with_default(default));
+            let mut default = ScalarValue::from_string(default, data_type)?;
+            let mut bound_default_expr = None;
+            if let Some(sql) = default_expr {
+                let planner = LogicalPlanner {
+                    context: PlannerContext {
+                        catalog: &db.catalog,
+                        clock: db.clock.clone(),
+                        search_path: vec![DEFAULT_SCHEMA_NAME.to_string()],
+                    },
+                };
+                (default, bound_default_expr) =
+                    planner.bind_column_default(column_name, &parse_expr(sql)?, data_type)?;
+            }
+            columns.push(
+                Column::new(column_name.clone(), data_type, *nullable)
+                    .with_relation(Some(table_ref.clone()))
+                    .with_default(default)
+                    .with_default_expr(bound_default_expr)
+                    .with_collation(match collation {
+                        Some(collation) => collation.as_str().try_into()?,
+                        None => Collation::Binary,
+                    }),
+            );
         }
         let schema = Arc::new(Schema::new(columns));
+        let partitioning = match (partition_column, partition_of, partition_from, partition_to) {
+            (None, _, _, _) => None,
+            (Some(column), None, _, _) => Some(Partitioning::Range {
+                column: column.clone(),
+            }),
+            (Some(column), Some(parent), Some(from), Some(to)) => {
+                let data_type = schema.column_with_name(None, column)?.data_type;
+                Some(Partitioning::Partition {
+                    parent: parent.clone(),
+                    column: column.clone(),
+                    from: ScalarValue::from_string(from, data_type)?,
+                    to: ScalarValue::from_string(to, data_type)?,
+                })
+            }
+            _ => return error,
+        };
 
         // load last page id
         let last_page_id =
             load_table_last_page_id(&mut db.catalog, *first_page_id, schema.clone())?;
-        let table_heap = TableHeap {
-            schema: schema.clone(),
-            buffer_pool: db.buffer_pool.clone(),
-            first_page_id: AtomicPageId::new(*first_page_id),
-            last_page_id: AtomicPageId::new(last_page_id),
-        };
+        let table_heap = TableHeap::open(
+            schema.clone(),
+            db.buffer_pool.clone(),
+            *first_page_id,
+            last_page_id,
+        );
         db.catalog.load_table(
-            TableReference::full(catalog, table_schema, table_name),
-            CatalogTable::new(table_name, Arc::new(table_heap)),
+            table_ref,
+            CatalogTable::new(table_name, Arc::new(table_heap))
+                .with_ttl_column(ttl_column.clone())
+                .with_partitioning(partitioning)
+                .with_read_only(*read_only)
+                .with_clustered_index(clustered_index.clone())
+                .with_foreign_keys(foreign_keys),
         )?;
     }
     Ok(())
@@ -274,27 +418,146 @@ fn load_user_indexes(db: &mut Database) -> BustubxResult<()> {
         let ScalarValue::UInt32(Some(root_page_id)) = index_tuple.value(7)? else {
             return error;
         };
+        let ScalarValue::Boolean(Some(unique)) = index_tuple.value(8)? else {
+            return error;
+        };
+        let ScalarValue::Varchar(key_columns) = index_tuple.value(9)? else {
+            return error;
+        };
+        let deferrable = match index_tuple.value(10)? {
+            ScalarValue::Varchar(None) => None,
+            ScalarValue::Varchar(Some(timing)) if timing == "IMMEDIATE" => {
+                Some(ConstraintTiming::Immediate)
+            }
+            ScalarValue::Varchar(Some(timing)) if timing == "DEFERRED" => {
+                Some(ConstraintTiming::Deferred)
+            }
+            _ => return error,
+        };
 
         let table_ref = TableReference::full(catalog_name, table_schema_name, table_name);
-        let table_schema = db.catalog.table_heap(&table_ref)?.schema.clone();
-        let key_schema = Arc::new(parse_key_schema_from_varchar(
-            key_schema_str.as_str(),
-            table_schema,
-        )?);
+        let key_schema = if index_name == ROW_ID_INDEX_NAME {
+            ROW_ID_KEY_SCHEMA.clone()
+        } else if let Some(key_columns) = key_columns {
+            Arc::new(parse_key_columns_from_varchar(key_columns)?)
+        } else {
+            let table_schema = db.catalog.table_heap(&table_ref)?.schema.clone();
+            Arc::new(parse_key_schema_from_varchar(
+                key_schema_str.as_str(),
+                table_schema,
+            )?)
+        };
 
-        let b_plus_tree_index = BPlusTreeIndex {
+        let b_plus_tree_index = BPlusTreeIndex::new(
             key_schema,
-            buffer_pool: db.buffer_pool.clone(),
-            internal_max_size: *internal_max_size,
-            leaf_max_size: *leaf_max_size,
-            root_page_id: AtomicPageId::new(*root_page_id),
-        };
-        db.catalog
-            .load_index(table_ref, index_name, Arc::new(b_plus_tree_index))?;
+            db.buffer_pool.clone(),
+            *internal_max_size,
+            *leaf_max_size,
+        )
+        .with_unique(*unique)
+        .with_deferrable(deferrable)
+        .with_root_page_id(*root_page_id);
+        // indexes of a db file written before leaves linked back get the links once
+        let linked = b_plus_tree_index.link_leaves_back()?;
+        if linked > 0 {
+            debug!(
+                "linked the {} leaves of index {} back to their previous leaves",
+                linked, index_name
+            );
+        }
+        if index_name == ROW_ID_INDEX_NAME {
+            db.catalog
+                .load_row_ids(table_ref, Arc::new(b_plus_tree_index))?;
+        } else if key_columns.is_some() {
+            load_expression_index(db, &table_ref, index_name, Arc::new(b_plus_tree_index))?;
+        } else {
+            db.catalog
+                .load_index(table_ref, index_name, Arc::new(b_plus_tree_index))?;
+        }
     }
     Ok(())
 }
 
+// No function is registered while the catalog loads, so an expression index calling one
+// stays unresolved until `Database::register_function` registers it.
+fn load_expression_index(
+    db: &mut Database,
+    table_ref: &TableReference,
+    index_name: &str,
+    index: Arc<BPlusTreeIndex>,
+) -> BustubxResult<()> {
+    let parts = bind_key_parts(db, table_ref, &index.key_schema);
+    let catalog_table = db.catalog.catalog_table_mut(table_ref)?;
+    match parts {
+        Ok(parts) => catalog_table.add_index_with_parts(index_name.to_string(), index, parts),
+        Err(BustubxError::UnknownFunction(function)) => {
+            catalog_table.add_unresolved_index(index_name.to_string(), index, function);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// A key column named after a column of the table is that column, any other is the SQL
+// text of an expression over the columns.
+fn bind_key_parts(
+    db: &Database,
+    table_ref: &TableReference,
+    key_schema: &Schema,
+) -> BustubxResult<Vec<KeyPart>> {
+    let table_schema = db.catalog.table_heap(table_ref)?.schema.clone();
+    let planner = LogicalPlanner {
+        context: PlannerContext {
+            catalog: &db.catalog,
+            clock: db.clock.clone(),
+            search_path: vec![DEFAULT_SCHEMA_NAME.to_string()],
+        },
+    };
+    key_schema
+        .columns
+        .iter()
+        .map(|col| match table_schema.index_of(None, &col.name) {
+            Ok(ordinal) => Ok(KeyPart::Column(ordinal)),
+            Err(_) => Ok(KeyPart::Expr(planner.bind_expr(&parse_expr(&col.name)?)?)),
+        })
+        .collect()
+}
+
+/// Bind the keys of the expression indexes left unresolved, after a function was
+/// registered. An index resolved is bulk loaded from the rows of its table, which may have
+/// changed without it, e.g. by a vacuum or a recovery. Returns the indexes resolved.
+pub fn resolve_index_keys(db: &mut Database) -> BustubxResult<Vec<String>> {
+    let mut unresolved = vec![];
+    for (schema_name, catalog_schema) in db.catalog.schemas.iter() {
+        for (table_name, catalog_table) in catalog_schema.tables.iter() {
+            for index_name in catalog_table.unresolved_indexes() {
+                unresolved.push((
+                    TableReference::partial(schema_name, table_name),
+                    index_name.clone(),
+                    catalog_table.indexes[index_name].clone(),
+                ));
+            }
+        }
+    }
+    let mut resolved = vec![];
+    for (table_ref, index_name, index) in unresolved {
+        load_expression_index(db, &table_ref, &index_name, index)?;
+        if db
+            .catalog
+            .catalog_table(&table_ref)?
+            .unresolved_indexes()
+            .all(|name| *name != index_name)
+        {
+            db.catalog.reindex(&table_ref, Some(index_name.as_str()))?;
+            resolved.push(index_name);
+        }
+    }
+    if !resolved.is_empty() {
+        db.catalog.persist_index_roots()?;
+    }
+    Ok(resolved)
+}
+
 fn load_table_last_page_id(
     catalog: &mut Catalog,
     first_page_id: PageId,
@@ -323,10 +586,110 @@ pub fn key_schema_to_varchar(key_schema: &Schema) -> String {
         .join(", ")
 }
 
+// a key column of an index with an expression key, the SQL text of an expression is its name
+#[derive(Serialize, Deserialize)]
+struct SavedKeyColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+}
+
+/// The key columns of an index as JSON, with their types, which the names of expression
+/// keys do not tell before they are bound.
+pub fn key_columns_to_varchar(key_schema: &Schema) -> BustubxResult<String> {
+    let saved = key_schema
+        .columns
+        .iter()
+        .map(|col| SavedKeyColumn {
+            name: col.name.clone(),
+            // written as SQL like the types in information_schema.columns, which parse back
+            data_type: sqlparser::ast::DataType::from(&col.data_type).to_string(),
+            nullable: col.nullable,
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string(&saved)
+        .map_err(|e| BustubxError::Internal(format!("cannot encode key columns: {e}")))
+}
+
+fn parse_key_columns_from_varchar(varchar: &str) -> BustubxResult<Schema> {
+    let saved: Vec<SavedKeyColumn> = serde_json::from_str(varchar)
+        .map_err(|e| BustubxError::Internal(format!("cannot decode key columns: {e}")))?;
+    let columns = saved
+        .into_iter()
+        .map(|col| {
+            let data_type: DataType = col.data_type.as_str().try_into()?;
+            Ok(Column::new(col.name, data_type, col.nullable))
+        })
+        .collect::<BustubxResult<Vec<_>>>()?;
+    Ok(Schema::new(columns))
+}
+
+// a foreign key with its parent split into schema and table
+#[derive(Serialize, Deserialize)]
+struct SavedForeignKey {
+    name: String,
+    columns: Vec<String>,
+    parent_schema: String,
+    parent_table: String,
+    parent_columns: Vec<String>,
+    deferrable: Option<String>,
+}
+
+/// The foreign keys of a table as JSON, None for a table without any.
+pub fn foreign_keys_to_varchar(foreign_keys: &[ForeignKey]) -> BustubxResult<Option<String>> {
+    if foreign_keys.is_empty() {
+        return Ok(None);
+    }
+    let saved = foreign_keys
+        .iter()
+        .map(|foreign_key| SavedForeignKey {
+            name: foreign_key.name.clone(),
+            columns: foreign_key.columns.clone(),
+            parent_schema: foreign_key
+                .parent
+                .schema()
+                .unwrap_or(DEFAULT_SCHEMA_NAME)
+                .to_string(),
+            parent_table: foreign_key.parent.table().to_string(),
+            parent_columns: foreign_key.parent_columns.clone(),
+            deferrable: foreign_key.deferrable.map(|timing| timing.to_string()),
+        })
+        .collect::<Vec<_>>();
+    serde_json::to_string(&saved)
+        .map(Some)
+        .map_err(|e| BustubxError::Internal(format!("cannot encode foreign keys: {e}")))
+}
+
+fn parse_foreign_keys_from_varchar(varchar: &str) -> BustubxResult<Vec<ForeignKey>> {
+    let saved: Vec<SavedForeignKey> = serde_json::from_str(varchar)
+        .map_err(|e| BustubxError::Internal(format!("cannot decode foreign keys: {e}")))?;
+    saved
+        .into_iter()
+        .map(|saved| {
+            let deferrable = match saved.deferrable.as_deref() {
+                None => None,
+                Some("IMMEDIATE") => Some(ConstraintTiming::Immediate),
+                Some("DEFERRED") => Some(ConstraintTiming::Deferred),
+                Some(timing) => {
+                    return Err(BustubxError::Internal(format!(
+                        "cannot decode foreign key timing {timing}"
+                    )))
+                }
+            };
+            Ok(ForeignKey {
+                name: saved.name,
+                columns: saved.columns,
+                parent: TableReference::partial(saved.parent_schema, saved.parent_table),
+                parent_columns: saved.parent_columns,
+                deferrable,
+            })
+        })
+        .collect()
+}
+
 fn parse_key_schema_from_varchar(varchar: &str, table_schema: SchemaRef) -> BustubxResult<Schema> {
     let column_names = varchar
         .split(",")
-        .into_iter()
         .map(|name| name.trim())
         .collect::<Vec<&str>>();
     let indices = column_names
@@ -0,0 +1,253 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::catalog::{Schema, SchemaRef};
+use crate::expression::{Expr, ExprTrait};
+use crate::{BustubxError, BustubxResult, Tuple};
+
+/// Derives the keys of an index from the rows of its table. Built once with the index and
+/// kept by the catalog next to it, see [`crate::catalog::CatalogTable::key_projection`].
+#[derive(Debug)]
+pub struct KeyProjection {
+    key_schema: SchemaRef,
+    parts: Vec<KeyPart>,
+    // columns of the table schema the projection was validated against
+    table_columns: usize,
+    // keys projected, lets tests tell that index maintenance goes through the projection
+    projected: AtomicU64,
+}
+
+/// Where one column of the key comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyPart {
+    /// Column of the table by ordinal.
+    Column(usize),
+    /// Expression bound against the table schema, of the type of the key column.
+    Expr(Expr),
+}
+
+impl KeyProjection {
+    /// Key made of table columns, the key columns are looked up by name.
+    pub fn try_new(table_schema: &Schema, key_schema: SchemaRef) -> BustubxResult<Self> {
+        let parts = key_schema
+            .columns
+            .iter()
+            .map(|col| {
+                table_schema
+                    .index_of(col.relation.as_ref(), &col.name)
+                    .map(KeyPart::Column)
+            })
+            .collect::<BustubxResult<Vec<_>>>()?;
+        Self::try_new_with_parts(table_schema, key_schema, parts)
+    }
+
+    /// Key with a part per key column, each part yields a value of the type of its column.
+    pub fn try_new_with_parts(
+        table_schema: &Schema,
+        key_schema: SchemaRef,
+        parts: Vec<KeyPart>,
+    ) -> BustubxResult<Self> {
+        if parts.len() != key_schema.column_count() {
+            return Err(BustubxError::Internal(format!(
+                "{} key parts for {} key columns",
+                parts.len(),
+                key_schema.column_count()
+            )));
+        }
+        for (part, key_column) in parts.iter().zip(key_schema.columns.iter()) {
+            let data_type = match part {
+                KeyPart::Column(ordinal) => table_schema.column_with_index(*ordinal)?.data_type,
+                KeyPart::Expr(expr) => expr.data_type(table_schema)?,
+            };
+            if data_type != key_column.data_type {
+                return Err(BustubxError::Internal(format!(
+                    "key column {} is {} but its part yields {}",
+                    key_column.name, key_column.data_type, data_type
+                )));
+            }
+        }
+        Ok(Self {
+            key_schema,
+            parts,
+            table_columns: table_schema.column_count(),
+            projected: AtomicU64::new(0),
+        })
+    }
+
+    pub fn key_schema(&self) -> &SchemaRef {
+        &self.key_schema
+    }
+
+    pub fn parts(&self) -> &[KeyPart] {
+        &self.parts
+    }
+
+    pub fn projected(&self) -> u64 {
+        self.projected.load(Ordering::Relaxed)
+    }
+
+    /// Key of a row of the table.
+    pub fn project(&self, tuple: &Tuple) -> BustubxResult<Tuple> {
+        let mut key = Tuple {
+            schema: self.key_schema.clone(),
+            data: Vec::with_capacity(self.parts.len()),
+        };
+        self.project_into(tuple, &mut key)?;
+        Ok(key)
+    }
+
+    /// Key of a row of the table written over `key`, reusing its allocation.
+    pub fn project_into(&self, tuple: &Tuple, key: &mut Tuple) -> BustubxResult<()> {
+        // a row of another layout than the one the projection was built for
+        if tuple.data.len() != self.table_columns {
+            return Err(BustubxError::Internal(format!(
+                "key projection built for {} columns applied to a row of {}",
+                self.table_columns,
+                tuple.data.len()
+            )));
+        }
+        key.data.clear();
+        for part in self.parts.iter() {
+            let value = match part {
+                KeyPart::Column(ordinal) => tuple.data[*ordinal].clone(),
+                KeyPart::Expr(expr) => expr.evaluate(tuple)?,
+            };
+            key.data.push(value);
+        }
+        if !std::sync::Arc::ptr_eq(&key.schema, &self.key_schema) {
+            key.schema = self.key_schema.clone();
+        }
+        self.projected.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::catalog::{Column, DataType, KeyPart, KeyProjection, Schema};
+    use crate::common::{ScalarValue, TableReference};
+    use crate::expression::{BinaryExpr, BinaryOp, ColumnExpr, Expr};
+    use crate::{Database, Tuple};
+
+    fn table_schema() -> Schema {
+        Schema::new(vec![
+            Column::new("a", DataType::Int32, false),
+            Column::new("b", DataType::Int32, true),
+            Column::new("c", DataType::Varchar(None), true),
+        ])
+    }
+
+    fn row(a: i32, b: i32, c: &str) -> Tuple {
+        Tuple::new(
+            Arc::new(table_schema()),
+            vec![a.into(), b.into(), c.to_string().into()],
+        )
+    }
+
+    #[test]
+    pub fn test_key_projection_reorders_columns() {
+        let schema = table_schema();
+        let key_schema = Arc::new(schema.project(&[2, 0]).unwrap());
+        let projection = KeyProjection::try_new(&schema, key_schema.clone()).unwrap();
+        assert_eq!(projection.parts(), [KeyPart::Column(2), KeyPart::Column(0)]);
+
+        let key = projection.project(&row(1, 2, "x")).unwrap();
+        assert_eq!(key.schema, key_schema);
+        assert_eq!(key.data, vec!["x".to_string().into(), 1i32.into()]);
+
+        // the allocation of the previous key is reused
+        let mut key = key;
+        projection.project_into(&row(3, 4, "y"), &mut key).unwrap();
+        assert_eq!(key.data, vec!["y".to_string().into(), 3i32.into()]);
+        assert_eq!(projection.projected(), 2);
+    }
+
+    #[test]
+    pub fn test_key_projection_rejects_dropped_column() {
+        let schema = table_schema();
+        let key_schema = Arc::new(schema.project(&[1]).unwrap());
+        let projection = KeyProjection::try_new(&schema, key_schema.clone()).unwrap();
+
+        // the table without column b
+        let dropped = schema.project(&[0, 2]).unwrap();
+        assert!(KeyProjection::try_new(&dropped, key_schema.clone()).is_err());
+        // nor may b be replaced by a column of another type
+        assert!(
+            KeyProjection::try_new_with_parts(&dropped, key_schema, vec![KeyPart::Column(1)])
+                .is_err()
+        );
+        // a projection kept from before the drop refuses the narrower rows
+        let narrow_row = Tuple::new(Arc::new(dropped), vec![1i32.into(), "x".to_string().into()]);
+        assert!(projection.project(&narrow_row).is_err());
+    }
+
+    #[test]
+    pub fn test_key_projection_expression_key() {
+        let schema = table_schema();
+        let key_schema = Arc::new(Schema::new(vec![Column::new(
+            "a_plus_b",
+            DataType::Int32,
+            true,
+        )]));
+        let column = |name: &str| {
+            Box::new(Expr::Column(ColumnExpr {
+                relation: None,
+                name: name.to_string(),
+            }))
+        };
+        let sum = Expr::Binary(BinaryExpr {
+            left: column("a"),
+            op: BinaryOp::Plus,
+            right: column("b"),
+        });
+        let projection = KeyProjection::try_new_with_parts(
+            &schema,
+            key_schema.clone(),
+            vec![KeyPart::Expr(sum)],
+        )
+        .unwrap();
+        let key = projection.project(&row(2, 5, "x")).unwrap();
+        assert_eq!(key.data, vec![ScalarValue::Int32(Some(7))]);
+
+        // an expression of another type than its key column
+        let text = Expr::Column(ColumnExpr {
+            relation: None,
+            name: "c".to_string(),
+        });
+        assert!(
+            KeyProjection::try_new_with_parts(&schema, key_schema, vec![KeyPart::Expr(text)])
+                .is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_index_maintenance_uses_key_projection() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        let table_ref = TableReference::bare("t1");
+        let projection = db
+            .catalog
+            .catalog_table(&table_ref)
+            .unwrap()
+            .key_projection("idx_b")
+            .unwrap();
+
+        db.run("insert into t1 values (1, 10), (2, 20), (3, 30)")
+            .unwrap();
+        assert_eq!(projection.projected(), 3);
+        db.run("delete from t1 where a = 2").unwrap();
+        assert!(projection.projected() > 3);
+        // the same instance is shared by every statement
+        assert!(Arc::ptr_eq(
+            &projection,
+            &db.catalog
+                .catalog_table(&table_ref)
+                .unwrap()
+                .key_projection("idx_b")
+                .unwrap()
+        ));
+        assert_eq!(db.run("select a from t1 where b = 30").unwrap().len(), 1);
+    }
+}
@@ -1,11 +1,28 @@
+mod access_stats;
+mod analyze;
+#[allow(clippy::module_inception)]
 mod catalog;
 mod column;
 mod data_type;
+mod dump;
+mod foreign_key;
+mod index_build;
 mod information;
+mod key_projection;
+mod relation_size;
 mod schema;
+mod statistics;
 
+pub use access_stats::*;
+pub use analyze::{AnalyzeJob, AnalyzedTable, AutoAnalyzer, TableModifications};
 pub use catalog::*;
-pub use column::{Column, ColumnRef};
+pub use column::{Column, ColumnRef, DefaultExpr};
 pub use data_type::DataType;
+pub use dump::*;
+pub use foreign_key::*;
+pub use index_build::IndexBuild;
 pub use information::*;
+pub use key_projection::{KeyPart, KeyProjection};
+pub use relation_size::{IndexSize, PageOwner, TableSize};
 pub use schema::*;
+pub use statistics::*;
@@ -0,0 +1,275 @@
+use std::sync::atomic::Ordering;
+
+use crate::buffer::{BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID};
+use crate::catalog::CatalogTable;
+use crate::common::TableReference;
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::{BPlusTreePage, TableHeap};
+use crate::BustubxResult;
+
+/// On-disk footprint of a table and its indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableSize {
+    pub table: TableReference,
+    pub heap_pages: usize,
+    pub live_tuples: usize,
+    // deleted tuples still taking space in the heap pages
+    pub dead_tuples: usize,
+    // sorted by index name
+    pub indexes: Vec<IndexSize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSize {
+    pub name: String,
+    // pages of every tree level, the root level first
+    pub level_pages: Vec<usize>,
+    // entries of the leaf pages
+    pub entries: usize,
+    // used fraction of the page capacity over all pages, 0 for an empty index
+    pub avg_fill: f64,
+    // pages the index would take bulk loaded from its entries with full pages
+    pub packed_pages: usize,
+}
+
+/// Relation a page of the database file belongs to, see
+/// [`crate::catalog::Catalog::page_owners`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageOwner {
+    pub table: TableReference,
+    // None for a heap page, the logical row id map of a table is named `ROW_ID_INDEX_NAME`
+    pub index: Option<String>,
+}
+
+impl std::fmt::Display for PageOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.index {
+            Some(index) => write!(f, "index {} of {}", index, self.table),
+            None => write!(f, "{}", self.table),
+        }
+    }
+}
+
+impl TableSize {
+    /// Walk the heap page chain reading only the page headers, and every index tree.
+    pub fn measure(table: TableReference, catalog_table: &CatalogTable) -> BustubxResult<Self> {
+        Self::measure_heap(
+            table,
+            &catalog_table.table,
+            catalog_table
+                .indexes
+                .iter()
+                .map(|(name, index)| (name.as_str(), index.as_ref())),
+        )
+    }
+
+    /// [`Self::measure`] of a heap and indexes held outside the catalog.
+    pub fn measure_heap<'a>(
+        table: TableReference,
+        heap: &TableHeap,
+        indexes: impl IntoIterator<Item = (&'a str, &'a BPlusTreeIndex)>,
+    ) -> BustubxResult<Self> {
+        let (heap_pages, live_tuples, dead_tuples) = heap.count_pages()?;
+        let mut indexes = indexes.into_iter().collect::<Vec<_>>();
+        indexes.sort_by_key(|(name, _)| *name);
+        let indexes = indexes
+            .into_iter()
+            .map(|(name, index)| IndexSize::measure(name, index))
+            .collect::<BustubxResult<Vec<_>>>()?;
+        Ok(Self {
+            table,
+            heap_pages,
+            live_tuples,
+            dead_tuples,
+            indexes,
+        })
+    }
+
+    pub fn heap_bytes(&self) -> usize {
+        self.heap_pages * BUSTUBX_PAGE_SIZE
+    }
+
+    /// Heap and indexes together
+    pub fn total_bytes(&self) -> usize {
+        self.heap_bytes() + self.indexes.iter().map(IndexSize::bytes).sum::<usize>()
+    }
+}
+
+impl IndexSize {
+    pub fn measure(name: &str, index: &BPlusTreeIndex) -> BustubxResult<Self> {
+        let mut level_pages = vec![];
+        let mut entries = 0;
+        let (mut used, mut capacity) = (0usize, 0usize);
+
+        let root_page_id = index.root_page_id.load(Ordering::SeqCst);
+        let mut level = if root_page_id == INVALID_PAGE_ID {
+            vec![]
+        } else {
+            vec![root_page_id]
+        };
+        while !level.is_empty() {
+            level_pages.push(level.len());
+            let mut next_level = vec![];
+            for page_id in level {
+                let (_, tree_page) = index
+                    .buffer_pool
+                    .fetch_tree_page(page_id, index.key_schema.clone())?;
+                match tree_page {
+                    BPlusTreePage::Internal(page) => {
+                        used += page.header.current_size as usize;
+                        capacity += page.header.max_size as usize;
+                        next_level.extend(page.values());
+                    }
+                    BPlusTreePage::Leaf(page) => {
+                        used += page.header.current_size as usize;
+                        capacity += page.header.max_size as usize;
+                        entries += page.header.current_size as usize;
+                    }
+                }
+            }
+            level = next_level;
+        }
+
+        let mut packed_pages = 0;
+        let mut level_size = entries.div_ceil(index.leaf_max_size as usize);
+        while level_size > 0 {
+            packed_pages += level_size;
+            level_size = match level_size {
+                1 => 0,
+                _ => level_size.div_ceil(index.internal_max_size as usize),
+            };
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            level_pages,
+            entries,
+            avg_fill: if capacity == 0 {
+                0.0
+            } else {
+                used as f64 / capacity as f64
+            },
+            packed_pages,
+        })
+    }
+
+    pub fn pages(&self) -> usize {
+        self.level_pages.iter().sum()
+    }
+
+    /// Fraction of the pages a REINDEX would free, 0 for an empty or packed index.
+    pub fn bloat(&self) -> f64 {
+        match self.pages() {
+            0 => 0.0,
+            pages => 1.0 - self.packed_pages.min(pages) as f64 / pages as f64,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.pages() * BUSTUBX_PAGE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::TableSize;
+    use crate::common::ScalarValue;
+    use crate::Database;
+
+    fn t1_size(db: &Database) -> TableSize {
+        db.relation_sizes()
+            .unwrap()
+            .into_iter()
+            .find(|size| size.table.schema() == Some("public") && size.table.table() == "t1")
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_relation_sizes() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+
+        let size = t1_size(&db);
+        assert_eq!(size.heap_pages, 1);
+        assert_eq!((size.live_tuples, size.dead_tuples), (0, 0));
+        assert_eq!(size.indexes[0].name, "idx_a");
+        assert_eq!(size.indexes[0].pages(), 0);
+        assert_eq!(size.indexes[0].avg_fill, 0.0);
+
+        let rows = (0..1000).collect::<Vec<_>>();
+        for chunk in rows.chunks(100) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({a}, 'padding padding padding padding padding {a}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        let size = t1_size(&db);
+        assert!(size.heap_pages > 10, "{size:?}");
+        assert_eq!((size.live_tuples, size.dead_tuples), (1000, 0));
+        let index = &size.indexes[0];
+        assert_eq!(index.entries, 1000);
+        assert_eq!(index.level_pages[0], 1);
+        assert!(index.level_pages.len() > 2, "{index:?}");
+        assert!(index.avg_fill > 0.0 && index.avg_fill <= 1.0);
+        assert_eq!(
+            size.total_bytes(),
+            (size.heap_pages + index.pages()) * crate::buffer::BUSTUBX_PAGE_SIZE
+        );
+
+        // deleted tuples keep their heap space
+        db.run("delete from t1 where a >= 100").unwrap();
+        let after_delete = t1_size(&db);
+        assert_eq!(after_delete.heap_pages, size.heap_pages);
+        assert_eq!(
+            (after_delete.live_tuples, after_delete.dead_tuples),
+            (100, 900)
+        );
+        assert_eq!(after_delete.indexes[0].entries, 100);
+        assert!(after_delete.indexes[0].pages() < index.pages());
+
+        let rows = db
+            .run(
+                "select relation_name, relation_kind, live_tuples, dead_tuples, size_bytes \
+                 from information_schema.relation_sizes where table_name = 't1'",
+            )
+            .unwrap();
+        let rows = rows.into_iter().map(|row| row.data).collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    "t1".to_string().into(),
+                    "table".to_string().into(),
+                    100u64.into(),
+                    900u64.into(),
+                    (after_delete.heap_bytes() as u64).into(),
+                ],
+                vec![
+                    "idx_a".to_string().into(),
+                    "index".to_string().into(),
+                    ScalarValue::UInt64(None),
+                    ScalarValue::UInt64(None),
+                    (after_delete.indexes[0].bytes() as u64).into(),
+                ],
+            ]
+        );
+
+        // joins against the tables listing
+        let rows = db
+            .run(
+                "select information_schema.relation_sizes.total_bytes from information_schema.tables \
+                 inner join information_schema.relation_sizes \
+                 on table_name = information_schema.relation_sizes.relation_name \
+                 where relation_kind = 'table' and table_name = 't1'",
+            )
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0].data,
+            vec![(after_delete.total_bytes() as u64).into()]
+        );
+    }
+}
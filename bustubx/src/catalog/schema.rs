@@ -15,6 +15,13 @@ pub static INSERT_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
         false,
     )]))
 });
+pub static EXPLAIN_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![Column::new(
+        "plan",
+        DataType::Varchar(None),
+        false,
+    )]))
+});
 pub static UPDATE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
     Arc::new(Schema::new(vec![Column::new(
         "update_rows",
@@ -22,6 +29,118 @@ pub static UPDATE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
         false,
     )]))
 });
+pub static DELETE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![Column::new(
+        "delete_rows",
+        DataType::Int32,
+        false,
+    )]))
+});
+pub static SHOW_TABLES_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![Column::new(
+        "table_name",
+        DataType::Varchar(None),
+        false,
+    )]))
+});
+pub static DESCRIBE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("column_name", DataType::Varchar(None), false),
+        Column::new("data_type", DataType::Varchar(None), false),
+        Column::new("nullable", DataType::Boolean, false),
+        Column::new("default", DataType::Varchar(None), true),
+        // indexes covering the column
+        Column::new("key", DataType::Varchar(None), true),
+    ]))
+});
+pub static SHOW_CREATE_TABLE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("table_name", DataType::Varchar(None), false),
+        Column::new("create_statement", DataType::Varchar(None), false),
+    ]))
+});
+pub static SHOW_STATS_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("name", DataType::Varchar(None), false),
+        Column::new("value", DataType::UInt64, false),
+    ]))
+});
+pub static SHOW_SETTING_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("name", DataType::Varchar(None), false),
+        Column::new("setting", DataType::Varchar(None), false),
+    ]))
+});
+pub static SHOW_ALL_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("name", DataType::Varchar(None), false),
+        Column::new("setting", DataType::Varchar(None), false),
+        Column::new("type", DataType::Varchar(None), false),
+        Column::new("scope", DataType::Varchar(None), false),
+        Column::new("description", DataType::Varchar(None), false),
+    ]))
+});
+pub static SHOW_TRANSACTIONS_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("txn_id", DataType::UInt64, false),
+        Column::new("state", DataType::Varchar(None), false),
+        Column::new("age_ms", DataType::UInt64, false),
+        Column::new("idle_ms", DataType::UInt64, false),
+        Column::new("rows_written", DataType::UInt64, false),
+        Column::new("horizon_blocker", DataType::Boolean, false),
+    ]))
+});
+pub static SHOW_INDEX_TREE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![Column::new(
+        "tree",
+        DataType::Varchar(None),
+        false,
+    )]))
+});
+pub static VACUUM_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("tables", DataType::UInt64, false),
+        Column::new("pages_reclaimed", DataType::UInt64, false),
+        Column::new("dead_tuples_removed", DataType::UInt64, false),
+    ]))
+});
+pub static ANALYZE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("tables", DataType::UInt64, false),
+        Column::new("rows_sampled", DataType::UInt64, false),
+    ]))
+});
+pub static REINDEX_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("indexes", DataType::UInt64, false),
+        Column::new("pages_before", DataType::UInt64, false),
+        Column::new("pages_after", DataType::UInt64, false),
+    ]))
+});
+pub static CLUSTER_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("rows", DataType::UInt64, false),
+        Column::new("pages_before", DataType::UInt64, false),
+        Column::new("pages_after", DataType::UInt64, false),
+    ]))
+});
+pub static CHECKPOINT_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    // lsn of the last page write, null without a replication log
+    Arc::new(Schema::new(vec![Column::new(
+        "lsn",
+        DataType::UInt64,
+        true,
+    )]))
+});
+pub static CHECK_TABLE_OUTPUT_SCHEMA_REF: LazyLock<SchemaRef> = LazyLock::new(|| {
+    // one row per finding, page_id is null for findings about no particular page
+    Arc::new(Schema::new(vec![
+        Column::new("severity", DataType::Varchar(None), false),
+        Column::new("object", DataType::Varchar(None), false),
+        Column::new("page_id", DataType::UInt32, true),
+        Column::new("description", DataType::Varchar(None), false),
+    ]))
+});
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Schema {
@@ -33,13 +152,32 @@ impl Schema {
         Self::new_with_check(columns.into_iter().map(Arc::new).collect())
     }
 
+    /// Like [`Schema::new`], but a duplicated column is an error instead of a panic.
+    pub fn try_new(columns: Vec<Column>) -> BustubxResult<Self> {
+        for (idx1, col1) in columns.iter().enumerate() {
+            for col2 in &columns[idx1 + 1..] {
+                let same_relation = match (&col1.relation, &col2.relation) {
+                    (Some(rel1), Some(rel2)) => rel1.resolved_eq(rel2),
+                    (None, None) => true,
+                    (Some(_), None) | (None, Some(_)) => false,
+                };
+                if same_relation && col1.name == col2.name {
+                    return Err(BustubxError::Plan(format!(
+                        "Duplicated column \"{}\"",
+                        col1.name
+                    )));
+                }
+            }
+        }
+        Ok(Self::new(columns))
+    }
+
     fn new_with_check(columns: Vec<ColumnRef>) -> Self {
         for (idx1, col1) in columns.iter().enumerate() {
-            for idx2 in idx1 + 1..columns.len() {
-                let col2 = &columns[idx2];
+            for col2 in &columns[idx1 + 1..] {
                 match (&col1.relation, &col2.relation) {
                     (Some(rel1), Some(rel2)) => {
-                        assert_ne!(rel1.resolved_eq(rel2) && col1.name == col2.name, true)
+                        assert!(!(rel1.resolved_eq(rel2) && col1.name == col2.name))
                     }
                     (None, None) => assert_ne!(col1.name, col2.name),
                     (Some(_), None) | (None, Some(_)) => {}
@@ -0,0 +1,728 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::catalog::SchemaRef;
+use crate::common::ScalarValue;
+use crate::expression::{BinaryExpr, BinaryOp, Expr};
+use crate::storage::TableHeap;
+use crate::BustubxResult;
+
+pub const DEFAULT_HISTOGRAM_BUCKETS: usize = 32;
+pub const DEFAULT_MOST_COMMON_VALUES: usize = 16;
+// Default of the `analyze_sample_size` setting, bounds the pages read and the rows kept in
+// memory while analyzing a table
+pub const ANALYZE_SAMPLE_SIZE: usize = 30000;
+// Used when a predicate can't be estimated from statistics
+pub const DEFAULT_SELECTIVITY: f64 = 0.33;
+// Analyze logs a warning for an index with a larger bloat estimate
+pub const INDEX_BLOAT_WARNING_THRESHOLD: f64 = 0.5;
+// Statistics are stale once more rows than this fraction of the analyzed row count were
+// written since, the planner then takes the distinct values from the sketches
+pub const STALE_STATISTICS_FRACTION: f64 = 0.2;
+// 4096 registers, a standard error of about 1.6%
+const SKETCH_PRECISION: u32 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    pub row_count: usize,
+    pub sample_size: usize,
+    pub columns: HashMap<String, ColumnStatistics>,
+    // bloat estimate of every index by name, see `IndexSize::bloat`
+    pub index_bloat: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    // fraction of rows which are null
+    pub null_fraction: f64,
+    // estimated number of distinct non-null values
+    pub ndv: usize,
+    pub min: Option<ScalarValue>,
+    pub max: Option<ScalarValue>,
+    // most common values with the fraction of rows holding them, most common first
+    pub most_common_values: Vec<(ScalarValue, f64)>,
+    // built from the values not in `most_common_values`
+    pub histogram: Histogram,
+}
+
+/// Equi-height histogram, every bucket holds the same number of values.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Histogram {
+    // bucket i covers [bounds[i], bounds[i + 1]]
+    pub bounds: Vec<ScalarValue>,
+}
+
+impl TableStatistics {
+    /// Statistics of a two stage sample of the live rows: up to `sample_size` pages picked
+    /// uniformly from the page chain, then up to `sample_size` live rows picked uniformly
+    /// from those pages. Deleted tuples are left out, the row count is the live rows per
+    /// sampled page scaled to every page of the chain.
+    pub fn analyze(
+        heap: &TableHeap,
+        bucket_count: usize,
+        sample_size: usize,
+    ) -> BustubxResult<Self> {
+        let mut sampler = ReservoirSampler::new(sample_size);
+        let page_ids = heap.page_ids()?;
+        let sampled_pages = sampler.choose(&page_ids, sample_size);
+        for page_id in sampled_pages.iter() {
+            for (_, meta, tuple) in heap.page_tuples(*page_id)? {
+                if !meta.is_deleted {
+                    sampler.add(tuple.data);
+                }
+            }
+        }
+        let row_count = if sampled_pages.len() == page_ids.len() {
+            sampler.seen
+        } else {
+            (sampler.seen as f64 * page_ids.len() as f64 / sampled_pages.len() as f64).round()
+                as usize
+        };
+        Ok(Self::from_sample(
+            &heap.schema,
+            row_count,
+            sampler.sample,
+            bucket_count,
+        ))
+    }
+
+    fn from_sample(
+        schema: &SchemaRef,
+        row_count: usize,
+        sample: Vec<Vec<ScalarValue>>,
+        bucket_count: usize,
+    ) -> Self {
+        let columns = schema
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(idx, col)| {
+                let values = sample.iter().map(|row| row[idx].clone()).collect();
+                (
+                    col.name.clone(),
+                    ColumnStatistics::from_sample(values, row_count, bucket_count),
+                )
+            })
+            .collect();
+        Self {
+            row_count,
+            sample_size: sample.len(),
+            columns,
+            index_bloat: HashMap::new(),
+        }
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStatistics> {
+        self.columns.get(name)
+    }
+
+    /// Estimated fraction of rows matching the predicate.
+    pub fn selectivity(&self, predicate: &Expr) -> f64 {
+        let Expr::Binary(BinaryExpr { left, op, right }) = predicate else {
+            return DEFAULT_SELECTIVITY;
+        };
+        match op {
+            BinaryOp::And => self.selectivity(left) * self.selectivity(right),
+            BinaryOp::Or => {
+                let l = self.selectivity(left);
+                let r = self.selectivity(right);
+                l + r - l * r
+            }
+            _ => {
+                let (column, op, value) = match (left.as_ref(), right.as_ref()) {
+                    (Expr::Column(column), Expr::Literal(literal)) => (column, *op, &literal.value),
+                    (Expr::Literal(literal), Expr::Column(column)) => {
+                        let Some(op) = op.flip_comparison() else {
+                            return DEFAULT_SELECTIVITY;
+                        };
+                        (column, op, &literal.value)
+                    }
+                    _ => return DEFAULT_SELECTIVITY,
+                };
+                self.column(&column.name)
+                    .and_then(|stats| stats.selectivity(op, value))
+                    .unwrap_or(DEFAULT_SELECTIVITY)
+            }
+        }
+    }
+}
+
+impl ColumnStatistics {
+    fn from_sample(values: Vec<ScalarValue>, row_count: usize, bucket_count: usize) -> Self {
+        let sample_size = values.len();
+        let mut non_null: Vec<ScalarValue> = values.into_iter().filter(|v| !v.is_null()).collect();
+        let null_fraction = if sample_size == 0 {
+            0.0
+        } else {
+            (sample_size - non_null.len()) as f64 / sample_size as f64
+        };
+        non_null.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        let mut counts: Vec<(ScalarValue, usize)> = Vec::new();
+        for value in non_null.iter() {
+            match counts.last_mut() {
+                Some((last, count)) if last == value => *count += 1,
+                _ => counts.push((value.clone(), 1)),
+            }
+        }
+        let ndv = estimate_ndv(&counts, sample_size, row_count);
+
+        // values more common than the average are worth remembering exactly
+        let mut candidates: Vec<&(ScalarValue, usize)> = counts
+            .iter()
+            .filter(|(_, count)| *count > 1 && *count * counts.len() > non_null.len())
+            .collect();
+        candidates.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        candidates.truncate(DEFAULT_MOST_COMMON_VALUES);
+        let most_common_values: Vec<(ScalarValue, f64)> = candidates
+            .into_iter()
+            .map(|(value, count)| (value.clone(), *count as f64 / sample_size as f64))
+            .collect();
+
+        let rest: Vec<ScalarValue> = non_null
+            .iter()
+            .filter(|v| !most_common_values.iter().any(|(mcv, _)| mcv == *v))
+            .cloned()
+            .collect();
+
+        Self {
+            null_fraction,
+            ndv,
+            min: non_null.first().cloned(),
+            max: non_null.last().cloned(),
+            most_common_values,
+            histogram: Histogram::new(&rest, bucket_count),
+        }
+    }
+
+    fn mcv_fraction(&self) -> f64 {
+        self.most_common_values.iter().map(|(_, f)| f).sum()
+    }
+
+    /// Estimated fraction of rows satisfying `column <op> value`, None if `op` is not a comparison.
+    pub fn selectivity(&self, op: BinaryOp, value: &ScalarValue) -> Option<f64> {
+        if value.is_null() {
+            // comparisons with null never match
+            return Some(0.0);
+        }
+        let value = match &self.min {
+            Some(min) => value.cast_to(&min.data_type()).ok()?,
+            None => return Some(0.0),
+        };
+        let non_null = 1.0 - self.null_fraction;
+        let selectivity = match op {
+            BinaryOp::Eq => self.eq_selectivity(&value),
+            BinaryOp::NotEq => non_null - self.eq_selectivity(&value),
+            BinaryOp::Lt => self.lt_selectivity(&value),
+            BinaryOp::LtEq => self.lt_selectivity(&value) + self.eq_selectivity(&value),
+            BinaryOp::Gt => non_null - self.lt_selectivity(&value) - self.eq_selectivity(&value),
+            BinaryOp::GtEq => non_null - self.lt_selectivity(&value),
+            _ => return None,
+        };
+        Some(selectivity.clamp(0.0, 1.0))
+    }
+
+    fn eq_selectivity(&self, value: &ScalarValue) -> f64 {
+        if let Some((_, fraction)) = self.most_common_values.iter().find(|(v, _)| v == value) {
+            return *fraction;
+        }
+        if self.out_of_range(value) {
+            return 0.0;
+        }
+        let rest_ndv = self
+            .ndv
+            .saturating_sub(self.most_common_values.len())
+            .max(1);
+        (1.0 - self.null_fraction - self.mcv_fraction()).max(0.0) / rest_ndv as f64
+    }
+
+    fn lt_selectivity(&self, value: &ScalarValue) -> f64 {
+        let mcv: f64 = self
+            .most_common_values
+            .iter()
+            .filter(|(v, _)| v < value)
+            .map(|(_, f)| f)
+            .sum();
+        let rest = (1.0 - self.null_fraction - self.mcv_fraction()).max(0.0);
+        mcv + rest * self.histogram.fraction_below(value)
+    }
+
+    fn out_of_range(&self, value: &ScalarValue) -> bool {
+        matches!((&self.min, &self.max), (Some(min), Some(max)) if value < min || value > max)
+    }
+
+    /// Range estimate assuming values are spread uniformly between min and max,
+    /// the fallback when no histogram is available.
+    pub fn uniform_selectivity(&self, op: BinaryOp, value: &ScalarValue) -> Option<f64> {
+        let (min, max) = (self.min.as_ref()?, self.max.as_ref()?);
+        let non_null = 1.0 - self.null_fraction;
+        let eq = non_null / self.ndv.max(1) as f64;
+        let below = non_null * interpolate(min, max, &value.cast_to(&min.data_type()).ok()?)?;
+        let selectivity = match op {
+            BinaryOp::Eq => eq,
+            BinaryOp::NotEq => non_null - eq,
+            BinaryOp::Lt => below,
+            BinaryOp::LtEq => below + eq,
+            BinaryOp::Gt => non_null - below - eq,
+            BinaryOp::GtEq => non_null - below,
+            _ => return None,
+        };
+        Some(selectivity.clamp(0.0, 1.0))
+    }
+}
+
+impl Histogram {
+    pub fn new(sorted_values: &[ScalarValue], bucket_count: usize) -> Self {
+        if sorted_values.is_empty() || bucket_count == 0 {
+            return Self::default();
+        }
+        let bucket_count = bucket_count.min(sorted_values.len());
+        let last = sorted_values.len() - 1;
+        let bounds = (0..=bucket_count)
+            .map(|i| sorted_values[i * last / bucket_count].clone())
+            .collect();
+        Self { bounds }
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.bounds.len().saturating_sub(1)
+    }
+
+    /// Fraction of the histogram values less than `value`.
+    pub fn fraction_below(&self, value: &ScalarValue) -> f64 {
+        let buckets = self.bucket_count();
+        if buckets == 0 {
+            return match self.bounds.first() {
+                Some(bound) if bound < value => 1.0,
+                _ => 0.0,
+            };
+        }
+        if value <= &self.bounds[0] {
+            return 0.0;
+        }
+        if value > &self.bounds[buckets] {
+            return 1.0;
+        }
+        let bucket = self.bounds[1..]
+            .partition_point(|bound| bound < value)
+            .min(buckets - 1);
+        let within =
+            interpolate(&self.bounds[bucket], &self.bounds[bucket + 1], value).unwrap_or(0.5);
+        (bucket as f64 + within) / buckets as f64
+    }
+}
+
+/// HyperLogLog sketch of the distinct non-null values of a column. Values are only ever
+/// added, a sketch kept up by DML counts deleted values until ANALYZE builds it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistinctSketch {
+    registers: Vec<u8>,
+}
+
+impl Default for DistinctSketch {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; 1 << SKETCH_PRECISION],
+        }
+    }
+}
+
+impl DistinctSketch {
+    pub fn add(&mut self, value: &ScalarValue) {
+        if value.is_null() {
+            return;
+        }
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - SKETCH_PRECISION)) as usize;
+        // the set bit bounds the rank when the remaining bits are all zero
+        let rest = (hash << SKETCH_PRECISION) | (1 << (SKETCH_PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    /// Estimated number of distinct values added, linear counting while most registers
+    /// are still empty.
+    pub fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-(*rank as i32)))
+            .sum();
+        let raw = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+        let estimate = if raw <= 2.5 * m && empty > 0 {
+            m * (m / empty as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as usize
+    }
+}
+
+// Position of `value` between `low` and `high` in [0, 1], None for non-numeric values
+fn interpolate(low: &ScalarValue, high: &ScalarValue, value: &ScalarValue) -> Option<f64> {
+    let (low, high, value) = (as_f64(low)?, as_f64(high)?, as_f64(value)?);
+    if high <= low {
+        return Some(if value > low { 1.0 } else { 0.0 });
+    }
+    Some(((value - low) / (high - low)).clamp(0.0, 1.0))
+}
+
+fn as_f64(value: &ScalarValue) -> Option<f64> {
+    match value {
+        ScalarValue::Int8(v) => v.map(|v| v as f64),
+        ScalarValue::Int16(v) => v.map(|v| v as f64),
+        ScalarValue::Int32(v) => v.map(|v| v as f64),
+        ScalarValue::Int64(v) => v.map(|v| v as f64),
+        ScalarValue::UInt8(v) => v.map(|v| v as f64),
+        ScalarValue::UInt16(v) => v.map(|v| v as f64),
+        ScalarValue::UInt32(v) => v.map(|v| v as f64),
+        ScalarValue::UInt64(v) => v.map(|v| v as f64),
+        ScalarValue::Float32(v) => v.map(|v| v as f64),
+        ScalarValue::Float64(v) => *v,
+        ScalarValue::Boolean(_) | ScalarValue::Varchar(_) | ScalarValue::Bytea(_) => None,
+    }
+}
+
+// Duj1 estimator (Haas and Stokes), exact when the sample covers the whole table
+fn estimate_ndv(counts: &[(ScalarValue, usize)], sample_size: usize, row_count: usize) -> usize {
+    let distinct = counts.len();
+    if sample_size == 0 || sample_size >= row_count {
+        return distinct;
+    }
+    let singletons = counts.iter().filter(|(_, count)| *count == 1).count() as f64;
+    let (n, total) = (sample_size as f64, row_count as f64);
+    let estimate = n * distinct as f64 / (n - singletons + singletons * n / total);
+    (estimate.round() as usize).clamp(distinct, row_count)
+}
+
+/// Keeps a uniform random sample of at most `capacity` rows (algorithm R).
+struct ReservoirSampler {
+    capacity: usize,
+    seen: usize,
+    sample: Vec<Vec<ScalarValue>>,
+    // xorshift state, fixed seed keeps ANALYZE deterministic
+    state: u64,
+}
+
+impl ReservoirSampler {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: 0,
+            sample: Vec::with_capacity(capacity.min(1024)),
+            state: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    fn add(&mut self, row: Vec<ScalarValue>) {
+        self.seen += 1;
+        if self.sample.len() < self.capacity {
+            self.sample.push(row);
+            return;
+        }
+        let idx = (self.next_random() % self.seen as u64) as usize;
+        if idx < self.capacity {
+            self.sample[idx] = row;
+        }
+    }
+
+    /// `count` of the `items` picked uniformly, in their order (selection sampling).
+    fn choose<T: Copy>(&mut self, items: &[T], count: usize) -> Vec<T> {
+        let mut chosen = Vec::with_capacity(count.min(items.len()));
+        for (idx, item) in items.iter().enumerate() {
+            let needed = (count - chosen.len()) as u64;
+            let left = (items.len() - idx) as u64;
+            if self.next_random() % left < needed {
+                chosen.push(*item);
+            }
+        }
+        chosen
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::statistics::{
+        DistinctSketch, ReservoirSampler, ANALYZE_SAMPLE_SIZE, DEFAULT_HISTOGRAM_BUCKETS,
+    };
+    use crate::common::{ScalarValue, TableReference};
+    use crate::config::ExecutionOptions;
+    use crate::execution::physical_plan::PhysicalPlan;
+    use crate::expression::BinaryOp;
+    use crate::planner::PhysicalPlanner;
+    use crate::Database;
+
+    fn insert_values(db: &mut Database, table: &str, values: impl Iterator<Item = i64>) {
+        let values = values.map(|v| format!("({v})")).collect::<Vec<_>>();
+        for chunk in values.chunks(200) {
+            db.run(&format!("insert into {table} values {}", chunk.join(", ")))
+                .unwrap();
+        }
+    }
+
+    fn uses_index_scan(db: &mut Database, sql: &str) -> bool {
+        let logical_plan = db.create_logical_plan(sql).unwrap();
+        let physical_plan = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(logical_plan)
+        .unwrap();
+        let PhysicalPlan::Project(project) = &physical_plan else {
+            panic!("unexpected plan {physical_plan:?}");
+        };
+        let PhysicalPlan::Filter(filter) = project.input.as_ref() else {
+            panic!("unexpected plan {:?}", project.input);
+        };
+        matches!(filter.input.as_ref(), PhysicalPlan::IndexScan(_))
+    }
+
+    #[test]
+    pub fn test_histogram_estimates_skewed_data() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint, b bigint)").unwrap();
+        // quadratic growth packs most rows near the low end
+        let rows = (0..1000i64).map(|i| format!("({}, {})", i * i, i % 10));
+        for chunk in rows.collect::<Vec<_>>().chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        db.run("insert into t1 values (null, 1)").unwrap();
+
+        let table_ref = TableReference::bare("t1");
+        let stats = db
+            .catalog
+            .analyze_table(&table_ref, DEFAULT_HISTOGRAM_BUCKETS, ANALYZE_SAMPLE_SIZE)
+            .unwrap();
+        assert_eq!(stats.row_count, 1001);
+        let a = stats.column("a").unwrap();
+        assert_eq!(a.ndv, 1000);
+        assert_eq!(a.min, Some(ScalarValue::Int64(Some(0))));
+        assert_eq!(a.max, Some(ScalarValue::Int64(Some(998001))));
+        assert_eq!(a.histogram.bucket_count(), DEFAULT_HISTOGRAM_BUCKETS);
+        assert!(a.most_common_values.is_empty());
+
+        // true selectivity of a < 10000 is 100 / 1001
+        let value = ScalarValue::Int64(Some(10000));
+        let actual = 100.0 / 1001.0;
+        let estimated = a.selectivity(BinaryOp::Lt, &value).unwrap();
+        let uniform = a.uniform_selectivity(BinaryOp::Lt, &value).unwrap();
+        assert!((estimated - actual).abs() < 0.02, "{estimated}");
+        assert!((uniform - actual).abs() > 0.08, "{uniform}");
+
+        // true selectivity of b = 1 is 101 / 1001
+        let b = stats.column("b").unwrap();
+        assert_eq!(b.ndv, 10);
+        let value = ScalarValue::Int64(Some(1));
+        let estimated = b.selectivity(BinaryOp::Eq, &value).unwrap();
+        assert!((estimated - 101.0 / 1001.0).abs() < 1e-9, "{estimated}");
+        assert_eq!(
+            b.selectivity(BinaryOp::Eq, &ScalarValue::Int64(Some(42))),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    pub fn test_most_common_values_estimates() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint)").unwrap();
+        insert_values(&mut db, "t1", (0..950).map(|i| i % 10));
+        insert_values(&mut db, "t1", (1..=50).map(|i| i * 1000));
+
+        let stats = db
+            .catalog
+            .analyze_table(
+                &TableReference::bare("t1"),
+                DEFAULT_HISTOGRAM_BUCKETS,
+                ANALYZE_SAMPLE_SIZE,
+            )
+            .unwrap();
+        let a = stats.column("a").unwrap();
+        assert_eq!(a.ndv, 60);
+        assert_eq!(a.most_common_values.len(), 10);
+
+        let value = ScalarValue::Int64(Some(3));
+        let estimated = a.selectivity(BinaryOp::Eq, &value).unwrap();
+        let uniform = a.uniform_selectivity(BinaryOp::Eq, &value).unwrap();
+        assert!((estimated - 0.095).abs() < 1e-9, "{estimated}");
+        assert!((uniform - 0.095).abs() > 0.07, "{uniform}");
+
+        let value = ScalarValue::Int64(Some(10));
+        let estimated = a.selectivity(BinaryOp::Lt, &value).unwrap();
+        let uniform = a.uniform_selectivity(BinaryOp::Lt, &value).unwrap();
+        assert!((estimated - 0.95).abs() < 0.01, "{estimated}");
+        assert!(uniform < 0.01, "{uniform}");
+    }
+
+    #[test]
+    pub fn test_statistics_flip_plan_choice() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint)").unwrap();
+        db.run("create index idx1 on t1 (a)").unwrap();
+        insert_values(&mut db, "t1", (0..950).map(|i| i % 10));
+        insert_values(&mut db, "t1", (1..=50).map(|i| i * 1000));
+
+        // without statistics the index is always preferred
+        assert!(uses_index_scan(&mut db, "select a from t1 where a < 10"));
+
+        db.catalog
+            .analyze_table(
+                &TableReference::bare("t1"),
+                DEFAULT_HISTOGRAM_BUCKETS,
+                ANALYZE_SAMPLE_SIZE,
+            )
+            .unwrap();
+        // 95% of the rows match, reading the heap directly is cheaper
+        assert!(!uses_index_scan(&mut db, "select a from t1 where a < 10"));
+        assert!(uses_index_scan(&mut db, "select a from t1 where a > 40000"));
+        assert_eq!(db.run("select a from t1 where a < 10").unwrap().len(), 950);
+    }
+
+    #[test]
+    pub fn test_analyze_samples_live_rows_of_churned_heap() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint, b varchar(100))")
+            .unwrap();
+        let rows = (0..6000i64).map(|i| format!("({i}, 'padding padding padding {i}')"));
+        for chunk in rows.collect::<Vec<_>>().chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        // the live rows are all on the last pages
+        db.run("delete from t1 where a < 4800").unwrap();
+        let table_ref = TableReference::bare("t1");
+        let pages = db
+            .catalog
+            .table_heap(&table_ref)
+            .unwrap()
+            .page_ids()
+            .unwrap();
+        assert!(pages.len() > 80, "{} pages", pages.len());
+
+        let stats = db
+            .catalog
+            .analyze_table(&table_ref, DEFAULT_HISTOGRAM_BUCKETS, 40)
+            .unwrap();
+        assert_eq!(stats.sample_size, 40);
+        let error = (stats.row_count as f64 - 1200.0).abs() / 1200.0;
+        assert!(error < 0.3, "estimated {} rows", stats.row_count);
+        // the histogram only holds live values
+        let a = stats.column("a").unwrap();
+        assert!(a.min >= Some(ScalarValue::Int64(Some(4800))), "{:?}", a.min);
+        assert!(a
+            .histogram
+            .bounds
+            .iter()
+            .all(|bound| bound >= &ScalarValue::Int64(Some(4800))));
+        let below = a
+            .selectivity(BinaryOp::Lt, &ScalarValue::Int64(Some(4800)))
+            .unwrap();
+        assert_eq!(below, 0.0);
+        let upper_half = a
+            .selectivity(BinaryOp::GtEq, &ScalarValue::Int64(Some(5400)))
+            .unwrap();
+        assert!((upper_half - 0.5).abs() < 0.15, "{upper_half}");
+
+        // every page fits the sample, the count is exact
+        let stats = db
+            .catalog
+            .analyze_table(&table_ref, DEFAULT_HISTOGRAM_BUCKETS, ANALYZE_SAMPLE_SIZE)
+            .unwrap();
+        assert_eq!((stats.row_count, stats.sample_size), (1200, 1200));
+
+        db.run("set analyze_sample_size = 50").unwrap();
+        let summary = db.run("analyze t1").unwrap();
+        assert_eq!(summary[0].data[1], ScalarValue::UInt64(Some(50)));
+    }
+
+    #[test]
+    pub fn test_distinct_sketches_follow_inserts() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint, b bigint)").unwrap();
+        db.run("create index t1_a on t1 (a)").unwrap();
+        db.run("set distinct_sketches = true").unwrap();
+        let rows = |range: std::ops::Range<i64>| range.map(|i| format!("({i}, {})", i % 10));
+        for chunk in rows(0..1000).collect::<Vec<_>>().chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        db.run("analyze t1").unwrap();
+        let table_ref = TableReference::bare("t1");
+        let modifications = db
+            .catalog
+            .catalog_table(&table_ref)
+            .unwrap()
+            .modifications
+            .clone();
+        assert!(modifications.has_sketch("a") && !modifications.has_sketch("b"));
+
+        // ten times the rows since ANALYZE, every one with a new value of a
+        for chunk in rows(1000..10000).collect::<Vec<_>>().chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        let stats = db.catalog.table_statistics(&table_ref).unwrap();
+        assert_eq!(stats.row_count, 10000);
+        let a = stats.column("a").unwrap();
+        let error = (a.ndv as f64 - 10000.0).abs() / 10000.0;
+        assert!(error < 0.05, "estimated {} distinct values", a.ndv);
+        assert_eq!(stats.column("b").unwrap().ndv, 10);
+
+        // deleted values still count until the next ANALYZE, up to the rows left
+        db.run("delete from t1 where a < 500").unwrap();
+        let stats = db.catalog.table_statistics(&table_ref).unwrap();
+        assert_eq!(stats.row_count, 9500);
+        assert_eq!(stats.column("a").unwrap().ndv, 9500);
+
+        db.run("analyze t1").unwrap();
+        let stats = db.catalog.table_statistics(&table_ref).unwrap();
+        assert_eq!(
+            (stats.row_count, stats.column("a").unwrap().ndv),
+            (9500, 9500)
+        );
+        let mut sketch = DistinctSketch::default();
+        (500..10000i64).for_each(|i| sketch.add(&i.into()));
+        let error = (sketch.estimate() as f64 - 9500.0).abs() / 9500.0;
+        assert!(
+            error < 0.05,
+            "estimated {} distinct values",
+            sketch.estimate()
+        );
+    }
+
+    #[test]
+    pub fn test_reservoir_sampler_bounded() {
+        let mut sampler = ReservoirSampler::new(100);
+        for i in 0..10000i64 {
+            sampler.add(vec![i.into()]);
+        }
+        assert_eq!(sampler.seen, 10000);
+        assert_eq!(sampler.sample.len(), 100);
+        // the sample is spread over the whole input, not only its head
+        assert!(sampler
+            .sample
+            .iter()
+            .any(|row| row[0] > ScalarValue::Int64(Some(5000))));
+
+        let chosen = sampler.choose(&(0..1000).collect::<Vec<_>>(), 100);
+        assert_eq!(chosen.len(), 100);
+        assert!(chosen.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(chosen.iter().any(|page| *page >= 900));
+        assert_eq!(sampler.choose(&[1, 2, 3], 10), vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,50 @@
+//! Allocator of the test binary counting the allocations of every thread, so a test can
+//! tell how many allocations a piece of code makes while other tests run concurrently.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use crate::buffer::BUSTUBX_PAGE_SIZE;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+    static PAGE_ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_alloc(size: usize) {
+    let _ = ALLOCS.try_with(|allocs| allocs.set(allocs.get() + 1));
+    if size >= BUSTUBX_PAGE_SIZE {
+        let _ = PAGE_ALLOCS.try_with(|allocs| allocs.set(allocs.get() + 1));
+    }
+}
+
+/// Allocations and reallocations made by the current thread so far.
+pub fn allocations() -> usize {
+    ALLOCS.with(|allocs| allocs.get())
+}
+
+/// Allocations and reallocations of at least a page made by the current thread so far.
+pub fn page_allocations() -> usize {
+    PAGE_ALLOCS.with(|allocs| allocs.get())
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_alloc(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_alloc(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
@@ -0,0 +1,106 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time of a [`crate::Database`]. `now()`, ttl expiration and
+/// transaction start times read it, never the system time directly.
+pub trait Clock: Send + Sync + Debug {
+    /// Seconds since the unix epoch
+    fn now(&self) -> i64;
+}
+
+pub type ClockRef = Arc<dyn Clock>;
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64)
+    }
+}
+
+/// Clock that only moves when told to.
+///
+/// ```ignore
+/// let clock = Arc::new(MockClock::new(1000));
+/// let mut db = Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone())?;
+/// clock.advance(60);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicI64,
+}
+
+impl MockClock {
+    pub fn new(now: i64) -> Self {
+        Self {
+            now: AtomicI64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, secs: i64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{MockClock, ScalarValue};
+    use crate::{Database, DatabaseOptions};
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_mock_clock_drives_database_time() {
+        // keep a handle to the clock to move the database time
+        let clock = Arc::new(MockClock::new(1000));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        db.run("create table t1 (a int, created bigint default now())")
+            .unwrap();
+        db.run("create table t2 (a int, expires_at bigint) with (ttl_column = 'expires_at')")
+            .unwrap();
+        db.set_hide_expired_rows(true);
+
+        db.run("insert into t1 (a) values (1), (2)").unwrap();
+        clock.advance(60);
+        db.run("insert into t1 (a) values (3)").unwrap();
+        let created = db
+            .run("select created from t1")
+            .unwrap()
+            .into_iter()
+            .map(|tuple| tuple.data[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            created,
+            vec![
+                ScalarValue::Int64(Some(1000)),
+                ScalarValue::Int64(Some(1000)),
+                ScalarValue::Int64(Some(1060)),
+            ]
+        );
+
+        db.run("insert into t2 values (1, 1100), (2, 1200)")
+            .unwrap();
+        assert_eq!(db.run("select a from t2").unwrap().len(), 2);
+        clock.set(1150);
+        assert_eq!(db.run("select a from t2").unwrap().len(), 1);
+        assert_eq!(db.expire_rows().unwrap()[0].1, 1);
+        clock.set(1201);
+        assert_eq!(db.expire_rows().unwrap()[0].1, 1);
+        assert!(db.run("select a from t2").unwrap().is_empty());
+    }
+}
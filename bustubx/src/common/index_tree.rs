@@ -0,0 +1,130 @@
+//! Text rendering of a B+ tree, level by level, for `SHOW INDEX TREE` and the debug HTTP
+//! endpoint.
+
+use crate::buffer::{AccessType, PageId};
+use crate::common::util::new_table;
+use crate::storage::codec::BPlusTreePageCodec;
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::{BPlusTreePage, Tuple};
+use crate::BustubxResult;
+use comfy_table::Cell;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+
+const ELLIPSIS: &str = "...";
+// pages printed per level by `SHOW INDEX TREE` and the debug HTTP endpoint
+pub(crate) const MAX_PAGES_PER_LEVEL: usize = 64;
+
+fn format_tuple_values(tuple: &Tuple) -> String {
+    tuple
+        .data
+        .iter()
+        .map(|v| format!("{v}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Render the index level by level, printing at most `max_depth` levels and `max_pages_per_level`
+/// pages of each level. Truncated parts are marked with "...".
+pub(crate) fn pretty_format_index_tree_with_limits(
+    index: &BPlusTreeIndex,
+    max_depth: usize,
+    max_pages_per_level: usize,
+) -> BustubxResult<String> {
+    let mut display = String::new();
+
+    if index.is_empty() {
+        display.push_str("Empty tree.");
+        return Ok(display);
+    }
+    // level-order traversal
+    let mut curr_queue: VecDeque<PageId> = VecDeque::new();
+    curr_queue.push_back(index.root_page_id.load(Ordering::SeqCst));
+
+    let mut level_index = 1;
+    loop {
+        if curr_queue.is_empty() {
+            return Ok(display);
+        }
+        if level_index > max_depth {
+            display.push_str(&format!("{ELLIPSIS}\n"));
+            return Ok(display);
+        }
+        let mut next_queue = VecDeque::new();
+
+        // print current level
+        display.push_str(&format!("B+ Tree Level No.{}:\n", level_index));
+
+        let mut level_header = vec![];
+        let mut level_row = vec![];
+
+        let truncated = curr_queue.len() > max_pages_per_level;
+        curr_queue.truncate(max_pages_per_level);
+        while let Some(page_id) = curr_queue.pop_front() {
+            // Use the sequential hint so that dumping a big index doesn't evict the working set
+            let page = index
+                .buffer_pool
+                .fetch_page_with_access_type(page_id, AccessType::Sequential)?;
+            let (curr_page, _) =
+                BPlusTreePageCodec::decode(page.read().unwrap().data(), index.key_schema.clone())?;
+            drop(page);
+
+            match curr_page {
+                BPlusTreePage::Internal(internal_page) => {
+                    let page_table = new_table(
+                        internal_page
+                            .array
+                            .iter()
+                            .map(|(tuple, _)| Cell::new(format_tuple_values(tuple)))
+                            .collect(),
+                        vec![internal_page
+                            .array
+                            .iter()
+                            .map(|(_, page_id)| Cell::new(page_id))
+                            .collect()],
+                    );
+
+                    level_header.push(Cell::new(format!(
+                        "page_id={}, size: {}/{}",
+                        page_id, internal_page.header.current_size, internal_page.header.max_size
+                    )));
+                    level_row.push(Cell::new(page_table));
+
+                    next_queue.extend(internal_page.values());
+                }
+                BPlusTreePage::Leaf(leaf_page) => {
+                    let page_table = new_table(
+                        leaf_page
+                            .array
+                            .iter()
+                            .map(|(tuple, _)| Cell::new(format_tuple_values(tuple)))
+                            .collect(),
+                        vec![leaf_page
+                            .array
+                            .iter()
+                            .map(|(_, rid)| Cell::new(format!("{}-{}", rid.page_id, rid.slot_num)))
+                            .collect()],
+                    );
+
+                    level_header.push(Cell::new(format!(
+                        "page_id={}, size: {}/{}, next_page_id={}",
+                        page_id,
+                        leaf_page.header.current_size,
+                        leaf_page.header.max_size,
+                        leaf_page.header.next_page_id
+                    )));
+                    level_row.push(Cell::new(page_table));
+                }
+            }
+        }
+        if truncated {
+            level_header.push(Cell::new(ELLIPSIS));
+            level_row.push(Cell::new(ELLIPSIS));
+        }
+        let level_table = new_table(level_header, vec![level_row]);
+        display.push_str(&format!("{level_table}\n"));
+
+        level_index += 1;
+        curr_queue = next_queue;
+    }
+}
@@ -1,8 +1,21 @@
+#[cfg(test)]
+pub(crate) mod alloc_count;
 mod bitmap;
+mod clock;
+pub(crate) mod index_tree;
+pub mod numeric;
+#[cfg(test)]
+pub(crate) mod page_leaks;
+#[cfg(test)]
+pub(crate) mod page_trace;
 mod scalar;
 mod table_ref;
+#[cfg(test)]
+pub(crate) mod test_util;
 pub mod util;
+pub mod value_ord;
 
 pub use bitmap::DynamicBitmap;
+pub use clock::{Clock, ClockRef, MockClock, SystemClock};
 pub use scalar::ScalarValue;
 pub use table_ref::TableReference;
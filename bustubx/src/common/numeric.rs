@@ -0,0 +1,352 @@
+//! Conversions between the numeric types, shared by planning, which derives the type of an
+//! expression from [`coerce_types`], and by evaluation, which converts and compares the
+//! values with [`cast_numeric`] and [`compare`].
+
+use std::cmp::Ordering;
+
+use crate::catalog::DataType;
+use crate::common::{value_ord, ScalarValue};
+use crate::{BustubxError, BustubxResult};
+
+pub fn is_numeric(data_type: &DataType) -> bool {
+    is_integer(data_type) || matches!(data_type, DataType::Float32 | DataType::Float64)
+}
+
+pub fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+    )
+}
+
+/// Type both operands of a comparison or an arithmetic operator are converted to.
+pub fn coerce_types(l: &DataType, r: &DataType) -> BustubxResult<DataType> {
+    use DataType::*;
+    if l == r {
+        return Ok(*l);
+    }
+    if !is_numeric(l) || !is_numeric(r) {
+        return Err(BustubxError::Internal(format!(
+            "Cannot coerce {} and {} for comparison",
+            l, r
+        )));
+    }
+    match (l, r) {
+        (Float64, _) | (_, Float64) => Ok(Float64),
+        (_, Float32) | (Float32, _) => Ok(Float32),
+        // The following match arms encode the following logic: Given the two
+        // integral types, we choose the narrowest possible integral type that
+        // accommodates all values of both types. No integral type holds every value
+        // of a signed type and `UInt64`, in which case we use `Int64`, the widest
+        // signed integral type, and values of `UInt64` above its range fail to convert.
+        (Int64, _)
+        | (_, Int64)
+        | (UInt64, Int8)
+        | (Int8, UInt64)
+        | (UInt64, Int16)
+        | (Int16, UInt64)
+        | (UInt64, Int32)
+        | (Int32, UInt64)
+        | (UInt32, Int8)
+        | (Int8, UInt32)
+        | (UInt32, Int16)
+        | (Int16, UInt32)
+        | (UInt32, Int32)
+        | (Int32, UInt32) => Ok(Int64),
+        (UInt64, _) | (_, UInt64) => Ok(UInt64),
+        (Int32, _)
+        | (_, Int32)
+        | (UInt16, Int16)
+        | (Int16, UInt16)
+        | (UInt16, Int8)
+        | (Int8, UInt16) => Ok(Int32),
+        (UInt32, _) | (_, UInt32) => Ok(UInt32),
+        (Int16, _) | (_, Int16) | (Int8, UInt8) | (UInt8, Int8) => Ok(Int16),
+        (UInt16, _) | (_, UInt16) => Ok(UInt16),
+        (Int8, _) | (_, Int8) => Ok(Int8),
+        (UInt8, _) | (_, UInt8) => Ok(UInt8),
+        _ => Err(BustubxError::Internal(format!(
+            "Cannot coerce {} and {} for comparison",
+            l, r
+        ))),
+    }
+}
+
+/// Value of an integer of any width, None for nulls and other types.
+pub fn integer_value(value: &ScalarValue) -> Option<i128> {
+    match *value {
+        ScalarValue::Int8(v) => v.map(i128::from),
+        ScalarValue::Int16(v) => v.map(i128::from),
+        ScalarValue::Int32(v) => v.map(i128::from),
+        ScalarValue::Int64(v) => v.map(i128::from),
+        ScalarValue::UInt8(v) => v.map(i128::from),
+        ScalarValue::UInt16(v) => v.map(i128::from),
+        ScalarValue::UInt32(v) => v.map(i128::from),
+        ScalarValue::UInt64(v) => v.map(i128::from),
+        _ => None,
+    }
+}
+
+fn float_value(value: &ScalarValue) -> Option<f64> {
+    match *value {
+        ScalarValue::Float32(v) => v.map(f64::from),
+        ScalarValue::Float64(v) => v,
+        _ => integer_value(value).map(|v| v as f64),
+    }
+}
+
+// the integer `v` as `data_type`, an error outside the range of the type
+fn integer_to(v: i128, data_type: &DataType) -> BustubxResult<ScalarValue> {
+    let out_of_range =
+        || BustubxError::Execution(format!("{} is out of range for type {}", v, data_type));
+    Ok(match data_type {
+        DataType::Int8 => ScalarValue::Int8(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::Int16 => ScalarValue::Int16(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::Int32 => ScalarValue::Int32(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::Int64 => ScalarValue::Int64(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::UInt8 => ScalarValue::UInt8(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::UInt16 => ScalarValue::UInt16(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::UInt32 => ScalarValue::UInt32(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::UInt64 => ScalarValue::UInt64(Some(v.try_into().map_err(|_| out_of_range())?)),
+        DataType::Float32 => ScalarValue::Float32(Some(v as f32)),
+        DataType::Float64 => ScalarValue::Float64(Some(v as f64)),
+        _ => {
+            return Err(BustubxError::Internal(format!(
+                "{} is not a numeric type",
+                data_type
+            )))
+        }
+    })
+}
+
+/// Convert a numeric value to the numeric type `data_type`. A value outside the range of
+/// an integer type is an error instead of wrapping around, a float converted to an integer
+/// is rounded to the nearest integer first.
+pub fn cast_numeric(value: &ScalarValue, data_type: &DataType) -> BustubxResult<ScalarValue> {
+    if !is_numeric(&value.data_type()) || !is_numeric(data_type) {
+        return Err(BustubxError::NotSupport(format!(
+            "Failed to cast {:?} to {} type",
+            value, data_type
+        )));
+    }
+    if value.is_null() {
+        return Ok(ScalarValue::new_empty(*data_type));
+    }
+    if let Some(v) = integer_value(value) {
+        return integer_to(v, data_type);
+    }
+    let Some(v) = float_value(value) else {
+        unreachable!("numeric value {value:?} is neither an integer nor a float");
+    };
+    match data_type {
+        DataType::Float32 => Ok(ScalarValue::Float32(Some(v as f32))),
+        DataType::Float64 => Ok(ScalarValue::Float64(Some(v))),
+        _ => {
+            let rounded = v.round();
+            // i128 holds every integer type, a float beyond it is out of range of them all
+            if !rounded.is_finite() || rounded.abs() >= 2f64.powi(127) {
+                return Err(BustubxError::Execution(format!(
+                    "{} is out of range for type {}",
+                    v, data_type
+                )));
+            }
+            integer_to(rounded as i128, data_type)
+        }
+    }
+}
+
+/// Order of two non-null values as the comparison operators see them. Integers of any
+/// width and signedness compare by their exact value, a float with another numeric value
+/// compares in the float type they coerce to, see [`value_ord`] for the order in a type.
+pub fn compare(left: &ScalarValue, right: &ScalarValue) -> BustubxResult<Ordering> {
+    if let (Some(l), Some(r)) = (integer_value(left), integer_value(right)) {
+        return Ok(l.cmp(&r));
+    }
+    let coercion_type = coerce_types(&left.data_type(), &right.data_type())?;
+    value_ord::compare(
+        &left.cast_to(&coercion_type)?,
+        &right.cast_to(&coercion_type)?,
+    )
+    .ok_or(BustubxError::Execution(format!(
+        "Can not compare {:?} and {:?}",
+        left, right
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+
+    use crate::catalog::{Column, DataType, Schema};
+    use crate::common::numeric::{cast_numeric, coerce_types, compare};
+    use crate::common::ScalarValue;
+    use crate::expression::{BinaryExpr, BinaryOp, ColumnExpr, Expr, ExprTrait};
+    use crate::storage::Tuple;
+    use crate::BustubxError;
+
+    const INTEGER_TYPES: [DataType; 8] = [
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+    ];
+
+    // MIN, MIN + 1, -1, 0, 1, MAX - 1 and MAX of the type, as far as it holds them
+    fn boundary_values(data_type: &DataType) -> Vec<ScalarValue> {
+        macro_rules! boundaries {
+            ($variant:ident, $t:ty) => {{
+                let mut values = vec![<$t>::MIN, <$t>::MIN + 1, 0, 1, <$t>::MAX - 1, <$t>::MAX];
+                values.extend(<$t>::try_from(-1).ok());
+                values.sort();
+                values.dedup();
+                values
+                    .into_iter()
+                    .map(|v| ScalarValue::$variant(Some(v)))
+                    .collect()
+            }};
+        }
+        match data_type {
+            DataType::Int8 => boundaries!(Int8, i8),
+            DataType::Int16 => boundaries!(Int16, i16),
+            DataType::Int32 => boundaries!(Int32, i32),
+            DataType::Int64 => boundaries!(Int64, i64),
+            DataType::UInt8 => boundaries!(UInt8, u8),
+            DataType::UInt16 => boundaries!(UInt16, u16),
+            DataType::UInt32 => boundaries!(UInt32, u32),
+            DataType::UInt64 => boundaries!(UInt64, u64),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    pub fn test_cast_numeric_is_checked() {
+        assert_eq!(
+            cast_numeric(&ScalarValue::Int64(Some(127)), &DataType::Int8).unwrap(),
+            ScalarValue::Int8(Some(127))
+        );
+        for (value, data_type) in [
+            (ScalarValue::Int64(Some(128)), DataType::Int8),
+            (ScalarValue::Int8(Some(-1)), DataType::UInt64),
+            (ScalarValue::UInt64(Some(u64::MAX)), DataType::Int64),
+            (ScalarValue::UInt32(Some(u32::MAX)), DataType::Int32),
+            (ScalarValue::Float64(Some(f64::NAN)), DataType::Int32),
+            (ScalarValue::Float64(Some(1e40)), DataType::UInt64),
+        ] {
+            assert!(
+                matches!(
+                    cast_numeric(&value, &data_type),
+                    Err(BustubxError::Execution(_))
+                ),
+                "{value:?} as {data_type}"
+            );
+        }
+        assert_eq!(
+            cast_numeric(&ScalarValue::Float64(Some(2.5)), &DataType::Int16).unwrap(),
+            ScalarValue::Int16(Some(3))
+        );
+        assert_eq!(
+            cast_numeric(&ScalarValue::Int8(None), &DataType::UInt32).unwrap(),
+            ScalarValue::UInt32(None)
+        );
+        assert!(cast_numeric(
+            &ScalarValue::Varchar(Some("1".to_string())),
+            &DataType::Int8
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_coerce_types_is_symmetric() {
+        for l in INTEGER_TYPES
+            .iter()
+            .chain(&[DataType::Float32, DataType::Float64])
+        {
+            for r in INTEGER_TYPES
+                .iter()
+                .chain(&[DataType::Float32, DataType::Float64])
+            {
+                assert_eq!(coerce_types(l, r).unwrap(), coerce_types(r, l).unwrap());
+            }
+        }
+        assert!(coerce_types(&DataType::Int8, &DataType::Varchar(None)).is_err());
+    }
+
+    // An index holds its keys in the type of the key column and orders them with the
+    // tuple ordering, a predicate compares the column with a constant of any other
+    // integer type. Both have to agree on every pair of values, or an index scan
+    // would return other rows than a filter.
+    #[test]
+    pub fn test_tuple_ordering_agrees_with_comparisons() {
+        let comparisons = [
+            (BinaryOp::Lt, [Ordering::Less].as_slice()),
+            (BinaryOp::LtEq, &[Ordering::Less, Ordering::Equal]),
+            (BinaryOp::Eq, &[Ordering::Equal]),
+            (BinaryOp::NotEq, &[Ordering::Less, Ordering::Greater]),
+            (BinaryOp::GtEq, &[Ordering::Greater, Ordering::Equal]),
+            (BinaryOp::Gt, &[Ordering::Greater]),
+        ];
+        for key_type in INTEGER_TYPES {
+            for value_type in INTEGER_TYPES {
+                let schema = Arc::new(Schema::new(vec![
+                    Column::new("k", key_type, false),
+                    Column::new("v", value_type, false),
+                ]));
+                let key_schema = Arc::new(Schema::new(vec![Column::new("k", key_type, false)]));
+                for key in boundary_values(&key_type) {
+                    for value in boundary_values(&value_type) {
+                        let tuple = Tuple::new(schema.clone(), vec![key.clone(), value.clone()]);
+                        let order = compare(&key, &value).unwrap();
+                        for (op, accepted) in comparisons {
+                            let expr = Expr::Binary(BinaryExpr {
+                                left: Box::new(Expr::Column(ColumnExpr {
+                                    relation: None,
+                                    name: "k".to_string(),
+                                })),
+                                op,
+                                right: Box::new(Expr::Column(ColumnExpr {
+                                    relation: None,
+                                    name: "v".to_string(),
+                                })),
+                            });
+                            assert_eq!(
+                                expr.evaluate(&tuple).unwrap(),
+                                ScalarValue::Boolean(Some(accepted.contains(&order))),
+                                "{key:?} {op} {value:?}"
+                            );
+                        }
+
+                        // the probe key of an index scan, a value the key type does not
+                        // hold matches no key and must not be probed with
+                        let Ok(probe) = value.cast_to(&key_type) else {
+                            assert!(boundary_values(&key_type).iter().all(|key| compare(
+                                key, &value
+                            )
+                            .unwrap()
+                                != Ordering::Equal));
+                            continue;
+                        };
+                        let key_tuple = Tuple::new(key_schema.clone(), vec![key.clone()]);
+                        let probe_tuple = Tuple::new(key_schema.clone(), vec![probe]);
+                        assert_eq!(
+                            key_tuple.partial_cmp(&probe_tuple),
+                            Some(order),
+                            "{key:?} {value:?}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Checks that code gives back the pages it allocates. A page leaks when it is still
+//! allocated after the checked code but nothing owns it anymore, either allocated by the
+//! code or owned before it ran, e.g. a page of a dropped table nobody freed.
+
+use std::collections::BTreeSet;
+
+use crate::buffer::{BufferPoolManager, PageId};
+use crate::storage::index::BPlusTreeIndex;
+use crate::Database;
+
+/// Run `f` on the database and fail listing the pages it leaked, pages of tables, indexes
+/// and row id maps are owned. With the `debug-history` feature every leaked page comes
+/// with the operation that wrote it last.
+pub fn assert_no_page_leaks<T>(db: &mut Database, f: impl FnOnce(&mut Database) -> T) -> T {
+    let owned = |db: &Database| {
+        db.page_owners()
+            .unwrap()
+            .into_keys()
+            .collect::<BTreeSet<_>>()
+    };
+    let buffer_pool = db.buffer_pool.clone();
+    let allocated_before = buffer_pool.disk_manager.allocated_page_ids().unwrap();
+    let owned_before = owned(db);
+    let result = f(db);
+    check_leaks(&buffer_pool, allocated_before, owned_before, owned(db));
+    result
+}
+
+/// Run `f` on the index and fail listing the pages it leaked, the pages of the tree are
+/// owned.
+pub fn assert_no_index_page_leaks<T>(
+    index: &BPlusTreeIndex,
+    f: impl FnOnce(&BPlusTreeIndex) -> T,
+) -> T {
+    let owned = |index: &BPlusTreeIndex| {
+        index
+            .page_ids()
+            .unwrap()
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+    };
+    let allocated_before = index.buffer_pool.disk_manager.allocated_page_ids().unwrap();
+    let owned_before = owned(index);
+    let result = f(index);
+    check_leaks(
+        &index.buffer_pool,
+        allocated_before,
+        owned_before,
+        owned(index),
+    );
+    result
+}
+
+fn check_leaks(
+    buffer_pool: &BufferPoolManager,
+    allocated_before: BTreeSet<PageId>,
+    owned_before: BTreeSet<PageId>,
+    owned_after: BTreeSet<PageId>,
+) {
+    let leaked = buffer_pool
+        .disk_manager
+        .allocated_page_ids()
+        .unwrap()
+        .into_iter()
+        .filter(|page_id| !owned_after.contains(page_id))
+        .filter(|page_id| !allocated_before.contains(page_id) || owned_before.contains(page_id))
+        .collect::<Vec<_>>();
+    assert!(
+        leaked.is_empty(),
+        "{} pages leaked: {}",
+        leaked.len(),
+        leaked
+            .iter()
+            .map(|page_id| describe(buffer_pool, *page_id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+// the page id, with the operation tag of the last write of the page
+#[cfg(feature = "debug-history")]
+fn describe(buffer_pool: &BufferPoolManager, page_id: PageId) -> String {
+    match buffer_pool.page_history(page_id).last() {
+        Some(record) => format!("{} (last written by {})", page_id, record.operation),
+        None => format!("{} (never written)", page_id),
+    }
+}
+
+#[cfg(not(feature = "debug-history"))]
+fn describe(_buffer_pool: &BufferPoolManager, page_id: PageId) -> String {
+    page_id.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::page_leaks::assert_no_page_leaks;
+    use crate::Database;
+
+    #[test]
+    pub fn test_leaked_pages_are_reported() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        assert_no_page_leaks(&mut db, |db| {
+            db.run("create table t2 (a int)").unwrap();
+            db.run("insert into t1 values (1), (2)").unwrap();
+        });
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            assert_no_page_leaks(&mut db, |db| {
+                db.buffer_pool.new_page().unwrap().read().unwrap().page_id
+            })
+        }));
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("1 pages leaked: "), "{message}");
+        #[cfg(feature = "debug-history")]
+        assert!(message.ends_with(" (never written)"), "{message}");
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::buffer::{PageAccess, PageAccessKind};
+use crate::{Database, Tuple};
+
+/// Run `sql` with the `trace_page_accesses` setting on, the setting is restored afterwards.
+/// Returns the rows and the pages the statement fetched and allocated.
+pub fn traced_run(db: &mut Database, sql: &str) -> (Vec<Tuple>, Vec<PageAccess>) {
+    let previous = db.setting("trace_page_accesses").unwrap();
+    db.set_setting("trace_page_accesses", "on").unwrap();
+    let rows = db.run(sql).unwrap();
+    let trace = db.last_page_trace().unwrap().to_vec();
+    db.set_setting("trace_page_accesses", &previous.to_string())
+        .unwrap();
+    (rows, trace)
+}
+
+/// Distinct pages fetched, resident or read from disk alike, by relation of the page. A
+/// page fetched again, e.g. once per row by a scan, counts once.
+pub fn page_reads(db: &Database, trace: &[PageAccess]) -> BTreeMap<String, usize> {
+    let owners = db.page_owners().unwrap();
+    let page_ids = trace
+        .iter()
+        .filter(|access| access.kind != PageAccessKind::New)
+        .map(|access| access.page_id)
+        .collect::<BTreeSet<_>>();
+    let mut reads = BTreeMap::new();
+    for page_id in page_ids {
+        let owner = match owners.get(&page_id) {
+            Some(owner) => owner.to_string(),
+            None => format!("page {} of no relation", page_id),
+        };
+        *reads.entry(owner).or_insert(0) += 1;
+    }
+    reads
+}
+
+/// Run `sql` and fail if it fetched more than `max_reads` distinct pages.
+pub fn assert_max_page_reads(db: &mut Database, sql: &str, max_reads: usize) -> Vec<Tuple> {
+    let (rows, trace) = traced_run(db, sql);
+    let reads = page_reads(db, &trace);
+    let total = reads.values().sum::<usize>();
+    assert!(
+        total <= max_reads,
+        "{sql} read {total} pages, more than {max_reads}: {reads:?}"
+    );
+    rows
+}
+
+/// Run `sql` and fail if it fetched a page of a table outside `expected_tables`, the pages
+/// of the indexes of a table count as pages of the table.
+pub fn assert_pages_read_subset(
+    db: &mut Database,
+    sql: &str,
+    expected_tables: &[&str],
+) -> Vec<Tuple> {
+    let (rows, trace) = traced_run(db, sql);
+    let owners = db.page_owners().unwrap();
+    let unexpected = trace
+        .iter()
+        .filter(|access| access.kind != PageAccessKind::New)
+        .filter(|access| {
+            !owners
+                .get(&access.page_id)
+                .is_some_and(|owner| expected_tables.contains(&owner.table.table()))
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        unexpected.is_empty(),
+        "{sql} read pages outside of {expected_tables:?}: {:?}",
+        page_reads(db, &trace)
+    );
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::PageAccessKind;
+    use crate::common::page_trace::{
+        assert_max_page_reads, assert_pages_read_subset, page_reads, traced_run,
+    };
+    use crate::common::ScalarValue;
+    use crate::Database;
+
+    // 2000 rows of about 110 bytes, spread over a few dozen heap pages
+    fn fixture() -> Database {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create table t2 (a int)").unwrap();
+        for chunk in (0..2000).collect::<Vec<_>>().chunks(200) {
+            let rows = chunk
+                .iter()
+                .map(|i| format!("({i}, '{i:0>100}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {rows}")).unwrap();
+        }
+        db.run("insert into t2 values (1), (2), (3)").unwrap();
+        db
+    }
+
+    fn heap_pages(db: &Database, table: &str) -> usize {
+        db.page_owners()
+            .unwrap()
+            .values()
+            .filter(|owner| owner.table.table() == table && owner.index.is_none())
+            .count()
+    }
+
+    // levels of the index of `table`, a root to leaf path reads a page of each
+    fn index_height(db: &Database, table: &str) -> usize {
+        db.catalog
+            .relation_sizes()
+            .unwrap()
+            .into_iter()
+            .find(|size| size.table.table() == table)
+            .map(|size| size.indexes[0].level_pages.len())
+            .unwrap()
+    }
+
+    #[test]
+    pub fn test_page_trace_of_statement() {
+        let mut db = fixture();
+        assert!(db.last_page_trace().is_none());
+
+        let (rows, trace) = traced_run(&mut db, "select a from t1 where b = 'x'");
+        assert!(rows.is_empty());
+        // a full scan fetches every heap page, all of them resident
+        let heap_pages = heap_pages(&db, "t1");
+        assert!(heap_pages > 20, "{heap_pages} heap pages");
+        assert_eq!(
+            page_reads(&db, &trace).get("bustubx.public.t1"),
+            Some(&heap_pages)
+        );
+        assert!(trace
+            .iter()
+            .all(|access| access.kind == PageAccessKind::Hit));
+
+        // the trace is kept until the next traced statement
+        db.run("select a from t2").unwrap();
+        assert_eq!(db.last_page_trace().unwrap(), trace.as_slice());
+        let (_, trace) = traced_run(&mut db, "insert into t2 values (4)");
+        assert!(!trace.is_empty());
+        assert_pages_read_subset(&mut db, "select a from t2", &["t2"]);
+    }
+
+    #[test]
+    pub fn test_index_scan_page_reads() {
+        let mut db = fixture();
+        let height = index_height(&db, "t1");
+        // the root to leaf path, the next leaf where the range may go on and the heap page
+        // of the row
+        let rows = assert_max_page_reads(&mut db, "select b from t1 where a = 1234", height + 2);
+        assert_eq!(
+            rows[0].data,
+            vec![ScalarValue::Varchar(Some(format!("{:0>100}", 1234)))]
+        );
+        assert_pages_read_subset(&mut db, "select b from t1 where a = 1234", &["t1"]);
+        // a narrow range reads a leaf or two and the heap pages of its rows, past the root to
+        // leaf paths either side of a separator equal to the start, as keys equal to it may
+        // begin left of the separator
+        let rows = assert_max_page_reads(
+            &mut db,
+            "select a from t1 where a >= 500 and a < 510",
+            2 * height + 2,
+        );
+        assert_eq!(rows.len(), 10);
+    }
+
+    #[test]
+    pub fn test_limit_page_reads() {
+        let mut db = fixture();
+        let height = index_height(&db, "t1");
+        // the scan stops on the first heap page once the limit is reached, past the root to
+        // leaf path and the leaf after it
+        let rows = assert_max_page_reads(&mut db, "select a from t1 limit 5", height + 2);
+        assert_eq!(rows.len(), 5);
+        // top-N in index order reads the first leaves instead of sorting the whole table
+        let rows = assert_max_page_reads(
+            &mut db,
+            "select a, b from t1 order by a limit 5",
+            height + 2,
+        );
+        assert_eq!(
+            rows.iter()
+                .map(|row| row.data[0].clone())
+                .collect::<Vec<_>>(),
+            (0..5i32).map(ScalarValue::from).collect::<Vec<_>>()
+        );
+        assert_pages_read_subset(&mut db, "select a, b from t1 order by a limit 5", &["t1"]);
+    }
+
+    #[test]
+    pub fn test_min_max_page_reads() {
+        let mut db = fixture();
+        let height = index_height(&db, "t1");
+        // a root to leaf path to either end of the index and the heap page of the row
+        let rows = assert_max_page_reads(&mut db, "select min(a) from t1", height + 1);
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(0))]);
+        let rows = assert_max_page_reads(&mut db, "select max(a) from t1", height + 1);
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(1999))]);
+        // the start of a range is sought as by an index scan, on the root to leaf paths
+        // either side of a separator, and the end on one more path
+        let rows = assert_max_page_reads(
+            &mut db,
+            "select min(a), max(a) from t1 where a > 100 and a < 1500",
+            3 * height + 3,
+        );
+        assert_eq!(
+            rows[0].data,
+            vec![
+                ScalarValue::Int32(Some(101)),
+                ScalarValue::Int32(Some(1499))
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_join_with_empty_input_page_reads() {
+        let mut db = fixture();
+        db.run("create table t3 (a int)").unwrap();
+        let height = index_height(&db, "t1");
+        // the join stops once the empty right input was read for the first left row, past
+        // the root to leaf path the planner estimates the size of t1 on
+        let rows = assert_max_page_reads(&mut db, "select * from t1, t3", height + 2);
+        assert!(rows.is_empty());
+        let rows = assert_max_page_reads(
+            &mut db,
+            "select * from t1 join t3 on t1.a = t3.a",
+            height + 2,
+        );
+        assert!(rows.is_empty());
+        // no left row is read against an empty subquery
+        let rows = assert_pages_read_subset(
+            &mut db,
+            "select a from t1 where a in (select a from t3)",
+            &["t3"],
+        );
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    pub fn test_partition_pruning_page_reads() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int) partition by range (a)")
+            .unwrap();
+        for (i, (from, to)) in [(0, 1000), (1000, 2000), (2000, 3000)]
+            .into_iter()
+            .enumerate()
+        {
+            db.run(&format!(
+                "create table t1_{i} partition of t1 for values from ({from}) to ({to})"
+            ))
+            .unwrap();
+        }
+        let rows = (0..3000).map(|i| format!("({i}, {i})")).collect::<Vec<_>>();
+        db.run(&format!("insert into t1 values {}", rows.join(", ")))
+            .unwrap();
+
+        // only the heap of the partition holding the range is read
+        let rows = assert_pages_read_subset(
+            &mut db,
+            "select a from t1 where a >= 1100 and a < 1200",
+            &["t1_1"],
+        );
+        assert_eq!(rows.len(), 100);
+        let heap_pages = heap_pages(&db, "t1_1");
+        assert_max_page_reads(
+            &mut db,
+            "select a from t1 where a >= 1100 and a < 1200",
+            heap_pages,
+        );
+    }
+}
@@ -1,4 +1,6 @@
 use crate::catalog::DataType;
+use crate::common::util::{decode_hex, encode_hex};
+use crate::common::{numeric, value_ord};
 use crate::{BustubxError, BustubxResult};
 use std::cmp::Ordering;
 
@@ -16,6 +18,7 @@ pub enum ScalarValue {
     Float32(Option<f32>),
     Float64(Option<f64>),
     Varchar(Option<String>),
+    Bytea(Option<Vec<u8>>),
 }
 
 impl ScalarValue {
@@ -33,6 +36,7 @@ impl ScalarValue {
             DataType::Float32 => Self::Float32(None),
             DataType::Float64 => Self::Float64(None),
             DataType::Varchar(_) => Self::Varchar(None),
+            DataType::Bytea => Self::Bytea(None),
         }
     }
 
@@ -50,6 +54,7 @@ impl ScalarValue {
             ScalarValue::Float32(_) => DataType::Float32,
             ScalarValue::Float64(_) => DataType::Float64,
             ScalarValue::Varchar(_) => DataType::Varchar(None),
+            ScalarValue::Bytea(_) => DataType::Bytea,
         }
     }
 
@@ -67,6 +72,7 @@ impl ScalarValue {
             ScalarValue::Float32(v) => v.is_none(),
             ScalarValue::Float64(v) => v.is_none(),
             ScalarValue::Varchar(v) => v.is_none(),
+            ScalarValue::Bytea(v) => v.is_none(),
         }
     }
 
@@ -78,95 +84,32 @@ impl ScalarValue {
         if &self.data_type() == data_type {
             return Ok(self.clone());
         }
+        // NULL literals are bound as null Int8, a NULL casts to every type
+        if self.is_null() {
+            return Ok(ScalarValue::new_empty(*data_type));
+        }
+        if numeric::is_numeric(&self.data_type()) && numeric::is_numeric(data_type) {
+            return numeric::cast_numeric(self, data_type);
+        }
 
         match data_type {
-            DataType::Int8 => {
-                let data = match self {
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as i8)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::Int8)
-            }
-            DataType::Int16 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as i16)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as i16)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::Int16)
-            }
-            DataType::Int32 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as i32)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as i32)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::Int32)
-            }
-            DataType::Int64 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as i64)),
-                    ScalarValue::Int32(v) => Ok(v.map(|v| v as i64)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::Int64)
-            }
-            DataType::UInt8 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as u8)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as u8)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::UInt8)
-            }
-            DataType::UInt16 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as u16)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as u16)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::UInt16)
-            }
-            DataType::UInt32 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as u32)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as u32)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::UInt32)
-            }
-            DataType::UInt64 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as u64)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as u64)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::UInt64)
-            }
-            DataType::Float32 => {
-                let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as f32)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as f32)),
-                    ScalarValue::Float64(v) => Ok(v.map(|v| v as f32)),
-                    _ => Err(error),
-                };
-                data.map(ScalarValue::Float32)
-            }
-            DataType::Float64 => {
+            DataType::Varchar(_) => {
                 let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v as f64)),
-                    ScalarValue::Int32(v) => Ok(v.map(|v| v as f64)),
-                    ScalarValue::Int64(v) => Ok(v.map(|v| v as f64)),
+                    ScalarValue::Int8(v) => Ok(v.map(|v| v.to_string())),
+                    // the declared length is not enforced
+                    ScalarValue::Varchar(v) => Ok(v.clone()),
                     _ => Err(error),
                 };
-                data.map(ScalarValue::Float64)
+                data.map(ScalarValue::Varchar)
             }
-            DataType::Varchar(_) => {
+            DataType::Bytea => {
                 let data = match self {
-                    ScalarValue::Int8(v) => Ok(v.map(|v| v.to_string())),
+                    // an untyped NULL
+                    ScalarValue::Int8(None) => Ok(None),
+                    ScalarValue::Varchar(v) => Ok(v.as_ref().map(|v| v.as_bytes().to_vec())),
                     _ => Err(error),
                 };
-                data.map(ScalarValue::Varchar)
+                data.map(ScalarValue::Bytea)
             }
             _ => Err(error),
         }
@@ -190,7 +133,7 @@ impl ScalarValue {
         todo!()
     }
 
-    pub fn from_string(string: &String, data_type: DataType) -> BustubxResult<Self> {
+    pub fn from_string(string: &str, data_type: DataType) -> BustubxResult<Self> {
         let is_null = string.eq_ignore_ascii_case("null");
         match data_type {
             DataType::Boolean => {
@@ -315,9 +258,25 @@ impl ScalarValue {
                 Ok(ScalarValue::Float64(v))
             }
             DataType::Varchar(_) => {
-                let v = if is_null { None } else { Some(string.clone()) };
+                let v = if is_null {
+                    None
+                } else {
+                    Some(string.to_string())
+                };
                 Ok(ScalarValue::Varchar(v))
             }
+            DataType::Bytea => {
+                let v = if is_null {
+                    None
+                } else {
+                    // the displayed form, \x followed by the hex digits
+                    let hex = string
+                        .strip_prefix("\\x")
+                        .ok_or_else(|| BustubxError::Internal("Parse bytea failed".to_string()))?;
+                    Some(decode_hex(hex)?)
+                };
+                Ok(ScalarValue::Bytea(v))
+            }
         }
     }
 }
@@ -356,6 +315,8 @@ impl PartialEq for ScalarValue {
             (Float64(_), _) => false,
             (Varchar(v1), Varchar(v2)) => v1.eq(v2),
             (Varchar(_), _) => false,
+            (Bytea(v1), Bytea(v2)) => v1.eq(v2),
+            (Bytea(_), _) => false,
         }
     }
 }
@@ -364,39 +325,7 @@ impl Eq for ScalarValue {}
 
 impl PartialOrd for ScalarValue {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        use ScalarValue::*;
-        match (self, other) {
-            (Boolean(v1), Boolean(v2)) => v1.partial_cmp(v2),
-            (Boolean(_), _) => None,
-            (Int8(v1), Int8(v2)) => v1.partial_cmp(v2),
-            (Int8(_), _) => None,
-            (Int16(v1), Int16(v2)) => v1.partial_cmp(v2),
-            (Int16(_), _) => None,
-            (Int32(v1), Int32(v2)) => v1.partial_cmp(v2),
-            (Int32(_), _) => None,
-            (Int64(v1), Int64(v2)) => v1.partial_cmp(v2),
-            (Int64(_), _) => None,
-            (UInt8(v1), UInt8(v2)) => v1.partial_cmp(v2),
-            (UInt8(_), _) => None,
-            (UInt16(v1), UInt16(v2)) => v1.partial_cmp(v2),
-            (UInt16(_), _) => None,
-            (UInt32(v1), UInt32(v2)) => v1.partial_cmp(v2),
-            (UInt32(_), _) => None,
-            (UInt64(v1), UInt64(v2)) => v1.partial_cmp(v2),
-            (UInt64(_), _) => None,
-            (Float32(v1), Float32(v2)) => match (v1, v2) {
-                (Some(f1), Some(f2)) => Some(f1.total_cmp(f2)),
-                _ => v1.partial_cmp(v2),
-            },
-            (Float32(_), _) => None,
-            (Float64(v1), Float64(v2)) => match (v1, v2) {
-                (Some(f1), Some(f2)) => Some(f1.total_cmp(f2)),
-                _ => v1.partial_cmp(v2),
-            },
-            (Float64(_), _) => None,
-            (Varchar(v1), Varchar(v2)) => v1.partial_cmp(v2),
-            (Varchar(_), _) => None,
-        }
+        value_ord::compare(self, other)
     }
 }
 
@@ -416,6 +345,7 @@ impl std::hash::Hash for ScalarValue {
             UInt32(v) => v.hash(state),
             UInt64(v) => v.hash(state),
             Varchar(v) => v.hash(state),
+            Bytea(v) => v.hash(state),
         }
     }
 }
@@ -463,6 +393,8 @@ impl std::fmt::Display for ScalarValue {
             ScalarValue::Float64(Some(v)) => write!(f, "{v}"),
             ScalarValue::Varchar(None) => write!(f, "NULL"),
             ScalarValue::Varchar(Some(v)) => write!(f, "{v}"),
+            ScalarValue::Bytea(None) => write!(f, "NULL"),
+            ScalarValue::Bytea(Some(v)) => write!(f, "\\x{}", encode_hex(v)),
         }
     }
 }
@@ -495,3 +427,107 @@ impl_from_for_scalar!(u64, UInt64);
 impl_from_for_scalar!(f32, Float32);
 impl_from_for_scalar!(f64, Float64);
 impl_from_for_scalar!(String, Varchar);
+impl_from_for_scalar!(Vec<u8>, Bytea);
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::DataType;
+    use crate::common::util::encode_hex;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::{BustubxError, Database, DatabaseOptions};
+    use tempfile::TempDir;
+
+    #[test]
+    pub fn test_bytea_survives_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        // every byte value, close to the largest row a page holds
+        let large = (0..3000).map(|i| (i % 256) as u8).collect::<Vec<u8>>();
+        let literal = |bytes: &[u8]| format!("x'{}'", encode_hex(bytes));
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (id int, b bytea default x'cafe')")
+            .unwrap();
+        db.run(&format!(
+            "insert into t1 values (1, {}), (2, x''), (3, null)",
+            literal(&large)
+        ))
+        .unwrap();
+        db.run("insert into t1 (id) values (4)").unwrap();
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        let mut rows = db.run("select id, b from t1").unwrap();
+        rows.sort_by_key(|tuple| format!("{}", tuple.data[0]));
+        let values = rows
+            .into_iter()
+            .map(|tuple| tuple.data[1].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                large.clone().into(),
+                Vec::<u8>::new().into(),
+                ScalarValue::Bytea(None),
+                vec![0xca, 0xfe].into(),
+            ]
+        );
+        let rows = db
+            .run(&format!("select id from t1 where b = {}", literal(&large)))
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            ScalarValue::from_string("\\xCAFE", DataType::Bytea).unwrap(),
+            vec![0xca, 0xfe].into()
+        );
+    }
+
+    #[test]
+    pub fn test_oversized_bytea_values_are_refused() {
+        let mut db = Database::new_temp().unwrap();
+        let literal = |len: usize| format!("x'{}'", encode_hex(&vec![0xab; len]));
+        let count = |db: &mut Database, table: &str| {
+            db.run(&format!("select * from {table}")).unwrap().len()
+        };
+        db.run("create table t1 (id int, b bytea)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        db.run(&format!("insert into t1 values (1, {})", literal(300)))
+            .unwrap();
+        assert!(matches!(
+            db.run(&format!("insert into t1 values (2, {})", literal(600))),
+            Err(BustubxError::IndexKeyTooLarge { size, max }) if size > max
+        ));
+        assert!(matches!(
+            db.run(&format!("update t1 set b = {} where id = 1", literal(600))),
+            Err(BustubxError::IndexKeyTooLarge { .. })
+        ));
+        assert_eq!(count(&mut db, "t1"), 1);
+        assert!(db.run("check table t1 with indexes").unwrap().is_empty());
+
+        // rows written before the index was created are refused by the build
+        db.run("create table t2 (b bytea)").unwrap();
+        db.run(&format!("insert into t2 values ({})", literal(2000)))
+            .unwrap();
+        assert!(matches!(
+            db.run("create index idx_t2 on t2 (b)"),
+            Err(BustubxError::IndexKeyTooLarge { .. })
+        ));
+        assert!(db
+            .catalog
+            .index(&TableReference::bare("t2"), "idx_t2")
+            .unwrap()
+            .is_none());
+        assert_eq!(count(&mut db, "t2"), 1);
+
+        assert!(matches!(
+            db.run(&format!("insert into t2 values ({})", literal(5000))),
+            Err(BustubxError::TupleTooLarge { .. })
+        ));
+        assert_eq!(count(&mut db, "t2"), 1);
+
+        assert!(matches!(
+            db.run("insert into t2 values (x'0')"),
+            Err(BustubxError::Parser(_))
+        ));
+    }
+}
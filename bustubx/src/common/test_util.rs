@@ -0,0 +1,49 @@
+//! Rows of queries in the shapes the tests compare them in.
+
+use crate::common::ScalarValue;
+use crate::Database;
+
+/// Values of the rows `sql` returns, in the order it returns them.
+pub(crate) fn rows(db: &mut Database, sql: &str) -> Vec<Vec<ScalarValue>> {
+    db.run(sql)
+        .unwrap_or_else(|e| panic!("{sql}: {e}"))
+        .into_iter()
+        .map(|tuple| tuple.data)
+        .collect()
+}
+
+/// [`rows`] sorted, for queries returning them in no particular order.
+pub(crate) fn sorted_rows(db: &mut Database, sql: &str) -> Vec<Vec<ScalarValue>> {
+    let mut rows = rows(db, sql);
+    rows.sort_by_key(|row| format!("{row:?}"));
+    rows
+}
+
+/// [`rows`] printed with their values separated by spaces, e.g. `"2 20"`.
+pub(crate) fn text_rows(db: &mut Database, sql: &str) -> Vec<String> {
+    rows(db, sql)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// [`rows`] of integer columns, panics on any other value.
+pub(crate) fn int_rows(db: &mut Database, sql: &str) -> Vec<Vec<i64>> {
+    rows(db, sql)
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| match *value {
+                    ScalarValue::Int32(Some(v)) => v as i64,
+                    ScalarValue::Int64(Some(v)) => v,
+                    ref v => panic!("unexpected value {v}"),
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,388 @@
+//! The order of values. Index keys, comparison operators and ORDER BY all order values
+//! through this module, so an index scan, a filter and a sort agree on every pair of
+//! values.
+//!
+//! Within one data type:
+//!
+//! | DataType | Order |
+//! |----------|-------|
+//! | `Boolean` | `false` before `true` |
+//! | `Int8` .. `Int64`, `UInt8` .. `UInt64` | by value |
+//! | `Float32`, `Float64` | `total_cmp`: -NaN, -inf, negative values, -0.0, 0.0, positive values, inf, NaN |
+//! | `Varchar` | bytewise by UTF-8 encoding, see [`Collation`] |
+//! | `Bytea` | bytewise, a prefix before the longer string |
+//!
+//! NULL orders before every value of its type and equal to another NULL. ORDER BY places
+//! NULLs by `NULLS FIRST` or `NULLS LAST` of a key, by default where this order puts them:
+//! first for an ascending key and last for a descending one. Values of different types
+//! have no order, the comparison operators coerce numeric values to a common type before
+//! comparing them, see [`crate::common::numeric::compare`].
+
+use std::cmp::Ordering;
+
+use crate::catalog::ColumnRef;
+use crate::common::ScalarValue;
+use crate::BustubxError;
+
+/// How the strings of a column compare, chosen with `COLLATE` in the column definition.
+/// Index keys, comparisons and sorts on the column all use it, grouping and IN subqueries
+/// still compare strings bytewise.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Collation {
+    /// bytewise by UTF-8 encoding
+    #[default]
+    Binary,
+    /// bytewise with ASCII letters folded to lower case, like NOCASE of SQLite
+    NoCase,
+}
+
+impl Collation {
+    pub fn compare_str(&self, left: &str, right: &str) -> Ordering {
+        match self {
+            Collation::Binary => left.cmp(right),
+            Collation::NoCase => left
+                .bytes()
+                .map(|b| b.to_ascii_lowercase())
+                .cmp(right.bytes().map(|b| b.to_ascii_lowercase())),
+        }
+    }
+}
+
+impl TryFrom<&str> for Collation {
+    type Error = BustubxError;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        if name.eq_ignore_ascii_case("binary") {
+            Ok(Collation::Binary)
+        } else if name.eq_ignore_ascii_case("nocase") {
+            Ok(Collation::NoCase)
+        } else {
+            Err(BustubxError::NotSupport(format!(
+                "collation {name} not supported"
+            )))
+        }
+    }
+}
+
+impl std::fmt::Display for Collation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collation::Binary => write!(f, "binary"),
+            Collation::NoCase => write!(f, "nocase"),
+        }
+    }
+}
+
+/// Order of two values of the same data type, None if the types differ.
+pub fn compare(left: &ScalarValue, right: &ScalarValue) -> Option<Ordering> {
+    use ScalarValue::*;
+    match (left, right) {
+        (Boolean(v1), Boolean(v2)) => Some(v1.cmp(v2)),
+        (Int8(v1), Int8(v2)) => Some(v1.cmp(v2)),
+        (Int16(v1), Int16(v2)) => Some(v1.cmp(v2)),
+        (Int32(v1), Int32(v2)) => Some(v1.cmp(v2)),
+        (Int64(v1), Int64(v2)) => Some(v1.cmp(v2)),
+        (UInt8(v1), UInt8(v2)) => Some(v1.cmp(v2)),
+        (UInt16(v1), UInt16(v2)) => Some(v1.cmp(v2)),
+        (UInt32(v1), UInt32(v2)) => Some(v1.cmp(v2)),
+        (UInt64(v1), UInt64(v2)) => Some(v1.cmp(v2)),
+        (Float32(v1), Float32(v2)) => Some(nulls_first(v1, v2, f32::total_cmp)),
+        (Float64(v1), Float64(v2)) => Some(nulls_first(v1, v2, f64::total_cmp)),
+        (Varchar(v1), Varchar(v2)) => Some(v1.cmp(v2)),
+        (Bytea(v1), Bytea(v2)) => Some(v1.cmp(v2)),
+        _ => None,
+    }
+}
+
+/// Order of two values of the same data type, strings compare by `collation`.
+pub fn compare_collated(
+    left: &ScalarValue,
+    right: &ScalarValue,
+    collation: Collation,
+) -> Option<Ordering> {
+    match (left, right) {
+        (ScalarValue::Varchar(Some(v1)), ScalarValue::Varchar(Some(v2))) => {
+            Some(collation.compare_str(v1, v2))
+        }
+        _ => compare(left, right),
+    }
+}
+
+/// Order of two rows of `columns`, column by column in the collations of the columns.
+pub fn compare_rows(
+    left: &[ScalarValue],
+    right: &[ScalarValue],
+    columns: &[ColumnRef],
+) -> Option<Ordering> {
+    for ((l, r), column) in left.iter().zip(right).zip(columns) {
+        match compare_collated(l, r, column.collation)? {
+            Ordering::Equal => continue,
+            order => return Some(order),
+        }
+    }
+    Some(left.len().cmp(&right.len()))
+}
+
+/// Order of two values of a sort key, reversed for a descending key, NULLs before or
+/// after all values by `nulls_first` whatever the direction.
+pub fn compare_sort_key(
+    left: &ScalarValue,
+    right: &ScalarValue,
+    asc: bool,
+    nulls_first: bool,
+    collation: Collation,
+) -> Option<Ordering> {
+    let order = match (left.is_null(), right.is_null()) {
+        (false, false) => compare_collated(left, right, collation)?,
+        (l, r) => return Some(if nulls_first { r.cmp(&l) } else { l.cmp(&r) }),
+    };
+    Some(if asc { order } else { order.reverse() })
+}
+
+fn nulls_first<T>(v1: &Option<T>, v2: &Option<T>, cmp: fn(&T, &T) -> Ordering) -> Ordering {
+    match (v1, v2) {
+        (Some(v1), Some(v2)) => cmp(v1, v2),
+        (v1, v2) => v1.is_some().cmp(&v2.is_some()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::sync::Arc;
+
+    use crate::catalog::{Column, DataType, Schema};
+    use crate::common::value_ord::{compare, compare_collated, compare_sort_key, Collation};
+    use crate::common::ScalarValue;
+    use crate::execution::physical_plan::compare_tuples;
+    use crate::expression::{BinaryExpr, BinaryOp, ColumnExpr, Expr, ExprTrait};
+    use crate::planner::logical_plan::OrderByExpr;
+    use crate::storage::Tuple;
+
+    const DATA_TYPES: [DataType; 13] = [
+        DataType::Boolean,
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+        DataType::Float32,
+        DataType::Float64,
+        DataType::Varchar(None),
+        DataType::Bytea,
+    ];
+
+    // xorshift, a fixed seed keeps failures reproducible
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        // mostly small values so pairs are often equal, NULL one time in eight
+        fn value(&mut self, data_type: &DataType) -> ScalarValue {
+            let null = self.next().is_multiple_of(8);
+            let bits = match self.next() % 4 {
+                0 => self.next() % 4,
+                1 => u64::MAX - self.next() % 4,
+                _ => self.next(),
+            };
+            let bytes = |len: u64| bits.to_le_bytes()[..len as usize % 4].to_vec();
+            let value = match data_type {
+                DataType::Boolean => ScalarValue::Boolean(Some(bits % 2 == 0)),
+                DataType::Int8 => ScalarValue::Int8(Some(bits as i8)),
+                DataType::Int16 => ScalarValue::Int16(Some(bits as i16)),
+                DataType::Int32 => ScalarValue::Int32(Some(bits as i32)),
+                DataType::Int64 => ScalarValue::Int64(Some(bits as i64)),
+                DataType::UInt8 => ScalarValue::UInt8(Some(bits as u8)),
+                DataType::UInt16 => ScalarValue::UInt16(Some(bits as u16)),
+                DataType::UInt32 => ScalarValue::UInt32(Some(bits as u32)),
+                DataType::UInt64 => ScalarValue::UInt64(Some(bits)),
+                DataType::Float32 => ScalarValue::Float32(Some(match bits % 8 {
+                    0 => f32::NAN,
+                    1 => -0.0,
+                    2 => f32::NEG_INFINITY,
+                    _ => f32::from_bits(bits as u32),
+                })),
+                DataType::Float64 => ScalarValue::Float64(Some(match bits % 8 {
+                    0 => f64::NAN,
+                    1 => -0.0,
+                    2 => f64::INFINITY,
+                    _ => f64::from_bits(bits),
+                })),
+                DataType::Varchar(_) => ScalarValue::Varchar(Some(
+                    bytes(self.next())
+                        .into_iter()
+                        .map(|b| char::from(b'a' + b % 3))
+                        .collect(),
+                )),
+                DataType::Bytea => ScalarValue::Bytea(Some(bytes(self.next()))),
+            };
+            if null {
+                ScalarValue::new_empty(*data_type)
+            } else {
+                value
+            }
+        }
+    }
+
+    fn column(name: &str) -> Box<Expr> {
+        Box::new(Expr::Column(ColumnExpr {
+            relation: None,
+            name: name.to_string(),
+        }))
+    }
+
+    #[test]
+    pub fn test_value_order() {
+        assert_eq!(
+            compare(
+                &ScalarValue::Int32(None),
+                &ScalarValue::Int32(Some(i32::MIN))
+            ),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(&ScalarValue::Float64(None), &ScalarValue::Float64(None)),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            compare(
+                &ScalarValue::Float64(Some(-0.0)),
+                &ScalarValue::Float64(Some(0.0))
+            ),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(
+                &ScalarValue::Float32(Some(f32::INFINITY)),
+                &ScalarValue::Float32(Some(f32::NAN))
+            ),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare(&ScalarValue::Int32(Some(1)), &ScalarValue::Int64(Some(1))),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_collation_and_null_placement() {
+        let varchar = |v: Option<&str>| ScalarValue::Varchar(v.map(|v| v.to_string()));
+        let nocase =
+            |l, r| compare_collated(&varchar(Some(l)), &varchar(Some(r)), Collation::NoCase);
+        assert_eq!(nocase("Alice", "aLICE"), Some(Ordering::Equal));
+        // folded `B` orders after `a`, unlike in binary order
+        assert_eq!(nocase("B", "a"), Some(Ordering::Greater));
+        assert_eq!(
+            compare(&varchar(Some("B")), &varchar(Some("a"))),
+            Some(Ordering::Less)
+        );
+        // only ASCII letters fold
+        assert_eq!(nocase("É", "é"), Some(Ordering::Less));
+        assert_eq!(nocase("ab", "AbC"), Some(Ordering::Less));
+        assert_eq!(Collation::try_from("NOCASE").unwrap(), Collation::NoCase);
+        assert!(Collation::try_from("de_DE").is_err());
+
+        let (null, one) = (ScalarValue::Int32(None), ScalarValue::Int32(Some(1)));
+        for asc in [true, false] {
+            for nulls_first in [true, false] {
+                let order = compare_sort_key(&null, &one, asc, nulls_first, Collation::Binary);
+                let expected = if nulls_first {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+                assert_eq!(
+                    order,
+                    Some(expected),
+                    "asc: {asc} nulls first: {nulls_first}"
+                );
+            }
+        }
+        assert_eq!(
+            compare_sort_key(
+                &varchar(Some("a")),
+                &varchar(Some("B")),
+                false,
+                true,
+                Collation::NoCase
+            ),
+            Some(Ordering::Greater)
+        );
+    }
+
+    // Tuples order index keys, comparison operators filter rows and the sort comparator
+    // orders results, all three have to agree on every pair of values of a type.
+    #[test]
+    pub fn test_orderings_agree() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        for data_type in DATA_TYPES {
+            let schema = Arc::new(Schema::new(vec![
+                Column::new("l", data_type, true),
+                Column::new("r", data_type, true),
+            ]));
+            let key_schema = Arc::new(Schema::new(vec![Column::new("k", data_type, true)]));
+            for _ in 0..500 {
+                let left = random.value(&data_type);
+                let right = random.value(&data_type);
+                let order = compare(&left, &right).unwrap();
+
+                let key = |value: &ScalarValue| Tuple::new(key_schema.clone(), vec![value.clone()]);
+                assert_eq!(
+                    key(&left).partial_cmp(&key(&right)),
+                    Some(order),
+                    "{left:?} {right:?}"
+                );
+
+                for asc in [true, false] {
+                    let order_by = OrderByExpr {
+                        expr: column("k"),
+                        asc,
+                        nulls_first: asc,
+                    };
+                    let sorted = compare_tuples(&[order_by], &key(&left), &key(&right), false);
+                    assert_eq!(
+                        sorted.unwrap(),
+                        if asc { order } else { order.reverse() },
+                        "{left:?} {right:?} asc: {asc}"
+                    );
+                }
+
+                let row = Tuple::new(schema.clone(), vec![left.clone(), right.clone()]);
+                for (op, accepted) in [
+                    (BinaryOp::Lt, [Ordering::Less].as_slice()),
+                    (BinaryOp::LtEq, &[Ordering::Less, Ordering::Equal]),
+                    (BinaryOp::Eq, &[Ordering::Equal]),
+                    (BinaryOp::NotEq, &[Ordering::Less, Ordering::Greater]),
+                    (BinaryOp::GtEq, &[Ordering::Greater, Ordering::Equal]),
+                    (BinaryOp::Gt, &[Ordering::Greater]),
+                ] {
+                    let expr = Expr::Binary(BinaryExpr {
+                        left: column("l"),
+                        op,
+                        right: column("r"),
+                    });
+                    // comparing with NULL is unknown, only sorts and indexes place NULLs
+                    let expected = if left.is_null() || right.is_null() {
+                        ScalarValue::Boolean(None)
+                    } else {
+                        ScalarValue::Boolean(Some(accepted.contains(&order)))
+                    };
+                    assert_eq!(
+                        expr.evaluate(&row).unwrap(),
+                        expected,
+                        "{left:?} {op} {right:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::buffer::{BUFFER_POOL_SIZE, BUSTUBX_PAGE_SIZE};
+use crate::catalog::{ANALYZE_SAMPLE_SIZE, DEFAULT_SCHEMA_NAME};
+use crate::storage::CompressionCodec;
+use crate::transaction::{TransactionId, INVALID_TRANSACTION_ID};
+use crate::{BustubxError, BustubxResult};
+
+// The catalog alone pins a handful of pages while loading
+pub const MIN_BUFFER_POOL_SIZE: usize = 16;
+pub const DEFAULT_REPLACER_K: usize = 2;
+pub const DEFAULT_FRAME_WAIT_TIMEOUT: Duration = Duration::from_secs(1);
+pub const DEFAULT_CLEAN_EVICTION_WINDOW: usize = 8;
+pub const DEFAULT_AGGREGATE_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+pub const DEFAULT_INDEX_REBUILD_THRESHOLD: u32 = 50;
+pub const DEFAULT_SORT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+pub const DEFAULT_PLAN_CACHE_CAPACITY: usize = 128;
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+pub const DEFAULT_PARALLEL_SCAN_MIN_PAGES: usize = 64;
+pub const DEFAULT_TRANSACTION_MAX_RETRIES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    // Leave durability to the OS page cache
+    #[default]
+    Never,
+    // fsync the db file when the buffer pool is flushed
+    OnFlush,
+    // fsync the db file after every page write
+    Always,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferPoolOptions {
+    pub pool_size: usize,
+    // k of the LRU-K replacer
+    pub replacer_k: usize,
+    // how long spilling operators wait for another thread to unpin a frame of a full pool
+    pub frame_wait_timeout: Duration,
+    // an eviction takes a clean page over a dirty victim if one is among this many next
+    // victims, 0 evicts in plain LRU-K order
+    pub clean_eviction_window: usize,
+    // how often a background thread writes dirty unpinned pages back, none without one
+    pub background_flush_interval: Option<Duration>,
+}
+
+impl Default for BufferPoolOptions {
+    fn default() -> Self {
+        Self {
+            pool_size: BUFFER_POOL_SIZE,
+            replacer_k: DEFAULT_REPLACER_K,
+            frame_wait_timeout: DEFAULT_FRAME_WAIT_TIMEOUT,
+            clean_eviction_window: DEFAULT_CLEAN_EVICTION_WINDOW,
+            background_flush_interval: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskOptions {
+    pub page_size: usize,
+    pub sync_policy: SyncPolicy,
+    // initialize an existing zero length db file instead of refusing to open it
+    pub reinitialize_empty_file: bool,
+    // keep every page write in memory so it can be shipped to a standby
+    pub replication_log: bool,
+    // codec of the full segments of the replication log and of spilled sort runs, the
+    // pages of the db file are never compressed
+    pub compression: CompressionCodec,
+    // closed segments of the log are copied there for `Database::restore`, keeps the log
+    // even without `replication_log`
+    pub wal_archive_dir: Option<PathBuf>,
+}
+
+impl Default for DiskOptions {
+    fn default() -> Self {
+        Self {
+            page_size: BUSTUBX_PAGE_SIZE,
+            sync_policy: SyncPolicy::default(),
+            reinitialize_empty_file: false,
+            replication_log: false,
+            compression: CompressionCodec::None,
+            wal_archive_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionOptions {
+    // bytes of group state a hash aggregation keeps in memory before spilling rows to temp pages
+    pub aggregate_memory_budget: usize,
+    // ANALYZE samples this many pages of a table, then this many live rows of those pages
+    pub analyze_sample_size: usize,
+    // rows written to a table since its last ANALYZE that start one in the background, 0
+    // never does
+    pub auto_analyze_threshold: u64,
+    // ANALYZE also sketches the distinct values of the indexed columns, kept up by the
+    // writes after it and used once its statistics are stale
+    pub distinct_sketches: bool,
+    // scans skip rows of ttl tables that expired but were not deleted by `expire_rows` yet
+    pub hide_expired_rows: bool,
+    // percent of a table a DELETE is estimated to affect above which it skips per row
+    // index maintenance and bulk rebuilds the indexes afterwards, 100 never rebuilds
+    pub index_rebuild_threshold: u32,
+    // entries an index scan returns before it finds its position again by a descent, so a
+    // long scan also sees the entries written to the leaf it is on. 0 only does at leaf ends
+    pub index_scan_revalidate_interval: usize,
+    // bytes of rows a sort keeps in memory before writing them out as a sorted run
+    pub sort_memory_budget: usize,
+    // ORDER BY breaks ties of the sort keys on the remaining columns, so rows equal on
+    // the sort keys come out in the same order whatever order the input produced them in
+    pub deterministic_order: bool,
+    // plans of recently run statements kept for reuse, 0 plans every statement again
+    pub plan_cache_capacity: usize,
+    // how long a statement waits for the table locks it needs before failing, zero waits
+    // as long as it takes
+    pub lock_timeout: Duration,
+    // how long a statement may run before it is canceled, zero never cancels
+    pub statement_timeout: Duration,
+    // how long a transaction of `Session::run_transaction` may go without running a
+    // statement, the next one fails and aborts it. zero never aborts
+    pub idle_transaction_timeout: Duration,
+    // every statement records the pages it fetches and allocates, see
+    // `Database::last_page_trace`
+    pub trace_page_accesses: bool,
+    // EXPLAIN also shows why the optimizer applied its rules and access paths or not, see
+    // `OptimizerTrace`
+    pub optimizer_trace: bool,
+    // a script runs the statements after a failed one instead of stopping
+    pub continue_script_on_error: bool,
+    // scans, filters and projections hand rows to each other in batches of `batch_size`
+    pub batch_execution: bool,
+    pub batch_size: usize,
+    // worker threads of a sequential scan of a table with at least `parallel_scan_min_pages`
+    // pages, the rows then come out in no particular order. 0 and 1 scan serially
+    pub parallel_scan_workers: usize,
+    pub parallel_scan_min_pages: usize,
+    // times `Session::run_transaction` runs a transaction again after a retryable failure
+    pub transaction_max_retries: usize,
+    // schemas an unqualified table name is looked up in, the first one holding the table
+    // wins and new tables go to the first one that exists
+    pub search_path: Vec<String>,
+    // queries read the rows as they were right after this transaction committed, see
+    // `TransactionManager::snapshot`. `INVALID_TRANSACTION_ID` reads the latest rows
+    pub snapshot_txn: TransactionId,
+}
+
+impl Default for ExecutionOptions {
+    fn default() -> Self {
+        Self {
+            aggregate_memory_budget: DEFAULT_AGGREGATE_MEMORY_BUDGET,
+            analyze_sample_size: ANALYZE_SAMPLE_SIZE,
+            auto_analyze_threshold: 0,
+            distinct_sketches: false,
+            hide_expired_rows: false,
+            index_rebuild_threshold: DEFAULT_INDEX_REBUILD_THRESHOLD,
+            index_scan_revalidate_interval: 0,
+            sort_memory_budget: DEFAULT_SORT_MEMORY_BUDGET,
+            deterministic_order: false,
+            plan_cache_capacity: DEFAULT_PLAN_CACHE_CAPACITY,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            statement_timeout: Duration::ZERO,
+            idle_transaction_timeout: Duration::ZERO,
+            trace_page_accesses: false,
+            optimizer_trace: false,
+            continue_script_on_error: false,
+            batch_execution: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            parallel_scan_workers: 0,
+            parallel_scan_min_pages: DEFAULT_PARALLEL_SCAN_MIN_PAGES,
+            transaction_max_retries: DEFAULT_TRANSACTION_MAX_RETRIES,
+            search_path: vec![DEFAULT_SCHEMA_NAME.to_string()],
+            snapshot_txn: INVALID_TRANSACTION_ID,
+        }
+    }
+}
+
+/// Options used to open a [`crate::Database`].
+///
+/// ```ignore
+/// let options = DatabaseOptions::new()
+///     .buffer_pool_size(256)
+///     .sync_policy(SyncPolicy::OnFlush);
+/// let db = Database::open_with_options("test.db", options)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseOptions {
+    pub buffer_pool: BufferPoolOptions,
+    pub disk: DiskOptions,
+    pub execution: ExecutionOptions,
+}
+
+impl DatabaseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buffer_pool_size(mut self, pool_size: usize) -> Self {
+        self.buffer_pool.pool_size = pool_size;
+        self
+    }
+
+    pub fn replacer_k(mut self, k: usize) -> Self {
+        self.buffer_pool.replacer_k = k;
+        self
+    }
+
+    pub fn frame_wait_timeout(mut self, timeout: Duration) -> Self {
+        self.buffer_pool.frame_wait_timeout = timeout;
+        self
+    }
+
+    pub fn clean_eviction_window(mut self, window: usize) -> Self {
+        self.buffer_pool.clean_eviction_window = window;
+        self
+    }
+
+    pub fn background_flush_interval(mut self, interval: Duration) -> Self {
+        self.buffer_pool.background_flush_interval = Some(interval);
+        self
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.disk.page_size = page_size;
+        self
+    }
+
+    pub fn sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.disk.sync_policy = sync_policy;
+        self
+    }
+
+    pub fn reinitialize_empty_file(mut self, reinitialize: bool) -> Self {
+        self.disk.reinitialize_empty_file = reinitialize;
+        self
+    }
+
+    pub fn replication_log(mut self, enabled: bool) -> Self {
+        self.disk.replication_log = enabled;
+        self
+    }
+
+    pub fn compression(mut self, codec: CompressionCodec) -> Self {
+        self.disk.compression = codec;
+        self
+    }
+
+    pub fn wal_archive_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk.wal_archive_dir = Some(dir.into());
+        self
+    }
+
+    pub fn aggregate_memory_budget(mut self, bytes: usize) -> Self {
+        self.execution.aggregate_memory_budget = bytes;
+        self
+    }
+
+    pub fn analyze_sample_size(mut self, rows: usize) -> Self {
+        self.execution.analyze_sample_size = rows;
+        self
+    }
+
+    pub fn auto_analyze_threshold(mut self, rows: u64) -> Self {
+        self.execution.auto_analyze_threshold = rows;
+        self
+    }
+
+    pub fn distinct_sketches(mut self, enabled: bool) -> Self {
+        self.execution.distinct_sketches = enabled;
+        self
+    }
+
+    pub fn hide_expired_rows(mut self, hide: bool) -> Self {
+        self.execution.hide_expired_rows = hide;
+        self
+    }
+
+    pub fn index_rebuild_threshold(mut self, percent: u32) -> Self {
+        self.execution.index_rebuild_threshold = percent;
+        self
+    }
+
+    pub fn index_scan_revalidate_interval(mut self, entries: usize) -> Self {
+        self.execution.index_scan_revalidate_interval = entries;
+        self
+    }
+
+    pub fn sort_memory_budget(mut self, bytes: usize) -> Self {
+        self.execution.sort_memory_budget = bytes;
+        self
+    }
+
+    pub fn deterministic_order(mut self, deterministic: bool) -> Self {
+        self.execution.deterministic_order = deterministic;
+        self
+    }
+
+    pub fn plan_cache_capacity(mut self, capacity: usize) -> Self {
+        self.execution.plan_cache_capacity = capacity;
+        self
+    }
+
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.execution.lock_timeout = timeout;
+        self
+    }
+
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.execution.statement_timeout = timeout;
+        self
+    }
+
+    pub fn idle_transaction_timeout(mut self, timeout: Duration) -> Self {
+        self.execution.idle_transaction_timeout = timeout;
+        self
+    }
+
+    pub fn trace_page_accesses(mut self, trace: bool) -> Self {
+        self.execution.trace_page_accesses = trace;
+        self
+    }
+
+    pub fn continue_script_on_error(mut self, continue_on_error: bool) -> Self {
+        self.execution.continue_script_on_error = continue_on_error;
+        self
+    }
+
+    pub fn batch_execution(mut self, enabled: bool) -> Self {
+        self.execution.batch_execution = enabled;
+        self
+    }
+
+    pub fn batch_size(mut self, rows: usize) -> Self {
+        self.execution.batch_size = rows;
+        self
+    }
+
+    pub fn parallel_scan_workers(mut self, workers: usize) -> Self {
+        self.execution.parallel_scan_workers = workers;
+        self
+    }
+
+    pub fn parallel_scan_min_pages(mut self, pages: usize) -> Self {
+        self.execution.parallel_scan_min_pages = pages;
+        self
+    }
+
+    pub fn transaction_max_retries(mut self, retries: usize) -> Self {
+        self.execution.transaction_max_retries = retries;
+        self
+    }
+
+    pub fn search_path(mut self, schemas: Vec<String>) -> Self {
+        self.execution.search_path = schemas;
+        self
+    }
+
+    pub fn validate(&self) -> BustubxResult<()> {
+        if self.buffer_pool.pool_size < MIN_BUFFER_POOL_SIZE {
+            return Err(BustubxError::Config(format!(
+                "buffer pool size {} is less than {}",
+                self.buffer_pool.pool_size, MIN_BUFFER_POOL_SIZE
+            )));
+        }
+        if self.buffer_pool.replacer_k == 0 {
+            return Err(BustubxError::Config(
+                "replacer k must be greater than 0".to_string(),
+            ));
+        }
+        if self.buffer_pool.background_flush_interval == Some(Duration::ZERO) {
+            return Err(BustubxError::Config(
+                "background flush interval must be greater than 0".to_string(),
+            ));
+        }
+        if !self.disk.page_size.is_power_of_two() {
+            return Err(BustubxError::Config(format!(
+                "page size {} is not a power of two",
+                self.disk.page_size
+            )));
+        }
+        if self.execution.aggregate_memory_budget == 0 {
+            return Err(BustubxError::Config(
+                "aggregate memory budget must be greater than 0".to_string(),
+            ));
+        }
+        if self.execution.sort_memory_budget == 0 {
+            return Err(BustubxError::Config(
+                "sort memory budget must be greater than 0".to_string(),
+            ));
+        }
+        if self.execution.analyze_sample_size == 0 {
+            return Err(BustubxError::Config(
+                "analyze sample size must be greater than 0".to_string(),
+            ));
+        }
+        if self.execution.batch_size == 0 {
+            return Err(BustubxError::Config(
+                "batch size must be greater than 0".to_string(),
+            ));
+        }
+        if self.execution.index_rebuild_threshold > 100 {
+            return Err(BustubxError::Config(format!(
+                "index rebuild threshold {}% is greater than 100%",
+                self.execution.index_rebuild_threshold
+            )));
+        }
+        // Page layouts are still sized at compile time
+        if self.disk.page_size != BUSTUBX_PAGE_SIZE {
+            return Err(BustubxError::Config(format!(
+                "page size {} is not supported, only {} is supported",
+                self.disk.page_size, BUSTUBX_PAGE_SIZE
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Value of a timeout setting such as `'5s'`, `'250ms'` or `'1min'`, a number without
+/// unit counts milliseconds as in PostgreSQL.
+pub(crate) fn parse_timeout(value: &str) -> BustubxResult<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let invalid = || BustubxError::Config(format!("invalid timeout {value}"));
+    let number = number.parse::<f64>().map_err(|_| invalid())?;
+    let unit_millis = match unit.trim() {
+        "" | "ms" => 1.0,
+        "s" => 1000.0,
+        "min" => 60_000.0,
+        "h" => 3_600_000.0,
+        "d" => 86_400_000.0,
+        _ => return Err(invalid()),
+    };
+    Duration::try_from_secs_f64(number * unit_millis / 1000.0).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::{parse_timeout, DatabaseOptions, SyncPolicy, MIN_BUFFER_POOL_SIZE};
+    use crate::{BustubxError, Database};
+
+    #[test]
+    pub fn test_options_validate() {
+        assert!(DatabaseOptions::default().validate().is_ok());
+        assert!(matches!(
+            DatabaseOptions::new()
+                .buffer_pool_size(MIN_BUFFER_POOL_SIZE - 1)
+                .validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().replacer_k(0).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().page_size(5000).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().page_size(8192).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().aggregate_memory_budget(0).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().sort_memory_budget(0).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new().batch_size(0).validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(matches!(
+            DatabaseOptions::new()
+                .index_rebuild_threshold(101)
+                .validate(),
+            Err(BustubxError::Config(_))
+        ));
+        assert!(
+            Database::new_temp_with_options(DatabaseOptions::new().buffer_pool_size(1)).is_err()
+        );
+    }
+
+    #[test]
+    pub fn test_options_take_effect() {
+        let options = DatabaseOptions::new().buffer_pool_size(64);
+        let mut db = Database::new_temp_with_options(options.clone()).unwrap();
+        assert_eq!(db.options(), &options);
+        assert_eq!(db.buffer_pool.pool_size(), 64);
+
+        db.run("create table t1 (a int)").unwrap();
+        db.run("insert into t1 values (1)").unwrap();
+        db.flush().unwrap();
+        assert_eq!(db.buffer_pool.disk_manager.sync_count(), 0);
+
+        let mut db = Database::new_temp_with_options(
+            DatabaseOptions::new().sync_policy(SyncPolicy::OnFlush),
+        )
+        .unwrap();
+        // opening syncs the cleared clean shutdown marker
+        let sync_count = db.buffer_pool.disk_manager.sync_count();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("insert into t1 values (1)").unwrap();
+        assert_eq!(db.buffer_pool.disk_manager.sync_count(), sync_count);
+        db.flush().unwrap();
+        assert_eq!(db.buffer_pool.disk_manager.sync_count(), sync_count + 1);
+
+        let mut db =
+            Database::new_temp_with_options(DatabaseOptions::new().sync_policy(SyncPolicy::Always))
+                .unwrap();
+        let sync_count = db.buffer_pool.disk_manager.sync_count();
+        assert!(sync_count > 0);
+        db.run("create table t1 (a int)").unwrap();
+        db.flush().unwrap();
+        assert!(db.buffer_pool.disk_manager.sync_count() > sync_count);
+    }
+
+    #[test]
+    pub fn test_parse_timeout() {
+        assert_eq!(parse_timeout("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_timeout("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_timeout("1500").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_timeout(" 2 min").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_timeout("0.5h").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_timeout("0").unwrap(), Duration::ZERO);
+        for value in ["", "s", "5 weeks", "-1s", "1e400"] {
+            assert!(
+                matches!(parse_timeout(value), Err(BustubxError::Config(_))),
+                "{value}"
+            );
+        }
+    }
+}
@@ -1,122 +1,2050 @@
-use log::debug;
+use log::{debug, warn};
+use sqlparser::ast::{AnalyzeFormat, DiscardObject, Expr, Ident, ObjectName, Statement};
+use sqlparser::parser::ParserError;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 
-use crate::buffer::BUFFER_POOL_SIZE;
-use crate::catalog::load_catalog_data;
+use crate::background::{BackgroundTaskRegistry, TaskId, TaskStatus};
+use crate::capture::{CaptureSource, StatementCapture, DATABASE_SESSION_ID};
+use crate::catalog::{
+    dump_catalog, load_catalog_data, resolve_index_keys, AnalyzedTable, AutoAnalyzer, PageOwner,
+    TableAccessCounts, TableSize, DEFAULT_HISTOGRAM_BUCKETS, DEFAULT_SCHEMA_NAME,
+    EXPLAIN_OUTPUT_SCHEMA_REF, SHOW_ALL_OUTPUT_SCHEMA_REF, SHOW_INDEX_TREE_OUTPUT_SCHEMA_REF,
+    SHOW_SETTING_OUTPUT_SCHEMA_REF, SHOW_STATS_OUTPUT_SCHEMA_REF,
+    SHOW_TRANSACTIONS_OUTPUT_SCHEMA_REF,
+};
+use crate::common::index_tree::{pretty_format_index_tree_with_limits, MAX_PAGES_PER_LEVEL};
 use crate::common::util::{pretty_format_logical_plan, pretty_format_physical_plan};
+use crate::common::{ClockRef, SystemClock, TableReference};
+use crate::config::{DatabaseOptions, ExecutionOptions};
 use crate::error::{BustubxError, BustubxResult};
-use crate::optimizer::LogicalOptimizer;
+use crate::function::{FunctionSignature, ScalarUdf};
+use crate::optimizer::{LogicalOptimizer, OptimizerTrace};
+use crate::parser::{
+    AlterTableAccess, DeferrableIndex, DeferrableTable, MaintenanceStatement, OrderedDml,
+    PartitionStatement,
+};
 use crate::planner::logical_plan::LogicalPlan;
-use crate::planner::PhysicalPlanner;
+use crate::planner::{PhysicalPlanner, PlanCache, PlanCacheStats};
+use crate::settings::{SessionSettings, Setting, SettingScope, SettingValue};
+use crate::stats::RuntimeStats;
 use crate::{
-    buffer::BufferPoolManager,
+    buffer::{start_flusher, BufferPoolManager, PageAccess, PageId},
     catalog::Catalog,
-    execution::{ExecutionContext, ExecutionEngine},
+    execution::{
+        check_deferrable_keys, check_foreign_keys, check_not_in_trigger,
+        physical_plan::PhysicalPlan, ExecutionContext, ExecutionEngine, PlanTree,
+        TransactionWrites, TriggerEvent, TriggerFn, UndoRecord,
+    },
     planner::{LogicalPlanner, PlannerContext},
-    storage::{DiskManager, Tuple},
+    storage::{
+        backup_label_path, index::ConstraintTiming, write_timeline, BackupLabel, DiskManager,
+        LogArchive, LogManager, LogSegment, LogShipper, Lsn, RecoveryTarget, Tuple, FIRST_LSN,
+    },
+    transaction::{
+        IsolationLevel, LockManager, SequentialTransactionIds, TableLockMode, TransactionId,
+        TransactionIdSourceRef, TransactionInfo, TransactionManager, INVALID_TRANSACTION_ID,
+    },
 };
 
+/// Result of one statement of a script, see [`Database::execute_script`].
+#[derive(Debug)]
+pub struct StatementOutcome {
+    // the statement as written in the script, without the semicolon
+    pub sql: String,
+    // byte offset of the statement into the script
+    pub offset: usize,
+    pub result: BustubxResult<Vec<Tuple>>,
+}
+
 pub struct Database {
     disk_manager: Arc<DiskManager>,
     pub(crate) buffer_pool: Arc<BufferPoolManager>,
+    // the threads of the database, stopped before the buffer pool and the db file go away
+    background: BackgroundTaskRegistry,
+    // task writing dirty pages of the buffer pool back in the background, if configured
+    flusher: Option<TaskId>,
     pub(crate) catalog: Catalog,
+    // ANALYZE of the tables written `auto_analyze_threshold` rows since their last one
+    auto_analyzer: AutoAnalyzer,
+    options: DatabaseOptions,
+    pub(crate) clock: ClockRef,
+    pub(crate) txn_manager: Arc<TransactionManager>,
+    plan_cache: PlanCache,
+    lock_manager: Arc<LockManager>,
+    // lsn of the next log record to apply while the database is a read-only standby
+    replica_next_lsn: Option<Lsn>,
     temp_dir: Option<TempDir>,
+    // the db file was not closed cleanly and its indexes were rebuilt when opening it
+    recovered_on_open: bool,
+    // pages of the latest statement run with `trace_page_accesses`
+    last_page_trace: Option<Vec<PageAccess>>,
+    // file the statements of the database and its sessions are appended to
+    pub(crate) capture: Option<StatementCapture>,
+    closed: bool,
 }
 impl Database {
     pub fn new_on_disk(db_path: &str) -> BustubxResult<Self> {
-        let disk_manager = Arc::new(DiskManager::try_new(db_path)?);
-        let buffer_pool = Arc::new(BufferPoolManager::new(
-            BUFFER_POOL_SIZE,
-            disk_manager.clone(),
-        ));
+        Self::open_with_options(db_path, DatabaseOptions::default())
+    }
 
-        let catalog = Catalog::new(buffer_pool.clone());
+    pub fn open_with_options(
+        db_path: impl AsRef<Path>,
+        options: DatabaseOptions,
+    ) -> BustubxResult<Self> {
+        Self::open_with_clock(db_path, options, Arc::new(SystemClock))
+    }
 
-        let mut db = Self {
-            disk_manager,
-            buffer_pool,
-            catalog,
-            temp_dir: None,
-        };
-        load_catalog_data(&mut db)?;
+    /// Open with `clock` as the time source of `now()`.
+    pub fn open_with_clock(
+        db_path: impl AsRef<Path>,
+        options: DatabaseOptions,
+        clock: ClockRef,
+    ) -> BustubxResult<Self> {
+        options.validate()?;
+        Self::open_internal(db_path.as_ref(), options, clock, None)
+    }
+
+    /// Open a read-only standby that applies the log shipped from a primary,
+    /// see [`Database::apply_received`] and [`Database::promote`].
+    pub fn open_as_replica(db_path: impl AsRef<Path>) -> BustubxResult<Self> {
+        let mut db = Self::open_with_options(db_path, DatabaseOptions::default())?;
+        db.replica_next_lsn = Some(FIRST_LSN);
         Ok(db)
     }
 
     pub fn new_temp() -> BustubxResult<Self> {
+        Self::new_temp_with_options(DatabaseOptions::default())
+    }
+
+    pub fn new_temp_with_options(options: DatabaseOptions) -> BustubxResult<Self> {
+        Self::new_temp_with_clock(options, Arc::new(SystemClock))
+    }
+
+    pub fn new_temp_with_clock(options: DatabaseOptions, clock: ClockRef) -> BustubxResult<Self> {
+        options.validate()?;
         let temp_dir = TempDir::new()?;
         let temp_path = temp_dir.path().join("test.db");
-        let disk_manager =
-            Arc::new(DiskManager::try_new(temp_path.to_str().ok_or(
-                BustubxError::Internal("Invalid temp path".to_string()),
-            )?)?);
-        let buffer_pool = Arc::new(BufferPoolManager::new(
-            BUFFER_POOL_SIZE,
+        Self::open_internal(&temp_path, options, clock, Some(temp_dir))
+    }
+
+    fn open_internal(
+        db_path: &Path,
+        options: DatabaseOptions,
+        clock: ClockRef,
+        temp_dir: Option<TempDir>,
+    ) -> BustubxResult<Self> {
+        let disk_manager = Arc::new(DiskManager::try_new_with_options(
+            db_path,
+            options.disk.clone(),
+        )?);
+        let clean_shutdown = disk_manager.meta.read().unwrap().clean_shutdown;
+        // cleared before any page is written, a crash from here on leaves it unclean
+        disk_manager.set_clean_shutdown(false)?;
+        let buffer_pool = Arc::new(BufferPoolManager::new_with_options(
+            options.buffer_pool.clone(),
             disk_manager.clone(),
         ));
 
         let catalog = Catalog::new(buffer_pool.clone());
+        let plan_cache = PlanCache::new(options.execution.plan_cache_capacity);
 
+        let mut background = BackgroundTaskRegistry::default();
+        let flusher = start_configured_flusher(&mut background, &buffer_pool, &options)?;
         let mut db = Self {
             disk_manager,
+            background,
+            flusher,
             buffer_pool,
             catalog,
-            temp_dir: Some(temp_dir),
+            auto_analyzer: AutoAnalyzer::default(),
+            options,
+            txn_manager: Arc::new(TransactionManager::new(
+                clock.clone(),
+                Arc::new(SequentialTransactionIds::default()),
+            )),
+            clock,
+            plan_cache,
+            lock_manager: Arc::new(LockManager::new()),
+            replica_next_lsn: None,
+            temp_dir,
+            recovered_on_open: false,
+            last_page_trace: None,
+            capture: None,
+            closed: false,
         };
         load_catalog_data(&mut db)?;
+        // counters are only a hint, a missing or unreadable stats file leaves them at zero
+        if let Err(e) = db.catalog.load_access_stats() {
+            warn!(
+                "failed to load the access stats of {}: {}",
+                db_path.display(),
+                e
+            );
+        }
+        if !clean_shutdown {
+            warn!(
+                "{} was not closed cleanly, rebuilding its indexes",
+                db_path.display()
+            );
+            let tables = db.catalog.recover_indexes()?;
+            db.catalog.persist_index_roots()?;
+            db.buffer_pool.flush_all_pages()?.into_result()?;
+            debug!("recovered the indexes of {} tables", tables);
+            db.recovered_on_open = true;
+        }
         Ok(db)
     }
 
+    /// Stop the background tasks, flush every dirty page, then mark the db file as cleanly
+    /// shut down and fsync it, so the next open skips recovery. Every statement commits or
+    /// rolls back before [`Database::run`] returns, no transaction is left open to roll
+    /// back. A background task not stopping in time fails the close and leaves the db file
+    /// to recovery.
+    pub fn close(mut self) -> BustubxResult<()> {
+        self.shutdown()
+    }
+
+    // `close` of a database others may still hold, later statements fail
+    pub(crate) fn shutdown(&mut self) -> BustubxResult<()> {
+        self.check_open()?;
+        self.wait_for_auto_analyze()?;
+        if let Err(e) = self.catalog.save_access_stats() {
+            warn!("failed to save the access stats: {}", e);
+        }
+        self.flusher = None;
+        self.background.shutdown()?;
+        self.buffer_pool.flush_all_pages()?.into_result()?;
+        self.disk_manager.log_commit(self.clock.now())?;
+        self.disk_manager.set_clean_shutdown(true)?;
+        self.closed = true;
+        Ok(())
+    }
+
+    /// The background tasks of the database and whether they run, finished or panicked.
+    pub fn background_tasks(&self) -> Vec<TaskStatus> {
+        self.background.health()
+    }
+
+    /// Whether the db file was not closed cleanly, so opening it ran recovery.
+    pub fn recovered_on_open(&self) -> bool {
+        self.recovered_on_open
+    }
+
+    /// Options the database was opened with
+    pub fn options(&self) -> &DatabaseOptions {
+        &self.options
+    }
+
     pub fn run(&mut self, sql: &str) -> BustubxResult<Vec<Tuple>> {
-        let logical_plan = self.create_logical_plan(sql)?;
-        debug!(
-            "Logical Plan: \n{}",
-            pretty_format_logical_plan(&logical_plan)
-        );
+        self.run_captured(sql, None, &mut CaptureSource::new(DATABASE_SESSION_ID))
+    }
 
-        let optimized_logical_plan = LogicalOptimizer::new().optimize(&logical_plan)?;
-        debug!(
-            "Optimized Logical Plan: \n{}",
-            pretty_format_logical_plan(&logical_plan)
+    // `run` for a statement of a session, whose session scoped settings apply over the
+    // ones of the database
+    pub(crate) fn run_in_session(
+        &mut self,
+        sql: &str,
+        session: Option<&mut SessionSettings>,
+    ) -> BustubxResult<Vec<Tuple>> {
+        self.run_statement(sql, session, None)
+    }
+
+    // `run_in_session` for a statement of transaction `txn_id` run by
+    // `Session::run_transaction`, the changes of the statement are added to `transaction`
+    // once it succeeded
+    pub(crate) fn run_in_transaction(
+        &mut self,
+        sql: &str,
+        session: Option<&mut SessionSettings>,
+        txn_id: TransactionId,
+        transaction: &mut TransactionWrites,
+    ) -> BustubxResult<Vec<Tuple>> {
+        let idle_timeout = self
+            .statement_options(session.as_deref())
+            .idle_transaction_timeout;
+        self.txn_manager.check_idle(txn_id, idle_timeout)?;
+        let undo_len = transaction.undo_log.len();
+        let result = self.run_statement(sql, session, Some(transaction));
+        let written = &transaction.undo_log[undo_len..];
+        self.txn_manager.statement_done(
+            txn_id,
+            written
+                .iter()
+                .filter(|record| record.is_row_change())
+                .count() as u64,
+            written.iter().map(|record| record.table().clone()),
         );
+        result
+    }
+
+    /// Check the keys the statements of a transaction inserted into deferrable unique
+    /// indexes and the rows they wrote against the foreign keys before it commits, a key
+    /// more than one row holds or a row breaking a foreign key fails the transaction.
+    pub(crate) fn check_deferred_keys(&self, transaction: &TransactionWrites) -> BustubxResult<()> {
+        check_deferrable_keys(&self.catalog, &transaction.undo_log, |_| true)?;
+        check_foreign_keys(&self.catalog, &transaction.undo_log, |_| true)
+    }
+
+    /// Release the row locks of a transaction that committed or rolled back.
+    pub(crate) fn end_transaction(&self, txn_id: TransactionId) {
+        self.lock_manager.unlock_rows(txn_id);
+        self.txn_manager.end(txn_id);
+    }
+
+    /// Undo the changes of the statements of a failed transaction, latest first.
+    pub(crate) fn rollback_transaction(
+        &mut self,
+        undo_log: Vec<UndoRecord>,
+        session: Option<&SessionSettings>,
+    ) -> BustubxResult<()> {
+        if undo_log.is_empty() {
+            return Ok(());
+        }
+        let options = self.statement_options(session);
+        let mut context = self.statement_context(&options);
+        context.undo_log = undo_log;
+        context.rollback_statement()?;
+        self.catalog.persist_index_roots()
+    }
+
+    fn run_statement(
+        &mut self,
+        sql: &str,
+        session: Option<&mut SessionSettings>,
+        transaction: Option<&mut TransactionWrites>,
+    ) -> BustubxResult<Vec<Tuple>> {
+        let traced = self
+            .statement_options(session.as_deref())
+            .trace_page_accesses;
+        if !traced {
+            return self.dispatch_statement(sql, session, transaction);
+        }
+        self.buffer_pool.start_trace();
+        let result = self.dispatch_statement(sql, session, transaction);
+        self.last_page_trace = Some(self.buffer_pool.take_trace());
+        result
+    }
+
+    fn dispatch_statement(
+        &mut self,
+        sql: &str,
+        session: Option<&mut SessionSettings>,
+        transaction: Option<&mut TransactionWrites>,
+    ) -> BustubxResult<Vec<Tuple>> {
+        self.check_open()?;
+        check_not_in_trigger()?;
+        let analyzed = self.auto_analyzer.finished();
+        self.install_auto_analyzed(analyzed);
+        let mut options = self.statement_options(session.as_deref());
+        let traced = crate::parser::parse_explain_trace(sql);
+        if traced.is_some() {
+            options.optimizer_trace = true;
+        }
+        let sql = traced.as_deref().unwrap_or(sql);
+        match crate::parser::parse_maintenance_statement(sql)? {
+            Some(MaintenanceStatement::ResetStats) => {
+                self.reset_runtime_stats();
+                return Ok(vec![]);
+            }
+            Some(stmt) => return self.run_maintenance(&stmt, &options),
+            None => {}
+        }
+        if let Some(stmt) = crate::parser::parse_partition_statement(sql)? {
+            return self.run_partition_statement(&stmt, &options);
+        }
+        if let Some(stmt) = crate::parser::parse_alter_table_access(sql)? {
+            return self.run_alter_table_access(&stmt, &options);
+        }
+        if let Some(timing) = crate::parser::parse_set_constraints(sql)? {
+            let Some(transaction) = transaction else {
+                return Err(BustubxError::Plan(
+                    "SET CONSTRAINTS outside Session::run_transaction, a statement run on its \
+                     own checks its keys when it ends"
+                        .to_string(),
+                ));
+            };
+            // keys deferred so far are checked once they are not anymore
+            if timing == ConstraintTiming::Immediate {
+                self.check_deferred_keys(transaction)?;
+            }
+            transaction.constraints = Some(timing);
+            return Ok(vec![]);
+        }
+        if let Some(dml) = crate::parser::parse_ordered_dml(sql)? {
+            self.check_writable()?;
+            check_latest_snapshot(&options)?;
+            let physical_plan = self.create_ordered_dml_plan(&dml, &options)?;
+            return self.execute_plan(physical_plan, &options, false, transaction);
+        }
+        if let Some(index) = crate::parser::parse_deferrable_index(sql)? {
+            self.check_writable()?;
+            check_latest_snapshot(&options)?;
+            let physical_plan = self.create_deferrable_index_plan(&index, &options)?;
+            return self.execute_plan(physical_plan, &options, false, transaction);
+        }
+        if let Some(table) = crate::parser::parse_deferrable_table(sql)? {
+            self.check_writable()?;
+            check_latest_snapshot(&options)?;
+            let physical_plan = self.create_deferrable_table_plan(&table, &options)?;
+            return self.execute_plan(physical_plan, &options, false, transaction);
+        }
+        let as_of = crate::parser::parse_as_of(sql)?;
+        if let Some(as_of) = &as_of {
+            options.snapshot_txn = as_of.txn_id;
+        }
+        let stmt = parse_single_statement(as_of.as_ref().map_or(sql, |as_of| &as_of.sql))?;
+        if transaction.is_some()
+            && matches!(
+                stmt,
+                Statement::StartTransaction { .. }
+                    | Statement::Commit { .. }
+                    | Statement::Rollback { .. }
+                    | Statement::Savepoint { .. }
+            )
+        {
+            return Err(BustubxError::Plan(format!(
+                "{} inside Session::run_transaction, the transaction ends with the closure",
+                stmt
+            )));
+        }
+        if let Statement::Discard {
+            object_type: DiscardObject::PLANS | DiscardObject::ALL,
+        } = &stmt
+        {
+            self.plan_cache.clear();
+            return Ok(vec![]);
+        }
+        if let Statement::SetVariable {
+            variable, value, ..
+        } = &stmt
+        {
+            self.set_variable(variable, value, session)?;
+            return Ok(vec![]);
+        }
+        if let Statement::ShowVariable { variable } = &stmt {
+            return self.show_variable(variable, &options);
+        }
+        if let Statement::Explain {
+            analyze,
+            statement,
+            format,
+            ..
+        } = &stmt
+        {
+            let plan_tree = self.explain_statement(statement, *analyze, &options)?;
+            return explain_output(&plan_tree, format);
+        }
+
+        let read_only = is_read_only(&stmt);
+        if !read_only {
+            self.check_writable()?;
+            check_latest_snapshot(&options)?;
+        }
+        let physical_plan = self.create_physical_plan(&stmt, &options, None)?;
+        self.execute_plan(physical_plan, &options, read_only, transaction)
+    }
+
+    fn execute_plan(
+        &mut self,
+        physical_plan: PhysicalPlan,
+        options: &ExecutionOptions,
+        read_only: bool,
+        transaction: Option<&mut TransactionWrites>,
+    ) -> BustubxResult<Vec<Tuple>> {
+        let snapshot = match options.snapshot_txn {
+            INVALID_TRANSACTION_ID => None,
+            txn_id => Some(self.txn_manager.snapshot(txn_id)?),
+        };
+        // an auto analyze of a table dropped or rewritten would hold up the statement
+        for (table, mode) in physical_plan.table_locks() {
+            if mode == TableLockMode::AccessExclusive {
+                self.auto_analyzer.cancel(&table);
+            }
+        }
+        let mut execution_ctx = self.statement_context(options);
+        execution_ctx.snapshot = snapshot;
+        // a statement run on its own commits when it ends, so do its deferred keys
+        execution_ctx.constraints = match &transaction {
+            Some(transaction) => transaction.constraints,
+            None => Some(ConstraintTiming::Immediate),
+        };
+        let txn_id = execution_ctx.txn_id;
+        execution_ctx.row_lock_txn_id = transaction.as_ref().map_or(txn_id, |t| t.txn_id);
+        let mut execution_engine = ExecutionEngine {
+            context: execution_ctx,
+        };
+        let result = execution_engine.execute(Arc::new(physical_plan));
+        // a failed statement left nothing to undo, one run on its own committed
+        let changes = std::mem::take(&mut execution_engine.context.undo_log);
+        match transaction {
+            Some(transaction) => transaction.undo_log.extend(changes),
+            None => {
+                self.txn_manager.commit_changes(txn_id, &changes);
+                self.lock_manager.unlock_rows(txn_id);
+            }
+        }
+        // a failed statement was rolled back, which may have rebuilt indexes
+        if !read_only {
+            self.catalog.persist_index_roots()?;
+            self.start_auto_analyze(options)?;
+        }
+        result
+    }
+
+    // ANALYZE in the background every table written enough rows since its last one
+    fn start_auto_analyze(&mut self, options: &ExecutionOptions) -> BustubxResult<()> {
+        if options.auto_analyze_threshold == 0 {
+            return Ok(());
+        }
+        for table in self
+            .catalog
+            .tables_to_analyze(options.auto_analyze_threshold)
+        {
+            // held by the job until it is done, a table locked by another transaction is
+            // analyzed after its next write instead
+            let txn = self.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+            let table_locks = match self.lock_manager.lock_tables(
+                txn.txn_id,
+                vec![(table.clone(), TableLockMode::AccessShare)],
+                Duration::ZERO,
+            ) {
+                Ok(table_locks) => table_locks,
+                Err(BustubxError::RelationLocked { .. }) => continue,
+                Err(e) => return Err(e),
+            };
+            let job = self
+                .catalog
+                .analyze_job(
+                    &table,
+                    DEFAULT_HISTOGRAM_BUCKETS,
+                    options.analyze_sample_size,
+                    options.distinct_sketches,
+                )?
+                .with_table_locks(table_locks);
+            self.auto_analyzer.start(&mut self.background, job)?;
+        }
+        Ok(())
+    }
+
+    fn install_auto_analyzed(&mut self, analyzed: Vec<BustubxResult<AnalyzedTable>>) {
+        for result in analyzed {
+            // the table is analyzed again once written more
+            let installed = result.and_then(|analyzed| self.catalog.install_analyzed(analyzed));
+            if let Err(e) = installed {
+                warn!("auto analyze failed: {}", e);
+            }
+        }
+    }
+
+    /// Wait for the ANALYZE runs started by `auto_analyze_threshold` and install their
+    /// statistics, which the next statement does for the runs done by then.
+    pub fn wait_for_auto_analyze(&mut self) -> BustubxResult<()> {
+        let analyzed = self.auto_analyzer.wait(&mut self.background);
+        self.install_auto_analyzed(analyzed);
+        Ok(())
+    }
+
+    /// Run the semicolon separated statements of `sql` in order, one outcome per statement
+    /// run. Stops after the first failing statement unless
+    /// [`DatabaseOptions::continue_script_on_error`] is set. Parser errors carry their
+    /// position in the whole script.
+    pub fn execute_script(&mut self, sql: &str) -> Vec<StatementOutcome> {
+        self.execute_script_in_session(sql, None, &mut CaptureSource::new(DATABASE_SESSION_ID))
+    }
+
+    pub(crate) fn execute_script_in_session(
+        &mut self,
+        sql: &str,
+        mut session: Option<&mut SessionSettings>,
+        source: &mut CaptureSource,
+    ) -> Vec<StatementOutcome> {
+        let mut outcomes = vec![];
+        for (offset, statement) in crate::parser::split_statements(sql) {
+            let result = self
+                .run_captured(statement, session.as_deref_mut(), source)
+                .map_err(|e| match e {
+                    BustubxError::Parser(e) => locate_parser_error(sql, offset, statement, e),
+                    e => e,
+                });
+            let failed = result.is_err();
+            outcomes.push(StatementOutcome {
+                sql: statement.trim().to_string(),
+                offset,
+                result,
+            });
+            // a statement of the script may have changed the setting
+            let options = self.statement_options(session.as_deref());
+            if failed && !options.continue_script_on_error {
+                break;
+            }
+        }
+        outcomes
+    }
+
+    /// Physical plan of a query as a tree, `EXPLAIN ANALYZE <query>` executes the query
+    /// to fill in the rows every operator produced.
+    pub fn explain(&mut self, sql: &str) -> BustubxResult<PlanTree> {
+        self.check_open()?;
+        let mut options = self.statement_options(None);
+        let traced = crate::parser::parse_explain_trace(sql);
+        if traced.is_some() {
+            options.optimizer_trace = true;
+        }
+        let sql = traced.as_deref().unwrap_or(sql);
+        if let Some(dml) = crate::parser::parse_ordered_dml(sql)? {
+            let physical_plan = self.create_ordered_dml_plan(&dml, &options)?;
+            return Ok(PlanTree::new(&physical_plan, &self.catalog, None));
+        }
+        match parse_single_statement(sql)? {
+            Statement::Explain {
+                analyze, statement, ..
+            } => self.explain_statement(&statement, analyze, &options),
+            stmt => self.explain_statement(&stmt, false, &options),
+        }
+    }
+
+    fn explain_statement(
+        &mut self,
+        stmt: &Statement,
+        analyze: bool,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<PlanTree> {
+        let trace = options.optimizer_trace.then(OptimizerTrace::default);
+        let physical_plan = Arc::new(self.create_physical_plan(stmt, options, trace.as_ref())?);
+        let operator_rows = if analyze {
+            let read_only = is_read_only(stmt);
+            if !read_only {
+                self.check_writable()?;
+            }
+
+            let mut execution_ctx = self.statement_context(options);
+            execution_ctx.operator_rows = Some(HashMap::new());
+            let mut execution_engine = ExecutionEngine {
+                context: execution_ctx,
+            };
+            let result = execution_engine.execute(physical_plan.clone());
+            let operator_rows = execution_engine
+                .context
+                .operator_rows
+                .take()
+                .unwrap_or_default();
+            if !read_only {
+                self.catalog.persist_index_roots()?;
+            }
+            result?;
+            Some(operator_rows)
+        } else {
+            None
+        };
+        let mut plan_tree = PlanTree::new(&physical_plan, &self.catalog, operator_rows.as_ref());
+        plan_tree.trace = trace.map(OptimizerTrace::into_entries).unwrap_or_default();
+        Ok(plan_tree)
+    }
+
+    // every statement locks its tables under a transaction id of its own
+    // VACUUM, ANALYZE and CHECKPOINT, outside the plan cache since their plans list the
+    // tables existing when they run
+    fn run_maintenance(
+        &mut self,
+        stmt: &MaintenanceStatement,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        // CHECK TABLE only reads
+        if !matches!(stmt, MaintenanceStatement::CheckTable { .. }) {
+            self.check_writable()?;
+        }
+        let planner = LogicalPlanner {
+            context: PlannerContext {
+                catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path: options.search_path.clone(),
+            },
+        };
+        let logical_plan = planner.plan_maintenance(stmt)?;
+        self.execute_unoptimized(logical_plan, options)
+    }
+
+    // CREATE TABLE of a partitioned table or a partition, which the SQL parser has no
+    // grammar for
+    fn run_partition_statement(
+        &mut self,
+        stmt: &PartitionStatement,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        self.check_writable()?;
+        let planner = LogicalPlanner {
+            context: PlannerContext {
+                catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path: options.search_path.clone(),
+            },
+        };
+        let logical_plan = planner.plan_partition_statement(stmt)?;
+        self.execute_unoptimized(logical_plan, options)
+    }
+
+    // ALTER TABLE ... SET read_only or SET read_write, a change of the catalog alone
+    fn run_alter_table_access(
+        &mut self,
+        stmt: &AlterTableAccess,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        self.check_writable()?;
+        let planner = LogicalPlanner {
+            context: PlannerContext {
+                catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path: options.search_path.clone(),
+            },
+        };
+        let table = planner.bind_table_name(&stmt.table)?;
+        self.catalog.set_table_read_only(&table, stmt.read_only)?;
+        Ok(vec![])
+    }
+
+    // execute a plan of a statement that writes, without the optimizer and plan cache
+    fn execute_unoptimized(
+        &mut self,
+        logical_plan: LogicalPlan,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        let physical_planner = PhysicalPlanner {
+            catalog: &self.catalog,
+            options,
+            trace: None,
+        };
+        let physical_plan = physical_planner.create_physical_plan(logical_plan)?;
+        let mut execution_engine = ExecutionEngine {
+            context: self.statement_context(options),
+        };
+        let result = execution_engine.execute(Arc::new(physical_plan));
+        self.catalog.persist_index_roots()?;
+        result
+    }
+
+    fn statement_context<'a>(&'a mut self, options: &'a ExecutionOptions) -> ExecutionContext<'a> {
+        let txn = self.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        let mut context = ExecutionContext::new(&mut self.catalog, options, self.clock.clone());
+        context.lock_manager = Some(self.lock_manager.clone());
+        context.txn_manager = Some(self.txn_manager.clone());
+        context.txn_id = txn.txn_id;
+        context
+    }
+
+    fn create_physical_plan(
+        &mut self,
+        stmt: &Statement,
+        options: &ExecutionOptions,
+        trace: Option<&OptimizerTrace>,
+    ) -> BustubxResult<PhysicalPlan> {
+        let optimized_logical_plan = self.optimized_logical_plan(stmt, options, trace)?;
 
         // logical plan -> physical plan
         let physical_planner = PhysicalPlanner {
             catalog: &self.catalog,
+            options,
+            trace,
         };
-        let physical_plan = physical_planner.create_physical_plan(optimized_logical_plan);
+        let physical_plan = physical_planner.create_physical_plan(optimized_logical_plan)?;
         debug!(
             "Physical Plan: \n{}",
             pretty_format_physical_plan(&physical_plan)
         );
+        Ok(physical_plan)
+    }
 
-        let execution_ctx = ExecutionContext::new(&mut self.catalog);
-        let mut execution_engine = ExecutionEngine {
-            context: execution_ctx,
+    // DELETE and UPDATE with ORDER BY or LIMIT, outside the plan cache since the cache
+    // keys are printed statements which lack the clauses
+    fn create_ordered_dml_plan(
+        &self,
+        dml: &OrderedDml,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<PhysicalPlan> {
+        self.create_plan_with(options, |planner| planner.plan_ordered_dml(dml))
+    }
+
+    fn create_deferrable_index_plan(
+        &self,
+        index: &DeferrableIndex,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<PhysicalPlan> {
+        self.create_plan_with(options, |planner| planner.plan_deferrable_index(index))
+    }
+
+    fn create_deferrable_table_plan(
+        &self,
+        table: &DeferrableTable,
+        options: &ExecutionOptions,
+    ) -> BustubxResult<PhysicalPlan> {
+        self.create_plan_with(options, |planner| planner.plan_deferrable_table(table))
+    }
+
+    // physical plan of a statement the SQL parser has no grammar for, not optimized
+    fn create_plan_with(
+        &self,
+        options: &ExecutionOptions,
+        plan: impl FnOnce(&mut LogicalPlanner) -> BustubxResult<LogicalPlan>,
+    ) -> BustubxResult<PhysicalPlan> {
+        let mut planner = LogicalPlanner {
+            context: PlannerContext {
+                catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path: options.search_path.clone(),
+            },
+        };
+        let logical_plan = plan(&mut planner)?;
+        let physical_planner = PhysicalPlanner {
+            catalog: &self.catalog,
+            options,
+            trace: None,
+        };
+        physical_planner.create_physical_plan(logical_plan)
+    }
+
+    // Taken from the plan cache for queries and DML run before, the physical plan is made
+    // every time since it carries the executor state. Statements run with a search path of
+    // several schemas are planned every time, a table created later in an earlier schema
+    // of the path would change what their names resolve to. A traced statement is planned
+    // again, the trace records what the optimizer does.
+    fn optimized_logical_plan(
+        &mut self,
+        stmt: &Statement,
+        options: &ExecutionOptions,
+        trace: Option<&OptimizerTrace>,
+    ) -> BustubxResult<LogicalPlan> {
+        let cacheable = trace.is_none()
+            && matches!(
+                stmt,
+                Statement::Query(_)
+                    | Statement::Insert { .. }
+                    | Statement::Update { .. }
+                    | Statement::Delete { .. }
+            )
+            && options.search_path.len() <= 1;
+        // the printed statement is normalized, whitespace collapsed and keywords upper case,
+        // names of the default search path stay unprefixed
+        let cache_key = cacheable.then(|| match options.search_path.as_slice() {
+            [schema_name] if schema_name != DEFAULT_SCHEMA_NAME => {
+                format!("{schema_name}: {stmt}")
+            }
+            _ => stmt.to_string(),
+        });
+        if let Some(cache_key) = &cache_key {
+            if let Some(plan) = self.plan_cache.get(cache_key, &self.catalog) {
+                return Ok(plan);
+            }
+        }
+
+        let logical_plan = self.plan_statement(stmt, options.search_path.clone())?;
+        debug!(
+            "Logical Plan: \n{}",
+            pretty_format_logical_plan(&logical_plan)
+        );
+
+        let optimizer = LogicalOptimizer::new();
+        let optimized_logical_plan = match trace {
+            Some(trace) => optimizer.optimize_traced(&logical_plan, trace)?,
+            None => optimizer.optimize(&logical_plan)?,
         };
-        let tuples = execution_engine.execute(Arc::new(physical_plan))?;
-        Ok(tuples)
+        debug!(
+            "Optimized Logical Plan: \n{}",
+            pretty_format_logical_plan(&logical_plan)
+        );
+        if let Some(cache_key) = cache_key {
+            self.plan_cache
+                .insert(cache_key, &optimized_logical_plan, &self.catalog);
+        }
+        Ok(optimized_logical_plan)
+    }
+
+    /// Call `trigger` for every row of `table` an INSERT, UPDATE or DELETE changes, before or
+    /// after the change as `event` says. Triggers of an event run in registration order and
+    /// last as long as the database is open, they are not stored in the db file.
+    ///
+    /// A before trigger can replace the candidate row or skip it, an error fails the
+    /// statement. After triggers see the row as written, also inside a transaction, its
+    /// rollback does not undo what they did outside the database. A statement run from a
+    /// trigger fails with [`BustubxError::TriggerReentry`].
+    pub fn register_trigger(
+        &mut self,
+        table: &str,
+        event: TriggerEvent,
+        trigger: TriggerFn,
+    ) -> BustubxResult<()> {
+        let options = self.statement_options(None);
+        let planner = LogicalPlanner {
+            context: PlannerContext {
+                catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path: options.search_path.clone(),
+            },
+        };
+        let name = ObjectName(table.split('.').map(Ident::new).collect());
+        let table_ref = planner.bind_table_name(&name)?;
+        self.catalog.register_trigger(&table_ref, event, trigger)
+    }
+
+    /// Make `udf` callable from SQL as the scalar function `name`, anywhere an expression is,
+    /// including the key of an index. Calls are checked against `signature` when planned,
+    /// arguments of other numeric or string types are cast to it, and an error of `udf`
+    /// fails the statement. Names are case insensitive and a built-in or registered name
+    /// cannot be registered again.
+    ///
+    /// Like triggers, functions are not stored in the db file. Once the database is opened
+    /// again, an expression index calling a function is unresolved until the function is
+    /// registered, writes to its table fail with [`BustubxError::UnresolvedIndexKey`].
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        udf: Arc<dyn ScalarUdf>,
+    ) -> BustubxResult<()> {
+        self.catalog.functions.register(name, signature, udf)?;
+        let resolved = resolve_index_keys(self)?;
+        if !resolved.is_empty() {
+            debug!("function {} resolved indexes {:?}", name, resolved);
+        }
+        Ok(())
+    }
+
+    /// Write a SQL script rebuilding the schemas, tables, indexes and rows of the database,
+    /// see [`crate::catalog::dump_catalog`]. No statement runs while the database is borrowed,
+    /// so the script holds every table as of one point in time. Returns the number of rows
+    /// dumped.
+    pub fn dump(&self, writer: &mut impl std::io::Write) -> BustubxResult<usize> {
+        self.check_open()?;
+        dump_catalog(&self.catalog, writer)
+    }
+
+    /// Run a script written by [`Database::dump`], usually into an empty database. Stops at
+    /// the first failing statement and returns its error, the statements before it stay.
+    /// Returns the number of statements run.
+    pub fn restore_script(&mut self, mut reader: impl std::io::Read) -> BustubxResult<usize> {
+        let mut script = String::new();
+        reader.read_to_string(&mut script)?;
+        let outcomes = self.execute_script(&script);
+        let statements = outcomes.len();
+        for outcome in outcomes {
+            outcome.result?;
+        }
+        Ok(statements)
+    }
+
+    /// Hits and misses of the plan cache, `DISCARD PLANS` empties the cache.
+    pub fn plan_cache_stats(&self) -> PlanCacheStats {
+        self.plan_cache.stats()
+    }
+
+    /// Counters of the storage layer, locks and plan cache, also listed by `SHOW STATS`.
+    pub fn runtime_stats(&self) -> RuntimeStats {
+        let buffer_pool = self.buffer_pool.stats();
+        let (pages_allocated, pages_freed) = self.disk_manager.page_counts();
+        let plan_cache = self.plan_cache.stats();
+        let (uncompressed_bytes, compressed_bytes) = self.disk_manager.compressor().byte_counts();
+        RuntimeStats {
+            buffer_pool_hits: buffer_pool.hits,
+            buffer_pool_misses: buffer_pool.misses,
+            buffer_pool_evictions: buffer_pool.evictions,
+            dirty_writes: buffer_pool.dirty_writes,
+            dirty_evictions: buffer_pool.dirty_evictions,
+            dirty_evictions_avoided: buffer_pool.dirty_evictions_avoided,
+            pages_allocated,
+            pages_freed,
+            wal_bytes_written: self
+                .disk_manager
+                .log_manager()
+                .map_or(0, |log_manager| log_manager.bytes_written()),
+            uncompressed_bytes,
+            compressed_bytes,
+            fsyncs: self.disk_manager.sync_count(),
+            active_transactions: self.lock_manager.active_transactions() as u64,
+            lock_waits: self.lock_manager.lock_waits(),
+            plan_cache_hits: plan_cache.hits,
+            plan_cache_misses: plan_cache.misses,
+            dangling_index_entries: self.catalog.dangling_index_entries(),
+            auto_analyze_runs: self.auto_analyzer.runs(),
+        }
+    }
+
+    /// Zero the counters of [`Self::runtime_stats`], same as `RESET STATS`.
+    pub fn reset_runtime_stats(&mut self) {
+        self.buffer_pool.reset_stats();
+        self.disk_manager.reset_stats();
+        if let Some(log_manager) = self.disk_manager.log_manager() {
+            log_manager.reset_stats();
+        }
+        self.lock_manager.reset_stats();
+        self.plan_cache.reset_stats();
+        self.catalog.reset_stats();
+        self.auto_analyzer.reset_stats();
+    }
+
+    // one name and value row per counter
+    fn stats_output(&self) -> Vec<Tuple> {
+        self.runtime_stats()
+            .rows()
+            .into_iter()
+            .map(|(name, value)| {
+                Tuple::new(
+                    SHOW_STATS_OUTPUT_SCHEMA_REF.clone(),
+                    vec![name.to_string().into(), value.into()],
+                )
+            })
+            .collect()
     }
 
     pub fn create_logical_plan(&mut self, sql: &str) -> BustubxResult<LogicalPlan> {
+        self.check_open()?;
         // sql -> ast
-        let stmts = crate::parser::parse_sql(sql)?;
-        if stmts.len() != 1 {
-            return Err(BustubxError::NotSupport(
-                "only support one sql statement".to_string(),
-            ));
-        }
-        let stmt = &stmts[0];
+        let stmt = parse_single_statement(sql)?;
+        self.plan_statement(&stmt, self.options.execution.search_path.clone())
+    }
+
+    fn plan_statement(
+        &mut self,
+        stmt: &Statement,
+        search_path: Vec<String>,
+    ) -> BustubxResult<LogicalPlan> {
         let mut planner = LogicalPlanner {
             context: PlannerContext {
                 catalog: &self.catalog,
+                clock: self.clock.clone(),
+                search_path,
             },
         };
         // ast -> logical plan
         planner.plan(stmt)
     }
 
+    /// Delete the rows of ttl tables whose ttl column is before the current time of the
+    /// database clock, returns the number of deleted rows per ttl table.
+    pub fn expire_rows(&mut self) -> BustubxResult<Vec<(TableReference, usize)>> {
+        self.check_writable()?;
+        let deleted_rows = self.catalog.expire_rows(self.clock.now())?;
+        // deleted outside a transaction, snapshots cannot tell the rows apart anymore
+        for (table, deleted) in deleted_rows.iter() {
+            if *deleted > 0 {
+                self.txn_manager.forget_history(table);
+            }
+        }
+        self.catalog.persist_index_roots()?;
+        Ok(deleted_rows)
+    }
+
+    /// Replace the source transaction ids are taken from, before the database is shared
+    /// with [`crate::SharedDatabase::new`].
+    pub fn set_txn_id_source(&mut self, txn_ids: TransactionIdSourceRef) {
+        self.txn_manager = Arc::new(TransactionManager::new(self.clock.clone(), txn_ids));
+    }
+
+    /// Hide rows of ttl tables that expired but were not deleted by [`Database::expire_rows`] yet.
+    pub fn set_hide_expired_rows(&mut self, hide: bool) {
+        self.options.execution.hide_expired_rows = hide;
+    }
+
+    /// Percent of a table a DELETE must be estimated to affect to rebuild indexes in bulk.
+    pub fn set_index_rebuild_threshold(&mut self, percent: u32) -> BustubxResult<()> {
+        let options = self.options.clone().index_rebuild_threshold(percent);
+        options.validate()?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// Break ties of ORDER BY keys on the remaining columns, see
+    /// [`DatabaseOptions::deterministic_order`].
+    pub fn set_deterministic_order(&mut self, deterministic: bool) {
+        self.options.execution.deterministic_order = deterministic;
+    }
+
+    /// Keep running a script after a failed statement, see [`Database::execute_script`].
+    pub fn set_continue_script_on_error(&mut self, continue_on_error: bool) {
+        self.options.execution.continue_script_on_error = continue_on_error;
+    }
+
+    /// Run scans, filters and projections over batches of `batch_size` rows instead of
+    /// one row at a time.
+    pub fn set_batch_execution(&mut self, enabled: bool, batch_size: usize) -> BustubxResult<()> {
+        let options = self
+            .options
+            .clone()
+            .batch_execution(enabled)
+            .batch_size(batch_size);
+        options.validate()?;
+        self.options = options;
+        Ok(())
+    }
+
+    /// How long a statement waits for its table locks before failing with
+    /// [`BustubxError::RelationLocked`], zero waits as long as it takes.
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.options.execution.lock_timeout = timeout;
+    }
+
+    /// How long a statement runs before it is canceled with
+    /// [`BustubxError::StatementTimeout`], zero never cancels.
+    pub fn set_statement_timeout(&mut self, timeout: Duration) {
+        self.options.execution.statement_timeout = timeout;
+    }
+
+    /// Current value of the setting called `name`, see [`Setting`].
+    pub fn setting(&self, name: &str) -> BustubxResult<SettingValue> {
+        Ok(Setting::find(name)?.get(&self.options.execution))
+    }
+
+    /// Same as `SET name = value`, `value` is checked against the type and range of the
+    /// setting.
+    pub fn set_setting(&mut self, name: &str, value: &str) -> BustubxResult<()> {
+        let setting = Setting::find(name)?;
+        let value = setting.parse(value)?;
+        self.apply_setting(setting, value);
+        Ok(())
+    }
+
+    // Options a statement runs with, read once when it starts
+    pub(crate) fn statement_options(&self, session: Option<&SessionSettings>) -> ExecutionOptions {
+        match session {
+            Some(session) => session.apply(&self.options.execution),
+            None => self.options.execution.clone(),
+        }
+    }
+
+    // `SET name = value`, kept until the database is closed. Session scoped settings SET
+    // in a session stay with that session.
+    fn set_variable(
+        &mut self,
+        variable: &ObjectName,
+        value: &[Expr],
+        session: Option<&mut SessionSettings>,
+    ) -> BustubxResult<()> {
+        let setting = Setting::find(&variable.to_string())?;
+        let value = setting.parse_expr(value)?;
+        match session {
+            Some(session) if setting.scope == SettingScope::Session => session.set(setting, value),
+            _ => self.apply_setting(setting, value),
+        }
+        Ok(())
+    }
+
+    fn apply_setting(&mut self, setting: &Setting, value: SettingValue) {
+        setting.set(&mut self.options.execution, value);
+        self.plan_cache
+            .set_capacity(self.options.execution.plan_cache_capacity);
+    }
+
+    // `SHOW STATS`, `SHOW TRANSACTIONS`, `SHOW INDEX TREE name [ON table]`, `SHOW ALL` or
+    // `SHOW name`
+    fn show_variable(
+        &self,
+        variable: &[Ident],
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        if let [index_keyword, tree_keyword, index_name, on @ ..] = variable {
+            if index_keyword.value.eq_ignore_ascii_case("index")
+                && tree_keyword.value.eq_ignore_ascii_case("tree")
+            {
+                return self.show_index_tree(&index_name.value, on, options);
+            }
+        }
+        let name = variable
+            .iter()
+            .map(|ident| ident.value.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.eq_ignore_ascii_case("stats") {
+            return Ok(self.stats_output());
+        }
+        if name.eq_ignore_ascii_case("transactions") {
+            return Ok(self
+                .transactions()
+                .into_iter()
+                .map(|txn| {
+                    Tuple::new(
+                        SHOW_TRANSACTIONS_OUTPUT_SCHEMA_REF.clone(),
+                        vec![
+                            txn.txn_id.into(),
+                            txn.state.to_string().into(),
+                            (txn.age.as_millis() as u64).into(),
+                            (txn.idle.as_millis() as u64).into(),
+                            txn.rows_written.into(),
+                            txn.horizon_blocker.into(),
+                        ],
+                    )
+                })
+                .collect());
+        }
+        if name.eq_ignore_ascii_case("all") {
+            return Ok(Setting::all()
+                .iter()
+                .map(|setting| {
+                    Tuple::new(
+                        SHOW_ALL_OUTPUT_SCHEMA_REF.clone(),
+                        vec![
+                            setting.name.to_string().into(),
+                            setting.get(options).to_string().into(),
+                            setting.setting_type.to_string().into(),
+                            setting.scope.to_string().into(),
+                            setting.description.to_string().into(),
+                        ],
+                    )
+                })
+                .collect());
+        }
+        let setting = Setting::find(&name)?;
+        Ok(vec![Tuple::new(
+            SHOW_SETTING_OUTPUT_SCHEMA_REF.clone(),
+            vec![
+                setting.name.to_string().into(),
+                setting.get(options).to_string().into(),
+            ],
+        )])
+    }
+
+    // one row per line of the rendered index, looked up in the first schema of the search
+    // path holding it
+    fn show_index_tree(
+        &self,
+        index_name: &str,
+        on: &[Ident],
+        options: &ExecutionOptions,
+    ) -> BustubxResult<Vec<Tuple>> {
+        let table_name = match on {
+            [] => None,
+            [on_keyword, table_name] if on_keyword.value.eq_ignore_ascii_case("on") => {
+                Some(table_name.value.as_str())
+            }
+            _ => {
+                return Err(BustubxError::Plan(
+                    "expected SHOW INDEX TREE name [ON table]".to_string(),
+                ))
+            }
+        };
+        let mut tables = options
+            .search_path
+            .iter()
+            .map(|schema_name| {
+                let mut tables = self.catalog.tables_with_index(schema_name, index_name);
+                tables.retain(|table_ref| table_name.is_none_or(|name| table_ref.table() == name));
+                tables
+            })
+            .find(|tables| !tables.is_empty())
+            .unwrap_or_default();
+        if tables.len() > 1 {
+            return Err(BustubxError::Plan(format!(
+                "index {index_name} exists on several tables, pick one with ON table"
+            )));
+        }
+        let Some(index) = tables
+            .pop()
+            .map(|table_ref| self.catalog.index(&table_ref, index_name))
+            .transpose()?
+            .flatten()
+        else {
+            return Err(BustubxError::Plan(format!(
+                "index {index_name} does not exist"
+            )));
+        };
+        let display =
+            pretty_format_index_tree_with_limits(&index, usize::MAX, MAX_PAGES_PER_LEVEL)?;
+        Ok(display
+            .lines()
+            .map(|line| {
+                Tuple::new(
+                    SHOW_INDEX_TREE_OUTPUT_SCHEMA_REF.clone(),
+                    vec![line.to_string().into()],
+                )
+            })
+            .collect())
+    }
+
+    /// Open transactions of [`crate::Session::run_transaction`], also listed by
+    /// `SHOW TRANSACTIONS`. The oldest one is the horizon blocker: VACUUM leaves the tables
+    /// an open transaction wrote rows of alone until it ends.
+    pub fn transactions(&self) -> Vec<TransactionInfo> {
+        self.txn_manager.transactions()
+    }
+
+    /// Id of the latest transaction that committed inserted, updated or deleted rows, a
+    /// query `AS OF TRANSACTION` it reads the rows as they were right after it committed.
+    pub fn last_commit(&self) -> Option<TransactionId> {
+        self.txn_manager.last_commit()
+    }
+
+    /// Table locks of the statements of this database, shared with other threads that
+    /// need to keep statements off a table, and the row locks of its transactions.
+    pub fn lock_manager(&self) -> Arc<LockManager> {
+        self.lock_manager.clone()
+    }
+
+    /// On-disk footprint of every table and its indexes, also queryable as
+    /// `information_schema.relation_sizes`.
+    pub fn relation_sizes(&self) -> BustubxResult<Vec<TableSize>> {
+        self.catalog.relation_sizes()
+    }
+
+    /// Pages the latest statement run with the `trace_page_accesses` setting fetched and
+    /// allocated, in order. Statements run without the setting leave it alone.
+    pub fn last_page_trace(&self) -> Option<&[PageAccess]> {
+        self.last_page_trace.as_deref()
+    }
+
+    /// Table or index of every page of the heaps and indexes, to tell what a page trace
+    /// read.
+    pub fn page_owners(&self) -> BustubxResult<HashMap<PageId, PageOwner>> {
+        self.catalog.page_owners()
+    }
+
+    /// Access counters of every table and its indexes, as listed by
+    /// `information_schema.statistics`.
+    pub fn access_stats(&self) -> BustubxResult<Vec<(TableReference, TableAccessCounts)>> {
+        self.catalog.access_stats()
+    }
+
     pub fn flush(&self) -> BustubxResult<()> {
-        self.buffer_pool.flush_all_pages()
+        self.buffer_pool.flush_all_pages()?.into_result()?;
+        self.disk_manager.log_commit(self.clock.now())?;
+        Ok(())
+    }
+
+    /// Copy the database to `backup_path` as a base backup for [`Database::restore`]. A
+    /// label next to the copy names the lsn the archived log is replayed from.
+    pub fn backup(&self, backup_path: impl AsRef<Path>) -> BustubxResult<BackupLabel> {
+        self.check_open()?;
+        self.flush()?;
+        self.disk_manager.backup_to(backup_path.as_ref())
+    }
+
+    /// Restore the base backup at `base_backup` in place up to `target`, replaying the log
+    /// archived in `archive_dir` since the backup was taken, then open it.
+    ///
+    /// Changes after the target are discarded. The restored database goes on with a new
+    /// timeline in the same archive, its segments never mix with the discarded ones.
+    pub fn restore(
+        base_backup: impl AsRef<Path>,
+        archive_dir: impl AsRef<Path>,
+        target: RecoveryTarget,
+    ) -> BustubxResult<Self> {
+        Self::restore_with_options(base_backup, archive_dir, target, DatabaseOptions::default())
+    }
+
+    pub fn restore_with_options(
+        base_backup: impl AsRef<Path>,
+        archive_dir: impl AsRef<Path>,
+        target: RecoveryTarget,
+        options: DatabaseOptions,
+    ) -> BustubxResult<Self> {
+        let (base_backup, archive_dir) = (base_backup.as_ref(), archive_dir.as_ref());
+        let label_path = backup_label_path(base_backup);
+        let label: BackupLabel =
+            serde_json::from_slice(&std::fs::read(&label_path)?).map_err(|e| {
+                BustubxError::Storage(format!(
+                    "backup label {} is damaged: {e}",
+                    label_path.display()
+                ))
+            })?;
+        let records =
+            LogArchive::recovery_records(archive_dir, label.timeline, label.start_lsn, target)?;
+
+        // the records are archived already, the replay is not logged again
+        let disk_manager = DiskManager::try_new(base_backup)?;
+        for record in records.iter() {
+            disk_manager.apply_log_record(record)?;
+        }
+        disk_manager.sync()?;
+        drop(disk_manager);
+        let switch_lsn = records
+            .last()
+            .map_or(label.start_lsn - 1, |record| record.lsn);
+        debug!(
+            "restored {} to lsn {} from {} archived records",
+            base_backup.display(),
+            switch_lsn,
+            records.len()
+        );
+
+        let timeline = LogArchive::latest_timeline(archive_dir)? + 1;
+        LogArchive::write_history(archive_dir, timeline, label.timeline, switch_lsn)?;
+        write_timeline(base_backup, timeline)?;
+        // the copy moved past the lsn of its label
+        std::fs::remove_file(&label_path)?;
+        Self::open_with_options(base_backup, options.wal_archive_dir(archive_dir))
+    }
+
+    /// Page writes of the database, only kept with [`DatabaseOptions::replication_log`].
+    pub fn log_manager(&self) -> Option<&LogManager> {
+        self.disk_manager.log_manager()
+    }
+
+    /// Ship the page writes from `from_lsn` on to a standby, returns the lsn to continue
+    /// from next time. Only pages that reached the db file are shipped, flush first to
+    /// include the latest statements.
+    pub fn ship_log(&self, from_lsn: Lsn, shipper: &dyn LogShipper) -> BustubxResult<Lsn> {
+        let Some(log_manager) = self.log_manager() else {
+            return Err(BustubxError::Config(
+                "replication log is not enabled".to_string(),
+            ));
+        };
+        let mut next_lsn = from_lsn;
+        for segment in log_manager.read_segments(from_lsn)? {
+            next_lsn = segment.end_lsn().unwrap_or(next_lsn);
+            shipper.ship(segment)?;
+        }
+        Ok(next_lsn)
+    }
+
+    /// Apply one shipped segment to a standby.
+    pub fn apply_log_segment(&mut self, segment: &LogSegment) -> BustubxResult<()> {
+        self.apply_log_records(segment)?;
+        self.reload()
+    }
+
+    /// Apply every segment waiting in `receiver`, returns the number of applied segments.
+    pub fn apply_received(&mut self, receiver: &Receiver<LogSegment>) -> BustubxResult<usize> {
+        let mut segments = 0;
+        while let Ok(segment) = receiver.try_recv() {
+            self.apply_log_records(&segment)?;
+            segments += 1;
+        }
+        if segments > 0 {
+            self.reload()?;
+        }
+        Ok(segments)
+    }
+
+    /// Turn a standby into a primary that accepts writes.
+    pub fn promote(&mut self) -> BustubxResult<()> {
+        if self.replica_next_lsn.is_none() {
+            return Err(BustubxError::Execution(
+                "database is not a replica".to_string(),
+            ));
+        }
+        self.reload()?;
+        self.replica_next_lsn = None;
+        Ok(())
+    }
+
+    /// Lsn of the next log record a standby expects, `None` unless the database is a standby.
+    pub fn replica_next_lsn(&self) -> Option<Lsn> {
+        self.replica_next_lsn
+    }
+
+    fn apply_log_records(&mut self, segment: &LogSegment) -> BustubxResult<()> {
+        let Some(next_lsn) = self.replica_next_lsn.as_mut() else {
+            return Err(BustubxError::Execution(
+                "database is not a replica".to_string(),
+            ));
+        };
+        for record in segment.records.iter() {
+            // already applied from an earlier segment
+            if record.lsn < *next_lsn {
+                continue;
+            }
+            if record.lsn > *next_lsn {
+                return Err(BustubxError::Storage(format!(
+                    "log records {}..{} are missing",
+                    next_lsn, record.lsn
+                )));
+            }
+            self.disk_manager.apply_log_record(record)?;
+            *next_lsn += 1;
+        }
+        Ok(())
+    }
+
+    // Drop the cached pages and the catalog, then load both again from the db file
+    fn reload(&mut self) -> BustubxResult<()> {
+        if let Some(flusher) = self.flusher.take() {
+            self.background.stop(flusher)?;
+        }
+        self.buffer_pool = Arc::new(BufferPoolManager::new_with_options(
+            self.options.buffer_pool.clone(),
+            self.disk_manager.clone(),
+        ));
+        self.flusher =
+            start_configured_flusher(&mut self.background, &self.buffer_pool, &self.options)?;
+        self.catalog = Catalog::new(self.buffer_pool.clone());
+        self.plan_cache.clear();
+        load_catalog_data(self)
+    }
+
+    fn check_open(&self) -> BustubxResult<()> {
+        if self.closed {
+            return Err(BustubxError::DatabaseClosed);
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> BustubxResult<()> {
+        if self.replica_next_lsn.is_some() {
+            return Err(BustubxError::Execution(
+                "database is a read-only replica".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        // before the fields, a task may still write to the buffer pool or the db file
+        if let Err(e) = self.background.shutdown() {
+            warn!("{}", e);
+        }
+        // a temp database goes away with its directory
+        if self.closed || self.temp_dir.is_some() {
+            return;
+        }
+        warn!("database dropped without close, the next open runs recovery");
+        if let Err(e) = self
+            .buffer_pool
+            .flush_all_pages()
+            .and_then(|report| report.into_result())
+        {
+            warn!(
+                "failed to flush the buffer pool of a dropped database: {}",
+                e
+            );
+        }
+    }
+}
+
+// Writes change the latest rows, a statement reading an earlier snapshot cannot make them
+// background flusher of the buffer pool the options ask for
+fn start_configured_flusher(
+    background: &mut BackgroundTaskRegistry,
+    buffer_pool: &Arc<BufferPoolManager>,
+    options: &DatabaseOptions,
+) -> BustubxResult<Option<TaskId>> {
+    options
+        .buffer_pool
+        .background_flush_interval
+        .map(|interval| start_flusher(background, buffer_pool.clone(), interval))
+        .transpose()
+}
+
+fn check_latest_snapshot(options: &ExecutionOptions) -> BustubxResult<()> {
+    if options.snapshot_txn != INVALID_TRANSACTION_ID {
+        return Err(BustubxError::Plan(format!(
+            "cannot write while reading as of transaction {}, set snapshot_txn = 0 first",
+            options.snapshot_txn
+        )));
+    }
+    Ok(())
+}
+
+// Statements a read-only replica can run
+fn is_read_only(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Query(_)
+        | Statement::ShowTables { .. }
+        | Statement::ShowCreate { .. }
+        | Statement::ExplainTable { .. } => true,
+        Statement::Explain {
+            analyze, statement, ..
+        } => !analyze || is_read_only(statement),
+        _ => false,
+    }
+}
+
+// Position the error of the statement at `offset` in the whole script, the start of the
+// statement when the parser does not tell where it failed
+fn locate_parser_error(
+    script: &str,
+    offset: usize,
+    statement: &str,
+    error: ParserError,
+) -> BustubxError {
+    let position =
+        crate::parser::parse_error_location(statement, &error).and_then(|(line, column)| {
+            // byte offset of the line and column into the statement
+            let line_start = if line == 1 {
+                0
+            } else {
+                statement.match_indices('\n').nth(line - 2)?.0 + 1
+            };
+            let column_offset = statement[line_start..]
+                .char_indices()
+                .nth(column.saturating_sub(1))
+                .map_or(statement.len() - line_start, |(i, _)| i);
+            Some(line_start + column_offset)
+        });
+    let offset =
+        offset + position.unwrap_or_else(|| statement.len() - statement.trim_start().len());
+    let before = &script[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    BustubxError::ParserAt {
+        message: error.to_string(),
+        line,
+        column: before[line_start..].chars().count() + 1,
+        offset,
+    }
+}
+
+fn parse_single_statement(sql: &str) -> BustubxResult<Statement> {
+    let mut stmts = crate::parser::parse_sql(sql)?;
+    if stmts.len() != 1 {
+        return Err(BustubxError::NotSupport(
+            "only support one sql statement".to_string(),
+        ));
+    }
+    Ok(stmts.remove(0))
+}
+
+fn explain_output(
+    plan_tree: &PlanTree,
+    format: &Option<AnalyzeFormat>,
+) -> BustubxResult<Vec<Tuple>> {
+    let lines = match format {
+        None | Some(AnalyzeFormat::TEXT) => plan_tree.text_lines(),
+        Some(AnalyzeFormat::JSON) => vec![serde_json::to_string_pretty(plan_tree)
+            .map_err(|e| BustubxError::Internal(format!("failed to serialize plan: {e}")))?],
+        Some(format) => {
+            return Err(BustubxError::NotSupport(format!(
+                "explain format {} not supported",
+                format
+            )))
+        }
+    };
+    Ok(lines
+        .into_iter()
+        .map(|line| Tuple::new(EXPLAIN_OUTPUT_SCHEMA_REF.clone(), vec![line.into()]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use tempfile::TempDir;
+
+    use crate::common::test_util::text_rows;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::config::DatabaseOptions;
+    use crate::transaction::TableLockMode;
+    use crate::{BustubxError, Database, SharedDatabase, TaskHealth};
+
+    const SCRIPT: &str = "create table t1 (a int);\n\
+                          insert into t1 valus (2);\n\
+                          insert into t1 values (3);";
+
+    #[test]
+    pub fn test_execute_script_stops_on_error() {
+        let mut db = Database::new_temp().unwrap();
+        let outcomes = db.execute_script(SCRIPT);
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].result.is_ok());
+        assert_eq!(outcomes[1].sql, "insert into t1 valus (2)");
+        assert!(matches!(
+            outcomes[1].result,
+            Err(BustubxError::ParserAt { line: 2, .. })
+        ));
+        assert!(db.run("select a from t1").unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_execute_script_continues_on_error() {
+        let mut db = Database::new_temp().unwrap();
+        db.set_continue_script_on_error(true);
+        let outcomes = db.execute_script(SCRIPT);
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes[1].result.is_err());
+        assert!(outcomes[2].result.is_ok());
+        assert_eq!(
+            &SCRIPT[outcomes[2].offset..].trim(),
+            &"insert into t1 values (3);"
+        );
+        assert_eq!(db.run("select a from t1").unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_execute_script_error_position() {
+        let mut db = Database::new_temp().unwrap();
+        let script = "create table t1 (a int, b varchar(20));\n\
+                      insert into t1 values (1, 'semi; colon'), (2, 'it''s');\n  \
+                      select a from t1 where b = 'x;\ny' oops;";
+        let outcomes = db.execute_script(script);
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes[1].result.is_ok());
+        let Err(BustubxError::ParserAt {
+            line,
+            column,
+            offset,
+            ..
+        }) = &outcomes[2].result
+        else {
+            panic!("unexpected outcome {:?}", outcomes[2]);
+        };
+        assert_eq!(*offset, script.find("oops").unwrap());
+        // the quoted string spans a line break
+        assert_eq!((*line, *column), (4, 4));
+
+        // the rejected token starts the statement, in the middle of a line
+        let script = "create table t2 (a int); selec 2";
+        let outcomes = db.execute_script(script);
+        assert!(matches!(
+            outcomes[1].result,
+            Err(BustubxError::ParserAt {
+                line: 1,
+                column: 26,
+                offset: 25,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    pub fn test_show_index_tree() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create schema app").unwrap();
+        for table in ["t1", "app.t2"] {
+            db.run(&format!("create table {table} (a int)")).unwrap();
+            db.run(&format!("create index idx_a on {table} (a)"))
+                .unwrap();
+        }
+        let values = (0..200)
+            .map(|i| format!("({i})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+        let tree = |db: &mut Database, sql: &str| {
+            db.run(sql)
+                .unwrap()
+                .into_iter()
+                .map(|tuple| tuple.data[0].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let lines = tree(&mut db, "show index tree idx_a");
+        assert!(lines[0].starts_with("B+ Tree Level No.1:"), "{lines:?}");
+        assert!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("B+ Tree Level"))
+                .count()
+                > 1
+        );
+        assert_eq!(tree(&mut db, "show index tree idx_a on t1"), lines);
+        // looked up along the search path
+        db.run("set search_path = app, public").unwrap();
+        assert_eq!(tree(&mut db, "show index tree idx_a"), vec!["Empty tree."]);
+
+        for sql in [
+            "show index tree idx_missing",
+            "show index tree idx_a on t3",
+            "show index tree idx_a at t1",
+        ] {
+            assert!(matches!(db.run(sql), Err(BustubxError::Plan(_))), "{sql}");
+        }
+    }
+
+    #[test]
+    pub fn test_statement_timeout() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("create table t2 (a int)").unwrap();
+        db.run("create table t3 (b int)").unwrap();
+        db.run("create table t4 (c int)").unwrap();
+        let values = (0..200).map(|a| format!("({a})")).collect::<Vec<_>>();
+        for table in ["t1", "t3", "t4"] {
+            db.run(&format!("insert into {table} values {}", values.join(", ")))
+                .unwrap();
+        }
+        let cross_join = "select a from t1, t3, t4 where a + b + c < 0";
+
+        db.run("SET statement_timeout = '50ms'").unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            db.run(cross_join),
+            Err(BustubxError::StatementTimeout { timeout }) if timeout == Duration::from_millis(50)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the canceled statement leaves neither rows nor locks behind
+        assert!(matches!(
+            db.run(&format!("insert into t2 {cross_join}")),
+            Err(BustubxError::StatementTimeout { .. })
+        ));
+        db.lock_manager()
+            .lock_table(
+                999,
+                TableLockMode::AccessExclusive,
+                &TableReference::bare("t2"),
+                Duration::ZERO,
+            )
+            .unwrap();
+        db.lock_manager().unlock_all(999);
+        assert!(db.run("select a from t2").unwrap().is_empty());
+
+        db.run("set statement_timeout to 0").unwrap();
+        assert!(db
+            .run("select a from t1, t3 where a + b < 0")
+            .unwrap()
+            .is_empty());
+        assert!(db.run("set statement_timeout = 'soon'").is_err());
+        assert!(db.run("set application_name = 'bustubx'").is_err());
+    }
+
+    #[test]
+    pub fn test_lock_timeout_setting() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        let lock_manager = db.lock_manager();
+        let t1 = TableReference::bare("t1");
+        lock_manager
+            .lock_table(999, TableLockMode::AccessExclusive, &t1, Duration::ZERO)
+            .unwrap();
+
+        db.run("set lock_timeout = '50ms'").unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            db.run("select a from t1"),
+            Err(BustubxError::RelationLocked { timeout, .. }) if timeout == Duration::from_millis(50)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // without a lock timeout the statement waits for the holder
+        db.run("set lock_timeout = 0").unwrap();
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            rx.recv().unwrap();
+            thread::sleep(Duration::from_millis(200));
+            lock_manager.unlock_all(999);
+        });
+        tx.send(()).unwrap();
+        let start = Instant::now();
+        assert!(db.run("select a from t1").unwrap().is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_close_and_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let open = || Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        let mut db = open();
+        assert!(!db.recovered_on_open());
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20), (3, 30)")
+            .unwrap();
+        db.close().unwrap();
+
+        // a clean close skips recovery
+        let mut db = open();
+        assert!(!db.recovered_on_open());
+        assert_eq!(db.run("select b from t1 where a = 2").unwrap().len(), 1);
+        db.run("insert into t1 values (4, 40)").unwrap();
+        db.run("delete from t1 where a = 1").unwrap();
+        // stopped without close
+        drop(db);
+
+        let mut db = open();
+        assert!(db.recovered_on_open());
+        assert!(db.run("select b from t1 where a = 1").unwrap().is_empty());
+        assert_eq!(db.run("select b from t1 where a = 4").unwrap().len(), 1);
+        assert_eq!(db.run("select a from t1").unwrap().len(), 3);
+        db.close().unwrap();
+        assert!(!open().recovered_on_open());
+    }
+
+    #[test]
+    pub fn test_background_flusher() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = DatabaseOptions::new()
+            .buffer_pool_size(32)
+            .background_flush_interval(Duration::from_millis(1));
+        let mut db = Database::open_with_options(&db_path, options.clone()).unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        for i in 0..20 {
+            let values = (0..50)
+                .map(|j| format!("({}, {})", i * 50 + j, j))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+            thread::sleep(Duration::from_millis(2));
+        }
+        db.run("update t1 set b = b + 1 where a < 500").unwrap();
+        assert!(db.runtime_stats().buffer_pool_evictions > 0);
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, options).unwrap();
+        assert!(!db.recovered_on_open());
+        let sum = db.run("select count(*) from t1 where b > 49").unwrap();
+        assert_eq!(sum[0].data[0], ScalarValue::Int64(Some(10)));
+        assert_eq!(db.run("select b from t1 where a = 999").unwrap().len(), 1);
+        assert!(matches!(
+            Database::new_temp_with_options(
+                DatabaseOptions::new().background_flush_interval(Duration::ZERO)
+            ),
+            Err(BustubxError::Config(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_open_close_stops_background_tasks() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let options = DatabaseOptions::new()
+            .buffer_pool_size(16)
+            .background_flush_interval(Duration::from_millis(1))
+            .auto_analyze_threshold(20);
+        for round in 0..10 {
+            let mut db = Database::open_with_options(&db_path, options.clone()).unwrap();
+            assert!(!db.recovered_on_open());
+            if round == 0 {
+                db.run("create table t1 (a int, b int)").unwrap();
+            }
+            let values = (0..50)
+                .map(|i| format!("({}, {})", round * 50 + i, i))
+                .collect::<Vec<_>>()
+                .join(", ");
+            // starts an auto analyze beside the flusher
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+            assert!(db
+                .background_tasks()
+                .iter()
+                .any(|task| task.name == "flusher" && task.health == TaskHealth::Running));
+            db.close().unwrap();
+        }
+        // a temp database removes its directory after its tasks stopped
+        for _ in 0..10 {
+            let mut db = Database::new_temp_with_options(options.clone()).unwrap();
+            db.run("create table t1 (a int)").unwrap();
+            db.run("insert into t1 values (1), (2), (3)").unwrap();
+        }
+
+        let mut db = Database::open_with_options(&db_path, options).unwrap();
+        let rows = db.run("select count(*) from t1").unwrap();
+        assert_eq!(rows[0].data[0], ScalarValue::Int64(Some(500)));
+        assert!(db
+            .background_tasks()
+            .iter()
+            .all(|task| !matches!(task.health, TaskHealth::Panicked(_))));
+        db.close().unwrap();
+    }
+
+    #[test]
+    pub fn test_use_after_close() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t1 (a int)").unwrap();
+        db.close().unwrap();
+        assert!(matches!(db.close(), Err(BustubxError::DatabaseClosed)));
+        assert!(matches!(
+            session.run("select a from t1"),
+            Err(BustubxError::DatabaseClosed)
+        ));
+        assert!(matches!(
+            db.lock().unwrap().explain("select a from t1"),
+            Err(BustubxError::DatabaseClosed)
+        ));
+        let outcomes = session.execute_script("insert into t1 values (1); select a from t1");
+        assert!(matches!(
+            outcomes.unwrap()[0].result,
+            Err(BustubxError::DatabaseClosed)
+        ));
+    }
+    #[test]
+    pub fn test_queries_over_empty_tables() {
+        let mut db = Database::new_temp().unwrap();
+        for sql in [
+            "create table t1 (a int, b varchar(20))",
+            "create index idx_a on t1 (a)",
+            "create table t2 (a int primary key, c int)",
+            "create table t3 (a int, b varchar(20))",
+            "create table p (ts int, v int) partition by range (ts)",
+            "create table p_1 partition of p for values from (0) to (10)",
+        ] {
+            db.run(sql).unwrap();
+        }
+        let check = |db: &mut Database| {
+            for sql in [
+                "select * from t1",
+                "select a from t1 where a = 1",
+                "select a from t1 where a >= 1 and a < 5",
+                "select a from t1 order by a limit 3",
+                "select a, b from t1 order by b desc limit 2 offset 1",
+                "select * from t2 where a = 1",
+                "select * from t2 where a < 5 order by a desc",
+                "select * from t1, t3",
+                "select * from t1 inner join t2 on t1.a = t2.a",
+                "select * from t1 join t2 on t1.a = t2.a join t3 on t3.a = t1.a",
+                "select a from t1 where a in (select a from t3)",
+                "select a from t1 where a not in (select a from t3)",
+                "select a from t1 where exists (select a from t3 where t3.a = t1.a)",
+                "select b, count(a) from t1 group by b",
+                "select b from t1 group by b having count(a) > 1",
+                "select distinct b from t1",
+                "select b, count(*) from t1 group by grouping sets ((a), (b))",
+                "select * from (select a from t1 where a > 1) x",
+                "select * from p",
+                "select * from p where ts = 5",
+                "select * from p order by ts limit 2",
+            ] {
+                assert!(text_rows(db, sql).is_empty(), "{sql}");
+            }
+            assert_eq!(text_rows(db, "select count(*) from t1"), vec!["0"]);
+            assert_eq!(
+                text_rows(db, "select count(a), avg(a) from t1 where a = 5"),
+                vec!["0 NULL"]
+            );
+            assert_eq!(text_rows(db, "select count(distinct a) from t1"), vec!["0"]);
+            assert_eq!(
+                text_rows(db, "select count(*), avg(v) from p"),
+                vec!["0 NULL"]
+            );
+            assert_eq!(
+                text_rows(db, "select b, count(*) from t1 group by rollup(b)"),
+                vec!["NULL 0"]
+            );
+            assert_eq!(
+                text_rows(db, "select b, count(*) from t1 group by cube(a, b)"),
+                vec!["NULL 0"]
+            );
+            assert_eq!(
+                text_rows(
+                    db,
+                    "select b, count(*) from t1 group by grouping sets ((b), ())"
+                ),
+                vec!["NULL 0"]
+            );
+            for sql in [
+                "insert into t3 select a, b from t1",
+                "update t1 set b = 'x' where a = 1",
+                "update p_1 set v = 1",
+                "delete from t3",
+                "delete from p_1",
+                "analyze t1",
+                "vacuum t1",
+                "check table t1 with indexes",
+            ] {
+                db.run(sql).unwrap_or_else(|e| panic!("{sql}: {e}"));
+            }
+            assert_eq!(text_rows(db, "select count(*) from t3"), vec!["0"]);
+        };
+
+        check(&mut db);
+        db.run("insert into t1 values (1, 'x'), (2, 'y'), (3, 'z')")
+            .unwrap();
+        db.run("insert into t2 values (1, 1), (2, 2)").unwrap();
+        db.run("insert into p values (1, 1), (5, 5)").unwrap();
+        for sql in [
+            "delete from t1",
+            "delete from t2",
+            "delete from p_1",
+            "vacuum t1",
+        ] {
+            db.run(sql).unwrap();
+        }
+        check(&mut db);
     }
 }
@@ -0,0 +1,630 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{IndexSize, TableSize, DEFAULT_CATALOG_NAME};
+use crate::common::index_tree::{pretty_format_index_tree_with_limits, MAX_PAGES_PER_LEVEL};
+use crate::common::TableReference;
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::TableHeap;
+use crate::transaction::LockManager;
+use crate::{BustubxError, BustubxResult, RuntimeStats, SharedDatabase};
+
+// a client taking longer to send its request or read the response is dropped
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+// request line and headers
+const MAX_REQUEST_HEAD_BYTES: usize = 8 * 1024;
+
+/// Read-only HTTP listener serving the introspection APIs of a database as JSON, built
+/// with the `debug-http` feature:
+///
+/// - `/stats` runtime counters, see [`RuntimeStats`]
+/// - `/buffer_pool` every frame of the buffer pool, see [`BufferPoolSnapshot`]
+/// - `/tables` sizes of the tables and their indexes, see [`TableSnapshot`]
+/// - `/index/{name}` tree stats of an index, `?depth=N` adds its structure down to level N
+///   and `?table=T` picks the table when several have an index of that name
+/// - `/locks` holders and waiters of table locks, see [`LockSnapshot`]
+///
+/// There is no authentication. Requests are answered one at a time on a thread of the
+/// listener, the database is only locked to copy out the catalog or counters and every
+/// answer is built from that snapshot. The listener stops when the server is dropped.
+pub struct DebugServer {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl DebugServer {
+    /// Serve on `127.0.0.1:port`, port 0 picks a free port.
+    pub fn start(db: Arc<SharedDatabase>, port: u16) -> BustubxResult<Self> {
+        Self::bind(db, (Ipv4Addr::LOCALHOST, port))
+    }
+
+    /// Serve on `addr`, anything but a loopback address exposes the internals of the
+    /// database to the network.
+    pub fn bind(db: Arc<SharedDatabase>, addr: impl ToSocketAddrs) -> BustubxResult<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let sources = {
+            let guard = db.lock()?;
+            Sources {
+                buffer_pool: guard.buffer_pool.clone(),
+                lock_manager: guard.lock_manager(),
+                db: db.clone(),
+            }
+        };
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let shutdown = shutdown.clone();
+            thread::Builder::new()
+                .name("bustubx-debug-http".to_string())
+                .spawn(move || sources.serve(listener, &shutdown))?
+        };
+        Ok(Self {
+            local_addr,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for DebugServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // wake the listener blocked in accept
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        let _ = TcpStream::connect_timeout(&wake_addr, CONNECTION_TIMEOUT);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Body of `/buffer_pool`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BufferPoolSnapshot {
+    pub pool_size: usize,
+    pub frames: Vec<FrameSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrameSnapshot {
+    pub frame_id: usize,
+    /// None for a free frame
+    pub page_id: Option<u32>,
+    pub pin_count: u32,
+    pub is_dirty: bool,
+    /// Logical time of the latest access counted by the replacer
+    pub last_access: Option<u64>,
+}
+
+/// Element of the body of `/tables`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableSnapshot {
+    pub table: String,
+    pub heap_pages: usize,
+    pub live_tuples: usize,
+    pub dead_tuples: usize,
+    pub total_bytes: usize,
+    pub indexes: Vec<IndexStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexStats {
+    pub name: String,
+    /// Pages of every tree level, the root level first
+    pub level_pages: Vec<usize>,
+    pub entries: usize,
+    pub avg_fill: f64,
+    pub bloat: f64,
+    pub bytes: usize,
+}
+
+/// Body of `/index/{name}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexSnapshot {
+    pub table: String,
+    pub unique: bool,
+    pub key_columns: Vec<String>,
+    pub stats: IndexStats,
+    /// Tree printed level by level, only with `?depth=N`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structure: Option<String>,
+}
+
+/// Element of the body of `/locks`, sorted by table and in arrival order per table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockSnapshot {
+    pub table: String,
+    pub txn_id: u64,
+    pub mode: String,
+    /// False while the transaction waits for the lock
+    pub granted: bool,
+}
+
+/// Body of every answer but 200.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+impl From<&IndexSize> for IndexStats {
+    fn from(size: &IndexSize) -> Self {
+        Self {
+            name: size.name.clone(),
+            level_pages: size.level_pages.clone(),
+            entries: size.entries,
+            avg_fill: size.avg_fill,
+            bloat: size.bloat(),
+            bytes: size.bytes(),
+        }
+    }
+}
+
+impl From<&TableSize> for TableSnapshot {
+    fn from(size: &TableSize) -> Self {
+        Self {
+            table: size.table.to_string(),
+            heap_pages: size.heap_pages,
+            live_tuples: size.live_tuples,
+            dead_tuples: size.dead_tuples,
+            total_bytes: size.total_bytes(),
+            indexes: size.indexes.iter().map(IndexStats::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<BustubxError> for HttpError {
+    fn from(e: BustubxError) -> Self {
+        Self::new(500, e.to_string())
+    }
+}
+
+// a table of the catalog, copied out under the database lock
+struct CatalogTableRefs {
+    table: TableReference,
+    heap: Arc<TableHeap>,
+    indexes: Vec<(String, Arc<BPlusTreeIndex>)>,
+}
+
+// the parts of the database the endpoints read, the storage ones are shared with it
+struct Sources {
+    db: Arc<SharedDatabase>,
+    buffer_pool: Arc<BufferPoolManager>,
+    lock_manager: Arc<LockManager>,
+}
+
+impl Sources {
+    fn serve(&self, listener: TcpListener, shutdown: &AtomicBool) {
+        for stream in listener.incoming() {
+            if shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            let result = stream
+                .map_err(BustubxError::from)
+                .and_then(|stream| self.handle_connection(stream));
+            if let Err(e) = result {
+                debug!("debug http connection failed: {e}");
+            }
+        }
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> BustubxResult<()> {
+        stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+        let (status, body) = match read_request_line(&stream) {
+            Ok(request_line) => self.respond(&request_line),
+            Err(e) => error_response(e),
+        };
+        let response = format!(
+            "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            reason_phrase(status),
+            body.len()
+        );
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn respond(&self, request_line: &str) -> (u16, String) {
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return error_response(HttpError::new(400, "malformed request line"));
+        };
+        if method != "GET" {
+            return error_response(HttpError::new(405, format!("{method} not allowed")));
+        }
+        match self.route(target) {
+            Ok(body) => (200, body),
+            Err(e) => error_response(e),
+        }
+    }
+
+    fn route(&self, target: &str) -> Result<String, HttpError> {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let params = query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect::<Vec<_>>();
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| *value)
+        };
+        match path.trim_end_matches('/') {
+            "/stats" => to_json(&self.stats()?),
+            "/buffer_pool" => to_json(&self.buffer_pool()),
+            "/tables" => to_json(&self.tables()?),
+            "/locks" => to_json(&self.locks()),
+            path => match path.strip_prefix("/index/") {
+                Some(name) if !name.is_empty() => {
+                    let depth = param("depth")
+                        .map(|depth| {
+                            depth
+                                .parse::<usize>()
+                                .map_err(|_| HttpError::new(400, format!("invalid depth {depth}")))
+                        })
+                        .transpose()?;
+                    to_json(&self.index(name, param("table"), depth)?)
+                }
+                _ => Err(HttpError::new(404, format!("no endpoint {path}"))),
+            },
+        }
+    }
+
+    fn stats(&self) -> BustubxResult<RuntimeStats> {
+        Ok(self.db.lock()?.runtime_stats())
+    }
+
+    fn buffer_pool(&self) -> BufferPoolSnapshot {
+        BufferPoolSnapshot {
+            pool_size: self.buffer_pool.pool_size(),
+            frames: self
+                .buffer_pool
+                .frames()
+                .into_iter()
+                .map(|frame| FrameSnapshot {
+                    frame_id: frame.frame_id,
+                    page_id: frame.page_id,
+                    pin_count: frame.pin_count,
+                    is_dirty: frame.is_dirty,
+                    last_access: frame.last_access,
+                })
+                .collect(),
+        }
+    }
+
+    fn tables(&self) -> BustubxResult<Vec<TableSnapshot>> {
+        self.catalog_tables()?
+            .into_iter()
+            .map(|refs| {
+                let size = TableSize::measure_heap(
+                    refs.table,
+                    &refs.heap,
+                    refs.indexes
+                        .iter()
+                        .map(|(name, index)| (name.as_str(), index.as_ref())),
+                )?;
+                Ok(TableSnapshot::from(&size))
+            })
+            .collect()
+    }
+
+    fn index(
+        &self,
+        name: &str,
+        table: Option<&str>,
+        depth: Option<usize>,
+    ) -> Result<IndexSnapshot, HttpError> {
+        let mut matches = self
+            .catalog_tables()?
+            .into_iter()
+            .filter(|refs| {
+                // the table name may be qualified by the schema and catalog
+                let qualified = refs.table.to_string();
+                table.is_none_or(|table| {
+                    qualified == table || qualified.ends_with(&format!(".{table}"))
+                })
+            })
+            .filter_map(|refs| {
+                let index = refs
+                    .indexes
+                    .into_iter()
+                    .find(|(index_name, _)| index_name == name)?
+                    .1;
+                Some((refs.table, index))
+            })
+            .collect::<Vec<_>>();
+        let (table, index) = match matches.len() {
+            0 => return Err(HttpError::new(404, format!("no index {name}"))),
+            1 => matches.remove(0),
+            _ => {
+                return Err(HttpError::new(
+                    400,
+                    format!("several tables have an index {name}, pick one with ?table="),
+                ))
+            }
+        };
+        let size = IndexSize::measure(name, &index)?;
+        let structure = depth
+            .map(|depth| pretty_format_index_tree_with_limits(&index, depth, MAX_PAGES_PER_LEVEL))
+            .transpose()?;
+        Ok(IndexSnapshot {
+            table: table.to_string(),
+            unique: index.unique,
+            key_columns: index
+                .key_schema
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+            stats: IndexStats::from(&size),
+            structure,
+        })
+    }
+
+    fn locks(&self) -> Vec<LockSnapshot> {
+        self.lock_manager
+            .table_locks()
+            .into_iter()
+            .map(|lock| LockSnapshot {
+                table: lock.table.to_string(),
+                txn_id: lock.txn_id,
+                mode: lock.mode.to_string(),
+                granted: lock.granted,
+            })
+            .collect()
+    }
+
+    // every table of the catalog sorted by name, the database is locked only while the
+    // references are copied
+    fn catalog_tables(&self) -> BustubxResult<Vec<CatalogTableRefs>> {
+        let db = self.db.lock()?;
+        let mut tables = vec![];
+        for (schema_name, catalog_schema) in db.catalog.schemas.iter() {
+            for (table_name, catalog_table) in catalog_schema.tables.iter() {
+                tables.push(CatalogTableRefs {
+                    table: TableReference::full(DEFAULT_CATALOG_NAME, schema_name, table_name),
+                    heap: catalog_table.table.clone(),
+                    indexes: catalog_table
+                        .indexes
+                        .iter()
+                        .map(|(name, index)| (name.clone(), index.clone()))
+                        .collect(),
+                });
+            }
+        }
+        drop(db);
+        tables.sort_by(|a, b| a.table.cmp(&b.table));
+        Ok(tables)
+    }
+}
+
+// request line of the request, the headers are read and ignored
+fn read_request_line(stream: &TcpStream) -> Result<String, HttpError> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    let mut head_bytes = 0;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| HttpError::new(400, e.to_string()))?;
+        head_bytes += read;
+        if head_bytes > MAX_REQUEST_HEAD_BYTES {
+            return Err(HttpError::new(431, "request head too large"));
+        }
+        if read == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if request_line.is_empty() {
+            request_line = line.trim_end().to_string();
+        }
+    }
+    Ok(request_line)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, HttpError> {
+    serde_json::to_string(value).map_err(|e| HttpError::new(500, e.to_string()))
+}
+
+fn error_response(e: HttpError) -> (u16, String) {
+    let body = ErrorBody { error: e.message };
+    (
+        e.status,
+        serde_json::to_string(&body).unwrap_or_else(|_| "{}".to_string()),
+    )
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    use serde::de::DeserializeOwned;
+
+    use crate::common::TableReference;
+    use crate::debug_http::{
+        BufferPoolSnapshot, DebugServer, ErrorBody, IndexSnapshot, LockSnapshot, TableSnapshot,
+    };
+    use crate::{Database, RuntimeStats, SharedDatabase, TableLockMode};
+
+    fn request(addr: SocketAddr, method: &str, target: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {target} HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        (status, body.to_string())
+    }
+
+    fn get<T: DeserializeOwned>(addr: SocketAddr, target: &str) -> T {
+        let (status, body) = request(addr, "GET", target);
+        assert_eq!(status, 200, "{target}: {body}");
+        serde_json::from_str(&body).unwrap_or_else(|e| panic!("{target}: {e} in {body}"))
+    }
+
+    fn get_error(addr: SocketAddr, method: &str, target: &str) -> u16 {
+        let (status, body) = request(addr, method, target);
+        serde_json::from_str::<ErrorBody>(&body).unwrap();
+        status
+    }
+
+    #[test]
+    pub fn test_debug_http_endpoints() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t1 (a int, b int)").unwrap();
+        session.run("create table t2 (a int)").unwrap();
+        session.run("create unique index idx_a on t1 (a)").unwrap();
+        session.run("create index idx_a on t2 (a)").unwrap();
+        let values = (0..300)
+            .map(|i| format!("({i}, {})", i * 2))
+            .collect::<Vec<_>>()
+            .join(", ");
+        session
+            .run(&format!("insert into t1 values {values}"))
+            .unwrap();
+        session.run("delete from t1 where a < 100").unwrap();
+        let lock_manager = db.lock().unwrap().lock_manager();
+        lock_manager
+            .lock_table(
+                1000,
+                TableLockMode::AccessShare,
+                &TableReference::bare("t1"),
+                Duration::ZERO,
+            )
+            .unwrap();
+
+        let server = DebugServer::start(db.clone(), 0).unwrap();
+        let addr = server.local_addr();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+
+        let stats: RuntimeStats = get(addr, "/stats");
+        assert_eq!(stats.active_transactions, 1);
+        assert!(stats.pages_allocated > 0);
+
+        let buffer_pool: BufferPoolSnapshot = get(addr, "/buffer_pool");
+        assert_eq!(buffer_pool.frames.len(), buffer_pool.pool_size);
+        assert!(buffer_pool
+            .frames
+            .iter()
+            .enumerate()
+            .all(|(i, frame)| frame.frame_id == i));
+        assert!(buffer_pool
+            .frames
+            .iter()
+            .any(|frame| frame.page_id.is_some()));
+        // nothing is pinned between statements
+        assert!(buffer_pool.frames.iter().all(|frame| frame.pin_count == 0));
+        assert!(buffer_pool
+            .frames
+            .iter()
+            .all(|frame| frame.page_id.is_some() || frame.last_access.is_none()));
+
+        let tables: Vec<TableSnapshot> = get(addr, "/tables");
+        let t1 = tables
+            .iter()
+            .find(|table| table.table.ends_with(".t1"))
+            .unwrap();
+        assert_eq!((t1.live_tuples, t1.dead_tuples), (200, 100));
+        assert_eq!(t1.indexes.len(), 1);
+        assert_eq!(t1.indexes[0].entries, 200);
+
+        let index: IndexSnapshot = get(addr, "/index/idx_a?table=t1");
+        assert!(index.unique);
+        assert_eq!(index.key_columns, vec!["a".to_string()]);
+        assert_eq!(index.stats, t1.indexes[0]);
+        assert_eq!(index.structure, None);
+        let index: IndexSnapshot = get(addr, "/index/idx_a?table=t1&depth=1");
+        let structure = index.structure.unwrap();
+        assert_eq!(structure.matches("B+ Tree Level No.").count(), 1);
+        let index: IndexSnapshot = get(addr, "/index/idx_a?table=public.t2");
+        assert!(!index.unique);
+        assert_eq!(index.stats.entries, 0);
+
+        let locks: Vec<LockSnapshot> = get(addr, "/locks");
+        assert_eq!(
+            locks,
+            vec![LockSnapshot {
+                table: "public.t1".to_string(),
+                txn_id: 1000,
+                mode: "ACCESS SHARE".to_string(),
+                granted: true,
+            }]
+        );
+        lock_manager.unlock_all(1000);
+        assert!(get::<Vec<LockSnapshot>>(addr, "/locks").is_empty());
+
+        // the same index name on two tables needs the table
+        assert_eq!(get_error(addr, "GET", "/index/idx_a"), 400);
+        assert_eq!(get_error(addr, "GET", "/index/idx_a?depth=x"), 400);
+        assert_eq!(get_error(addr, "GET", "/index/missing"), 404);
+        assert_eq!(get_error(addr, "GET", "/unknown"), 404);
+        assert_eq!(get_error(addr, "POST", "/stats"), 405);
+
+        // queries go on while the server runs
+        assert_eq!(
+            session.run("select a from t1 where a = 150").unwrap().len(),
+            1
+        );
+        drop(server);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
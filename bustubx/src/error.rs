@@ -1,5 +1,10 @@
+use std::time::Duration;
 use thiserror::Error;
 
+use crate::common::TableReference;
+use crate::storage::RecordId;
+use crate::transaction::{TableLockMode, TransactionId};
+
 pub type BustubxResult<T, E = BustubxError> = Result<T, E>;
 
 #[derive(Debug, Error)]
@@ -11,11 +16,22 @@ pub enum BustubxError {
     Internal(String),
 
     #[error("IO error: {0}")]
-    Io(#[from] std::io::Error),
+    Io(#[source] std::io::Error),
 
     #[error("Parser error: {0}")]
     Parser(#[from] sqlparser::parser::ParserError),
 
+    /// Parser error of a script statement, positioned in the whole script
+    #[error("Parser error at line {line}, column {column}: {message}")]
+    ParserAt {
+        message: String,
+        // both counted from 1
+        line: usize,
+        column: usize,
+        // byte offset into the script
+        offset: usize,
+    },
+
     #[error("Plan error: {0}")]
     Plan(String),
 
@@ -24,4 +40,144 @@ pub enum BustubxError {
 
     #[error("Storage error: {0}")]
     Storage(String),
+
+    /// Every frame of the buffer pool is pinned, so no page can be read or created
+    #[error("Buffer pool full: {0}")]
+    BufferPoolFull(String),
+
+    #[error("Decode error at offset {offset}: {message}")]
+    Decode { offset: usize, message: String },
+
+    /// Page decoded with a schema encoding its tuples differently than the one it was
+    /// written with, both are fingerprints of [`crate::storage::codec::TupleCodec`]
+    #[error("Schema mismatch, page written with schema {found:#010x} read as {expected:#010x}")]
+    SchemaMismatch { expected: u32, found: u32 },
+
+    /// Tuples inserted into a table page at once do not all fit, the first `fits` of them
+    /// would, see [`crate::storage::TablePage::insert_tuples`]
+    #[error("Table page full, {fits} of {tuples} tuples fit")]
+    TablePageFull { fits: usize, tuples: usize },
+
+    /// Tuple encoded to more bytes than an empty table page holds
+    #[error("Tuple of {size} bytes does not fit into a table page")]
+    TupleTooLarge { size: usize },
+
+    /// Key encoded to more bytes than [`crate::storage::index::BPlusTreeIndex::max_key_size`]
+    #[error("Index key of {size} bytes exceeds the maximum of {max} bytes")]
+    IndexKeyTooLarge { size: usize, max: usize },
+
+    #[error("Config error: {0}")]
+    Config(String),
+
+    /// `SET` or `SHOW` of a name that is no [`crate::Setting`]
+    #[error("Unknown setting {0}")]
+    UnknownSetting(String),
+
+    /// Value of the wrong type or out of the range of the setting
+    #[error("Invalid value {value} for setting {name}: {reason}")]
+    InvalidSetting {
+        name: String,
+        value: String,
+        reason: String,
+    },
+
+    #[error("Disk full: {0}")]
+    DiskFull(String),
+
+    #[error("Not a bustubx database file: {0}")]
+    NotDatabaseFile(String),
+
+    #[error("Corrupt database file: {0}")]
+    CorruptDatabaseFile(String),
+
+    /// Key inserted into a unique index is present already, `rid` is the entry it maps to
+    #[error("Duplicate key in unique index, already held by {rid:?}")]
+    DuplicateKey { rid: RecordId },
+
+    /// Write to a table set read only by `ALTER TABLE ... SET read_only`, rejected when the
+    /// statement is planned
+    #[error("Table {table} is read only")]
+    TableReadOnly { table: TableReference },
+
+    /// Statement run from a trigger callback of `table`, it would wait for the statement
+    /// firing the trigger
+    #[error("Statement issued by a trigger of table {table}")]
+    TriggerReentry { table: TableReference },
+
+    /// Call of a function neither built in nor registered with
+    /// [`crate::Database::register_function`]
+    #[error("Unknown function {0}")]
+    UnknownFunction(String),
+
+    /// The key of an expression index calls a function not registered since the database
+    /// was opened, writes to the table fail until it is registered again
+    #[error("Index {index} of table {table} uses function {function}, which is not registered")]
+    UnresolvedIndexKey {
+        table: String,
+        index: String,
+        function: String,
+    },
+
+    #[error("Relation {table} is locked, {mode} lock not granted within {timeout:?}")]
+    RelationLocked {
+        table: TableReference,
+        mode: TableLockMode,
+        timeout: Duration,
+    },
+
+    /// Row an UPDATE or DELETE is about to change was written by a transaction that did not
+    /// end within [`crate::ExecutionOptions::lock_timeout`]
+    #[error("Row {rid:?} of {table} is locked, write lock not granted within {timeout:?}")]
+    RowLocked {
+        table: TableReference,
+        rid: RecordId,
+        timeout: Duration,
+    },
+
+    /// Statement canceled after running longer than
+    /// [`crate::ExecutionOptions::statement_timeout`], its changes are rolled back
+    #[error("Statement canceled after running longer than {timeout:?}")]
+    StatementTimeout { timeout: Duration },
+
+    /// Statement of a transaction that was idle for longer than
+    /// [`crate::ExecutionOptions::idle_transaction_timeout`], the transaction is aborted and
+    /// its changes are rolled back when it ends
+    #[error("Transaction {txn_id} aborted after being idle for longer than {timeout:?}")]
+    IdleTransactionTimeout {
+        txn_id: TransactionId,
+        timeout: Duration,
+    },
+
+    /// Query as of a transaction whose versions of `table` VACUUM reclaimed since, or that
+    /// committed before the database was opened
+    #[error("History of table {table} as of transaction {txn_id} is no longer available")]
+    HistoryUnavailable {
+        table: TableReference,
+        txn_id: TransactionId,
+    },
+
+    /// Database used after [`crate::Database::close`] or [`crate::SharedDatabase::close`]
+    #[error("Database is closed")]
+    DatabaseClosed,
+}
+
+impl BustubxError {
+    /// Whether the failure came from a conflict with a concurrent transaction, so running
+    /// the transaction again may succeed, see [`crate::Session::run_transaction`].
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            BustubxError::RelationLocked { .. } | BustubxError::RowLocked { .. }
+        )
+    }
+}
+
+impl From<std::io::Error> for BustubxError {
+    fn from(e: std::io::Error) -> Self {
+        if e.kind() == std::io::ErrorKind::StorageFull {
+            BustubxError::DiskFull(e.to_string())
+        } else {
+            BustubxError::Io(e)
+        }
+    }
 }
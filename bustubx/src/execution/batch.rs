@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::catalog::Schema;
+use crate::common::{numeric, ScalarValue};
+use crate::expression::{Alias, BinaryExpr, BinaryOp, ColumnExpr, Expr, ExprTrait, Literal};
+use crate::storage::Tuple;
+use crate::{BustubxError, BustubxResult};
+
+/// Rows an operator hands to its parent at once when batch execution is enabled,
+/// see [`crate::config::ExecutionOptions::batch_execution`].
+#[derive(Debug, Clone, Default)]
+pub struct TupleBatch {
+    pub tuples: Vec<Tuple>,
+}
+
+impl TupleBatch {
+    pub fn new(tuples: Vec<Tuple>) -> Self {
+        Self { tuples }
+    }
+
+    /// Batch of up to `batch_size` rows pulled one by one from a row operator,
+    /// None once the operator is exhausted.
+    pub fn collect(
+        batch_size: usize,
+        mut next: impl FnMut() -> BustubxResult<Option<Tuple>>,
+    ) -> BustubxResult<Option<Self>> {
+        let mut tuples = Vec::with_capacity(batch_size);
+        while tuples.len() < batch_size {
+            match next()? {
+                Some(tuple) => tuples.push(tuple),
+                None => break,
+            }
+        }
+        Ok((!tuples.is_empty()).then(|| Self::new(tuples)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tuples.len()
+    }
+}
+
+/// Next row of a batch operator consumed row by row, the rest of its batch is kept in
+/// `pending` for the following calls.
+pub(crate) fn next_buffered(
+    pending: &Mutex<VecDeque<Tuple>>,
+    mut next_batch: impl FnMut() -> BustubxResult<Option<TupleBatch>>,
+) -> BustubxResult<Option<Tuple>> {
+    let mut pending = pending.lock().unwrap();
+    if pending.is_empty() {
+        match next_batch()? {
+            Some(batch) => pending.extend(batch.tuples),
+            None => return Ok(None),
+        }
+    }
+    Ok(pending.pop_front())
+}
+
+/// Filter predicate compiled against the schema of a batch, comparisons of an integer
+/// column with a constant run without interpreting the expression tree per row.
+#[derive(Debug)]
+pub(crate) enum BatchPredicate<'a> {
+    IntComparison {
+        column: usize,
+        op: BinaryOp,
+        value: i128,
+        // evaluated instead for the rows whose value is null
+        expr: &'a Expr,
+    },
+    And(Box<BatchPredicate<'a>>, Box<BatchPredicate<'a>>),
+    Row(&'a Expr),
+}
+
+impl<'a> BatchPredicate<'a> {
+    pub fn compile(expr: &'a Expr, schema: &Schema) -> Self {
+        if let Expr::Binary(BinaryExpr { left, op, right }) = expr {
+            if *op == BinaryOp::And {
+                return BatchPredicate::And(
+                    Box::new(Self::compile(left, schema)),
+                    Box::new(Self::compile(right, schema)),
+                );
+            }
+            let comparison = match (left.as_ref(), right.as_ref()) {
+                (Expr::Column(column), Expr::Literal(literal)) => Some((column, *op, literal)),
+                (Expr::Literal(literal), Expr::Column(column)) => {
+                    op.flip_comparison().map(|op| (column, op, literal))
+                }
+                _ => None,
+            };
+            if let Some((column, op, literal)) = comparison {
+                if let Some((column, value)) = int_comparison(column, op, literal, schema) {
+                    return BatchPredicate::IntComparison {
+                        column,
+                        op,
+                        value,
+                        expr,
+                    };
+                }
+            }
+        }
+        BatchPredicate::Row(expr)
+    }
+
+    /// Value of the predicate for every row, None where it is null.
+    pub fn evaluate(&self, tuples: &[Tuple]) -> BustubxResult<Vec<Option<bool>>> {
+        match self {
+            BatchPredicate::IntComparison {
+                column,
+                op,
+                value,
+                expr,
+            } => {
+                let mut result = Vec::with_capacity(tuples.len());
+                for tuple in tuples {
+                    result.push(match numeric::integer_value(&tuple.data[*column]) {
+                        Some(v) => Some(match op {
+                            BinaryOp::Eq => v == *value,
+                            BinaryOp::NotEq => v != *value,
+                            BinaryOp::Lt => v < *value,
+                            BinaryOp::LtEq => v <= *value,
+                            BinaryOp::Gt => v > *value,
+                            _ => v >= *value,
+                        }),
+                        None => row_boolean(expr, tuple)?,
+                    });
+                }
+                Ok(result)
+            }
+            BatchPredicate::And(left, right) => {
+                let left = left.evaluate(tuples)?;
+                let right = right.evaluate(tuples)?;
+                // same as the row evaluation, a null operand makes the conjunction false
+                Ok(left
+                    .into_iter()
+                    .zip(right)
+                    .map(|(l, r)| Some(l.unwrap_or(false) && r.unwrap_or(false)))
+                    .collect())
+            }
+            BatchPredicate::Row(expr) => tuples
+                .iter()
+                .map(|tuple| row_boolean(expr, tuple))
+                .collect(),
+        }
+    }
+}
+
+/// Projection expression compiled against the schema of a batch, plain column
+/// references are read by position.
+#[derive(Debug)]
+pub(crate) enum BatchExpr<'a> {
+    Column(usize),
+    Row(&'a Expr),
+}
+
+impl<'a> BatchExpr<'a> {
+    pub fn compile(expr: &'a Expr, schema: &Schema) -> Self {
+        let column = match expr {
+            Expr::Column(column) => Some(column),
+            Expr::Alias(Alias { expr, .. }) => match expr.as_ref() {
+                Expr::Column(column) => Some(column),
+                _ => None,
+            },
+            _ => None,
+        };
+        match column.and_then(|c| schema.index_of(c.relation.as_ref(), &c.name).ok()) {
+            Some(index) => BatchExpr::Column(index),
+            None => BatchExpr::Row(expr),
+        }
+    }
+
+    pub fn evaluate(&self, tuples: &[Tuple]) -> BustubxResult<Vec<ScalarValue>> {
+        match self {
+            BatchExpr::Column(index) => Ok(tuples
+                .iter()
+                .map(|tuple| tuple.data[*index].clone())
+                .collect()),
+            BatchExpr::Row(expr) => tuples.iter().map(|tuple| expr.evaluate(tuple)).collect(),
+        }
+    }
+}
+
+// position of the column and the value of the constant, when they are compared as integers
+fn int_comparison(
+    column: &ColumnExpr,
+    op: BinaryOp,
+    literal: &Literal,
+    schema: &Schema,
+) -> Option<(usize, i128)> {
+    op.flip_comparison()?;
+    let index = schema
+        .index_of(column.relation.as_ref(), &column.name)
+        .ok()?;
+    // the row evaluation compares integers of any width by their exact value as well
+    if !numeric::is_integer(&schema.columns[index].data_type) {
+        return None;
+    }
+    Some((index, numeric::integer_value(&literal.value)?))
+}
+
+fn row_boolean(expr: &Expr, tuple: &Tuple) -> BustubxResult<Option<bool>> {
+    match expr.evaluate(tuple)? {
+        ScalarValue::Boolean(v) => Ok(v),
+        _ => Err(BustubxError::Execution(
+            "filter predicate value should be boolean".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_util::sorted_rows;
+    use crate::common::ScalarValue;
+    use crate::Database;
+
+    #[test]
+    pub fn test_batch_execution_matches_row_execution() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b bigint, c varchar(20))")
+            .unwrap();
+        for chunk in (0..3000).collect::<Vec<_>>().chunks(500) {
+            let values = chunk
+                .iter()
+                .map(|i| match i % 7 {
+                    0 => format!("({i}, null, 'row {i}')"),
+                    _ => format!("({}, {}, 'row {i}')", i % 100, i * 3),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        db.run("create table t2 (a int)").unwrap();
+        db.run("insert into t2 values (1), (2), (3)").unwrap();
+
+        let queries = [
+            "select a, c from t1 where a < 10",
+            "select * from t1 where 50 <= a and b > 300",
+            "select a from t1 where b <> 42",
+            "select b + 1, c from t1 where a = 7 and c = 'row 7'",
+            "select a as x from t1 where b >= 8000000000",
+            "select count(*) from t1 where a > 90",
+            "select t1.a, t2.a from t1, t2 where t1.a = t2.a and t1.b < 100",
+            "select a from t1 where a < 5 limit 3",
+        ];
+        for batch_size in [1, 7, 1024] {
+            db.set_batch_execution(true, batch_size).unwrap();
+            let batched = queries.map(|sql| sorted_rows(&mut db, sql));
+            db.set_batch_execution(false, batch_size).unwrap();
+            for (sql, batched) in queries.iter().zip(batched) {
+                let expected = sorted_rows(&mut db, sql);
+                if sql.contains("limit") {
+                    assert_eq!(batched.len(), expected.len(), "{sql}");
+                } else {
+                    assert_eq!(batched, expected, "{sql} batch size {batch_size}");
+                }
+            }
+        }
+
+        db.set_batch_execution(true, 64).unwrap();
+        assert!(db.set_batch_execution(true, 0).is_err());
+        assert_eq!(
+            sorted_rows(&mut db, "select count(*) from t1 where a < 10")[0],
+            vec![ScalarValue::Int64(Some(259))]
+        );
+        let tree = db
+            .explain("explain analyze select a from t1 where a < 10")
+            .unwrap();
+        assert_eq!(tree.actual_rows, Some(259));
+        assert_eq!(tree.children[0].actual_rows, Some(259));
+        assert_eq!(tree.children[0].children[0].actual_rows, Some(3000));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::catalog::Catalog;
+use crate::common::TableReference;
+use crate::execution::physical_plan::{
+    PhysicalAggregate, PhysicalDelete, PhysicalFilter, PhysicalHashSemiJoin, PhysicalIndexScan,
+    PhysicalLimit, PhysicalNestedLoopJoin, PhysicalParallelSeqScan, PhysicalPlan, PhysicalProject,
+    PhysicalSeqScan, PhysicalSort, PhysicalUpdate,
+};
+use crate::optimizer::TraceEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Machine readable physical plan, serialized as the document of `EXPLAIN FORMAT JSON`.
+///
+/// Every node of the tree is one operator, `children` are its inputs in execution order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanTree {
+    /// Operator type, e.g. `SeqScan` or `NestedLoopJoin`
+    pub operator: String,
+    /// Line the text EXPLAIN prints for the operator
+    pub description: String,
+    /// Expressions evaluated by the operator
+    pub expressions: Vec<String>,
+    /// Rows the operator is expected to produce, known when the scanned table has statistics
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_rows: Option<u64>,
+    /// Rows the operator produced, only filled by EXPLAIN ANALYZE
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual_rows: Option<u64>,
+    pub children: Vec<PlanTree>,
+    /// Decisions of the optimizer, only filled on the root by `EXPLAIN (TRACE)` or with
+    /// `optimizer_trace` on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<TraceEntry>,
+}
+
+impl PlanTree {
+    /// `operator_rows` are the per node row counts collected while executing `plan`.
+    pub fn new(
+        plan: &PhysicalPlan,
+        catalog: &Catalog,
+        operator_rows: Option<&HashMap<usize, u64>>,
+    ) -> Self {
+        let children = plan
+            .inputs()
+            .into_iter()
+            .map(|input| PlanTree::new(input, catalog, operator_rows))
+            .collect::<Vec<_>>();
+        let estimated_rows = estimate_rows(plan, catalog, &children);
+        Self {
+            operator: operator_name(plan).to_string(),
+            description: format!("{plan}"),
+            expressions: operator_expressions(plan),
+            estimated_rows,
+            actual_rows: operator_rows.map(|rows| rows.get(&plan.address()).copied().unwrap_or(0)),
+            children,
+            trace: vec![],
+        }
+    }
+
+    /// Lines of the text EXPLAIN, children are indented under their parent.
+    pub fn text_lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        self.collect_text_lines(0, &mut lines);
+        if !self.trace.is_empty() {
+            lines.push("Optimizer trace:".to_string());
+            lines.extend(self.trace.iter().map(|entry| format!("  {entry}")));
+        }
+        lines
+    }
+
+    fn collect_text_lines(&self, indent: usize, lines: &mut Vec<String>) {
+        let mut line = format!("{:indent$}{}", "", self.description);
+        if let Some(estimated_rows) = self.estimated_rows {
+            line.push_str(&format!(" (estimated rows={estimated_rows})"));
+        }
+        if let Some(actual_rows) = self.actual_rows {
+            line.push_str(&format!(" (actual rows={actual_rows})"));
+        }
+        lines.push(line);
+        for child in self.children.iter() {
+            child.collect_text_lines(indent + 2, lines);
+        }
+    }
+}
+
+pub(crate) fn operator_name(plan: &PhysicalPlan) -> &'static str {
+    match plan {
+        PhysicalPlan::Empty(_) => "Empty",
+        PhysicalPlan::CreateTable(_) => "CreateTable",
+        PhysicalPlan::CreateIndex(_) => "CreateIndex",
+        PhysicalPlan::DropTable(_) => "DropTable",
+        PhysicalPlan::CreateSchema(_) => "CreateSchema",
+        PhysicalPlan::DropSchema(_) => "DropSchema",
+        PhysicalPlan::Project(_) => "Project",
+        PhysicalPlan::Filter(_) => "Filter",
+        PhysicalPlan::SeqScan(_) => "SeqScan",
+        PhysicalPlan::ParallelSeqScan(_) => "ParallelSeqScan",
+        PhysicalPlan::IndexScan(_) => "IndexScan",
+        PhysicalPlan::Append(_) => "Append",
+        PhysicalPlan::Limit(_) => "Limit",
+        PhysicalPlan::Insert(_) => "Insert",
+        PhysicalPlan::Values(_) => "Values",
+        PhysicalPlan::NestedLoopJoin(_) => "NestedLoopJoin",
+        PhysicalPlan::HashSemiJoin(_) => "HashSemiJoin",
+        PhysicalPlan::Sort(_) => "Sort",
+        PhysicalPlan::Aggregate(_) => "Aggregate",
+        PhysicalPlan::Count(_) => "Count",
+        PhysicalPlan::MinMax(_) => "MinMax",
+        PhysicalPlan::Update(_) => "Update",
+        PhysicalPlan::Delete(_) => "Delete",
+        PhysicalPlan::Maintenance(_) => "Maintenance",
+    }
+}
+
+fn operator_expressions(plan: &PhysicalPlan) -> Vec<String> {
+    match plan {
+        PhysicalPlan::Project(PhysicalProject { exprs, .. }) => {
+            exprs.iter().map(|e| format!("{e}")).collect()
+        }
+        PhysicalPlan::Filter(PhysicalFilter { predicate, .. })
+        | PhysicalPlan::ParallelSeqScan(PhysicalParallelSeqScan {
+            predicate: Some(predicate),
+            ..
+        }) => vec![format!("{predicate}")],
+        PhysicalPlan::NestedLoopJoin(PhysicalNestedLoopJoin { condition, .. }) => {
+            condition.iter().map(|e| format!("{e}")).collect()
+        }
+        PhysicalPlan::HashSemiJoin(PhysicalHashSemiJoin {
+            left_keys,
+            right_keys,
+            ..
+        }) => left_keys
+            .iter()
+            .zip(right_keys.iter())
+            .map(|(l, r)| format!("{l} = {r}"))
+            .collect(),
+        PhysicalPlan::Sort(PhysicalSort { order_bys, .. }) => {
+            order_bys.iter().map(|e| format!("{e}")).collect()
+        }
+        PhysicalPlan::Aggregate(PhysicalAggregate {
+            group_exprs,
+            aggr_exprs,
+            grouping_sets,
+            ..
+        }) => {
+            let mut exprs = aggr_exprs
+                .iter()
+                .chain(group_exprs.iter())
+                .map(|e| format!("{e}"))
+                .collect::<Vec<_>>();
+            if !grouping_sets.is_empty() {
+                let sets = grouping_sets
+                    .iter()
+                    .map(|set| {
+                        let exprs = set
+                            .iter()
+                            .map(|idx| format!("{}", group_exprs[*idx]))
+                            .collect::<Vec<_>>();
+                        format!("({})", exprs.join(", "))
+                    })
+                    .collect::<Vec<_>>();
+                exprs.push(format!("GROUPING SETS ({})", sets.join(", ")));
+            }
+            exprs
+        }
+        PhysicalPlan::Update(PhysicalUpdate {
+            assignments,
+            selection,
+            ..
+        }) => {
+            let mut exprs = assignments
+                .iter()
+                .map(|(col, e)| format!("{col} = {e}"))
+                .collect::<Vec<_>>();
+            exprs.sort();
+            exprs.extend(selection.iter().map(|e| format!("{e}")));
+            exprs
+        }
+        PhysicalPlan::Delete(PhysicalDelete { selection, .. }) => {
+            selection.iter().map(|e| format!("{e}")).collect()
+        }
+        PhysicalPlan::Insert(insert) => insert
+            .default_exprs()
+            .into_iter()
+            .map(|(name, default_expr)| format!("{name} DEFAULT {default_expr}"))
+            .collect(),
+        PhysicalPlan::Empty(_)
+        | PhysicalPlan::CreateTable(_)
+        | PhysicalPlan::CreateIndex(_)
+        | PhysicalPlan::DropTable(_)
+        | PhysicalPlan::CreateSchema(_)
+        | PhysicalPlan::DropSchema(_)
+        | PhysicalPlan::SeqScan(_)
+        | PhysicalPlan::ParallelSeqScan(_)
+        | PhysicalPlan::Append(_)
+        | PhysicalPlan::IndexScan(_)
+        | PhysicalPlan::Count(_)
+        | PhysicalPlan::MinMax(_)
+        | PhysicalPlan::Maintenance(_)
+        | PhysicalPlan::Limit(_)
+        | PhysicalPlan::Values(_) => vec![],
+    }
+}
+
+fn estimate_rows(plan: &PhysicalPlan, catalog: &Catalog, children: &[PlanTree]) -> Option<u64> {
+    let table_rows = |table: &TableReference| {
+        catalog
+            .table_statistics(table)
+            .map(|stats| stats.row_count as u64)
+    };
+    match plan {
+        PhysicalPlan::SeqScan(PhysicalSeqScan { table, .. })
+        | PhysicalPlan::ParallelSeqScan(PhysicalParallelSeqScan {
+            table,
+            predicate: None,
+            ..
+        }) => table_rows(table),
+        PhysicalPlan::ParallelSeqScan(PhysicalParallelSeqScan {
+            table,
+            predicate: Some(predicate),
+            ..
+        }) => {
+            let stats = catalog.table_statistics(table)?;
+            Some((stats.row_count as f64 * stats.selectivity(predicate)).round() as u64)
+        }
+        PhysicalPlan::IndexScan(PhysicalIndexScan { table_ref, .. }) => table_rows(table_ref),
+        PhysicalPlan::Filter(PhysicalFilter {
+            predicate, input, ..
+        }) => {
+            let table = match input.as_ref() {
+                PhysicalPlan::SeqScan(PhysicalSeqScan { table, .. }) => table,
+                PhysicalPlan::IndexScan(PhysicalIndexScan { table_ref, .. }) => table_ref,
+                _ => return None,
+            };
+            let stats = catalog.table_statistics(table)?;
+            Some((stats.row_count as f64 * stats.selectivity(predicate)).round() as u64)
+        }
+        PhysicalPlan::Limit(PhysicalLimit { limit, offset, .. }) => {
+            let input_rows = children.first()?.estimated_rows?;
+            let rows = input_rows.saturating_sub(*offset as u64);
+            Some(limit.map_or(rows, |limit| rows.min(limit as u64)))
+        }
+        PhysicalPlan::Project(_) | PhysicalPlan::Sort(_) => children.first()?.estimated_rows,
+        PhysicalPlan::Append(_) => children.iter().map(|child| child.estimated_rows).sum(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::optimizer::{TraceDecision, TraceReason};
+    use crate::{Database, PlanTree};
+
+    fn new_db() -> Database {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create table t2 (a int, c int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20), (3, 30)")
+            .unwrap();
+        db.run("insert into t2 values (1, 100), (2, 100), (3, 300)")
+            .unwrap();
+        db
+    }
+
+    fn operators(tree: &PlanTree) -> Vec<String> {
+        let mut names = vec![tree.operator.clone()];
+        for child in tree.children.iter() {
+            names.extend(operators(child));
+        }
+        names
+    }
+
+    #[test]
+    pub fn test_explain_json_round_trip() {
+        let mut db = new_db();
+        let sql = "select t2.c, count(t1.b) from t1 inner join t2 on t1.a = t2.a \
+                   group by t2.c order by t2.c";
+        let tree = db.explain(sql).unwrap();
+        let operators = operators(&tree);
+        for operator in ["Sort", "Aggregate", "NestedLoopJoin", "SeqScan"] {
+            assert!(operators.iter().any(|op| op == operator), "{operators:?}");
+        }
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(serde_json::from_str::<PlanTree>(&json).unwrap(), tree);
+
+        let rows = db.run(&format!("explain (format json) {sql}")).unwrap();
+        assert_eq!(rows.len(), 1);
+        let ScalarValue::Varchar(Some(document)) = &rows[0].data[0] else {
+            panic!("unexpected explain output {:?}", rows[0]);
+        };
+        assert_eq!(serde_json::from_str::<PlanTree>(document).unwrap(), tree);
+
+        // one text line per node, in the same order
+        let text = db
+            .run(&format!("explain {sql}"))
+            .unwrap()
+            .into_iter()
+            .map(|tuple| tuple.data[0].clone())
+            .collect::<Vec<_>>();
+        let expected = tree
+            .text_lines()
+            .into_iter()
+            .map(ScalarValue::from)
+            .collect::<Vec<_>>();
+        assert_eq!(text, expected);
+        assert_eq!(text.len(), operators.len());
+
+        let mut value = serde_json::to_value(&tree).unwrap();
+        value["unknown"] = 1.into();
+        assert!(serde_json::from_value::<PlanTree>(value).is_err());
+    }
+
+    #[test]
+    pub fn test_explain_analyze_actual_rows() {
+        let mut db = new_db();
+        let tree = db.explain("select a from t1 where a > 1").unwrap();
+        assert_eq!(tree.actual_rows, None);
+
+        let tree = db
+            .explain("explain analyze select a from t1 where a > 1")
+            .unwrap();
+        assert_eq!(tree.operator, "Project");
+        assert_eq!(tree.actual_rows, Some(2));
+        let filter = &tree.children[0];
+        assert_eq!(filter.operator, "Filter");
+        assert_eq!(filter.expressions.len(), 1);
+        assert_eq!(filter.actual_rows, Some(2));
+        assert_eq!(filter.children[0].actual_rows, Some(3));
+    }
+
+    #[test]
+    pub fn test_explain_optimizer_trace() {
+        let mut db = new_db();
+        db.run("create index t1_ab on t1 (a, b)").unwrap();
+        assert!(db
+            .explain("select a from t1 where b = 20")
+            .unwrap()
+            .trace
+            .is_empty());
+
+        let tree = db
+            .explain("explain (trace) select a from t1 where b = 20")
+            .unwrap();
+        let entry = |decision: TraceDecision| {
+            tree.trace
+                .iter()
+                .find(|entry| entry.decision == decision)
+                .unwrap_or_else(|| panic!("no {decision} in {:?}", tree.trace))
+                .clone()
+        };
+        let index = entry(TraceDecision::IndexSelection);
+        assert_eq!(index.subject, "t1_ab (a, b) on t1");
+        assert!(!index.applied);
+        assert_eq!(
+            index.reason,
+            TraceReason::LeadingColumnNotCompared {
+                leading: "a".to_string(),
+                compared: vec!["b".to_string()],
+            }
+        );
+        assert_eq!(
+            entry(TraceDecision::SelectivityEstimate).reason,
+            TraceReason::MissingStatistics {
+                table: "t1".to_string()
+            }
+        );
+        assert!(!entry(TraceDecision::PredicatePushdown).applied);
+        let limit = entry(TraceDecision::LimitPushdown);
+        assert_eq!(
+            (limit.subject.as_str(), limit.applied),
+            ("PushDownLimit", false)
+        );
+
+        // the setting traces every EXPLAIN of the session, shown after the plan
+        db.run("analyze t1").unwrap();
+        db.run("set optimizer_trace = on").unwrap();
+        let explain = |db: &mut Database, sql: &str| {
+            let lines = db
+                .run(&format!("explain {sql}"))
+                .unwrap()
+                .into_iter()
+                .map(|tuple| tuple.data[0].to_string())
+                .collect::<Vec<_>>();
+            let start = lines.iter().position(|line| line == "Optimizer trace:");
+            lines[start.unwrap_or_else(|| panic!("no trace in {lines:?}"))..].to_vec()
+        };
+        let trace = explain(&mut db, "select a from t1 where a = 2");
+        assert!(
+            trace
+                .iter()
+                .any(|line| line
+                    .starts_with("  selectivity estimate: t1 used, estimated selectivity")),
+            "{trace:?}"
+        );
+        assert!(
+            trace.contains(
+                &"  index selection: t1_ab (a, b) on t1 skipped, key (a, b) has several \
+                  columns, ranges are only made for single column indexes"
+                    .to_string()
+            ),
+            "{trace:?}"
+        );
+
+        let trace = explain(
+            &mut db,
+            "select * from t1 inner join t2 on t1.a = t2.a where t1.a = 2",
+        );
+        assert!(
+            trace.contains(
+                &"  join reordering: Inner join skipped, joins are not reordered, they run \
+                  in the order written"
+                    .to_string()
+            ),
+            "{trace:?}"
+        );
+        assert!(
+            trace
+                .iter()
+                .any(|line| line.starts_with("  predicate pushdown: ")
+                    && line.contains("skipped, the predicate is above a NestedLoopJoin")),
+            "{trace:?}"
+        );
+    }
+}
@@ -1,10 +1,28 @@
+mod batch;
+mod explain;
 pub mod physical_plan;
+mod trigger;
 
+use log::error;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::catalog::SchemaRef;
-use crate::execution::physical_plan::PhysicalPlan;
-use crate::{catalog::Catalog, storage::Tuple, BustubxResult};
+use crate::common::{ClockRef, TableReference};
+use crate::config::ExecutionOptions;
+use crate::execution::physical_plan::{unique_violation, PhysicalPlan};
+use crate::storage::index::{ConstraintTiming, TreeIndexIterator};
+use crate::storage::RecordId;
+use crate::transaction::{LockManager, Snapshot, TransactionId, TransactionManager};
+use crate::{catalog::Catalog, storage::Tuple, BustubxError, BustubxResult};
+
+pub use batch::TupleBatch;
+pub(crate) use batch::{next_buffered, BatchExpr, BatchPredicate};
+pub(crate) use explain::operator_name;
+pub use explain::PlanTree;
+pub(crate) use trigger::check_not_in_trigger;
+pub use trigger::{TriggerAction, TriggerContext, TriggerEvent, TriggerFn, Triggers};
 
 pub trait VolcanoExecutor {
     fn init(&self, _context: &mut ExecutionContext) -> BustubxResult<()> {
@@ -19,13 +37,363 @@ pub trait VolcanoExecutor {
 #[derive(derive_new::new)]
 pub struct ExecutionContext<'a> {
     pub catalog: &'a mut Catalog,
+    pub options: &'a ExecutionOptions,
+    pub clock: ClockRef,
+    // rows produced per plan node keyed by the node address, only collected by EXPLAIN ANALYZE
+    #[new(default)]
+    pub operator_rows: Option<HashMap<usize, u64>>,
+    // rows inserted by the running statement, hidden from its scans, see `is_visible`
+    #[new(default)]
+    pub inserted_rids: HashSet<RecordId>,
+    // heap and index changes of the running statement, undone when the statement fails
+    #[new(default)]
+    pub undo_log: Vec<UndoRecord>,
+    // table locks are taken around the statement when set, with `txn_id` as the owner
+    #[new(default)]
+    pub lock_manager: Option<Arc<LockManager>>,
+    #[new(default)]
+    pub txn_id: TransactionId,
+    // owner of the row locks the statement takes, its transaction or the statement itself
+    #[new(default)]
+    pub row_lock_txn_id: TransactionId,
+    // open transactions of sessions, whose writes VACUUM keeps
+    #[new(default)]
+    pub txn_manager: Option<Arc<TransactionManager>>,
+    // the statement is canceled once this passed, see `ExecutionOptions::statement_timeout`
+    #[new(default)]
+    pub deadline: Option<Instant>,
+    // scans read the rows as of an earlier transaction when set, see `snapshot_txn`
+    #[new(default)]
+    pub snapshot: Option<Snapshot>,
+    // `SET CONSTRAINTS` of the transaction the statement is part of, the INITIALLY timing
+    // of every deferrable unique index and foreign key applies when not set
+    #[new(default)]
+    pub constraints: Option<ConstraintTiming>,
+}
+
+/// Changes of the statements a transaction ran so far and the timing `SET CONSTRAINTS`
+/// gave the deferrable unique indexes and foreign keys for the rest of it.
+#[derive(Debug, Default)]
+pub struct TransactionWrites {
+    // holds the row locks of the statements until it ends
+    pub txn_id: TransactionId,
+    // latest last
+    pub undo_log: Vec<UndoRecord>,
+    pub constraints: Option<ConstraintTiming>,
+}
+
+/// Change made by the running statement. A heap change is recorded before the indexes
+/// are touched, followed by the index entries it added or removed.
+#[derive(Debug)]
+pub enum UndoRecord {
+    Insert {
+        table: TableReference,
+        rid: RecordId,
+    },
+    Update {
+        table: TableReference,
+        rid: RecordId,
+        old_tuple: Tuple,
+    },
+    Delete {
+        table: TableReference,
+        rid: RecordId,
+    },
+    IndexInsert {
+        table: TableReference,
+        index_name: String,
+        key: Tuple,
+        entry: RecordId,
+    },
+    IndexDelete {
+        table: TableReference,
+        index_name: String,
+        key: Tuple,
+        entry: RecordId,
+    },
+    // the indexes were bulk loaded from the heap after rows were deleted from it
+    IndexesRebuilt {
+        table: TableReference,
+    },
+}
+
+impl UndoRecord {
+    pub fn table(&self) -> &TableReference {
+        match self {
+            UndoRecord::Insert { table, .. }
+            | UndoRecord::Update { table, .. }
+            | UndoRecord::Delete { table, .. }
+            | UndoRecord::IndexInsert { table, .. }
+            | UndoRecord::IndexDelete { table, .. }
+            | UndoRecord::IndexesRebuilt { table } => table,
+        }
+    }
+
+    /// Whether the record is a row written to the heap, not an index change following one.
+    pub fn is_row_change(&self) -> bool {
+        matches!(
+            self,
+            UndoRecord::Insert { .. } | UndoRecord::Update { .. } | UndoRecord::Delete { .. }
+        )
+    }
+}
+
+impl ExecutionContext<'_> {
+    /// Cancellation point of long running operators, fails the statement once its
+    /// deadline passed.
+    pub fn check_deadline(&self) -> BustubxResult<()> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(BustubxError::StatementTimeout {
+                timeout: self.options.statement_timeout,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the scans of the statement return the live row at `rid`. Transactions run
+    /// one at a time and write in place, so a live row is committed or written by an
+    /// earlier statement of the running transaction, which its later statements see before
+    /// it commits. Only the rows the statement inserted itself are hidden from it, so that
+    /// e.g. `INSERT INTO t SELECT * FROM t` never reads its own inserts.
+    pub fn is_visible(&self, rid: RecordId) -> bool {
+        !self.inserted_rids.contains(&rid)
+    }
+
+    /// Take the write lock of a row the statement is about to change, held until the
+    /// transaction of the statement ends. Waits like the table locks, see `lock_timeout`.
+    pub fn lock_row(&self, table: &TableReference, rid: RecordId) -> BustubxResult<()> {
+        match &self.lock_manager {
+            Some(lock_manager) => {
+                lock_manager.lock_row(self.row_lock_txn_id, table, rid, lock_wait(self.options))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Check the keys the statement inserted into the deferrable unique indexes and the
+    /// rows it wrote against the foreign keys checked at the end of every statement.
+    pub fn check_immediate_keys(&self) -> BustubxResult<()> {
+        let constraints = self.constraints;
+        let immediate = |initially| constraints.unwrap_or(initially) == ConstraintTiming::Immediate;
+        check_deferrable_keys(self.catalog, &self.undo_log, immediate)?;
+        // SET CONSTRAINTS leaves a foreign key that is not deferrable alone
+        check_foreign_keys(self.catalog, &self.undo_log, |deferrable| {
+            deferrable.is_none_or(immediate)
+        })
+    }
+
+    /// Undo the heap and index changes of the failed statement in reverse order. Tables
+    /// whose indexes the statement bulk loaded get them bulk loaded again afterwards.
+    pub fn rollback_statement(&mut self) -> BustubxResult<()> {
+        let mut rebuilt: Vec<TableReference> = vec![];
+        while let Some(record) = self.undo_log.pop() {
+            match record {
+                UndoRecord::Insert { table, rid } => {
+                    let catalog_table = self.catalog.catalog_table(&table)?;
+                    let mut meta = catalog_table.table.tuple_meta(rid)?;
+                    if let (Some(row_ids), Some(row_id)) = (&catalog_table.row_ids, meta.row_id) {
+                        row_ids.unmap(row_id)?;
+                    }
+                    meta.is_deleted = true;
+                    catalog_table.table.update_tuple_meta(meta, rid)?;
+                    self.inserted_rids.remove(&rid);
+                }
+                UndoRecord::Update {
+                    table,
+                    rid,
+                    old_tuple,
+                } => {
+                    self.catalog
+                        .table_heap(&table)?
+                        .update_tuple(rid, old_tuple)?;
+                }
+                UndoRecord::Delete { table, rid } => {
+                    let catalog_table = self.catalog.catalog_table(&table)?;
+                    let mut meta = catalog_table.table.tuple_meta(rid)?;
+                    // a delete leaving the indexes to a rebuild kept the mapping
+                    if let (Some(row_ids), Some(row_id)) = (&catalog_table.row_ids, meta.row_id) {
+                        if row_ids.resolve(row_id)?.is_none() {
+                            row_ids.map(row_id, rid)?;
+                        }
+                    }
+                    meta.is_deleted = false;
+                    catalog_table.table.update_tuple_meta(meta, rid)?;
+                }
+                UndoRecord::IndexInsert {
+                    table,
+                    index_name,
+                    key,
+                    entry,
+                } => {
+                    let catalog_table = self.catalog.catalog_table(&table)?;
+                    if let Some(index) = catalog_table.indexes.get(&index_name) {
+                        index.delete_entry(&key, entry)?;
+                    }
+                }
+                UndoRecord::IndexDelete {
+                    table,
+                    index_name,
+                    key,
+                    entry,
+                } => {
+                    let catalog_table = self.catalog.catalog_table(&table)?;
+                    if let Some(index) = catalog_table.indexes.get(&index_name) {
+                        index.insert(&key, entry)?;
+                    }
+                }
+                UndoRecord::IndexesRebuilt { table } => {
+                    if !rebuilt.contains(&table) {
+                        rebuilt.push(table);
+                    }
+                }
+            }
+        }
+        for table in rebuilt.iter() {
+            self.catalog.rebuild_indexes(table)?;
+        }
+        Ok(())
+    }
 }
 
 pub struct ExecutionEngine<'a> {
     pub context: ExecutionContext<'a>,
 }
+/// Fails on the first key `undo_log` inserted into a deferrable unique index that more than
+/// one live row holds, among the indexes `checked` picks by their INITIALLY timing.
+pub fn check_deferrable_keys(
+    catalog: &Catalog,
+    undo_log: &[UndoRecord],
+    checked: impl Fn(ConstraintTiming) -> bool,
+) -> BustubxResult<()> {
+    for record in undo_log {
+        let UndoRecord::IndexInsert {
+            table,
+            index_name,
+            key,
+            ..
+        } = record
+        else {
+            continue;
+        };
+        // NULLs never conflict with each other
+        if key.data.iter().any(|v| v.is_null()) {
+            continue;
+        }
+        // the table or the index was dropped since
+        let Ok(catalog_table) = catalog.catalog_table(table) else {
+            continue;
+        };
+        let Some(index) = catalog_table.indexes.get(index_name) else {
+            continue;
+        };
+        match index.deferrable {
+            Some(initially) if index.unique && checked(initially) => {}
+            _ => continue,
+        }
+        let mut live_rows = 0;
+        let mut entries = TreeIndexIterator::new(index.clone(), key.clone()..=key.clone());
+        while let Some(entry) = entries.next()? {
+            let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                continue;
+            };
+            if !catalog_table.table.tuple_meta(rid)?.is_deleted {
+                live_rows += 1;
+            }
+        }
+        if live_rows > 1 {
+            return Err(unique_violation(index_name));
+        }
+    }
+    Ok(())
+}
+
+/// Fails on the first row `undo_log` wrote that breaks a foreign key `checked` picks by its
+/// INITIALLY timing, None for one that is not deferrable: a row referencing a key no parent
+/// row holds, or the last parent row holding a key rows still reference deleted or changed.
+pub fn check_foreign_keys(
+    catalog: &Catalog,
+    undo_log: &[UndoRecord],
+    checked: impl Fn(Option<ConstraintTiming>) -> bool,
+) -> BustubxResult<()> {
+    if !catalog.has_foreign_keys() {
+        return Ok(());
+    }
+    for record in undo_log {
+        // the table was dropped since
+        let Ok(catalog_table) = catalog.catalog_table(record.table()) else {
+            continue;
+        };
+        let heap = &catalog_table.table;
+        match record {
+            UndoRecord::Insert { table, rid } => {
+                if let Some(tuple) = heap.live_tuple(*rid)? {
+                    catalog.check_key_referenced(table, &tuple, &checked)?;
+                }
+            }
+            UndoRecord::Update {
+                table,
+                rid,
+                old_tuple,
+            } => {
+                if let Some(tuple) = heap.live_tuple(*rid)? {
+                    catalog.check_key_referenced(table, &tuple, &checked)?;
+                }
+                catalog.check_key_unreferenced(table, old_tuple, &checked)?;
+            }
+            UndoRecord::Delete { table, rid } => {
+                let (meta, tuple) = heap.full_tuple(*rid)?;
+                if meta.is_deleted {
+                    catalog.check_key_unreferenced(table, &tuple, &checked)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// how long a lock request waits, a zero `lock_timeout` waits as long as it takes
+fn lock_wait(options: &ExecutionOptions) -> Duration {
+    if options.lock_timeout.is_zero() {
+        Duration::MAX
+    } else {
+        options.lock_timeout
+    }
+}
+
 impl ExecutionEngine<'_> {
+    /// Run the plan as one statement, a failing statement leaves no changes behind.
+    /// The table locks of the plan are held until the statement finished.
     pub fn execute(&mut self, plan: Arc<PhysicalPlan>) -> BustubxResult<Vec<Tuple>> {
+        let options = self.context.options;
+        if !options.statement_timeout.is_zero() {
+            self.context.deadline = Instant::now().checked_add(options.statement_timeout);
+        }
+        let _table_locks = match &self.context.lock_manager {
+            Some(lock_manager) => Some(lock_manager.lock_tables(
+                self.context.txn_id,
+                plan.table_locks(),
+                lock_wait(options),
+            )?),
+            None => None,
+        };
+        self.context.undo_log.clear();
+        // a duplicate key the statement left in a deferrable unique index fails it like one
+        // found right away
+        let result = self
+            .execute_plan(plan)
+            .and_then(|rows| self.context.check_immediate_keys().map(|_| rows));
+        if result.is_err() {
+            if let Err(e) = self.context.rollback_statement() {
+                error!("failed to roll back statement: {e}");
+            }
+            self.context.undo_log.clear();
+        }
+        // the undo log of a succeeded statement is left for the transaction it is part of
+        result
+    }
+
+    fn execute_plan(&mut self, plan: Arc<PhysicalPlan>) -> BustubxResult<Vec<Tuple>> {
         plan.init(&mut self.context)?;
         let mut result = Vec::new();
         loop {
@@ -1,14 +1,26 @@
+use crate::buffer::BufferPoolManager;
 use crate::catalog::SchemaRef;
 use crate::common::ScalarValue;
 use crate::execution::physical_plan::PhysicalPlan;
 use crate::execution::{ExecutionContext, VolcanoExecutor};
-use crate::expression::{Expr, ExprTrait};
-use crate::function::Accumulator;
+use crate::expression::{AggregateFunction, Expr, ExprTrait};
+use crate::function::{Accumulator, AggregateFunctionKind, GroupingAccumulator};
+use crate::storage::{TableHeap, TableIterator, EMPTY_TUPLE_META};
 use crate::{BustubxError, BustubxResult, Tuple};
+use log::{debug, warn};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
+// Number of temp heaps rows of groups over the memory budget are hashed into
+const SPILL_PARTITIONS: usize = 8;
+// Partitions are split again at most this many times, deeper ones aggregate over budget
+const MAX_SPILL_DEPTH: usize = 4;
+// Rough in-memory footprint of one accumulator
+const ACCUMULATOR_SIZE_ESTIMATE: usize = 64;
+
 #[derive(Debug)]
 pub struct PhysicalAggregate {
     /// The incoming physical plan
@@ -17,6 +29,8 @@ pub struct PhysicalAggregate {
     pub group_exprs: Vec<Expr>,
     /// Aggregate expressions
     pub aggr_exprs: Vec<Expr>,
+    /// Indexes into `group_exprs` of every grouping set, empty for a plain GROUP BY
+    pub grouping_sets: Vec<Vec<usize>>,
     /// The schema description of the aggregate output
     pub schema: SchemaRef,
 
@@ -35,20 +49,36 @@ impl PhysicalAggregate {
             input,
             group_exprs,
             aggr_exprs,
+            grouping_sets: vec![],
             schema,
             output_rows: Mutex::new(vec![]),
             cursor: AtomicUsize::new(0),
         }
     }
+
+    /// Aggregate the input once for every grouping set instead of grouping by all the
+    /// group expressions.
+    pub fn with_grouping_sets(mut self, grouping_sets: Vec<Vec<usize>>) -> Self {
+        self.grouping_sets = grouping_sets;
+        self
+    }
 }
 
 impl PhysicalAggregate {
-    fn build_accumulators(&self) -> BustubxResult<Vec<Box<dyn Accumulator>>> {
+    fn build_accumulators(
+        &self,
+        grouping_set: &[usize],
+    ) -> BustubxResult<Vec<Box<dyn Accumulator>>> {
         self.aggr_exprs
             .iter()
             .map(|expr| {
                 if let Expr::AggregateFunction(aggr) = expr {
-                    Ok(aggr.func_kind.create_accumulator())
+                    if aggr.func_kind == AggregateFunctionKind::Grouping {
+                        let mask = self.grouping_mask(aggr, grouping_set)?;
+                        return Ok(Box::new(GroupingAccumulator::new(mask)) as Box<dyn Accumulator>);
+                    }
+                    let data_type = aggr.data_type(&self.input.output_schema())?;
+                    Ok(aggr.func_kind.create_accumulator(data_type))
                 } else {
                     Err(BustubxError::Execution(format!(
                         "aggr expr is not AggregateFunction instead of {}",
@@ -58,6 +88,211 @@ impl PhysicalAggregate {
             })
             .collect::<BustubxResult<Vec<Box<dyn Accumulator>>>>()
     }
+
+    // GROUPING(..) of the groups of a grouping set
+    fn grouping_mask(
+        &self,
+        grouping: &AggregateFunction,
+        grouping_set: &[usize],
+    ) -> BustubxResult<i64> {
+        let mut mask = 0;
+        for arg in grouping.args.iter() {
+            let idx = self
+                .group_exprs
+                .iter()
+                .position(|e| e == arg)
+                .ok_or_else(|| {
+                    BustubxError::Execution(format!("GROUPING argument {} is not grouped by", arg))
+                })?;
+            mask = (mask << 1) | i64::from(!grouping_set.contains(&idx));
+        }
+        Ok(mask)
+    }
+
+    // The grouping sets to aggregate, a plain GROUP BY is the set of all group expressions
+    fn effective_grouping_sets(&self) -> Vec<Vec<usize>> {
+        if self.grouping_sets.is_empty() {
+            vec![(0..self.group_exprs.len()).collect()]
+        } else {
+            self.grouping_sets.clone()
+        }
+    }
+
+    /// Hash aggregate the rows produced by `next_row` into `output_rows`, grouping by the
+    /// group expressions of `grouping_set`, the others are null in the output.
+    ///
+    /// Groups are kept in memory until their estimated size reaches `budget`, rows of groups
+    /// seen after that are hashed into temp heaps which are aggregated one by one afterwards.
+    fn aggregate(
+        &self,
+        next_row: &mut dyn FnMut() -> BustubxResult<Option<Tuple>>,
+        grouping_set: &[usize],
+        buffer_pool: &Arc<BufferPoolManager>,
+        budget: usize,
+        depth: usize,
+        output_rows: &mut Vec<Tuple>,
+    ) -> BustubxResult<()> {
+        let mut groups: HashMap<Vec<ScalarValue>, Vec<Box<dyn Accumulator>>> = HashMap::new();
+        let mut memory_used = 0;
+        let mut partitions = SpillPartitions::new(self.input.output_schema(), buffer_pool.clone());
+        let input_schema = self.input.output_schema();
+        // null of the group expressions rolled up in the grouping set
+        let rolled_up = self
+            .group_exprs
+            .iter()
+            .enumerate()
+            .map(|(idx, e)| {
+                if grouping_set.contains(&idx) {
+                    Ok(None)
+                } else {
+                    Ok(Some(ScalarValue::new_empty(e.data_type(&input_schema)?)))
+                }
+            })
+            .collect::<BustubxResult<Vec<Option<ScalarValue>>>>()?;
+        while let Some(tuple) = next_row()? {
+            let group_key = self
+                .group_exprs
+                .iter()
+                .zip(rolled_up.iter())
+                .map(|(e, null)| match null {
+                    Some(null) => Ok(null.clone()),
+                    None => e.evaluate(&tuple),
+                })
+                .collect::<BustubxResult<Vec<ScalarValue>>>()?;
+            let group_accumulators = if let Some(acc) = groups.get_mut(&group_key) {
+                acc
+            } else {
+                let group_size = self.group_size_estimate(&group_key);
+                if memory_used + group_size > budget && depth < MAX_SPILL_DEPTH {
+                    partitions.spill(&group_key, depth, &tuple)?;
+                    continue;
+                }
+                memory_used += group_size;
+                let accumulators = self.build_accumulators(grouping_set)?;
+                groups.insert(group_key.clone(), accumulators);
+                groups.get_mut(&group_key).unwrap()
+            };
+            for (idx, acc) in group_accumulators.iter_mut().enumerate() {
+                acc.update_value(&self.aggr_exprs[idx].evaluate(&tuple)?)?;
+            }
+        }
+        // grouping by nothing, without a group expression or for the () set of ROLLUP, CUBE
+        // and GROUPING SETS, there is a row over no input too, a COUNT of 0 and NULL for the
+        // other aggregates
+        if grouping_set.is_empty()
+            && depth == 0
+            && groups.is_empty()
+            && partitions.heaps.iter().all(Option::is_none)
+        {
+            groups.insert(
+                rolled_up.into_iter().flatten().collect(),
+                self.build_accumulators(grouping_set)?,
+            );
+        }
+        if depth >= MAX_SPILL_DEPTH && memory_used > budget {
+            warn!(
+                "aggregate exceeded memory budget {} with {} bytes of groups",
+                budget, memory_used
+            );
+        }
+
+        for (group_key, accumulators) in groups.into_iter() {
+            let mut values = accumulators
+                .iter()
+                .map(|acc| acc.evaluate())
+                .collect::<BustubxResult<Vec<ScalarValue>>>()?;
+            values.extend(group_key);
+            output_rows.push(Tuple::new(self.schema.clone(), values));
+        }
+
+        // every group lives in exactly one partition
+        while let Some(partition) = partitions.heaps.pop() {
+            let Some(heap) = partition else {
+                continue;
+            };
+            debug!("aggregate spilled partition at depth {}", depth);
+            let heap = Arc::new(heap);
+            let result = {
+                let mut iterator = TableIterator::new(heap.clone(), ..);
+                self.aggregate(
+                    &mut || Ok(iterator.next()?.map(|(_, tuple)| tuple)),
+                    grouping_set,
+                    buffer_pool,
+                    budget,
+                    depth + 1,
+                    output_rows,
+                )
+            };
+            heap.destroy()?;
+            result?;
+        }
+        Ok(())
+    }
+
+    fn group_size_estimate(&self, group_key: &[ScalarValue]) -> usize {
+        let key_size: usize = group_key
+            .iter()
+            .map(|value| {
+                std::mem::size_of::<ScalarValue>()
+                    + match value {
+                        ScalarValue::Varchar(Some(v)) => v.len(),
+                        ScalarValue::Bytea(Some(v)) => v.len(),
+                        _ => 0,
+                    }
+            })
+            .sum();
+        key_size + self.aggr_exprs.len() * ACCUMULATOR_SIZE_ESTIMATE
+    }
+}
+
+/// Temp heaps holding spilled input rows, destroyed when dropped so errors do not leak pages.
+struct SpillPartitions {
+    schema: SchemaRef,
+    buffer_pool: Arc<BufferPoolManager>,
+    heaps: Vec<Option<TableHeap>>,
+}
+
+impl SpillPartitions {
+    fn new(schema: SchemaRef, buffer_pool: Arc<BufferPoolManager>) -> Self {
+        Self {
+            schema,
+            buffer_pool,
+            heaps: (0..SPILL_PARTITIONS).map(|_| None).collect(),
+        }
+    }
+
+    fn spill(
+        &mut self,
+        group_key: &[ScalarValue],
+        depth: usize,
+        tuple: &Tuple,
+    ) -> BustubxResult<()> {
+        // seed with the depth so a partition is split differently when aggregated again
+        let mut hasher = DefaultHasher::new();
+        depth.hash(&mut hasher);
+        group_key.hash(&mut hasher);
+        let partition = hasher.finish() as usize % SPILL_PARTITIONS;
+
+        if self.heaps[partition].is_none() {
+            self.heaps[partition] = Some(TableHeap::try_new_spill(
+                self.schema.clone(),
+                self.buffer_pool.clone(),
+            )?);
+        }
+        let heap = self.heaps[partition].as_ref().unwrap();
+        heap.insert_tuple(&EMPTY_TUPLE_META, tuple)?;
+        Ok(())
+    }
+}
+
+impl Drop for SpillPartitions {
+    fn drop(&mut self) {
+        for heap in self.heaps.drain(..).flatten() {
+            if let Err(e) = heap.destroy() {
+                warn!("failed to destroy aggregate spill partition: {}", e);
+            }
+        }
+    }
 }
 
 impl VolcanoExecutor for PhysicalAggregate {
@@ -71,36 +306,46 @@ impl VolcanoExecutor for PhysicalAggregate {
         let output_rows_len = self.output_rows.lock().unwrap().len();
         // build output rows
         if output_rows_len == 0 {
-            let mut groups: HashMap<Vec<ScalarValue>, Vec<Box<dyn Accumulator>>> = HashMap::new();
-            while let Some(tuple) = self.input.next(context)? {
-                let group_key = self
-                    .group_exprs
-                    .iter()
-                    .map(|e| e.evaluate(&tuple))
-                    .collect::<BustubxResult<Vec<ScalarValue>>>()?;
-                let group_accumulators = if let Some(acc) = groups.get_mut(&group_key) {
-                    acc
-                } else {
-                    let accumulators = self.build_accumulators()?;
-                    groups.insert(group_key.clone(), accumulators);
-                    groups.get_mut(&group_key).unwrap()
-                };
-                for (idx, acc) in group_accumulators.iter_mut().enumerate() {
-                    acc.update_value(&self.aggr_exprs[idx].evaluate(&tuple)?)?;
-                }
-            }
-
-            for (group_key, accumulators) in groups.into_iter() {
-                let mut values = accumulators
-                    .iter()
-                    .map(|acc| acc.evaluate())
-                    .collect::<BustubxResult<Vec<ScalarValue>>>()?;
-                values.extend(group_key);
-                self.output_rows
-                    .lock()
-                    .unwrap()
-                    .push(Tuple::new(self.schema.clone(), values));
+            let budget = context.options.aggregate_memory_budget;
+            let buffer_pool = context.catalog.buffer_pool.clone();
+            let mut output_rows = vec![];
+            let grouping_sets = self.effective_grouping_sets();
+            if let [grouping_set] = grouping_sets.as_slice() {
+                self.aggregate(
+                    &mut || self.input.next(context),
+                    grouping_set,
+                    &buffer_pool,
+                    budget,
+                    0,
+                    &mut output_rows,
+                )?;
+            } else {
+                // every grouping set reads the input again from a temp heap
+                let heap = Arc::new(TableHeap::try_new_spill(
+                    self.input.output_schema(),
+                    buffer_pool.clone(),
+                )?);
+                let result = (|| -> BustubxResult<()> {
+                    while let Some(tuple) = self.input.next(context)? {
+                        heap.insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+                    }
+                    for grouping_set in grouping_sets.iter() {
+                        let mut iterator = TableIterator::new(heap.clone(), ..);
+                        self.aggregate(
+                            &mut || Ok(iterator.next()?.map(|(_, tuple)| tuple)),
+                            grouping_set,
+                            &buffer_pool,
+                            budget,
+                            0,
+                            &mut output_rows,
+                        )?;
+                    }
+                    Ok(())
+                })();
+                heap.destroy()?;
+                result?;
             }
+            *self.output_rows.lock().unwrap() = output_rows;
         }
 
         let cursor = self.cursor.fetch_add(1, Ordering::SeqCst);
@@ -117,3 +362,123 @@ impl std::fmt::Display for PhysicalAggregate {
         write!(f, "Aggregate")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::config::ExecutionOptions;
+    use crate::execution::{ExecutionContext, ExecutionEngine};
+    use crate::optimizer::LogicalOptimizer;
+    use crate::planner::PhysicalPlanner;
+    use crate::{BustubxError, BustubxResult, Database};
+    use std::sync::Arc;
+
+    fn run_with_budget(
+        db: &mut Database,
+        sql: &str,
+        budget: usize,
+    ) -> BustubxResult<Vec<Vec<ScalarValue>>> {
+        let logical_plan = db.create_logical_plan(sql)?;
+        let logical_plan = LogicalOptimizer::new().optimize(&logical_plan)?;
+        let physical_plan = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(logical_plan)?;
+        let options = ExecutionOptions {
+            aggregate_memory_budget: budget,
+            ..Default::default()
+        };
+        let mut engine = ExecutionEngine {
+            context: ExecutionContext::new(&mut db.catalog, &options, db.clock.clone()),
+        };
+        let mut rows = engine
+            .execute(Arc::new(physical_plan))?
+            .into_iter()
+            .map(|tuple| tuple.data)
+            .collect::<Vec<_>>();
+        rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Ok(rows)
+    }
+
+    #[test]
+    pub fn test_aggregate_spill_matches_in_memory() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint, b bigint)").unwrap();
+        // 20k groups, the first 1k of them with a second row
+        let rows = (0..20_000i64)
+            .map(|i| format!("({}, {})", i, i % 7 + 1))
+            .chain((0..1_000i64).map(|i| format!("({}, {})", i, i % 3 + 1)))
+            .collect::<Vec<_>>();
+        for chunk in rows.chunks(1000) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        let sql = "select count(b), avg(b), a from t1 group by a";
+        let disk_manager = db.buffer_pool.disk_manager.clone();
+
+        let in_memory = run_with_budget(&mut db, sql, usize::MAX).unwrap();
+        assert_eq!(in_memory.len(), 20_000);
+        let file_len = disk_manager.db_file_len().unwrap();
+
+        let spilled = run_with_budget(&mut db, sql, 64 * 1024).unwrap();
+        assert_eq!(spilled, in_memory);
+        assert!(disk_manager.db_file_len().unwrap() > file_len);
+
+        // temp pages are given back, running again reuses them
+        let file_len = disk_manager.db_file_len().unwrap();
+        assert_eq!(run_with_budget(&mut db, sql, 64 * 1024).unwrap(), in_memory);
+        assert_eq!(disk_manager.db_file_len().unwrap(), file_len);
+
+        // the failing row belongs to a group that is only aggregated from a spilled partition
+        db.run("insert into t1 values (20000, 0)").unwrap();
+        assert!(matches!(
+            run_with_budget(
+                &mut db,
+                "select count(10 / b), a from t1 group by a",
+                64 * 1024
+            ),
+            Err(BustubxError::Execution(_))
+        ));
+        // the failed run gave its temp pages back too
+        let file_len = disk_manager.db_file_len().unwrap();
+        assert!(run_with_budget(&mut db, sql, 64 * 1024).is_ok());
+        assert_eq!(disk_manager.db_file_len().unwrap(), file_len);
+    }
+
+    #[test]
+    pub fn test_aggregate_grouping_sets_spill() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a bigint, b bigint, c bigint)")
+            .unwrap();
+        let rows = (0..20_000i64)
+            .map(|i| format!("({}, {}, {})", i % 2000, i % 3, i))
+            .collect::<Vec<_>>();
+        for chunk in rows.chunks(1000) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+        let sql = "select count(c), grouping(a, b), a, b from t1 group by rollup(a, b)";
+
+        let in_memory = run_with_budget(&mut db, sql, usize::MAX).unwrap();
+        // the 10 rows of every a cover the 3 b values
+        assert_eq!(in_memory.len(), 6000 + 2000 + 1);
+        let count_of = |mask: i64| {
+            in_memory
+                .iter()
+                .filter(|row| row[1] == ScalarValue::Int64(Some(mask)))
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(count_of(0)
+            .iter()
+            .all(|count| [3, 4].map(|c| ScalarValue::Int64(Some(c))).contains(count)));
+        assert!(count_of(1)
+            .iter()
+            .all(|count| count == &ScalarValue::Int64(Some(10))));
+        assert_eq!(count_of(3), vec![ScalarValue::Int64(Some(20_000))]);
+
+        assert_eq!(run_with_budget(&mut db, sql, 16 * 1024).unwrap(), in_memory);
+    }
+}
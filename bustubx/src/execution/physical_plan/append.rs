@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::catalog::SchemaRef;
+use crate::{
+    execution::{ExecutionContext, VolcanoExecutor},
+    storage::Tuple,
+    BustubxResult,
+};
+
+use super::PhysicalPlan;
+
+/// Rows of every input, one input after the other. Scans the partitions of a partitioned
+/// table, the rows are handed out as rows of the partitioned table.
+#[derive(Debug)]
+pub struct PhysicalAppend {
+    pub inputs: Vec<Arc<PhysicalPlan>>,
+    pub schema: SchemaRef,
+
+    // input the next row is taken from
+    cursor: AtomicUsize,
+}
+
+impl PhysicalAppend {
+    pub fn new(inputs: Vec<Arc<PhysicalPlan>>, schema: SchemaRef) -> Self {
+        PhysicalAppend {
+            inputs,
+            schema,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl VolcanoExecutor for PhysicalAppend {
+    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        for input in self.inputs.iter() {
+            input.init(context)?;
+        }
+        self.cursor.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        loop {
+            let cursor = self.cursor.load(Ordering::SeqCst);
+            let Some(input) = self.inputs.get(cursor) else {
+                return Ok(None);
+            };
+            if let Some(tuple) = input.next(context)? {
+                return Ok(Some(Tuple::new(self.schema.clone(), tuple.data)));
+            }
+            self.cursor.store(cursor + 1, Ordering::SeqCst);
+        }
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalAppend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Append")
+    }
+}
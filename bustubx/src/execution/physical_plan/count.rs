@@ -0,0 +1,292 @@
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::catalog::SchemaRef;
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::storage::index::TreeIndexIterator;
+use crate::storage::TableIterator;
+use crate::{BustubxResult, Tuple};
+
+/// Where a [`PhysicalCount`] takes its row count from.
+#[derive(Debug, Clone)]
+pub enum CountSource {
+    /// Every row of the table, from the live tuple counter or the heap page headers
+    Table,
+    /// Entries of a single column index in the key range of the predicate
+    IndexRange {
+        index_name: String,
+        start_bound: Bound<Tuple>,
+        end_bound: Bound<Tuple>,
+    },
+}
+
+/// `COUNT(*)` of a table without grouping, answered without decoding the rows when the
+/// stored counts match what a scan would return.
+#[derive(Debug)]
+pub struct PhysicalCount {
+    pub table_ref: TableReference,
+    pub source: CountSource,
+    // one count column per COUNT(*) of the aggregate
+    pub schema: SchemaRef,
+
+    done: AtomicBool,
+}
+
+impl PhysicalCount {
+    pub fn new(table_ref: TableReference, source: CountSource, schema: SchemaRef) -> Self {
+        Self {
+            table_ref,
+            source,
+            schema,
+            done: AtomicBool::new(false),
+        }
+    }
+
+    fn count(&self, context: &ExecutionContext) -> BustubxResult<usize> {
+        let catalog_table = context.catalog.catalog_table(&self.table_ref)?;
+        let table_heap = &catalog_table.table;
+        // hidden expired rows and rows the statement inserted itself are not in the
+        // stored counts, such rows are told apart by decoding them
+        let expire_before = (context.options.hide_expired_rows
+            && catalog_table.ttl_column.is_some())
+        .then(|| context.clock.now());
+        let exact = expire_before.is_none() && context.inserted_rids.is_empty();
+
+        let visible = |rid, tuple: Option<Tuple>| -> BustubxResult<bool> {
+            if !context.is_visible(rid) {
+                return Ok(false);
+            }
+            match expire_before {
+                Some(now) => {
+                    let tuple = match tuple {
+                        Some(tuple) => tuple,
+                        None => table_heap.tuple(rid)?,
+                    };
+                    Ok(!catalog_table.is_expired(&tuple, now)?)
+                }
+                None => Ok(true),
+            }
+        };
+
+        match &self.source {
+            CountSource::Table => {
+                if exact {
+                    if let Some(live_tuples) = table_heap.live_tuple_count() {
+                        return Ok(live_tuples);
+                    }
+                    let (_, live_tuples, _) = table_heap.count_pages()?;
+                    return Ok(live_tuples);
+                }
+                let mut count = 0;
+                let mut iterator = TableIterator::new(table_heap.clone(), ..);
+                while let Some((rid, tuple)) = iterator.next()? {
+                    if !table_heap.tuple_meta(rid)?.is_deleted && visible(rid, Some(tuple))? {
+                        count += 1;
+                    }
+                }
+                Ok(count)
+            }
+            CountSource::IndexRange {
+                index_name,
+                start_bound,
+                end_bound,
+            } => {
+                let index = context.catalog.index(&self.table_ref, index_name)?.unwrap();
+                // counted as a scan of the index, no row is fetched
+                catalog_table
+                    .access_stats
+                    .index(index_name)
+                    .scans
+                    .fetch_add(1, Ordering::Relaxed);
+                if exact {
+                    return Ok(
+                        index.count_range(start_bound.as_ref(), end_bound.as_ref())? as usize
+                    );
+                }
+                let mut iterator =
+                    TreeIndexIterator::new(index, (start_bound.clone(), end_bound.clone()));
+                let mut count = 0;
+                while let Some(entry) = iterator.next_rid()? {
+                    let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                        continue;
+                    };
+                    if visible(rid, None)? {
+                        count += 1;
+                    }
+                }
+                Ok(count)
+            }
+        }
+    }
+}
+
+impl VolcanoExecutor for PhysicalCount {
+    fn init(&self, _context: &mut ExecutionContext) -> BustubxResult<()> {
+        self.done.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return Ok(None);
+        }
+        let count = ScalarValue::Int64(Some(self.count(context)? as i64));
+        Ok(Some(Tuple::new(
+            self.schema.clone(),
+            vec![count; self.schema.column_count()],
+        )))
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.source {
+            CountSource::Table => write!(f, "Count: {}", self.table_ref),
+            CountSource::IndexRange { index_name, .. } => {
+                write!(f, "Count: {} using {}", self.table_ref, index_name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{MockClock, ScalarValue, TableReference};
+    use crate::{Database, DatabaseOptions};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn count(db: &mut Database, sql: &str) -> i64 {
+        let rows = db.run(sql).unwrap();
+        assert_eq!(rows.len(), 1, "{sql}");
+        match rows[0].data[0] {
+            ScalarValue::Int64(Some(count)) => count,
+            ref value => panic!("unexpected count {value}"),
+        }
+    }
+
+    fn plan_operators(db: &mut Database, sql: &str) -> Vec<String> {
+        let mut operators = vec![];
+        let mut pending = vec![db.explain(sql).unwrap()];
+        while let Some(tree) = pending.pop() {
+            operators.push(tree.operator);
+            pending.extend(tree.children);
+        }
+        operators
+    }
+
+    // count of the naive plan, every row decoded and filtered
+    fn scanned_rows(db: &mut Database, sql: &str) -> i64 {
+        db.run(sql).unwrap().len() as i64
+    }
+
+    #[test]
+    pub fn test_count_without_decoding_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        for chunk in (0..1000).collect::<Vec<_>>().chunks(100) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({a}, 'padding {a}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        db.run("insert into t1 values (null, 'null key')").unwrap();
+        db.run("delete from t1 where a >= 900").unwrap();
+        let table_ref = TableReference::full("bustubx", "public", "t1");
+        let decodes = |db: &Database| db.catalog.table_heap(&table_ref).unwrap().tuple_decodes();
+
+        let sql = "select count(*) from t1";
+        assert!(plan_operators(&mut db, sql).contains(&"Count".to_string()));
+        let before = decodes(&db);
+        assert_eq!(count(&mut db, sql), 901);
+        assert_eq!(decodes(&db), before);
+        assert_eq!(scanned_rows(&mut db, "select a from t1"), 901);
+        // the scan decodes every live tuple
+        assert!(decodes(&db) - before >= 901);
+
+        let sql = "select count(*) from t1 where a >= 100 and a < 300";
+        assert!(plan_operators(&mut db, sql).contains(&"Count".to_string()));
+        let before = decodes(&db);
+        assert_eq!(count(&mut db, sql), 200);
+        assert_eq!(decodes(&db), before);
+        assert_eq!(
+            scanned_rows(&mut db, "select a from t1 where a >= 100 and a < 300"),
+            200
+        );
+        assert_eq!(
+            count(&mut db, "select count(*) from t1 where 500 <= a"),
+            400
+        );
+        assert_eq!(count(&mut db, "select count(*) from t1 where a = 42"), 1);
+        assert_eq!(count(&mut db, "select count(*) from t1 where a = 950"), 0);
+
+        // an unbounded start would count the null key
+        for sql in [
+            "select count(*) from t1 where a < 300",
+            "select count(a) from t1",
+            "select count(*) from t1 where b = 'padding 1'",
+        ] {
+            assert!(
+                !plan_operators(&mut db, sql).contains(&"Count".to_string()),
+                "{sql}"
+            );
+        }
+        assert_eq!(count(&mut db, "select count(*) from t1 where a < 300"), 300);
+
+        // reopened the live counter is unknown, the heap page headers are counted instead
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(
+            db.catalog
+                .table_heap(&table_ref)
+                .unwrap()
+                .live_tuple_count(),
+            None
+        );
+        let before = decodes(&db);
+        assert_eq!(count(&mut db, "select count(*) from t1"), 901);
+        assert_eq!(decodes(&db), before);
+        assert_eq!(
+            db.catalog
+                .table_heap(&table_ref)
+                .unwrap()
+                .live_tuple_count(),
+            Some(901)
+        );
+        db.run("insert into t1 values (2000, 'late')").unwrap();
+        assert_eq!(count(&mut db, "select count(*) from t1"), 902);
+        assert_eq!(scanned_rows(&mut db, "select a from t1"), 902);
+    }
+
+    #[test]
+    pub fn test_count_hidden_expired_rows() {
+        let clock = Arc::new(MockClock::new(1000));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        db.run(
+            "create table cache (k int, v int, expires_at bigint) with (ttl_column = 'expires_at')",
+        )
+        .unwrap();
+        db.run("create index cache_k on cache (k)").unwrap();
+        db.run("insert into cache values (1, 10, 100), (2, 20, 200), (3, 30, 4102444800), (4, 40, 4102444800)")
+            .unwrap();
+
+        assert_eq!(count(&mut db, "select count(*) from cache"), 4);
+        assert_eq!(count(&mut db, "select count(*) from cache where k >= 2"), 3);
+        db.set_hide_expired_rows(true);
+        assert_eq!(count(&mut db, "select count(*) from cache"), 2);
+        assert_eq!(count(&mut db, "select count(*) from cache where k >= 2"), 2);
+        assert_eq!(scanned_rows(&mut db, "select k from cache where k >= 2"), 2);
+    }
+}
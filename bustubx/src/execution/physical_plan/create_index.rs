@@ -1,7 +1,8 @@
-use crate::catalog::{SchemaRef, EMPTY_SCHEMA_REF};
+use crate::catalog::{Column, IndexBuild, KeyPart, Schema, SchemaRef, EMPTY_SCHEMA_REF};
 use crate::common::TableReference;
-use crate::expression::{ColumnExpr, Expr};
+use crate::expression::{Alias, ColumnExpr, Expr, ExprTrait};
 use crate::planner::logical_plan::OrderByExpr;
+use crate::storage::index::ConstraintTiming;
 use crate::{
     execution::{ExecutionContext, VolcanoExecutor},
     storage::Tuple,
@@ -15,15 +16,33 @@ pub struct PhysicalCreateIndex {
     pub table: TableReference,
     pub table_schema: SchemaRef,
     pub columns: Vec<OrderByExpr>,
+    pub unique: bool,
+    pub deferrable: Option<ConstraintTiming>,
 }
 
 impl VolcanoExecutor for PhysicalCreateIndex {
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
         let mut key_indices = vec![];
+        let mut key_columns = vec![];
+        let mut parts = vec![];
         for col in self.columns.iter() {
             match col.expr.as_ref() {
                 Expr::Column(ColumnExpr { name, .. }) => {
-                    key_indices.push(self.table_schema.index_of(None, name)?);
+                    let ordinal = self.table_schema.index_of(None, name)?;
+                    key_indices.push(ordinal);
+                    key_columns.push(
+                        self.table_schema
+                            .column_with_index(ordinal)?
+                            .as_ref()
+                            .clone(),
+                    );
+                    parts.push(KeyPart::Column(ordinal));
+                }
+                // an expression key, named after its SQL text by the planner
+                Expr::Alias(Alias { expr, name }) => {
+                    let data_type = expr.data_type(&self.table_schema)?;
+                    key_columns.push(Column::new(name.clone(), data_type, true));
+                    parts.push(KeyPart::Expr(expr.as_ref().clone()));
                 }
                 _ => {
                     return Err(BustubxError::Execution(format!(
@@ -33,10 +52,30 @@ impl VolcanoExecutor for PhysicalCreateIndex {
                 }
             }
         }
-        let key_schema = Arc::new(self.table_schema.project(&key_indices)?);
+        // a key of plain columns is looked up by name, like the indexes loaded on open
+        let (key_schema, parts) = if key_indices.len() == self.columns.len() {
+            (Arc::new(self.table_schema.project(&key_indices)?), None)
+        } else {
+            (Arc::new(Schema::new(key_columns)), Some(parts))
+        };
         context
             .catalog
-            .create_index(self.name.clone(), &self.table, key_schema)?;
+            .create_index_with_build(
+                self.name.clone(),
+                &self.table,
+                key_schema,
+                parts,
+                self.unique,
+                self.deferrable,
+                &IndexBuild::from_options(context.options),
+            )
+            .map_err(|e| match e {
+                BustubxError::DuplicateKey { .. } => BustubxError::Execution(format!(
+                    "could not create unique index {}, the table holds duplicate keys",
+                    self.name
+                )),
+                e => e,
+            })?;
         Ok(None)
     }
     fn output_schema(&self) -> SchemaRef {
@@ -0,0 +1,32 @@
+use crate::catalog::{SchemaRef, EMPTY_SCHEMA_REF};
+use crate::{
+    execution::{ExecutionContext, VolcanoExecutor},
+    storage::Tuple,
+    BustubxResult,
+};
+
+#[derive(derive_new::new, Debug)]
+pub struct PhysicalCreateSchema {
+    pub schema_name: String,
+    pub if_not_exists: bool,
+}
+
+impl VolcanoExecutor for PhysicalCreateSchema {
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        if self.if_not_exists && context.catalog.schemas.contains_key(&self.schema_name) {
+            return Ok(None);
+        }
+        context.catalog.create_schema(self.schema_name.clone())?;
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        EMPTY_SCHEMA_REF.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalCreateSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CreateSchema: {}", self.schema_name)
+    }
+}
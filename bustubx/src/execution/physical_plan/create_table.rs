@@ -1,4 +1,4 @@
-use crate::catalog::SchemaRef;
+use crate::catalog::{ForeignKey, Partitioning, SchemaRef};
 use crate::common::TableReference;
 use crate::{
     catalog::Schema,
@@ -12,13 +12,30 @@ use std::sync::Arc;
 pub struct PhysicalCreateTable {
     pub table: TableReference,
     pub schema: Schema,
+    pub ttl_column: Option<String>,
+    pub logical_row_ids: bool,
+    pub partitioning: Option<Partitioning>,
+    pub foreign_keys: Vec<ForeignKey>,
 }
 
 impl VolcanoExecutor for PhysicalCreateTable {
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
-        context
-            .catalog
-            .create_table(self.table.clone(), Arc::new(self.schema.clone()))?;
+        context.catalog.create_table(
+            self.table.clone(),
+            Arc::new(self.schema.clone()),
+            self.ttl_column.clone(),
+            self.logical_row_ids,
+            self.partitioning.clone(),
+        )?;
+        if !self.foreign_keys.is_empty() {
+            context
+                .catalog
+                .set_foreign_keys(&self.table, self.foreign_keys.clone())?;
+        }
+        // rows of a table of the same name dropped before are no versions of this one
+        if let Some(txn_manager) = &context.txn_manager {
+            txn_manager.forget_history(&self.table);
+        }
         Ok(None)
     }
     fn output_schema(&self) -> SchemaRef {
@@ -0,0 +1,376 @@
+use crate::catalog::{SchemaRef, DELETE_OUTPUT_SCHEMA_REF};
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::{ExecutionContext, TriggerEvent, UndoRecord, VolcanoExecutor};
+use crate::expression::{Expr, ExprTrait};
+use crate::{BustubxError, BustubxResult, Tuple};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use super::dml_order::{lock_latest_row, DmlOrder, DmlRows};
+
+#[derive(Debug)]
+pub struct PhysicalDelete {
+    pub table: TableReference,
+    pub table_schema: SchemaRef,
+    pub selection: Option<Expr>,
+    pub order: DmlOrder,
+
+    delete_rows: AtomicU32,
+    // skip per row index maintenance and rebuild the indexes once the heap is done
+    rebuild_indexes: AtomicBool,
+    rows: Mutex<Option<DmlRows>>,
+}
+
+impl PhysicalDelete {
+    pub fn new(table: TableReference, table_schema: SchemaRef, selection: Option<Expr>) -> Self {
+        Self {
+            table,
+            table_schema,
+            selection,
+            order: DmlOrder::default(),
+            delete_rows: AtomicU32::new(0),
+            rebuild_indexes: AtomicBool::new(false),
+            rows: Mutex::new(None),
+        }
+    }
+
+    pub fn with_order(mut self, order: DmlOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Estimated fraction of the table the statement deletes, unknown for a filtered
+    /// delete on a table without statistics.
+    fn estimated_fraction(&self, context: &ExecutionContext) -> Option<f64> {
+        match &self.selection {
+            None => Some(1.0),
+            Some(selection) => context
+                .catalog
+                .table_statistics(&self.table)
+                .map(|stats| stats.selectivity(selection)),
+        }
+    }
+}
+
+impl VolcanoExecutor for PhysicalDelete {
+    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        self.delete_rows.store(0, Ordering::SeqCst);
+        let threshold = context.options.index_rebuild_threshold as f64 / 100.0;
+        // a limited delete usually removes a few rows, the indexes are kept up per row
+        let rebuild_indexes = self.order.limit.is_none()
+            && self
+                .estimated_fraction(context)
+                .is_some_and(|fraction| fraction > threshold);
+        self.rebuild_indexes
+            .store(rebuild_indexes, Ordering::SeqCst);
+        let rows = DmlRows::new(&self.order, &self.table, self.selection.as_ref(), context)?;
+        *self.rows.lock().unwrap() = Some(rows);
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        let Some(rows) = &mut *self.rows.lock().unwrap() else {
+            return Err(BustubxError::Execution(
+                "table iterator not created".to_string(),
+            ));
+        };
+        let table_heap = context.catalog.table_heap(&self.table)?;
+        let catalog_table = context.catalog.catalog_table(&self.table)?;
+        let access_stats = catalog_table.access_stats.clone();
+        let modifications = catalog_table.modifications.clone();
+        let triggers = catalog_table.triggers.clone();
+        let rebuild_indexes = self.rebuild_indexes.load(Ordering::SeqCst);
+
+        while let Some((rid, tuple)) = rows.next()? {
+            if table_heap.tuple_meta(rid)?.is_deleted {
+                continue;
+            }
+            if let Some(selection) = &self.selection {
+                if !selection.evaluate(&tuple)?.as_boolean()?.unwrap_or(false) {
+                    continue;
+                }
+            }
+            let Some(tuple) = lock_latest_row(
+                context,
+                &self.table,
+                &table_heap,
+                rid,
+                tuple,
+                self.selection.as_ref(),
+            )?
+            else {
+                continue;
+            };
+            if !triggers.fire_before(
+                TriggerEvent::BeforeDelete,
+                &self.table,
+                Some(rid),
+                Some(&tuple),
+                None,
+            )? {
+                continue;
+            }
+            context.undo_log.push(UndoRecord::Delete {
+                table: self.table.clone(),
+                rid,
+            });
+            if rebuild_indexes {
+                let mut meta = table_heap.tuple_meta(rid)?;
+                meta.is_deleted = true;
+                table_heap.update_tuple_meta(meta, rid)?;
+            } else {
+                let removed = context.catalog.delete_tuple(&self.table, rid)?;
+                context
+                    .undo_log
+                    .extend(removed.into_iter().map(|(index_name, key, entry)| {
+                        UndoRecord::IndexDelete {
+                            table: self.table.clone(),
+                            index_name,
+                            key,
+                            entry,
+                        }
+                    }));
+            }
+            self.delete_rows.fetch_add(1, Ordering::SeqCst);
+            access_stats.tuples_deleted.fetch_add(1, Ordering::Relaxed);
+            modifications.row_deleted();
+            triggers.fire_after(
+                TriggerEvent::AfterDelete,
+                &self.table,
+                rid,
+                Some(&tuple),
+                None,
+            )?;
+        }
+
+        if self.delete_rows.load(Ordering::SeqCst) == 0 {
+            return Ok(None);
+        }
+        if rebuild_indexes {
+            context.catalog.rebuild_indexes(&self.table)?;
+            context.undo_log.push(UndoRecord::IndexesRebuilt {
+                table: self.table.clone(),
+            });
+        }
+        let delete_rows = self.delete_rows.swap(0, Ordering::SeqCst);
+        Ok(Some(Tuple::new(
+            self.output_schema(),
+            vec![ScalarValue::Int32(Some(delete_rows as i32))],
+        )))
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        DELETE_OUTPUT_SCHEMA_REF.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalDelete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Delete: {}{}", self.table, self.order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{ANALYZE_SAMPLE_SIZE, DEFAULT_HISTOGRAM_BUCKETS};
+    use crate::common::{ScalarValue, TableReference};
+    use crate::execution::{TriggerAction, TriggerEvent, TriggerFn};
+    use crate::storage::index::TreeIndexIterator;
+    use crate::storage::{RecordId, TableIterator};
+    use crate::{Database, Tuple};
+    use std::sync::Arc;
+
+    const ROWS: i32 = 2000;
+
+    fn fixture(index_rebuild_threshold: u32) -> Database {
+        let mut db = Database::new_temp().unwrap();
+        db.set_index_rebuild_threshold(index_rebuild_threshold)
+            .unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b, a)").unwrap();
+        let rows = (0..ROWS).collect::<Vec<_>>();
+        for chunk in rows.chunks(100) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({}, {})", a, a % 7))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        db.catalog
+            .analyze_table(
+                &TableReference::bare("t1"),
+                DEFAULT_HISTOGRAM_BUCKETS,
+                ANALYZE_SAMPLE_SIZE,
+            )
+            .unwrap();
+        db
+    }
+
+    // (key read back from the heap, rid) of every index entry in index order
+    fn index_entries(db: &Database, index_name: &str) -> Vec<(Tuple, RecordId)> {
+        let table_ref = TableReference::bare("t1");
+        let index = db.catalog.index(&table_ref, index_name).unwrap().unwrap();
+        let table_heap = db.catalog.table_heap(&table_ref).unwrap();
+        let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+        let mut entries = vec![];
+        while let Some(rid) = iterator.next().unwrap() {
+            assert!(!table_heap.tuple_meta(rid).unwrap().is_deleted);
+            let key = table_heap
+                .tuple(rid)
+                .unwrap()
+                .project_with_schema(index.key_schema.clone())
+                .unwrap();
+            entries.push((key, rid));
+        }
+        entries
+    }
+
+    #[test]
+    pub fn test_delete_index_rebuild_matches_per_row() {
+        let mut per_row_db = fixture(100);
+        let mut rebuild_db = fixture(0);
+
+        let mut fetches = vec![];
+        for db in [&mut per_row_db, &mut rebuild_db] {
+            let before = db.buffer_pool.stats().fetches();
+            let rows = db.run("delete from t1 where a >= 200").unwrap();
+            fetches.push(db.buffer_pool.stats().fetches() - before);
+            assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(ROWS - 200))]);
+        }
+
+        for index_name in ["idx_a", "idx_b"] {
+            let entries = index_entries(&per_row_db, index_name);
+            assert_eq!(entries.len(), 200);
+            assert_eq!(entries, index_entries(&rebuild_db, index_name));
+        }
+        for db in [&mut per_row_db, &mut rebuild_db] {
+            let rows = db.run("select a from t1 where a = 150").unwrap();
+            assert_eq!(rows.len(), 1);
+            assert!(db
+                .run("select a from t1 where a = 1500")
+                .unwrap()
+                .is_empty());
+            db.run("insert into t1 values (5000, 1)").unwrap();
+            assert_eq!(db.run("select a from t1 where a = 5000").unwrap().len(), 1);
+        }
+
+        // one heap scan and a bulk load beat thousands of B+ tree deletes and merges
+        assert!(fetches[1] < fetches[0], "{fetches:?}");
+    }
+
+    #[test]
+    pub fn test_delete_oldest_rows_by_index() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table events (id int, ts bigint)").unwrap();
+        db.run("create index idx_ts on events (ts)").unwrap();
+        // timestamps out of insertion order
+        let values = (0..50)
+            .map(|id| format!("({id}, {})", (id * 37) % 50 + 1000))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into events values {values}"))
+            .unwrap();
+
+        let sql = "delete from events order by ts limit 10";
+        let plan = db.explain(sql).unwrap();
+        assert_eq!(plan.operator, "Delete");
+        assert_eq!(
+            plan.description,
+            "Delete: events order by index idx_ts limit 10"
+        );
+        assert_eq!(
+            db.explain("delete from events order by ts desc limit 10")
+                .unwrap()
+                .description,
+            "Delete: events order by index idx_ts desc limit 10"
+        );
+
+        let timestamps = |db: &mut Database| {
+            db.run("select ts from events order by ts")
+                .unwrap()
+                .iter()
+                .map(|tuple| tuple.data[0].clone())
+                .collect::<Vec<_>>()
+        };
+        for round in 0..5 {
+            let rows = db.run(sql).unwrap();
+            assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(10))]);
+            let expected = (1000 + (round + 1) * 10..1050)
+                .map(|ts| ScalarValue::Int64(Some(ts)))
+                .collect::<Vec<_>>();
+            assert_eq!(timestamps(&mut db), expected);
+        }
+        assert!(db.run(sql).unwrap().is_empty());
+
+        // the newest rows are read from the end of the index
+        db.run("insert into events values (1, 10), (2, 30), (3, 20), (4, 40)")
+            .unwrap();
+        db.run("delete from events order by ts desc limit 2")
+            .unwrap();
+        assert_eq!(
+            timestamps(&mut db),
+            vec![ScalarValue::Int64(Some(10)), ScalarValue::Int64(Some(20))]
+        );
+        db.run("delete from events").unwrap();
+
+        // without an index the matching rows are sorted
+        db.run("insert into events values (1, 3), (2, 1), (3, 2), (4, 1)")
+            .unwrap();
+        let sql = "delete from events where id > 1 order by ts desc, id limit 2";
+        let description = db.explain(sql).unwrap().description;
+        assert!(
+            description.starts_with("Delete: events order by ")
+                && description.ends_with(" limit 2")
+                && !description.contains("index"),
+            "{description}"
+        );
+        db.run(sql).unwrap();
+        let ids = db
+            .run("select id from events order by id")
+            .unwrap()
+            .iter()
+            .map(|tuple| tuple.data[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![ScalarValue::Int32(Some(1)), ScalarValue::Int32(Some(4))]
+        );
+    }
+
+    #[test]
+    pub fn test_delete_rechecks_rows_changed_after_read() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t (id int, v int)").unwrap();
+        db.run("insert into t values (1, 0), (2, 10), (3, 20)")
+            .unwrap();
+        let heap = db.catalog.table_heap(&TableReference::bare("t")).unwrap();
+        let mut rids = vec![];
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, _)) = iterator.next().unwrap() {
+            rids.push(rid);
+        }
+        // deleting row 1 moves row 2 out of the selection it was read with
+        let trigger: TriggerFn = Arc::new(move |context| {
+            if context.rid == Some(rids[0]) {
+                let schema = context.old.unwrap().schema.clone();
+                let row = vec![ScalarValue::Int32(Some(2)), ScalarValue::Int32(Some(50))];
+                heap.update_tuple(rids[1], Tuple::new(schema, row))?;
+            }
+            Ok(TriggerAction::Continue)
+        });
+        db.register_trigger("t", TriggerEvent::BeforeDelete, trigger)
+            .unwrap();
+
+        let deleted = db.run("delete from t where v < 30 order by id").unwrap();
+        assert_eq!(deleted[0].data, vec![ScalarValue::Int32(Some(2))]);
+        let ids = db
+            .run("select id from t")
+            .unwrap()
+            .iter()
+            .map(|tuple| tuple.data[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![ScalarValue::Int32(Some(2))]);
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+
+use crate::common::TableReference;
+use crate::execution::ExecutionContext;
+use crate::expression::{Expr, ExprTrait};
+use crate::planner::logical_plan::{write_order, OrderByExpr};
+use crate::storage::index::TreeIndexIterator;
+use crate::storage::{RecordId, TableHeap, TableIterator};
+use crate::{BustubxResult, Tuple};
+
+use super::sort::sort_in_memory;
+
+/// ORDER BY and LIMIT of a DELETE or UPDATE, picking the rows the statement changes.
+#[derive(Debug, Clone, Default)]
+pub struct DmlOrder {
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<usize>,
+    // index whose key order is the ordering, read instead of sorting the rows
+    pub index: Option<String>,
+    // the index is read from the largest key to the smallest
+    pub descending: bool,
+}
+
+impl DmlOrder {
+    pub fn is_empty(&self) -> bool {
+        self.order_by.is_empty() && self.limit.is_none()
+    }
+
+    /// The rows of the table matching the selection, in the order and up to the limit.
+    ///
+    /// They are collected before the statement changes any of them, a row updated or moved
+    /// by the statement is never picked a second time.
+    pub fn target_rows(
+        &self,
+        table: &TableReference,
+        selection: Option<&Expr>,
+        context: &ExecutionContext,
+    ) -> BustubxResult<VecDeque<(RecordId, Tuple)>> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let matches = |tuple: &Tuple| -> BustubxResult<bool> {
+            match selection {
+                Some(selection) => Ok(selection.evaluate(tuple)?.as_boolean()?.unwrap_or(false)),
+                None => Ok(true),
+            }
+        };
+        let mut rows = vec![];
+        if limit == 0 {
+            return Ok(rows.into());
+        }
+
+        if let Some(index_name) = &self.index {
+            // the index yields the rows in order, reading stops at the limit
+            let catalog_table = context.catalog.catalog_table(table)?;
+            if let Some(index) = context.catalog.index(table, index_name)? {
+                let access_stats = catalog_table.access_stats.index(index_name);
+                access_stats.scans.fetch_add(1, Ordering::Relaxed);
+                let mut iterator = if self.descending {
+                    TreeIndexIterator::new_desc(index, ..)
+                } else {
+                    TreeIndexIterator::new(index, ..)
+                };
+                while let Some(entry) = iterator.next_rid()? {
+                    let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                        continue;
+                    };
+                    let Some(tuple) = catalog_table.table.live_tuple(rid)? else {
+                        continue;
+                    };
+                    if !matches(&tuple)? {
+                        continue;
+                    }
+                    rows.push((rid, tuple));
+                    if rows.len() == limit {
+                        break;
+                    }
+                }
+                access_stats
+                    .tuples_fetched
+                    .fetch_add(rows.len() as u64, Ordering::Relaxed);
+                return Ok(rows.into());
+            }
+        }
+
+        let table_heap = context.catalog.table_heap(table)?;
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next()? {
+            if table_heap.tuple_meta(rid)?.is_deleted || !matches(&tuple)? {
+                continue;
+            }
+            rows.push((rid, tuple));
+            // without an order any rows will do
+            if self.order_by.is_empty() && rows.len() == limit {
+                break;
+            }
+        }
+        sort_in_memory(
+            &self.order_by,
+            &mut rows,
+            |(_, tuple)| tuple,
+            context.options.deterministic_order,
+        )?;
+        rows.truncate(limit);
+        Ok(rows.into())
+    }
+}
+
+impl std::fmt::Display for DmlOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.index {
+            Some(index) => {
+                write!(f, " order by index {}", index)?;
+                if self.descending {
+                    write!(f, " desc")?;
+                }
+                write_order(f, &[], self.limit)
+            }
+            None => write_order(f, &self.order_by, self.limit),
+        }
+    }
+}
+
+/// Rows a DELETE or UPDATE goes through, the whole heap or the rows picked by its order.
+#[derive(Debug)]
+pub(crate) enum DmlRows {
+    Scan(TableIterator),
+    Targets(VecDeque<(RecordId, Tuple)>),
+}
+
+impl DmlRows {
+    pub fn new(
+        order: &DmlOrder,
+        table: &TableReference,
+        selection: Option<&Expr>,
+        context: &ExecutionContext,
+    ) -> BustubxResult<Self> {
+        if order.is_empty() {
+            let table_heap = context.catalog.table_heap(table)?;
+            Ok(DmlRows::Scan(TableIterator::new(table_heap, ..)))
+        } else {
+            Ok(DmlRows::Targets(
+                order.target_rows(table, selection, context)?,
+            ))
+        }
+    }
+
+    pub fn next(&mut self) -> BustubxResult<Option<(RecordId, Tuple)>> {
+        match self {
+            DmlRows::Scan(iterator) => iterator.next(),
+            DmlRows::Targets(rows) => Ok(rows.pop_front()),
+        }
+    }
+}
+
+/// Lock a row the statement is about to change and return its latest version, None when
+/// it was deleted since it was read or its latest version no longer matches `selection`.
+///
+/// The rows are read before they are changed, an ordered statement reads all of them
+/// first. A row written in between by another transaction, or by a trigger of this
+/// statement, is checked again on its latest version, so the new values of an UPDATE are
+/// never computed from an outdated one and no write is lost.
+pub(crate) fn lock_latest_row(
+    context: &ExecutionContext,
+    table: &TableReference,
+    table_heap: &TableHeap,
+    rid: RecordId,
+    read: Tuple,
+    selection: Option<&Expr>,
+) -> BustubxResult<Option<Tuple>> {
+    context.lock_row(table, rid)?;
+    let Some(latest) = table_heap.live_tuple(rid)? else {
+        return Ok(None);
+    };
+    if latest.data == read.data {
+        return Ok(Some(read));
+    }
+    if let Some(selection) = selection {
+        if !selection.evaluate(&latest)?.as_boolean()?.unwrap_or(false) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(latest))
+}
@@ -0,0 +1,33 @@
+use crate::catalog::{SchemaRef, EMPTY_SCHEMA_REF};
+use crate::common::TableReference;
+use crate::{
+    execution::{ExecutionContext, VolcanoExecutor},
+    storage::Tuple,
+    BustubxResult,
+};
+
+#[derive(derive_new::new, Debug)]
+pub struct PhysicalDropSchema {
+    pub schema_names: Vec<String>,
+    // locked for the statement, dropped with their schema
+    pub tables: Vec<TableReference>,
+}
+
+impl VolcanoExecutor for PhysicalDropSchema {
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        for schema_name in self.schema_names.iter() {
+            context.catalog.drop_schema(schema_name)?;
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        EMPTY_SCHEMA_REF.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalDropSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DropSchema: {}", self.schema_names.join(", "))
+    }
+}
@@ -0,0 +1,36 @@
+use crate::catalog::{SchemaRef, EMPTY_SCHEMA_REF};
+use crate::common::TableReference;
+use crate::{
+    execution::{ExecutionContext, VolcanoExecutor},
+    storage::Tuple,
+    BustubxResult,
+};
+
+#[derive(derive_new::new, Debug)]
+pub struct PhysicalDropTable {
+    pub tables: Vec<TableReference>,
+}
+
+impl VolcanoExecutor for PhysicalDropTable {
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        for table in self.tables.iter() {
+            context.catalog.drop_table(table)?;
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        EMPTY_SCHEMA_REF.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalDropTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| table.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "DropTable: {}", tables.join(", "))
+    }
+}
@@ -1,11 +1,12 @@
 use log::debug;
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 
 use crate::catalog::SchemaRef;
 use crate::expression::{Expr, ExprTrait};
 use crate::{
     common::ScalarValue,
-    execution::{ExecutionContext, VolcanoExecutor},
+    execution::{next_buffered, BatchPredicate, ExecutionContext, TupleBatch, VolcanoExecutor},
     storage::Tuple,
     BustubxError, BustubxResult,
 };
@@ -16,20 +17,51 @@ use super::PhysicalPlan;
 pub struct PhysicalFilter {
     pub predicate: Expr,
     pub input: Arc<PhysicalPlan>,
+
+    // rest of the current batch when the parent consumes rows in batch execution
+    #[new(default)]
+    pending: Mutex<VecDeque<Tuple>>,
+}
+
+impl PhysicalFilter {
+    /// Rows of the next input batches passing the predicate, evaluated over a whole
+    /// batch at once.
+    pub fn next_batch(&self, context: &mut ExecutionContext) -> BustubxResult<Option<TupleBatch>> {
+        while let Some(batch) = self.input.next_batch(context)? {
+            let predicate = BatchPredicate::compile(&self.predicate, &batch.tuples[0].schema);
+            let selection = predicate.evaluate(&batch.tuples)?;
+            let mut tuples = Vec::with_capacity(batch.len());
+            for (tuple, selected) in batch.tuples.into_iter().zip(selection) {
+                // a null predicate, e.g. comparing with NULL, rejects the row
+                if selected == Some(true) {
+                    tuples.push(tuple);
+                }
+            }
+            if !tuples.is_empty() {
+                return Ok(Some(TupleBatch::new(tuples)));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl VolcanoExecutor for PhysicalFilter {
     fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
         debug!("init filter executor");
+        self.pending.lock().unwrap().clear();
         self.input.init(context)
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        if context.options.batch_execution {
+            return next_buffered(&self.pending, || self.next_batch(context));
+        }
         loop {
             if let Some(tuple) = self.input.next(context)? {
                 let compare_res = self.predicate.evaluate(&tuple)?;
-                if let ScalarValue::Boolean(Some(v)) = compare_res {
-                    if v {
+                if let ScalarValue::Boolean(v) = compare_res {
+                    // a null predicate, e.g. comparing with NULL, rejects the row
+                    if v == Some(true) {
                         return Ok(Some(tuple));
                     }
                 } else {
@@ -1,19 +1,33 @@
-use crate::catalog::SchemaRef;
+use log::warn;
+
+use crate::catalog::{IndexAccessStats, SchemaRef};
 use crate::common::TableReference;
 use crate::execution::{ExecutionContext, VolcanoExecutor};
 use crate::storage::index::TreeIndexIterator;
 use crate::{BustubxError, BustubxResult, Tuple};
 use std::ops::{Bound, RangeBounds};
-use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+/// Start and end bound of a range of index keys.
+pub type KeyRange = (Bound<Tuple>, Bound<Tuple>);
 
 #[derive(Debug)]
 pub struct PhysicalIndexScan {
-    table_ref: TableReference,
-    index_name: String,
-    table_schema: SchemaRef,
-    start_bound: Bound<Tuple>,
-    end_bound: Bound<Tuple>,
-    iterator: Mutex<Option<TreeIndexIterator>>,
+    pub table_ref: TableReference,
+    pub index_name: String,
+    pub table_schema: SchemaRef,
+    // read one after the other, sorted and disjoint so no entry is in two of them
+    pub ranges: Vec<KeyRange>,
+    // largest key first, the ranges are read from the last to the first
+    pub descending: bool,
+    // leading key values every entry read starts with, read instead of the ranges
+    pub prefix: Option<Tuple>,
+    // and the position of the range it reads, counted in reading order
+    iterator: Mutex<Option<(TreeIndexIterator, usize)>>,
+    // rows whose ttl is before this time are skipped, set when expired rows are hidden
+    expire_before: Mutex<Option<i64>>,
+    access_stats: Mutex<Option<Arc<IndexAccessStats>>>,
 }
 
 impl PhysicalIndexScan {
@@ -22,16 +36,72 @@ impl PhysicalIndexScan {
         index_name: String,
         table_schema: SchemaRef,
         range: R,
+    ) -> Self {
+        Self::with_ranges(
+            table_ref,
+            index_name,
+            table_schema,
+            vec![(range.start_bound().cloned(), range.end_bound().cloned())],
+        )
+    }
+
+    /// Scan of several ranges of the index, in the order given. The ranges have to be
+    /// sorted and disjoint, the entries are returned in key order then.
+    pub fn with_ranges(
+        table_ref: TableReference,
+        index_name: String,
+        table_schema: SchemaRef,
+        ranges: Vec<KeyRange>,
     ) -> Self {
         Self {
             table_ref,
             index_name,
             table_schema,
-            start_bound: range.start_bound().cloned(),
-            end_bound: range.end_bound().cloned(),
+            ranges,
+            descending: false,
+            prefix: None,
             iterator: Mutex::new(None),
+            expire_before: Mutex::new(None),
+            access_stats: Mutex::new(None),
         }
     }
+
+    /// Scan of the entries whose key starts with `prefix`, values of the leading key
+    /// columns, e.g. every entry with `a = 5` of an `(a, b)` index.
+    pub fn with_prefix(
+        table_ref: TableReference,
+        index_name: String,
+        table_schema: SchemaRef,
+        prefix: Tuple,
+    ) -> Self {
+        let mut scan = Self::with_ranges(table_ref, index_name, table_schema, vec![]);
+        scan.prefix = Some(prefix);
+        scan
+    }
+
+    /// Read the entries from the largest key to the smallest.
+    pub fn with_descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    // range read at `position` in reading order
+    fn range_at(&self, position: usize) -> Option<&KeyRange> {
+        if self.descending {
+            let index = self.ranges.len().checked_sub(position + 1)?;
+            self.ranges.get(index)
+        } else {
+            self.ranges.get(position)
+        }
+    }
+
+    // reads the whole index
+    fn is_full_scan(&self) -> bool {
+        matches!(
+            self.ranges.as_slice(),
+            [(Bound::Unbounded, Bound::Unbounded)]
+        )
+    }
 }
 
 impl VolcanoExecutor for PhysicalIndexScan {
@@ -40,25 +110,76 @@ impl VolcanoExecutor for PhysicalIndexScan {
             .catalog
             .index(&self.table_ref, &self.index_name)?
             .unwrap();
-        *self.iterator.lock().unwrap() = Some(TreeIndexIterator::new(
-            index,
-            (self.start_bound.clone(), self.end_bound.clone()),
-        ));
+        let interval = context.options.index_scan_revalidate_interval;
+        // a single iterator moves from range to range, it never returns an entry twice
+        *self.iterator.lock().unwrap() = if let Some(prefix) = &self.prefix {
+            let iterator = TreeIndexIterator::new_prefix(index, prefix.clone())?;
+            Some((iterator.with_revalidate_interval(interval), 0))
+        } else {
+            self.range_at(0).map(|range| {
+                let iterator = if self.descending {
+                    TreeIndexIterator::new_desc(index, range.clone())
+                } else {
+                    TreeIndexIterator::new(index, range.clone())
+                };
+                (iterator.with_revalidate_interval(interval), 0)
+            })
+        };
+        let catalog_table = context.catalog.catalog_table(&self.table_ref)?;
+        *self.expire_before.lock().unwrap() = (context.options.hide_expired_rows
+            && catalog_table.ttl_column.is_some())
+        .then(|| context.clock.now());
+        let access_stats = catalog_table.access_stats.index(&self.index_name);
+        access_stats.scans.fetch_add(1, Ordering::Relaxed);
+        *self.access_stats.lock().unwrap() = Some(access_stats);
         Ok(())
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
         let mut guard = self.iterator.lock().unwrap();
-        let Some(iterator) = &mut *guard else {
+        let Some((iterator, range_idx)) = &mut *guard else {
+            if self.ranges.is_empty() && self.prefix.is_none() {
+                return Ok(None);
+            }
             return Err(BustubxError::Execution(
                 "index iterator not created".to_string(),
             ));
         };
-        let table_heap = context.catalog.table_heap(&self.table_ref)?;
-        if let Some(rid) = iterator.next()? {
-            Ok(Some(table_heap.tuple(rid)?))
-        } else {
-            Ok(None)
+        let catalog_table = context.catalog.catalog_table(&self.table_ref)?;
+        let expire_before = *self.expire_before.lock().unwrap();
+        loop {
+            let Some(entry) = iterator.next_rid()? else {
+                *range_idx += 1;
+                let Some(range) = self.range_at(*range_idx) else {
+                    return Ok(None);
+                };
+                iterator.reset_range(range.clone());
+                continue;
+            };
+            let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                continue;
+            };
+            if !context.is_visible(rid) {
+                continue;
+            }
+            // a row removed without its index entry is skipped instead of failing the scan
+            let Some(tuple) = catalog_table.table.live_tuple(rid)? else {
+                context.catalog.record_dangling_index_entry();
+                warn!(
+                    "index {} of {} points at {:?} holding no live row",
+                    self.index_name, self.table_ref, rid
+                );
+                continue;
+            };
+            if let Some(now) = expire_before {
+                if catalog_table.is_expired(&tuple, now)? {
+                    continue;
+                }
+            }
+            if let Some(access_stats) = &*self.access_stats.lock().unwrap() {
+                access_stats.tuples_fetched.fetch_add(1, Ordering::Relaxed);
+            }
+            return Ok(Some(tuple));
         }
     }
 
@@ -69,6 +190,83 @@ impl VolcanoExecutor for PhysicalIndexScan {
 
 impl std::fmt::Display for PhysicalIndexScan {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "IndexScan: {}", self.index_name)
+        write!(f, "IndexScan: {}", self.index_name)?;
+        if let Some(prefix) = &self.prefix {
+            write!(f, " prefix {}", format_key(prefix))?;
+        } else if !self.is_full_scan() {
+            let ranges = self
+                .ranges
+                .iter()
+                .map(|(start, end)| format_key_range(start, end))
+                .collect::<Vec<_>>();
+            write!(f, " ranges {}", ranges.join(", "))?;
+        }
+        if self.descending {
+            write!(f, " desc")?;
+        }
+        Ok(())
+    }
+}
+
+// `5`, keys of several columns in parentheses
+fn format_key(tuple: &Tuple) -> String {
+    match tuple.data.as_slice() {
+        [value] => value.to_string(),
+        values => format!(
+            "({})",
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+// `[3, 7)` and `(-inf, 5]`
+fn format_key_range(start: &Bound<Tuple>, end: &Bound<Tuple>) -> String {
+    let start = match start {
+        Bound::Included(tuple) => format!("[{}", format_key(tuple)),
+        Bound::Excluded(tuple) => format!("({}", format_key(tuple)),
+        Bound::Unbounded => "(-inf".to_string(),
+    };
+    let end = match end {
+        Bound::Included(tuple) => format!("{}]", format_key(tuple)),
+        Bound::Excluded(tuple) => format!("{})", format_key(tuple)),
+        Bound::Unbounded => "+inf)".to_string(),
+    };
+    format!("{start}, {end}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Database;
+
+    #[test]
+    pub fn test_index_scan_revalidate_interval() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        let values = (0..300)
+            .map(|i| format!("({}, {i})", (i * 7) % 100))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+        let queries = [
+            "select a, b from t1 where a >= 10 and a < 60",
+            "select a, b from t1 where a = 42 or a = 77",
+            "select a from t1 where a > 20 order by a desc",
+        ];
+        for sql in queries {
+            let plan = db.explain(sql).unwrap().text_lines().join("\n");
+            assert!(plan.contains("IndexScan"), "{plan}");
+        }
+        let expected = queries.map(|sql| db.run(sql).unwrap());
+
+        // descending again every few entries returns the same rows in the same order
+        db.run("set index_scan_revalidate_interval = 3").unwrap();
+        for (sql, rows) in queries.into_iter().zip(expected) {
+            assert_eq!(db.run(sql).unwrap(), rows, "{sql}");
+        }
     }
 }
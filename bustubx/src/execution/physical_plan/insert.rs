@@ -1,27 +1,55 @@
 use log::debug;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 use std::sync::{atomic::AtomicU32, Arc};
 
-use crate::catalog::{SchemaRef, INSERT_OUTPUT_SCHEMA_REF};
+use crate::catalog::{
+    CatalogTable, DefaultExpr, KeyProjection, Schema, SchemaRef, INSERT_OUTPUT_SCHEMA_REF,
+};
 use crate::common::TableReference;
-use crate::storage::EMPTY_TUPLE_META;
+use crate::expression::{Expr, ExprTrait};
+use crate::planner::logical_plan::{OnConflict, OnConflictAction};
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::{RecordId, EMPTY_TUPLE};
 use crate::{
     common::ScalarValue,
-    execution::{ExecutionContext, VolcanoExecutor},
+    execution::{ExecutionContext, TriggerEvent, Triggers, UndoRecord, VolcanoExecutor},
     storage::Tuple,
-    BustubxResult,
+    BustubxError, BustubxResult,
 };
 
 use super::PhysicalPlan;
 
+// an index of the target table by name, with the projection deriving its keys
+pub(crate) type IndexKeys = (String, Arc<BPlusTreeIndex>, Arc<KeyProjection>);
+
+/// The indexes of the table ordered by name, so rows are checked against them in the same
+/// order every time.
+pub(crate) fn index_keys(catalog_table: &CatalogTable) -> BustubxResult<Vec<IndexKeys>> {
+    let mut indexes = catalog_table
+        .indexes
+        .iter()
+        .map(|(name, index)| {
+            let projection = catalog_table.key_projection(name)?;
+            Ok((name.clone(), index.clone(), projection))
+        })
+        .collect::<BustubxResult<Vec<IndexKeys>>>()?;
+    indexes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(indexes)
+}
+
 #[derive(Debug)]
 pub struct PhysicalInsert {
     pub table: TableReference,
     pub table_schema: SchemaRef,
     pub projected_schema: SchemaRef,
     pub input: Arc<PhysicalPlan>,
+    pub on_conflict: Option<OnConflict>,
 
     insert_rows: AtomicU32,
+    // rows inserted or updated by this statement
+    touched_rids: Mutex<HashSet<RecordId>>,
 }
 impl PhysicalInsert {
     pub fn new(
@@ -29,21 +57,223 @@ impl PhysicalInsert {
         table_schema: SchemaRef,
         projected_schema: SchemaRef,
         input: Arc<PhysicalPlan>,
+        on_conflict: Option<OnConflict>,
     ) -> Self {
         Self {
             table,
             table_schema,
             projected_schema,
             input,
+            on_conflict,
             insert_rows: AtomicU32::new(0),
+            touched_rids: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Non-constant defaults of the columns the statement does not provide.
+    pub fn default_exprs(&self) -> Vec<(&str, &DefaultExpr)> {
+        self.table_schema
+            .columns
+            .iter()
+            .filter(|col| {
+                self.projected_schema
+                    .index_of(col.relation.as_ref(), &col.name)
+                    .is_err()
+            })
+            .filter_map(|col| {
+                col.default_expr
+                    .as_ref()
+                    .map(|default_expr| (col.name.as_str(), default_expr))
+            })
+            .collect()
+    }
+
+    /// Unique index the key of `tuple` collides on and the rid of the live row holding that key.
+    /// Deferrable indexes are left to the check when the statement or transaction ends.
+    fn find_conflict(
+        &self,
+        catalog_table: &CatalogTable,
+        indexes: &[IndexKeys],
+        tuple: &Tuple,
+        self_rid: Option<RecordId>,
+    ) -> BustubxResult<Option<(String, RecordId)>> {
+        for (index_name, index, projection) in indexes
+            .iter()
+            .filter(|(_, index, _)| index.unique && index.deferrable.is_none())
+        {
+            let key = projection.project(tuple)?;
+            // NULLs never conflict with each other
+            if key.data.iter().any(|v| v.is_null()) {
+                continue;
+            }
+            let Some(entry) = index.get(&key)? else {
+                continue;
+            };
+            if let Some(rid) = catalog_table.resolve_index_entry(entry)? {
+                if Some(rid) != self_rid && !catalog_table.table.tuple_meta(rid)?.is_deleted {
+                    return Ok(Some((index_name.clone(), rid)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Apply DO UPDATE to the existing row, returns false if the row was filtered out or
+    /// skipped by a trigger. The update triggers fire for the row.
+    #[allow(clippy::too_many_arguments)]
+    fn update_conflicting_row(
+        &self,
+        table: &TableReference,
+        catalog_table: &CatalogTable,
+        triggers: &Triggers,
+        indexes: &[IndexKeys],
+        rid: RecordId,
+        excluded: Tuple,
+        assignments: &HashMap<String, Expr>,
+        selection: &Option<Expr>,
+        undo_log: &mut Vec<UndoRecord>,
+    ) -> BustubxResult<bool> {
+        if !self.touched_rids.lock().unwrap().insert(rid) {
+            return Err(BustubxError::Execution(
+                "ON CONFLICT DO UPDATE command cannot affect row a second time".to_string(),
+            ));
+        }
+        let existing = catalog_table.table.tuple(rid)?;
+        let excluded_schema = Schema::new(
+            self.table_schema
+                .columns
+                .iter()
+                .map(|col| {
+                    col.as_ref()
+                        .clone()
+                        .with_relation(Some(TableReference::bare(EXCLUDED_RELATION)))
+                })
+                .collect(),
+        );
+        let excluded = Tuple::new(Arc::new(excluded_schema), excluded.data);
+        // unqualified columns resolve to the existing row
+        let merged = Tuple::try_merge([existing.clone(), excluded])?;
+
+        if let Some(selection) = selection {
+            if !selection.evaluate(&merged)?.as_boolean()?.unwrap_or(false) {
+                return Ok(false);
+            }
+        }
+
+        let mut new_data = existing.data.clone();
+        for (col_name, value_expr) in assignments.iter() {
+            let index = self.table_schema.index_of(None, col_name)?;
+            let col_datatype = self.table_schema.columns[index].data_type;
+            new_data[index] = value_expr.evaluate(&merged)?.cast_to(&col_datatype)?;
+        }
+        let mut new_tuple = Tuple::new(self.table_schema.clone(), new_data);
+        if !triggers.fire_before(
+            TriggerEvent::BeforeUpdate,
+            &self.table,
+            Some(rid),
+            Some(&existing),
+            Some(&mut new_tuple),
+        )? {
+            return Ok(false);
+        }
+        catalog_table.check_partition_key(&new_tuple)?;
+
+        if let Some((index_name, _)) =
+            self.find_conflict(catalog_table, indexes, &new_tuple, Some(rid))?
+        {
+            return Err(unique_violation(&index_name));
+        }
+        undo_log.push(UndoRecord::Update {
+            table: table.clone(),
+            rid,
+            old_tuple: existing.clone(),
+        });
+        let entry = catalog_table.index_entry(rid)?;
+        update_index_entries(table, indexes, entry, &existing, &new_tuple, undo_log)?;
+        let after = triggers
+            .has(TriggerEvent::AfterUpdate)
+            .then(|| new_tuple.clone());
+        catalog_table.modifications.row_updated(&new_tuple);
+        catalog_table.table.update_tuple(rid, new_tuple)?;
+        if let Some(new_tuple) = after {
+            triggers.fire_after(
+                TriggerEvent::AfterUpdate,
+                &self.table,
+                rid,
+                Some(&existing),
+                Some(&new_tuple),
+            )?;
+        }
+        Ok(true)
+    }
+}
+
+// relation name the proposed row is referred to by in ON CONFLICT DO UPDATE
+pub const EXCLUDED_RELATION: &str = "excluded";
+
+pub(crate) fn unique_violation(index_name: &str) -> BustubxError {
+    BustubxError::Execution(format!(
+        "duplicate key value violates unique index {}",
+        index_name
+    ))
+}
+
+// a unique index rejects a duplicate before changing any page, nothing to undo there
+pub(crate) fn insert_index_entry(
+    table: &TableReference,
+    index_name: &str,
+    index: &BPlusTreeIndex,
+    key: Tuple,
+    entry: RecordId,
+    undo_log: &mut Vec<UndoRecord>,
+) -> BustubxResult<()> {
+    match index.insert(&key, entry) {
+        Err(BustubxError::DuplicateKey { .. }) => return Err(unique_violation(index_name)),
+        result => result?,
+    }
+    undo_log.push(UndoRecord::IndexInsert {
+        table: table.clone(),
+        index_name: index_name.to_string(),
+        key,
+        entry,
+    });
+    Ok(())
+}
+
+/// Move the entry of an updated row to its new key in every index whose key changed.
+pub(crate) fn update_index_entries(
+    table: &TableReference,
+    indexes: &[IndexKeys],
+    entry: RecordId,
+    old_tuple: &Tuple,
+    new_tuple: &Tuple,
+    undo_log: &mut Vec<UndoRecord>,
+) -> BustubxResult<()> {
+    for (index_name, index, projection) in indexes.iter() {
+        let old_key = projection.project(old_tuple)?;
+        let new_key = projection.project(new_tuple)?;
+        if old_key == new_key {
+            continue;
         }
+        if index.delete_entry(&old_key, entry)? {
+            undo_log.push(UndoRecord::IndexDelete {
+                table: table.clone(),
+                index_name: index_name.clone(),
+                key: old_key,
+                entry,
+            });
+        }
+        insert_index_entry(table, index_name, index, new_key, entry, undo_log)?;
     }
+    Ok(())
 }
+
 impl VolcanoExecutor for PhysicalInsert {
     fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
         debug!("init insert executor");
         self.input.init(context)?;
         self.insert_rows.store(0, Ordering::SeqCst);
+        self.touched_rids.lock().unwrap().clear();
         Ok(())
     }
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
@@ -78,29 +308,113 @@ impl VolcanoExecutor for PhysicalInsert {
                     .index_of(col.relation.as_ref(), &col.name)
                 {
                     full_data.push(casted_data[idx].clone());
+                } else if let Some(default_expr) = &col.default_expr {
+                    // evaluated per row so every row sees its own now()
+                    full_data.push(
+                        default_expr
+                            .expr
+                            .evaluate(&EMPTY_TUPLE)?
+                            .cast_to(&col.data_type)?,
+                    );
                 } else {
                     full_data.push(col.default.clone())
                 }
             }
 
-            let tuple = Tuple::new(self.table_schema.clone(), full_data);
+            let mut tuple = Tuple::new(self.table_schema.clone(), full_data);
+            // the triggers of the table the statement names, also for a partitioned table
+            let triggers = &context.catalog.catalog_table(&self.table)?.triggers;
+            if !triggers.fire_before(
+                TriggerEvent::BeforeInsert,
+                &self.table,
+                None,
+                None,
+                Some(&mut tuple),
+            )? {
+                continue;
+            }
 
-            let table_heap = context.catalog.table_heap(&self.table)?;
-            let rid = table_heap.insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+            // a row of a partitioned table goes to the partition holding its key
+            let table = context.catalog.insert_target(&self.table, &tuple)?;
+            let catalog_table = context.catalog.catalog_table(&table)?;
+            // the columns of a partition belong to it, its index keys are projected from them
+            let tuple = Tuple::new(catalog_table.table.schema.clone(), tuple.data);
+            let indexes = index_keys(catalog_table)?;
 
-            let indexes = context.catalog.table_indexes(&self.table)?;
-            for index in indexes {
-                if let Ok(key_tuple) = tuple.project_with_schema(index.key_schema.clone()) {
-                    let root_page_id = index.root_page_id.load(Ordering::SeqCst);
-                    index.insert(&key_tuple, rid)?;
-                    let new_root_page_id = index.root_page_id.load(Ordering::SeqCst);
-                    if new_root_page_id != root_page_id {
-                        // TODO update system table
+            if let Some((index_name, rid)) =
+                self.find_conflict(catalog_table, &indexes, &tuple, None)?
+            {
+                let Some(on_conflict) = &self.on_conflict else {
+                    return Err(unique_violation(&index_name));
+                };
+                if on_conflict
+                    .index_name
+                    .as_ref()
+                    .is_some_and(|target| target != &index_name)
+                {
+                    return Err(unique_violation(&index_name));
+                }
+                match &on_conflict.action {
+                    OnConflictAction::DoNothing => {}
+                    OnConflictAction::DoUpdate {
+                        assignments,
+                        selection,
+                    } => {
+                        if self.update_conflicting_row(
+                            &table,
+                            catalog_table,
+                            triggers,
+                            &indexes,
+                            rid,
+                            tuple,
+                            assignments,
+                            selection,
+                            &mut context.undo_log,
+                        )? {
+                            self.insert_rows.fetch_add(1, Ordering::SeqCst);
+                            catalog_table
+                                .access_stats
+                                .tuples_updated
+                                .fetch_add(1, Ordering::Relaxed);
+                        }
                     }
                 }
+                continue;
+            }
+
+            let rid = catalog_table.insert_tuple(&tuple)?;
+            self.touched_rids.lock().unwrap().insert(rid);
+            context.inserted_rids.insert(rid);
+            context.undo_log.push(UndoRecord::Insert {
+                table: table.clone(),
+                rid,
+            });
+
+            let entry = catalog_table.index_entry(rid)?;
+            for (index_name, index, projection) in indexes.iter() {
+                insert_index_entry(
+                    &table,
+                    index_name,
+                    index,
+                    projection.project(&tuple)?,
+                    entry,
+                    &mut context.undo_log,
+                )?;
             }
 
             self.insert_rows.fetch_add(1, Ordering::SeqCst);
+            catalog_table
+                .access_stats
+                .tuples_inserted
+                .fetch_add(1, Ordering::Relaxed);
+            catalog_table.modifications.row_inserted(&tuple);
+            triggers.fire_after(
+                TriggerEvent::AfterInsert,
+                &self.table,
+                rid,
+                None,
+                Some(&tuple),
+            )?;
         }
     }
 
@@ -111,6 +425,240 @@ impl VolcanoExecutor for PhysicalInsert {
 
 impl std::fmt::Display for PhysicalInsert {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Insert")
+        let default_exprs = self.default_exprs();
+        if default_exprs.is_empty() {
+            return write!(f, "Insert");
+        }
+        let default_exprs = default_exprs
+            .into_iter()
+            .map(|(name, default_expr)| format!("{name} DEFAULT {default_expr}"))
+            .collect::<Vec<_>>();
+        write!(f, "Insert: {}", default_exprs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{Clock, ScalarValue, TableReference};
+    use crate::{BustubxError, Database, DatabaseOptions};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    // advances one second every time it is read
+    #[derive(Debug)]
+    struct TickingClock(AtomicI64);
+
+    impl Clock for TickingClock {
+        fn now(&self) -> i64 {
+            self.0.fetch_add(1, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    pub fn test_insert_on_conflict_counts() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create unique index idx1 on t1 (a)").unwrap();
+        db.run("insert into t1 values (1, 1), (2, 2)").unwrap();
+
+        let rows = db
+            .run("insert into t1 values (2, 5), (3, 3), (1, 7) on conflict (a) do nothing")
+            .unwrap();
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(1))]);
+
+        let rows = db
+            .run("insert into t1 values (2, 5), (4, 4) on conflict (a) do update set b = excluded.b * 10 + b")
+            .unwrap();
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(2))]);
+        let rows = db.run("select b from t1 where a = 2").unwrap();
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(52))]);
+
+        assert!(matches!(
+            db.run("insert into t1 values (3, 1), (3, 2) on conflict (a) do update set b = 0"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            db.run("insert into t1 values (4, 1)"),
+            Err(BustubxError::Execution(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_insert_volatile_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let clock = Arc::new(TickingClock(AtomicI64::new(1000)));
+        let mut db =
+            Database::open_with_clock(&db_path, DatabaseOptions::default(), clock.clone()).unwrap();
+        db.run("create table t1 (a int, created bigint default now(), b int default 1 + 2)")
+            .unwrap();
+        assert!(matches!(
+            db.run("create table t2 (a int, b int default a)"),
+            Err(BustubxError::Plan(_))
+        ));
+
+        // statements read the clock too, only the order of the readings is known
+        let before = clock.0.load(Ordering::SeqCst);
+        db.run("insert into t1 (a) values (1), (2)").unwrap();
+        let after = clock.0.load(Ordering::SeqCst);
+        db.run("insert into t1 values (3, 7, 4)").unwrap();
+        let rows = db.run("select a, created, b from t1").unwrap();
+        let mut rows = rows.into_iter().map(|row| row.data).collect::<Vec<_>>();
+        let created = rows
+            .iter_mut()
+            .map(|row| std::mem::replace(&mut row[1], ScalarValue::Int64(None)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                vec![1i32.into(), ScalarValue::Int64(None), 3i32.into()],
+                vec![2i32.into(), ScalarValue::Int64(None), 3i32.into()],
+                vec![3i32.into(), ScalarValue::Int64(None), 4i32.into()],
+            ]
+        );
+        let [ScalarValue::Int64(Some(first)), ScalarValue::Int64(Some(second)), ScalarValue::Int64(Some(7))] =
+            created[..]
+        else {
+            panic!("unexpected created values {created:?}");
+        };
+        assert!(before <= first && first < second && second < after);
+
+        let tree = db.explain("insert into t1 (a) values (4)").unwrap();
+        assert_eq!(tree.operator, "Insert");
+        assert_eq!(tree.description, "Insert: created DEFAULT now()");
+        assert_eq!(tree.expressions, vec!["created DEFAULT now()".to_string()]);
+        let tree = db.explain("insert into t1 values (4, 0, 0)").unwrap();
+        assert!(tree.expressions.is_empty());
+
+        // the default is stored as an expression and bound again on open
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db =
+            Database::open_with_clock(&db_path, DatabaseOptions::default(), clock).unwrap();
+        db.run("insert into t1 (a) values (5)").unwrap();
+        let rows = db.run("select created from t1 where a = 5").unwrap();
+        assert!(matches!(rows[0].data[0], ScalarValue::Int64(Some(created)) if created > second));
+        let rows = db.run("describe t1").unwrap();
+        assert_eq!(rows[1].data[3], "now()".to_string().into());
+    }
+
+    #[test]
+    pub fn test_failed_statement_rolls_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(20))").unwrap();
+        db.run("create unique index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        db.run("insert into t1 values (1, 'one'), (2, 'two'), (3, 'three')")
+            .unwrap();
+        let all_rows = |db: &mut Database| {
+            db.run("select a, b from t1")
+                .unwrap()
+                .into_iter()
+                .map(|row| row.data)
+                .collect::<Vec<_>>()
+        };
+        let before = all_rows(&mut db);
+
+        // the third row violates idx_a after two rows went in
+        assert!(matches!(
+            db.run("insert into t1 values (10, 'ten'), (11, 'eleven'), (2, 'dup'), (12, 'twelve')"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert_eq!(all_rows(&mut db), before);
+        assert!(db.run("select a from t1 where a = 10").unwrap().is_empty());
+        assert!(db
+            .run("select a from t1 where b = 'eleven'")
+            .unwrap()
+            .is_empty());
+
+        // the first row is updated before the second one fails
+        assert!(matches!(
+            db.run("insert into t1 values (3, 'x'), (3, 'y') on conflict (a) do update set b = 'updated'"),
+            Err(BustubxError::Execution(_))
+        ));
+        // rows before the one dividing by zero are already changed
+        assert!(matches!(
+            db.run("update t1 set b = 'updated', a = 10 / (a - 2)"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert!(matches!(
+            db.run("delete from t1 where 10 / (a - 3) < 0"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert_eq!(all_rows(&mut db), before);
+        assert!(db
+            .run("select a from t1 where b = 'updated'")
+            .unwrap()
+            .is_empty());
+
+        // the indexes still match the heap, also after reopening
+        db.run("insert into t1 values (10, 'ten')").unwrap();
+        db.flush().unwrap();
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(db.run("select b from t1 where a = 10").unwrap().len(), 1);
+        assert_eq!(db.run("select a from t1 where b = 'two'").unwrap().len(), 1);
+        assert!(matches!(
+            db.run("insert into t1 values (2, 'dup')"),
+            Err(BustubxError::Execution(_))
+        ));
+    }
+
+    // A failed statement takes back its own index entries instead of rebuilding the
+    // indexes, the trees keep their root pages.
+    #[test]
+    pub fn test_rollback_undoes_index_entries() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int) with (logical_row_ids = 'true')")
+            .unwrap();
+        db.run("create unique index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        let values = (0..2000)
+            .map(|i| format!("({i}, {})", i % 10))
+            .collect::<Vec<_>>();
+        db.run(&format!("insert into t1 values {}", values.join(", ")))
+            .unwrap();
+        let table_ref = TableReference::bare("t1");
+        let root_page_ids = |db: &Database| {
+            let catalog_table = db.catalog.catalog_table(&table_ref).unwrap();
+            let mut root_page_ids = catalog_table
+                .indexes
+                .iter()
+                .map(|(name, index)| (name.clone(), index.root_page_id.load(Ordering::SeqCst)))
+                .collect::<Vec<_>>();
+            root_page_ids.sort();
+            root_page_ids
+        };
+        let before = root_page_ids(&db);
+        let count = |db: &mut Database, sql: &str| db.run(sql).unwrap().len();
+
+        assert!(db
+            .run("insert into t1 values (5000, 1), (5001, 2), (7, 3)")
+            .is_err());
+        assert!(db
+            .run("insert into t1 values (7, 0) on conflict (a) do update set a = 6000, b = 99")
+            .is_ok());
+        assert!(db
+            .run("insert into t1 values (8, 0), (6000, 0) on conflict (a) do update set a = 7000")
+            .is_err());
+        assert!(db
+            .run("delete from t1 where 10 / (a - 100) < 1000")
+            .is_err());
+        assert_eq!(root_page_ids(&db), before);
+
+        assert_eq!(count(&mut db, "select a from t1"), 2000);
+        assert_eq!(count(&mut db, "select a from t1 where a = 5000"), 0);
+        assert_eq!(count(&mut db, "select a from t1 where b = 3"), 200);
+        assert_eq!(count(&mut db, "select a from t1 where a = 8"), 1);
+        assert_eq!(count(&mut db, "select a from t1 where a = 50"), 1);
+        assert_eq!(count(&mut db, "select a from t1 where a = 7000"), 0);
+        assert_eq!(count(&mut db, "select a from t1 where a = 6000"), 1);
+        // the row ids of the rows taken back are unmapped, the keys can be inserted again
+        db.run("insert into t1 values (5000, 1), (5001, 2)")
+            .unwrap();
+        assert_eq!(count(&mut db, "select a from t1 where a >= 5000"), 3);
     }
 }
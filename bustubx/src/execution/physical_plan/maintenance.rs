@@ -0,0 +1,758 @@
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::catalog::{SchemaRef, DEFAULT_HISTOGRAM_BUCKETS};
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::planner::logical_plan::MaintenanceKind;
+use crate::transaction::TableLockMode;
+use crate::{BustubxError, BustubxResult, Tuple};
+
+/// Runs a maintenance statement over its tables one after the other and returns a one row
+/// summary of the work done, or one row per finding of CHECK TABLE.
+#[derive(Debug)]
+pub struct PhysicalMaintenance {
+    pub kind: MaintenanceKind,
+    pub tables: Vec<TableReference>,
+    pub schema: SchemaRef,
+
+    // rows left to return, None until the statement ran
+    rows: Mutex<Option<VecDeque<Vec<ScalarValue>>>>,
+}
+
+impl PhysicalMaintenance {
+    pub fn new(kind: MaintenanceKind, tables: Vec<TableReference>, schema: SchemaRef) -> Self {
+        Self {
+            kind,
+            tables,
+            schema,
+            rows: Mutex::new(None),
+        }
+    }
+
+    /// VACUUM FULL and CLUSTER rewrite the heap so they keep every other statement off the table,
+    /// REINDEX and CHECK TABLE keep writers out while readers go on, plain VACUUM and
+    /// ANALYZE run next to readers and writers.
+    pub fn lock_mode(&self) -> TableLockMode {
+        match self.kind {
+            MaintenanceKind::Vacuum { full: true } | MaintenanceKind::Cluster { .. } => {
+                TableLockMode::AccessExclusive
+            }
+            MaintenanceKind::Reindex { .. } | MaintenanceKind::CheckTable { .. } => {
+                TableLockMode::Share
+            }
+            _ => TableLockMode::AccessShare,
+        }
+    }
+
+    fn run(&self, context: &mut ExecutionContext) -> BustubxResult<Vec<Vec<ScalarValue>>> {
+        if let MaintenanceKind::CheckTable { with_indexes } = &self.kind {
+            let mut rows = vec![];
+            for table in self.tables.iter() {
+                for finding in context.catalog.check_table(table, *with_indexes)? {
+                    let description = finding.report();
+                    rows.push(vec![
+                        finding.severity.to_string().into(),
+                        finding.object.into(),
+                        ScalarValue::UInt32(finding.page_id),
+                        description.into(),
+                    ]);
+                }
+            }
+            return Ok(rows);
+        }
+        self.run_summary(context).map(|row| vec![row])
+    }
+
+    fn run_summary(&self, context: &mut ExecutionContext) -> BustubxResult<Vec<ScalarValue>> {
+        let tables = ScalarValue::UInt64(Some(self.tables.len() as u64));
+        match &self.kind {
+            MaintenanceKind::Vacuum { full } => {
+                let (mut pages_reclaimed, mut dead_tuples) = (0, 0);
+                for table in self.tables.iter() {
+                    // rolling back an open transaction needs the tuples it deleted
+                    let writer = context
+                        .txn_manager
+                        .as_ref()
+                        .and_then(|txn_manager| txn_manager.writer_of(table));
+                    if let Some(txn_id) = writer {
+                        warn!(
+                            "vacuum of {} held back by open transaction {}",
+                            table, txn_id
+                        );
+                        continue;
+                    }
+                    let (pages, tuples) = context.catalog.vacuum_table(table, *full)?;
+                    // the versions of deleted rows are gone, reused record ids hold others
+                    if let Some(txn_manager) = &context.txn_manager {
+                        txn_manager.forget_history(table);
+                    }
+                    pages_reclaimed += pages as u64;
+                    dead_tuples += tuples as u64;
+                }
+                Ok(vec![tables, pages_reclaimed.into(), dead_tuples.into()])
+            }
+            MaintenanceKind::Analyze => {
+                let mut rows_sampled = 0;
+                for table in self.tables.iter() {
+                    let job = context.catalog.analyze_job(
+                        table,
+                        DEFAULT_HISTOGRAM_BUCKETS,
+                        context.options.analyze_sample_size,
+                        context.options.distinct_sketches,
+                    )?;
+                    if let Some(statistics) = context.catalog.install_analyzed(job.run()?)? {
+                        rows_sampled += statistics.sample_size as u64;
+                    }
+                }
+                Ok(vec![tables, rows_sampled.into()])
+            }
+            MaintenanceKind::Checkpoint => {
+                let horizon = context
+                    .txn_manager
+                    .as_ref()
+                    .and_then(|txn_manager| txn_manager.horizon());
+                if let Some(txn) = horizon {
+                    info!(
+                        "checkpoint while transaction {} is open for {:?} with {} rows written, \
+                         it pins the horizon",
+                        txn.txn_id, txn.age, txn.rows_written
+                    );
+                }
+                if let Err(e) = context.catalog.save_access_stats() {
+                    warn!("failed to save the access stats: {}", e);
+                }
+                let buffer_pool = &context.catalog.buffer_pool;
+                // the lsn returned vouches for every page, a partial flush fails the checkpoint
+                buffer_pool.flush_all_pages()?.into_result()?;
+                // a restore can stop at the commit record, it closes and archives a segment
+                let lsn = buffer_pool.disk_manager.log_commit(context.clock.now())?;
+                Ok(vec![ScalarValue::UInt64(lsn)])
+            }
+            MaintenanceKind::Reindex { index } => {
+                let (mut indexes, mut pages_before, mut pages_after) = (0, 0, 0);
+                for table in self.tables.iter() {
+                    let (count, before, after) =
+                        context.catalog.reindex(table, index.as_deref())?;
+                    indexes += count as u64;
+                    pages_before += before as u64;
+                    pages_after += after as u64;
+                }
+                Ok(vec![
+                    indexes.into(),
+                    pages_before.into(),
+                    pages_after.into(),
+                ])
+            }
+            MaintenanceKind::Cluster { index } => {
+                let (mut rows, mut pages_before, mut pages_after) = (0, 0, 0);
+                for table in self.tables.iter() {
+                    let (count, before, after) = context.catalog.cluster_table(table, index)?;
+                    // the rows moved, the versions kept by record id belong to no row
+                    if let Some(txn_manager) = &context.txn_manager {
+                        txn_manager.forget_history(table);
+                    }
+                    rows += count as u64;
+                    pages_before += before as u64;
+                    pages_after += after as u64;
+                }
+                Ok(vec![rows.into(), pages_before.into(), pages_after.into()])
+            }
+            MaintenanceKind::CheckTable { .. } => Err(BustubxError::Internal(
+                "CHECK TABLE returns findings instead of a summary".to_string(),
+            )),
+        }
+    }
+}
+
+impl VolcanoExecutor for PhysicalMaintenance {
+    fn init(&self, _context: &mut ExecutionContext) -> BustubxResult<()> {
+        *self.rows.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        let mut rows = self.rows.lock().unwrap();
+        if rows.is_none() {
+            *rows = Some(self.run(context)?.into());
+        }
+        let values = rows.as_mut().and_then(|rows| rows.pop_front());
+        Ok(values.map(|values| Tuple::new(self.schema.clone(), values)))
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalMaintenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.tables.is_empty() {
+            let tables = self
+                .tables
+                .iter()
+                .map(|table| table.to_string())
+                .collect::<Vec<_>>();
+            write!(f, ": {}", tables.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    use crate::buffer::{operation_scope, BUSTUBX_PAGE_SIZE};
+    use crate::common::page_leaks::assert_no_page_leaks;
+    use crate::common::page_trace::traced_run;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::storage::{RecordId, TableIterator};
+    use crate::transaction::TableLockMode;
+    use crate::{BustubxError, Database, DatabaseOptions, Tuple};
+
+    fn insert_rows(db: &mut Database, table: &str, rows: std::ops::Range<i32>) {
+        for chunk in rows.collect::<Vec<_>>().chunks(200) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({a}, 'padding padding padding {a}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into {table} values {values}"))
+                .unwrap();
+        }
+    }
+
+    fn values(db: &mut Database, sql: &str) -> Vec<ScalarValue> {
+        let rows = db.run(sql).unwrap();
+        assert_eq!(rows.len(), 1, "{sql}");
+        rows[0].data.clone()
+    }
+
+    fn ints(db: &mut Database, sql: &str) -> Vec<i32> {
+        let mut rows = db
+            .run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| match tuple.data[0] {
+                ScalarValue::Int32(Some(v)) => v,
+                ref v => panic!("unexpected value {v}"),
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    pub fn test_vacuum_reclaims_deleted_pages() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        insert_rows(&mut db, "t1", 0..2000);
+        db.run("delete from t1 where a < 1500").unwrap();
+        let table_ref = TableReference::full("bustubx", "public", "t1");
+        let pages = |db: &Database| db.catalog.table_heap(&table_ref).unwrap().count_pages();
+        let (pages_before, _, _) = pages(&db).unwrap();
+
+        let summary = assert_no_page_leaks(&mut db, |db| values(db, "vacuum t1"));
+        let ScalarValue::UInt64(Some(reclaimed)) = summary[1] else {
+            panic!("unexpected vacuum output {summary:?}");
+        };
+        assert_eq!(summary[0], 1u64.into());
+        assert!(reclaimed > 0);
+        assert_eq!(pages(&db).unwrap().0, pages_before - reclaimed as usize);
+        assert_eq!(
+            ints(&mut db, "select a from t1"),
+            (1500..2000).collect::<Vec<_>>()
+        );
+        assert_eq!(ints(&mut db, "select a from t1 where a = 1700"), vec![1700]);
+        // nothing left to reclaim, new rows reuse the chain
+        assert_eq!(values(&mut db, "VACUUM t1;")[1], 0u64.into());
+        insert_rows(&mut db, "t1", 5000..5100);
+        assert_eq!(ints(&mut db, "select a from t1").len(), 600);
+
+        assert!(matches!(
+            db.run("vacuum missing"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(db.run("vacuum t1 t2").is_err());
+    }
+
+    #[test]
+    pub fn test_vacuum_removes_dangling_index_entries() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        insert_rows(&mut db, "t1", 0..2000);
+        let table_ref = TableReference::bare("t1");
+        // rows deleted without their index entries
+        let table_heap = db.catalog.table_heap(&table_ref).unwrap();
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        while let Some((rid, tuple)) = iterator.next().unwrap() {
+            if tuple.data[0] < ScalarValue::Int32(Some(1500)) {
+                let mut meta = table_heap.tuple_meta(rid).unwrap();
+                meta.is_deleted = true;
+                table_heap.update_tuple_meta(meta, rid).unwrap();
+            }
+        }
+
+        // the scan skips entries of deleted rows
+        db.reset_runtime_stats();
+        assert!(ints(&mut db, "select a from t1 where a < 1500").is_empty());
+        assert_eq!(db.runtime_stats().dangling_index_entries, 1500);
+
+        let summary = assert_no_page_leaks(&mut db, |db| values(db, "vacuum t1"));
+        assert_ne!(summary[1], 0u64.into());
+        // new rows take the record ids of the reclaimed ones
+        insert_rows(&mut db, "t1", 5000..6500);
+        db.reset_runtime_stats();
+
+        let key = |index: &str, value: ScalarValue| {
+            let index = db.catalog.index(&table_ref, index).unwrap().unwrap();
+            index
+                .get(&Tuple::new(index.key_schema.clone(), vec![value]))
+                .unwrap()
+        };
+        let b = |a: i32| ScalarValue::from(format!("padding padding padding {a}"));
+        // rows of reclaimed pages, the page of the last deleted rows also holds live ones
+        for a in [800, 1000, 1400] {
+            assert_eq!(key("idx_a", a.into()), None, "{a}");
+            assert_eq!(key("idx_b", b(a)), None, "{a}");
+        }
+        for a in [1500, 1999, 5000, 6499] {
+            assert!(key("idx_a", a.into()).is_some(), "{a}");
+            assert!(key("idx_b", b(a)).is_some(), "{a}");
+        }
+        for a in [1000, 1700, 6000] {
+            let expected = if a < 1500 { vec![] } else { vec![a] };
+            assert_eq!(
+                ints(&mut db, &format!("select a from t1 where a = {a}")),
+                expected
+            );
+            assert_eq!(
+                ints(
+                    &mut db,
+                    &format!("select a from t1 where b = 'padding padding padding {a}'")
+                ),
+                expected
+            );
+        }
+        assert_eq!(ints(&mut db, "select a from t1").len(), 2000);
+
+        // only the entries of deleted rows on pages vacuum kept are left dangling
+        let ScalarValue::UInt64(Some(removed)) = summary[2] else {
+            panic!("unexpected vacuum output {summary:?}");
+        };
+        let not_reclaimed = 1500 - removed;
+        assert!(not_reclaimed > 0 && not_reclaimed < 1500, "{not_reclaimed}");
+        db.reset_runtime_stats();
+        assert!(ints(&mut db, "select a from t1 where a < 1500").is_empty());
+        assert_eq!(db.runtime_stats().dangling_index_entries, not_reclaimed);
+    }
+
+    #[test]
+    pub fn test_vacuum_full_rebuilds_indexes() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create table t2 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        insert_rows(&mut db, "t1", 0..1000);
+        insert_rows(&mut db, "t2", 0..10);
+        // every other row, no page becomes empty
+        db.run("delete from t1 where a - a / 2 * 2 = 1").unwrap();
+
+        assert_eq!(values(&mut db, "vacuum t1")[1], 0u64.into());
+        let summary = assert_no_page_leaks(&mut db, |db| values(db, "vacuum full"));
+        assert_eq!(summary[0], 2u64.into());
+        assert_eq!(summary[2], 500u64.into());
+        let ScalarValue::UInt64(Some(reclaimed)) = summary[1] else {
+            panic!("unexpected vacuum output {summary:?}");
+        };
+        assert!(reclaimed > 0);
+        let even = (0..1000).filter(|a| a % 2 == 0).collect::<Vec<_>>();
+        assert_eq!(ints(&mut db, "select a from t1"), even);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 998"), vec![998]);
+        assert!(ints(&mut db, "select a from t1 where a = 999").is_empty());
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(ints(&mut db, "select a from t1"), even);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 500"), vec![500]);
+        assert_eq!(ints(&mut db, "select a from t2").len(), 10);
+    }
+
+    #[test]
+    pub fn test_analyze_and_checkpoint() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        insert_rows(&mut db, "t1", 0..300);
+        let table_ref = TableReference::bare("t1");
+        assert!(db.catalog.table_statistics(&table_ref).is_none());
+        assert_eq!(
+            values(&mut db, "analyze t1"),
+            vec![1u64.into(), 300u64.into()]
+        );
+        assert_eq!(
+            db.catalog.table_statistics(&table_ref).unwrap().row_count,
+            300
+        );
+        assert_eq!(values(&mut db, "ANALYZE")[0], 1u64.into());
+        assert_eq!(
+            values(&mut db, "checkpoint"),
+            vec![ScalarValue::UInt64(None)]
+        );
+
+        let mut db =
+            Database::new_temp_with_options(DatabaseOptions::new().replication_log(true)).unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        insert_rows(&mut db, "t1", 0..10);
+        assert!(matches!(
+            values(&mut db, "checkpoint")[0],
+            ScalarValue::UInt64(Some(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_reindex_shrinks_bloated_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        insert_rows(&mut db, "t1", 0..3000);
+        // scattered over the key range, about two thirds of the rows
+        let deleted = |a: i32| (a * 7919) % 13 < 9;
+        db.run("delete from t1 where a * 7919 - a * 7919 / 13 * 13 < 9")
+            .unwrap();
+        let live = (0..3000).filter(|a| !deleted(*a)).collect::<Vec<_>>();
+
+        let table_ref = TableReference::bare("t1");
+        let index_pages = |db: &Database, name: &str| {
+            let size = db
+                .relation_sizes()
+                .unwrap()
+                .into_iter()
+                .find(|size| size.table.table() == "t1")
+                .unwrap();
+            size.indexes
+                .into_iter()
+                .find(|index| index.name == name)
+                .unwrap()
+                .pages()
+        };
+        let bloat_sql = "select estimated_bloat from information_schema.relation_sizes \
+                         where relation_name = 'idx_a'";
+        assert_eq!(values(&mut db, bloat_sql), vec![ScalarValue::Float64(None)]);
+        db.run("analyze t1").unwrap();
+        let bloat = db.catalog.table_statistics(&table_ref).unwrap().index_bloat["idx_a"];
+        assert!(bloat > 0.25, "{bloat}");
+        assert_eq!(values(&mut db, bloat_sql), vec![bloat.into()]);
+
+        // readers go on while the table is locked for the reindex, writers wait
+        let pages_before = index_pages(&db, "idx_a");
+        let idx_b_pages = index_pages(&db, "idx_b");
+        db.set_lock_timeout(Duration::from_millis(20));
+        db.lock_manager()
+            .lock_table(999, TableLockMode::Share, &table_ref, Duration::ZERO)
+            .unwrap();
+        assert_eq!(ints(&mut db, "select a from t1 where a = 5"), vec![5]);
+        assert!(matches!(
+            db.run("insert into t1 values (5000, 'new')"),
+            Err(BustubxError::RelationLocked {
+                mode: TableLockMode::RowExclusive,
+                ..
+            })
+        ));
+        let summary = values(&mut db, "reindex idx_a");
+        db.lock_manager().unlock_all(999);
+        let ScalarValue::UInt64(Some(pages_after)) = summary[2] else {
+            panic!("unexpected reindex output {summary:?}");
+        };
+        assert_eq!(summary[0], 1u64.into());
+        assert_eq!(summary[1], (pages_before as u64).into());
+        assert!((pages_after as usize) < pages_before);
+        assert_eq!(index_pages(&db, "idx_a"), pages_after as usize);
+        assert_eq!(index_pages(&db, "idx_b"), idx_b_pages);
+        db.run("analyze t1").unwrap();
+        let statistics = db.catalog.table_statistics(&table_ref).unwrap();
+        assert_eq!(statistics.index_bloat["idx_a"], 0.0);
+        assert!(statistics.index_bloat["idx_b"] > 0.25);
+
+        let summary = values(&mut db, "REINDEX TABLE public.t1;");
+        assert_eq!(summary[0], 2u64.into());
+        assert!(index_pages(&db, "idx_b") < idx_b_pages);
+        for a in [0, 1, 2, 1234, 2999] {
+            let expected = if deleted(a) { vec![] } else { vec![a] };
+            assert_eq!(
+                ints(&mut db, &format!("select a from t1 where a = {a}")),
+                expected
+            );
+        }
+        assert_eq!(ints(&mut db, "select a from t1"), live);
+        // the rebuilt trees take new entries
+        insert_rows(&mut db, "t1", 5000..5010);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 5005"), vec![5005]);
+
+        assert!(matches!(
+            db.run("reindex missing"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("reindex table missing"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(ints(&mut db, "select a from t1 where a = 5"), vec![5]);
+        assert_eq!(ints(&mut db, "select a from t1").len(), live.len() + 10);
+    }
+
+    // (severity, object, page id, description) of every finding
+    fn findings(db: &mut Database, sql: &str) -> Vec<(String, String, Option<u32>, String)> {
+        db.run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| match &tuple.data[..] {
+                [ScalarValue::Varchar(Some(severity)), ScalarValue::Varchar(Some(object)), ScalarValue::UInt32(page_id), ScalarValue::Varchar(Some(description))] => {
+                    (severity.clone(), object.clone(), *page_id, description.clone())
+                }
+                data => panic!("unexpected finding {data:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_check_table_reports_findings() {
+        let mut db = Database::new_temp().unwrap();
+        for table in ["t1", "t2"] {
+            db.run(&format!("create table {table} (a int, b varchar(100))"))
+                .unwrap();
+            db.run(&format!("create index idx_{table} on {table} (a)"))
+                .unwrap();
+            insert_rows(&mut db, table, 0..2000);
+        }
+        db.run("delete from t1 where a >= 300 and a < 900").unwrap();
+        db.run("update t1 set b = 'short' where a < 100").unwrap();
+        // a healthy table has no findings
+        assert!(findings(&mut db, "check table t1").is_empty());
+        assert!(findings(&mut db, "check table t1 with indexes").is_empty());
+
+        // an entry of a row deleted without it, and one of a row that never existed
+        let table_ref = TableReference::bare("t1");
+        let table_heap = db.catalog.table_heap(&table_ref).unwrap();
+        let index = db.catalog.index(&table_ref, "idx_t1").unwrap().unwrap();
+        let key = |a: i32| Tuple::new(index.key_schema.clone(), vec![a.into()]);
+        let deleted_rid = index.get(&key(1500)).unwrap().unwrap();
+        let mut meta = table_heap.tuple_meta(deleted_rid).unwrap();
+        meta.is_deleted = true;
+        table_heap.update_tuple_meta(meta, deleted_rid).unwrap();
+        index
+            .insert(&key(5000), RecordId::new(deleted_rid.page_id, 9999))
+            .unwrap();
+        // only the index check sees them
+        assert!(findings(&mut db, "check table t1").is_empty());
+        let mut found = findings(&mut db, "check table t1 with indexes");
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(found.len(), 2, "{found:?}");
+        assert_eq!(
+            (found[0].0.as_str(), found[0].1.as_str()),
+            ("error", "idx_t1")
+        );
+        assert!(found[0].3.contains("holding no row"), "{found:?}");
+        assert_eq!(
+            (found[1].0.as_str(), found[1].1.as_str()),
+            ("warning", "idx_t1")
+        );
+        assert!(found[1].3.contains("deleted row"), "{found:?}");
+        assert!(found.iter().all(|finding| finding.2.is_some()));
+        // the rebuilt index has the entries of the live rows only
+        db.run("reindex table t1").unwrap();
+        assert!(findings(&mut db, "check table t1 with indexes").is_empty());
+
+        // a slot reaching past the end of its page
+        let table_heap = db.catalog.table_heap(&TableReference::bare("t2")).unwrap();
+        let page_id = table_heap.first_page_id.load(Ordering::SeqCst);
+        let page = db.catalog.buffer_pool.fetch_page(page_id).unwrap();
+        let mut data: [u8; BUSTUBX_PAGE_SIZE] = page.read().unwrap().data().try_into().unwrap();
+        // size of the first slot, after the page header and the offset of the slot
+        let header_len = match data[4] {
+            flags if flags & 0xc0 == 0xc0 => 14,
+            flags if flags & 0x80 != 0 => 10,
+            _ => 8,
+        };
+        data[header_len + 2..header_len + 4].copy_from_slice(&u16::MAX.to_be_bytes());
+        {
+            let _op = operation_scope("corrupt_slot");
+            page.write().unwrap().set_data(data);
+        }
+        drop(page);
+        let found = findings(&mut db, "check table t2 with indexes");
+        assert_eq!(found.len(), 1, "{found:?}");
+        assert_eq!(
+            (found[0].0.as_str(), found[0].1.as_str(), found[0].2),
+            ("error", "t2", Some(page_id))
+        );
+        assert!(found[0].3.contains("slot 0"), "{found:?}");
+        // the damaging write is the last one of the page
+        #[cfg(feature = "debug-history")]
+        assert!(found[0].3.ends_with(" corrupt_slot)"), "{found:?}");
+
+        assert!(matches!(
+            db.run("check table missing"),
+            Err(BustubxError::Plan(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_vacuum_full_waits_for_readers() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        insert_rows(&mut db, "t1", 0..10);
+        db.set_lock_timeout(Duration::from_millis(20));
+        let table_ref = TableReference::bare("t1");
+        db.lock_manager()
+            .lock_table(999, TableLockMode::AccessShare, &table_ref, Duration::ZERO)
+            .unwrap();
+
+        assert!(matches!(
+            db.run("vacuum full t1"),
+            Err(BustubxError::RelationLocked {
+                mode: TableLockMode::AccessExclusive,
+                ..
+            })
+        ));
+        db.run("vacuum t1").unwrap();
+        db.run("analyze t1").unwrap();
+        db.lock_manager().unlock_all(999);
+        db.run("vacuum full t1").unwrap();
+    }
+
+    #[test]
+    pub fn test_cluster_rewrites_heap_in_index_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(100))").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        // keys scattered over the heap
+        let keys = (0..2000).map(|i| i * 7919 % 2000).collect::<Vec<_>>();
+        for chunk in keys.chunks(200) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({a}, 'padding padding padding {a}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        db.run("delete from t1 where a >= 1500").unwrap();
+
+        let table_ref = TableReference::bare("t1");
+        // positions in the page chain of the heap pages the range scan fetched
+        let heap_positions = |db: &mut Database| {
+            let (rows, trace) = traced_run(db, "select a from t1 where a >= 100 and a < 200");
+            let page_ids = db
+                .catalog
+                .table_heap(&table_ref)
+                .unwrap()
+                .page_ids()
+                .unwrap();
+            let positions = trace
+                .iter()
+                .filter_map(|access| page_ids.iter().position(|id| *id == access.page_id))
+                .collect::<BTreeSet<_>>();
+            (rows.len(), positions)
+        };
+        let (rows, scattered) = heap_positions(&mut db);
+        assert_eq!(rows, 100);
+        let sizes = |db: &Database| {
+            let sizes = db.relation_sizes().unwrap();
+            sizes
+                .into_iter()
+                .find(|size| size.table.table() == "t1")
+                .unwrap()
+        };
+        let before = sizes(&db);
+        assert_eq!(before.dead_tuples, 500);
+        let select = "select a from t1 where a < 1000";
+        let expected = ints(&mut db, select);
+
+        db.buffer_pool.disk_manager.reset_stats();
+        let summary = values(&mut db, "cluster t1 using idx_a");
+        assert_eq!(summary[0], 1500u64.into());
+        assert_eq!(summary[1], (before.heap_pages as u64).into());
+        let ScalarValue::UInt64(Some(pages_after)) = summary[2] else {
+            panic!("unexpected cluster output {summary:?}");
+        };
+        let after = sizes(&db);
+        assert_eq!(after.heap_pages, pages_after as usize);
+        assert!(after.heap_pages < before.heap_pages);
+        assert_eq!((after.live_tuples, after.dead_tuples), (1500, 0));
+
+        // the range reads a few consecutive heap pages instead of a page per row
+        let (rows, clustered) = heap_positions(&mut db);
+        assert_eq!(rows, 100);
+        let first = *clustered.first().unwrap();
+        let last = *clustered.last().unwrap();
+        assert_eq!(last - first + 1, clustered.len());
+        assert!(clustered.len() <= 3, "{clustered:?}");
+        assert!(scattered.len() > 5 * clustered.len(), "{scattered:?}");
+        assert_eq!(ints(&mut db, select), expected);
+        assert_eq!(
+            ints(
+                &mut db,
+                "select a from t1 where b = 'padding padding padding 7'"
+            ),
+            vec![7]
+        );
+        // the old chain and the old index trees went back to the disk manager
+        let index_pages = before
+            .indexes
+            .iter()
+            .map(|index| index.level_pages.iter().sum::<usize>())
+            .sum::<usize>();
+        let (_, pages_freed) = db.buffer_pool.disk_manager.page_counts();
+        assert_eq!(pages_freed as usize, before.heap_pages + index_pages);
+
+        assert!(matches!(
+            db.run("cluster t1 using missing"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("cluster missing using idx_a"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert_eq!(
+            db.catalog
+                .catalog_table(&table_ref)
+                .unwrap()
+                .clustered_index
+                .as_deref(),
+            Some("idx_a")
+        );
+        db.close().unwrap();
+
+        // the clustered index and the new first page are in the catalog
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(ints(&mut db, select), expected);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 1499"), vec![1499]);
+        assert_eq!(
+            db.catalog
+                .catalog_table(&table_ref)
+                .unwrap()
+                .clustered_index
+                .as_deref(),
+            Some("idx_a")
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::catalog::SchemaRef;
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::function::AggregateFunctionKind;
+use crate::storage::index::TreeIndexIterator;
+use crate::storage::RecordId;
+use crate::{BustubxResult, Tuple};
+
+/// MIN and MAX without grouping of the leading key column of an index, read off the
+/// entries at the ends of a key range instead of aggregating the rows. Entries of rows a
+/// scan would not return are stepped over.
+#[derive(Debug)]
+pub struct PhysicalMinMax {
+    pub table_ref: TableReference,
+    pub index_name: String,
+    pub start_bound: Bound<Tuple>,
+    pub end_bound: Bound<Tuple>,
+    // Min or Max, one per column of the schema
+    pub aggregates: Vec<AggregateFunctionKind>,
+    pub schema: SchemaRef,
+
+    done: AtomicBool,
+}
+
+impl PhysicalMinMax {
+    pub fn new(
+        table_ref: TableReference,
+        index_name: String,
+        (start_bound, end_bound): (Bound<Tuple>, Bound<Tuple>),
+        aggregates: Vec<AggregateFunctionKind>,
+        schema: SchemaRef,
+    ) -> Self {
+        Self {
+            table_ref,
+            index_name,
+            start_bound,
+            end_bound,
+            aggregates,
+            schema,
+            done: AtomicBool::new(false),
+        }
+    }
+
+    // Whether a scan returns the row of an index entry
+    fn is_visible(&self, context: &ExecutionContext, entry: RecordId) -> BustubxResult<bool> {
+        let catalog_table = context.catalog.catalog_table(&self.table_ref)?;
+        let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+            return Ok(false);
+        };
+        if !context.is_visible(rid) {
+            return Ok(false);
+        }
+        let Some(tuple) = catalog_table.table.live_tuple(rid)? else {
+            return Ok(false);
+        };
+        if context.options.hide_expired_rows && catalog_table.ttl_column.is_some() {
+            return Ok(!catalog_table.is_expired(&tuple, context.clock.now())?);
+        }
+        Ok(true)
+    }
+
+    // Leading key value of the first visible entry of the range not having it NULL
+    fn min(&self, context: &ExecutionContext) -> BustubxResult<Option<ScalarValue>> {
+        let index = context
+            .catalog
+            .index(&self.table_ref, &self.index_name)?
+            .unwrap();
+        let mut iterator =
+            TreeIndexIterator::new(index, (self.start_bound.clone(), self.end_bound.clone()));
+        while let Some((key, entry)) = iterator.next_entry()? {
+            if !key.data[0].is_null() && self.is_visible(context, entry)? {
+                return Ok(Some(key.data[0].clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    // Leading key value of the last visible entry of the range, NULL keys sort first so
+    // once the last entry has a NULL every entry of the range has
+    fn max(&self, context: &ExecutionContext) -> BustubxResult<Option<ScalarValue>> {
+        let index = context
+            .catalog
+            .index(&self.table_ref, &self.index_name)?
+            .unwrap();
+        let Some((last_key, entry)) = index.last_entry_before(self.end_bound.as_ref())? else {
+            return Ok(None);
+        };
+        let after_start = match &self.start_bound {
+            Bound::Included(start) => &last_key >= start,
+            Bound::Excluded(start) => &last_key > start,
+            Bound::Unbounded => true,
+        };
+        if !after_start || last_key.data[0].is_null() {
+            return Ok(None);
+        }
+        if self.is_visible(context, entry)? {
+            return Ok(Some(last_key.data[0].clone()));
+        }
+        // the range is read up to the last entry to find the visible one before it
+        let mut iterator =
+            TreeIndexIterator::new(index, (self.start_bound.clone(), Bound::Included(last_key)));
+        let mut max = None;
+        while let Some((key, entry)) = iterator.next_entry()? {
+            if !key.data[0].is_null() && self.is_visible(context, entry)? {
+                max = Some(key.data[0].clone());
+            }
+        }
+        Ok(max)
+    }
+}
+
+impl VolcanoExecutor for PhysicalMinMax {
+    fn init(&self, _context: &mut ExecutionContext) -> BustubxResult<()> {
+        self.done.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return Ok(None);
+        }
+        // probed as a scan of the index
+        context
+            .catalog
+            .catalog_table(&self.table_ref)?
+            .access_stats
+            .index(&self.index_name)
+            .scans
+            .fetch_add(1, Ordering::Relaxed);
+        let probe = |kind: AggregateFunctionKind| self.aggregates.contains(&kind);
+        let min = if probe(AggregateFunctionKind::Min) {
+            self.min(context)?
+        } else {
+            None
+        };
+        let max = if probe(AggregateFunctionKind::Max) {
+            self.max(context)?
+        } else {
+            None
+        };
+        let values = self
+            .aggregates
+            .iter()
+            .zip(self.schema.columns.iter())
+            .map(|(aggregate, column)| {
+                let value = match aggregate {
+                    AggregateFunctionKind::Min => min.clone(),
+                    _ => max.clone(),
+                };
+                value.unwrap_or(ScalarValue::new_empty(column.data_type))
+            })
+            .collect();
+        Ok(Some(Tuple::new(self.schema.clone(), values)))
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalMinMax {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MinMax: {} using {}", self.table_ref, self.index_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_util::text_rows;
+    use crate::Database;
+
+    fn plan_operators(db: &mut Database, sql: &str) -> Vec<String> {
+        let mut operators = vec![];
+        let mut pending = vec![db.explain(sql).unwrap()];
+        while let Some(tree) = pending.pop() {
+            operators.push(tree.operator);
+            pending.extend(tree.children);
+        }
+        operators
+    }
+
+    #[test]
+    pub fn test_min_max_from_index() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(20), c int)")
+            .unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_cb on t1 (c, b)").unwrap();
+        let values = (0..500)
+            .map(|i| format!("({}, 'b{i}', {})", (i * 37) % 1000, i % 7))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+        db.run("insert into t1 values (null, 'null', null), (null, 'null', 3)")
+            .unwrap();
+        db.run("delete from t1 where a >= 990 or a < 5").unwrap();
+
+        // `a + 0` is no key column, those aggregate every row
+        for (sql, aggregated) in [
+            (
+                "select min(a), max(a) from t1",
+                "select min(a + 0), max(a + 0) from t1",
+            ),
+            (
+                "select max(a) from t1 where a > 100 and a <= 500",
+                "select max(a + 0) from t1 where a > 100 and a <= 500",
+            ),
+            (
+                "select min(a) from t1 where a > 100",
+                "select min(a + 0) from t1 where a > 100",
+            ),
+            (
+                "select max(a), min(a) from t1 where a < 300",
+                "select max(a + 0), min(a + 0) from t1 where a < 300",
+            ),
+            (
+                "select min(a), max(a) from t1 where a = 74",
+                "select min(a + 0), max(a + 0) from t1 where a = 74",
+            ),
+            (
+                "select min(c), max(c) from t1",
+                "select min(c + 0), max(c + 0) from t1",
+            ),
+        ] {
+            assert!(
+                plan_operators(&mut db, sql).contains(&"MinMax".to_string()),
+                "{sql}"
+            );
+            assert!(!plan_operators(&mut db, aggregated).contains(&"MinMax".to_string()));
+            assert_eq!(
+                text_rows(&mut db, sql),
+                text_rows(&mut db, aggregated),
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            text_rows(&mut db, "select min(a), max(a) from t1 where a > 100"),
+            vec!["101 989"]
+        );
+        // deleted rows have no entries, an empty range has no value
+        assert_eq!(text_rows(&mut db, "select min(a) from t1"), vec!["19"]);
+        assert_eq!(
+            text_rows(&mut db, "select min(a), max(a) from t1 where a > 2000"),
+            vec!["NULL NULL"]
+        );
+
+        // other columns, other aggregates and grouping are aggregated row by row
+        for sql in [
+            "select min(b) from t1",
+            "select min(a) from t1 where b = 'b1'",
+            "select min(a), count(a) from t1",
+            "select min(a), max(c) from t1",
+            "select c, max(a) from t1 group by c",
+            "select min(a) from t1 where a > 100 or a < 3",
+        ] {
+            assert!(
+                !plan_operators(&mut db, sql).contains(&"MinMax".to_string()),
+                "{sql}"
+            );
+        }
+        assert_eq!(text_rows(&mut db, "select min(b) from t1"), vec!["b1"]);
+    }
+
+    #[test]
+    pub fn test_min_max_falls_back() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b varchar(20) collate nocase)")
+            .unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create index idx_b on t1 (b)").unwrap();
+        db.run("insert into t1 values (1, 'b'), (2, 'A'), (3, 'a')")
+            .unwrap();
+        let original = db.last_commit().unwrap();
+        db.run("insert into t1 values (4, 'Z')").unwrap();
+
+        // the index orders case-insensitively, MIN compares the stored strings
+        let sql = "select min(b), max(b) from t1";
+        assert!(!plan_operators(&mut db, sql).contains(&"MinMax".to_string()));
+        assert_eq!(text_rows(&mut db, sql), vec!["A b"]);
+
+        // the index only knows the latest rows
+        db.run(&format!("set snapshot_txn = {original}")).unwrap();
+        let sql = "select max(a) from t1";
+        assert!(!plan_operators(&mut db, sql).contains(&"MinMax".to_string()));
+        assert_eq!(text_rows(&mut db, sql), vec!["3"]);
+        db.run("set snapshot_txn = 0").unwrap();
+        assert_eq!(text_rows(&mut db, sql), vec!["4"]);
+
+        // the rows a statement inserts are not seen by its own query
+        db.run("insert into t1 (a) select max(a) + 1 from t1")
+            .unwrap();
+        db.run("insert into t1 (a) select min(a) - 1 from t1")
+            .unwrap();
+        assert_eq!(
+            text_rows(&mut db, "select min(a), max(a) from t1"),
+            vec!["0 5"]
+        );
+    }
+}
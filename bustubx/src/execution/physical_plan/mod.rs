@@ -1,36 +1,64 @@
 mod aggregate;
+mod append;
+mod count;
 mod create_index;
+mod create_schema;
 mod create_table;
+mod delete;
+mod dml_order;
+mod drop_schema;
+mod drop_table;
 mod empty;
 mod filter;
 mod index_scan;
 mod insert;
 mod limit;
+mod maintenance;
+mod min_max;
 mod nested_loop_join;
+mod parallel_seq_scan;
 mod project;
+mod semi_join;
 mod seq_scan;
 mod sort;
 mod update;
 mod values;
 
 pub use aggregate::PhysicalAggregate;
+pub use append::PhysicalAppend;
+pub use count::{CountSource, PhysicalCount};
 pub use create_index::PhysicalCreateIndex;
+pub use create_schema::PhysicalCreateSchema;
 pub use create_table::PhysicalCreateTable;
+pub use delete::PhysicalDelete;
+pub use dml_order::DmlOrder;
+pub use drop_schema::PhysicalDropSchema;
+pub use drop_table::PhysicalDropTable;
 pub use empty::PhysicalEmpty;
 pub use filter::PhysicalFilter;
-pub use index_scan::PhysicalIndexScan;
+pub use index_scan::{KeyRange, PhysicalIndexScan};
 pub use insert::PhysicalInsert;
+pub(crate) use insert::{index_keys, unique_violation, update_index_entries};
 pub use limit::PhysicalLimit;
+pub use maintenance::PhysicalMaintenance;
+pub use min_max::PhysicalMinMax;
 pub use nested_loop_join::PhysicalNestedLoopJoin;
+pub use parallel_seq_scan::PhysicalParallelSeqScan;
 pub use project::PhysicalProject;
+pub use semi_join::PhysicalHashSemiJoin;
 pub use seq_scan::PhysicalSeqScan;
+#[cfg(test)]
+pub(crate) use sort::compare_tuples;
 pub use sort::PhysicalSort;
+pub(crate) use sort::{tuple_size_estimate, SortRun};
 pub use update::PhysicalUpdate;
 pub use values::PhysicalValues;
 
 use crate::catalog::SchemaRef;
+use crate::common::TableReference;
+use crate::transaction::TableLockMode;
 use crate::{
-    execution::{ExecutionContext, VolcanoExecutor},
+    execution::{ExecutionContext, TupleBatch, VolcanoExecutor},
     storage::Tuple,
     BustubxResult,
 };
@@ -40,17 +68,27 @@ pub enum PhysicalPlan {
     Empty(PhysicalEmpty),
     CreateTable(PhysicalCreateTable),
     CreateIndex(PhysicalCreateIndex),
+    DropTable(PhysicalDropTable),
+    CreateSchema(PhysicalCreateSchema),
+    DropSchema(PhysicalDropSchema),
     Project(PhysicalProject),
     Filter(PhysicalFilter),
     SeqScan(PhysicalSeqScan),
+    ParallelSeqScan(PhysicalParallelSeqScan),
     IndexScan(PhysicalIndexScan),
+    Append(PhysicalAppend),
     Limit(PhysicalLimit),
     Insert(PhysicalInsert),
     Values(PhysicalValues),
     NestedLoopJoin(PhysicalNestedLoopJoin),
+    HashSemiJoin(PhysicalHashSemiJoin),
     Sort(PhysicalSort),
     Aggregate(PhysicalAggregate),
+    Count(PhysicalCount),
+    MinMax(PhysicalMinMax),
     Update(PhysicalUpdate),
+    Delete(PhysicalDelete),
+    Maintenance(PhysicalMaintenance),
 }
 
 impl PhysicalPlan {
@@ -64,18 +102,109 @@ impl PhysicalPlan {
                 left_input,
                 right_input,
                 ..
+            })
+            | PhysicalPlan::HashSemiJoin(PhysicalHashSemiJoin {
+                left_input,
+                right_input,
+                ..
             }) => vec![left_input, right_input],
             PhysicalPlan::Sort(PhysicalSort { input, .. }) => vec![input],
             PhysicalPlan::Aggregate(PhysicalAggregate { input, .. }) => vec![input],
+            PhysicalPlan::Append(PhysicalAppend { inputs, .. }) => {
+                inputs.iter().map(|input| input.as_ref()).collect()
+            }
             PhysicalPlan::Empty(_)
             | PhysicalPlan::CreateTable(_)
             | PhysicalPlan::CreateIndex(_)
+            | PhysicalPlan::DropTable(_)
+            | PhysicalPlan::CreateSchema(_)
+            | PhysicalPlan::DropSchema(_)
             | PhysicalPlan::SeqScan(_)
+            | PhysicalPlan::ParallelSeqScan(_)
             | PhysicalPlan::IndexScan(_)
+            | PhysicalPlan::Count(_)
+            | PhysicalPlan::MinMax(_)
             | PhysicalPlan::Update(_)
+            | PhysicalPlan::Delete(_)
+            | PhysicalPlan::Maintenance(_)
             | PhysicalPlan::Values(_) => vec![],
         }
     }
+
+    /// Tables the plan reads rows of take ACCESS SHARE and tables it writes rows of ROW
+    /// EXCLUSIVE, a table an index is built on or rewritten by VACUUM FULL ACCESS EXCLUSIVE.
+    pub fn table_locks(&self) -> Vec<(TableReference, TableLockMode)> {
+        let mut locks = match self {
+            PhysicalPlan::SeqScan(PhysicalSeqScan { table, .. })
+            | PhysicalPlan::ParallelSeqScan(PhysicalParallelSeqScan { table, .. }) => {
+                vec![(table.clone(), TableLockMode::AccessShare)]
+            }
+            PhysicalPlan::Insert(PhysicalInsert { table, .. })
+            | PhysicalPlan::Update(PhysicalUpdate { table, .. })
+            | PhysicalPlan::Delete(PhysicalDelete { table, .. }) => {
+                vec![(table.clone(), TableLockMode::RowExclusive)]
+            }
+            PhysicalPlan::IndexScan(PhysicalIndexScan { table_ref, .. })
+            | PhysicalPlan::Count(PhysicalCount { table_ref, .. })
+            | PhysicalPlan::MinMax(PhysicalMinMax { table_ref, .. }) => {
+                vec![(table_ref.clone(), TableLockMode::AccessShare)]
+            }
+            PhysicalPlan::CreateIndex(PhysicalCreateIndex { table, .. }) => {
+                vec![(table.clone(), TableLockMode::AccessExclusive)]
+            }
+            PhysicalPlan::DropTable(PhysicalDropTable { tables })
+            | PhysicalPlan::DropSchema(PhysicalDropSchema { tables, .. }) => tables
+                .iter()
+                .map(|table| (table.clone(), TableLockMode::AccessExclusive))
+                .collect(),
+            PhysicalPlan::Maintenance(maintenance) => maintenance
+                .tables
+                .iter()
+                .map(|table| (table.clone(), maintenance.lock_mode()))
+                .collect(),
+            PhysicalPlan::Empty(_)
+            | PhysicalPlan::CreateTable(_)
+            | PhysicalPlan::CreateSchema(_)
+            | PhysicalPlan::Project(_)
+            | PhysicalPlan::Filter(_)
+            | PhysicalPlan::Append(_)
+            | PhysicalPlan::Limit(_)
+            | PhysicalPlan::Values(_)
+            | PhysicalPlan::NestedLoopJoin(_)
+            | PhysicalPlan::HashSemiJoin(_)
+            | PhysicalPlan::Sort(_)
+            | PhysicalPlan::Aggregate(_) => vec![],
+        };
+        for input in self.inputs() {
+            locks.extend(input.table_locks());
+        }
+        locks
+    }
+
+    /// Next batch of rows when batch execution is enabled, None once the plan is exhausted.
+    /// Scans, filters and projections produce batches themselves, the rows of the other
+    /// operators are collected from their `next`.
+    pub fn next_batch(&self, context: &mut ExecutionContext) -> BustubxResult<Option<TupleBatch>> {
+        context.check_deadline()?;
+        let batch = match self {
+            PhysicalPlan::SeqScan(op) => op.next_batch(context)?,
+            PhysicalPlan::Filter(op) => op.next_batch(context)?,
+            PhysicalPlan::Project(op) => op.next_batch(context)?,
+            _ => {
+                let batch_size = context.options.batch_size;
+                return TupleBatch::collect(batch_size, || self.next(context));
+            }
+        };
+        if let (Some(batch), Some(operator_rows)) = (&batch, context.operator_rows.as_mut()) {
+            *operator_rows.entry(self.address()).or_default() += batch.len() as u64;
+        }
+        Ok(batch)
+    }
+
+    // identifies the node while the plan is alive
+    pub(crate) fn address(&self) -> usize {
+        self as *const Self as usize
+    }
 }
 
 impl VolcanoExecutor for PhysicalPlan {
@@ -84,37 +213,66 @@ impl VolcanoExecutor for PhysicalPlan {
             PhysicalPlan::Empty(op) => op.init(context),
             PhysicalPlan::CreateTable(op) => op.init(context),
             PhysicalPlan::CreateIndex(op) => op.init(context),
+            PhysicalPlan::DropTable(op) => op.init(context),
+            PhysicalPlan::CreateSchema(op) => op.init(context),
+            PhysicalPlan::DropSchema(op) => op.init(context),
             PhysicalPlan::Insert(op) => op.init(context),
             PhysicalPlan::Values(op) => op.init(context),
             PhysicalPlan::Project(op) => op.init(context),
             PhysicalPlan::Filter(op) => op.init(context),
             PhysicalPlan::SeqScan(op) => op.init(context),
+            PhysicalPlan::ParallelSeqScan(op) => op.init(context),
             PhysicalPlan::IndexScan(op) => op.init(context),
+            PhysicalPlan::Append(op) => op.init(context),
             PhysicalPlan::Limit(op) => op.init(context),
             PhysicalPlan::NestedLoopJoin(op) => op.init(context),
+            PhysicalPlan::HashSemiJoin(op) => op.init(context),
             PhysicalPlan::Sort(op) => op.init(context),
             PhysicalPlan::Aggregate(op) => op.init(context),
+            PhysicalPlan::Count(op) => op.init(context),
+            PhysicalPlan::MinMax(op) => op.init(context),
             PhysicalPlan::Update(op) => op.init(context),
+            PhysicalPlan::Delete(op) => op.init(context),
+            PhysicalPlan::Maintenance(op) => op.init(context),
         }
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
-        match self {
+        // every operator pulls its input rows through here, so this is where a statement
+        // running past its deadline is canceled
+        context.check_deadline()?;
+        let tuple = match self {
             PhysicalPlan::Empty(op) => op.next(context),
             PhysicalPlan::CreateTable(op) => op.next(context),
             PhysicalPlan::CreateIndex(op) => op.next(context),
+            PhysicalPlan::DropTable(op) => op.next(context),
+            PhysicalPlan::CreateSchema(op) => op.next(context),
+            PhysicalPlan::DropSchema(op) => op.next(context),
             PhysicalPlan::Insert(op) => op.next(context),
             PhysicalPlan::Values(op) => op.next(context),
             PhysicalPlan::Project(op) => op.next(context),
             PhysicalPlan::Filter(op) => op.next(context),
             PhysicalPlan::SeqScan(op) => op.next(context),
+            PhysicalPlan::ParallelSeqScan(op) => op.next(context),
             PhysicalPlan::IndexScan(op) => op.next(context),
+            PhysicalPlan::Append(op) => op.next(context),
             PhysicalPlan::Limit(op) => op.next(context),
             PhysicalPlan::NestedLoopJoin(op) => op.next(context),
+            PhysicalPlan::HashSemiJoin(op) => op.next(context),
             PhysicalPlan::Sort(op) => op.next(context),
             PhysicalPlan::Aggregate(op) => op.next(context),
+            PhysicalPlan::Count(op) => op.next(context),
+            PhysicalPlan::MinMax(op) => op.next(context),
             PhysicalPlan::Update(op) => op.next(context),
+            PhysicalPlan::Delete(op) => op.next(context),
+            PhysicalPlan::Maintenance(op) => op.next(context),
+        }?;
+        if tuple.is_some() {
+            if let Some(operator_rows) = context.operator_rows.as_mut() {
+                *operator_rows.entry(self.address()).or_default() += 1;
+            }
         }
+        Ok(tuple)
     }
 
     fn output_schema(&self) -> SchemaRef {
@@ -122,17 +280,27 @@ impl VolcanoExecutor for PhysicalPlan {
             Self::Empty(op) => op.output_schema(),
             Self::CreateTable(op) => op.output_schema(),
             Self::CreateIndex(op) => op.output_schema(),
+            Self::DropTable(op) => op.output_schema(),
+            Self::CreateSchema(op) => op.output_schema(),
+            Self::DropSchema(op) => op.output_schema(),
             Self::Insert(op) => op.output_schema(),
             Self::Values(op) => op.output_schema(),
             Self::Project(op) => op.output_schema(),
             Self::Filter(op) => op.output_schema(),
             Self::SeqScan(op) => op.output_schema(),
+            Self::ParallelSeqScan(op) => op.output_schema(),
             Self::IndexScan(op) => op.output_schema(),
+            Self::Append(op) => op.output_schema(),
             Self::Limit(op) => op.output_schema(),
             Self::NestedLoopJoin(op) => op.output_schema(),
+            Self::HashSemiJoin(op) => op.output_schema(),
             Self::Sort(op) => op.output_schema(),
             Self::Aggregate(op) => op.output_schema(),
+            Self::Count(op) => op.output_schema(),
+            Self::MinMax(op) => op.output_schema(),
             Self::Update(op) => op.output_schema(),
+            Self::Delete(op) => op.output_schema(),
+            Self::Maintenance(op) => op.output_schema(),
         }
     }
 }
@@ -143,17 +311,27 @@ impl std::fmt::Display for PhysicalPlan {
             Self::Empty(op) => write!(f, "{op}"),
             Self::CreateTable(op) => write!(f, "{op}"),
             Self::CreateIndex(op) => write!(f, "{op}"),
+            Self::DropTable(op) => write!(f, "{op}"),
+            Self::CreateSchema(op) => write!(f, "{op}"),
+            Self::DropSchema(op) => write!(f, "{op}"),
             Self::Insert(op) => write!(f, "{op}"),
             Self::Values(op) => write!(f, "{op}"),
             Self::Project(op) => write!(f, "{op}"),
             Self::Filter(op) => write!(f, "{op}"),
             Self::SeqScan(op) => write!(f, "{op}"),
+            Self::ParallelSeqScan(op) => write!(f, "{op}"),
             Self::IndexScan(op) => write!(f, "{op}"),
+            Self::Append(op) => write!(f, "{op}"),
             Self::Limit(op) => write!(f, "{op}"),
             Self::NestedLoopJoin(op) => write!(f, "{op}"),
+            Self::HashSemiJoin(op) => write!(f, "{op}"),
             Self::Sort(op) => write!(f, "{op}"),
             Self::Aggregate(op) => write!(f, "{op}"),
+            Self::Count(op) => write!(f, "{op}"),
+            Self::MinMax(op) => write!(f, "{op}"),
             Self::Update(op) => write!(f, "{op}"),
+            Self::Delete(op) => write!(f, "{op}"),
+            Self::Maintenance(op) => write!(f, "{op}"),
         }
     }
 }
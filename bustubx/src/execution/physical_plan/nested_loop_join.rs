@@ -21,7 +21,10 @@ pub struct PhysicalNestedLoopJoin {
     pub right_input: Arc<PhysicalPlan>,
     pub schema: SchemaRef,
 
-    left_tuple: Mutex<Option<Tuple>>,
+    // left row being joined and whether a right row matched it so far
+    left_tuple: Mutex<Option<(Tuple, bool)>>,
+    // whether the right input has no rows, known once it was read through
+    right_empty: Mutex<Option<bool>>,
 }
 impl PhysicalNestedLoopJoin {
     pub fn new(
@@ -38,6 +41,7 @@ impl PhysicalNestedLoopJoin {
             right_input,
             schema,
             left_tuple: Mutex::new(None),
+            right_empty: Mutex::new(None),
         }
     }
 }
@@ -47,29 +51,37 @@ impl VolcanoExecutor for PhysicalNestedLoopJoin {
         self.left_input.init(context)?;
         self.right_input.init(context)?;
         *self.left_tuple.lock().unwrap() = None;
+        *self.right_empty.lock().unwrap() = None;
         Ok(())
     }
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
         let left_tuple = self.left_tuple.lock().unwrap();
         let mut left_next_tuple = if left_tuple.is_none() {
-            self.left_input.next(context)?
+            self.left_input.next(context)?.map(|tuple| (tuple, false))
         } else {
-            Some(left_tuple.clone().unwrap())
+            left_tuple.clone()
         };
         // release mutex
         drop(left_tuple);
 
-        while left_next_tuple.is_some() {
-            let left_tuple = left_next_tuple.clone().unwrap();
-
-            let mut right_next_tuple = self.right_input.next(context)?;
+        while let Some((left_tuple, matched)) = left_next_tuple {
+            // an empty right input is read once rather than again for every left row
+            let right_empty = *self.right_empty.lock().unwrap() == Some(true);
+            let mut right_next_tuple = if right_empty {
+                None
+            } else {
+                self.right_input.next(context)?
+            };
+            if right_next_tuple.is_some() {
+                *self.right_empty.lock().unwrap() = Some(false);
+            }
             while right_next_tuple.is_some() {
                 let right_tuple = right_next_tuple.unwrap();
 
                 // TODO judge if matches
                 if self.condition.is_none() {
                     // save latest left_next_result before return
-                    *self.left_tuple.lock().unwrap() = Some(left_tuple.clone());
+                    *self.left_tuple.lock().unwrap() = Some((left_tuple.clone(), true));
 
                     return Ok(Some(Tuple::try_merge(vec![left_tuple, right_tuple])?));
                 } else {
@@ -77,26 +89,43 @@ impl VolcanoExecutor for PhysicalNestedLoopJoin {
                     let merged_tuple =
                         Tuple::try_merge(vec![left_tuple.clone(), right_tuple.clone()])?;
                     let evaluate_res = condition.evaluate(&merged_tuple)?;
-                    // TODO support left/right join after null support added
-                    if let ScalarValue::Boolean(Some(v)) = evaluate_res {
-                        if v {
+                    // TODO support right join
+                    match evaluate_res {
+                        ScalarValue::Boolean(Some(true)) => {
                             // save latest left_next_result before return
-                            *self.left_tuple.lock().unwrap() = Some(left_tuple.clone());
+                            *self.left_tuple.lock().unwrap() = Some((left_tuple.clone(), true));
 
                             return Ok(Some(Tuple::try_merge(vec![left_tuple, right_tuple])?));
                         }
-                    } else {
-                        panic!("nested loop join condition should be boolean")
+                        // a condition comparing with NULL matches nothing
+                        ScalarValue::Boolean(_) => {}
+                        _ => panic!("nested loop join condition should be boolean"),
                     }
                 }
 
                 right_next_tuple = self.right_input.next(context)?;
             }
 
+            let right_empty = *self.right_empty.lock().unwrap().get_or_insert(true);
+            // no left row finds a right row to join with
+            if right_empty && matches!(self.join_type, JoinType::Inner | JoinType::Cross) {
+                break;
+            }
             // reset right executor
-            self.right_input.init(context)?;
-            left_next_tuple = self.left_input.next(context)?;
+            if !right_empty {
+                self.right_input.init(context)?;
+            }
+            left_next_tuple = self.left_input.next(context)?.map(|tuple| (tuple, false));
+
+            // a left row without any match comes out once with a null right side
+            if self.join_type == JoinType::LeftOuter && !matched {
+                *self.left_tuple.lock().unwrap() = left_next_tuple;
+                let right_tuple = Tuple::empty(self.right_input.output_schema());
+                return Ok(Some(Tuple::try_merge(vec![left_tuple, right_tuple])?));
+            }
         }
+        // exhausted, a call after the end must not start over with the saved left row
+        *self.left_tuple.lock().unwrap() = None;
         Ok(None)
     }
 
@@ -0,0 +1,319 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::buffer::PageId;
+use crate::catalog::{SchemaRef, TableAccessStats};
+use crate::common::TableReference;
+use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::expression::{Expr, ExprTrait};
+use crate::storage::{RecordId, TableHeap, Tuple};
+use crate::{BustubxError, BustubxResult};
+
+// batches of one page every worker may produce ahead of the operator
+const BATCHES_AHEAD_PER_WORKER: usize = 2;
+
+type ScanBatch = BustubxResult<Vec<Tuple>>;
+
+/// Sequential scan read by worker threads, each one scanning a run of consecutive pages
+/// of the heap and applying the pushed down predicate. The rows of all workers meet in a
+/// bounded channel the operator reads from, in no particular order.
+///
+/// The workers read under the table lock of the statement and are stopped before the
+/// operator fails or is dropped. The first worker failing cancels the others and its error
+/// fails the statement.
+#[derive(Debug)]
+pub struct PhysicalParallelSeqScan {
+    pub table: TableReference,
+    pub table_schema: SchemaRef,
+    pub predicate: Option<Expr>,
+    pub workers: usize,
+
+    state: Mutex<Option<ScanWorkers>>,
+}
+
+impl PhysicalParallelSeqScan {
+    pub fn new(
+        table: TableReference,
+        table_schema: SchemaRef,
+        predicate: Option<Expr>,
+        workers: usize,
+    ) -> Self {
+        Self {
+            table,
+            table_schema,
+            predicate,
+            workers,
+            state: Mutex::new(None),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ScanWorkers {
+    // None once the workers are stopped
+    receiver: Option<Receiver<ScanBatch>>,
+    cancel: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+    pending: VecDeque<Tuple>,
+    access_stats: Arc<TableAccessStats>,
+}
+
+impl ScanWorkers {
+    // Cancel the workers and wait for them to finish, an error if one of them panicked.
+    fn stop(&mut self) -> BustubxResult<()> {
+        self.cancel.store(true, Ordering::SeqCst);
+        // wakes up the workers waiting for room in the channel
+        self.receiver = None;
+        let mut panicked = false;
+        for handle in self.handles.drain(..) {
+            panicked |= handle.join().is_err();
+        }
+        if panicked {
+            return Err(BustubxError::Internal(
+                "a parallel scan worker panicked".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ScanWorkers {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn scan_pages(
+    heap: Arc<TableHeap>,
+    page_ids: Vec<PageId>,
+    predicate: Option<Expr>,
+    inserted_rids: Arc<HashSet<RecordId>>,
+    sender: SyncSender<ScanBatch>,
+    cancel: Arc<AtomicBool>,
+) {
+    for page_id in page_ids {
+        if cancel.load(Ordering::SeqCst) {
+            return;
+        }
+        let batch = heap.page_tuples(page_id).and_then(|tuples| {
+            let mut batch = Vec::with_capacity(tuples.len());
+            for (rid, meta, tuple) in tuples {
+                if meta.is_deleted || inserted_rids.contains(&rid) {
+                    continue;
+                }
+                if let Some(predicate) = &predicate {
+                    if !predicate.evaluate(&tuple)?.as_boolean()?.unwrap_or(false) {
+                        continue;
+                    }
+                }
+                batch.push(tuple);
+            }
+            Ok(batch)
+        });
+        match batch {
+            Ok(batch) if batch.is_empty() => {}
+            Ok(batch) => {
+                // the operator stopped reading
+                if sender.send(Ok(batch)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                cancel.store(true, Ordering::SeqCst);
+                let _ = sender.send(Err(e));
+                return;
+            }
+        }
+    }
+}
+
+impl VolcanoExecutor for PhysicalParallelSeqScan {
+    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(mut workers) = state.take() {
+            workers.stop()?;
+        }
+        let catalog_table = context.catalog.catalog_table(&self.table)?;
+        let heap = catalog_table.table.clone();
+        let access_stats = catalog_table.access_stats.clone();
+        access_stats.seq_scans.fetch_add(1, Ordering::Relaxed);
+        let partitions = heap.partition_pages(self.workers)?;
+        let (sender, receiver) = mpsc::sync_channel(partitions.len() * BATCHES_AHEAD_PER_WORKER);
+        let cancel = Arc::new(AtomicBool::new(false));
+        // the rows `ExecutionContext::is_visible` hides, the workers run without the context
+        let inserted_rids = Arc::new(context.inserted_rids.clone());
+        let handles = partitions
+            .into_iter()
+            .map(|page_ids| {
+                let heap = heap.clone();
+                let predicate = self.predicate.clone();
+                let inserted_rids = inserted_rids.clone();
+                let sender = sender.clone();
+                let cancel = cancel.clone();
+                thread::spawn(move || {
+                    scan_pages(heap, page_ids, predicate, inserted_rids, sender, cancel)
+                })
+            })
+            .collect();
+        *state = Some(ScanWorkers {
+            receiver: Some(receiver),
+            cancel,
+            handles,
+            pending: VecDeque::new(),
+            access_stats,
+        });
+        Ok(())
+    }
+
+    fn next(&self, _context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        let mut state = self.state.lock().unwrap();
+        let Some(workers) = state.as_mut() else {
+            return Err(BustubxError::Execution(
+                "parallel scan workers not started".to_string(),
+            ));
+        };
+        loop {
+            if let Some(tuple) = workers.pending.pop_front() {
+                workers
+                    .access_stats
+                    .seq_tuples_read
+                    .fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(tuple));
+            }
+            let Some(receiver) = &workers.receiver else {
+                return Ok(None);
+            };
+            match receiver.recv() {
+                Ok(Ok(batch)) => workers.pending.extend(batch),
+                Ok(Err(e)) => {
+                    let _ = workers.stop();
+                    return Err(e);
+                }
+                // every worker finished its pages
+                Err(_) => {
+                    workers.stop()?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.table_schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalParallelSeqScan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ParallelSeqScan: {} workers", self.workers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BustubxError, Database};
+
+    fn fixture() -> Database {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (id int, v varchar(100))").unwrap();
+        for chunk in (0..3000).collect::<Vec<i32>>().chunks(300) {
+            let values = chunk
+                .iter()
+                .map(|id| format!("({id}, 'padding of row {id}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        db.run("delete from t1 where id > 2900").unwrap();
+        db.run("set parallel_scan_min_pages = 2").unwrap();
+        db
+    }
+
+    fn operators(db: &mut Database, sql: &str) -> Vec<String> {
+        let mut operators = vec![];
+        let mut pending = vec![db.explain(sql).unwrap()];
+        while let Some(tree) = pending.pop() {
+            operators.push(tree.operator);
+            pending.extend(tree.children);
+        }
+        operators
+    }
+
+    // rows as a multiset, the workers produce them in no particular order
+    fn sorted_rows(db: &mut Database, sql: &str) -> Vec<String> {
+        let mut rows = db
+            .run(sql)
+            .unwrap()
+            .iter()
+            .map(|tuple| format!("{:?}", tuple.data))
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    fn pinned_frames(db: &Database) -> u32 {
+        db.buffer_pool
+            .frames()
+            .iter()
+            .map(|frame| frame.pin_count)
+            .sum()
+    }
+
+    #[test]
+    pub fn test_parallel_scan_matches_serial_scan() {
+        let mut db = fixture();
+        let pinned = pinned_frames(&db);
+        let queries = [
+            "select * from t1",
+            "select id from t1 where id >= 1000 and id < 2500",
+            "select v from t1 where id = 17",
+        ];
+        let serial = queries
+            .iter()
+            .map(|sql| sorted_rows(&mut db, sql))
+            .collect::<Vec<_>>();
+        assert_eq!(serial[0].len(), 2901);
+        assert!(!operators(&mut db, queries[0]).contains(&"ParallelSeqScan".to_string()));
+
+        db.run("set parallel_scan_workers = 4").unwrap();
+        for (sql, expected) in queries.iter().zip(serial) {
+            let operators = operators(&mut db, sql);
+            assert!(operators.contains(&"ParallelSeqScan".to_string()), "{sql}");
+            // the workers apply the filter
+            assert!(!operators.contains(&"Filter".to_string()), "{sql}");
+            assert_eq!(sorted_rows(&mut db, sql), expected, "{sql}");
+        }
+        // workers of a scan the limit stopped reading are shut down with the plan
+        assert_eq!(db.run("select id from t1 limit 5").unwrap().len(), 5);
+        assert_eq!(pinned_frames(&db), pinned);
+
+        // too small a table is scanned serially
+        db.run("set parallel_scan_min_pages = 100000").unwrap();
+        assert!(!operators(&mut db, queries[0]).contains(&"ParallelSeqScan".to_string()));
+    }
+
+    #[test]
+    pub fn test_parallel_scan_worker_error() {
+        let mut db = fixture();
+        let pinned = pinned_frames(&db);
+        db.run("set parallel_scan_workers = 4").unwrap();
+
+        // only the worker reaching the row fails, the others are canceled
+        let sql = "select id from t1 where 100 / (id - 2222) > 0";
+        assert!(operators(&mut db, sql).contains(&"ParallelSeqScan".to_string()));
+        for _ in 0..5 {
+            match db.run(sql) {
+                Err(BustubxError::Execution(e)) => assert!(e.contains("division by zero"), "{e}"),
+                result => panic!("unexpected result {result:?}"),
+            }
+            assert_eq!(pinned_frames(&db), pinned);
+        }
+        assert_eq!(
+            db.run("select id from t1 where id > 2200").unwrap().len(),
+            700
+        );
+    }
+}
@@ -1,9 +1,10 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 
 use crate::catalog::SchemaRef;
 use crate::expression::{Expr, ExprTrait};
 use crate::{
-    execution::{ExecutionContext, VolcanoExecutor},
+    execution::{next_buffered, BatchExpr, ExecutionContext, TupleBatch, VolcanoExecutor},
     storage::Tuple,
     BustubxResult,
 };
@@ -15,14 +16,51 @@ pub struct PhysicalProject {
     pub exprs: Vec<Expr>,
     pub schema: SchemaRef,
     pub input: Arc<PhysicalPlan>,
+
+    // rest of the current batch when the parent consumes rows in batch execution
+    #[new(default)]
+    pending: Mutex<VecDeque<Tuple>>,
+}
+
+impl PhysicalProject {
+    /// Next input batch projected one expression at a time over all of its rows.
+    pub fn next_batch(&self, context: &mut ExecutionContext) -> BustubxResult<Option<TupleBatch>> {
+        let Some(batch) = self.input.next_batch(context)? else {
+            return Ok(None);
+        };
+        let schema = batch.tuples[0].schema.clone();
+        let mut columns = self
+            .exprs
+            .iter()
+            .map(|expr| {
+                BatchExpr::compile(expr, &schema)
+                    .evaluate(&batch.tuples)
+                    .map(|values| values.into_iter())
+            })
+            .collect::<BustubxResult<Vec<_>>>()?;
+        let tuples = (0..batch.len())
+            .map(|_| {
+                let values = columns
+                    .iter_mut()
+                    .map(|column| column.next().unwrap())
+                    .collect();
+                Tuple::new(self.schema.clone(), values)
+            })
+            .collect();
+        Ok(Some(TupleBatch::new(tuples)))
+    }
 }
 
 impl VolcanoExecutor for PhysicalProject {
     fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        self.pending.lock().unwrap().clear();
         self.input.init(context)
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        if context.options.batch_execution {
+            return next_buffered(&self.pending, || self.next_batch(context));
+        }
         if let Some(tuple) = self.input.next(context)? {
             let mut new_values = Vec::new();
             for expr in &self.exprs {
@@ -0,0 +1,396 @@
+use log::debug;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::catalog::{DataType, SchemaRef};
+use crate::common::{numeric, ScalarValue};
+use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::expression::{Expr, ExprTrait};
+use crate::planner::logical_plan::JoinType;
+use crate::storage::Tuple;
+use crate::BustubxResult;
+
+use super::PhysicalPlan;
+
+/// Semi or anti join of the left input with the keys of the right input, every left row
+/// comes out at most once. The right input is read into a hash set of its keys first.
+#[derive(Debug)]
+pub struct PhysicalHashSemiJoin {
+    // LeftSemi, LeftAnti or NullAwareLeftAnti
+    pub join_type: JoinType,
+    // key pairs compared for equality, none for an uncorrelated EXISTS
+    pub left_keys: Vec<Expr>,
+    pub right_keys: Vec<Expr>,
+    // type both keys of a pair are compared as, the key type itself when unknown
+    pub key_types: Vec<Option<DataType>>,
+    pub left_input: Arc<PhysicalPlan>,
+    pub right_input: Arc<PhysicalPlan>,
+    pub schema: SchemaRef,
+
+    build_side: Mutex<Option<BuildSide>>,
+}
+
+#[derive(Debug, Default)]
+struct BuildSide {
+    // keys of the right rows without a null key part
+    keys: HashSet<Vec<ScalarValue>>,
+    has_rows: bool,
+    has_null_key: bool,
+}
+
+impl PhysicalHashSemiJoin {
+    pub fn new(
+        join_type: JoinType,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        key_types: Vec<Option<DataType>>,
+        left_input: Arc<PhysicalPlan>,
+        right_input: Arc<PhysicalPlan>,
+        schema: SchemaRef,
+    ) -> Self {
+        Self {
+            join_type,
+            left_keys,
+            right_keys,
+            key_types,
+            left_input,
+            right_input,
+            schema,
+            build_side: Mutex::new(None),
+        }
+    }
+
+    // `None` when a part of the key is null, a null key equals nothing
+    fn evaluate_key(
+        &self,
+        keys: &[Expr],
+        tuple: &Tuple,
+    ) -> BustubxResult<Option<Vec<ScalarValue>>> {
+        let mut key = Vec::with_capacity(keys.len());
+        for (expr, key_type) in keys.iter().zip(self.key_types.iter()) {
+            let value = expr.evaluate(tuple)?;
+            if value.is_null() {
+                return Ok(None);
+            }
+            key.push(match key_type {
+                Some(key_type) if !matches!(key_type, DataType::Varchar(_)) => {
+                    match value.cast_to(key_type) {
+                        Ok(value) => value,
+                        // an integer beyond the key type equals no key of the other side
+                        Err(_) if numeric::integer_value(&value).is_some() => value,
+                        Err(e) => return Err(e),
+                    }
+                }
+                _ => value,
+            });
+        }
+        Ok(Some(key))
+    }
+
+    fn build(&self, context: &mut ExecutionContext) -> BustubxResult<BuildSide> {
+        let mut build_side = BuildSide::default();
+        while let Some(tuple) = self.right_input.next(context)? {
+            build_side.has_rows = true;
+            match self.evaluate_key(&self.right_keys, &tuple)? {
+                Some(key) => {
+                    build_side.keys.insert(key);
+                }
+                None => build_side.has_null_key = true,
+            }
+        }
+        Ok(build_side)
+    }
+
+    fn passes(&self, build_side: &BuildSide, tuple: &Tuple) -> BustubxResult<bool> {
+        let key = self.evaluate_key(&self.left_keys, tuple)?;
+        let matched = key
+            .as_ref()
+            .is_some_and(|key| build_side.keys.contains(key));
+        Ok(match self.join_type {
+            JoinType::LeftSemi => matched,
+            JoinType::LeftAnti => !matched,
+            // `x NOT IN (...)` is null rather than true once x or a value of the
+            // subquery is null, unless the subquery has no rows at all
+            _ => !build_side.has_rows || (!matched && key.is_some() && !build_side.has_null_key),
+        })
+    }
+}
+
+impl VolcanoExecutor for PhysicalHashSemiJoin {
+    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        debug!("init hash semi join executor");
+        self.left_input.init(context)?;
+        self.right_input.init(context)?;
+        *self.build_side.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        let mut build_side = self.build_side.lock().unwrap();
+        if build_side.is_none() {
+            *build_side = Some(self.build(context)?);
+        }
+        let build_side = build_side.as_ref().unwrap();
+        // no left row has a match in an empty right input
+        if self.join_type == JoinType::LeftSemi && !build_side.has_rows {
+            return Ok(None);
+        }
+        while let Some(tuple) = self.left_input.next(context)? {
+            if self.passes(build_side, &tuple)? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+
+    fn output_schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl std::fmt::Display for PhysicalHashSemiJoin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HashSemiJoin: {}", self.join_type)?;
+        if !self.left_keys.is_empty() {
+            let keys = self
+                .left_keys
+                .iter()
+                .zip(self.right_keys.iter())
+                .map(|(l, r)| format!("{l} = {r}"))
+                .collect::<Vec<_>>();
+            write!(f, " On {}", keys.join(" AND "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database, PlanTree};
+
+    fn int_rows(db: &mut Database, sql: &str) -> Vec<Option<i64>> {
+        let mut rows = db
+            .run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| match tuple.data[0] {
+                ScalarValue::Int32(v) => v.map(|v| v as i64),
+                ScalarValue::Int64(v) => v,
+                ref v => panic!("unexpected value {v}"),
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    fn find_operator(tree: &PlanTree, operator: &str) -> Option<PlanTree> {
+        if tree.operator == operator {
+            return Some(tree.clone());
+        }
+        tree.children
+            .iter()
+            .find_map(|child| find_operator(child, operator))
+    }
+
+    // rows of `outer` whose key passes `[NOT] IN` of `inner`, evaluated by the SQL rules
+    // one row at a time
+    fn naive_in(outer: &[Option<i64>], inner: &[Option<i64>], negated: bool) -> Vec<Option<i64>> {
+        outer
+            .iter()
+            .filter(|a| {
+                // true, false or null
+                let result = match a {
+                    _ if inner.is_empty() => Some(false),
+                    None => None,
+                    Some(a) if inner.contains(&Some(*a)) => Some(true),
+                    Some(_) if inner.contains(&None) => None,
+                    Some(_) => Some(false),
+                };
+                result.map(|v| v != negated).unwrap_or(false)
+            })
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    pub fn test_semi_join_parity_with_naive_evaluation() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create table t2 (x bigint)").unwrap();
+        db.run("create table empty (x int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20), (2, 21), (3, 30), (null, 40), (4, 50)")
+            .unwrap();
+        db.run("insert into t2 values (2), (2), (3), (5)").unwrap();
+
+        for with_null in [false, true] {
+            if with_null {
+                db.run("insert into t2 values (null)").unwrap();
+            }
+            let outer = int_rows(&mut db, "select a from t1");
+            for (inner_table, inner_sql) in
+                [("t2", "select x from t2"), ("empty", "select x from empty")]
+            {
+                let inner = int_rows(&mut db, inner_sql);
+                for negated in [false, true] {
+                    let not = if negated { "not " } else { "" };
+                    let sql =
+                        format!("select a from t1 where a {not}in (select x from {inner_table})");
+                    assert_eq!(
+                        int_rows(&mut db, &sql),
+                        naive_in(&outer, &inner, negated),
+                        "{sql}"
+                    );
+                    let sql =
+                        format!("select a from t1 where {not}exists (select x from {inner_table})");
+                    let expected = if inner.is_empty() == negated {
+                        outer.clone()
+                    } else {
+                        vec![]
+                    };
+                    assert_eq!(int_rows(&mut db, &sql), expected, "{sql}");
+                }
+            }
+        }
+
+        // combined with other predicates and another subquery
+        assert_eq!(
+            int_rows(
+                &mut db,
+                "select b from t1 where b > 20 and a in (select x from t2) \
+                 and not exists (select x from empty)"
+            ),
+            vec![Some(21), Some(30)]
+        );
+        assert_eq!(
+            int_rows(
+                &mut db,
+                "select a from t1 where not (a in (select a from t1 where b >= 30 and b < 40))"
+            ),
+            vec![Some(1), Some(2), Some(2), Some(4)]
+        );
+        assert!(db
+            .run("select a from t1 where a in (select x, x from t2)")
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_correlated_semi_join_parity_with_naive_evaluation() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, k int)").unwrap();
+        db.run("create table t2 (x int, k bigint)").unwrap();
+        db.run("insert into t1 values (1, 1), (2, 1), (3, 2), (4, 3), (5, null)")
+            .unwrap();
+        db.run("insert into t2 values (1, 1), (9, 1), (3, 2), (null, 2), (7, null)")
+            .unwrap();
+        let t1 = [
+            (1, Some(1)),
+            (2, Some(1)),
+            (3, Some(2)),
+            (4, Some(3)),
+            (5, None),
+        ];
+        let t2 = [
+            (Some(1), Some(1)),
+            (Some(9), Some(1)),
+            (Some(3), Some(2)),
+            (None, Some(2)),
+            (Some(7), None),
+        ];
+
+        for negated in [false, true] {
+            let not = if negated { "not " } else { "" };
+            let sql = format!(
+                "select a from t1 where {not}exists (select 1 from t2 where t2.k = t1.k and x > 2)"
+            );
+            let expected = t1
+                .iter()
+                .filter(|(_, k)| {
+                    let exists = t2
+                        .iter()
+                        .any(|(x, t2_k)| k.is_some() && t2_k == k && x.is_some_and(|x| x > 2));
+                    exists != negated
+                })
+                .map(|(a, _)| Some(*a as i64))
+                .collect::<Vec<_>>();
+            assert_eq!(int_rows(&mut db, &sql), expected, "{sql}");
+        }
+        let sql = "select a from t1 where a in (select x from t2 where k = t1.k)";
+        let expected = t1
+            .iter()
+            .filter(|(a, k)| {
+                t2.iter()
+                    .any(|(x, t2_k)| k.is_some() && t2_k == k && *x == Some(*a))
+            })
+            .map(|(a, _)| Some(*a as i64))
+            .collect::<Vec<_>>();
+        assert_eq!(int_rows(&mut db, sql), expected, "{sql}");
+
+        // with a subquery of its own
+        db.run("create table t3 (y int)").unwrap();
+        db.run("insert into t3 values (3), (9)").unwrap();
+        assert_eq!(
+            int_rows(
+                &mut db,
+                "select a from t1 where exists (select 1 from t2 where t2.k = t1.k \
+                 and x in (select y from t3))"
+            ),
+            vec![Some(1), Some(2), Some(3)]
+        );
+
+        let tree = db
+            .explain("select a from t1 where not exists (select 1 from t2 where t1.k = t2.k)")
+            .unwrap();
+        let join = find_operator(&tree, "HashSemiJoin").unwrap();
+        assert_eq!(join.description, "HashSemiJoin: LeftAnti On t1.k = t2.k");
+
+        for sql in [
+            "select a from t1 where exists (select 1 from t2 where t2.k > t1.k)",
+            "select a from t1 where exists (select 1 from t2 where t2.k = t1.k or x = 1)",
+            "select a from t1 where exists (select max(x) from t2 where t2.k = t1.k)",
+            "select a from t1 where exists (select 1 from t2 where t2.k = t1.k limit 1)",
+            "select a from t1 where a not in (select x from t2 where t2.k = t1.k)",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(BustubxError::NotSupport(_))),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_semi_join_plan_shape() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create table t2 (x int)").unwrap();
+
+        for (sql, join_type) in [
+            ("select a from t1 where a in (select x from t2)", "LeftSemi"),
+            (
+                "select a from t1 where exists (select x from t2)",
+                "LeftSemi",
+            ),
+            (
+                "select a from t1 where not exists (select x from t2)",
+                "LeftAnti",
+            ),
+            (
+                "select a from t1 where a not in (select x from t2)",
+                "NullAwareLeftAnti",
+            ),
+        ] {
+            let tree = db.explain(sql).unwrap();
+            let join = find_operator(&tree, "HashSemiJoin").expect(sql);
+            assert!(
+                join.description
+                    .starts_with(&format!("HashSemiJoin: {join_type}")),
+                "{sql}: {}",
+                join.description
+            );
+            assert_eq!(join.children.len(), 2);
+            assert!(find_operator(&tree, "NestedLoopJoin").is_none(), "{sql}");
+        }
+        let tree = db.explain("select a from t1 where a > 1").unwrap();
+        assert!(find_operator(&tree, "HashSemiJoin").is_none());
+    }
+}
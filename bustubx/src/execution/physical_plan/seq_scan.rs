@@ -1,9 +1,10 @@
+use std::sync::atomic::Ordering;
 use std::sync::Mutex;
 
 use crate::catalog::SchemaRef;
 use crate::common::TableReference;
 use crate::{
-    execution::{ExecutionContext, VolcanoExecutor},
+    execution::{ExecutionContext, TupleBatch, VolcanoExecutor},
     storage::{TableIterator, Tuple},
     BustubxError, BustubxResult,
 };
@@ -14,6 +15,8 @@ pub struct PhysicalSeqScan {
     pub table_schema: SchemaRef,
 
     iterator: Mutex<Option<TableIterator>>,
+    // rows whose ttl is before this time are skipped, set when expired rows are hidden
+    expire_before: Mutex<Option<i64>>,
 }
 
 impl PhysicalSeqScan {
@@ -22,24 +25,83 @@ impl PhysicalSeqScan {
             table,
             table_schema,
             iterator: Mutex::new(None),
+            expire_before: Mutex::new(None),
         }
     }
-}
 
-impl VolcanoExecutor for PhysicalSeqScan {
-    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
-        let table_heap = context.catalog.table_heap(&self.table)?;
-        *self.iterator.lock().unwrap() = Some(TableIterator::new(table_heap, ..));
-        Ok(())
+    /// Up to `batch_size` visible rows, read under a single lock of the iterator.
+    pub fn next_batch(&self, context: &mut ExecutionContext) -> BustubxResult<Option<TupleBatch>> {
+        let mut tuples = Vec::with_capacity(context.options.batch_size);
+        self.scan(context, context.options.batch_size, &mut tuples)?;
+        Ok((!tuples.is_empty()).then(|| TupleBatch::new(tuples)))
     }
 
-    fn next(&self, _context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+    fn scan(
+        &self,
+        context: &ExecutionContext,
+        limit: usize,
+        tuples: &mut Vec<Tuple>,
+    ) -> BustubxResult<()> {
         let Some(iterator) = &mut *self.iterator.lock().unwrap() else {
             return Err(BustubxError::Execution(
                 "table iterator not created".to_string(),
             ));
         };
-        Ok(iterator.next()?.map(|full| full.1))
+        let catalog_table = context.catalog.catalog_table(&self.table)?;
+        let expire_before = *self.expire_before.lock().unwrap();
+        let read_before = tuples.len();
+        while tuples.len() < limit {
+            let Some((rid, tuple)) = iterator.next()? else {
+                break;
+            };
+            if !context.is_visible(rid) {
+                continue;
+            }
+            let meta = catalog_table.table.tuple_meta(rid)?;
+            let tuple = match &context.snapshot {
+                Some(snapshot) => match snapshot.version(&self.table, rid, &meta, tuple) {
+                    Some(tuple) => tuple,
+                    None => continue,
+                },
+                None if meta.is_deleted => continue,
+                None => tuple,
+            };
+            if let Some(now) = expire_before {
+                if catalog_table.is_expired(&tuple, now)? {
+                    continue;
+                }
+            }
+            tuples.push(tuple);
+        }
+        catalog_table
+            .access_stats
+            .seq_tuples_read
+            .fetch_add((tuples.len() - read_before) as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl VolcanoExecutor for PhysicalSeqScan {
+    fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
+        if let Some(snapshot) = &context.snapshot {
+            snapshot.check_table(&self.table)?;
+        }
+        let catalog_table = context.catalog.catalog_table(&self.table)?;
+        *self.expire_before.lock().unwrap() = (context.options.hide_expired_rows
+            && catalog_table.ttl_column.is_some())
+        .then(|| context.clock.now());
+        *self.iterator.lock().unwrap() = Some(TableIterator::new(catalog_table.table.clone(), ..));
+        catalog_table
+            .access_stats
+            .seq_scans
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
+        let mut tuples = Vec::with_capacity(1);
+        self.scan(context, 1, &mut tuples)?;
+        Ok(tuples.pop())
     }
 
     fn output_schema(&self) -> SchemaRef {
@@ -1,10 +1,17 @@
 use std::cmp::Ordering as CmpOrdering;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::sync::{Arc, LazyLock, Mutex};
 
-use crate::catalog::SchemaRef;
+use log::{debug, warn};
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Column, DataType, Schema, SchemaRef};
+use crate::common::value_ord::Collation;
+use crate::common::{value_ord, ScalarValue};
 use crate::expression::ExprTrait;
 use crate::planner::logical_plan::OrderByExpr;
+use crate::storage::codec::{ByteReader, CommonCodec, TupleCodec};
+use crate::storage::{Compressor, TableHeap, TableIterator, EMPTY_TUPLE_META};
 use crate::{
     execution::{ExecutionContext, VolcanoExecutor},
     storage::Tuple,
@@ -13,84 +20,385 @@ use crate::{
 
 use super::PhysicalPlan;
 
+// Column holding the input position of a row written to a sorted run
+const SEQ_COLUMN: &str = "__sort_seq";
+// encoded rows compressed together in a compressed sorted run
+const SORT_RUN_BLOCK_BYTES: usize = 32 * 1024;
+// bytes of a compressed block stored per heap row, well below what a page holds
+const SORT_RUN_PIECE_BYTES: usize = 1024;
+
+// heap rows of a compressed sorted run, a piece of a block and whether it ends the block
+static BLOCK_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Column::new("__sort_block", DataType::Bytea, false),
+        Column::new("__sort_block_end", DataType::Boolean, false),
+    ]))
+});
+
+/// Stable sort, rows equal on the sort keys keep their input order.
+///
+/// Rows are sorted in memory until their estimated size reaches the sort memory budget,
+/// beyond that every full buffer is written to a temp heap as a sorted run and the runs
+/// are merged. Runs carry the input position of their rows, so the merge keeps equal
+/// rows in input order too.
 #[derive(Debug)]
 pub struct PhysicalSort {
     pub order_bys: Vec<OrderByExpr>,
     pub input: Arc<PhysicalPlan>,
 
-    all_tuples: Mutex<Vec<Tuple>>,
-    cursor: AtomicUsize,
+    sorted_rows: Mutex<Option<SortedRows>>,
 }
+
+#[derive(Debug)]
+enum SortedRows {
+    InMemory(VecDeque<Tuple>),
+    Runs(Vec<SortRun>),
+}
+
 impl PhysicalSort {
     pub fn new(order_bys: Vec<OrderByExpr>, input: Arc<PhysicalPlan>) -> Self {
         PhysicalSort {
             order_bys,
             input,
-            all_tuples: Mutex::new(Vec::new()),
-            cursor: AtomicUsize::new(0),
+            sorted_rows: Mutex::new(None),
         }
     }
 
-    fn sort_tuples(&self, a: &Tuple, b: &Tuple) -> BustubxResult<CmpOrdering> {
-        let mut ordering = CmpOrdering::Equal;
-        let mut index = 0;
-        while ordering == CmpOrdering::Equal && index < self.order_bys.len() {
-            let a_value = self.order_bys[index].expr.evaluate(a)?;
-            let b_value = self.order_bys[index].expr.evaluate(b)?;
-            ordering = if self.order_bys[index].asc {
-                a_value.partial_cmp(&b_value)
+    fn load(&self, context: &mut ExecutionContext) -> BustubxResult<SortedRows> {
+        let budget = context.options.sort_memory_budget;
+        let deterministic = context.options.deterministic_order;
+        let buffer_pool = context.catalog.buffer_pool.clone();
+
+        let mut rows: Vec<(u64, Tuple)> = vec![];
+        let mut memory_used = 0;
+        let mut runs = vec![];
+        let mut seq = 0;
+        while let Some(tuple) = self.input.next(context)? {
+            memory_used += tuple_size_estimate(&tuple);
+            rows.push((seq, tuple));
+            seq += 1;
+            if memory_used > budget {
+                sort_in_memory(
+                    &self.order_bys,
+                    &mut rows,
+                    |(_, tuple)| tuple,
+                    deterministic,
+                )?;
+                runs.push(SortRun::write(
+                    std::mem::take(&mut rows),
+                    self.input.output_schema(),
+                    &buffer_pool,
+                )?);
+                memory_used = 0;
+            }
+        }
+
+        if runs.is_empty() {
+            let mut rows = rows.into_iter().map(|(_, tuple)| tuple).collect::<Vec<_>>();
+            sort_in_memory(&self.order_bys, &mut rows, |tuple| tuple, deterministic)?;
+            return Ok(SortedRows::InMemory(rows.into()));
+        }
+        if !rows.is_empty() {
+            sort_in_memory(
+                &self.order_bys,
+                &mut rows,
+                |(_, tuple)| tuple,
+                deterministic,
+            )?;
+            runs.push(SortRun::write(
+                rows,
+                self.input.output_schema(),
+                &buffer_pool,
+            )?);
+        }
+        debug!("sort spilled {} runs", runs.len());
+        Ok(SortedRows::Runs(runs))
+    }
+
+    /// Smallest head of the runs, equal rows come from the earliest input position.
+    fn next_merged(
+        &self,
+        runs: &mut [SortRun],
+        deterministic: bool,
+    ) -> BustubxResult<Option<Tuple>> {
+        let mut min_run: Option<usize> = None;
+        for idx in 0..runs.len() {
+            let Some((seq, tuple)) = &runs[idx].head else {
+                continue;
+            };
+            let is_smaller = match min_run {
+                None => true,
+                Some(min_idx) => {
+                    let (min_seq, min_tuple) = runs[min_idx].head.as_ref().unwrap();
+                    compare_tuples(&self.order_bys, tuple, min_tuple, deterministic)?
+                        .then(seq.cmp(min_seq))
+                        == CmpOrdering::Less
+                }
+            };
+            if is_smaller {
+                min_run = Some(idx);
+            }
+        }
+        let Some(min_idx) = min_run else {
+            return Ok(None);
+        };
+        let (_, tuple) = runs[min_idx].head.take().unwrap();
+        runs[min_idx].advance()?;
+        Ok(Some(tuple))
+    }
+}
+
+/// Rows sorted by a [`PhysicalSort`] that went over its memory budget, or by a worker of
+/// a parallel index build, written to a temp heap which is destroyed when the run is
+/// dropped.
+///
+/// With compression enabled the rows are encoded into blocks of about
+/// [`SORT_RUN_BLOCK_BYTES`], each compressed and stored in pieces that fit a heap row.
+#[derive(Debug)]
+pub(crate) struct SortRun {
+    heap: Arc<TableHeap>,
+    schema: SchemaRef,
+    // rows of the input with their input position appended
+    row_schema: SchemaRef,
+    compressed: bool,
+    iterator: TableIterator,
+    // rows of the current block of a compressed run not handed out yet
+    decoded: VecDeque<Tuple>,
+    // next row of the run with its input position
+    pub(crate) head: Option<(u64, Tuple)>,
+}
+
+impl SortRun {
+    pub(crate) fn write(
+        rows: Vec<(u64, Tuple)>,
+        schema: SchemaRef,
+        buffer_pool: &Arc<BufferPoolManager>,
+    ) -> BustubxResult<Self> {
+        let row_schema = Arc::new(Schema::try_merge([
+            schema.as_ref().clone(),
+            Schema::new(vec![Column::new(SEQ_COLUMN, DataType::UInt64, false)]),
+        ])?);
+        let compressor = buffer_pool.disk_manager.compressor().clone();
+        let heap_schema = if compressor.is_enabled() {
+            BLOCK_SCHEMA.clone()
+        } else {
+            row_schema.clone()
+        };
+        let heap = Arc::new(TableHeap::try_new_spill(heap_schema, buffer_pool.clone())?);
+        let mut run = Self {
+            heap: heap.clone(),
+            schema,
+            row_schema: row_schema.clone(),
+            compressed: compressor.is_enabled(),
+            iterator: TableIterator::new(heap, ..),
+            decoded: VecDeque::new(),
+            head: None,
+        };
+        let mut block = vec![];
+        for (seq, tuple) in rows {
+            let mut data = tuple.data;
+            data.push(ScalarValue::UInt64(Some(seq)));
+            let tuple = Tuple::new(row_schema.clone(), data);
+            if !run.compressed {
+                run.heap.insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+                continue;
+            }
+            let bytes = TupleCodec::encode(&tuple);
+            block.extend(CommonCodec::encode_u32(bytes.len() as u32));
+            block.extend(bytes);
+            if block.len() >= SORT_RUN_BLOCK_BYTES {
+                run.write_block(&compressor.compress(&std::mem::take(&mut block)))?;
+            }
+        }
+        if !block.is_empty() {
+            run.write_block(&compressor.compress(&block))?;
+        }
+        run.advance()?;
+        Ok(run)
+    }
+
+    fn write_block(&self, block: &[u8]) -> BustubxResult<()> {
+        let pieces = block.chunks(SORT_RUN_PIECE_BYTES).count();
+        for (i, piece) in block.chunks(SORT_RUN_PIECE_BYTES).enumerate() {
+            let tuple = Tuple::new(
+                BLOCK_SCHEMA.clone(),
+                vec![piece.to_vec().into(), (i + 1 == pieces).into()],
+            );
+            self.heap.insert_tuple(&EMPTY_TUPLE_META, &tuple)?;
+        }
+        Ok(())
+    }
+
+    // decode the next block of a compressed run, false at the end of the run
+    fn read_block(&mut self) -> BustubxResult<bool> {
+        let mut block = vec![];
+        loop {
+            let Some((_, tuple)) = self.iterator.next()? else {
+                if block.is_empty() {
+                    return Ok(false);
+                }
+                return Err(BustubxError::Internal(
+                    "sorted run ends in the middle of a block".to_string(),
+                ));
+            };
+            let (ScalarValue::Bytea(Some(piece)), ScalarValue::Boolean(Some(last))) =
+                (&tuple.data[0], &tuple.data[1])
+            else {
+                return Err(BustubxError::Internal(format!(
+                    "unexpected sorted run block piece {:?}",
+                    tuple.data
+                )));
+            };
+            block.extend(piece);
+            if *last {
+                break;
+            }
+        }
+        let bytes = Compressor::decompress(&block)?;
+        let mut reader = ByteReader::new(&bytes);
+        while reader.offset() < bytes.len() {
+            let len = reader.read(CommonCodec::decode_u32)? as usize;
+            let (tuple, _) = TupleCodec::decode(reader.take(len)?, self.row_schema.clone())?;
+            self.decoded.push_back(tuple);
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn advance(&mut self) -> BustubxResult<()> {
+        let tuple = if self.compressed {
+            if self.decoded.is_empty() && !self.read_block()? {
+                None
             } else {
-                b_value.partial_cmp(&a_value)
+                self.decoded.pop_front()
             }
-            .ok_or(BustubxError::Execution(format!(
-                "Can not compare {:?} and {:?}",
-                a_value, b_value
-            )))?;
-            index += 1;
+        } else {
+            self.iterator.next()?.map(|(_, tuple)| tuple)
+        };
+        let Some(tuple) = tuple else {
+            self.head = None;
+            return Ok(());
+        };
+        let mut data = tuple.data;
+        let Some(ScalarValue::UInt64(Some(seq))) = data.pop() else {
+            return Err(BustubxError::Internal(
+                "sorted run row without input position".to_string(),
+            ));
+        };
+        self.head = Some((seq, Tuple::new(self.schema.clone(), data)));
+        Ok(())
+    }
+}
+
+impl Drop for SortRun {
+    fn drop(&mut self) {
+        if let Err(e) = self.heap.destroy() {
+            warn!("failed to destroy sorted run: {}", e);
         }
-        Ok(ordering)
     }
 }
 
+/// Compare two rows on the sort keys, with `deterministic` rows equal on the keys are
+/// ordered by their values.
+pub(crate) fn compare_tuples(
+    order_bys: &[OrderByExpr],
+    a: &Tuple,
+    b: &Tuple,
+    deterministic: bool,
+) -> BustubxResult<CmpOrdering> {
+    let mut ordering = CmpOrdering::Equal;
+    let mut index = 0;
+    while ordering == CmpOrdering::Equal && index < order_bys.len() {
+        let order_by = &order_bys[index];
+        let a_value = order_by.expr.evaluate(a)?;
+        let b_value = order_by.expr.evaluate(b)?;
+        let collation = match a_value {
+            ScalarValue::Varchar(Some(_)) => {
+                order_by.expr.collation(&a.schema)?.unwrap_or_default()
+            }
+            _ => Collation::Binary,
+        };
+        ordering = value_ord::compare_sort_key(
+            &a_value,
+            &b_value,
+            order_by.asc,
+            order_by.nulls_first,
+            collation,
+        )
+        .ok_or(BustubxError::Execution(format!(
+            "Can not compare {:?} and {:?}",
+            a_value, b_value
+        )))?;
+        index += 1;
+    }
+    if ordering == CmpOrdering::Equal && deterministic {
+        // rows the plan does not tell apart by a row id are ordered by their values
+        ordering = value_ord::compare_rows(&a.data, &b.data, &a.schema.columns)
+            .unwrap_or(CmpOrdering::Equal);
+    }
+    Ok(ordering)
+}
+
+/// Stable in memory sort of rows holding a tuple.
+pub(crate) fn sort_in_memory<T>(
+    order_bys: &[OrderByExpr],
+    rows: &mut [T],
+    tuple: impl Fn(&T) -> &Tuple,
+    deterministic: bool,
+) -> BustubxResult<()> {
+    let mut error = None;
+    // slice::sort_by is stable
+    rows.sort_by(|a, b| {
+        let ordering = compare_tuples(order_bys, tuple(a), tuple(b), deterministic);
+        if let Ok(ordering) = ordering {
+            ordering
+        } else {
+            error = Some(ordering.unwrap_err());
+            CmpOrdering::Equal
+        }
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+    Ok(())
+}
+
+pub(crate) fn tuple_size_estimate(tuple: &Tuple) -> usize {
+    tuple
+        .data
+        .iter()
+        .map(|value| {
+            std::mem::size_of::<ScalarValue>()
+                + match value {
+                    ScalarValue::Varchar(Some(v)) => v.len(),
+                    ScalarValue::Bytea(Some(v)) => v.len(),
+                    _ => 0,
+                }
+        })
+        .sum()
+}
+
 impl VolcanoExecutor for PhysicalSort {
     fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
         self.input.init(context)?;
-        *self.all_tuples.lock().unwrap() = vec![];
-        self.cursor.store(0, Ordering::SeqCst);
+        *self.sorted_rows.lock().unwrap() = None;
         Ok(())
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
-        let all_tuples_len = self.all_tuples.lock().unwrap().len();
-        if all_tuples_len == 0 {
-            // load all tuples from input
-            let mut all_tuples = Vec::new();
-            while let Some(tuple) = self.input.next(context)? {
-                all_tuples.push(tuple);
-            }
-
-            // sort all tuples
-            let mut error = None;
-            all_tuples.sort_by(|a, b| {
-                let ordering = self.sort_tuples(a, b);
-                if let Ok(ordering) = ordering {
-                    ordering
-                } else {
-                    error = Some(ordering.unwrap_err());
-                    CmpOrdering::Equal
+        let mut sorted_rows = self.sorted_rows.lock().unwrap();
+        if sorted_rows.is_none() {
+            *sorted_rows = Some(self.load(context)?);
+        }
+        match sorted_rows.as_mut().unwrap() {
+            SortedRows::InMemory(rows) => Ok(rows.pop_front()),
+            SortedRows::Runs(runs) => {
+                let tuple = self.next_merged(runs, context.options.deterministic_order)?;
+                if tuple.is_none() {
+                    // give the temp pages back as soon as the merge is done
+                    runs.clear();
                 }
-            });
-            if let Some(error) = error {
-                return Err(error);
+                Ok(tuple)
             }
-            *self.all_tuples.lock().unwrap() = all_tuples;
-        }
-
-        let cursor = self.cursor.fetch_add(1, Ordering::SeqCst);
-        if cursor >= self.all_tuples.lock().unwrap().len() {
-            Ok(None)
-        } else {
-            Ok(self.all_tuples.lock().unwrap().get(cursor).cloned())
         }
     }
 
@@ -112,3 +420,170 @@ impl std::fmt::Display for PhysicalSort {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::buffer::INVALID_PAGE_ID;
+    use crate::common::page_leaks::assert_no_page_leaks;
+    use crate::common::util::pretty_format_tuples;
+    use crate::common::TableReference;
+    use crate::config::MIN_BUFFER_POOL_SIZE;
+    use crate::{BustubxError, CompressionCodec, Database, DatabaseOptions};
+
+    fn fixture(options: DatabaseOptions, ids: impl Iterator<Item = i32>) -> Database {
+        let mut db = Database::new_temp_with_options(options).unwrap();
+        db.run("create table t1 (id int, k int, v varchar(20))")
+            .unwrap();
+        let values = ids
+            .map(|id| format!("({id}, {}, 'row {id}')", id % 7))
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {values}")).unwrap();
+        db
+    }
+
+    #[test]
+    pub fn test_sort_stable_when_spilled() {
+        let mut in_memory = fixture(DatabaseOptions::new(), 0..500);
+        let mut spilled = fixture(DatabaseOptions::new().sort_memory_budget(1024), 0..500);
+
+        let sql = "select k, id from t1 order by k desc";
+        let expected = in_memory.run(sql).unwrap();
+        assert_eq!(expected.len(), 500);
+        // rows equal on k keep the scan order
+        for pair in expected.windows(2) {
+            if pair[0].data[0] == pair[1].data[0] {
+                assert!(pair[0].data[1] < pair[1].data[1], "{pair:?}");
+            }
+        }
+        // the temp pages of the runs are given back, also when a limit stops the merge
+        let rows = assert_no_page_leaks(&mut spilled, |db| db.run(sql).unwrap());
+        assert_eq!(rows, expected);
+
+        let sql = "select id, k from t1 order by k limit 20";
+        let rows = assert_no_page_leaks(&mut spilled, |db| db.run(sql).unwrap());
+        assert_eq!(rows, in_memory.run(sql).unwrap());
+
+        // byte identical across runs of the same query
+        let sql = "select k, v from t1 order by k";
+        let first = pretty_format_tuples(&spilled.run(sql).unwrap()).to_string();
+        let second = pretty_format_tuples(&spilled.run(sql).unwrap()).to_string();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    pub fn test_sort_deterministic_order() {
+        let sql = "select k, v from t1 order by k";
+        let mut forward = fixture(DatabaseOptions::new(), 0..100);
+        let mut backward = fixture(DatabaseOptions::new(), (0..100).rev());
+        // ties follow the input order
+        assert_ne!(forward.run(sql).unwrap(), backward.run(sql).unwrap());
+
+        let options = DatabaseOptions::new().sort_memory_budget(512);
+        let mut spilled = fixture(options, (0..100).rev());
+        for db in [&mut forward, &mut backward, &mut spilled] {
+            db.set_deterministic_order(true);
+        }
+        let expected = forward.run(sql).unwrap();
+        assert_eq!(backward.run(sql).unwrap(), expected);
+        assert_eq!(spilled.run(sql).unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_compressed_sort_runs() {
+        let options = DatabaseOptions::new().sort_memory_budget(64 * 1024);
+        let mut plain = Database::new_temp_with_options(options.clone()).unwrap();
+        let mut compressed =
+            Database::new_temp_with_options(options.compression(CompressionCodec::Lz)).unwrap();
+        for db in [&mut plain, &mut compressed] {
+            db.run("create table t1 (id int, k int, v varchar(100))")
+                .unwrap();
+            for chunk in (0..2000).rev().collect::<Vec<_>>().chunks(500) {
+                let values = chunk
+                    .iter()
+                    .map(|id| format!("({id}, {}, '{}')", id % 7, "padding ".repeat(10)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                db.run(&format!("insert into t1 values {values}")).unwrap();
+            }
+            db.reset_runtime_stats();
+        }
+
+        let sql = "select k, id, v from t1 order by k";
+        let expected = plain.run(sql).unwrap();
+        assert_eq!(expected.len(), 2000);
+        let rows = assert_no_page_leaks(&mut compressed, |db| db.run(sql).unwrap());
+        assert_eq!(rows, expected);
+
+        let plain_stats = plain.runtime_stats();
+        let stats = compressed.runtime_stats();
+        assert_eq!(plain_stats.uncompressed_bytes, 0);
+        assert!(
+            stats.compressed_bytes * 4 < stats.uncompressed_bytes,
+            "{stats:?}"
+        );
+        // the runs take fewer temp pages
+        assert!(
+            stats.pages_allocated * 2 < plain_stats.pages_allocated,
+            "{stats:?} {plain_stats:?}"
+        );
+    }
+
+    #[test]
+    pub fn test_spilled_sort_waits_for_pinned_frames() {
+        let options = DatabaseOptions::new()
+            .buffer_pool_size(MIN_BUFFER_POOL_SIZE)
+            .sort_memory_budget(1024)
+            .frame_wait_timeout(Duration::from_secs(10));
+        let mut db = fixture(options, 0..200);
+        let expected = fixture(DatabaseOptions::new(), 0..200)
+            .run("select k, id from t1 order by k")
+            .unwrap();
+
+        // the pages of the runs and those of the holder are given back
+        let rows = assert_no_page_leaks(&mut db, |db| {
+            let buffer_pool = db.buffer_pool.clone();
+            let table_heap = db.catalog.table_heap(&TableReference::bare("t1")).unwrap();
+            let (pinned_tx, pinned_rx) = mpsc::channel();
+            let holder = thread::spawn(move || {
+                // the pages of t1 stay readable, every other frame is taken
+                let mut pages = vec![];
+                let mut page_id = table_heap.first_page_id.load(Ordering::SeqCst);
+                while page_id != INVALID_PAGE_ID {
+                    let (page, table_page) = buffer_pool
+                        .fetch_table_page(page_id, table_heap.schema.clone())
+                        .unwrap();
+                    pages.push(page);
+                    page_id = table_page.header.next_page_id;
+                }
+                let mut new_page_ids = vec![];
+                while let Ok(page) = buffer_pool.new_page() {
+                    new_page_ids.push(page.read().unwrap().page_id);
+                    pages.push(page);
+                }
+                pinned_tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+                drop(pages);
+                for page_id in new_page_ids {
+                    buffer_pool.delete_page(page_id).unwrap();
+                }
+            });
+            pinned_rx.recv().unwrap();
+
+            assert!(matches!(
+                db.buffer_pool.new_page(),
+                Err(BustubxError::BufferPoolFull(_))
+            ));
+            // the spilled runs wait for the frames instead of failing the statement
+            let rows = db.run("select k, id from t1 order by k").unwrap();
+            holder.join().unwrap();
+            rows
+        });
+        assert_eq!(rows, expected);
+    }
+}
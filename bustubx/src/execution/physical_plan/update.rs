@@ -1,22 +1,25 @@
 use crate::catalog::{SchemaRef, UPDATE_OUTPUT_SCHEMA_REF};
 use crate::common::{ScalarValue, TableReference};
-use crate::execution::{ExecutionContext, VolcanoExecutor};
+use crate::execution::{ExecutionContext, TriggerEvent, UndoRecord, VolcanoExecutor};
 use crate::expression::{Expr, ExprTrait};
-use crate::storage::{TableIterator, EMPTY_TUPLE};
 use crate::{BustubxError, BustubxResult, Tuple};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Mutex;
 
+use super::dml_order::{lock_latest_row, DmlOrder, DmlRows};
+use super::{index_keys, update_index_entries};
+
 #[derive(Debug)]
 pub struct PhysicalUpdate {
     pub table: TableReference,
     pub table_schema: SchemaRef,
     pub assignments: HashMap<String, Expr>,
     pub selection: Option<Expr>,
+    pub order: DmlOrder,
 
     update_rows: AtomicU32,
-    table_iterator: Mutex<Option<TableIterator>>,
+    rows: Mutex<Option<DmlRows>>,
 }
 
 impl PhysicalUpdate {
@@ -31,45 +34,112 @@ impl PhysicalUpdate {
             table_schema,
             assignments,
             selection,
+            order: DmlOrder::default(),
             update_rows: AtomicU32::new(0),
-            table_iterator: Mutex::new(None),
+            rows: Mutex::new(None),
         }
     }
+
+    pub fn with_order(mut self, order: DmlOrder) -> Self {
+        self.order = order;
+        self
+    }
 }
 
 impl VolcanoExecutor for PhysicalUpdate {
     fn init(&self, context: &mut ExecutionContext) -> BustubxResult<()> {
         self.update_rows.store(0, Ordering::SeqCst);
-        let table_heap = context.catalog.table_heap(&self.table)?;
-        *self.table_iterator.lock().unwrap() = Some(TableIterator::new(table_heap.clone(), ..));
+        let rows = DmlRows::new(&self.order, &self.table, self.selection.as_ref(), context)?;
+        *self.rows.lock().unwrap() = Some(rows);
         Ok(())
     }
 
     fn next(&self, context: &mut ExecutionContext) -> BustubxResult<Option<Tuple>> {
-        // TODO may scan index
-        let Some(table_iterator) = &mut *self.table_iterator.lock().unwrap() else {
+        let Some(rows) = &mut *self.rows.lock().unwrap() else {
             return Err(BustubxError::Execution(
                 "table iterator not created".to_string(),
             ));
         };
         let table_heap = context.catalog.table_heap(&self.table)?;
+        let catalog_table = context.catalog.catalog_table(&self.table)?;
+        let access_stats = catalog_table.access_stats.clone();
+        let modifications = catalog_table.modifications.clone();
+        let triggers = catalog_table.triggers.clone();
+        let indexes = index_keys(catalog_table)?;
 
         loop {
-            if let Some((rid, mut tuple)) = table_iterator.next()? {
+            if let Some((rid, tuple)) = rows.next()? {
+                if table_heap.tuple_meta(rid)?.is_deleted {
+                    continue;
+                }
                 if let Some(selection) = &self.selection {
                     if !selection.evaluate(&tuple)?.as_boolean()?.unwrap_or(false) {
                         continue;
                     }
                 }
-                // update tuple data
+                let Some(mut tuple) = lock_latest_row(
+                    context,
+                    &self.table,
+                    &table_heap,
+                    rid,
+                    tuple,
+                    self.selection.as_ref(),
+                )?
+                else {
+                    continue;
+                };
+                // update tuple data, assignments see the row before the update
+                let old_tuple = tuple.clone();
                 for (col_name, value_expr) in self.assignments.iter() {
-                    let index = tuple.schema.index_of(None, &col_name)?;
+                    let index = tuple.schema.index_of(None, col_name)?;
                     let col_datatype = tuple.schema.columns[index].data_type;
-                    let new_value = value_expr.evaluate(&EMPTY_TUPLE)?.cast_to(&col_datatype)?;
+                    let new_value = value_expr.evaluate(&old_tuple)?.cast_to(&col_datatype)?;
                     tuple.data[index] = new_value;
                 }
+                if !triggers.fire_before(
+                    TriggerEvent::BeforeUpdate,
+                    &self.table,
+                    Some(rid),
+                    Some(&old_tuple),
+                    Some(&mut tuple),
+                )? {
+                    continue;
+                }
+                // a row cannot move to another partition
+                context
+                    .catalog
+                    .catalog_table(&self.table)?
+                    .check_partition_key(&tuple)?;
+                let after = triggers
+                    .has(TriggerEvent::AfterUpdate)
+                    .then(|| (old_tuple.clone(), tuple.clone()));
+                context.undo_log.push(UndoRecord::Update {
+                    table: self.table.clone(),
+                    rid,
+                    old_tuple: old_tuple.clone(),
+                });
+                let entry = catalog_table.index_entry(rid)?;
+                update_index_entries(
+                    &self.table,
+                    &indexes,
+                    entry,
+                    &old_tuple,
+                    &tuple,
+                    &mut context.undo_log,
+                )?;
+                modifications.row_updated(&tuple);
                 table_heap.update_tuple(rid, tuple)?;
                 self.update_rows.fetch_add(1, Ordering::SeqCst);
+                access_stats.tuples_updated.fetch_add(1, Ordering::Relaxed);
+                if let Some((old_tuple, tuple)) = after {
+                    triggers.fire_after(
+                        TriggerEvent::AfterUpdate,
+                        &self.table,
+                        rid,
+                        Some(&old_tuple),
+                        Some(&tuple),
+                    )?;
+                }
             } else {
                 return if self.update_rows.load(Ordering::SeqCst) == 0 {
                     Ok(None)
@@ -91,6 +161,204 @@ impl VolcanoExecutor for PhysicalUpdate {
 
 impl std::fmt::Display for PhysicalUpdate {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Update")
+        write!(f, "Update{}", self.order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::common::test_util::int_rows;
+    use crate::common::ScalarValue::{self, Int32};
+    use crate::common::TableReference;
+    use crate::execution::{TriggerAction, TriggerEvent, TriggerFn};
+    use crate::storage::TableIterator;
+    use crate::{BustubxError, Database, Tuple};
+
+    #[test]
+    pub fn test_update_with_order_and_limit() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table jobs (id int, priority int, state varchar(10))")
+            .unwrap();
+        db.run(
+            "insert into jobs values (1, 5, 'queued'), (2, 9, 'queued'), (3, 1, 'queued'), \
+             (4, 9, 'queued'), (5, 7, 'queued')",
+        )
+        .unwrap();
+
+        let sql = "update jobs set state = 'running' where state = 'queued' \
+                   order by priority desc, id limit 3";
+        let rows = db.run(sql).unwrap();
+        assert_eq!(rows[0].data, vec![ScalarValue::Int32(Some(3))]);
+        let running = |db: &mut Database| {
+            db.run("select id from jobs where state = 'running' order by id")
+                .unwrap()
+                .iter()
+                .map(|tuple| tuple.data[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            running(&mut db),
+            [2, 4, 5].map(|id| ScalarValue::Int32(Some(id))).to_vec()
+        );
+
+        // rows picked up front, the update never sees a row it already changed
+        db.run("update jobs set priority = priority + 10 order by priority limit 4")
+            .unwrap();
+        let priorities = db
+            .run("select priority from jobs order by id")
+            .unwrap()
+            .iter()
+            .map(|tuple| tuple.data[0].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            priorities,
+            [15, 19, 11, 9, 17]
+                .map(|priority| ScalarValue::Int32(Some(priority)))
+                .to_vec()
+        );
+        assert!(db
+            .run("update jobs set state = 'done' limit 0")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    pub fn test_update_moves_index_entries() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t (id int, k int)").unwrap();
+        db.run("create unique index idx_k on t (k)").unwrap();
+        db.run("insert into t values (1, 1), (2, 2), (3, 3)")
+            .unwrap();
+        let plan = db
+            .run("explain select id from t where k = 10")
+            .unwrap()
+            .iter()
+            .map(|tuple| tuple.data[0].to_string())
+            .collect::<Vec<_>>();
+        assert!(
+            plan.iter().any(|line| line.contains("IndexScan")),
+            "{plan:?}"
+        );
+
+        db.run("update t set k = k * 10 where id < 3").unwrap();
+        assert_eq!(
+            int_rows(&mut db, "select id from t where k = 10"),
+            vec![vec![1]]
+        );
+        assert_eq!(
+            int_rows(&mut db, "select id from t where k = 20"),
+            vec![vec![2]]
+        );
+        assert!(int_rows(&mut db, "select id from t where k = 1").is_empty());
+        assert!(db.run("check table t with indexes").unwrap().is_empty());
+
+        // the old key is free again, a failed update puts every entry back
+        db.run("insert into t values (4, 1)").unwrap();
+        assert!(matches!(
+            db.run("update t set k = 3 where id > 3 or id = 1"),
+            Err(BustubxError::Execution(msg)) if msg.contains("idx_k")
+        ));
+        assert_eq!(
+            int_rows(&mut db, "select id from t where k = 1"),
+            vec![vec![4]]
+        );
+        assert_eq!(
+            int_rows(&mut db, "select id from t where k = 10"),
+            vec![vec![1]]
+        );
+        assert!(db.run("check table t with indexes").unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_update_swaps_deferrable_unique_keys() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t (id int, k int)").unwrap();
+        db.run("create unique index idx_k on t (k)").unwrap();
+        db.run("create table d (id int, k int)").unwrap();
+        db.run("create unique index idx_d on d (k) deferrable")
+            .unwrap();
+        db.run("insert into t values (1, 1), (2, 2)").unwrap();
+        db.run("insert into d values (1, 1), (2, 2)").unwrap();
+
+        // checked row by row, the first row moves onto the key of the second
+        assert!(matches!(
+            db.run("update t set k = 3 - k"),
+            Err(BustubxError::Execution(msg)) if msg.contains("idx_k")
+        ));
+        assert_eq!(
+            int_rows(&mut db, "select id, k from t order by id"),
+            vec![vec![1, 1], vec![2, 2]]
+        );
+        assert!(db.run("check table t with indexes").unwrap().is_empty());
+
+        // checked once the statement ended, every key is held once again by then
+        db.run("update d set k = 3 - k").unwrap();
+        assert_eq!(
+            int_rows(&mut db, "select id, k from d order by id"),
+            vec![vec![1, 2], vec![2, 1]]
+        );
+        assert_eq!(
+            int_rows(&mut db, "select id from d where k = 1"),
+            vec![vec![2]]
+        );
+        assert!(db.run("check table d with indexes").unwrap().is_empty());
+
+        assert!(matches!(
+            db.run("update d set k = 5"),
+            Err(BustubxError::Execution(msg)) if msg.contains("idx_d")
+        ));
+        assert!(matches!(
+            db.run("insert into d values (3, 1)"),
+            Err(BustubxError::Execution(msg)) if msg.contains("idx_d")
+        ));
+        assert_eq!(
+            int_rows(&mut db, "select id, k from d order by id"),
+            vec![vec![1, 2], vec![2, 1]]
+        );
+        assert!(db.run("check table d with indexes").unwrap().is_empty());
+        // NULLs never conflict
+        db.run("insert into d values (3, null), (4, null)").unwrap();
+    }
+
+    #[test]
+    pub fn test_update_rechecks_rows_changed_after_read() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t (id int, v int)").unwrap();
+        db.run("insert into t values (1, 0), (2, 10), (3, 20), (4, 30)")
+            .unwrap();
+        let heap = db.catalog.table_heap(&TableReference::bare("t")).unwrap();
+        let mut rids = vec![];
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, _)) = iterator.next().unwrap() {
+            rids.push(rid);
+        }
+        // once row 1 is updated, the rows read with it change as if another transaction
+        // wrote them: row 2 is updated, row 3 deleted and row 4 leaves the selection
+        let trigger: TriggerFn = Arc::new(move |context| {
+            if context.rid == Some(rids[0]) {
+                let schema = context.old.unwrap().schema.clone();
+                let row = |id, v| Tuple::new(schema.clone(), vec![Int32(Some(id)), Int32(Some(v))]);
+                heap.update_tuple(rids[1], row(2, 100))?;
+                let mut meta = heap.tuple_meta(rids[2])?;
+                meta.is_deleted = true;
+                heap.update_tuple_meta(meta, rids[2])?;
+                heap.update_tuple(rids[3], row(4, -1))?;
+            }
+            Ok(TriggerAction::Continue)
+        });
+        db.register_trigger("t", TriggerEvent::BeforeUpdate, trigger)
+            .unwrap();
+
+        // all rows are read before the first one is updated
+        assert_eq!(
+            int_rows(&mut db, "update t set v = v + 1 where v >= 0 order by id"),
+            vec![vec![2]]
+        );
+        assert_eq!(
+            int_rows(&mut db, "select id, v from t order by id"),
+            vec![vec![1, 1], vec![2, 101], vec![4, -1]]
+        );
     }
 }
@@ -0,0 +1,384 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use crate::common::TableReference;
+use crate::storage::RecordId;
+use crate::{BustubxError, BustubxResult, Tuple};
+
+/// Row change a trigger fires on, see [`crate::Database::register_trigger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriggerEvent {
+    BeforeInsert,
+    AfterInsert,
+    BeforeUpdate,
+    AfterUpdate,
+    BeforeDelete,
+    AfterDelete,
+}
+
+impl TriggerEvent {
+    /// Whether the trigger runs before the row is written, so it can change or skip it.
+    pub fn is_before(&self) -> bool {
+        matches!(
+            self,
+            TriggerEvent::BeforeInsert | TriggerEvent::BeforeUpdate | TriggerEvent::BeforeDelete
+        )
+    }
+}
+
+/// What a trigger callback decides about its row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerAction {
+    /// Go on with the row as the trigger saw it.
+    Continue,
+    /// Before insert or update only, write this row instead of the candidate. The values
+    /// are cast to the column types.
+    Replace(Tuple),
+    /// Before triggers only, leave the row alone and go on with the statement. Returning an
+    /// error fails the whole statement instead.
+    Skip,
+}
+
+/// The row a trigger fires for.
+#[derive(Debug)]
+pub struct TriggerContext<'a> {
+    pub event: TriggerEvent,
+    pub table: &'a TableReference,
+    // None before an insert, the row has no place yet
+    pub rid: Option<RecordId>,
+    // the row before an update and the deleted row
+    pub old: Option<&'a Tuple>,
+    // the candidate row of an insert or update, after triggers see the row as written
+    pub new: Option<&'a Tuple>,
+}
+
+pub type TriggerFn = Arc<dyn Fn(&TriggerContext) -> BustubxResult<TriggerAction> + Send + Sync>;
+
+thread_local! {
+    // table whose trigger runs on this thread
+    static FIRING_TABLE: RefCell<Option<TableReference>> = const { RefCell::new(None) };
+}
+
+/// Fails with [`BustubxError::TriggerReentry`] when called from a trigger callback. A
+/// statement issued by a trigger would wait for the statement firing it forever.
+pub(crate) fn check_not_in_trigger() -> BustubxResult<()> {
+    FIRING_TABLE.with(|firing| match &*firing.borrow() {
+        Some(table) => Err(BustubxError::TriggerReentry {
+            table: table.clone(),
+        }),
+        None => Ok(()),
+    })
+}
+
+// marks the thread as running a trigger of `table` until dropped
+struct FiringGuard;
+
+impl FiringGuard {
+    fn enter(table: &TableReference) -> Self {
+        FIRING_TABLE.with(|firing| *firing.borrow_mut() = Some(table.clone()));
+        FiringGuard
+    }
+}
+
+impl Drop for FiringGuard {
+    fn drop(&mut self) {
+        FIRING_TABLE.with(|firing| *firing.borrow_mut() = None);
+    }
+}
+
+/// Trigger callbacks of a table in registration order. They live as long as the open
+/// database and are not stored in the catalog.
+#[derive(Clone, Default)]
+pub struct Triggers {
+    triggers: Vec<(TriggerEvent, TriggerFn)>,
+}
+
+impl std::fmt::Debug for Triggers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.triggers.iter().map(|(event, _)| event))
+            .finish()
+    }
+}
+
+impl Triggers {
+    pub fn add(&mut self, event: TriggerEvent, trigger: TriggerFn) {
+        self.triggers.push((event, trigger));
+    }
+
+    pub fn has(&self, event: TriggerEvent) -> bool {
+        self.triggers.iter().any(|(e, _)| *e == event)
+    }
+
+    /// Run the before triggers of `event`, each one sees `new` as replaced by the ones
+    /// before it. Returns false when one of them skipped the row.
+    pub fn fire_before(
+        &self,
+        event: TriggerEvent,
+        table: &TableReference,
+        rid: Option<RecordId>,
+        old: Option<&Tuple>,
+        mut new: Option<&mut Tuple>,
+    ) -> BustubxResult<bool> {
+        for (_, trigger) in self.triggers.iter().filter(|(e, _)| *e == event) {
+            let context = TriggerContext {
+                event,
+                table,
+                rid,
+                old,
+                new: new.as_deref(),
+            };
+            let action = {
+                let _firing = FiringGuard::enter(table);
+                trigger(&context)?
+            };
+            match action {
+                TriggerAction::Continue => {}
+                TriggerAction::Skip => return Ok(false),
+                TriggerAction::Replace(replacement) => {
+                    let Some(new) = new.as_deref_mut() else {
+                        return Err(BustubxError::Execution(format!(
+                            "{:?} trigger of table {} cannot replace the row",
+                            event, table
+                        )));
+                    };
+                    replace_row(new, replacement)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run the after triggers of `event` on the row as written.
+    pub fn fire_after(
+        &self,
+        event: TriggerEvent,
+        table: &TableReference,
+        rid: RecordId,
+        old: Option<&Tuple>,
+        new: Option<&Tuple>,
+    ) -> BustubxResult<()> {
+        for (_, trigger) in self.triggers.iter().filter(|(e, _)| *e == event) {
+            let context = TriggerContext {
+                event,
+                table,
+                rid: Some(rid),
+                old,
+                new,
+            };
+            let action = {
+                let _firing = FiringGuard::enter(table);
+                trigger(&context)?
+            };
+            if action != TriggerAction::Continue {
+                return Err(BustubxError::Execution(format!(
+                    "{:?} trigger of table {} returned {:?}, the row is written already",
+                    event, table, action
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// the values of `replacement` in the layout of `row`
+fn replace_row(row: &mut Tuple, replacement: Tuple) -> BustubxResult<()> {
+    if replacement.data.len() != row.data.len() {
+        return Err(BustubxError::Execution(format!(
+            "trigger replaced a row of {} columns by one of {}",
+            row.data.len(),
+            replacement.data.len()
+        )));
+    }
+    for (i, value) in replacement.data.into_iter().enumerate() {
+        row.data[i] = value.cast_to(&row.schema.column_with_index(i)?.data_type)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, Weak};
+
+    use crate::common::test_util::rows;
+    use crate::common::ScalarValue;
+    use crate::{
+        BustubxError, Database, SharedDatabase, TriggerAction, TriggerContext, TriggerEvent, Tuple,
+    };
+
+    #[test]
+    pub fn test_before_insert_trigger_normalizes_column() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table users (id int, email varchar(100))")
+            .unwrap();
+        db.run("create index idx_email on users (email)").unwrap();
+        db.register_trigger(
+            "users",
+            TriggerEvent::BeforeInsert,
+            Arc::new(|ctx: &TriggerContext| {
+                let mut row = ctx.new.unwrap().clone();
+                if let ScalarValue::Varchar(Some(email)) = &row.data[1] {
+                    row.data[1] = email.trim().to_lowercase().into();
+                }
+                Ok(TriggerAction::Replace(row))
+            }),
+        )
+        .unwrap();
+
+        db.run("insert into users values (1, ' Alice@Example.COM'), (2, 'bob@example.com')")
+            .unwrap();
+        assert_eq!(
+            rows(
+                &mut db,
+                "select id from users where email = 'alice@example.com'"
+            ),
+            vec![vec![1i32.into()]]
+        );
+        // a replacement of another layout is refused
+        db.register_trigger(
+            "users",
+            TriggerEvent::BeforeInsert,
+            Arc::new(|_: &TriggerContext| {
+                Ok(TriggerAction::Replace(Tuple::new(
+                    Arc::new(crate::catalog::Schema::new(vec![
+                        crate::catalog::Column::new("id", crate::catalog::DataType::Int32, true),
+                    ])),
+                    vec![3i32.into()],
+                )))
+            }),
+        )
+        .unwrap();
+        assert!(matches!(
+            db.run("insert into users values (3, 'c@example.com')"),
+            Err(BustubxError::Execution(_))
+        ));
+        assert_eq!(rows(&mut db, "select id from users").len(), 2);
+        assert!(db
+            .register_trigger(
+                "missing",
+                TriggerEvent::BeforeInsert,
+                Arc::new(|_: &TriggerContext| Ok(TriggerAction::Continue)),
+            )
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_before_trigger_vetoes_rows() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20), (3, 30)")
+            .unwrap();
+        // negative values are skipped, a b of 0 fails the statement
+        db.register_trigger(
+            "t1",
+            TriggerEvent::BeforeInsert,
+            Arc::new(|ctx: &TriggerContext| match &ctx.new.unwrap().data[1] {
+                ScalarValue::Int32(Some(b)) if *b < 0 => Ok(TriggerAction::Skip),
+                ScalarValue::Int32(Some(0)) => {
+                    Err(BustubxError::Execution("b must not be 0".to_string()))
+                }
+                _ => Ok(TriggerAction::Continue),
+            }),
+        )
+        .unwrap();
+        // row 1 is protected
+        db.register_trigger(
+            "t1",
+            TriggerEvent::BeforeDelete,
+            Arc::new(|ctx: &TriggerContext| {
+                if ctx.old.unwrap().data[0] == 1i32.into() {
+                    Ok(TriggerAction::Skip)
+                } else {
+                    Ok(TriggerAction::Continue)
+                }
+            }),
+        )
+        .unwrap();
+
+        let inserted = rows(&mut db, "insert into t1 values (4, 40), (5, 0 - 50)");
+        assert_eq!(inserted, vec![vec![1i32.into()]]);
+        // the rows before the failing one are rolled back with the statement
+        assert!(db.run("insert into t1 values (6, 60), (7, 0)").is_err());
+        let deleted = rows(&mut db, "delete from t1 where a < 3");
+        assert_eq!(deleted, vec![vec![1i32.into()]]);
+        assert_eq!(
+            rows(&mut db, "select a from t1"),
+            [1, 3, 4].map(|a| vec![a.into()]).to_vec()
+        );
+    }
+
+    #[test]
+    pub fn test_after_update_trigger_observes_rows() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20)").unwrap();
+        let seen = Arc::new(Mutex::new(vec![]));
+        let log = seen.clone();
+        db.register_trigger(
+            "t1",
+            TriggerEvent::AfterUpdate,
+            Arc::new(move |ctx: &TriggerContext| {
+                log.lock().unwrap().push((
+                    ctx.rid.unwrap(),
+                    ctx.old.unwrap().data.clone(),
+                    ctx.new.unwrap().data.clone(),
+                ));
+                Ok(TriggerAction::Continue)
+            }),
+        )
+        .unwrap();
+
+        db.run("update t1 set b = b + 1 where a = 2").unwrap();
+        let (rid, old, new) = seen.lock().unwrap().pop().unwrap();
+        assert_eq!(old, vec![2i32.into(), 20i32.into()]);
+        assert_eq!(new, vec![2i32.into(), 21i32.into()]);
+        let table_heap = db
+            .catalog
+            .table_heap(&crate::common::TableReference::bare("t1"))
+            .unwrap();
+        assert_eq!(table_heap.tuple(rid).unwrap().data, new);
+
+        // fired inside a transaction even though its rollback undoes the update
+        let shared = SharedDatabase::new(db);
+        let mut session = shared.session();
+        let result: crate::BustubxResult<()> = session.run_transaction(|txn| {
+            txn.run("update t1 set b = 0 where a = 1")?;
+            Err(BustubxError::Execution("abort".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert_eq!(
+            session.run("select b from t1 where a = 1").unwrap()[0].data,
+            vec![10i32.into()]
+        );
+    }
+
+    #[test]
+    pub fn test_trigger_reentry_fails() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        let shared = SharedDatabase::new(db);
+        let weak: Weak<SharedDatabase> = Arc::downgrade(&shared);
+        shared
+            .lock()
+            .unwrap()
+            .register_trigger(
+                "t1",
+                TriggerEvent::AfterInsert,
+                Arc::new(move |_: &TriggerContext| {
+                    let shared = weak.upgrade().unwrap();
+                    shared.session().run("insert into t1 values (100)")?;
+                    Ok(TriggerAction::Continue)
+                }),
+            )
+            .unwrap();
+
+        let mut session = shared.session();
+        match session.run("insert into t1 values (1)") {
+            Err(BustubxError::TriggerReentry { table }) => assert_eq!(table.table(), "t1"),
+            result => panic!("expected a reentry error, got {result:?}"),
+        }
+        // the thread is out of the trigger again
+        assert!(session.run("select a from t1").unwrap().is_empty());
+    }
+}
@@ -15,27 +15,38 @@ pub struct AggregateFunction {
     pub distinct: bool,
 }
 
+impl AggregateFunction {
+    fn arg(&self) -> BustubxResult<&Expr> {
+        self.args.first().ok_or(BustubxError::Internal(format!(
+            "aggregate function {} should have one arg instead of {:?}",
+            self.func_kind, self.args
+        )))
+    }
+}
+
 impl ExprTrait for AggregateFunction {
-    fn data_type(&self, _input_schema: &Schema) -> BustubxResult<DataType> {
+    fn data_type(&self, input_schema: &Schema) -> BustubxResult<DataType> {
         match self.func_kind {
-            AggregateFunctionKind::Count => Ok(DataType::Int64),
+            AggregateFunctionKind::Count | AggregateFunctionKind::Grouping => Ok(DataType::Int64),
             AggregateFunctionKind::Avg => Ok(DataType::Float64),
+            AggregateFunctionKind::Min | AggregateFunctionKind::Max => {
+                self.arg()?.data_type(input_schema)
+            }
         }
     }
 
     fn nullable(&self, _input_schema: &Schema) -> BustubxResult<bool> {
-        Ok(true)
+        Ok(self.func_kind != AggregateFunctionKind::Grouping)
     }
 
     fn evaluate(&self, tuple: &Tuple) -> BustubxResult<ScalarValue> {
         match self.func_kind {
-            AggregateFunctionKind::Count | AggregateFunctionKind::Avg => {
-                let expr = self.args.first().ok_or(BustubxError::Internal(format!(
-                    "aggregate function {} should have one arg instead of {:?}",
-                    self.func_kind, self.args
-                )))?;
-                expr.evaluate(tuple)
-            }
+            AggregateFunctionKind::Count
+            | AggregateFunctionKind::Avg
+            | AggregateFunctionKind::Min
+            | AggregateFunctionKind::Max => self.arg()?.evaluate(tuple),
+            // computed from the grouping set, not the rows
+            AggregateFunctionKind::Grouping => Ok(ScalarValue::Int64(None)),
         }
     }
 
@@ -50,6 +61,12 @@ impl ExprTrait for AggregateFunction {
 
 impl std::fmt::Display for AggregateFunction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.func_kind)
+        // tells apart the columns of aggregates over different arguments
+        let args = self
+            .args
+            .iter()
+            .map(|arg| format!("{arg}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}({})", self.func_kind, args.join(", "))
     }
 }
@@ -29,7 +29,8 @@ impl ExprTrait for Alias {
             self.name.clone(),
             self.data_type(input_schema)?,
             self.nullable(input_schema)?,
-        ))
+        )
+        .with_collation(self.expr.collation(input_schema)?.unwrap_or_default()))
     }
 }
 
@@ -1,6 +1,7 @@
 use crate::catalog::Schema;
 use crate::catalog::{Column, DataType};
-use crate::common::ScalarValue;
+use crate::common::value_ord::Collation;
+use crate::common::{numeric, ScalarValue};
 use crate::error::BustubxResult;
 use crate::expression::{Expr, ExprTrait};
 use crate::storage::Tuple;
@@ -18,10 +19,27 @@ pub struct BinaryExpr {
     pub right: Box<Expr>,
 }
 
+impl BinaryExpr {
+    /// Collation strings are compared by, that of a string column on either side. Columns
+    /// of different collations on both sides do not compare.
+    pub fn comparison_collation(&self, input_schema: &Schema) -> BustubxResult<Collation> {
+        match (
+            self.left.collation(input_schema)?,
+            self.right.collation(input_schema)?,
+        ) {
+            (Some(left), Some(right)) if left != right => Err(BustubxError::Plan(format!(
+                "{} compares strings of collations {} and {}",
+                self, left, right
+            ))),
+            (left, right) => Ok(left.or(right).unwrap_or_default()),
+        }
+    }
+}
+
 impl ExprTrait for BinaryExpr {
     fn data_type(&self, input_schema: &Schema) -> BustubxResult<DataType> {
-        let _left_type = self.left.data_type(input_schema)?;
-        let _right_type = self.right.data_type(input_schema)?;
+        let left_type = self.left.data_type(input_schema)?;
+        let right_type = self.right.data_type(input_schema)?;
         match self.op {
             BinaryOp::Gt
             | BinaryOp::Lt
@@ -31,10 +49,9 @@ impl ExprTrait for BinaryExpr {
             | BinaryOp::NotEq
             | BinaryOp::And
             | BinaryOp::Or => Ok(DataType::Boolean),
-            BinaryOp::Plus => todo!(),
-            BinaryOp::Minus => todo!(),
-            BinaryOp::Multiply => todo!(),
-            BinaryOp::Divide => todo!(),
+            BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Multiply | BinaryOp::Divide => {
+                numeric::coerce_types(&left_type, &right_type)
+            }
         }
     }
 
@@ -45,13 +62,20 @@ impl ExprTrait for BinaryExpr {
     fn evaluate(&self, tuple: &Tuple) -> BustubxResult<ScalarValue> {
         let l = self.left.evaluate(tuple)?;
         let r = self.right.evaluate(tuple)?;
+        let collation = || self.comparison_collation(&tuple.schema);
         match self.op {
-            BinaryOp::Gt => evaluate_comparison(l, r, &[Ordering::Greater]),
-            BinaryOp::Lt => evaluate_comparison(l, r, &[Ordering::Less]),
-            BinaryOp::GtEq => evaluate_comparison(l, r, &[Ordering::Greater, Ordering::Equal]),
-            BinaryOp::LtEq => evaluate_comparison(l, r, &[Ordering::Less, Ordering::Equal]),
-            BinaryOp::Eq => evaluate_comparison(l, r, &[Ordering::Equal]),
-            BinaryOp::NotEq => evaluate_comparison(l, r, &[Ordering::Greater, Ordering::Less]),
+            BinaryOp::Gt => evaluate_comparison(l, r, &[Ordering::Greater], collation),
+            BinaryOp::Lt => evaluate_comparison(l, r, &[Ordering::Less], collation),
+            BinaryOp::GtEq => {
+                evaluate_comparison(l, r, &[Ordering::Greater, Ordering::Equal], collation)
+            }
+            BinaryOp::LtEq => {
+                evaluate_comparison(l, r, &[Ordering::Less, Ordering::Equal], collation)
+            }
+            BinaryOp::Eq => evaluate_comparison(l, r, &[Ordering::Equal], collation),
+            BinaryOp::NotEq => {
+                evaluate_comparison(l, r, &[Ordering::Greater, Ordering::Less], collation)
+            }
             BinaryOp::And => {
                 let l_bool = l.as_boolean()?;
                 let r_bool = r.as_boolean()?;
@@ -62,10 +86,17 @@ impl ExprTrait for BinaryExpr {
                     }
                 }
             }
-            _ => Err(BustubxError::NotSupport(format!(
-                "binary operator {:?} not support evaluating yet",
-                self.op
-            ))),
+            BinaryOp::Or => match (l.as_boolean()?, r.as_boolean()?) {
+                (Some(v1), Some(v2)) => Ok((v1 || v2).into()),
+                (Some(true), None) | (None, Some(true)) => Ok(true.into()),
+                // unknown unless a side is true
+                (Some(false), None) | (None, Some(false)) | (None, None) => {
+                    Ok(ScalarValue::Boolean(None))
+                }
+            },
+            BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Multiply | BinaryOp::Divide => {
+                evaluate_arithmetic(l, r, self.op)
+            }
         }
     }
 
@@ -82,21 +113,89 @@ fn evaluate_comparison(
     left: ScalarValue,
     right: ScalarValue,
     accepted_orderings: &[Ordering],
+    collation: impl FnOnce() -> BustubxResult<Collation>,
 ) -> BustubxResult<ScalarValue> {
-    let coercion_type =
-        DataType::comparison_numeric_coercion(&left.data_type(), &right.data_type())?;
-    let order = left
-        .cast_to(&coercion_type)?
-        .partial_cmp(&right.cast_to(&coercion_type)?)
-        .ok_or(BustubxError::Execution(format!(
-            "Can not compare {:?} and {:?}",
-            left, right
-        )))?;
+    // comparing with NULL is neither true nor false
+    if left.is_null() || right.is_null() {
+        return Ok(ScalarValue::Boolean(None));
+    }
+    let order = match (&left, &right) {
+        (ScalarValue::Varchar(Some(l)), ScalarValue::Varchar(Some(r))) => {
+            collation()?.compare_str(l, r)
+        }
+        _ => numeric::compare(&left, &right)?,
+    };
     Ok(ScalarValue::Boolean(Some(
         accepted_orderings.contains(&order),
     )))
 }
 
+macro_rules! integer_arithmetic {
+    ($variant:ident, $l:expr, $r:expr, $op:expr) => {
+        match ($l, $r) {
+            (Some(l), Some(r)) => {
+                let value = match $op {
+                    BinaryOp::Plus => l.checked_add(r),
+                    BinaryOp::Minus => l.checked_sub(r),
+                    BinaryOp::Multiply => l.checked_mul(r),
+                    _ if r == 0 => {
+                        return Err(BustubxError::Execution("division by zero".to_string()))
+                    }
+                    _ => l.checked_div(r),
+                };
+                value
+                    .map(|v| ScalarValue::$variant(Some(v)))
+                    .ok_or_else(|| {
+                        BustubxError::Execution(format!("{:?} {} {:?} out of range", l, $op, r))
+                    })
+            }
+            _ => Ok(ScalarValue::$variant(None)),
+        }
+    };
+}
+
+macro_rules! float_arithmetic {
+    ($variant:ident, $l:expr, $r:expr, $op:expr) => {
+        match ($l, $r) {
+            (Some(l), Some(r)) => match $op {
+                BinaryOp::Plus => Ok(ScalarValue::$variant(Some(l + r))),
+                BinaryOp::Minus => Ok(ScalarValue::$variant(Some(l - r))),
+                BinaryOp::Multiply => Ok(ScalarValue::$variant(Some(l * r))),
+                _ if r == 0.0 => Err(BustubxError::Execution("division by zero".to_string())),
+                _ => Ok(ScalarValue::$variant(Some(l / r))),
+            },
+            _ => Ok(ScalarValue::$variant(None)),
+        }
+    };
+}
+
+fn evaluate_arithmetic(
+    left: ScalarValue,
+    right: ScalarValue,
+    op: BinaryOp,
+) -> BustubxResult<ScalarValue> {
+    let coercion_type = numeric::coerce_types(&left.data_type(), &right.data_type())?;
+    match (
+        left.cast_to(&coercion_type)?,
+        right.cast_to(&coercion_type)?,
+    ) {
+        (ScalarValue::Int8(l), ScalarValue::Int8(r)) => integer_arithmetic!(Int8, l, r, op),
+        (ScalarValue::Int16(l), ScalarValue::Int16(r)) => integer_arithmetic!(Int16, l, r, op),
+        (ScalarValue::Int32(l), ScalarValue::Int32(r)) => integer_arithmetic!(Int32, l, r, op),
+        (ScalarValue::Int64(l), ScalarValue::Int64(r)) => integer_arithmetic!(Int64, l, r, op),
+        (ScalarValue::UInt8(l), ScalarValue::UInt8(r)) => integer_arithmetic!(UInt8, l, r, op),
+        (ScalarValue::UInt16(l), ScalarValue::UInt16(r)) => integer_arithmetic!(UInt16, l, r, op),
+        (ScalarValue::UInt32(l), ScalarValue::UInt32(r)) => integer_arithmetic!(UInt32, l, r, op),
+        (ScalarValue::UInt64(l), ScalarValue::UInt64(r)) => integer_arithmetic!(UInt64, l, r, op),
+        (ScalarValue::Float32(l), ScalarValue::Float32(r)) => float_arithmetic!(Float32, l, r, op),
+        (ScalarValue::Float64(l), ScalarValue::Float64(r)) => float_arithmetic!(Float64, l, r, op),
+        (l, r) => Err(BustubxError::NotSupport(format!(
+            "Can not apply {} to {:?} and {:?}",
+            op, l, r
+        ))),
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Hash)]
 pub enum BinaryOp {
     Plus,
@@ -113,6 +212,20 @@ pub enum BinaryOp {
     Or,
 }
 
+impl BinaryOp {
+    /// The comparison with its operands swapped, `1 < a` is `a > 1`.
+    pub fn flip_comparison(&self) -> Option<BinaryOp> {
+        match self {
+            BinaryOp::Eq | BinaryOp::NotEq => Some(*self),
+            BinaryOp::Lt => Some(BinaryOp::Gt),
+            BinaryOp::LtEq => Some(BinaryOp::GtEq),
+            BinaryOp::Gt => Some(BinaryOp::Lt),
+            BinaryOp::GtEq => Some(BinaryOp::LtEq),
+            _ => None,
+        }
+    }
+}
+
 impl TryFrom<&sqlparser::ast::BinaryOperator> for BinaryOp {
     type Error = BustubxError;
 
@@ -39,7 +39,8 @@ impl ExprTrait for ColumnExpr {
             self.data_type(input_schema)?,
             self.nullable(input_schema)?,
         )
-        .with_relation(self.relation.clone().or(column.relation.clone())))
+        .with_relation(self.relation.clone().or(column.relation.clone()))
+        .with_collation(column.collation))
     }
 }
 
@@ -4,18 +4,21 @@ mod binary;
 mod cast;
 mod column;
 mod literal;
+mod scalar;
 mod util;
 
 pub use aggregate::AggregateFunction;
 pub use alias::Alias;
-pub use binary::BinaryExpr;
+pub use binary::{BinaryExpr, BinaryOp};
 pub use cast::Cast;
 pub use column::ColumnExpr;
 pub use literal::Literal;
+pub use scalar::ScalarFunction;
 pub use util::*;
 
 use crate::catalog::Schema;
 use crate::catalog::{Column, DataType};
+use crate::common::value_ord::Collation;
 use crate::common::ScalarValue;
 use crate::storage::Tuple;
 use crate::BustubxResult;
@@ -49,6 +52,98 @@ pub enum Expr {
     Cast(Cast),
     /// Represents the call of an aggregate built-in function with arguments.
     AggregateFunction(AggregateFunction),
+    /// Represents the call of a scalar built-in function with arguments.
+    ScalarFunction(ScalarFunction),
+}
+
+impl Expr {
+    /// Whether the expression calls a volatile function, it must then be evaluated
+    /// for every row instead of being folded into a constant.
+    pub fn is_volatile(&self) -> bool {
+        self.exists(&|e| matches!(e, Expr::ScalarFunction(func) if func.func_kind.is_volatile()))
+    }
+
+    /// Whether the expression reads a column of its input
+    pub fn references_columns(&self) -> bool {
+        self.exists(&|e| matches!(e, Expr::Column(_)))
+    }
+
+    /// Whether the expression reads the column `name` of its input, of any relation
+    pub fn references_column(&self, name: &str) -> bool {
+        self.exists(&|e| matches!(e, Expr::Column(column) if column.name == name))
+    }
+
+    /// Whether every column the expression reads resolves in `schema`
+    pub fn is_bound_by(&self, schema: &Schema) -> bool {
+        !self.exists(&|e| {
+            matches!(e, Expr::Column(column)
+                if schema.index_of(column.relation.as_ref(), &column.name).is_err())
+        })
+    }
+
+    /// Whether the expression reads a column only `outer_schema` resolves, not `schema`, as
+    /// a subquery reading its outer query does
+    pub fn reads_outer(&self, schema: &Schema, outer_schema: &Schema) -> bool {
+        self.exists(&|e| {
+            matches!(e, Expr::Column(column)
+                if schema.index_of(column.relation.as_ref(), &column.name).is_err()
+                    && outer_schema.index_of(column.relation.as_ref(), &column.name).is_ok())
+        })
+    }
+
+    /// Collation of the strings the expression yields, that of the string column it reads
+    /// and None for other expressions. Fails for a comparison between string columns of
+    /// different collations, anywhere in the expression.
+    pub fn collation(&self, schema: &Schema) -> BustubxResult<Option<Collation>> {
+        match self {
+            Expr::Column(column) => Ok(schema
+                .column_with_name(column.relation.as_ref(), &column.name)
+                .ok()
+                .filter(|column| matches!(column.data_type, DataType::Varchar(_)))
+                .map(|column| column.collation)),
+            Expr::Alias(Alias { expr, .. }) => expr.collation(schema),
+            Expr::Cast(Cast { expr, data_type }) => {
+                let collation = expr.collation(schema)?;
+                Ok(collation.filter(|_| matches!(data_type, DataType::Varchar(_))))
+            }
+            Expr::Binary(binary) => {
+                if binary.op.flip_comparison().is_some() {
+                    binary.comparison_collation(schema)?;
+                } else {
+                    binary.left.collation(schema)?;
+                    binary.right.collation(schema)?;
+                }
+                Ok(None)
+            }
+            Expr::AggregateFunction(AggregateFunction { args, .. })
+            | Expr::ScalarFunction(ScalarFunction { args, .. }) => {
+                for arg in args {
+                    arg.collation(schema)?;
+                }
+                Ok(None)
+            }
+            Expr::Literal(_) => Ok(None),
+        }
+    }
+
+    fn exists(&self, predicate: &impl Fn(&Expr) -> bool) -> bool {
+        if predicate(self) {
+            return true;
+        }
+        match self {
+            Expr::Alias(Alias { expr, .. }) | Expr::Cast(Cast { expr, .. }) => {
+                expr.exists(predicate)
+            }
+            Expr::Binary(BinaryExpr { left, right, .. }) => {
+                left.exists(predicate) || right.exists(predicate)
+            }
+            Expr::AggregateFunction(AggregateFunction { args, .. })
+            | Expr::ScalarFunction(ScalarFunction { args, .. }) => {
+                args.iter().any(|arg| arg.exists(predicate))
+            }
+            Expr::Column(_) | Expr::Literal(_) => false,
+        }
+    }
 }
 
 impl ExprTrait for Expr {
@@ -60,6 +155,7 @@ impl ExprTrait for Expr {
             Expr::Binary(binary) => binary.data_type(input_schema),
             Expr::Cast(cast) => cast.data_type(input_schema),
             Expr::AggregateFunction(aggr) => aggr.data_type(input_schema),
+            Expr::ScalarFunction(func) => func.data_type(input_schema),
         }
     }
 
@@ -71,6 +167,7 @@ impl ExprTrait for Expr {
             Expr::Binary(binary) => binary.nullable(input_schema),
             Expr::Cast(cast) => cast.nullable(input_schema),
             Expr::AggregateFunction(aggr) => aggr.nullable(input_schema),
+            Expr::ScalarFunction(func) => func.nullable(input_schema),
         }
     }
 
@@ -82,6 +179,7 @@ impl ExprTrait for Expr {
             Expr::Binary(binary) => binary.evaluate(tuple),
             Expr::Cast(cast) => cast.evaluate(tuple),
             Expr::AggregateFunction(aggr) => aggr.evaluate(tuple),
+            Expr::ScalarFunction(func) => func.evaluate(tuple),
         }
     }
 
@@ -93,6 +191,7 @@ impl ExprTrait for Expr {
             Expr::Binary(binary) => binary.to_column(input_schema),
             Expr::Cast(cast) => cast.to_column(input_schema),
             Expr::AggregateFunction(aggr) => aggr.to_column(input_schema),
+            Expr::ScalarFunction(func) => func.to_column(input_schema),
         }
     }
 }
@@ -106,6 +205,7 @@ impl std::fmt::Display for Expr {
             Expr::Binary(e) => write!(f, "{e}"),
             Expr::Cast(e) => write!(f, "{e}"),
             Expr::AggregateFunction(e) => write!(f, "{e}"),
+            Expr::ScalarFunction(e) => write!(f, "{e}"),
         }
     }
 }
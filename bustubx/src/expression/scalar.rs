@@ -0,0 +1,86 @@
+use crate::catalog::{Column, DataType, Schema};
+use crate::common::{ClockRef, ScalarValue};
+use crate::expression::{Expr, ExprTrait};
+use crate::function::{implicitly_casts, ScalarFunctionKind};
+use crate::{BustubxError, BustubxResult, Tuple};
+
+#[derive(Clone, Debug)]
+pub struct ScalarFunction {
+    /// the function kind
+    pub func_kind: ScalarFunctionKind,
+    /// List of expressions to feed to the functions as arguments
+    pub args: Vec<Expr>,
+    /// Clock of the database the function was bound in
+    pub clock: ClockRef,
+}
+
+impl PartialEq for ScalarFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.func_kind == other.func_kind && self.args == other.args
+    }
+}
+
+impl Eq for ScalarFunction {}
+
+impl ExprTrait for ScalarFunction {
+    fn data_type(&self, input_schema: &Schema) -> BustubxResult<DataType> {
+        // the arguments of a registered function are cast to its signature when evaluated
+        if let ScalarFunctionKind::Udf(func) = &self.func_kind {
+            for (i, (arg, expected)) in self.args.iter().zip(func.signature.args.iter()).enumerate()
+            {
+                let data_type = arg.data_type(input_schema)?;
+                if !implicitly_casts(&data_type, expected) {
+                    return Err(BustubxError::Plan(format!(
+                        "The function {} expects {} as argument {} instead of {}",
+                        func.name,
+                        expected,
+                        i + 1,
+                        data_type
+                    )));
+                }
+            }
+        }
+        Ok(self.func_kind.return_type())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> BustubxResult<bool> {
+        // a registered function may return null for any arguments
+        if matches!(self.func_kind, ScalarFunctionKind::Udf(_)) {
+            return Ok(true);
+        }
+        for arg in self.args.iter() {
+            if arg.nullable(input_schema)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn evaluate(&self, tuple: &Tuple) -> BustubxResult<ScalarValue> {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.evaluate(tuple))
+            .collect::<BustubxResult<Vec<_>>>()?;
+        self.func_kind.evaluate(&args, self.clock.as_ref())
+    }
+
+    fn to_column(&self, input_schema: &Schema) -> BustubxResult<Column> {
+        Ok(Column::new(
+            format!("{}", self),
+            self.data_type(input_schema)?,
+            self.nullable(input_schema)?,
+        ))
+    }
+}
+
+impl std::fmt::Display for ScalarFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| format!("{arg}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}({})", self.func_kind, args.join(", "))
+    }
+}
@@ -0,0 +1,27 @@
+use crate::common::ScalarValue;
+use crate::function::Accumulator;
+use crate::BustubxResult;
+
+/// GROUPING(..) of a group, a bit for every argument, the last one being the lowest,
+/// set if the argument is rolled up in the grouping set of the group. The aggregate
+/// knows the grouping set, the mask does not depend on the rows.
+#[derive(Debug, Clone)]
+pub struct GroupingAccumulator {
+    mask: i64,
+}
+
+impl GroupingAccumulator {
+    pub fn new(mask: i64) -> Self {
+        Self { mask }
+    }
+}
+
+impl Accumulator for GroupingAccumulator {
+    fn update_value(&mut self, _value: &ScalarValue) -> BustubxResult<()> {
+        Ok(())
+    }
+
+    fn evaluate(&self) -> BustubxResult<ScalarValue> {
+        Ok(self.mask.into())
+    }
+}
@@ -0,0 +1,60 @@
+use std::cmp::Ordering;
+
+use crate::catalog::DataType;
+use crate::common::ScalarValue;
+use crate::function::Accumulator;
+use crate::{BustubxError, BustubxResult};
+
+/// MIN or MAX of the non-null values, NULL without any.
+#[derive(Debug, Clone)]
+pub struct MinMaxAccumulator {
+    data_type: DataType,
+    value: Option<ScalarValue>,
+    // the ordering a new value has to have against the kept one to replace it
+    keeps: Ordering,
+}
+
+impl MinMaxAccumulator {
+    pub fn min(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            value: None,
+            keeps: Ordering::Less,
+        }
+    }
+
+    pub fn max(data_type: DataType) -> Self {
+        Self {
+            data_type,
+            value: None,
+            keeps: Ordering::Greater,
+        }
+    }
+}
+
+impl Accumulator for MinMaxAccumulator {
+    fn update_value(&mut self, value: &ScalarValue) -> BustubxResult<()> {
+        if value.is_null() {
+            return Ok(());
+        }
+        let replaces = match &self.value {
+            Some(kept) => {
+                value.partial_cmp(kept).ok_or_else(|| {
+                    BustubxError::Execution(format!("Cannot compare {} with {}", value, kept))
+                })? == self.keeps
+            }
+            None => true,
+        };
+        if replaces {
+            self.value = Some(value.clone());
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> BustubxResult<ScalarValue> {
+        Ok(self
+            .value
+            .clone()
+            .unwrap_or(ScalarValue::new_empty(self.data_type)))
+    }
+}
@@ -1,10 +1,15 @@
 mod avg;
 mod count;
+mod grouping;
+mod min_max;
 
 pub use avg::AvgAccumulator;
 pub use count::CountAccumulator;
+pub use grouping::GroupingAccumulator;
+pub use min_max::MinMaxAccumulator;
 use std::fmt::Debug;
 
+use crate::catalog::DataType;
 use crate::common::ScalarValue;
 use crate::BustubxResult;
 use strum::{EnumIter, IntoEnumIterator};
@@ -13,13 +18,21 @@ use strum::{EnumIter, IntoEnumIterator};
 pub enum AggregateFunctionKind {
     Count,
     Avg,
+    Grouping,
+    Min,
+    Max,
 }
 
 impl AggregateFunctionKind {
-    pub fn create_accumulator(&self) -> Box<dyn Accumulator> {
+    /// Accumulator of the function, evaluating to values of `data_type`.
+    pub fn create_accumulator(&self, data_type: DataType) -> Box<dyn Accumulator> {
         match self {
             AggregateFunctionKind::Count => Box::new(CountAccumulator::new()),
             AggregateFunctionKind::Avg => Box::new(AvgAccumulator::new()),
+            // every column is grouped without grouping sets
+            AggregateFunctionKind::Grouping => Box::new(GroupingAccumulator::new(0)),
+            AggregateFunctionKind::Min => Box::new(MinMaxAccumulator::min(data_type)),
+            AggregateFunctionKind::Max => Box::new(MinMaxAccumulator::max(data_type)),
         }
     }
 
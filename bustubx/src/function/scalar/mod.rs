@@ -1 +1,76 @@
+mod udf;
 
+use std::sync::Arc;
+
+use crate::catalog::DataType;
+use crate::common::{Clock, ScalarValue};
+use crate::{BustubxError, BustubxResult};
+
+pub use udf::{
+    implicitly_casts, signature, FunctionRegistry, FunctionSignature, RegisteredFunction, ScalarUdf,
+};
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ScalarFunctionKind {
+    /// Seconds since the unix epoch read from the database clock
+    Now,
+    /// Function registered by the embedder, see [`crate::Database::register_function`]
+    Udf(Arc<RegisteredFunction>),
+}
+
+impl ScalarFunctionKind {
+    /// The built-in function of the name, registered functions are looked up in the
+    /// [`FunctionRegistry`] of the catalog.
+    pub fn find(name: &str) -> Option<Self> {
+        [ScalarFunctionKind::Now]
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(name))
+    }
+
+    /// A volatile function may return a different value on every call, it is evaluated
+    /// once per row and never folded into a constant.
+    pub fn is_volatile(&self) -> bool {
+        match self {
+            ScalarFunctionKind::Now => true,
+            ScalarFunctionKind::Udf(_) => false,
+        }
+    }
+
+    pub fn return_type(&self) -> DataType {
+        match self {
+            ScalarFunctionKind::Now => DataType::Int64,
+            ScalarFunctionKind::Udf(func) => func.signature.return_type,
+        }
+    }
+
+    pub fn arg_count(&self) -> usize {
+        match self {
+            ScalarFunctionKind::Now => 0,
+            ScalarFunctionKind::Udf(func) => func.signature.args.len(),
+        }
+    }
+
+    pub fn evaluate(&self, args: &[ScalarValue], clock: &dyn Clock) -> BustubxResult<ScalarValue> {
+        if args.len() != self.arg_count() {
+            return Err(BustubxError::Execution(format!(
+                "function {} expects {} args instead of {}",
+                self,
+                self.arg_count(),
+                args.len()
+            )));
+        }
+        match self {
+            ScalarFunctionKind::Now => Ok(ScalarValue::Int64(Some(clock.now()))),
+            ScalarFunctionKind::Udf(func) => func.invoke(args),
+        }
+    }
+}
+
+impl std::fmt::Display for ScalarFunctionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScalarFunctionKind::Now => write!(f, "now"),
+            ScalarFunctionKind::Udf(func) => write!(f, "{}", func.name),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::catalog::DataType;
+use crate::common::numeric::is_numeric;
+use crate::common::ScalarValue;
+use crate::function::{AggregateFunctionKind, ScalarFunctionKind};
+use crate::{BustubxError, BustubxResult};
+
+/// Scalar function implemented by the embedder, see [`crate::Database::register_function`].
+///
+/// It is called once per row with the evaluated arguments, already cast to the types of
+/// its signature, and must return a value of the return type of the signature. A function
+/// is taken to be deterministic, the same arguments give the same value, as the key of an
+/// expression index needs.
+pub trait ScalarUdf: Send + Sync {
+    fn invoke(&self, args: &[ScalarValue]) -> BustubxResult<ScalarValue>;
+}
+
+impl<F> ScalarUdf for F
+where
+    F: Fn(&[ScalarValue]) -> BustubxResult<ScalarValue> + Send + Sync,
+{
+    fn invoke(&self, args: &[ScalarValue]) -> BustubxResult<ScalarValue> {
+        self(args)
+    }
+}
+
+/// Argument and return types of a registered function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub args: Vec<DataType>,
+    pub return_type: DataType,
+}
+
+pub fn signature(args: &[DataType], return_type: DataType) -> FunctionSignature {
+    FunctionSignature {
+        args: args.to_vec(),
+        return_type,
+    }
+}
+
+/// Whether a value of `from` is cast to `to` without an explicit CAST when passed to a
+/// registered function: numbers to any numeric type, strings to strings of any length.
+pub fn implicitly_casts(from: &DataType, to: &DataType) -> bool {
+    from == to
+        || (is_numeric(from) && is_numeric(to))
+        || matches!((from, to), (DataType::Varchar(_), DataType::Varchar(_)))
+}
+
+pub struct RegisteredFunction {
+    pub name: String,
+    pub signature: FunctionSignature,
+    pub udf: Arc<dyn ScalarUdf>,
+}
+
+impl RegisteredFunction {
+    pub fn invoke(&self, args: &[ScalarValue]) -> BustubxResult<ScalarValue> {
+        let mut cast_args = Vec::with_capacity(args.len());
+        for (i, (arg, data_type)) in args.iter().zip(self.signature.args.iter()).enumerate() {
+            if !arg.is_null() && !implicitly_casts(&arg.data_type(), data_type) {
+                return Err(BustubxError::Execution(format!(
+                    "function {} expects {} as argument {} instead of {}",
+                    self.name,
+                    data_type,
+                    i + 1,
+                    arg.data_type()
+                )));
+            }
+            cast_args.push(arg.cast_to(data_type)?);
+        }
+        let value = self.udf.invoke(&cast_args)?;
+        if !value.is_null() && !implicitly_casts(&value.data_type(), &self.signature.return_type) {
+            return Err(BustubxError::Execution(format!(
+                "function {} returned {} instead of {}",
+                self.name,
+                value.data_type(),
+                self.signature.return_type
+            )));
+        }
+        value.cast_to(&self.signature.return_type)
+    }
+}
+
+// functions of the same name are the same, names are unique in a registry
+impl PartialEq for RegisteredFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for RegisteredFunction {}
+
+impl std::fmt::Debug for RegisteredFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredFunction")
+            .field("name", &self.name)
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+/// Scalar functions registered by the embedder, by lowercase name. Like triggers they are
+/// not persisted, they are registered again after every open.
+#[derive(Debug, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Arc<RegisteredFunction>>,
+}
+
+impl FunctionRegistry {
+    pub fn register(
+        &mut self,
+        name: &str,
+        signature: FunctionSignature,
+        udf: Arc<dyn ScalarUdf>,
+    ) -> BustubxResult<Arc<RegisteredFunction>> {
+        let name = name.to_lowercase();
+        if AggregateFunctionKind::find(&name).is_some()
+            || ScalarFunctionKind::find(&name).is_some()
+            || self.functions.contains_key(&name)
+        {
+            return Err(BustubxError::Plan(format!(
+                "function {} already exists",
+                name
+            )));
+        }
+        let function = Arc::new(RegisteredFunction {
+            name: name.clone(),
+            signature,
+            udf,
+        });
+        self.functions.insert(name, function.clone());
+        Ok(function)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<RegisteredFunction>> {
+        self.functions.get(&name.to_lowercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::catalog::DataType;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::function::signature;
+    use crate::{BustubxError, BustubxResult, Database, DatabaseOptions};
+
+    fn geo_dist(args: &[ScalarValue]) -> BustubxResult<ScalarValue> {
+        let mut coords = vec![];
+        for arg in args {
+            match arg {
+                ScalarValue::Float64(Some(v)) => coords.push(*v),
+                _ => return Ok(ScalarValue::Float64(None)),
+            }
+        }
+        let (dx, dy) = (coords[0] - coords[2], coords[1] - coords[3]);
+        Ok(ScalarValue::Float64(Some((dx * dx + dy * dy).sqrt())))
+    }
+
+    fn register_geo_dist(db: &mut Database) {
+        db.register_function(
+            "geo_dist",
+            signature(&[DataType::Float64; 4], DataType::Float64),
+            Arc::new(geo_dist),
+        )
+        .unwrap();
+    }
+
+    fn fixture(db: &mut Database) {
+        db.run("create table points (id int, x double, y int)")
+            .unwrap();
+        db.run("insert into points values (1, 3.0, 4), (2, 1.0, 1), (3, 6.0, 8), (4, null, 2)")
+            .unwrap();
+    }
+
+    fn ids(db: &mut Database, sql: &str) -> Vec<ScalarValue> {
+        db.run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|row| row.data[0].clone())
+            .collect()
+    }
+
+    #[test]
+    pub fn test_registered_function_in_query() {
+        let mut db = Database::new_temp().unwrap();
+        fixture(&mut db);
+        assert!(matches!(
+            db.run("select geo_dist(x, y, 0, 0) from points"),
+            Err(BustubxError::UnknownFunction(name)) if name == "geo_dist"
+        ));
+        register_geo_dist(&mut db);
+
+        // the int column and literals are cast to the double arguments
+        let sql = "select id, geo_dist(x, y, 0, 0) from points \
+                   where geo_dist(x, y, 0, 0) < 6.0 order by geo_dist(x, y, 1, 1) desc";
+        let rows = db.run(sql).unwrap();
+        assert_eq!(
+            rows.iter().map(|row| row.data.clone()).collect::<Vec<_>>(),
+            vec![
+                vec![1i32.into(), 5.0f64.into()],
+                vec![2i32.into(), 2f64.sqrt().into()],
+            ]
+        );
+        assert_eq!(
+            ids(
+                &mut db,
+                "select id from points where GEO_DIST(x, y, 6, 8) = 0.0"
+            ),
+            vec![3i32.into()]
+        );
+        let plan = db.explain(sql).unwrap().text_lines().join("\n");
+        assert!(plan.contains("geo_dist("), "{plan}");
+
+        assert!(matches!(
+            db.run("select geo_dist(x, y) from points"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("select geo_dist(x, y, 'a', 0) from points"),
+            Err(BustubxError::Plan(_))
+        ));
+        // built-in and registered names are taken
+        for name in ["geo_dist", "now", "count"] {
+            assert!(db
+                .register_function(name, signature(&[], DataType::Int32), Arc::new(geo_dist))
+                .is_err());
+        }
+    }
+
+    #[test]
+    pub fn test_registered_function_error() {
+        let mut db = Database::new_temp().unwrap();
+        fixture(&mut db);
+        db.register_function(
+            "checked_inverse",
+            signature(&[DataType::Float64], DataType::Float64),
+            Arc::new(|args: &[ScalarValue]| match &args[0] {
+                ScalarValue::Float64(Some(v)) if *v == 0.0 => Err(BustubxError::Execution(
+                    "checked_inverse of zero".to_string(),
+                )),
+                ScalarValue::Float64(v) => Ok(ScalarValue::Float64(v.map(|v| 1.0 / v))),
+                _ => unreachable!(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            ids(
+                &mut db,
+                "select id from points where checked_inverse(y) = 0.5"
+            ),
+            vec![4i32.into()]
+        );
+        db.run("insert into points values (5, 0.0, 0)").unwrap();
+        assert!(matches!(
+            db.run("select id from points where checked_inverse(y) > 0.0"),
+            Err(BustubxError::Execution(message)) if message == "checked_inverse of zero"
+        ));
+    }
+
+    #[test]
+    pub fn test_expression_index_on_reopen() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let table_ref = TableReference::bare("points");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        register_geo_dist(&mut db);
+        db.run("create table points (id int, x double, y int)")
+            .unwrap();
+        db.run("create index idx_dist on points (geo_dist(x, y, 0, 0))")
+            .unwrap();
+        assert!(matches!(
+            db.run("create index idx_now on points (now())"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("insert into points values (1, 3.0, 4), (2, 1.0, 1)")
+            .unwrap();
+        assert!(db.catalog.check_table(&table_ref, true).unwrap().is_empty());
+        db.flush().unwrap();
+        db.close().unwrap();
+
+        // the index is loaded but cannot be maintained until its function is registered
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(
+            ids(&mut db, "select id from points order by id"),
+            vec![1i32.into(), 2i32.into()]
+        );
+        assert!(matches!(
+            db.run("insert into points values (3, 6.0, 8)"),
+            Err(BustubxError::UnresolvedIndexKey { index, function, .. })
+                if index == "idx_dist" && function == "geo_dist"
+        ));
+        register_geo_dist(&mut db);
+        db.run("insert into points values (3, 6.0, 8)").unwrap();
+        db.run("delete from points where id = 1").unwrap();
+        assert!(db.catalog.check_table(&table_ref, true).unwrap().is_empty());
+        assert_eq!(
+            ids(
+                &mut db,
+                "select id from points where geo_dist(x, y, 0, 0) > 1.0"
+            ),
+            vec![2i32.into(), 3i32.into()]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use crate::common::util::encode_hex;
+use crate::common::{ScalarValue, TableReference};
+use crate::execution::TransactionWrites;
+use crate::storage::index::{BPlusTreeIndex, TreeIndexIterator};
+use crate::storage::TableHeap;
+use crate::transaction::IsolationLevel;
+use crate::{BustubxError, BustubxResult, Database, Tuple};
+
+/// Change of a key applied by [`KvStore::batch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvWrite {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+}
+
+/// Persistent ordered map of byte strings, see [`Database::kv`].
+///
+/// The pairs are the rows of the table named after the namespace, `(key bytea, value
+/// bytea)` with the unique index `<namespace>_key` on the key. Writes run as SQL statements
+/// so they are logged, recovered and locked like any other, and SQL reads and writes the
+/// table as usual. Reads walk the index in key order, bytewise with a prefix first.
+pub struct KvStore<'a> {
+    db: &'a mut Database,
+    table: TableReference,
+}
+
+impl Database {
+    /// The key-value store `namespace`, its table and index are created the first time.
+    /// The namespace is a plain identifier, it names a table of the default schema.
+    pub fn kv(&mut self, namespace: &str) -> BustubxResult<KvStore<'_>> {
+        let valid = namespace
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(BustubxError::Plan(format!(
+                "key-value namespace {:?} is not an identifier",
+                namespace
+            )));
+        }
+        let table = TableReference::bare(namespace.to_ascii_lowercase());
+        if self.catalog.catalog_table(&table).is_err() {
+            self.run(&format!(
+                "create table {namespace} (key bytea not null, value bytea not null)"
+            ))?;
+            self.run(&format!(
+                "create unique index {namespace}_key on {namespace} (key)"
+            ))?;
+        }
+        let store = KvStore { db: self, table };
+        store.index()?;
+        Ok(store)
+    }
+}
+
+impl KvStore<'_> {
+    /// Set the value of `key`, replacing the one it had.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> BustubxResult<()> {
+        self.db.run(&self.put_sql(key, value))?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &[u8]) -> BustubxResult<Option<Vec<u8>>> {
+        let mut pairs = self.range(key..=key)?;
+        Ok(pairs.pop().map(|(_, value)| value))
+    }
+
+    /// Remove `key`, false if it had no value.
+    pub fn delete(&mut self, key: &[u8]) -> BustubxResult<bool> {
+        let rows = self.db.run(&self.delete_sql(key))?;
+        Ok(rows
+            .first()
+            .is_some_and(|row| row.data[0] != ScalarValue::Int32(Some(0))))
+    }
+
+    /// Pairs whose keys are in `range`, in key order.
+    pub fn range<K: AsRef<[u8]>>(
+        &self,
+        range: impl RangeBounds<K>,
+    ) -> BustubxResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let index = self.index()?;
+        let key = |bound: Bound<&K>| {
+            bound.map(|key| {
+                Tuple::new(
+                    index.key_schema.clone(),
+                    vec![ScalarValue::Bytea(Some(key.as_ref().to_vec()))],
+                )
+            })
+        };
+        let range = (key(range.start_bound()), key(range.end_bound()));
+        let catalog_table = self.db.catalog.catalog_table(&self.table)?;
+        let heap: &Arc<TableHeap> = &catalog_table.table;
+        let mut pairs = vec![];
+        let mut iterator = TreeIndexIterator::new(index, range);
+        while let Some(entry) = iterator.next()? {
+            let Some(rid) = catalog_table.resolve_index_entry(entry)? else {
+                continue;
+            };
+            let Some(tuple) = heap.live_tuple(rid)? else {
+                continue;
+            };
+            match (&tuple.data[0], &tuple.data[1]) {
+                (ScalarValue::Bytea(Some(key)), ScalarValue::Bytea(Some(value))) => {
+                    pairs.push((key.clone(), value.clone()))
+                }
+                _ => {
+                    return Err(BustubxError::Internal(format!(
+                        "row {:?} of key-value table {} is not a pair of byte strings",
+                        tuple.data, self.table
+                    )))
+                }
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Apply `writes` in order as one transaction, none of them is applied if one fails.
+    pub fn batch(&mut self, writes: impl IntoIterator<Item = KvWrite>) -> BustubxResult<()> {
+        let txn_id = self
+            .db
+            .txn_manager
+            .begin_tracked(IsolationLevel::SnapshotIsolation)
+            .txn_id;
+        let mut transaction = TransactionWrites {
+            txn_id,
+            ..Default::default()
+        };
+        let mut result = Ok(());
+        for write in writes {
+            let sql = match &write {
+                KvWrite::Put { key, value } => self.put_sql(key, value),
+                KvWrite::Delete { key } => self.delete_sql(key),
+            };
+            result = self
+                .db
+                .run_in_transaction(&sql, None, txn_id, &mut transaction)
+                .map(|_| ());
+            if result.is_err() {
+                break;
+            }
+        }
+        let result = result.and_then(|_| self.db.check_deferred_keys(&transaction));
+        let rolled_back = match result {
+            Ok(_) => {
+                self.db
+                    .txn_manager
+                    .commit_changes(txn_id, &transaction.undo_log);
+                Ok(())
+            }
+            Err(_) => self.db.rollback_transaction(transaction.undo_log, None),
+        };
+        self.db.end_transaction(txn_id);
+        rolled_back?;
+        result
+    }
+
+    // The unique index on the key, fails for a table of the name that is no key-value table
+    fn index(&self) -> BustubxResult<Arc<BPlusTreeIndex>> {
+        let catalog_table = self.db.catalog.catalog_table(&self.table)?;
+        let index_name = format!("{}_key", self.table.table());
+        catalog_table
+            .indexes
+            .get(&index_name)
+            .filter(|index| index.unique && catalog_table.table.schema.column_count() == 2)
+            .cloned()
+            .ok_or_else(|| {
+                BustubxError::Plan(format!(
+                    "table {} is not a key-value table, it has no unique index {}",
+                    self.table, index_name
+                ))
+            })
+    }
+
+    fn put_sql(&self, key: &[u8], value: &[u8]) -> String {
+        format!(
+            "insert into {} values (x'{}', x'{}') on conflict (key) do update set value = excluded.value",
+            self.table,
+            encode_hex(key),
+            encode_hex(value)
+        )
+    }
+
+    fn delete_sql(&self, key: &[u8]) -> String {
+        format!(
+            "delete from {} where key = x'{}'",
+            self.table,
+            encode_hex(key)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempfile::TempDir;
+
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database, DatabaseOptions, KvWrite};
+
+    // big endian keys order as their numbers, some share a prefix with a shorter key
+    fn key(i: u32) -> Vec<u8> {
+        let mut key = i.to_be_bytes().to_vec();
+        if i.is_multiple_of(3) {
+            key.push(0);
+        }
+        if i.is_multiple_of(5) {
+            key.extend_from_slice(&[0xff, 0x00]);
+        }
+        key
+    }
+
+    #[test]
+    pub fn test_kv_crud_and_range() {
+        let mut db = Database::new_temp().unwrap();
+        let mut kv = db.kv("pairs").unwrap();
+        let mut expected = BTreeMap::new();
+        // inserted out of key order
+        let writes = (0..3000u32).map(|i| i * 7 % 3000).map(|i| {
+            let value = format!("value {i}").into_bytes();
+            expected.insert(key(i), value.clone());
+            KvWrite::Put { key: key(i), value }
+        });
+        kv.batch(writes.collect::<Vec<_>>()).unwrap();
+        for i in (0..3000).step_by(40) {
+            kv.put(&key(i), b"updated").unwrap();
+            expected.insert(key(i), b"updated".to_vec());
+        }
+        for i in (1..3000).step_by(100) {
+            assert!(kv.delete(&key(i)).unwrap());
+            expected.remove(&key(i));
+        }
+        assert!(!kv.delete(&key(1)).unwrap());
+        assert!(!kv.delete(b"missing").unwrap());
+
+        assert_eq!(kv.get(&key(80)).unwrap(), Some(b"updated".to_vec()));
+        assert_eq!(kv.get(&key(9)).unwrap(), Some(b"value 9".to_vec()));
+        assert_eq!(kv.get(&key(101)).unwrap(), None);
+        // a prefix of a key is another key
+        assert_eq!(kv.get(&3u32.to_be_bytes()).unwrap(), None);
+
+        let all = kv.range::<&[u8]>(..).unwrap();
+        assert_eq!(all, expected.clone().into_iter().collect::<Vec<_>>());
+        let (start, end) = (key(1000), key(1500));
+        assert_eq!(
+            kv.range(start.clone()..end.clone()).unwrap(),
+            expected
+                .range(start.clone()..end.clone())
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            kv.range(start.clone()..=end.clone())
+                .unwrap()
+                .last()
+                .unwrap()
+                .0,
+            end
+        );
+        assert!(kv.range(end..start).unwrap().is_empty());
+
+        // the same rows through SQL
+        drop(kv);
+        let count = db.run("select count(*) from pairs").unwrap();
+        assert_eq!(
+            count[0].data[0],
+            ScalarValue::Int64(Some(expected.len() as i64))
+        );
+        let rows = db
+            .run("select value from pairs where key = x'00000007'")
+            .unwrap();
+        assert_eq!(
+            rows[0].data[0],
+            ScalarValue::Bytea(Some(b"value 7".to_vec()))
+        );
+        db.run("insert into pairs values (x'ff', x'01')").unwrap();
+        db.run("delete from pairs where key = x'00000008'").unwrap();
+        let kv = db.kv("pairs").unwrap();
+        assert_eq!(kv.get(&[0xff]).unwrap(), Some(vec![1]));
+        assert_eq!(kv.get(&key(8)).unwrap(), None);
+        drop(kv);
+        assert!(matches!(
+            db.run("insert into pairs values (x'ff', x'02')"),
+            Err(BustubxError::Execution(_))
+        ));
+
+        db.run("create table t1 (a int)").unwrap();
+        assert!(matches!(db.kv("t1"), Err(BustubxError::Plan(_))));
+        assert!(matches!(db.kv("pairs; drop"), Err(BustubxError::Plan(_))));
+    }
+
+    #[test]
+    pub fn test_kv_batch_is_atomic() {
+        let mut db = Database::new_temp().unwrap();
+        let mut kv = db.kv("pairs").unwrap();
+        kv.put(b"a", b"1").unwrap();
+        kv.batch([
+            KvWrite::Put {
+                key: b"b".to_vec(),
+                value: b"2".to_vec(),
+            },
+            KvWrite::Delete { key: b"a".to_vec() },
+            KvWrite::Put {
+                key: b"b".to_vec(),
+                value: b"3".to_vec(),
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            kv.range::<&[u8]>(..).unwrap(),
+            vec![(b"b".to_vec(), b"3".to_vec())]
+        );
+
+        // a second unique index fails the last write, the others are undone
+        drop(kv);
+        db.run("create unique index pairs_value on pairs (value)")
+            .unwrap();
+        let mut kv = db.kv("pairs").unwrap();
+        let result = kv.batch([
+            KvWrite::Put {
+                key: b"c".to_vec(),
+                value: b"4".to_vec(),
+            },
+            KvWrite::Delete { key: b"b".to_vec() },
+            KvWrite::Put {
+                key: b"d".to_vec(),
+                value: b"4".to_vec(),
+            },
+        ]);
+        assert!(result.is_err());
+        assert_eq!(
+            kv.range::<&[u8]>(..).unwrap(),
+            vec![(b"b".to_vec(), b"3".to_vec())]
+        );
+        drop(kv);
+        assert!(db.transactions().is_empty());
+        assert_eq!(db.run("select key from pairs").unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_kv_puts_survive_crash() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let open = || Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        let mut db = open();
+        let mut kv = db.kv("pairs").unwrap();
+        for i in 0..500u32 {
+            kv.put(&i.to_be_bytes(), &(i * 2).to_be_bytes()).unwrap();
+        }
+        kv.delete(&7u32.to_be_bytes()).unwrap();
+        drop(kv);
+        // stopped without close
+        drop(db);
+
+        let mut db = open();
+        assert!(db.recovered_on_open());
+        let kv = db.kv("pairs").unwrap();
+        assert_eq!(
+            kv.get(&9u32.to_be_bytes()).unwrap(),
+            Some(18u32.to_be_bytes().to_vec())
+        );
+        assert_eq!(kv.get(&7u32.to_be_bytes()).unwrap(), None);
+        assert_eq!(kv.range::<&[u8]>(..).unwrap().len(), 499);
+    }
+}
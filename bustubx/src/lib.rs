@@ -1,18 +1,54 @@
+mod background;
 mod buffer;
+mod capture;
 mod catalog;
 mod common;
+mod config;
 mod database;
+#[cfg(feature = "debug-http")]
+mod debug_http;
 mod error;
 mod execution;
 mod expression;
 mod function;
+mod kv;
 mod optimizer;
 mod parser;
 mod planner;
+mod session;
+mod settings;
+mod stats;
 mod storage;
 mod transaction;
 
+pub use background::{TaskHealth, TaskId, TaskStatus};
+pub use buffer::{AccessType, BufferPoolManager, PageAccess, PageAccessKind};
+pub use capture::{
+    read_capture, CapturedStatement, ReplayReport, ReplayedStatement, DATABASE_SESSION_ID,
+};
+pub use catalog::{DataType, IndexSize, PageOwner, TableSize};
 pub use common::util::pretty_format_tuples;
-pub use database::Database;
+pub use common::{Clock, ClockRef, MockClock, ScalarValue, SystemClock};
+pub use config::{BufferPoolOptions, DatabaseOptions, DiskOptions, ExecutionOptions, SyncPolicy};
+pub use database::{Database, StatementOutcome};
+#[cfg(feature = "debug-http")]
+pub use debug_http::{
+    BufferPoolSnapshot, DebugServer, ErrorBody, FrameSnapshot, IndexSnapshot, IndexStats,
+    LockSnapshot, TableSnapshot,
+};
 pub use error::{BustubxError, BustubxResult};
-pub use storage::Tuple;
+pub use execution::{PlanTree, TriggerAction, TriggerContext, TriggerEvent, TriggerFn};
+pub use function::{signature, FunctionSignature, ScalarUdf};
+pub use kv::{KvStore, KvWrite};
+pub use planner::PlanCacheStats;
+pub use session::{Session, SharedDatabase, TransactionSession};
+pub use settings::{Setting, SettingScope, SettingType, SettingValue};
+pub use stats::RuntimeStats;
+pub use storage::{
+    BackupLabel, CompressionCodec, DiskManager, LogManager, LogRecord, LogSegment, LogShipper, Lsn,
+    RecoveryTarget, Tuple,
+};
+pub use transaction::{
+    LockManager, SequentialTransactionIds, TableLockMode, TransactionId, TransactionIdSource,
+    TransactionIdSourceRef, TransactionInfo, TransactionState,
+};
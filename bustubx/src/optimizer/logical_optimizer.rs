@@ -1,5 +1,6 @@
 use crate::error::BustubxResult;
 use crate::optimizer::rule::{EliminateLimit, MergeLimit, PushDownLimit};
+use crate::optimizer::{OptimizerTrace, TraceDecision, TraceReason};
 use crate::planner::logical_plan::LogicalPlan;
 use std::sync::Arc;
 
@@ -15,6 +16,15 @@ pub trait LogicalOptimizerRule {
     /// A human-readable name for this optimizer rule
     fn name(&self) -> &str;
 
+    /// The part of a plan the rule rewrites, e.g. `LIMIT above another LIMIT`, an
+    /// optimizer trace names it when the rule did not apply
+    fn pattern(&self) -> &str;
+
+    /// Decision the rule shows up as in an optimizer trace
+    fn decision(&self) -> TraceDecision {
+        TraceDecision::Rewrite
+    }
+
     /// How should the rule be applied by the optimizer
     ///
     /// If a rule use default None, it should traverse recursively plan inside itself
@@ -37,16 +47,11 @@ pub struct LogicalOptimizer {
 
 impl LogicalOptimizer {
     pub fn new() -> Self {
-        let rules: Vec<Arc<dyn LogicalOptimizerRule + Sync + Send>> = vec![
+        Self::with_rules(vec![
             Arc::new(EliminateLimit {}),
             Arc::new(MergeLimit {}),
             Arc::new(PushDownLimit {}),
-        ];
-
-        Self {
-            rules,
-            max_passes: 3,
-        }
+        ])
     }
 
     pub fn with_rules(rules: Vec<Arc<dyn LogicalOptimizerRule + Send + Sync>>) -> Self {
@@ -56,18 +61,51 @@ impl LogicalOptimizer {
         }
     }
 
+    /// Apply the rules for `max_passes` passes.
     pub fn optimize(&self, plan: &LogicalPlan) -> BustubxResult<LogicalPlan> {
+        self.apply_rules(plan, None)
+    }
+
+    /// [`Self::optimize`] recording in `trace` for every rule whether it rewrote the plan in
+    /// any pass.
+    pub fn optimize_traced(
+        &self,
+        plan: &LogicalPlan,
+        trace: &OptimizerTrace,
+    ) -> BustubxResult<LogicalPlan> {
+        self.apply_rules(plan, Some(trace))
+    }
+
+    fn apply_rules(
+        &self,
+        plan: &LogicalPlan,
+        trace: Option<&OptimizerTrace>,
+    ) -> BustubxResult<LogicalPlan> {
         let mut new_plan = plan.clone();
+        let mut applied = vec![false; self.rules.len()];
         let mut i = 0;
         while i < self.max_passes {
-            for rule in &self.rules {
+            for (rule, applied) in self.rules.iter().zip(applied.iter_mut()) {
                 if let Some(optimized_plan) = self.optimize_recursively(rule, &new_plan)? {
                     new_plan = optimized_plan;
+                    *applied = true;
                 }
             }
 
             i += 1;
         }
+        if let Some(trace) = trace {
+            for (rule, applied) in self.rules.iter().zip(applied) {
+                let reason = if applied {
+                    TraceReason::Applied
+                } else {
+                    TraceReason::NoMatch {
+                        pattern: rule.pattern().to_string(),
+                    }
+                };
+                trace.record(rule.decision(), rule.name(), applied, reason);
+            }
+        }
         Ok(new_plan)
     }
 
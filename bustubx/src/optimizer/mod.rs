@@ -1,4 +1,6 @@
 mod logical_optimizer;
 pub mod rule;
+mod trace;
 
 pub use logical_optimizer::{LogicalOptimizer, LogicalOptimizerRule};
+pub use trace::{OptimizerTrace, TraceDecision, TraceEntry, TraceReason};
@@ -35,6 +35,10 @@ impl LogicalOptimizerRule for EliminateLimit {
         "EliminateLimit"
     }
 
+    fn pattern(&self) -> &str {
+        "LIMIT 0 or LIMIT ALL without an offset"
+    }
+
     fn apply_order(&self) -> Option<ApplyOrder> {
         Some(ApplyOrder::BottomUp)
     }
@@ -38,6 +38,10 @@ impl LogicalOptimizerRule for MergeLimit {
         "MergeLimit"
     }
 
+    fn pattern(&self) -> &str {
+        "LIMIT above another LIMIT"
+    }
+
     fn apply_order(&self) -> Option<ApplyOrder> {
         Some(ApplyOrder::TopDown)
     }
@@ -1,6 +1,6 @@
 use crate::error::BustubxResult;
 use crate::optimizer::logical_optimizer::ApplyOrder;
-use crate::optimizer::LogicalOptimizerRule;
+use crate::optimizer::{LogicalOptimizerRule, TraceDecision};
 use crate::planner::logical_plan::{LogicalPlan, Sort};
 
 pub struct PushDownLimit;
@@ -40,6 +40,14 @@ impl LogicalOptimizerRule for PushDownLimit {
         "PushDownLimit"
     }
 
+    fn pattern(&self) -> &str {
+        "LIMIT above an ORDER BY"
+    }
+
+    fn decision(&self) -> TraceDecision {
+        TraceDecision::LimitPushdown
+    }
+
     fn apply_order(&self) -> Option<ApplyOrder> {
         Some(ApplyOrder::TopDown)
     }
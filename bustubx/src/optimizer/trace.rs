@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Kind of choice the optimizer or the physical planner made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceDecision {
+    // an index or a scan as the access path of a filtered table
+    IndexSelection,
+    // the selectivity estimate access paths are compared with
+    SelectivityEstimate,
+    LimitPushdown,
+    PredicatePushdown,
+    JoinReordering,
+    // any other rewrite rule of the logical optimizer
+    Rewrite,
+}
+
+impl Display for TraceDecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceDecision::IndexSelection => write!(f, "index selection"),
+            TraceDecision::SelectivityEstimate => write!(f, "selectivity estimate"),
+            TraceDecision::LimitPushdown => write!(f, "limit pushdown"),
+            TraceDecision::PredicatePushdown => write!(f, "predicate pushdown"),
+            TraceDecision::JoinReordering => write!(f, "join reordering"),
+            TraceDecision::Rewrite => write!(f, "rewrite"),
+        }
+    }
+}
+
+/// Why a rule or an access path applied or not, see [`TraceEntry`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TraceReason {
+    Applied,
+    // the index or scan was usable but the planner took `chosen`
+    OtherChosen {
+        chosen: String,
+    },
+    // no comparison of the leading key column with a constant, `compared` are the columns
+    // the predicate compares
+    LeadingColumnNotCompared {
+        leading: String,
+        compared: Vec<String>,
+    },
+    // ranges are only made for single column indexes
+    CompositeKey {
+        columns: Vec<String>,
+    },
+    // the constant does not convert to the key type without changing, e.g. `a < 1.5` on
+    // an int column
+    TypeMismatch {
+        column: String,
+        key_type: String,
+        value: String,
+    },
+    // a disjunct of an OR does not constrain the key column, its rows are anywhere
+    DisjunctNotCompared {
+        leading: String,
+    },
+    TooUnselective {
+        estimated: f64,
+        max: f64,
+    },
+    // queries as of an earlier transaction read the heap, indexes only know the latest rows
+    ReadsSnapshot,
+    // the partitions are pruned and scanned instead
+    PartitionedTable,
+    MissingStatistics {
+        table: String,
+    },
+    Estimated {
+        selectivity: f64,
+    },
+    // the rule found nothing it rewrites
+    NoMatch {
+        pattern: String,
+    },
+    // a predicate above another operator than a table scan stays where it is
+    NotAboveScan {
+        operator: String,
+    },
+    // the predicate is handed to the access path of the filtered table
+    IntoScan {
+        access_path: String,
+    },
+    // the access path reads every row, the predicate filters them above it
+    NoAccessPath {
+        access_path: String,
+    },
+    // joins run in the order of the FROM clause
+    JoinOrderAsWritten,
+}
+
+impl Display for TraceReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceReason::Applied => write!(f, "applied"),
+            TraceReason::OtherChosen { chosen } => write!(f, "{chosen} was chosen instead"),
+            TraceReason::LeadingColumnNotCompared { leading, compared } => {
+                if compared.is_empty() {
+                    write!(
+                        f,
+                        "leading column {leading} is not compared, the predicate compares no column with a constant"
+                    )
+                } else {
+                    write!(
+                        f,
+                        "leading column {leading} is not compared, the predicate compares {}",
+                        compared.join(", ")
+                    )
+                }
+            }
+            TraceReason::CompositeKey { columns } => write!(
+                f,
+                "key ({}) has several columns, ranges are only made for single column indexes",
+                columns.join(", ")
+            ),
+            TraceReason::TypeMismatch {
+                column,
+                key_type,
+                value,
+            } => write!(
+                f,
+                "{value} does not convert to the {key_type} key of {column} without a change, it needs a cast"
+            ),
+            TraceReason::DisjunctNotCompared { leading } => write!(
+                f,
+                "a disjunct of the OR does not compare leading column {leading}"
+            ),
+            TraceReason::TooUnselective { estimated, max } => write!(
+                f,
+                "estimated selectivity {estimated:.3} is above {max}, a sequential scan is cheaper"
+            ),
+            TraceReason::ReadsSnapshot => write!(
+                f,
+                "the query reads an earlier snapshot, indexes only hold the latest rows"
+            ),
+            TraceReason::PartitionedTable => {
+                write!(f, "the table is partitioned, its partitions are scanned")
+            }
+            TraceReason::MissingStatistics { table } => write!(
+                f,
+                "{table} has no statistics, selectivity is not estimated, run ANALYZE"
+            ),
+            TraceReason::Estimated { selectivity } => {
+                write!(f, "estimated selectivity {selectivity:.3}")
+            }
+            TraceReason::NoMatch { pattern } => write!(f, "the plan has no {pattern}"),
+            TraceReason::NotAboveScan { operator } => write!(
+                f,
+                "the predicate is above a {operator}, predicates are only pushed into table scans"
+            ),
+            TraceReason::IntoScan { access_path } => {
+                write!(f, "evaluated by the {access_path}")
+            }
+            TraceReason::NoAccessPath { access_path } => write!(
+                f,
+                "the {access_path} reads every row, the predicate filters them"
+            ),
+            TraceReason::JoinOrderAsWritten => {
+                write!(f, "joins are not reordered, they run in the order written")
+            }
+        }
+    }
+}
+
+/// One decision of an optimizer trace, `subject` names the index, rule, table or join
+/// it is about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceEntry {
+    pub decision: TraceDecision,
+    pub subject: String,
+    pub applied: bool,
+    pub reason: TraceReason,
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let verdict = if self.applied { "used" } else { "skipped" };
+        write!(f, "{}: {} {}", self.decision, self.subject, verdict)?;
+        if self.reason != TraceReason::Applied {
+            write!(f, ", {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decisions recorded while planning a statement with `optimizer_trace` on, in the order
+/// they were made, shown by EXPLAIN.
+#[derive(Debug, Default)]
+pub struct OptimizerTrace {
+    entries: RefCell<Vec<TraceEntry>>,
+}
+
+impl OptimizerTrace {
+    pub fn record(
+        &self,
+        decision: TraceDecision,
+        subject: impl Into<String>,
+        applied: bool,
+        reason: TraceReason,
+    ) {
+        self.entries.borrow_mut().push(TraceEntry {
+            decision,
+            subject: subject.into(),
+            applied,
+            reason,
+        });
+    }
+
+    pub fn into_entries(self) -> Vec<TraceEntry> {
+        self.entries.into_inner()
+    }
+}
@@ -1,11 +1,812 @@
 use crate::error::BustubxResult;
-use sqlparser::{ast::Statement, dialect::PostgreSqlDialect, parser::Parser};
+use crate::storage::index::ConstraintTiming;
+use sqlparser::{
+    ast::{Expr, Ident, ObjectName, OrderByExpr, Statement},
+    dialect::PostgreSqlDialect,
+    keywords::Keyword,
+    parser::{Parser, ParserError},
+    tokenizer::{Token, Tokenizer},
+};
+
+/// Maintenance statements the SQL parser has no grammar for, parsed by
+/// [`parse_maintenance_statement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaintenanceStatement {
+    // VACUUM [FULL] [table]
+    Vacuum {
+        full: bool,
+        table: Option<ObjectName>,
+    },
+    // ANALYZE [table]
+    Analyze {
+        table: Option<ObjectName>,
+    },
+    // CHECKPOINT
+    Checkpoint,
+    // REINDEX [INDEX] index | REINDEX TABLE table
+    Reindex {
+        target: ReindexTarget,
+    },
+    // RESET STATS
+    ResetStats,
+    // CHECK TABLE table [WITH INDEXES]
+    CheckTable {
+        table: ObjectName,
+        with_indexes: bool,
+    },
+    // CLUSTER table USING index
+    Cluster {
+        table: ObjectName,
+        index: Ident,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReindexTarget {
+    Index(ObjectName),
+    Table(ObjectName),
+}
+
+/// Range partitioning DDL the SQL parser has no grammar for, parsed by
+/// [`parse_partition_statement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionStatement {
+    // CREATE TABLE ... PARTITION BY RANGE (column)
+    CreatePartitioned {
+        create_table: Box<Statement>,
+        column: Ident,
+    },
+    // CREATE TABLE name PARTITION OF parent FOR VALUES FROM (from) TO (to)
+    CreatePartition {
+        name: ObjectName,
+        parent: ObjectName,
+        from: Box<Expr>,
+        to: Box<Expr>,
+    },
+}
+
+/// `DELETE` or `UPDATE` with `ORDER BY` or `LIMIT`, which the SQL parser has no grammar
+/// for, parsed by [`parse_ordered_dml`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedDml {
+    pub statement: Statement,
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<Expr>,
+}
+
+/// `ALTER TABLE name SET read_only` or `SET read_write`, which the SQL parser has no grammar
+/// for, parsed by [`parse_alter_table_access`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlterTableAccess {
+    pub table: ObjectName,
+    pub read_only: bool,
+}
+
+/// Query reading its tables `AS OF TRANSACTION txn_id`, which the SQL parser has no grammar
+/// for, parsed by [`parse_as_of`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsOfQuery {
+    // the query without its AS OF clauses
+    pub sql: String,
+    pub txn_id: u64,
+}
+
+/// `CREATE UNIQUE INDEX ... DEFERRABLE`, which the SQL parser has no grammar for, parsed by
+/// [`parse_deferrable_index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferrableIndex {
+    pub statement: Statement,
+    pub initially: ConstraintTiming,
+}
+
+/// Element of the parenthesized list of `CREATE TABLE`, numbered among the columns or among
+/// the table constraints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableElement {
+    Column(usize),
+    Constraint(usize),
+}
+
+/// `CREATE TABLE` with `DEFERRABLE` foreign keys, which the SQL parser has no grammar for,
+/// parsed by [`parse_deferrable_table`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferrableTable {
+    pub statement: Statement,
+    // the INITIALLY timing of the foreign keys of each element declared DEFERRABLE
+    pub foreign_keys: Vec<(TableElement, ConstraintTiming)>,
+}
 
 pub fn parse_sql(sql: &str) -> BustubxResult<Vec<Statement>> {
-    let stmts = Parser::parse_sql(&PostgreSqlDialect {}, sql)?;
+    let stmts = match unparenthesize_explain_options(sql) {
+        Some(sql) => Parser::parse_sql(&PostgreSqlDialect {}, &sql)?,
+        None => Parser::parse_sql(&PostgreSqlDialect {}, sql)?,
+    };
     Ok(stmts)
 }
 
+/// `VACUUM`, `ANALYZE`, `CHECKPOINT`, `REINDEX`, `RESET STATS`, `CHECK TABLE` or `CLUSTER`
+/// statement, None when `sql` starts with another statement.
+pub fn parse_maintenance_statement(sql: &str) -> BustubxResult<Option<MaintenanceStatement>> {
+    let mut parser = Parser::new(&PostgreSqlDialect {}).try_with_sql(sql)?;
+    let Token::Word(word) = parser.next_token().token else {
+        return Ok(None);
+    };
+    let statement = match word.value.to_ascii_uppercase().as_str() {
+        "VACUUM" => {
+            let full = parser.parse_keyword(Keyword::FULL);
+            MaintenanceStatement::Vacuum {
+                full,
+                table: parse_optional_table(&mut parser)?,
+            }
+        }
+        "ANALYZE" => MaintenanceStatement::Analyze {
+            table: parse_optional_table(&mut parser)?,
+        },
+        "CHECKPOINT" => MaintenanceStatement::Checkpoint,
+        "REINDEX" => {
+            let target = if parser.parse_keyword(Keyword::TABLE) {
+                ReindexTarget::Table(parser.parse_object_name()?)
+            } else {
+                // the INDEX keyword is optional
+                let _ = parser.parse_keyword(Keyword::INDEX);
+                ReindexTarget::Index(parser.parse_object_name()?)
+            };
+            MaintenanceStatement::Reindex { target }
+        }
+        "RESET" => {
+            let found = parser.next_token();
+            match &found.token {
+                Token::Word(word) if word.value.eq_ignore_ascii_case("stats") => {}
+                _ => return Ok(parser.expected("STATS", found)?),
+            }
+            MaintenanceStatement::ResetStats
+        }
+        "CLUSTER" => {
+            let table = parser.parse_object_name()?;
+            parser.expect_keyword(Keyword::USING)?;
+            MaintenanceStatement::Cluster {
+                table,
+                index: parser.parse_identifier()?,
+            }
+        }
+        "CHECK" => {
+            parser.expect_keyword(Keyword::TABLE)?;
+            let table = parser.parse_object_name()?;
+            let with_indexes = parser.parse_keyword(Keyword::WITH);
+            if with_indexes {
+                let found = parser.next_token();
+                match &found.token {
+                    Token::Word(word) if word.value.eq_ignore_ascii_case("indexes") => {}
+                    _ => return Ok(parser.expected("INDEXES", found)?),
+                }
+            }
+            MaintenanceStatement::CheckTable {
+                table,
+                with_indexes,
+            }
+        }
+        _ => return Ok(None),
+    };
+    // the trailing semicolon is optional
+    let _ = parser.consume_token(&Token::SemiColon);
+    let found = parser.peek_token();
+    if found.token != Token::EOF {
+        return Ok(parser.expected("end of statement", found)?);
+    }
+    Ok(Some(statement))
+}
+
+/// `CREATE TABLE` of a table partitioned by range or of one of its partitions, None when
+/// `sql` is another statement.
+pub fn parse_partition_statement(sql: &str) -> BustubxResult<Option<PartitionStatement>> {
+    let is_create = sql
+        .trim_start()
+        .get(..6)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("create"));
+    if !is_create {
+        return Ok(None);
+    }
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(ParserError::from)?;
+    tokens.retain(|token| !matches!(token, Token::Whitespace(_)));
+    let is_keyword = |token: Option<&Token>, keyword: Keyword| matches!(token, Some(Token::Word(word)) if word.keyword == keyword);
+    if !is_keyword(tokens.first(), Keyword::CREATE) || !is_keyword(tokens.get(1), Keyword::TABLE) {
+        return Ok(None);
+    }
+    // the first PARTITION outside the column list
+    let mut depth = 0;
+    let mut partition_at = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::Word(word) if depth == 0 && word.keyword == Keyword::PARTITION => {
+                partition_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(partition_at) = partition_at else {
+        return Ok(None);
+    };
+
+    let statement = if is_keyword(tokens.get(partition_at + 1), Keyword::OF) {
+        let mut parser = Parser::new(&dialect).with_tokens(tokens);
+        parser.expect_keywords(&[Keyword::CREATE, Keyword::TABLE])?;
+        let name = parser.parse_object_name()?;
+        parser.expect_keywords(&[Keyword::PARTITION, Keyword::OF])?;
+        let parent = parser.parse_object_name()?;
+        parser.expect_keywords(&[Keyword::FOR, Keyword::VALUES, Keyword::FROM])?;
+        parser.expect_token(&Token::LParen)?;
+        let from = parser.parse_expr()?;
+        parser.expect_token(&Token::RParen)?;
+        parser.expect_keyword(Keyword::TO)?;
+        parser.expect_token(&Token::LParen)?;
+        let to = parser.parse_expr()?;
+        parser.expect_token(&Token::RParen)?;
+        expect_end_of_statement(&mut parser)?;
+        PartitionStatement::CreatePartition {
+            name,
+            parent,
+            from: Box::new(from),
+            to: Box::new(to),
+        }
+    } else {
+        let clause = tokens.split_off(partition_at);
+        let create_table = Parser::new(&dialect)
+            .with_tokens(tokens)
+            .parse_statement()?;
+        let mut parser = Parser::new(&dialect).with_tokens(clause);
+        parser.expect_keywords(&[Keyword::PARTITION, Keyword::BY, Keyword::RANGE])?;
+        parser.expect_token(&Token::LParen)?;
+        let column = parser.parse_identifier()?;
+        parser.expect_token(&Token::RParen)?;
+        expect_end_of_statement(&mut parser)?;
+        PartitionStatement::CreatePartitioned {
+            create_table: Box::new(create_table),
+            column,
+        }
+    };
+    Ok(Some(statement))
+}
+
+/// `DELETE` or `UPDATE` ending in `ORDER BY` or `LIMIT` clauses, None when `sql` is
+/// another statement or has neither clause.
+pub fn parse_ordered_dml(sql: &str) -> BustubxResult<Option<OrderedDml>> {
+    let is_dml = sql.trim_start().get(..6).is_some_and(|keyword| {
+        keyword.eq_ignore_ascii_case("delete") || keyword.eq_ignore_ascii_case("update")
+    });
+    if !is_dml {
+        return Ok(None);
+    }
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(ParserError::from)?;
+    tokens.retain(|token| !matches!(token, Token::Whitespace(_)));
+    // the first ORDER or LIMIT outside of parentheses, subqueries keep theirs
+    let mut depth = 0;
+    let mut clause_at = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::Word(word)
+                if depth == 0 && matches!(word.keyword, Keyword::ORDER | Keyword::LIMIT) =>
+            {
+                clause_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(clause_at) = clause_at else {
+        return Ok(None);
+    };
+
+    let clause = tokens.split_off(clause_at);
+    let statement = Parser::new(&dialect)
+        .with_tokens(tokens)
+        .parse_statement()?;
+    if !matches!(
+        statement,
+        Statement::Delete { .. } | Statement::Update { .. }
+    ) {
+        return Ok(None);
+    }
+    let mut parser = Parser::new(&dialect).with_tokens(clause);
+    let order_by = if parser.parse_keywords(&[Keyword::ORDER, Keyword::BY]) {
+        parser.parse_comma_separated(Parser::parse_order_by_expr)?
+    } else {
+        vec![]
+    };
+    let limit = if parser.parse_keyword(Keyword::LIMIT) {
+        Some(parser.parse_expr()?)
+    } else {
+        None
+    };
+    expect_end_of_statement(&mut parser)?;
+    Ok(Some(OrderedDml {
+        statement,
+        order_by,
+        limit,
+    }))
+}
+
+/// `ALTER TABLE` setting a table read only or writable again, None when `sql` is another
+/// statement, other `ALTER TABLE` forms included.
+pub fn parse_alter_table_access(sql: &str) -> BustubxResult<Option<AlterTableAccess>> {
+    let is_alter = sql
+        .trim_start()
+        .get(..5)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("alter"));
+    if !is_alter {
+        return Ok(None);
+    }
+    let mut parser = Parser::new(&PostgreSqlDialect {}).try_with_sql(sql)?;
+    if !parser.parse_keywords(&[Keyword::ALTER, Keyword::TABLE]) {
+        return Ok(None);
+    }
+    // left to the SQL parser to report
+    let Ok(table) = parser.parse_object_name() else {
+        return Ok(None);
+    };
+    if !parser.parse_keyword(Keyword::SET) {
+        return Ok(None);
+    }
+    let found = parser.next_token();
+    let read_only = match &found.token {
+        Token::Word(word) if word.value.eq_ignore_ascii_case("read_only") => true,
+        Token::Word(word) if word.value.eq_ignore_ascii_case("read_write") => false,
+        _ => return Ok(parser.expected("read_only or read_write", found)?),
+    };
+    expect_end_of_statement(&mut parser)?;
+    Ok(Some(AlterTableAccess { table, read_only }))
+}
+
+/// Query with `AS OF TRANSACTION txn_id` after a table, None when `sql` is another
+/// statement or has no such clause. The query reads every table as of the transaction,
+/// clauses naming different transactions are refused.
+pub fn parse_as_of(sql: &str) -> BustubxResult<Option<AsOfQuery>> {
+    let is_query = sql.trim_start().get(..4).is_some_and(|keyword| {
+        keyword.eq_ignore_ascii_case("sele") || keyword.eq_ignore_ascii_case("with")
+    });
+    if !is_query {
+        return Ok(None);
+    }
+    let dialect = PostgreSqlDialect {};
+    let tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(ParserError::from)?;
+    let significant = (0..tokens.len())
+        .filter(|i| !matches!(tokens[*i], Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+    let is_keyword = |i: usize, keyword: Keyword| matches!(&tokens[i], Token::Word(word) if word.keyword == keyword);
+    let mut txn_id = None;
+    let mut removed = vec![false; tokens.len()];
+    for (i, clause) in significant.windows(4).enumerate() {
+        if !is_keyword(clause[0], Keyword::AS)
+            || !is_keyword(clause[1], Keyword::OF)
+            || !is_keyword(clause[2], Keyword::TRANSACTION)
+        {
+            continue;
+        }
+        let id = match &tokens[clause[3]] {
+            Token::Number(number, _) => number.parse::<u64>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            ParserError::ParserError(format!(
+                "Expected a transaction id, found: {}",
+                tokens[clause[3]]
+            ))
+        })?;
+        if txn_id.is_some_and(|txn_id| txn_id != id) {
+            return Err(ParserError::ParserError(format!(
+                "AS OF clauses of one query name transactions {} and {}",
+                txn_id.unwrap_or_default(),
+                id
+            ))
+            .into());
+        }
+        txn_id = Some(id);
+        // with the whitespace before the clause
+        let start = i.checked_sub(1).map_or(clause[0], |i| significant[i] + 1);
+        removed[start..=clause[3]].fill(true);
+    }
+    let Some(txn_id) = txn_id else {
+        return Ok(None);
+    };
+    let sql = tokens
+        .iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(token, _)| token.to_string())
+        .collect();
+    Ok(Some(AsOfQuery { sql, txn_id }))
+}
+
+/// `CREATE INDEX` ending in `DEFERRABLE [INITIALLY DEFERRED | INITIALLY IMMEDIATE]`, None
+/// when `sql` is another statement or has no such clause. Without INITIALLY the keys are
+/// checked at the end of every statement until `SET CONSTRAINTS ALL DEFERRED`.
+pub fn parse_deferrable_index(sql: &str) -> BustubxResult<Option<DeferrableIndex>> {
+    let is_create = sql
+        .trim_start()
+        .get(..6)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("create"));
+    if !is_create {
+        return Ok(None);
+    }
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(ParserError::from)?;
+    tokens.retain(|token| !matches!(token, Token::Whitespace(_)));
+    // DEFERRABLE after the parenthesized key columns
+    let mut depth = 0;
+    let mut clause_at = None;
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            token if depth == 0 && is_word(token, "deferrable") => {
+                clause_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(clause_at) = clause_at else {
+        return Ok(None);
+    };
+
+    let clause = tokens.split_off(clause_at + 1);
+    let statement = Parser::new(&dialect)
+        .with_tokens(tokens[..clause_at].to_vec())
+        .parse_statement()?;
+    if !matches!(statement, Statement::CreateIndex { .. }) {
+        return Ok(None);
+    }
+    let mut parser = Parser::new(&dialect).with_tokens(clause);
+    let initially = if is_word(&parser.peek_token().token, "initially") {
+        parser.next_token();
+        parse_constraint_timing(&mut parser)?
+    } else {
+        ConstraintTiming::Immediate
+    };
+    expect_end_of_statement(&mut parser)?;
+    Ok(Some(DeferrableIndex {
+        statement,
+        initially,
+    }))
+}
+
+/// `CREATE TABLE` whose columns or table constraints end their `REFERENCES` clause with
+/// `DEFERRABLE [INITIALLY DEFERRED | INITIALLY IMMEDIATE]`, None when `sql` is another
+/// statement or has no such clause.
+pub fn parse_deferrable_table(sql: &str) -> BustubxResult<Option<DeferrableTable>> {
+    let is_create = sql
+        .trim_start()
+        .get(..6)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("create"));
+    if !is_create {
+        return Ok(None);
+    }
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize()
+        .map_err(ParserError::from)?;
+    tokens.retain(|token| !matches!(token, Token::Whitespace(_)));
+    let Some(list_start) = tokens.iter().position(|token| token == &Token::LParen) else {
+        return Ok(None);
+    };
+
+    // the elements are classified like the SQL parser does, by their first word
+    let is_constraint = |token: &Token| {
+        ["constraint", "primary", "unique", "foreign", "check"]
+            .iter()
+            .any(|word| is_word(token, word))
+    };
+    let mut columns = 0;
+    let mut constraints = 0;
+    let mut element = None;
+    let mut references = false;
+    let mut removed = vec![false; tokens.len()];
+    let mut foreign_keys = vec![];
+    let mut depth = 0;
+    let mut i = list_start;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if element.is_none() && depth == 1 {
+            element = Some(if is_constraint(token) {
+                constraints += 1;
+                TableElement::Constraint(constraints - 1)
+            } else {
+                columns += 1;
+                TableElement::Column(columns - 1)
+            });
+            references = false;
+        }
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen if depth == 1 => break,
+            Token::RParen => depth -= 1,
+            Token::Comma if depth == 1 => element = None,
+            Token::Word(word) if depth == 1 && word.keyword == Keyword::REFERENCES => {
+                references = true
+            }
+            token if depth == 1 && is_word(token, "deferrable") => {
+                let Some(element) = element.filter(|_| references) else {
+                    return Err(ParserError::ParserError(
+                        "DEFERRABLE only applies to a foreign key".to_string(),
+                    )
+                    .into());
+                };
+                let mut end = i;
+                let initially = if tokens.get(i + 1).is_some_and(|t| is_word(t, "initially")) {
+                    end = i + 2;
+                    match tokens.get(end) {
+                        Some(t) if is_word(t, "deferred") => ConstraintTiming::Deferred,
+                        Some(t) if is_word(t, "immediate") => ConstraintTiming::Immediate,
+                        found => {
+                            return Err(ParserError::ParserError(format!(
+                                "Expected DEFERRED or IMMEDIATE, found: {}",
+                                found.map_or("EOF".to_string(), |t| t.to_string())
+                            ))
+                            .into())
+                        }
+                    }
+                } else {
+                    ConstraintTiming::Immediate
+                };
+                removed[i..=end].fill(true);
+                foreign_keys.push((element, initially));
+                i = end;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if foreign_keys.is_empty() {
+        return Ok(None);
+    }
+
+    let tokens = tokens
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(token, _)| token)
+        .collect();
+    let statement = Parser::new(&dialect)
+        .with_tokens(tokens)
+        .parse_statement()?;
+    if !matches!(statement, Statement::CreateTable { .. }) {
+        return Ok(None);
+    }
+    Ok(Some(DeferrableTable {
+        statement,
+        foreign_keys,
+    }))
+}
+
+/// `SET CONSTRAINTS ALL DEFERRED` or `SET CONSTRAINTS ALL IMMEDIATE`, None when `sql` is
+/// another statement. Constraints are not named, the timing applies to every deferrable
+/// unique index.
+pub fn parse_set_constraints(sql: &str) -> BustubxResult<Option<ConstraintTiming>> {
+    let is_set = sql
+        .trim_start()
+        .get(..3)
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("set"));
+    if !is_set {
+        return Ok(None);
+    }
+    let mut parser = Parser::new(&PostgreSqlDialect {}).try_with_sql(sql)?;
+    parser.expect_keyword(Keyword::SET)?;
+    if !is_word(&parser.peek_token().token, "constraints") {
+        return Ok(None);
+    }
+    parser.next_token();
+    parser.expect_keyword(Keyword::ALL)?;
+    let timing = parse_constraint_timing(&mut parser)?;
+    expect_end_of_statement(&mut parser)?;
+    Ok(Some(timing))
+}
+
+fn parse_constraint_timing(parser: &mut Parser) -> Result<ConstraintTiming, ParserError> {
+    let found = parser.next_token();
+    match &found.token {
+        token if is_word(token, "deferred") => Ok(ConstraintTiming::Deferred),
+        token if is_word(token, "immediate") => Ok(ConstraintTiming::Immediate),
+        _ => parser.expected("DEFERRED or IMMEDIATE", found),
+    }
+}
+
+// an unquoted word the SQL parser has no keyword for
+fn is_word(token: &Token, value: &str) -> bool {
+    match token {
+        Token::Word(word) => word.quote_style.is_none() && word.value.eq_ignore_ascii_case(value),
+        _ => false,
+    }
+}
+
+fn expect_end_of_statement(parser: &mut Parser) -> Result<(), ParserError> {
+    let _ = parser.consume_token(&Token::SemiColon);
+    let found = parser.peek_token();
+    if found.token != Token::EOF {
+        return parser.expected("end of statement", found);
+    }
+    Ok(())
+}
+
+fn parse_optional_table(parser: &mut Parser) -> Result<Option<ObjectName>, ParserError> {
+    match parser.peek_token().token {
+        Token::EOF | Token::SemiColon => Ok(None),
+        _ => parser.parse_object_name().map(Some),
+    }
+}
+
+pub fn parse_expr(sql: &str) -> BustubxResult<Expr> {
+    let expr = Parser::new(&PostgreSqlDialect {})
+        .try_with_sql(sql)?
+        .parse_expr()?;
+    Ok(expr)
+}
+
+/// Split a script on the semicolons ending its statements, skipping semicolons inside
+/// quoted strings, quoted identifiers and comments. Returns every statement with its
+/// byte offset into `sql`, statements holding only whitespace and comments are left out.
+pub fn split_statements(sql: &str) -> Vec<(usize, &str)> {
+    let bytes = sql.as_bytes();
+    let mut statements = vec![];
+    let mut start = 0;
+    // whether the current statement has anything besides whitespace and comments
+    let mut has_content = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"') => {
+                // a doubled quote inside is an escaped quote, scanning on handles it
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                has_content = true;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                // block comments nest like in postgres
+                let mut depth = 0;
+                while i < bytes.len() {
+                    if bytes[i..].starts_with(b"/*") {
+                        depth += 1;
+                        i += 2;
+                    } else if bytes[i..].starts_with(b"*/") {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                continue;
+            }
+            b';' => {
+                if has_content {
+                    statements.push((start, &sql[start..i]));
+                }
+                start = i + 1;
+                has_content = false;
+            }
+            b if b.is_ascii_whitespace() => {}
+            _ => has_content = true,
+        }
+        i += 1;
+    }
+    if has_content {
+        statements.push((start, &sql[start..]));
+    }
+    statements
+}
+
+/// Line and column, both counted from 1, of the token `sql` failed to parse at with
+/// `error`, `None` when the position is unknown.
+pub fn parse_error_location(sql: &str, error: &ParserError) -> Option<(usize, usize)> {
+    let dialect = PostgreSqlDialect {};
+    let tokens = match Tokenizer::new(&dialect, sql).tokenize_with_location() {
+        Ok(tokens) => tokens,
+        Err(e) => return Some((e.line as usize, e.col as usize)),
+    };
+    let mut parser = Parser::new(&dialect).with_tokens_with_locations(tokens);
+    if parser.parse_statements().is_ok() {
+        return None;
+    }
+    // the parser stops on the token it rejected or right after it, the error message
+    // names the rejected token
+    let message = error.to_string();
+    let current = parser.peek_token();
+    parser.prev_token();
+    let previous = parser.peek_token();
+    let rejected = [current.clone(), previous]
+        .into_iter()
+        .find(|token| message.contains(&format!("found: {}", token.token)))
+        .unwrap_or(current);
+    // tokens made up by the parser, e.g. the end of input, have no location
+    if rejected.location.line == 0 {
+        return None;
+    }
+    Some((
+        rejected.location.line as usize,
+        rejected.location.column as usize,
+    ))
+}
+
+/// `EXPLAIN (TRACE) ...` without the TRACE option, which the SQL parser has no grammar for,
+/// None when `sql` has no such option. The other parenthesized options are kept.
+pub fn parse_explain_trace(sql: &str) -> Option<String> {
+    let sql = sql.trim_start();
+    if !sql.get(..7)?.eq_ignore_ascii_case("explain") {
+        return None;
+    }
+    let (options, statement) = sql[7..].trim_start().strip_prefix('(')?.split_once(')')?;
+    let options = options
+        .split(',')
+        .map(|option| option.trim())
+        .collect::<Vec<_>>();
+    let kept = options
+        .iter()
+        .filter(|option| !option.eq_ignore_ascii_case("trace"))
+        .copied()
+        .collect::<Vec<_>>();
+    if kept.len() == options.len() {
+        return None;
+    }
+    if kept.is_empty() {
+        return Some(format!("EXPLAIN {}", statement.trim_start()));
+    }
+    Some(format!(
+        "EXPLAIN ({}) {}",
+        kept.join(", "),
+        statement.trim_start()
+    ))
+}
+
+// in the order the parser expects them
+const EXPLAIN_OPTIONS: [&str; 3] = ["ANALYZE", "VERBOSE", "FORMAT"];
+
+// Rewrite `EXPLAIN (ANALYZE, FORMAT JSON) ...` into `EXPLAIN ANALYZE FORMAT JSON ...`,
+// the parser only accepts the unparenthesized options.
+fn unparenthesize_explain_options(sql: &str) -> Option<String> {
+    let sql = sql.trim_start();
+    if !sql.get(..7)?.eq_ignore_ascii_case("explain") {
+        return None;
+    }
+    let (options, statement) = sql[7..].trim_start().strip_prefix('(')?.split_once(')')?;
+    let mut options = options
+        .split(',')
+        .map(|option| option.trim())
+        .collect::<Vec<_>>();
+    let position = |option: &str| {
+        let keyword = option.split_whitespace().next()?;
+        EXPLAIN_OPTIONS
+            .iter()
+            .position(|k| k.eq_ignore_ascii_case(keyword))
+    };
+    if !options.iter().all(|option| position(option).is_some()) {
+        return None;
+    }
+    options.sort_by_key(|option| position(option));
+    Some(format!("EXPLAIN {} {}", options.join(" "), statement))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -15,4 +816,329 @@ mod tests {
         let stmts = super::parse_sql(sql).unwrap();
         println!("{:#?}", stmts[0]);
     }
+
+    #[test]
+    pub fn test_split_statements() {
+        let sql = "select 1; insert into t1 values ('a;b', \"c;d\");\n\
+                   -- comment; still a comment\n\
+                   select /* nested /* comment; */ ; */ 2;;  \n-- trailing comment";
+        let statements = super::split_statements(sql);
+        assert_eq!(
+            statements.iter().map(|(_, s)| s.trim()).collect::<Vec<_>>(),
+            vec![
+                "select 1",
+                "insert into t1 values ('a;b', \"c;d\")",
+                "-- comment; still a comment\nselect /* nested /* comment; */ ; */ 2",
+            ]
+        );
+        for (offset, statement) in statements {
+            assert_eq!(&sql[offset..offset + statement.len()], statement);
+        }
+        assert!(super::split_statements(" ; -- nothing\n").is_empty());
+    }
+
+    #[test]
+    pub fn test_parse_maintenance_statement() {
+        use super::{parse_maintenance_statement, MaintenanceStatement, ReindexTarget};
+        use sqlparser::ast::{Ident, ObjectName};
+
+        let table = |parts: &[&str]| {
+            Some(ObjectName(
+                parts.iter().map(|part| Ident::new(*part)).collect(),
+            ))
+        };
+        for (sql, expected) in [
+            (
+                "vacuum",
+                MaintenanceStatement::Vacuum {
+                    full: false,
+                    table: None,
+                },
+            ),
+            (
+                "VACUUM FULL public.t1;",
+                MaintenanceStatement::Vacuum {
+                    full: true,
+                    table: table(&["public", "t1"]),
+                },
+            ),
+            (
+                "  vacuum t1",
+                MaintenanceStatement::Vacuum {
+                    full: false,
+                    table: table(&["t1"]),
+                },
+            ),
+            ("analyze", MaintenanceStatement::Analyze { table: None }),
+            (
+                "Analyze t1",
+                MaintenanceStatement::Analyze {
+                    table: table(&["t1"]),
+                },
+            ),
+            ("checkpoint", MaintenanceStatement::Checkpoint),
+            (
+                "reindex idx_a",
+                MaintenanceStatement::Reindex {
+                    target: ReindexTarget::Index(table(&["idx_a"]).unwrap()),
+                },
+            ),
+            (
+                "REINDEX INDEX idx_a;",
+                MaintenanceStatement::Reindex {
+                    target: ReindexTarget::Index(table(&["idx_a"]).unwrap()),
+                },
+            ),
+            (
+                "reindex table public.t1",
+                MaintenanceStatement::Reindex {
+                    target: ReindexTarget::Table(table(&["public", "t1"]).unwrap()),
+                },
+            ),
+            ("reset stats;", MaintenanceStatement::ResetStats),
+            (
+                "check table t1",
+                MaintenanceStatement::CheckTable {
+                    table: table(&["t1"]).unwrap(),
+                    with_indexes: false,
+                },
+            ),
+            (
+                "CHECK TABLE public.t1 WITH INDEXES;",
+                MaintenanceStatement::CheckTable {
+                    table: table(&["public", "t1"]).unwrap(),
+                    with_indexes: true,
+                },
+            ),
+            (
+                "CLUSTER public.t1 USING idx_a;",
+                MaintenanceStatement::Cluster {
+                    table: table(&["public", "t1"]).unwrap(),
+                    index: Ident::new("idx_a"),
+                },
+            ),
+        ] {
+            assert_eq!(
+                parse_maintenance_statement(sql).unwrap(),
+                Some(expected),
+                "{sql}"
+            );
+        }
+        assert_eq!(parse_maintenance_statement("select 1").unwrap(), None);
+        assert_eq!(
+            parse_maintenance_statement("explain analyze select 1").unwrap(),
+            None
+        );
+        assert!(parse_maintenance_statement("vacuum t1 t2").is_err());
+        assert!(parse_maintenance_statement("checkpoint now").is_err());
+        assert!(parse_maintenance_statement("reindex").is_err());
+        assert!(parse_maintenance_statement("reindex table").is_err());
+        assert!(parse_maintenance_statement("reset all").is_err());
+        assert!(parse_maintenance_statement("check t1").is_err());
+        assert!(parse_maintenance_statement("check table t1 with stats").is_err());
+        assert!(parse_maintenance_statement("cluster t1").is_err());
+        assert!(parse_maintenance_statement("cluster t1 using").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_alter_table_access() {
+        use super::{parse_alter_table_access, AlterTableAccess};
+        use sqlparser::ast::{Ident, ObjectName};
+
+        assert_eq!(
+            parse_alter_table_access("ALTER TABLE public.t1 SET READ_ONLY;").unwrap(),
+            Some(AlterTableAccess {
+                table: ObjectName(vec![Ident::new("public"), Ident::new("t1")]),
+                read_only: true,
+            })
+        );
+        assert_eq!(
+            parse_alter_table_access("alter table t1 set read_write").unwrap(),
+            Some(AlterTableAccess {
+                table: ObjectName(vec![Ident::new("t1")]),
+                read_only: false,
+            })
+        );
+        // left to the SQL parser
+        assert_eq!(
+            parse_alter_table_access("alter table t1 drop column a").unwrap(),
+            None
+        );
+        assert_eq!(parse_alter_table_access("select 1").unwrap(), None);
+        assert!(parse_alter_table_access("alter table t1 set logged").is_err());
+        assert!(parse_alter_table_access("alter table t1 set read_only now").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_ordered_dml() {
+        use super::{parse_ordered_dml, parse_sql};
+
+        let parsed = parse_ordered_dml(
+            "delete from t1 where id in (select id from t2 order by id limit 3) \
+             order by ts, id desc limit 100;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            parsed.statement,
+            parse_sql("delete from t1 where id in (select id from t2 order by id limit 3)")
+                .unwrap()
+                .remove(0)
+        );
+        assert_eq!(
+            parsed
+                .order_by
+                .iter()
+                .map(|order| order.to_string())
+                .collect::<Vec<_>>(),
+            vec!["ts", "id DESC"]
+        );
+        assert_eq!(parsed.limit.unwrap().to_string(), "100");
+
+        let parsed = parse_ordered_dml("UPDATE t1 SET a = a + 1 LIMIT 5")
+            .unwrap()
+            .unwrap();
+        assert!(parsed.order_by.is_empty());
+        assert_eq!(parsed.limit.unwrap().to_string(), "5");
+
+        assert_eq!(
+            parse_ordered_dml("delete from t1 where a = 1").unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_ordered_dml("select * from t1 order by a limit 1").unwrap(),
+            None
+        );
+        assert!(parse_ordered_dml("delete from t1 order by").is_err());
+        assert!(parse_ordered_dml("delete from t1 limit 1 order by a").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_deferrable_index() {
+        use super::{parse_deferrable_index, parse_set_constraints, parse_sql};
+        use crate::storage::index::ConstraintTiming;
+
+        let parsed = parse_deferrable_index(
+            "create unique index idx_k on t (k) deferrable initially deferred;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            parsed.statement,
+            parse_sql("create unique index idx_k on t (k)")
+                .unwrap()
+                .remove(0)
+        );
+        assert_eq!(parsed.initially, ConstraintTiming::Deferred);
+        let parsed = parse_deferrable_index("CREATE UNIQUE INDEX idx_k ON t (k) DEFERRABLE")
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.initially, ConstraintTiming::Immediate);
+
+        assert_eq!(
+            parse_deferrable_index("create unique index idx_k on t (k)").unwrap(),
+            None
+        );
+        assert_eq!(parse_deferrable_index("select 1").unwrap(), None);
+        assert!(parse_deferrable_index("create index idx_k on t (k) deferrable later").is_err());
+
+        assert_eq!(
+            parse_set_constraints("SET CONSTRAINTS ALL DEFERRED;").unwrap(),
+            Some(ConstraintTiming::Deferred)
+        );
+        assert_eq!(
+            parse_set_constraints("set constraints all immediate").unwrap(),
+            Some(ConstraintTiming::Immediate)
+        );
+        assert_eq!(parse_set_constraints("set work_mem = 1").unwrap(), None);
+        assert!(parse_set_constraints("set constraints idx_k deferred").is_err());
+        assert!(parse_set_constraints("set constraints all").is_err());
+    }
+
+    #[test]
+    pub fn test_parse_deferrable_table() {
+        use super::{parse_deferrable_table, parse_sql, TableElement};
+        use crate::storage::index::ConstraintTiming;
+
+        let parsed = parse_deferrable_table(
+            "create table c (id int, p1 int references p (id) deferrable initially deferred, \
+             p2 varchar(10), unique (id), \
+             foreign key (p2) references p (name) deferrable);",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            parsed.statement,
+            parse_sql(
+                "create table c (id int, p1 int references p (id), p2 varchar(10), \
+                 unique (id), foreign key (p2) references p (name))"
+            )
+            .unwrap()
+            .remove(0)
+        );
+        assert_eq!(
+            parsed.foreign_keys,
+            vec![
+                (TableElement::Column(1), ConstraintTiming::Deferred),
+                (TableElement::Constraint(1), ConstraintTiming::Immediate),
+            ]
+        );
+
+        assert_eq!(
+            parse_deferrable_table("create table c (id int references p (id))").unwrap(),
+            None
+        );
+        assert_eq!(parse_deferrable_table("select 1").unwrap(), None);
+        assert!(parse_deferrable_table("create table c (id int deferrable)").is_err());
+        assert!(parse_deferrable_table(
+            "create table c (id int references p (id) deferrable initially later)"
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_parse_as_of() {
+        use super::parse_as_of;
+
+        let as_of = parse_as_of("select a from t as of transaction 7 where a > 1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(as_of.sql, "select a from t where a > 1");
+        assert_eq!(as_of.txn_id, 7);
+        let as_of = parse_as_of(
+            "SELECT * FROM t AS OF TRANSACTION 3 x JOIN u AS OF TRANSACTION 3 ON x.a = u.a",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(as_of.sql, "SELECT * FROM t x JOIN u ON x.a = u.a");
+
+        assert_eq!(parse_as_of("select a from t as t1").unwrap(), None);
+        assert_eq!(parse_as_of("delete from t").unwrap(), None);
+        assert!(parse_as_of("select * from t as of transaction x").is_err());
+        assert!(parse_as_of("select * from t as of transaction 1, u as of transaction 2").is_err());
+    }
+
+    #[test]
+    pub fn test_explain_parenthesized_options() {
+        assert_eq!(
+            super::parse_sql("explain (format json, analyze) select a from t1").unwrap(),
+            super::parse_sql("explain analyze format json select a from t1").unwrap()
+        );
+        assert_eq!(
+            super::unparenthesize_explain_options("explain (select 1) union (select 2)"),
+            None
+        );
+        assert_eq!(
+            super::parse_explain_trace("explain (trace) select a from t1").as_deref(),
+            Some("EXPLAIN select a from t1")
+        );
+        assert_eq!(
+            super::parse_explain_trace("EXPLAIN (format json, TRACE) select a from t1").as_deref(),
+            Some("EXPLAIN (format json) select a from t1")
+        );
+        assert_eq!(
+            super::parse_explain_trace("explain (analyze) select 1"),
+            None
+        );
+    }
 }
@@ -11,6 +11,9 @@ pub struct Aggregate {
     pub group_exprs: Vec<Expr>,
     /// Aggregate expressions
     pub aggr_exprs: Vec<Expr>,
+    /// Indexes into `group_exprs` of every grouping set, the group expressions missing
+    /// from a set are null in its rows. Empty for a plain GROUP BY of all of them.
+    pub grouping_sets: Vec<Vec<usize>>,
     /// The schema description of the aggregate output
     pub schema: SchemaRef,
 }
@@ -1,6 +1,7 @@
 use crate::catalog::SchemaRef;
 use crate::common::TableReference;
 use crate::planner::logical_plan::OrderByExpr;
+use crate::storage::index::ConstraintTiming;
 
 #[derive(derive_new::new, Debug, Clone)]
 pub struct CreateIndex {
@@ -8,10 +9,21 @@ pub struct CreateIndex {
     pub table: TableReference,
     pub table_schema: SchemaRef,
     pub columns: Vec<OrderByExpr>,
+    pub unique: bool,
+    pub deferrable: Option<ConstraintTiming>,
 }
 
 impl std::fmt::Display for CreateIndex {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "CreateIndex: {}", self.index_name)
+        match (self.unique, self.deferrable) {
+            (true, Some(timing)) => write!(
+                f,
+                "CreateIndex: {} (unique, deferrable initially {})",
+                self.index_name,
+                timing.to_string().to_lowercase()
+            ),
+            (true, None) => write!(f, "CreateIndex: {} (unique)", self.index_name),
+            (false, _) => write!(f, "CreateIndex: {}", self.index_name),
+        }
     }
 }
@@ -0,0 +1,12 @@
+#[derive(derive_new::new, Debug, Clone)]
+pub struct CreateSchema {
+    pub schema_name: String,
+    // `IF NOT EXISTS` of a schema that exists creates nothing
+    pub if_not_exists: bool,
+}
+
+impl std::fmt::Display for CreateSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CreateSchema: {}", self.schema_name)
+    }
+}
@@ -1,10 +1,15 @@
-use crate::catalog::Column;
+use crate::catalog::{Column, ForeignKey, Partitioning};
 use crate::common::TableReference;
 
 #[derive(Debug, Clone)]
 pub struct CreateTable {
     pub name: TableReference,
     pub columns: Vec<Column>,
+    pub ttl_column: Option<String>,
+    // rows get logical row ids, see `crate::storage::RowIdMap`
+    pub logical_row_ids: bool,
+    pub partitioning: Option<Partitioning>,
+    pub foreign_keys: Vec<ForeignKey>,
 }
 
 impl std::fmt::Display for CreateTable {
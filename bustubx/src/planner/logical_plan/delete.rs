@@ -0,0 +1,40 @@
+use crate::catalog::SchemaRef;
+use crate::common::TableReference;
+use crate::expression::Expr;
+use crate::planner::logical_plan::OrderByExpr;
+
+#[derive(derive_new::new, Debug, Clone)]
+pub struct Delete {
+    pub table: TableReference,
+    pub table_schema: SchemaRef,
+    pub selection: Option<Expr>,
+    // ORDER BY and LIMIT picking the rows changed, empty and None for all matching rows
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<usize>,
+}
+
+impl std::fmt::Display for Delete {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Delete: {}", self.table)?;
+        write_order(f, &self.order_by, self.limit)
+    }
+}
+
+// ORDER BY and LIMIT of a DELETE or UPDATE
+pub(crate) fn write_order(
+    f: &mut std::fmt::Formatter<'_>,
+    order_by: &[OrderByExpr],
+    limit: Option<usize>,
+) -> std::fmt::Result {
+    if !order_by.is_empty() {
+        let exprs = order_by
+            .iter()
+            .map(|order| format!("{order}"))
+            .collect::<Vec<_>>();
+        write!(f, " order by {}", exprs.join(", "))?;
+    }
+    if let Some(limit) = limit {
+        write!(f, " limit {}", limit)?;
+    }
+    Ok(())
+}
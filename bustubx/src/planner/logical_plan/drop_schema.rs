@@ -0,0 +1,15 @@
+use crate::common::TableReference;
+
+#[derive(derive_new::new, Debug, Clone)]
+pub struct DropSchema {
+    // schemas of `IF EXISTS` that do not exist are left out
+    pub schema_names: Vec<String>,
+    // tables of the schemas, only CASCADE drops schemas holding tables
+    pub tables: Vec<TableReference>,
+}
+
+impl std::fmt::Display for DropSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DropSchema: {}", self.schema_names.join(", "))
+    }
+}
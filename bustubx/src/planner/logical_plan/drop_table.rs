@@ -0,0 +1,18 @@
+use crate::common::TableReference;
+
+#[derive(derive_new::new, Debug, Clone)]
+pub struct DropTable {
+    // tables of `IF EXISTS` that do not exist are left out
+    pub tables: Vec<TableReference>,
+}
+
+impl std::fmt::Display for DropTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tables = self
+            .tables
+            .iter()
+            .map(|table| table.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "DropTable: {}", tables.join(", "))
+    }
+}
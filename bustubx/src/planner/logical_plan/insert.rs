@@ -1,6 +1,8 @@
 use crate::catalog::SchemaRef;
 use crate::common::TableReference;
+use crate::expression::Expr;
 use crate::planner::logical_plan::LogicalPlan;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 #[derive(derive_new::new, Debug, Clone)]
@@ -9,6 +11,24 @@ pub struct Insert {
     pub table_schema: SchemaRef,
     pub projected_schema: SchemaRef,
     pub input: Arc<LogicalPlan>,
+    pub on_conflict: Option<OnConflict>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OnConflict {
+    // unique index resolved from the conflict target, none means any unique index
+    pub index_name: Option<String>,
+    pub action: OnConflictAction,
+}
+
+#[derive(Debug, Clone)]
+pub enum OnConflictAction {
+    DoNothing,
+    // assignments and selection may refer to the proposed row as `excluded`
+    DoUpdate {
+        assignments: HashMap<String, Expr>,
+        selection: Option<Expr>,
+    },
 }
 
 impl std::fmt::Display for Insert {
@@ -23,6 +43,17 @@ impl std::fmt::Display for Insert {
                 .map(|c| c.name.clone())
                 .collect::<Vec<_>>()
                 .join(", ")
-        )
+        )?;
+        if let Some(on_conflict) = &self.on_conflict {
+            write!(f, " on conflict")?;
+            if let Some(index_name) = &on_conflict.index_name {
+                write!(f, " ({index_name})")?;
+            }
+            match &on_conflict.action {
+                OnConflictAction::DoNothing => write!(f, " do nothing")?,
+                OnConflictAction::DoUpdate { .. } => write!(f, " do update")?,
+            }
+        }
+        Ok(())
     }
 }
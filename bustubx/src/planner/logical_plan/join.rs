@@ -25,6 +25,14 @@ pub enum JoinType {
     // select * from x, y
     // select * from x cross join y
     Cross,
+    // select * from x where exists (select * from y)
+    // select * from x where x.a in (select y.a from y)
+    LeftSemi,
+    // select * from x where not exists (select * from y)
+    LeftAnti,
+    // select * from x where x.a not in (select y.a from y), no row passes when
+    // the key of the row or any key of y is null
+    NullAwareLeftAnti,
 }
 
 impl std::fmt::Display for Join {
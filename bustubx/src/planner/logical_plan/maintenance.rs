@@ -0,0 +1,61 @@
+use crate::catalog::SchemaRef;
+use crate::common::TableReference;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaintenanceKind {
+    // FULL compacts the heap instead of only reclaiming pages of deleted rows
+    Vacuum { full: bool },
+    Analyze,
+    Checkpoint,
+    // rebuilds the named index of the table, or all of its indexes
+    Reindex { index: Option<String> },
+    // integrity checks of the table, WITH INDEXES adds its indexes
+    CheckTable { with_indexes: bool },
+    // rewrites the heap in the key order of the index
+    Cluster { index: String },
+}
+
+/// `VACUUM`, `ANALYZE`, `CHECKPOINT`, `REINDEX`, `CHECK TABLE` or `CLUSTER` over `tables`, planned
+/// without the optimizer.
+#[derive(derive_new::new, Debug, Clone)]
+pub struct Maintenance {
+    pub kind: MaintenanceKind,
+    pub tables: Vec<TableReference>,
+    // the one row summary of the statement, the findings of CHECK TABLE
+    pub schema: SchemaRef,
+}
+
+impl std::fmt::Display for MaintenanceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaintenanceKind::Vacuum { full: false } => write!(f, "Vacuum"),
+            MaintenanceKind::Vacuum { full: true } => write!(f, "Vacuum Full"),
+            MaintenanceKind::Analyze => write!(f, "Analyze"),
+            MaintenanceKind::Checkpoint => write!(f, "Checkpoint"),
+            MaintenanceKind::Reindex { index: None } => write!(f, "Reindex"),
+            MaintenanceKind::Reindex { index: Some(index) } => write!(f, "Reindex {index}"),
+            MaintenanceKind::CheckTable {
+                with_indexes: false,
+            } => write!(f, "Check Table"),
+            MaintenanceKind::CheckTable { with_indexes: true } => {
+                write!(f, "Check Table With Indexes")
+            }
+            MaintenanceKind::Cluster { index } => write!(f, "Cluster {index}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Maintenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.tables.is_empty() {
+            let tables = self
+                .tables
+                .iter()
+                .map(|table| table.to_string())
+                .collect::<Vec<_>>();
+            write!(f, ": {}", tables.join(", "))?;
+        }
+        Ok(())
+    }
+}
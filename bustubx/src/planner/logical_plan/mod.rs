@@ -1,11 +1,16 @@
 mod aggregate;
 mod create_index;
+mod create_schema;
 mod create_table;
+mod delete;
+mod drop_schema;
+mod drop_table;
 mod empty_relation;
 mod filter;
 mod insert;
 mod join;
 mod limit;
+mod maintenance;
 mod project;
 mod sort;
 mod table_scan;
@@ -15,12 +20,18 @@ mod values;
 
 pub use aggregate::Aggregate;
 pub use create_index::CreateIndex;
+pub use create_schema::CreateSchema;
 pub use create_table::CreateTable;
+pub(crate) use delete::write_order;
+pub use delete::Delete;
+pub use drop_schema::DropSchema;
+pub use drop_table::DropTable;
 pub use empty_relation::EmptyRelation;
 pub use filter::Filter;
-pub use insert::Insert;
+pub use insert::{Insert, OnConflict, OnConflictAction};
 pub use join::{Join, JoinType};
 pub use limit::Limit;
+pub use maintenance::{Maintenance, MaintenanceKind};
 pub use project::Project;
 pub use sort::{OrderByExpr, Sort};
 pub use table_scan::TableScan;
@@ -29,7 +40,8 @@ pub use util::*;
 pub use values::Values;
 
 use crate::catalog::{
-    SchemaRef, EMPTY_SCHEMA_REF, INSERT_OUTPUT_SCHEMA_REF, UPDATE_OUTPUT_SCHEMA_REF,
+    SchemaRef, DELETE_OUTPUT_SCHEMA_REF, EMPTY_SCHEMA_REF, INSERT_OUTPUT_SCHEMA_REF,
+    UPDATE_OUTPUT_SCHEMA_REF,
 };
 use crate::{BustubxError, BustubxResult};
 use std::sync::Arc;
@@ -38,6 +50,9 @@ use std::sync::Arc;
 pub enum LogicalPlan {
     CreateTable(CreateTable),
     CreateIndex(CreateIndex),
+    DropTable(DropTable),
+    CreateSchema(CreateSchema),
+    DropSchema(DropSchema),
     Filter(Filter),
     Insert(Insert),
     Join(Join),
@@ -49,6 +64,8 @@ pub enum LogicalPlan {
     EmptyRelation(EmptyRelation),
     Aggregate(Aggregate),
     Update(Update),
+    Delete(Delete),
+    Maintenance(Maintenance),
 }
 
 impl LogicalPlan {
@@ -56,6 +73,9 @@ impl LogicalPlan {
         match self {
             LogicalPlan::CreateTable(_) => &EMPTY_SCHEMA_REF,
             LogicalPlan::CreateIndex(_) => &EMPTY_SCHEMA_REF,
+            LogicalPlan::DropTable(_) => &EMPTY_SCHEMA_REF,
+            LogicalPlan::CreateSchema(_) => &EMPTY_SCHEMA_REF,
+            LogicalPlan::DropSchema(_) => &EMPTY_SCHEMA_REF,
             LogicalPlan::Filter(Filter { input, .. }) => input.schema(),
             LogicalPlan::Insert(_) => &INSERT_OUTPUT_SCHEMA_REF,
             LogicalPlan::Join(Join { schema, .. }) => schema,
@@ -67,6 +87,8 @@ impl LogicalPlan {
             LogicalPlan::EmptyRelation(EmptyRelation { schema, .. }) => schema,
             LogicalPlan::Aggregate(Aggregate { schema, .. }) => schema,
             LogicalPlan::Update(_) => &UPDATE_OUTPUT_SCHEMA_REF,
+            LogicalPlan::Delete(_) => &DELETE_OUTPUT_SCHEMA_REF,
+            LogicalPlan::Maintenance(Maintenance { schema, .. }) => schema,
         }
     }
 
@@ -81,9 +103,14 @@ impl LogicalPlan {
             LogicalPlan::Aggregate(Aggregate { input, .. }) => vec![input],
             LogicalPlan::CreateTable(_)
             | LogicalPlan::CreateIndex(_)
+            | LogicalPlan::DropTable(_)
+            | LogicalPlan::CreateSchema(_)
+            | LogicalPlan::DropSchema(_)
             | LogicalPlan::TableScan(_)
             | LogicalPlan::Values(_)
             | LogicalPlan::Update(_)
+            | LogicalPlan::Delete(_)
+            | LogicalPlan::Maintenance(_)
             | LogicalPlan::EmptyRelation(_) => vec![],
         }
     }
@@ -108,11 +135,13 @@ impl LogicalPlan {
                 table,
                 table_schema,
                 projected_schema,
+                on_conflict,
                 ..
             }) => Ok(LogicalPlan::Insert(Insert {
                 table: table.clone(),
                 table_schema: table_schema.clone(),
                 projected_schema: projected_schema.clone(),
+                on_conflict: on_conflict.clone(),
                 input: Arc::new(
                     inputs
                         .first()
@@ -144,7 +173,7 @@ impl LogicalPlan {
                 ),
                 right: Arc::new(
                     inputs
-                        .first()
+                        .get(1)
                         .ok_or_else(|| {
                             BustubxError::Internal(format!(
                                 "inputs {:?} should have at least two",
@@ -209,11 +238,13 @@ impl LogicalPlan {
             LogicalPlan::Aggregate(Aggregate {
                 group_exprs,
                 aggr_exprs,
+                grouping_sets,
                 schema,
                 ..
             }) => Ok(LogicalPlan::Aggregate(Aggregate {
                 group_exprs: group_exprs.clone(),
                 aggr_exprs: aggr_exprs.clone(),
+                grouping_sets: grouping_sets.clone(),
                 schema: schema.clone(),
                 input: Arc::new(
                     inputs
@@ -229,9 +260,14 @@ impl LogicalPlan {
             })),
             LogicalPlan::CreateTable(_)
             | LogicalPlan::CreateIndex(_)
+            | LogicalPlan::DropTable(_)
+            | LogicalPlan::CreateSchema(_)
+            | LogicalPlan::DropSchema(_)
             | LogicalPlan::TableScan(_)
             | LogicalPlan::Values(_)
             | LogicalPlan::Update(_)
+            | LogicalPlan::Delete(_)
+            | LogicalPlan::Maintenance(_)
             | LogicalPlan::EmptyRelation(_) => Ok(self.clone()),
         }
     }
@@ -242,6 +278,9 @@ impl std::fmt::Display for LogicalPlan {
         match self {
             LogicalPlan::CreateTable(v) => write!(f, "{v}"),
             LogicalPlan::CreateIndex(v) => write!(f, "{v}"),
+            LogicalPlan::DropTable(v) => write!(f, "{v}"),
+            LogicalPlan::CreateSchema(v) => write!(f, "{v}"),
+            LogicalPlan::DropSchema(v) => write!(f, "{v}"),
             LogicalPlan::Filter(v) => write!(f, "{v}"),
             LogicalPlan::Insert(v) => write!(f, "{v}"),
             LogicalPlan::Join(v) => write!(f, "{v}"),
@@ -253,6 +292,8 @@ impl std::fmt::Display for LogicalPlan {
             LogicalPlan::EmptyRelation(v) => write!(f, "{v}"),
             LogicalPlan::Aggregate(v) => write!(f, "{v}"),
             LogicalPlan::Update(v) => write!(f, "{v}"),
+            LogicalPlan::Delete(v) => write!(f, "{v}"),
+            LogicalPlan::Maintenance(v) => write!(f, "{v}"),
         }
     }
 }
@@ -15,7 +15,8 @@ pub struct OrderByExpr {
     pub expr: Box<Expr>,
     /// The direction of the sort
     pub asc: bool,
-    /// Whether to put Nulls before all other data values
+    /// Whether to put Nulls before all other data values, by default they are first in
+    /// ascending and last in descending order
     pub nulls_first: bool,
 }
 
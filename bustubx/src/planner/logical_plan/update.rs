@@ -1,6 +1,7 @@
 use crate::catalog::SchemaRef;
 use crate::common::TableReference;
 use crate::expression::Expr;
+use crate::planner::logical_plan::{write_order, OrderByExpr};
 use std::collections::HashMap;
 
 #[derive(derive_new::new, Debug, Clone)]
@@ -9,10 +10,14 @@ pub struct Update {
     pub table_schema: SchemaRef,
     pub assignments: HashMap<String, Expr>,
     pub selection: Option<Expr>,
+    // ORDER BY and LIMIT picking the rows changed, empty and None for all matching rows
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<usize>,
 }
 
 impl std::fmt::Display for Update {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Update: {}", self.table,)
+        write!(f, "Update: {}", self.table)?;
+        write_order(f, &self.order_by, self.limit)
     }
 }
@@ -39,6 +39,7 @@ pub fn build_join_schema(
             .chain(&nullify_columns(right_cols))
             .cloned()
             .collect(),
+        JoinType::LeftSemi | JoinType::LeftAnti | JoinType::NullAwareLeftAnti => left_cols.clone(),
     };
     Ok(Schema { columns })
 }
@@ -49,5 +50,5 @@ pub fn project_schema(input: &LogicalPlan, exprs: &[Expr]) -> BustubxResult<Sche
     for expr in exprs {
         columns.push(expr.to_column(input_schema)?)
     }
-    Ok(Schema::new(columns))
+    Schema::try_new(columns)
 }
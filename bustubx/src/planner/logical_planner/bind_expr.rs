@@ -1,8 +1,11 @@
+use crate::catalog::Schema;
+use crate::common::util::decode_hex;
 use crate::common::{ScalarValue, TableReference};
-use crate::expression::{AggregateFunction, BinaryExpr, ColumnExpr, Expr, Literal};
-use crate::function::AggregateFunctionKind;
+use crate::expression::{AggregateFunction, BinaryExpr, ColumnExpr, Expr, Literal, ScalarFunction};
+use crate::function::{implicitly_casts, AggregateFunctionKind, ScalarFunctionKind};
 use crate::planner::LogicalPlanner;
 use crate::{BustubxError, BustubxResult};
+use sqlparser::parser::ParserError;
 
 impl LogicalPlanner<'_> {
     pub fn bind_expr(&self, sql: &sqlparser::ast::Expr) -> BustubxResult<Expr> {
@@ -21,6 +24,20 @@ impl LogicalPlanner<'_> {
                 }))
             }
             sqlparser::ast::Expr::Value(value) => self.bind_value(value),
+            sqlparser::ast::Expr::Nested(expr) => self.bind_expr(expr),
+            // a negative number literal, e.g. in the VALUES of a dump
+            sqlparser::ast::Expr::UnaryOp {
+                op: sqlparser::ast::UnaryOperator::Minus,
+                expr,
+            } => match expr.as_ref() {
+                sqlparser::ast::Expr::Value(sqlparser::ast::Value::Number(s, long)) => {
+                    self.bind_value(&sqlparser::ast::Value::Number(format!("-{s}"), *long))
+                }
+                _ => Err(BustubxError::NotSupport(format!(
+                    "sqlparser expr {} not supported",
+                    sql
+                ))),
+            },
             sqlparser::ast::Expr::CompoundIdentifier(idents) => match idents.as_slice() {
                 [col] => Ok(Expr::Column(ColumnExpr {
                     relation: None,
@@ -58,12 +75,27 @@ impl LogicalPlanner<'_> {
         }
     }
 
+    /// Bind a predicate over rows of `schema`, its comparisons must not mix collations.
+    pub fn bind_predicate(
+        &self,
+        sql: &sqlparser::ast::Expr,
+        schema: &Schema,
+    ) -> BustubxResult<Expr> {
+        let predicate = self.bind_expr(sql)?;
+        predicate.collation(schema)?;
+        Ok(predicate)
+    }
+
     pub fn bind_value(&self, value: &sqlparser::ast::Value) -> BustubxResult<Expr> {
         match value {
             sqlparser::ast::Value::Number(s, _) => {
                 if let Ok(num) = s.parse::<i64>() {
                     return Ok(Expr::Literal(Literal { value: num.into() }));
                 }
+                // beyond i64, exact for BIGINT UNSIGNED columns
+                if let Ok(num) = s.parse::<u64>() {
+                    return Ok(Expr::Literal(Literal { value: num.into() }));
+                }
                 if let Ok(num) = s.parse::<f64>() {
                     return Ok(Expr::Literal(Literal { value: num.into() }));
                 }
@@ -78,6 +110,17 @@ impl LogicalPlanner<'_> {
             sqlparser::ast::Value::SingleQuotedString(s) => Ok(Expr::Literal(Literal {
                 value: s.clone().into(),
             })),
+            // x'deadbeef'
+            sqlparser::ast::Value::HexStringLiteral(s) => match decode_hex(s) {
+                Ok(bytes) => Ok(Expr::Literal(Literal {
+                    value: bytes.into(),
+                })),
+                // a malformed literal is a syntax error of the statement
+                Err(_) => Err(ParserError::ParserError(format!(
+                    "invalid hex string literal x'{s}'"
+                ))
+                .into()),
+            },
             _ => Err(BustubxError::NotSupport(format!(
                 "sqlparser value {} not supported",
                 value
@@ -92,7 +135,15 @@ impl LogicalPlanner<'_> {
             let args = function
                 .args
                 .iter()
-                .map(|arg| self.bind_function_arg(arg))
+                .map(|arg| match arg {
+                    // count(*) counts every row, the same as counting a non-null constant
+                    sqlparser::ast::FunctionArg::Unnamed(
+                        sqlparser::ast::FunctionArgExpr::Wildcard,
+                    ) if func_kind == AggregateFunctionKind::Count => {
+                        Ok(Expr::Literal(Literal { value: 1i64.into() }))
+                    }
+                    _ => self.bind_function_arg(arg),
+                })
                 .collect::<BustubxResult<Vec<Expr>>>()?;
             return Ok(Expr::AggregateFunction(AggregateFunction {
                 func_kind,
@@ -101,10 +152,72 @@ impl LogicalPlanner<'_> {
             }));
         }
 
-        Err(BustubxError::Plan(format!(
-            "The function {} is not supported",
-            function
-        )))
+        if let Some(func_kind) = ScalarFunctionKind::find(name.as_str()) {
+            let args = function
+                .args
+                .iter()
+                .map(|arg| self.bind_function_arg(arg))
+                .collect::<BustubxResult<Vec<Expr>>>()?;
+            if args.len() != func_kind.arg_count() {
+                return Err(BustubxError::Plan(format!(
+                    "The function {} expects {} args instead of {}",
+                    func_kind,
+                    func_kind.arg_count(),
+                    args.len()
+                )));
+            }
+            return Ok(Expr::ScalarFunction(ScalarFunction {
+                func_kind,
+                args,
+                clock: self.context.clock.clone(),
+            }));
+        }
+
+        if let Some(func) = self.context.catalog.functions.get(name.as_str()) {
+            let args = function
+                .args
+                .iter()
+                .map(|arg| self.bind_function_arg(arg))
+                .collect::<BustubxResult<Vec<Expr>>>()?;
+            if args.len() != func.signature.args.len() {
+                return Err(BustubxError::Plan(format!(
+                    "The function {} expects {} args instead of {}",
+                    func.name,
+                    func.signature.args.len(),
+                    args.len()
+                )));
+            }
+            // literals are cast to the signature here, other arguments when evaluated
+            let args = args
+                .into_iter()
+                .zip(func.signature.args.iter())
+                .enumerate()
+                .map(|(i, (arg, data_type))| match arg {
+                    Expr::Literal(Literal { value }) => {
+                        if !value.is_null() && !implicitly_casts(&value.data_type(), data_type) {
+                            return Err(BustubxError::Plan(format!(
+                                "The function {} expects {} as argument {} instead of {}",
+                                func.name,
+                                data_type,
+                                i + 1,
+                                value.data_type()
+                            )));
+                        }
+                        Ok(Expr::Literal(Literal {
+                            value: value.cast_to(data_type)?,
+                        }))
+                    }
+                    arg => Ok(arg),
+                })
+                .collect::<BustubxResult<Vec<Expr>>>()?;
+            return Ok(Expr::ScalarFunction(ScalarFunction {
+                func_kind: ScalarFunctionKind::Udf(func),
+                args,
+                clock: self.context.clock.clone(),
+            }));
+        }
+
+        Err(BustubxError::UnknownFunction(name))
     }
 
     pub fn bind_function_arg(&self, arg: &sqlparser::ast::FunctionArg) -> BustubxResult<Expr> {
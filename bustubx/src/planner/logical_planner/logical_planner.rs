@@ -1,11 +1,14 @@
 use crate::{BustubxError, BustubxResult};
 
-use crate::catalog::Catalog;
-use crate::common::TableReference;
+use crate::catalog::{Catalog, DEFAULT_SCHEMA_NAME};
+use crate::common::{ClockRef, TableReference};
 use crate::planner::logical_plan::{LogicalPlan, OrderByExpr};
 
 pub struct PlannerContext<'a> {
     pub catalog: &'a Catalog,
+    pub clock: ClockRef,
+    // schemas unqualified table names are resolved in, see `ExecutionOptions::search_path`
+    pub search_path: Vec<String>,
 }
 
 pub struct LogicalPlanner<'a> {
@@ -14,28 +17,74 @@ pub struct LogicalPlanner<'a> {
 impl<'a> LogicalPlanner<'a> {
     pub fn plan(&mut self, stmt: &sqlparser::ast::Statement) -> BustubxResult<LogicalPlan> {
         match stmt {
-            sqlparser::ast::Statement::CreateTable { name, columns, .. } => {
-                self.plan_create_table(name, columns)
-            }
+            sqlparser::ast::Statement::CreateTable {
+                name,
+                columns,
+                constraints,
+                with_options,
+                ..
+            } => self.plan_create_table(name, columns, constraints, with_options, &[]),
             sqlparser::ast::Statement::CreateIndex {
                 name,
                 table_name,
                 columns,
+                unique,
+                ..
+            } => self.plan_create_index(name, table_name, columns, *unique),
+            sqlparser::ast::Statement::CreateSchema {
+                schema_name,
+                if_not_exists,
+            } => self.plan_create_schema(schema_name, *if_not_exists),
+            sqlparser::ast::Statement::Drop {
+                object_type: sqlparser::ast::ObjectType::Schema,
+                if_exists,
+                names,
+                cascade,
+                ..
+            } => self.plan_drop_schema(*if_exists, names, *cascade),
+            sqlparser::ast::Statement::Drop {
+                object_type,
+                if_exists,
+                names,
                 ..
-            } => self.plan_create_index(name, table_name, columns),
+            } => self.plan_drop_table(object_type, *if_exists, names),
+            sqlparser::ast::Statement::AlterTable { name, operation } => {
+                self.plan_alter_table(name, operation)
+            }
+            sqlparser::ast::Statement::Truncate { table_name, .. } => {
+                let table = self.bind_table_name(table_name)?;
+                self.context.catalog.check_table_writable(&table)?;
+                Err(BustubxError::NotSupport(format!(
+                    "TRUNCATE {} is not supported, delete its rows",
+                    table
+                )))
+            }
             sqlparser::ast::Statement::Query(query) => self.plan_query(query),
             sqlparser::ast::Statement::Insert {
                 table_name,
                 columns,
                 source,
+                on,
                 ..
-            } => self.plan_insert(table_name, columns, source),
+            } => self.plan_insert(table_name, columns, source, on),
             sqlparser::ast::Statement::Update {
                 table,
                 assignments,
                 selection,
                 ..
             } => self.plan_update(table, assignments, selection),
+            sqlparser::ast::Statement::Delete {
+                from, selection, ..
+            } => self.plan_delete(from, selection),
+            sqlparser::ast::Statement::ShowTables {
+                db_name, filter, ..
+            } => self.plan_show_tables(db_name, filter),
+            sqlparser::ast::Statement::ExplainTable { table_name, .. } => {
+                self.plan_describe(table_name)
+            }
+            sqlparser::ast::Statement::ShowCreate { obj_type, obj_name } => {
+                self.plan_show_create_table(obj_type, obj_name)
+            }
             _ => unimplemented!(),
         }
     }
@@ -45,19 +94,38 @@ impl<'a> LogicalPlanner<'a> {
         order_by: &sqlparser::ast::OrderByExpr,
     ) -> BustubxResult<OrderByExpr> {
         let expr = self.bind_expr(&order_by.expr)?;
+        let asc = order_by.asc.unwrap_or(true);
         Ok(OrderByExpr {
             expr: Box::new(expr),
-            asc: order_by.asc.unwrap_or(true),
-            nulls_first: order_by.nulls_first.unwrap_or(false),
+            asc,
+            nulls_first: order_by.nulls_first.unwrap_or(asc),
         })
     }
 
+    /// Reference to an existing table. An unqualified name is looked up in the schemas of
+    /// the search path in order and the first schema holding the table wins, a name found
+    /// nowhere refers to the first schema of the path that exists. Tables of the default
+    /// schema keep bare references.
     pub fn bind_table_name(
         &self,
         table_name: &sqlparser::ast::ObjectName,
     ) -> BustubxResult<TableReference> {
         match table_name.0.as_slice() {
-            [table] => Ok(TableReference::bare(table.value.clone())),
+            [table] => {
+                let schema_name = self
+                    .context
+                    .search_path
+                    .iter()
+                    .find(|schema_name| {
+                        self.context
+                            .catalog
+                            .schemas
+                            .get(schema_name.as_str())
+                            .is_some_and(|schema| schema.tables.contains_key(&table.value))
+                    })
+                    .map_or_else(|| self.creation_schema(), Ok)?;
+                Ok(self.schema_table_reference(schema_name, &table.value))
+            }
             [schema, table] => Ok(TableReference::partial(
                 schema.value.clone(),
                 table.value.clone(),
@@ -73,4 +141,42 @@ impl<'a> LogicalPlanner<'a> {
             ))),
         }
     }
+
+    /// Reference to a table about to be created, an unqualified name goes to the first
+    /// schema of the search path that exists.
+    pub fn bind_new_table_name(
+        &self,
+        table_name: &sqlparser::ast::ObjectName,
+    ) -> BustubxResult<TableReference> {
+        match table_name.0.as_slice() {
+            [table] => Ok(self.schema_table_reference(self.creation_schema()?, &table.value)),
+            _ => self.bind_table_name(table_name),
+        }
+    }
+
+    fn creation_schema(&self) -> BustubxResult<&String> {
+        self.context
+            .search_path
+            .iter()
+            .find(|schema_name| {
+                self.context
+                    .catalog
+                    .schemas
+                    .contains_key(schema_name.as_str())
+            })
+            .ok_or_else(|| {
+                BustubxError::Plan(format!(
+                    "no schema of search path {} exists",
+                    self.context.search_path.join(", ")
+                ))
+            })
+    }
+
+    fn schema_table_reference(&self, schema_name: &str, table_name: &str) -> TableReference {
+        if schema_name == DEFAULT_SCHEMA_NAME {
+            TableReference::bare(table_name)
+        } else {
+            TableReference::partial(schema_name, table_name)
+        }
+    }
 }
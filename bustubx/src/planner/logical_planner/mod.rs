@@ -1,10 +1,20 @@
 mod bind_expr;
+#[allow(clippy::module_inception)]
 mod logical_planner;
+mod plan_alter_table;
 mod plan_create_index;
 mod plan_create_table;
+mod plan_delete;
+mod plan_drop_table;
 mod plan_insert;
+mod plan_maintenance;
+mod plan_ordered_dml;
 mod plan_query;
+mod plan_schema;
 mod plan_set_expr;
+mod plan_show;
+mod plan_subquery;
 mod plan_update;
 
 pub use logical_planner::{LogicalPlanner, PlannerContext};
+pub(crate) use plan_show::{create_index_sql, create_table_sql, sql_literal};
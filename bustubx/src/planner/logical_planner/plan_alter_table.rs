@@ -0,0 +1,49 @@
+use crate::planner::logical_plan::LogicalPlan;
+use crate::{BustubxError, BustubxResult};
+
+use super::LogicalPlanner;
+
+impl<'a> LogicalPlanner<'a> {
+    /// Table layouts can not be changed yet, the statement is rejected before touching the
+    /// table. Dropping or changing a column that indexes are built on reports those indexes,
+    /// a read only table reports that first. `SET read_only` and `SET read_write` are run
+    /// by [`crate::Database`] directly, see [`crate::parser::parse_alter_table_access`].
+    pub fn plan_alter_table(
+        &self,
+        name: &sqlparser::ast::ObjectName,
+        operation: &sqlparser::ast::AlterTableOperation,
+    ) -> BustubxResult<LogicalPlan> {
+        let table = self.bind_table_name(name)?;
+        self.context.catalog.check_table_writable(&table)?;
+        let column = match operation {
+            sqlparser::ast::AlterTableOperation::DropColumn { column_name, .. }
+            | sqlparser::ast::AlterTableOperation::AlterColumn {
+                column_name,
+                op: sqlparser::ast::AlterColumnOperation::SetDataType { .. },
+            } => Some(column_name),
+            sqlparser::ast::AlterTableOperation::RenameColumn {
+                old_column_name, ..
+            } => Some(old_column_name),
+            sqlparser::ast::AlterTableOperation::ChangeColumn { old_name, .. } => Some(old_name),
+            _ => None,
+        };
+        if let Some(column) = column {
+            let indexes = self
+                .context
+                .catalog
+                .dependent_indexes(&table, &column.value)?;
+            if !indexes.is_empty() {
+                return Err(BustubxError::Plan(format!(
+                    "column {} of table {} is used by indexes {}",
+                    column.value,
+                    table,
+                    indexes.join(", ")
+                )));
+            }
+        }
+        Err(BustubxError::NotSupport(format!(
+            "ALTER TABLE {} {} is not supported",
+            table, operation
+        )))
+    }
+}
@@ -1,3 +1,5 @@
+use crate::expression::{Alias, Expr};
+use crate::parser::DeferrableIndex;
 use crate::planner::logical_plan::{CreateIndex, LogicalPlan};
 use crate::{BustubxError, BustubxResult};
 
@@ -9,6 +11,7 @@ impl<'a> LogicalPlanner<'a> {
         index_name: &sqlparser::ast::ObjectName,
         table_name: &sqlparser::ast::ObjectName,
         columns: &[sqlparser::ast::OrderByExpr],
+        unique: bool,
     ) -> BustubxResult<LogicalPlan> {
         let index_name = index_name.0.first().map_or(
             Err(BustubxError::Plan(format!(
@@ -19,15 +22,55 @@ impl<'a> LogicalPlanner<'a> {
         let table = self.bind_table_name(table_name)?;
         let mut columns_expr = vec![];
         for col in columns.iter() {
-            let col_expr = self.bind_order_by_expr(col)?;
+            let mut col_expr = self.bind_order_by_expr(col)?;
+            if col_expr.expr.is_volatile() {
+                return Err(BustubxError::Plan(format!(
+                    "index key {} is volatile",
+                    col.expr
+                )));
+            }
+            // the key column of an expression is named after its SQL text, bound again
+            // when the database is opened
+            if !matches!(col_expr.expr.as_ref(), Expr::Column(_)) {
+                col_expr.expr = Box::new(Expr::Alias(Alias {
+                    expr: col_expr.expr,
+                    name: col.expr.to_string(),
+                }));
+            }
             columns_expr.push(col_expr);
         }
+        if self.context.catalog.catalog_table(&table)?.is_partitioned() {
+            return Err(BustubxError::NotSupport(format!(
+                "index on partitioned table {} not supported, index its partitions",
+                table
+            )));
+        }
+        self.context.catalog.check_table_writable(&table)?;
         let table_schema = self.context.catalog.table_heap(&table)?.schema.clone();
         Ok(LogicalPlan::CreateIndex(CreateIndex {
             index_name,
             table,
             table_schema,
             columns: columns_expr,
+            unique,
+            deferrable: None,
         }))
     }
+
+    /// `CREATE UNIQUE INDEX ... DEFERRABLE`, whose keys may repeat until the statement or
+    /// the transaction ends.
+    pub fn plan_deferrable_index(&mut self, index: &DeferrableIndex) -> BustubxResult<LogicalPlan> {
+        match self.plan(&index.statement)? {
+            LogicalPlan::CreateIndex(create_index) if create_index.unique => {
+                Ok(LogicalPlan::CreateIndex(CreateIndex {
+                    deferrable: Some(index.initially),
+                    ..create_index
+                }))
+            }
+            plan => Err(BustubxError::Plan(format!(
+                "DEFERRABLE only applies to a unique index, not {}",
+                plan
+            ))),
+        }
+    }
 }
@@ -1,10 +1,17 @@
 use crate::{BustubxError, BustubxResult};
 use std::collections::HashSet;
 
-use crate::catalog::{Column, DataType};
-use crate::common::ScalarValue;
-use crate::expression::Expr;
+use crate::catalog::{
+    key_index, Column, DataType, DefaultExpr, ForeignKey, Partitioning, DEFAULT_SCHEMA_NAME,
+    EMPTY_SCHEMA_REF,
+};
+use crate::common::value_ord::Collation;
+use crate::common::{ScalarValue, TableReference};
+use crate::expression::ExprTrait;
+use crate::parser::{DeferrableTable, PartitionStatement, TableElement};
 use crate::planner::logical_plan::{CreateTable, LogicalPlan};
+use crate::storage::index::ConstraintTiming;
+use crate::storage::EMPTY_TUPLE;
 
 use super::LogicalPlanner;
 
@@ -13,8 +20,11 @@ impl<'a> LogicalPlanner<'a> {
         &self,
         name: &sqlparser::ast::ObjectName,
         column_defs: &Vec<sqlparser::ast::ColumnDef>,
+        constraints: &[sqlparser::ast::TableConstraint],
+        with_options: &[sqlparser::ast::SqlOption],
+        deferrable: &[(TableElement, ConstraintTiming)],
     ) -> BustubxResult<LogicalPlan> {
-        let name = self.bind_table_name(name)?;
+        let name = self.bind_new_table_name(name)?;
         let mut columns = vec![];
         for col_def in column_defs {
             let data_type: DataType = (&col_def.data_type).try_into()?;
@@ -33,29 +43,424 @@ impl<'a> LogicalPlanner<'a> {
                         unreachable!()
                     }
                 });
-            let default = if let Some(expr) = default_expr {
-                let expr = self.bind_expr(expr)?;
-                match expr {
-                    Expr::Literal(lit) => lit.value.cast_to(&data_type)?,
-                    _ => {
-                        return Err(BustubxError::Internal(
-                            "The expr is not literal".to_string(),
-                        ))
-                    }
-                }
+            let (default, default_expr) = if let Some(expr) = default_expr {
+                self.bind_column_default(&col_def.name.value, expr, data_type)?
             } else {
-                ScalarValue::new_empty(data_type)
+                (ScalarValue::new_empty(data_type), None)
+            };
+            let collation = match &col_def.collation {
+                None => Collation::Binary,
+                Some(collation) if matches!(data_type, DataType::Varchar(_)) => {
+                    Collation::try_from(collation.to_string().as_str())?
+                }
+                Some(collation) => {
+                    return Err(BustubxError::Plan(format!(
+                        "column {} of type {} cannot have collation {}",
+                        col_def.name, data_type, collation
+                    )))
+                }
             };
 
             columns.push(
                 Column::new(col_def.name.value.clone(), data_type, !not_null)
                     .with_relation(Some(name.clone()))
-                    .with_default(default),
+                    .with_default(default)
+                    .with_default_expr(default_expr)
+                    .with_collation(collation),
             )
         }
 
         check_column_name_conflict(&columns)?;
-        Ok(LogicalPlan::CreateTable(CreateTable { name, columns }))
+        let foreign_keys =
+            self.bind_foreign_keys(&name, &columns, column_defs, constraints, deferrable)?;
+        let mut ttl_column = None;
+        let mut logical_row_ids = false;
+        for option in with_options {
+            if option.name.value.eq_ignore_ascii_case("ttl_column") {
+                ttl_column = Some(bind_ttl_column(&option.value, &columns)?);
+            } else if option.name.value.eq_ignore_ascii_case("logical_row_ids") {
+                logical_row_ids = bind_logical_row_ids(&option.value)?;
+            } else {
+                return Err(BustubxError::NotSupport(format!(
+                    "table option {} not supported",
+                    option.name
+                )));
+            }
+        }
+        Ok(LogicalPlan::CreateTable(CreateTable {
+            name,
+            columns,
+            ttl_column,
+            logical_row_ids,
+            partitioning: None,
+            foreign_keys,
+        }))
+    }
+
+    /// `CREATE TABLE` of a table partitioned by range, or of a partition taking the columns
+    /// and ttl column of its parent.
+    pub fn plan_partition_statement(
+        &self,
+        stmt: &PartitionStatement,
+    ) -> BustubxResult<LogicalPlan> {
+        match stmt {
+            PartitionStatement::CreatePartitioned {
+                create_table,
+                column,
+            } => {
+                let sqlparser::ast::Statement::CreateTable {
+                    name,
+                    columns,
+                    constraints,
+                    with_options,
+                    ..
+                } = create_table.as_ref()
+                else {
+                    return Err(BustubxError::Plan(format!(
+                        "{} cannot be partitioned",
+                        create_table
+                    )));
+                };
+                let LogicalPlan::CreateTable(mut create_table) =
+                    self.plan_create_table(name, columns, constraints, with_options, &[])?
+                else {
+                    unreachable!()
+                };
+                if !create_table.foreign_keys.is_empty() {
+                    return Err(BustubxError::NotSupport(format!(
+                        "foreign key of partitioned table {} not supported",
+                        create_table.name
+                    )));
+                }
+                if !create_table
+                    .columns
+                    .iter()
+                    .any(|col| col.name == column.value)
+                {
+                    return Err(BustubxError::Plan(format!(
+                        "partition column {} does not exist",
+                        column.value
+                    )));
+                }
+                create_table.partitioning = Some(Partitioning::Range {
+                    column: column.value.clone(),
+                });
+                Ok(LogicalPlan::CreateTable(create_table))
+            }
+            PartitionStatement::CreatePartition {
+                name,
+                parent,
+                from,
+                to,
+            } => {
+                let name = self.bind_new_table_name(name)?;
+                let parent = self.bind_table_name(parent)?;
+                if name.schema().unwrap_or(DEFAULT_SCHEMA_NAME)
+                    != parent.schema().unwrap_or(DEFAULT_SCHEMA_NAME)
+                {
+                    return Err(BustubxError::Plan(format!(
+                        "partition {} must be in the schema of table {}",
+                        name, parent
+                    )));
+                }
+                let parent_table = self.context.catalog.catalog_table(&parent)?;
+                let Some(Partitioning::Range { column }) = &parent_table.partitioning else {
+                    return Err(BustubxError::Plan(format!(
+                        "table {} is not partitioned",
+                        parent
+                    )));
+                };
+                let data_type = parent_table
+                    .table
+                    .schema
+                    .column_with_name(None, column)?
+                    .data_type;
+                let bind_bound = |expr: &sqlparser::ast::Expr| -> BustubxResult<ScalarValue> {
+                    let value = self
+                        .bind_expr(expr)?
+                        .evaluate(&EMPTY_TUPLE)?
+                        .cast_to(&data_type)?;
+                    if value.is_null() {
+                        return Err(BustubxError::Plan(format!(
+                            "partition bound {} is null",
+                            expr
+                        )));
+                    }
+                    Ok(value)
+                };
+                let partitioning = Partitioning::Partition {
+                    parent: parent.table().to_string(),
+                    column: column.clone(),
+                    from: bind_bound(from)?,
+                    to: bind_bound(to)?,
+                };
+                let columns = parent_table
+                    .table
+                    .schema
+                    .columns
+                    .iter()
+                    .map(|col| col.as_ref().clone().with_relation(Some(name.clone())))
+                    .collect();
+                Ok(LogicalPlan::CreateTable(CreateTable {
+                    name,
+                    columns,
+                    ttl_column: parent_table.ttl_column.clone(),
+                    logical_row_ids: false,
+                    partitioning: Some(partitioning),
+                    foreign_keys: vec![],
+                }))
+            }
+        }
+    }
+
+    /// `CREATE TABLE` with a `DEFERRABLE` foreign key, whose references may dangle until
+    /// the statement or the transaction ends.
+    pub fn plan_deferrable_table(&self, table: &DeferrableTable) -> BustubxResult<LogicalPlan> {
+        let sqlparser::ast::Statement::CreateTable {
+            name,
+            columns,
+            constraints,
+            with_options,
+            ..
+        } = &table.statement
+        else {
+            unreachable!()
+        };
+        self.plan_create_table(
+            name,
+            columns,
+            constraints,
+            with_options,
+            &table.foreign_keys,
+        )
+    }
+
+    /// Constant defaults are folded into a value once, volatile ones are kept as an
+    /// expression and evaluated for every inserted row.
+    pub fn bind_column_default(
+        &self,
+        column_name: &str,
+        sql: &sqlparser::ast::Expr,
+        data_type: DataType,
+    ) -> BustubxResult<(ScalarValue, Option<DefaultExpr>)> {
+        let expr = self.bind_expr(sql)?;
+        if expr.references_columns() {
+            return Err(BustubxError::Plan(format!(
+                "default of column {} cannot reference columns",
+                column_name
+            )));
+        }
+        if !expr.is_volatile() {
+            return Ok((expr.evaluate(&EMPTY_TUPLE)?.cast_to(&data_type)?, None));
+        }
+        // fail on a default that can never be stored in the column
+        ScalarValue::new_empty(expr.data_type(&EMPTY_SCHEMA_REF)?).cast_to(&data_type)?;
+        let default_expr = DefaultExpr {
+            sql: format!("{sql}"),
+            expr,
+        };
+        Ok((ScalarValue::new_empty(data_type), Some(default_expr)))
+    }
+
+    /// `REFERENCES` of a column or `FOREIGN KEY` of the table. The parent must exist and
+    /// hold a unique index on exactly the referenced columns. Only NO ACTION and RESTRICT
+    /// are supported, a key is checked, never cascaded.
+    fn bind_foreign_keys(
+        &self,
+        name: &TableReference,
+        columns: &[Column],
+        column_defs: &[sqlparser::ast::ColumnDef],
+        constraints: &[sqlparser::ast::TableConstraint],
+        deferrable: &[(TableElement, ConstraintTiming)],
+    ) -> BustubxResult<Vec<ForeignKey>> {
+        let timing = |element: TableElement| {
+            deferrable
+                .iter()
+                .find(|(deferrable_element, _)| *deferrable_element == element)
+                .map(|(_, timing)| *timing)
+        };
+        let mut declared = vec![];
+        for (i, col_def) in column_defs.iter().enumerate() {
+            for opt in col_def.options.iter() {
+                if let sqlparser::ast::ColumnOption::ForeignKey {
+                    foreign_table,
+                    referred_columns,
+                    on_delete,
+                    on_update,
+                } = &opt.option
+                {
+                    declared.push((
+                        opt.name.as_ref(),
+                        vec![col_def.name.clone()],
+                        foreign_table,
+                        referred_columns,
+                        [on_delete, on_update],
+                        timing(TableElement::Column(i)),
+                    ));
+                }
+            }
+        }
+        for (i, constraint) in constraints.iter().enumerate() {
+            if let sqlparser::ast::TableConstraint::ForeignKey {
+                name,
+                columns,
+                foreign_table,
+                referred_columns,
+                on_delete,
+                on_update,
+            } = constraint
+            {
+                declared.push((
+                    name.as_ref(),
+                    columns.clone(),
+                    foreign_table,
+                    referred_columns,
+                    [on_delete, on_update],
+                    timing(TableElement::Constraint(i)),
+                ));
+            }
+        }
+
+        let mut foreign_keys: Vec<ForeignKey> = vec![];
+        for (fk_name, fk_columns, foreign_table, referred_columns, actions, deferrable) in declared
+        {
+            for action in actions.into_iter().flatten() {
+                if !matches!(
+                    action,
+                    sqlparser::ast::ReferentialAction::NoAction
+                        | sqlparser::ast::ReferentialAction::Restrict
+                ) {
+                    return Err(BustubxError::NotSupport(format!(
+                        "foreign key action {} not supported",
+                        action
+                    )));
+                }
+            }
+            let fk_columns = fk_columns
+                .iter()
+                .map(|ident| ident.value.clone())
+                .collect::<Vec<_>>();
+            let parent = self.bind_table_name(foreign_table)?;
+            let parent = TableReference::partial(
+                parent.schema().unwrap_or(DEFAULT_SCHEMA_NAME),
+                parent.table(),
+            );
+            let parent_table = self.context.catalog.catalog_table(&parent)?;
+            if parent_table.partitioning.is_some() {
+                return Err(BustubxError::NotSupport(format!(
+                    "foreign key referencing partitioned table {} not supported",
+                    parent
+                )));
+            }
+            if referred_columns.is_empty() {
+                return Err(BustubxError::NotSupport(format!(
+                    "foreign key referencing {} must name the referenced columns",
+                    parent
+                )));
+            }
+            let parent_columns = referred_columns
+                .iter()
+                .map(|ident| ident.value.clone())
+                .collect::<Vec<_>>();
+            if parent_columns.len() != fk_columns.len() {
+                return Err(BustubxError::Plan(format!(
+                    "foreign key of {} columns references {} columns of {}",
+                    fk_columns.len(),
+                    parent_columns.len(),
+                    parent
+                )));
+            }
+            for (column_name, parent_column_name) in fk_columns.iter().zip(&parent_columns) {
+                let Some(col) = columns.iter().find(|col| &col.name == column_name) else {
+                    return Err(BustubxError::Plan(format!(
+                        "foreign key column {} does not exist",
+                        column_name
+                    )));
+                };
+                let parent_col = parent_table
+                    .table
+                    .schema
+                    .column_with_name(None, parent_column_name)?;
+                if col.data_type != parent_col.data_type {
+                    return Err(BustubxError::Plan(format!(
+                        "foreign key column {} of type {} cannot reference {}.{} of type {}",
+                        column_name,
+                        col.data_type,
+                        parent,
+                        parent_column_name,
+                        parent_col.data_type
+                    )));
+                }
+            }
+            if !key_index(parent_table, &parent_columns).is_some_and(|index| index.unique) {
+                return Err(BustubxError::Plan(format!(
+                    "there is no unique index on ({}) of referenced table {}",
+                    parent_columns.join(", "),
+                    parent
+                )));
+            }
+            let fk_name = fk_name.map_or_else(
+                || ForeignKey::default_name(name.table(), &fk_columns),
+                |ident| ident.value.clone(),
+            );
+            if foreign_keys
+                .iter()
+                .any(|foreign_key| foreign_key.name == fk_name)
+            {
+                return Err(BustubxError::Plan(format!(
+                    "foreign key {} already exists",
+                    fk_name
+                )));
+            }
+            foreign_keys.push(ForeignKey {
+                name: fk_name,
+                columns: fk_columns,
+                parent,
+                parent_columns,
+                deferrable,
+            });
+        }
+        Ok(foreign_keys)
+    }
+}
+
+/// `WITH (ttl_column = 'expires_at')` names a BIGINT column holding the epoch seconds
+/// after which the row expires.
+fn bind_ttl_column(value: &sqlparser::ast::Value, columns: &[Column]) -> BustubxResult<String> {
+    let sqlparser::ast::Value::SingleQuotedString(name) = value else {
+        return Err(BustubxError::Plan(format!(
+            "ttl_column must be a string literal, got {}",
+            value
+        )));
+    };
+    let Some(col) = columns.iter().find(|col| &col.name == name) else {
+        return Err(BustubxError::Plan(format!(
+            "ttl column {} does not exist",
+            name
+        )));
+    };
+    if col.data_type != DataType::Int64 {
+        return Err(BustubxError::Plan(format!(
+            "ttl column {} must be BIGINT, got {}",
+            name, col.data_type
+        )));
+    }
+    Ok(name.clone())
+}
+
+/// `WITH (logical_row_ids = true)` makes the indexes of the table point at stable row ids
+/// instead of record ids, so VACUUM FULL only remaps the row ids.
+fn bind_logical_row_ids(value: &sqlparser::ast::Value) -> BustubxResult<bool> {
+    match value {
+        sqlparser::ast::Value::Boolean(enabled) => Ok(*enabled),
+        sqlparser::ast::Value::SingleQuotedString(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        sqlparser::ast::Value::SingleQuotedString(s) if s.eq_ignore_ascii_case("false") => {
+            Ok(false)
+        }
+        _ => Err(BustubxError::Plan(format!(
+            "logical_row_ids must be a boolean, got {}",
+            value
+        ))),
     }
 }
 
@@ -73,3 +478,69 @@ fn check_column_name_conflict(columns: &[Column]) -> BustubxResult<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::ForeignKey;
+    use crate::common::TableReference;
+    use crate::storage::index::ConstraintTiming;
+    use crate::{BustubxError, Database};
+
+    #[test]
+    pub fn test_create_table_binds_foreign_keys() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table parent (id int, name varchar(10))")
+            .unwrap();
+        db.run("create index idx_name on parent (name)").unwrap();
+        // no unique index on the referenced columns
+        assert!(matches!(
+            db.run("create table child (id int, parent_id int references parent (id))"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("create table child (id int, name varchar(10) references parent (name))"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("create unique index idx_id on parent (id)").unwrap();
+        for sql in [
+            "create table child (id int, parent_id int references parent (id) on delete cascade)",
+            "create table child (id int, parent_id int references parent)",
+            "create table child (id int, ts int, parent_id int references parent (id)) \
+             partition by range (ts)",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(BustubxError::NotSupport(_))),
+                "{sql}"
+            );
+        }
+        for sql in [
+            "create table child (id int, parent_id bigint references parent (id))",
+            "create table child (id int, foreign key (parent_id) references parent (id))",
+            "create table child (id int, parent_id int references missing (id))",
+            "create table child (id int deferrable, parent_id int)",
+        ] {
+            assert!(db.run(sql).is_err(), "{sql}");
+        }
+        assert!(db.run("select * from child").is_err());
+
+        db.run(
+            "create table child (id int, parent_id int references parent (id) \
+             on delete restrict deferrable)",
+        )
+        .unwrap();
+        let catalog_table = db
+            .catalog
+            .catalog_table(&TableReference::bare("child"))
+            .unwrap();
+        assert_eq!(
+            catalog_table.foreign_keys,
+            vec![ForeignKey {
+                name: "child_parent_id_fkey".to_string(),
+                columns: vec!["parent_id".to_string()],
+                parent: TableReference::partial("public", "parent"),
+                parent_columns: vec!["id".to_string()],
+                deferrable: Some(ConstraintTiming::Immediate),
+            }]
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use crate::planner::logical_plan::{Delete, LogicalPlan};
+use crate::planner::LogicalPlanner;
+use crate::{BustubxError, BustubxResult};
+
+impl<'a> LogicalPlanner<'a> {
+    pub fn plan_delete(
+        &self,
+        from: &[sqlparser::ast::TableWithJoins],
+        selection: &Option<sqlparser::ast::Expr>,
+    ) -> BustubxResult<LogicalPlan> {
+        let table_ref = match from {
+            [sqlparser::ast::TableWithJoins { relation, joins }] if joins.is_empty() => {
+                match relation {
+                    sqlparser::ast::TableFactor::Table { name, .. } => {
+                        self.bind_table_name(name)?
+                    }
+                    _ => {
+                        return Err(BustubxError::Plan(format!(
+                            "table {} is not supported",
+                            relation
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(BustubxError::NotSupport(
+                    "delete only supports a single table".to_string(),
+                ))
+            }
+        };
+        self.context.catalog.check_table_writable(&table_ref)?;
+
+        if self
+            .context
+            .catalog
+            .catalog_table(&table_ref)?
+            .is_partitioned()
+        {
+            return Err(BustubxError::NotSupport(format!(
+                "delete of partitioned table {} not supported, delete its partitions",
+                table_ref
+            )));
+        }
+        let table_schema = self.context.catalog.table_heap(&table_ref)?.schema.clone();
+
+        let selection = match selection {
+            Some(e) => Some(self.bind_predicate(e, &table_schema)?),
+            None => None,
+        };
+
+        Ok(LogicalPlan::Delete(Delete {
+            table: table_ref,
+            table_schema,
+            selection,
+            order_by: vec![],
+            limit: None,
+        }))
+    }
+}
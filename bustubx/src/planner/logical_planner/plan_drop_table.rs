@@ -0,0 +1,39 @@
+use crate::planner::logical_plan::{DropTable, LogicalPlan};
+use crate::{BustubxError, BustubxResult};
+
+use super::LogicalPlanner;
+
+impl<'a> LogicalPlanner<'a> {
+    /// Dropping a partition removes its range from the partitioned table, dropping a
+    /// partitioned table drops its partitions too. Read only tables cannot be dropped.
+    pub fn plan_drop_table(
+        &self,
+        object_type: &sqlparser::ast::ObjectType,
+        if_exists: bool,
+        names: &[sqlparser::ast::ObjectName],
+    ) -> BustubxResult<LogicalPlan> {
+        if !matches!(object_type, sqlparser::ast::ObjectType::Table) {
+            return Err(BustubxError::NotSupport(format!(
+                "drop {} not supported",
+                object_type
+            )));
+        }
+        let mut tables = vec![];
+        for name in names {
+            let table = self.bind_table_name(name)?;
+            if self.context.catalog.catalog_table(&table).is_err() {
+                if if_exists {
+                    continue;
+                }
+                return Err(BustubxError::Plan(format!(
+                    "table {} does not exist",
+                    table
+                )));
+            }
+            self.context.catalog.check_table_writable(&table)?;
+            tables.push(table);
+        }
+        self.context.catalog.check_drop_unreferenced(&tables)?;
+        Ok(LogicalPlan::DropTable(DropTable::new(tables)))
+    }
+}
@@ -1,7 +1,9 @@
-use crate::BustubxResult;
+use crate::common::TableReference;
+use crate::{BustubxError, BustubxResult};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::planner::logical_plan::{Insert, LogicalPlan, Values};
+use crate::planner::logical_plan::{Insert, LogicalPlan, OnConflict, OnConflictAction, Values};
 
 use super::LogicalPlanner;
 
@@ -9,11 +11,13 @@ impl<'a> LogicalPlanner<'a> {
     pub fn plan_insert(
         &self,
         table_name: &sqlparser::ast::ObjectName,
-        columns_ident: &Vec<sqlparser::ast::Ident>,
+        columns_ident: &[sqlparser::ast::Ident],
         source: &sqlparser::ast::Query,
+        on: &Option<sqlparser::ast::OnInsert>,
     ) -> BustubxResult<LogicalPlan> {
         let mut input = self.plan_set_expr(source.body.as_ref())?;
         let table = self.bind_table_name(table_name)?;
+        self.context.catalog.check_table_writable(&table)?;
         let table_schema = self.context.catalog.table_heap(&table)?.schema.clone();
 
         let projected_schema = if columns_ident.is_empty() {
@@ -38,11 +42,118 @@ impl<'a> LogicalPlanner<'a> {
             })
         }
 
+        let on_conflict = match on {
+            None => None,
+            Some(sqlparser::ast::OnInsert::OnConflict(on_conflict)) => {
+                Some(self.bind_on_conflict(&table, on_conflict)?)
+            }
+            Some(on) => {
+                return Err(BustubxError::NotSupport(format!(
+                    "insert {} not supported",
+                    on
+                )))
+            }
+        };
+
         Ok(LogicalPlan::Insert(Insert {
             table,
             table_schema,
             projected_schema,
             input: Arc::new(input),
+            on_conflict,
         }))
     }
+
+    fn bind_on_conflict(
+        &self,
+        table: &TableReference,
+        on_conflict: &sqlparser::ast::OnConflict,
+    ) -> BustubxResult<OnConflict> {
+        let indexes = &self.context.catalog.catalog_table(table)?.indexes;
+        let index_name = match &on_conflict.conflict_target {
+            Some(sqlparser::ast::ConflictTarget::Columns(columns)) => {
+                let mut target: Vec<&str> = columns.iter().map(|c| c.value.as_str()).collect();
+                target.sort();
+                let index_name = indexes
+                    .iter()
+                    .filter(|(_, index)| index.unique)
+                    .find(|(_, index)| {
+                        let mut keys: Vec<&str> = index
+                            .key_schema
+                            .columns
+                            .iter()
+                            .map(|col| col.name.as_str())
+                            .collect();
+                        keys.sort();
+                        keys == target
+                    })
+                    .map(|(name, _)| name.clone())
+                    .ok_or_else(|| {
+                        BustubxError::Plan(format!(
+                            "there is no unique index on {} ({})",
+                            table,
+                            target.join(", ")
+                        ))
+                    })?;
+                Some(index_name)
+            }
+            Some(sqlparser::ast::ConflictTarget::OnConstraint(name)) => {
+                let index_name = name.to_string();
+                if !indexes.get(&index_name).is_some_and(|index| index.unique) {
+                    return Err(BustubxError::Plan(format!(
+                        "unique index {} does not exist",
+                        index_name
+                    )));
+                }
+                Some(index_name)
+            }
+            None => None,
+        };
+        // the keys of a deferrable index may repeat until the check, they decide nothing
+        if let Some(index_name) = index_name
+            .as_ref()
+            .filter(|index_name| indexes[*index_name].deferrable.is_some())
+        {
+            return Err(BustubxError::Plan(format!(
+                "ON CONFLICT does not support deferrable unique index {}",
+                index_name
+            )));
+        }
+
+        let action = match &on_conflict.action {
+            sqlparser::ast::OnConflictAction::DoNothing => OnConflictAction::DoNothing,
+            sqlparser::ast::OnConflictAction::DoUpdate(do_update) => {
+                if index_name.is_none() {
+                    return Err(BustubxError::Plan(
+                        "ON CONFLICT DO UPDATE requires a conflict target".to_string(),
+                    ));
+                }
+                let table_schema = self.context.catalog.table_heap(table)?.schema.clone();
+                let mut assignments = HashMap::new();
+                for assign in do_update.assignments.iter() {
+                    let column_name = assign
+                        .id
+                        .first()
+                        .ok_or(BustubxError::Plan(format!(
+                            "Assignment {} is not supported",
+                            assign
+                        )))?
+                        .value
+                        .clone();
+                    table_schema.index_of(None, &column_name)?;
+                    assignments.insert(column_name, self.bind_expr(&assign.value)?);
+                }
+                let selection = match &do_update.selection {
+                    Some(e) => Some(self.bind_expr(e)?),
+                    None => None,
+                };
+                OnConflictAction::DoUpdate {
+                    assignments,
+                    selection,
+                }
+            }
+        };
+
+        Ok(OnConflict { index_name, action })
+    }
 }
@@ -0,0 +1,130 @@
+use crate::catalog::{
+    ANALYZE_OUTPUT_SCHEMA_REF, CHECKPOINT_OUTPUT_SCHEMA_REF, CHECK_TABLE_OUTPUT_SCHEMA_REF,
+    CLUSTER_OUTPUT_SCHEMA_REF, DEFAULT_SCHEMA_NAME, REINDEX_OUTPUT_SCHEMA_REF,
+    VACUUM_OUTPUT_SCHEMA_REF,
+};
+use crate::common::TableReference;
+use crate::parser::{MaintenanceStatement, ReindexTarget};
+use crate::planner::logical_plan::{LogicalPlan, Maintenance, MaintenanceKind};
+use crate::{BustubxError, BustubxResult};
+
+use super::LogicalPlanner;
+
+impl<'a> LogicalPlanner<'a> {
+    /// Without a table VACUUM and ANALYZE cover every user table.
+    pub fn plan_maintenance(&self, stmt: &MaintenanceStatement) -> BustubxResult<LogicalPlan> {
+        let (kind, table, schema) = match stmt {
+            MaintenanceStatement::Vacuum { full, table } => (
+                MaintenanceKind::Vacuum { full: *full },
+                table,
+                VACUUM_OUTPUT_SCHEMA_REF.clone(),
+            ),
+            MaintenanceStatement::Analyze { table } => (
+                MaintenanceKind::Analyze,
+                table,
+                ANALYZE_OUTPUT_SCHEMA_REF.clone(),
+            ),
+            MaintenanceStatement::Checkpoint => {
+                return Ok(LogicalPlan::Maintenance(Maintenance::new(
+                    MaintenanceKind::Checkpoint,
+                    vec![],
+                    CHECKPOINT_OUTPUT_SCHEMA_REF.clone(),
+                )));
+            }
+            MaintenanceStatement::Reindex { target } => return self.plan_reindex(target),
+            MaintenanceStatement::CheckTable {
+                table,
+                with_indexes,
+            } => {
+                return Ok(LogicalPlan::Maintenance(Maintenance::new(
+                    MaintenanceKind::CheckTable {
+                        with_indexes: *with_indexes,
+                    },
+                    vec![self.bind_existing_table(table)?],
+                    CHECK_TABLE_OUTPUT_SCHEMA_REF.clone(),
+                )));
+            }
+            MaintenanceStatement::Cluster { table, index } => {
+                let table_ref = self.bind_existing_table(table)?;
+                // rewrites the rows in index order
+                self.context.catalog.check_table_writable(&table_ref)?;
+                let index = index.value.clone();
+                if !self
+                    .context
+                    .catalog
+                    .catalog_table(&table_ref)?
+                    .indexes
+                    .contains_key(&index)
+                {
+                    return Err(BustubxError::Plan(format!(
+                        "index {} of table {} does not exist",
+                        index, table_ref
+                    )));
+                }
+                return Ok(LogicalPlan::Maintenance(Maintenance::new(
+                    MaintenanceKind::Cluster { index },
+                    vec![table_ref],
+                    CLUSTER_OUTPUT_SCHEMA_REF.clone(),
+                )));
+            }
+            MaintenanceStatement::ResetStats => {
+                return Err(BustubxError::Internal(
+                    "RESET STATS is run without a plan".to_string(),
+                ))
+            }
+        };
+        let tables = match table {
+            Some(table_name) => vec![self.bind_existing_table(table_name)?],
+            None => self.context.catalog.user_tables(),
+        };
+        Ok(LogicalPlan::Maintenance(Maintenance::new(
+            kind, tables, schema,
+        )))
+    }
+
+    // an index is looked up by name among the tables of its schema
+    fn plan_reindex(&self, target: &ReindexTarget) -> BustubxResult<LogicalPlan> {
+        let (index, table_ref) = match target {
+            ReindexTarget::Table(table_name) => (None, self.bind_existing_table(table_name)?),
+            ReindexTarget::Index(index_name) => {
+                let index_ref = self.bind_table_name(index_name)?;
+                let schema_name = index_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+                let mut tables = self
+                    .context
+                    .catalog
+                    .tables_with_index(schema_name, index_ref.table());
+                if tables.len() > 1 {
+                    return Err(BustubxError::Plan(format!(
+                        "index {} exists on several tables, use REINDEX TABLE",
+                        index_ref
+                    )));
+                }
+                let Some(table_ref) = tables.pop() else {
+                    return Err(BustubxError::Plan(format!(
+                        "index {} does not exist",
+                        index_ref
+                    )));
+                };
+                (Some(index_ref.table().to_string()), table_ref)
+            }
+        };
+        self.context.catalog.check_table_writable(&table_ref)?;
+        Ok(LogicalPlan::Maintenance(Maintenance::new(
+            MaintenanceKind::Reindex { index },
+            vec![table_ref],
+            REINDEX_OUTPUT_SCHEMA_REF.clone(),
+        )))
+    }
+
+    fn bind_existing_table(
+        &self,
+        table_name: &sqlparser::ast::ObjectName,
+    ) -> BustubxResult<TableReference> {
+        let table_ref = self.bind_table_name(table_name)?;
+        self.context
+            .catalog
+            .catalog_table(&table_ref)
+            .map_err(|_| BustubxError::Plan(format!("table {} does not exist", table_ref)))?;
+        Ok(table_ref)
+    }
+}
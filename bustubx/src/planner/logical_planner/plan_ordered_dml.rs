@@ -0,0 +1,35 @@
+use crate::parser::OrderedDml;
+use crate::planner::logical_plan::{Delete, LogicalPlan, Update};
+use crate::planner::LogicalPlanner;
+use crate::{BustubxError, BustubxResult};
+
+impl<'a> LogicalPlanner<'a> {
+    /// DELETE or UPDATE changing the first rows of its ORDER BY, up to its LIMIT.
+    pub fn plan_ordered_dml(&mut self, dml: &OrderedDml) -> BustubxResult<LogicalPlan> {
+        let mut order_by = vec![];
+        for order in &dml.order_by {
+            order_by.push(self.bind_order_by_expr(order)?);
+        }
+        let limit = match &dml.limit {
+            Some(limit) => Some(self.bind_limit(limit)?),
+            None => None,
+        };
+
+        match self.plan(&dml.statement)? {
+            LogicalPlan::Delete(delete) => Ok(LogicalPlan::Delete(Delete {
+                order_by,
+                limit,
+                ..delete
+            })),
+            LogicalPlan::Update(update) => Ok(LogicalPlan::Update(Update {
+                order_by,
+                limit,
+                ..update
+            })),
+            plan => Err(BustubxError::Plan(format!(
+                "ORDER BY and LIMIT only apply to DELETE and UPDATE, not {}",
+                plan
+            ))),
+        }
+    }
+}
@@ -1,17 +1,187 @@
+use crate::catalog::Schema;
 use crate::common::ScalarValue;
-use crate::expression::Expr;
+use crate::expression::{columnize_expr, Alias, ColumnExpr, Expr};
 use crate::{BustubxError, BustubxResult};
 use std::sync::Arc;
 
-use crate::planner::logical_plan::{Limit, LogicalPlan, Sort};
+use crate::planner::logical_plan::{
+    project_schema, Limit, LogicalPlan, OrderByExpr, Project, Sort,
+};
 
 use super::LogicalPlanner;
 
 impl<'a> LogicalPlanner<'a> {
     pub fn plan_query(&self, query: &sqlparser::ast::Query) -> BustubxResult<LogicalPlan> {
-        let plan = self.plan_set_expr(&query.body)?;
-        let plan = self.plan_order_by(plan, &query.order_by)?;
-        self.plan_limit(plan, &query.limit, &query.offset)
+        let (plan, visible_columns) = match query.body.as_ref() {
+            sqlparser::ast::SetExpr::Select(select) if !query.order_by.is_empty() => {
+                self.plan_select_order_by(select, &query.order_by)?
+            }
+            body => {
+                let plan = self.plan_set_expr(body)?;
+                (self.plan_order_by(plan, &query.order_by)?, None)
+            }
+        };
+        let plan = self.plan_limit(plan, &query.limit, &query.offset)?;
+        match visible_columns {
+            Some(visible_columns) => self.plan_hide_columns(plan, visible_columns),
+            None => Ok(plan),
+        }
+    }
+
+    /// SELECT with ORDER BY. A sort key naming a select item alias refers to that item, an
+    /// integer literal to the select item at that position (from 1), any other expression
+    /// is evaluated on the select items or, if it reads columns they do not provide, on the
+    /// input of the projection. Keys of the latter kind are computed by hidden columns
+    /// appended to the projection, their number of visible columns is returned along with
+    /// the plan so they are dropped once sorted.
+    fn plan_select_order_by(
+        &self,
+        select: &sqlparser::ast::Select,
+        order_by: &[sqlparser::ast::OrderByExpr],
+    ) -> BustubxResult<(LogicalPlan, Option<usize>)> {
+        // select items sharing a name do not plan, a sort key naming them is reported instead
+        for order in order_by {
+            let sqlparser::ast::Expr::Identifier(ident) = &order.expr else {
+                continue;
+            };
+            let matches = select
+                .projection
+                .iter()
+                .filter(|item| select_item_name(item) == Some(ident.value.as_str()))
+                .count();
+            if matches > 1 {
+                return Err(BustubxError::Plan(format!(
+                    "ORDER BY {} is ambiguous, {} select items are named {}",
+                    ident, matches, ident.value
+                )));
+            }
+        }
+        let LogicalPlan::Project(Project {
+            mut exprs, input, ..
+        }) = self.plan_select(select)?
+        else {
+            return Err(BustubxError::Internal(
+                "select is not planned as a projection".to_string(),
+            ));
+        };
+        let output_schema = project_schema(&input, &exprs)?;
+        let visible_columns = exprs.len();
+
+        let mut order_by_exprs = vec![];
+        for order in order_by {
+            let expr =
+                match self.bind_sort_key_in_select_list(&order.expr, &exprs, &output_schema)? {
+                    Some(expr) => expr,
+                    None => {
+                        let expr = self.bind_expr(&order.expr)?;
+                        // a column of the input is kept as is by columnize_expr
+                        let column = columnize_expr(&expr, &Arc::new(output_schema.clone()))
+                            .ok()
+                            .filter(|column| column.is_bound_by(&output_schema));
+                        if let Some(column) = column {
+                            column
+                        } else if expr.is_bound_by(&output_schema)
+                            && !matches!(expr, Expr::AggregateFunction(_))
+                        {
+                            expr
+                        } else {
+                            let name = format!("__sort_key{}", exprs.len() - visible_columns);
+                            let hidden = columnize_expr(&expr, input.schema()).unwrap_or(expr);
+                            exprs.push(Expr::Alias(Alias {
+                                expr: Box::new(hidden),
+                                name: name.clone(),
+                            }));
+                            Expr::Column(ColumnExpr {
+                                relation: None,
+                                name,
+                            })
+                        }
+                    }
+                };
+            let asc = order.asc.unwrap_or(true);
+            order_by_exprs.push(OrderByExpr {
+                expr: Box::new(expr),
+                asc,
+                nulls_first: order.nulls_first.unwrap_or(asc),
+            });
+        }
+
+        let hidden_columns = exprs.len() > visible_columns;
+        let schema = Arc::new(project_schema(&input, &exprs)?);
+        let plan = LogicalPlan::Sort(Sort {
+            order_by: order_by_exprs,
+            input: Arc::new(LogicalPlan::Project(Project {
+                exprs,
+                input,
+                schema,
+            })),
+            limit: None,
+        });
+        Ok((plan, hidden_columns.then_some(visible_columns)))
+    }
+
+    // Column of the select list an alias or position refers to, None for other expressions.
+    fn bind_sort_key_in_select_list(
+        &self,
+        expr: &sqlparser::ast::Expr,
+        select_exprs: &[Expr],
+        output_schema: &Schema,
+    ) -> BustubxResult<Option<Expr>> {
+        let column_expr = |idx: usize| {
+            let column = &output_schema.columns[idx];
+            Expr::Column(ColumnExpr {
+                relation: column.relation.clone(),
+                name: column.name.clone(),
+            })
+        };
+        match expr {
+            sqlparser::ast::Expr::Identifier(ident)
+                if select_exprs
+                    .iter()
+                    .any(|e| matches!(e, Expr::Alias(alias) if alias.name == ident.value)) =>
+            {
+                let idx = output_schema
+                    .columns
+                    .iter()
+                    .position(|column| column.name == ident.value)
+                    .ok_or_else(|| {
+                        BustubxError::Internal(format!("select item {} not found", ident))
+                    })?;
+                Ok(Some(column_expr(idx)))
+            }
+            sqlparser::ast::Expr::Value(sqlparser::ast::Value::Number(position, _)) => {
+                let count = output_schema.column_count();
+                match position.parse::<usize>() {
+                    Ok(position) if (1..=count).contains(&position) => {
+                        Ok(Some(column_expr(position - 1)))
+                    }
+                    _ => Err(BustubxError::Plan(format!(
+                        "ORDER BY position {} is not in select list, it has {} items",
+                        position, count
+                    ))),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Projection keeping the first `count` columns of the input.
+    fn plan_hide_columns(&self, input: LogicalPlan, count: usize) -> BustubxResult<LogicalPlan> {
+        let exprs = input.schema().columns[..count]
+            .iter()
+            .map(|column| {
+                Expr::Column(ColumnExpr {
+                    relation: column.relation.clone(),
+                    name: column.name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let schema = Arc::new(project_schema(&input, &exprs)?);
+        Ok(LogicalPlan::Project(Project {
+            exprs,
+            input: Arc::new(input),
+            schema,
+        }))
     }
 
     pub fn plan_order_by(
@@ -47,22 +217,7 @@ impl<'a> LogicalPlanner<'a> {
 
         let limit = match limit {
             None => None,
-            Some(limit_expr) => {
-                let n = match self.bind_expr(limit_expr)? {
-                    Expr::Literal(lit) => match lit.value {
-                        ScalarValue::Int64(Some(v)) if v >= 0 => Ok(v as usize),
-                        _ => Err(BustubxError::Plan(format!(
-                            "LIMIT must not be negative, {}",
-                            lit.value
-                        ))),
-                    },
-                    _ => Err(BustubxError::Plan(format!(
-                        "LIMIT must be literal, {}",
-                        limit_expr
-                    ))),
-                }?;
-                Some(n)
-            }
+            Some(limit_expr) => Some(self.bind_limit(limit_expr)?),
         };
 
         let offset = match offset {
@@ -93,4 +248,112 @@ impl<'a> LogicalPlanner<'a> {
             input: Arc::new(input),
         }))
     }
+
+    pub fn bind_limit(&self, limit_expr: &sqlparser::ast::Expr) -> BustubxResult<usize> {
+        match self.bind_expr(limit_expr)? {
+            Expr::Literal(lit) => match lit.value {
+                ScalarValue::Int64(Some(v)) if v >= 0 => Ok(v as usize),
+                _ => Err(BustubxError::Plan(format!(
+                    "LIMIT must not be negative, {}",
+                    lit.value
+                ))),
+            },
+            _ => Err(BustubxError::Plan(format!(
+                "LIMIT must be literal, {}",
+                limit_expr
+            ))),
+        }
+    }
+}
+
+// Name of the column a select item produces, None if it is not known before binding
+fn select_item_name(item: &sqlparser::ast::SelectItem) -> Option<&str> {
+    match item {
+        sqlparser::ast::SelectItem::ExprWithAlias { alias, .. } => Some(&alias.value),
+        sqlparser::ast::SelectItem::UnnamedExpr(sqlparser::ast::Expr::Identifier(ident)) => {
+            Some(&ident.value)
+        }
+        sqlparser::ast::SelectItem::UnnamedExpr(sqlparser::ast::Expr::CompoundIdentifier(
+            idents,
+        )) => idents.last().map(|ident| ident.value.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database};
+
+    fn first_column(db: &mut Database, sql: &str, column_count: usize) -> Vec<i64> {
+        db.run(sql)
+            .unwrap()
+            .iter()
+            .map(|tuple| {
+                assert_eq!(tuple.schema.column_count(), column_count, "{sql}");
+                match tuple.data[0] {
+                    ScalarValue::Int32(Some(v)) => v as i64,
+                    ScalarValue::Int64(Some(v)) => v,
+                    ref v => panic!("unexpected value {v}"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_order_by_resolution() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("insert into t1 values (1, 5), (2, 3), (3, 4), (4, 1), (5, 2)")
+            .unwrap();
+
+        // alias
+        assert_eq!(
+            first_column(&mut db, "select a * 2 as x, b from t1 order by x desc", 2),
+            vec![10, 8, 6, 4, 2]
+        );
+        // an alias comes before a column of the input
+        assert_eq!(
+            first_column(&mut db, "select a as b from t1 order by b", 1),
+            vec![1, 2, 3, 4, 5]
+        );
+        // position
+        assert_eq!(
+            first_column(&mut db, "select b, a from t1 order by 2 desc", 2),
+            vec![2, 1, 4, 3, 5]
+        );
+        // expression over the select items
+        assert_eq!(
+            first_column(&mut db, "select a, b from t1 order by a + b desc, a", 2),
+            vec![3, 5, 1, 2, 4]
+        );
+        // expressions over the input, sorted on hidden columns
+        assert_eq!(
+            first_column(&mut db, "select a from t1 order by b desc", 1),
+            vec![1, 3, 2, 5, 4]
+        );
+        assert_eq!(
+            first_column(&mut db, "select a from t1 order by a + b, a desc", 1),
+            vec![4, 2, 1, 5, 3]
+        );
+        assert_eq!(
+            first_column(&mut db, "select a from t1 order by b limit 2", 1),
+            vec![4, 5]
+        );
+        assert_eq!(
+            first_column(&mut db, "select a as x from t1 order by 1 desc, b", 1),
+            vec![5, 4, 3, 2, 1]
+        );
+
+        for (sql, message) in [
+            ("select a as x, b as x from t1 order by x", "ambiguous"),
+            ("select a, b from t1 order by 3", "position 3"),
+            ("select a, b from t1 order by 0", "position 0"),
+        ] {
+            match db.run(sql) {
+                Err(BustubxError::Plan(e)) => assert!(e.contains(message), "{sql}: {e}"),
+                result => panic!("{sql}: {result:?}"),
+            }
+        }
+    }
 }
@@ -0,0 +1,90 @@
+use crate::catalog::INFORMATION_SCHEMA_NAME;
+use crate::common::TableReference;
+use crate::planner::logical_plan::{CreateSchema, DropSchema, LogicalPlan};
+use crate::{BustubxError, BustubxResult};
+
+use super::LogicalPlanner;
+
+impl<'a> LogicalPlanner<'a> {
+    pub fn plan_create_schema(
+        &self,
+        schema_name: &sqlparser::ast::SchemaName,
+        if_not_exists: bool,
+    ) -> BustubxResult<LogicalPlan> {
+        let sqlparser::ast::SchemaName::Simple(name) = schema_name else {
+            return Err(BustubxError::NotSupport(format!(
+                "create schema {} not supported",
+                schema_name
+            )));
+        };
+        let schema_name = bind_schema_name(name)?;
+        if !if_not_exists && self.context.catalog.schemas.contains_key(&schema_name) {
+            return Err(BustubxError::Plan(format!(
+                "schema {} already exists",
+                schema_name
+            )));
+        }
+        Ok(LogicalPlan::CreateSchema(CreateSchema::new(
+            schema_name,
+            if_not_exists,
+        )))
+    }
+
+    /// Only CASCADE drops a schema holding tables, the tables go with it.
+    pub fn plan_drop_schema(
+        &self,
+        if_exists: bool,
+        names: &[sqlparser::ast::ObjectName],
+        cascade: bool,
+    ) -> BustubxResult<LogicalPlan> {
+        let mut schema_names = vec![];
+        let mut tables = vec![];
+        for name in names {
+            let schema_name = bind_schema_name(name)?;
+            if schema_name == INFORMATION_SCHEMA_NAME {
+                return Err(BustubxError::Plan(format!(
+                    "schema {} cannot be dropped",
+                    schema_name
+                )));
+            }
+            let Some(catalog_schema) = self.context.catalog.schemas.get(&schema_name) else {
+                if if_exists {
+                    continue;
+                }
+                return Err(BustubxError::Plan(format!(
+                    "schema {} does not exist",
+                    schema_name
+                )));
+            };
+            if !cascade && !catalog_schema.tables.is_empty() {
+                return Err(BustubxError::Plan(format!(
+                    "schema {} is not empty, drop it with CASCADE",
+                    schema_name
+                )));
+            }
+            let mut table_names = catalog_schema.tables.keys().collect::<Vec<_>>();
+            table_names.sort();
+            for table_name in table_names {
+                let table = TableReference::partial(&schema_name, table_name);
+                self.context.catalog.check_table_writable(&table)?;
+                tables.push(table);
+            }
+            schema_names.push(schema_name);
+        }
+        self.context.catalog.check_drop_unreferenced(&tables)?;
+        Ok(LogicalPlan::DropSchema(DropSchema::new(
+            schema_names,
+            tables,
+        )))
+    }
+}
+
+fn bind_schema_name(name: &sqlparser::ast::ObjectName) -> BustubxResult<String> {
+    match name.0.as_slice() {
+        [schema] => Ok(schema.value.clone()),
+        _ => Err(BustubxError::Plan(format!(
+            "Fail to plan schema name: {}",
+            name
+        ))),
+    }
+}
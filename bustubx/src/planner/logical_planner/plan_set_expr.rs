@@ -1,15 +1,29 @@
-use crate::catalog::{Column, Schema};
-use crate::expression::{columnize_expr, Alias, ColumnExpr, Expr, ExprTrait};
+use crate::catalog::SchemaRef;
+use crate::catalog::{
+    Column, Schema, INFORMATION_SCHEMA_NAME, INFORMATION_SCHEMA_RELATION_SIZES,
+    INFORMATION_SCHEMA_STATISTICS,
+};
+use crate::expression::{
+    columnize_expr, AggregateFunction, Alias, BinaryExpr, BinaryOp, Cast, ColumnExpr, Expr,
+    ExprTrait,
+};
+use crate::function::AggregateFunctionKind;
 use crate::planner::logical_plan::{
     build_join_schema, project_schema, EmptyRelation, Filter, Join, LogicalPlan, Project,
     TableScan, Values,
 };
 use crate::planner::logical_plan::{Aggregate, JoinType};
+use crate::planner::logical_planner::plan_subquery::{
+    is_scalar_subquery_predicate, is_subquery_predicate, split_conjunction,
+};
 use crate::planner::LogicalPlanner;
 use crate::{BustubxError, BustubxResult};
 use std::sync::Arc;
 use std::vec;
 
+// CUBE expands to 2^n grouping sets
+const MAX_CUBE_ITEMS: usize = 12;
+
 impl LogicalPlanner<'_> {
     pub fn plan_set_expr(&self, set_expr: &sqlparser::ast::SetExpr) -> BustubxResult<LogicalPlan> {
         match set_expr {
@@ -25,8 +39,14 @@ impl LogicalPlanner<'_> {
     pub fn plan_select(&self, select: &sqlparser::ast::Select) -> BustubxResult<LogicalPlan> {
         let table_scan = self.plan_from_tables(&select.from)?;
         let selection = self.plan_selection(table_scan, &select.selection)?;
-        let aggregate = self.plan_aggregate(selection, &select.projection, &select.group_by)?;
-        self.plan_project(aggregate, &select.projection)
+        let aggregate = self.plan_aggregate(
+            selection,
+            &select.projection,
+            &select.group_by,
+            &select.having,
+        )?;
+        let having = self.plan_having(aggregate, &select.having)?;
+        self.plan_project(having, &select.projection)
     }
 
     pub fn plan_aggregate(
@@ -34,21 +54,50 @@ impl LogicalPlanner<'_> {
         input: LogicalPlan,
         project: &Vec<sqlparser::ast::SelectItem>,
         group_by: &[sqlparser::ast::Expr],
+        having: &Option<sqlparser::ast::Expr>,
     ) -> BustubxResult<LogicalPlan> {
         let mut exprs = vec![];
         for select_item in project {
             exprs.extend(self.bind_select_item(&input, select_item)?);
         }
 
-        let aggr_exprs = exprs
+        let mut aggr_exprs = exprs
             .iter()
             .filter(|e| matches!(e, Expr::AggregateFunction(_)))
             .cloned()
             .collect::<Vec<Expr>>();
-        let group_exprs = group_by
-            .iter()
-            .map(|e| self.bind_expr(e))
-            .collect::<BustubxResult<Vec<Expr>>>()?;
+        // aggregates only HAVING reads are computed as well
+        if let Some(having) = having {
+            let mut having_aggr_exprs = vec![];
+            collect_aggregate_functions(&self.bind_expr(having)?, &mut having_aggr_exprs);
+            for e in having_aggr_exprs {
+                if !aggr_exprs.contains(&e) {
+                    aggr_exprs.push(e);
+                }
+            }
+        }
+        let (group_exprs, grouping_sets) = self.bind_group_by(group_by)?;
+        for e in aggr_exprs.iter() {
+            let Expr::AggregateFunction(AggregateFunction {
+                func_kind: AggregateFunctionKind::Grouping,
+                args,
+                ..
+            }) = e
+            else {
+                continue;
+            };
+            if args.is_empty() {
+                return Err(BustubxError::Plan(
+                    "GROUPING needs at least one argument".to_string(),
+                ));
+            }
+            if let Some(arg) = args.iter().find(|arg| !group_exprs.contains(arg)) {
+                return Err(BustubxError::Plan(format!(
+                    "GROUPING argument {} is not a GROUP BY expression",
+                    arg
+                )));
+            }
+        }
 
         if aggr_exprs.is_empty() && group_exprs.is_empty() {
             Ok(input)
@@ -57,21 +106,138 @@ impl LogicalPlanner<'_> {
                 .iter()
                 .map(|e| e.to_column(input.schema()))
                 .collect::<BustubxResult<Vec<Column>>>()?;
-            columns.extend(
-                group_exprs
-                    .iter()
-                    .map(|e| e.to_column(input.schema()))
-                    .collect::<BustubxResult<Vec<Column>>>()?,
-            );
+            for (idx, e) in group_exprs.iter().enumerate() {
+                let mut column = e.to_column(input.schema())?;
+                // null in the rows of the grouping sets rolling it up
+                if grouping_sets.iter().any(|set| !set.contains(&idx)) {
+                    column.nullable = true;
+                }
+                columns.push(column);
+            }
             Ok(LogicalPlan::Aggregate(Aggregate {
                 input: Arc::new(input),
                 group_exprs,
                 aggr_exprs,
+                grouping_sets,
                 schema: Arc::new(Schema::new(columns)),
             }))
         }
     }
 
+    /// Group expressions of a GROUP BY and its grouping sets, ROLLUP and CUBE expanded to
+    /// the grouping sets they stand for. The grouping sets of the GROUP BY items multiply,
+    /// `a, ROLLUP(b, c)` groups by (a, b, c), (a, b) and (a). A GROUP BY of plain
+    /// expressions has no grouping sets.
+    fn bind_group_by(
+        &self,
+        group_by: &[sqlparser::ast::Expr],
+    ) -> BustubxResult<(Vec<Expr>, Vec<Vec<usize>>)> {
+        let mut group_exprs: Vec<Expr> = vec![];
+        let mut grouping_sets: Vec<Vec<usize>> = vec![vec![]];
+        let mut has_grouping_sets = false;
+        for item in group_by {
+            let item_sets: Vec<Vec<&sqlparser::ast::Expr>> = match item {
+                sqlparser::ast::Expr::Rollup(groups) => {
+                    has_grouping_sets = true;
+                    (0..=groups.len())
+                        .rev()
+                        .map(|len| groups[..len].iter().flatten().collect())
+                        .collect()
+                }
+                sqlparser::ast::Expr::Cube(groups) => {
+                    has_grouping_sets = true;
+                    if groups.len() > MAX_CUBE_ITEMS {
+                        return Err(BustubxError::Plan(format!(
+                            "CUBE of {} items, at most {} are supported",
+                            groups.len(),
+                            MAX_CUBE_ITEMS
+                        )));
+                    }
+                    // from all the items down to none, the first item is the highest bit
+                    (0..1usize << groups.len())
+                        .rev()
+                        .map(|mask| {
+                            groups
+                                .iter()
+                                .enumerate()
+                                .filter(|(idx, _)| mask & (1 << (groups.len() - 1 - idx)) != 0)
+                                .flat_map(|(_, group)| group.iter())
+                                .collect()
+                        })
+                        .collect()
+                }
+                sqlparser::ast::Expr::GroupingSets(sets) => {
+                    has_grouping_sets = true;
+                    sets.iter().map(|set| set.iter().collect()).collect()
+                }
+                expr => vec![vec![expr]],
+            };
+            let mut item_sets_idx = vec![];
+            for set in item_sets {
+                let mut set_idx = vec![];
+                for expr in set {
+                    let expr = self.bind_expr(expr)?;
+                    let idx = match group_exprs.iter().position(|e| e == &expr) {
+                        Some(idx) => idx,
+                        None => {
+                            group_exprs.push(expr);
+                            group_exprs.len() - 1
+                        }
+                    };
+                    set_idx.push(idx);
+                }
+                item_sets_idx.push(set_idx);
+            }
+            grouping_sets = grouping_sets
+                .iter()
+                .flat_map(|set| {
+                    item_sets_idx.iter().map(move |item_set| {
+                        let mut set = set.clone();
+                        for idx in item_set {
+                            if !set.contains(idx) {
+                                set.push(*idx);
+                            }
+                        }
+                        set.sort();
+                        set
+                    })
+                })
+                .collect();
+        }
+        if !has_grouping_sets {
+            grouping_sets.clear();
+        }
+        Ok((group_exprs, grouping_sets))
+    }
+
+    pub fn plan_having(
+        &self,
+        input: LogicalPlan,
+        having: &Option<sqlparser::ast::Expr>,
+    ) -> BustubxResult<LogicalPlan> {
+        let Some(having) = having else {
+            return Ok(input);
+        };
+        if !matches!(input, LogicalPlan::Aggregate(_)) {
+            return Err(BustubxError::Plan(
+                "HAVING needs a GROUP BY or an aggregate".to_string(),
+            ));
+        }
+        // aggregates and group expressions are read from the output of the aggregate
+        let predicate = columnize_aggregate_output(&self.bind_expr(having)?, input.schema());
+        if !predicate.is_bound_by(input.schema()) {
+            return Err(BustubxError::Plan(format!(
+                "HAVING {} reads columns neither grouped by nor aggregated",
+                having
+            )));
+        }
+        predicate.collation(input.schema())?;
+        Ok(LogicalPlan::Filter(Filter {
+            input: Arc::new(input),
+            predicate,
+        }))
+    }
+
     pub fn plan_project(
         &self,
         input: LogicalPlan,
@@ -139,21 +305,60 @@ impl LogicalPlanner<'_> {
         input: LogicalPlan,
         selection: &Option<sqlparser::ast::Expr>,
     ) -> BustubxResult<LogicalPlan> {
-        match selection {
-            None => Ok(input),
-            Some(predicate) => {
-                let predicate = self.bind_expr(predicate)?;
-                Ok(LogicalPlan::Filter(Filter {
-                    input: Arc::new(input),
-                    predicate,
+        let Some(predicate) = selection else {
+            return Ok(input);
+        };
+        let mut conjuncts = vec![];
+        split_conjunction(predicate, &mut conjuncts);
+        if !conjuncts
+            .iter()
+            .any(|e| is_subquery_predicate(e) || is_scalar_subquery_predicate(e))
+        {
+            let predicate = self.bind_predicate(predicate, input.schema())?;
+            return Ok(LogicalPlan::Filter(Filter {
+                input: Arc::new(input),
+                predicate,
+            }));
+        }
+
+        // the other conjuncts filter the rows first, then every subquery predicate
+        // becomes a semi or anti join and every scalar subquery comparison a left join
+        let (subquery_predicates, others): (Vec<_>, Vec<_>) = conjuncts
+            .into_iter()
+            .partition(|e| is_subquery_predicate(e));
+        let (scalar_subquery_predicates, others): (Vec<_>, Vec<_>) = others
+            .into_iter()
+            .partition(|e| is_scalar_subquery_predicate(e));
+        let mut plan = input;
+        let predicate = others
+            .into_iter()
+            .map(|e| self.bind_predicate(e, plan.schema()))
+            .reduce(|left, right| {
+                Ok(Expr::Binary(BinaryExpr {
+                    left: Box::new(left?),
+                    op: BinaryOp::And,
+                    right: Box::new(right?),
                 }))
-            }
+            })
+            .transpose()?;
+        if let Some(predicate) = predicate {
+            plan = LogicalPlan::Filter(Filter {
+                input: Arc::new(plan),
+                predicate,
+            });
         }
+        for subquery_predicate in subquery_predicates {
+            plan = self.plan_subquery_predicate(plan, subquery_predicate)?;
+        }
+        for (ordinal, predicate) in scalar_subquery_predicates.into_iter().enumerate() {
+            plan = self.plan_scalar_subquery_predicate(plan, predicate, ordinal)?;
+        }
+        Ok(plan)
     }
 
     pub fn plan_from_tables(
         &self,
-        from: &Vec<sqlparser::ast::TableWithJoins>,
+        from: &[sqlparser::ast::TableWithJoins],
     ) -> BustubxResult<LogicalPlan> {
         match from.len() {
             0 => Ok(LogicalPlan::EmptyRelation(EmptyRelation {
@@ -224,8 +429,8 @@ impl LogicalPlanner<'_> {
     ) -> BustubxResult<LogicalPlan> {
         match constraint {
             sqlparser::ast::JoinConstraint::On(expr) => {
-                let expr = self.bind_expr(expr)?;
                 let schema = Arc::new(build_join_schema(left.schema(), right.schema(), join_type)?);
+                let expr = self.bind_predicate(expr, &schema)?;
                 Ok(LogicalPlan::Join(Join {
                     left: Arc::new(left),
                     right: Arc::new(right),
@@ -268,6 +473,16 @@ impl LogicalPlanner<'_> {
             sqlparser::ast::TableFactor::Table { name, .. } => {
                 // TODO handle alias
                 let table_ref = self.bind_table_name(name)?;
+                if table_ref.schema() == Some(INFORMATION_SCHEMA_NAME)
+                    && table_ref.table() == INFORMATION_SCHEMA_RELATION_SIZES
+                {
+                    return self.plan_relation_sizes(table_ref);
+                }
+                if table_ref.schema() == Some(INFORMATION_SCHEMA_NAME)
+                    && table_ref.table() == INFORMATION_SCHEMA_STATISTICS
+                {
+                    return self.plan_statistics(table_ref);
+                }
                 let schema = self.context.catalog.table_heap(&table_ref)?.schema.clone();
                 Ok(LogicalPlan::TableScan(TableScan {
                     table_ref,
@@ -308,3 +523,56 @@ impl LogicalPlanner<'_> {
         }))
     }
 }
+
+fn collect_aggregate_functions(expr: &Expr, aggr_exprs: &mut Vec<Expr>) {
+    match expr {
+        Expr::AggregateFunction(_) => {
+            if !aggr_exprs.contains(expr) {
+                aggr_exprs.push(expr.clone());
+            }
+        }
+        Expr::Alias(Alias { expr, .. }) | Expr::Cast(Cast { expr, .. }) => {
+            collect_aggregate_functions(expr, aggr_exprs)
+        }
+        Expr::Binary(BinaryExpr { left, right, .. }) => {
+            collect_aggregate_functions(left, aggr_exprs);
+            collect_aggregate_functions(right, aggr_exprs);
+        }
+        Expr::ScalarFunction(func) => {
+            for arg in func.args.iter() {
+                collect_aggregate_functions(arg, aggr_exprs);
+            }
+        }
+        Expr::Column(_) | Expr::Literal(_) => {}
+    }
+}
+
+// Replace the parts of `expr` the aggregate outputs by its columns
+fn columnize_aggregate_output(expr: &Expr, schema: &SchemaRef) -> Expr {
+    if !matches!(expr, Expr::Column(_) | Expr::Literal(_)) {
+        if let Ok(column) = columnize_expr(expr, schema) {
+            return column;
+        }
+    }
+    match expr {
+        Expr::Binary(BinaryExpr { left, op, right }) => Expr::Binary(BinaryExpr {
+            left: Box::new(columnize_aggregate_output(left, schema)),
+            op: *op,
+            right: Box::new(columnize_aggregate_output(right, schema)),
+        }),
+        Expr::Cast(Cast { expr, data_type }) => Expr::Cast(Cast {
+            expr: Box::new(columnize_aggregate_output(expr, schema)),
+            data_type: *data_type,
+        }),
+        Expr::ScalarFunction(func) => {
+            let mut func = func.clone();
+            func.args = func
+                .args
+                .iter()
+                .map(|arg| columnize_aggregate_output(arg, schema))
+                .collect();
+            Expr::ScalarFunction(func)
+        }
+        _ => expr.clone(),
+    }
+}
@@ -0,0 +1,490 @@
+use crate::catalog::{
+    Catalog, CatalogTable, Partitioning, Schema, DEFAULT_SCHEMA_NAME, DESCRIBE_OUTPUT_SCHEMA_REF,
+    RELATION_SIZES_SCHMEA, SHOW_CREATE_TABLE_OUTPUT_SCHEMA_REF, SHOW_TABLES_OUTPUT_SCHEMA_REF,
+    STATISTICS_SCHMEA,
+};
+use crate::common::util::encode_hex;
+use crate::common::value_ord::Collation;
+use crate::common::{ScalarValue, TableReference};
+use crate::expression::{Expr, Literal};
+use crate::planner::logical_plan::{LogicalPlan, Values};
+use crate::{BustubxError, BustubxResult};
+use std::sync::Arc;
+
+use super::LogicalPlanner;
+
+impl<'a> LogicalPlanner<'a> {
+    pub fn plan_show_tables(
+        &self,
+        db_name: &Option<sqlparser::ast::Ident>,
+        filter: &Option<sqlparser::ast::ShowStatementFilter>,
+    ) -> BustubxResult<LogicalPlan> {
+        if let Some(filter) = filter {
+            return Err(BustubxError::NotSupport(format!(
+                "show tables filter {} not supported",
+                filter
+            )));
+        }
+        let schema_name = db_name
+            .as_ref()
+            .map_or(DEFAULT_SCHEMA_NAME, |ident| ident.value.as_str());
+        let Some(catalog_schema) = self.context.catalog.schemas.get(schema_name) else {
+            return Err(BustubxError::Plan(format!(
+                "schema {} does not exist",
+                schema_name
+            )));
+        };
+        let mut table_names: Vec<&String> = catalog_schema.tables.keys().collect();
+        table_names.sort();
+        let values = table_names
+            .into_iter()
+            .map(|name| vec![literal(name.clone())])
+            .collect();
+        Ok(LogicalPlan::Values(Values {
+            schema: SHOW_TABLES_OUTPUT_SCHEMA_REF.clone(),
+            values,
+        }))
+    }
+
+    pub fn plan_describe(
+        &self,
+        table_name: &sqlparser::ast::ObjectName,
+    ) -> BustubxResult<LogicalPlan> {
+        let table_ref = self.bind_table_name(table_name)?;
+        let catalog_table = lookup_table(self.context.catalog, &table_ref)?;
+
+        let mut values = vec![];
+        for col in catalog_table.table.schema.columns.iter() {
+            let sql_type: sqlparser::ast::DataType = (&col.data_type).into();
+            let default = if let Some(default_expr) = &col.default_expr {
+                default_expr.sql.clone().into()
+            } else if col.default.is_null() {
+                ScalarValue::Varchar(None)
+            } else {
+                sql_literal(&col.default).into()
+            };
+            let mut index_names: Vec<&String> = catalog_table
+                .indexes
+                .iter()
+                .filter(|(_, index)| {
+                    index
+                        .key_schema
+                        .columns
+                        .iter()
+                        .any(|key| key.name == col.name)
+                })
+                .map(|(name, _)| name)
+                .collect();
+            index_names.sort();
+            let key = if index_names.is_empty() {
+                ScalarValue::Varchar(None)
+            } else {
+                index_names
+                    .into_iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .into()
+            };
+            values.push(vec![
+                literal(col.name.clone()),
+                literal(format!("{sql_type}")),
+                literal(col.nullable),
+                literal(default),
+                literal(key),
+            ]);
+        }
+        Ok(LogicalPlan::Values(Values {
+            schema: DESCRIBE_OUTPUT_SCHEMA_REF.clone(),
+            values,
+        }))
+    }
+
+    pub fn plan_show_create_table(
+        &self,
+        obj_type: &sqlparser::ast::ShowCreateObject,
+        obj_name: &sqlparser::ast::ObjectName,
+    ) -> BustubxResult<LogicalPlan> {
+        if !matches!(obj_type, sqlparser::ast::ShowCreateObject::Table) {
+            return Err(BustubxError::NotSupport(format!(
+                "show create {} not supported",
+                obj_type
+            )));
+        }
+        let table_ref = self.bind_table_name(obj_name)?;
+        let catalog_table = lookup_table(self.context.catalog, &table_ref)?;
+        Ok(LogicalPlan::Values(Values {
+            schema: SHOW_CREATE_TABLE_OUTPUT_SCHEMA_REF.clone(),
+            values: vec![vec![
+                literal(table_ref.table().to_string()),
+                literal(create_table_statement(&table_ref, catalog_table)),
+            ]],
+        }))
+    }
+
+    /// `information_schema.relation_sizes`, measured when the query is planned.
+    pub fn plan_relation_sizes(&self, table_ref: TableReference) -> BustubxResult<LogicalPlan> {
+        let mut values = vec![];
+        for table_size in self.context.catalog.relation_sizes()? {
+            let schema_name = table_size.table.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+            let table_name = table_size.table.table();
+            let statistics = self.context.catalog.table_statistics(&table_size.table);
+            values.push(vec![
+                literal(schema_name.to_string()),
+                literal(table_name.to_string()),
+                literal(table_name.to_string()),
+                literal("table".to_string()),
+                literal(table_size.heap_pages as u64),
+                literal(table_size.live_tuples as u64),
+                literal(table_size.dead_tuples as u64),
+                literal(ScalarValue::Float64(None)),
+                literal(ScalarValue::Float64(None)),
+                literal(table_size.heap_bytes() as u64),
+                literal(table_size.total_bytes() as u64),
+            ]);
+            for index_size in table_size.indexes.iter() {
+                let bloat = statistics
+                    .as_ref()
+                    .and_then(|statistics| statistics.index_bloat.get(&index_size.name).copied());
+                values.push(vec![
+                    literal(schema_name.to_string()),
+                    literal(table_name.to_string()),
+                    literal(index_size.name.clone()),
+                    literal("index".to_string()),
+                    literal(index_size.pages() as u64),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(index_size.avg_fill),
+                    literal(ScalarValue::Float64(bloat)),
+                    literal(index_size.bytes() as u64),
+                    literal(index_size.bytes() as u64),
+                ]);
+            }
+        }
+        // qualified so the columns can be told apart in a join with information_schema.tables
+        let schema = Schema::new(
+            RELATION_SIZES_SCHMEA
+                .columns
+                .iter()
+                .map(|col| col.as_ref().clone().with_relation(Some(table_ref.clone())))
+                .collect(),
+        );
+        Ok(LogicalPlan::Values(Values {
+            schema: Arc::new(schema),
+            values,
+        }))
+    }
+
+    /// `information_schema.statistics`, the access counters when the query is planned.
+    pub fn plan_statistics(&self, table_ref: TableReference) -> BustubxResult<LogicalPlan> {
+        let mut values = vec![];
+        for (table, counts) in self.context.catalog.access_stats()? {
+            let schema_name = table.schema().unwrap_or(DEFAULT_SCHEMA_NAME);
+            let table_name = table.table();
+            let (index_scans, index_tuples_fetched) = counts.index_totals();
+            values.push(vec![
+                literal(schema_name.to_string()),
+                literal(table_name.to_string()),
+                literal(table_name.to_string()),
+                literal("table".to_string()),
+                literal(counts.seq_scans),
+                literal(counts.seq_tuples_read),
+                literal(index_scans),
+                literal(index_tuples_fetched),
+                literal(counts.tuples_inserted),
+                literal(counts.tuples_updated),
+                literal(counts.tuples_deleted),
+            ]);
+            for (index_name, index_counts) in counts.indexes.iter() {
+                values.push(vec![
+                    literal(schema_name.to_string()),
+                    literal(table_name.to_string()),
+                    literal(index_name.clone()),
+                    literal("index".to_string()),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(index_counts.scans),
+                    literal(index_counts.tuples_fetched),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(ScalarValue::UInt64(None)),
+                    literal(ScalarValue::UInt64(None)),
+                ]);
+            }
+        }
+        let schema = Schema::new(
+            STATISTICS_SCHMEA
+                .columns
+                .iter()
+                .map(|col| col.as_ref().clone().with_relation(Some(table_ref.clone())))
+                .collect(),
+        );
+        Ok(LogicalPlan::Values(Values {
+            schema: Arc::new(schema),
+            values,
+        }))
+    }
+}
+
+fn lookup_table<'a>(
+    catalog: &'a Catalog,
+    table_ref: &TableReference,
+) -> BustubxResult<&'a CatalogTable> {
+    catalog
+        .catalog_table(table_ref)
+        .map_err(|_| BustubxError::Plan(format!("table {} does not exist", table_ref)))
+}
+
+fn literal(value: impl Into<ScalarValue>) -> Expr {
+    Expr::Literal(Literal {
+        value: value.into(),
+    })
+}
+
+/// Canonical DDL of the table followed by one `CREATE INDEX` per index, separated by `;\n`.
+fn create_table_statement(table_ref: &TableReference, catalog_table: &CatalogTable) -> String {
+    let mut statements = vec![create_table_sql(table_ref, catalog_table)];
+    statements.extend(create_index_sql(table_ref, catalog_table));
+    statements.join(";\n")
+}
+
+/// `CREATE TABLE` of the table, without its indexes. The parent of a partition is named in
+/// the schema of `table_ref`.
+pub(crate) fn create_table_sql(table_ref: &TableReference, catalog_table: &CatalogTable) -> String {
+    let mut columns = catalog_table
+        .table
+        .schema
+        .columns
+        .iter()
+        .map(|col| {
+            let sql_type: sqlparser::ast::DataType = (&col.data_type).into();
+            let mut def = format!("{} {}", col.name, sql_type);
+            if col.collation != Collation::Binary {
+                def.push_str(&format!(" COLLATE {}", col.collation));
+            }
+            if !col.nullable {
+                def.push_str(" NOT NULL");
+            }
+            if let Some(default_expr) = &col.default_expr {
+                def.push_str(&format!(" DEFAULT {}", default_expr));
+            } else if !col.default.is_null() {
+                def.push_str(&format!(" DEFAULT {}", sql_literal(&col.default)));
+            }
+            def
+        })
+        .collect::<Vec<_>>();
+    for foreign_key in catalog_table.foreign_keys.iter() {
+        let mut def = format!(
+            "CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+            foreign_key.name,
+            foreign_key.columns.join(", "),
+            foreign_key.parent,
+            foreign_key.parent_columns.join(", ")
+        );
+        if let Some(initially) = foreign_key.deferrable {
+            def.push_str(&format!(" DEFERRABLE INITIALLY {}", initially));
+        }
+        columns.push(def);
+    }
+    let mut create_table = match &catalog_table.partitioning {
+        Some(Partitioning::Partition {
+            parent, from, to, ..
+        }) => format!(
+            "CREATE TABLE {} PARTITION OF {} FOR VALUES FROM ({}) TO ({})",
+            table_ref,
+            match table_ref.schema() {
+                Some(schema) => format!("{schema}.{parent}"),
+                None => parent.clone(),
+            },
+            sql_literal(from),
+            sql_literal(to)
+        ),
+        _ => format!("CREATE TABLE {} ({})", table_ref, columns.join(", ")),
+    };
+    let mut options = vec![];
+    if let Some(ttl_column) = &catalog_table.ttl_column {
+        options.push(format!("ttl_column = '{}'", ttl_column));
+    }
+    if catalog_table.row_ids.is_some() {
+        options.push("logical_row_ids = true".to_string());
+    }
+    if !options.is_empty()
+        && !matches!(
+            catalog_table.partitioning,
+            Some(Partitioning::Partition { .. })
+        )
+    {
+        create_table.push_str(&format!(" WITH ({})", options.join(", ")));
+    }
+    if let Some(Partitioning::Range { column }) = &catalog_table.partitioning {
+        create_table.push_str(&format!(" PARTITION BY RANGE ({})", column));
+    }
+    create_table
+}
+
+/// `CREATE INDEX` of every index of the table, ordered by index name.
+pub(crate) fn create_index_sql(
+    table_ref: &TableReference,
+    catalog_table: &CatalogTable,
+) -> Vec<String> {
+    let mut statements = vec![];
+    let mut index_names: Vec<&String> = catalog_table.indexes.keys().collect();
+    index_names.sort();
+    for index_name in index_names {
+        let index = &catalog_table.indexes[index_name];
+        let key_columns = index
+            .key_schema
+            .columns
+            .iter()
+            .map(|col| col.name.clone())
+            .collect::<Vec<_>>();
+        statements.push(format!(
+            "CREATE {}INDEX {} ON {} ({}){}",
+            if index.unique { "UNIQUE " } else { "" },
+            index_name,
+            table_ref,
+            key_columns.join(", "),
+            index
+                .deferrable
+                .map(|timing| format!(" DEFERRABLE INITIALLY {}", timing))
+                .unwrap_or_default()
+        ));
+    }
+    statements
+}
+
+/// The value as a SQL literal, NULL for a null of any type.
+pub(crate) fn sql_literal(value: &ScalarValue) -> String {
+    match value {
+        ScalarValue::Varchar(Some(v)) => format!("'{}'", v.replace('\'', "''")),
+        ScalarValue::Bytea(Some(v)) => format!("x'{}'", encode_hex(v)),
+        ScalarValue::Boolean(Some(v)) => if *v { "TRUE" } else { "FALSE" }.to_string(),
+        v => format!("{v}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::Catalog;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::{BustubxError, Database};
+
+    // (name, data type, nullable, default) of every column, (name, key columns, unique) of every index
+    // and the ttl column
+    type TableDefinition = (
+        Vec<(String, String, bool, ScalarValue)>,
+        Vec<(String, Vec<String>, bool)>,
+        Option<String>,
+    );
+
+    fn table_definition(catalog: &Catalog, table: &str) -> TableDefinition {
+        let catalog_table = catalog.catalog_table(&TableReference::bare(table)).unwrap();
+        let columns = catalog_table
+            .table
+            .schema
+            .columns
+            .iter()
+            .map(|col| {
+                (
+                    col.name.clone(),
+                    format!("{} {}", col.data_type, col.collation),
+                    col.nullable,
+                    col.default.clone(),
+                )
+            })
+            .collect();
+        let mut indexes: Vec<(String, Vec<String>, bool)> = catalog_table
+            .indexes
+            .iter()
+            .map(|(name, index)| {
+                let keys = index
+                    .key_schema
+                    .columns
+                    .iter()
+                    .map(|col| col.name.clone())
+                    .collect();
+                (name.clone(), keys, index.unique)
+            })
+            .collect();
+        indexes.sort();
+        (columns, indexes, catalog_table.ttl_column.clone())
+    }
+
+    #[test]
+    pub fn test_show_tables_and_describe() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t2 (a int)").unwrap();
+        db.run("create table t1 (a int not null default 3, b varchar(10), c double)")
+            .unwrap();
+        db.run("create index idx1 on t1 (a)").unwrap();
+        db.run("create index idx2 on t1 (a, b)").unwrap();
+
+        let tables = db.run("show tables").unwrap();
+        let names: Vec<_> = tables.iter().map(|t| t.data[0].clone()).collect();
+        assert_eq!(
+            names,
+            vec![
+                ScalarValue::Varchar(Some("t1".to_string())),
+                ScalarValue::Varchar(Some("t2".to_string()))
+            ]
+        );
+
+        let columns = db.run("describe t1").unwrap();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(
+            columns[0].data,
+            vec![
+                "a".to_string().into(),
+                "INTEGER".to_string().into(),
+                false.into(),
+                "3".to_string().into(),
+                "idx1, idx2".to_string().into(),
+            ]
+        );
+        assert_eq!(columns[1].data[2], true.into());
+        assert_eq!(columns[1].data[3], ScalarValue::Varchar(None));
+        assert_eq!(columns[1].data[4], "idx2".to_string().into());
+        assert_eq!(columns[2].data[4], ScalarValue::Varchar(None));
+
+        assert!(matches!(
+            db.run("describe not_exist"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert!(matches!(
+            db.run("show create table not_exist"),
+            Err(BustubxError::Plan(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_show_create_table_round_trip() {
+        let mut db = Database::new_temp().unwrap();
+        db.run(
+            "create table t1 (a int not null default 3, b varchar(10) collate nocase default 'it''s', \
+             c double, d bigint unsigned not null, e boolean default true, f bigint) \
+             with (ttl_column = 'f')",
+        )
+        .unwrap();
+        db.run("create index idx1 on t1 (a)").unwrap();
+        db.run("create unique index idx2 on t1 (d, b)").unwrap();
+
+        let rows = db.run("show create table t1").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].data[0], "t1".to_string().into());
+        let ScalarValue::Varchar(Some(ddl)) = &rows[0].data[1] else {
+            panic!("unexpected ddl {:?}", rows[0].data[1]);
+        };
+
+        let mut fresh_db = Database::new_temp().unwrap();
+        for stmt in ddl.split(";\n") {
+            fresh_db.run(stmt).unwrap();
+        }
+        assert_eq!(
+            table_definition(&fresh_db.catalog, "t1"),
+            table_definition(&db.catalog, "t1")
+        );
+
+        // the reconstruction is canonical
+        let rows = fresh_db.run("show create table t1").unwrap();
+        assert_eq!(rows[0].data[1], ScalarValue::Varchar(Some(ddl.clone())));
+    }
+}
@@ -0,0 +1,811 @@
+use std::sync::Arc;
+
+use crate::catalog::{Column, Schema};
+use crate::expression::{columnize_expr, Alias, BinaryExpr, BinaryOp, ColumnExpr, Expr, ExprTrait};
+use crate::function::AggregateFunctionKind;
+use crate::planner::logical_plan::{
+    build_join_schema, project_schema, Aggregate, Filter, Join, JoinType, LogicalPlan, Project,
+};
+use crate::{BustubxError, BustubxResult};
+
+use super::LogicalPlanner;
+
+impl LogicalPlanner<'_> {
+    /// Turn an `[NOT] EXISTS (subquery)` or `expr [NOT] IN (subquery)` predicate into a
+    /// semi or anti join of `input` with the subquery, see [`is_subquery_predicate`].
+    /// A subquery correlated to `input` by equalities in its WHERE, e.g. `EXISTS (SELECT 1
+    /// FROM u WHERE u.a = t.a)`, joins on them as well. Other reads of the outer query
+    /// fail with NotSupport.
+    pub fn plan_subquery_predicate(
+        &self,
+        input: LogicalPlan,
+        predicate: &sqlparser::ast::Expr,
+    ) -> BustubxResult<LogicalPlan> {
+        let Some((key, subquery, negated)) = subquery_predicate(predicate) else {
+            return Err(BustubxError::Internal(format!(
+                "{predicate} is not a subquery predicate"
+            )));
+        };
+        self.check_outer_references(subquery, &[input.schema()])?;
+        let (right, subquery_key, correlation) =
+            match self.plan_correlated_subquery(subquery, key.is_some(), input.schema())? {
+                Some(correlated) => correlated,
+                None => {
+                    let right = self.plan_query(subquery)?;
+                    let subquery_key = match (key, right.schema().columns.as_slice()) {
+                        (None, _) => None,
+                        (Some(_), [column]) => Some(Expr::Column(ColumnExpr {
+                            relation: column.relation.clone(),
+                            name: column.name.clone(),
+                        })),
+                        (Some(_), columns) => {
+                            return Err(BustubxError::Plan(format!(
+                                "subquery of IN returns {} columns instead of one",
+                                columns.len()
+                            )))
+                        }
+                    };
+                    (right, subquery_key, vec![])
+                }
+            };
+        let join_type = match (key, negated) {
+            (_, false) => JoinType::LeftSemi,
+            (None, true) => JoinType::LeftAnti,
+            // a NULL of the subquery would only empty the rows correlated to it
+            (Some(_), true) if !correlation.is_empty() => {
+                return Err(BustubxError::NotSupport(format!(
+                    "correlated subquery {subquery} not supported under NOT IN"
+                )))
+            }
+            (Some(_), true) => JoinType::NullAwareLeftAnti,
+        };
+        let key = match (key, subquery_key) {
+            (Some(key), Some(subquery_key)) => Some((self.bind_expr(key)?, subquery_key)),
+            _ => None,
+        };
+        let condition = key
+            .into_iter()
+            .chain(correlation)
+            .map(|(left, right)| {
+                Expr::Binary(BinaryExpr {
+                    left: Box::new(left),
+                    op: BinaryOp::Eq,
+                    right: Box::new(right),
+                })
+            })
+            .reduce(|left, right| {
+                Expr::Binary(BinaryExpr {
+                    left: Box::new(left),
+                    op: BinaryOp::And,
+                    right: Box::new(right),
+                })
+            });
+        let schema = Arc::new(build_join_schema(
+            input.schema(),
+            right.schema(),
+            join_type,
+        )?);
+        Ok(LogicalPlan::Join(Join {
+            left: Arc::new(input),
+            right: Arc::new(right),
+            join_type,
+            condition,
+            schema,
+        }))
+    }
+
+    // The rows of an EXISTS or IN subquery whose WHERE reads the outer query, filtered by
+    // its other conjuncts, with the expression IN compares with and the outer expressions
+    // paired with the subquery expressions they equal. None for a subquery whose WHERE
+    // does not read the outer query.
+    #[allow(clippy::type_complexity)]
+    fn plan_correlated_subquery(
+        &self,
+        subquery: &sqlparser::ast::Query,
+        has_key: bool,
+        outer_schema: &Schema,
+    ) -> BustubxResult<Option<(LogicalPlan, Option<Expr>, Vec<(Expr, Expr)>)>> {
+        let sqlparser::ast::SetExpr::Select(select) = subquery.body.as_ref() else {
+            return Ok(None);
+        };
+        let Some(selection) = &select.selection else {
+            return Ok(None);
+        };
+        let input = self.plan_from_tables(&select.from)?;
+        let mut conjuncts = vec![];
+        split_conjunction(selection, &mut conjuncts);
+        // subquery predicates of the subquery itself join its rows after the filter
+        let (nested, conjuncts): (Vec<_>, Vec<_>) = conjuncts
+            .into_iter()
+            .partition(|e| is_subquery_predicate(e) || is_scalar_subquery_predicate(e));
+        let conjuncts = conjuncts
+            .into_iter()
+            .map(|conjunct| self.bind_expr(conjunct))
+            .collect::<BustubxResult<Vec<_>>>()?;
+        if !conjuncts
+            .iter()
+            .any(|conjunct| conjunct.reads_outer(input.schema(), outer_schema))
+        {
+            return Ok(None);
+        }
+
+        let unsupported = |reason: &str| {
+            BustubxError::NotSupport(format!(
+                "correlated subquery {subquery} not supported: {reason}"
+            ))
+        };
+        if subquery.with.is_some()
+            || !subquery.order_by.is_empty()
+            || subquery.limit.is_some()
+            || subquery.offset.is_some()
+            || !select.group_by.is_empty()
+            || select.having.is_some()
+        {
+            return Err(unsupported("expected a SELECT over a FROM and WHERE"));
+        }
+        let projection = select
+            .projection
+            .iter()
+            .map(|item| match item {
+                sqlparser::ast::SelectItem::UnnamedExpr(expr)
+                | sqlparser::ast::SelectItem::ExprWithAlias { expr, .. } => {
+                    self.bind_expr(expr).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect::<BustubxResult<Vec<_>>>()?;
+        if projection.iter().flatten().any(|expr| {
+            !expr.is_bound_by(input.schema()) || matches!(expr, Expr::AggregateFunction(_))
+        }) {
+            return Err(unsupported(
+                "expected a select list without aggregates over its own columns",
+            ));
+        }
+        let subquery_key = match projection.as_slice() {
+            _ if !has_key => None,
+            [Some(expr)] => Some(expr.clone()),
+            _ => return Err(unsupported("IN expects a select list of one expression")),
+        };
+
+        let (filters, correlation) =
+            split_correlation(conjuncts, input.schema(), outer_schema, unsupported)?;
+        let mut plan = input;
+        if let Some(predicate) = filters.into_iter().reduce(|left, right| {
+            Expr::Binary(BinaryExpr {
+                left: Box::new(left),
+                op: BinaryOp::And,
+                right: Box::new(right),
+            })
+        }) {
+            plan = LogicalPlan::Filter(Filter {
+                input: Arc::new(plan),
+                predicate,
+            });
+        }
+        let mut ordinal = 0;
+        for predicate in nested {
+            plan = if is_subquery_predicate(predicate) {
+                self.plan_subquery_predicate(plan, predicate)?
+            } else {
+                ordinal += 1;
+                self.plan_scalar_subquery_predicate(plan, predicate, ordinal - 1)?
+            };
+        }
+        Ok(Some((plan, subquery_key, correlation)))
+    }
+
+    /// Turn a comparison with a scalar aggregate subquery correlated to `input` by
+    /// equalities, e.g. `a = (SELECT avg(b) FROM t2 WHERE t2.k = t1.k)`, into a left join
+    /// of `input` with the aggregate of the subquery grouped by the inner sides of the
+    /// equalities. The comparison is evaluated after the join, an outer row without a
+    /// group sees the subquery as NULL. `ordinal` keeps the columns of the subqueries of
+    /// one WHERE apart.
+    pub fn plan_scalar_subquery_predicate(
+        &self,
+        input: LogicalPlan,
+        predicate: &sqlparser::ast::Expr,
+        ordinal: usize,
+    ) -> BustubxResult<LogicalPlan> {
+        let Some((outer_expr, op, subquery, subquery_on_left)) =
+            scalar_subquery_comparison(predicate)
+        else {
+            return Err(BustubxError::Internal(format!(
+                "{predicate} is not a scalar subquery comparison"
+            )));
+        };
+        self.check_outer_references(subquery, &[input.schema()])?;
+        let value_name = format!("__subquery{ordinal}");
+        let (right, outer_keys) =
+            self.plan_grouped_subquery(subquery, input.schema(), &value_name)?;
+
+        let condition = outer_keys
+            .into_iter()
+            .map(|(outer_key, key_name)| {
+                Expr::Binary(BinaryExpr {
+                    left: Box::new(outer_key),
+                    op: BinaryOp::Eq,
+                    right: Box::new(Expr::Column(ColumnExpr {
+                        relation: None,
+                        name: key_name,
+                    })),
+                })
+            })
+            .reduce(|left, right| {
+                Expr::Binary(BinaryExpr {
+                    left: Box::new(left),
+                    op: BinaryOp::And,
+                    right: Box::new(right),
+                })
+            });
+        let schema = Arc::new(build_join_schema(
+            input.schema(),
+            right.schema(),
+            JoinType::LeftOuter,
+        )?);
+        // the columns of the input, the subquery columns are dropped after the filter
+        let exprs = input
+            .schema()
+            .columns
+            .iter()
+            .map(|column| {
+                Expr::Column(ColumnExpr {
+                    relation: column.relation.clone(),
+                    name: column.name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let join = LogicalPlan::Join(Join {
+            left: Arc::new(input),
+            right: Arc::new(right),
+            join_type: JoinType::LeftOuter,
+            condition,
+            schema,
+        });
+
+        let outer_expr = self.bind_expr(outer_expr)?;
+        let value = Expr::Column(ColumnExpr {
+            relation: None,
+            name: value_name,
+        });
+        let (left, right) = if subquery_on_left {
+            (value, outer_expr)
+        } else {
+            (outer_expr, value)
+        };
+        let filter = LogicalPlan::Filter(Filter {
+            input: Arc::new(join),
+            predicate: Expr::Binary(BinaryExpr {
+                left: Box::new(left),
+                op: op.try_into()?,
+                right: Box::new(right),
+            }),
+        });
+        let schema = Arc::new(project_schema(&filter, &exprs)?);
+        Ok(LogicalPlan::Project(Project {
+            exprs,
+            input: Arc::new(filter),
+            schema,
+        }))
+    }
+
+    // Fails with NotSupport for a subquery reading a column of an enclosing query where
+    // the planner can not decorrelate it: anywhere but in the WHERE, or in the WHERE of a
+    // subquery nested deeper than right under that query. `outer_schemas` are the schemas
+    // of the enclosing queries, innermost first.
+    fn check_outer_references(
+        &self,
+        subquery: &sqlparser::ast::Query,
+        outer_schemas: &[&Schema],
+    ) -> BustubxResult<()> {
+        let unsupported = |expr: &Expr| {
+            BustubxError::NotSupport(format!(
+                "subquery {subquery} not supported: {expr} reads an outer query, which is \
+                 only supported in equalities of its WHERE"
+            ))
+        };
+        let select = match subquery.body.as_ref() {
+            sqlparser::ast::SetExpr::Select(select) => select,
+            sqlparser::ast::SetExpr::Query(query) => {
+                return self.check_outer_references(query, outer_schemas)
+            }
+            _ => return Ok(()),
+        };
+        let input = self.plan_from_tables(&select.from)?;
+        // `expr` bound if it reads a column only one of `scopes` resolves
+        let outer_column = |expr: &sqlparser::ast::Expr, scopes: &[&Schema]| {
+            // expressions the binder does not know fail when the subquery is planned
+            let Ok(expr) = self.bind_expr(expr) else {
+                return None;
+            };
+            scopes
+                .iter()
+                .any(|scope| expr.reads_outer(input.schema(), scope))
+                .then_some(expr)
+        };
+
+        let mut elsewhere = vec![];
+        for item in select.projection.iter() {
+            if let sqlparser::ast::SelectItem::UnnamedExpr(expr)
+            | sqlparser::ast::SelectItem::ExprWithAlias { expr, .. } = item
+            {
+                elsewhere.push(expr);
+            }
+        }
+        for join in select.from.iter().flat_map(|table| table.joins.iter()) {
+            if let sqlparser::ast::JoinOperator::Inner(sqlparser::ast::JoinConstraint::On(expr))
+            | sqlparser::ast::JoinOperator::LeftOuter(sqlparser::ast::JoinConstraint::On(
+                expr,
+            ))
+            | sqlparser::ast::JoinOperator::RightOuter(sqlparser::ast::JoinConstraint::On(
+                expr,
+            ))
+            | sqlparser::ast::JoinOperator::FullOuter(sqlparser::ast::JoinConstraint::On(
+                expr,
+            )) = &join.join_operator
+            {
+                elsewhere.push(expr);
+            }
+        }
+        elsewhere.extend(select.group_by.iter());
+        elsewhere.extend(select.having.iter());
+        elsewhere.extend(subquery.order_by.iter().map(|order_by| &order_by.expr));
+        if let Some(expr) = elsewhere
+            .into_iter()
+            .find_map(|expr| outer_column(expr, outer_schemas))
+        {
+            return Err(unsupported(&expr));
+        }
+
+        let mut conjuncts = vec![];
+        if let Some(selection) = &select.selection {
+            split_conjunction(selection, &mut conjuncts);
+        }
+        let mut scopes = vec![input.schema().as_ref()];
+        scopes.extend(outer_schemas);
+        for conjunct in conjuncts {
+            let nested = if let Some((key, nested, _)) = subquery_predicate(conjunct) {
+                Some((key, nested))
+            } else {
+                scalar_subquery_comparison(conjunct)
+                    .map(|(expr, _, nested, _)| (Some(expr), nested))
+            };
+            match nested {
+                // the compared expression reads the rows of this subquery
+                Some((key, nested)) => {
+                    if let Some(expr) = key.and_then(|key| outer_column(key, outer_schemas)) {
+                        return Err(unsupported(&expr));
+                    }
+                    self.check_outer_references(nested, &scopes)?;
+                }
+                // equalities with the query right around are decorrelated
+                None => {
+                    if let Some(expr) =
+                        outer_column(conjunct, outer_schemas.get(1..).unwrap_or_default())
+                    {
+                        return Err(unsupported(&expr));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The aggregate of the subquery grouped by its correlation keys, projected to the
+    // value named `value_name` followed by the keys. Also returns every outer key with the
+    // name of the column it is compared with.
+    fn plan_grouped_subquery(
+        &self,
+        subquery: &sqlparser::ast::Query,
+        outer_schema: &Schema,
+        value_name: &str,
+    ) -> BustubxResult<(LogicalPlan, Vec<(Expr, String)>)> {
+        let unsupported = |reason: &str| {
+            BustubxError::NotSupport(format!("subquery {subquery} not supported: {reason}"))
+        };
+        let sqlparser::ast::SetExpr::Select(select) = subquery.body.as_ref() else {
+            return Err(unsupported("not a SELECT"));
+        };
+        if subquery.with.is_some()
+            || !subquery.order_by.is_empty()
+            || subquery.limit.is_some()
+            || subquery.offset.is_some()
+            || !select.group_by.is_empty()
+            || select.having.is_some()
+        {
+            return Err(unsupported("expected one aggregate over a FROM and WHERE"));
+        }
+        let input = self.plan_from_tables(&select.from)?;
+
+        // the aggregate must be NULL over no rows, like a subquery without a row
+        let aggr = match select.projection.as_slice() {
+            [sqlparser::ast::SelectItem::UnnamedExpr(expr)]
+            | [sqlparser::ast::SelectItem::ExprWithAlias { expr, .. }] => self.bind_expr(expr)?,
+            _ => return Err(unsupported("expected one aggregate")),
+        };
+        match &aggr {
+            Expr::AggregateFunction(func)
+                if func.func_kind != AggregateFunctionKind::Count
+                    && aggr.is_bound_by(input.schema()) => {}
+            _ => {
+                return Err(unsupported(
+                    "expected one aggregate other than count over its own columns",
+                ))
+            }
+        }
+
+        let mut conjuncts = vec![];
+        if let Some(selection) = &select.selection {
+            split_conjunction(selection, &mut conjuncts);
+        }
+        let conjuncts = conjuncts
+            .into_iter()
+            .map(|conjunct| self.bind_expr(conjunct))
+            .collect::<BustubxResult<Vec<_>>>()?;
+        let (filters, correlation) =
+            split_correlation(conjuncts, input.schema(), outer_schema, unsupported)?;
+        let (outer_keys, inner_keys): (Vec<_>, Vec<_>) = correlation.into_iter().unzip();
+
+        let mut plan = input;
+        if let Some(predicate) = filters.into_iter().reduce(|left, right| {
+            Expr::Binary(BinaryExpr {
+                left: Box::new(left),
+                op: BinaryOp::And,
+                right: Box::new(right),
+            })
+        }) {
+            plan = LogicalPlan::Filter(Filter {
+                input: Arc::new(plan),
+                predicate,
+            });
+        }
+        let columns = std::iter::once(&aggr)
+            .chain(inner_keys.iter())
+            .map(|e| e.to_column(plan.schema()))
+            .collect::<BustubxResult<Vec<Column>>>()?;
+        let aggregate_schema = Arc::new(Schema::new(columns));
+
+        let key_names = (0..outer_keys.len())
+            .map(|i| format!("{value_name}_key{i}"))
+            .collect::<Vec<_>>();
+        let exprs = std::iter::once((&aggr, value_name))
+            .chain(inner_keys.iter().zip(key_names.iter().map(String::as_str)))
+            .map(|(e, name)| {
+                Ok(Expr::Alias(Alias {
+                    expr: Box::new(columnize_expr(e, &aggregate_schema)?),
+                    name: name.to_string(),
+                }))
+            })
+            .collect::<BustubxResult<Vec<Expr>>>()?;
+        let aggregate = LogicalPlan::Aggregate(Aggregate {
+            input: Arc::new(plan),
+            group_exprs: inner_keys,
+            aggr_exprs: vec![aggr],
+            grouping_sets: vec![],
+            schema: aggregate_schema,
+        });
+        let schema = Arc::new(project_schema(&aggregate, &exprs)?);
+        let plan = LogicalPlan::Project(Project {
+            exprs,
+            input: Arc::new(aggregate),
+            schema,
+        });
+        Ok((plan, outer_keys.into_iter().zip(key_names).collect()))
+    }
+}
+
+// Conjuncts of a subquery WHERE reading only the subquery filter its rows, the others must
+// equal an expression of the subquery with one of the outer query. Returns the filters and
+// the outer expressions paired with the subquery expressions they equal.
+#[allow(clippy::type_complexity)]
+fn split_correlation(
+    conjuncts: Vec<Expr>,
+    inner_schema: &Schema,
+    outer_schema: &Schema,
+    unsupported: impl Fn(&str) -> BustubxError,
+) -> BustubxResult<(Vec<Expr>, Vec<(Expr, Expr)>)> {
+    let is_outer = |e: &Expr| !e.is_bound_by(inner_schema) && e.is_bound_by(outer_schema);
+    let mut filters = vec![];
+    let mut correlation = vec![];
+    for conjunct in conjuncts {
+        if conjunct.is_bound_by(inner_schema) {
+            filters.push(conjunct);
+            continue;
+        }
+        match conjunct {
+            Expr::Binary(BinaryExpr {
+                left,
+                op: BinaryOp::Eq,
+                right,
+            }) if is_outer(&left) && right.is_bound_by(inner_schema) => {
+                correlation.push((*left, *right));
+            }
+            Expr::Binary(BinaryExpr {
+                left,
+                op: BinaryOp::Eq,
+                right,
+            }) if is_outer(&right) && left.is_bound_by(inner_schema) => {
+                correlation.push((*right, *left));
+            }
+            conjunct => {
+                // a column neither query has fails to resolve as usual
+                let scope = Schema::try_merge([inner_schema.clone(), outer_schema.clone()])?;
+                if !conjunct.is_bound_by(&scope) {
+                    conjunct.data_type(&scope)?;
+                }
+                return Err(unsupported(
+                    "only correlation by equalities with the outer query is supported",
+                ));
+            }
+        }
+    }
+    Ok((filters, correlation))
+}
+
+pub fn is_subquery_predicate(predicate: &sqlparser::ast::Expr) -> bool {
+    subquery_predicate(predicate).is_some()
+}
+
+// The outer key, the subquery and whether the predicate is negated
+fn subquery_predicate(
+    predicate: &sqlparser::ast::Expr,
+) -> Option<(Option<&sqlparser::ast::Expr>, &sqlparser::ast::Query, bool)> {
+    match predicate {
+        sqlparser::ast::Expr::Exists { subquery, negated } => Some((None, subquery, *negated)),
+        sqlparser::ast::Expr::InSubquery {
+            expr,
+            subquery,
+            negated,
+        } => Some((Some(expr), subquery, *negated)),
+        sqlparser::ast::Expr::Nested(expr) => subquery_predicate(expr),
+        sqlparser::ast::Expr::UnaryOp {
+            op: sqlparser::ast::UnaryOperator::Not,
+            expr,
+        } => subquery_predicate(expr).map(|(key, subquery, negated)| (key, subquery, !negated)),
+        _ => None,
+    }
+}
+
+pub fn is_scalar_subquery_predicate(predicate: &sqlparser::ast::Expr) -> bool {
+    scalar_subquery_comparison(predicate).is_some()
+}
+
+// The compared expression, the comparison, the subquery and whether the subquery is the
+// left operand
+fn scalar_subquery_comparison(
+    predicate: &sqlparser::ast::Expr,
+) -> Option<(
+    &sqlparser::ast::Expr,
+    &sqlparser::ast::BinaryOperator,
+    &sqlparser::ast::Query,
+    bool,
+)> {
+    match predicate {
+        sqlparser::ast::Expr::BinaryOp { left, op, right }
+            if matches!(
+                op,
+                sqlparser::ast::BinaryOperator::Eq
+                    | sqlparser::ast::BinaryOperator::NotEq
+                    | sqlparser::ast::BinaryOperator::Lt
+                    | sqlparser::ast::BinaryOperator::LtEq
+                    | sqlparser::ast::BinaryOperator::Gt
+                    | sqlparser::ast::BinaryOperator::GtEq
+            ) =>
+        {
+            match (left.as_ref(), right.as_ref()) {
+                (sqlparser::ast::Expr::Subquery(subquery), expr) => {
+                    Some((expr, op, subquery.as_ref(), true))
+                }
+                (expr, sqlparser::ast::Expr::Subquery(subquery)) => {
+                    Some((expr, op, subquery.as_ref(), false))
+                }
+                _ => None,
+            }
+        }
+        sqlparser::ast::Expr::Nested(expr) => scalar_subquery_comparison(expr),
+        _ => None,
+    }
+}
+
+/// Operands of the top level ANDs of `predicate`.
+pub fn split_conjunction<'a>(
+    predicate: &'a sqlparser::ast::Expr,
+    conjuncts: &mut Vec<&'a sqlparser::ast::Expr>,
+) {
+    match predicate {
+        sqlparser::ast::Expr::BinaryOp {
+            left,
+            op: sqlparser::ast::BinaryOperator::And,
+            right,
+        } => {
+            split_conjunction(left, conjuncts);
+            split_conjunction(right, conjuncts);
+        }
+        _ => conjuncts.push(predicate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_util::rows;
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database};
+
+    type Row = (Option<i64>, Option<i64>);
+
+    fn int(value: &ScalarValue) -> Option<i64> {
+        match *value {
+            ScalarValue::Int32(v) => v.map(|v| v as i64),
+            ScalarValue::Int64(v) => v,
+            ref v => panic!("unexpected value {v}"),
+        }
+    }
+
+    fn pairs(db: &mut Database, sql: &str) -> Vec<Row> {
+        let mut pairs = rows(db, sql)
+            .iter()
+            .map(|row| (int(&row[0]), int(&row[1])))
+            .collect::<Vec<_>>();
+        pairs.sort();
+        pairs
+    }
+
+    // rows of `outer` passing `a <op> (SELECT avg(b) FROM inner WHERE inner.k = outer.k
+    // AND b > min_b)`, evaluated one outer row at a time
+    fn naive(outer: &[Row], inner: &[Row], op: &str, min_b: i64) -> Vec<Row> {
+        outer
+            .iter()
+            .filter(|(a, k)| {
+                let values = inner
+                    .iter()
+                    .filter(|(b, inner_k)| {
+                        k.is_some() && inner_k == k && b.is_some_and(|b| b > min_b)
+                    })
+                    .map(|(b, _)| b.unwrap() as f64)
+                    .collect::<Vec<_>>();
+                // the subquery is NULL without rows, comparing with NULL is never true
+                let (Some(a), false) = (a, values.is_empty()) else {
+                    return false;
+                };
+                let avg = values.iter().sum::<f64>() / values.len() as f64;
+                let a = *a as f64;
+                match op {
+                    "=" => a == avg,
+                    "<>" => a != avg,
+                    "<" => a < avg,
+                    _ => a >= avg,
+                }
+            })
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    pub fn test_scalar_subquery_parity_with_naive_evaluation() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, k int)").unwrap();
+        db.run("create table t2 (b int, k int)").unwrap();
+        // k = 3 only has null values, k = 4 and null have no group at all
+        db.run(
+            "insert into t1 values (3, 1), (2, 1), (3, 1), (5, 2), (1, 2), (0, 3), \
+             (7, 4), (3, null), (null, 1)",
+        )
+        .unwrap();
+        db.run("insert into t2 values (2, 1), (4, 1), (5, 2), (null, 3), (9, null), (1, 5)")
+            .unwrap();
+        let outer = pairs(&mut db, "select a, k from t1");
+        let inner = pairs(&mut db, "select b, k from t2");
+
+        for op in ["=", "<>", "<", ">="] {
+            for (filter, min_b) in [("", i64::MIN), (" and t2.b > 2", 2)] {
+                let subquery = format!("(select avg(b) from t2 where t2.k = t1.k{filter})");
+                let expected = naive(&outer, &inner, op, min_b);
+                let sql = format!("select a, k from t1 where a {op} {subquery}");
+                assert_eq!(pairs(&mut db, &sql), expected, "{sql}");
+                let flipped = match op {
+                    "<" => ">",
+                    ">=" => "<=",
+                    op => op,
+                };
+                let sql = format!("select a, k from t1 where {subquery} {flipped} a");
+                assert_eq!(pairs(&mut db, &sql), expected, "{sql}");
+            }
+        }
+
+        // unqualified columns of the subquery resolve to its own table
+        assert_eq!(
+            pairs(
+                &mut db,
+                "select a, k from t1 where a = (select avg(b) from t2 where t1.k = k)"
+            ),
+            vec![(Some(3), Some(1)), (Some(3), Some(1)), (Some(5), Some(2))]
+        );
+        // combined with other predicates and a second subquery
+        assert_eq!(
+            pairs(
+                &mut db,
+                "select a, k from t1 where a > 2 \
+                 and a = (select avg(b) from t2 where t2.k = t1.k) \
+                 and a in (select b from t2 where b > 4)"
+            ),
+            vec![(Some(5), Some(2))]
+        );
+        assert_eq!(
+            pairs(
+                &mut db,
+                "select a, k from t1 where a <= (select avg(b) from t2 where t2.k = t1.k) \
+                 and a > (select avg(b) from t2 where t2.k = t1.k and t2.b < 3)"
+            ),
+            vec![(Some(3), Some(1)), (Some(3), Some(1))]
+        );
+        // uncorrelated, compared with the average of every row
+        assert_eq!(
+            pairs(
+                &mut db,
+                "select a, k from t1 where a > (select avg(b) from t2)"
+            ),
+            vec![(Some(5), Some(2)), (Some(7), Some(4))]
+        );
+    }
+
+    #[test]
+    pub fn test_unsupported_scalar_subqueries() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, k int)").unwrap();
+        db.run("create table t2 (b int, k int)").unwrap();
+
+        for sql in [
+            // count is 0 rather than NULL for an outer row without a group
+            "select a from t1 where a = (select count(b) from t2 where t2.k = t1.k)",
+            "select a from t1 where a = (select avg(b) from t2 where t2.k > t1.k)",
+            "select a from t1 where a = (select avg(b) from t2 where t2.k = t1.k or t2.b = 1)",
+            "select a from t1 where a = (select b from t2 where t2.k = t1.k)",
+            "select a from t1 where a = (select avg(t1.a) from t2 where t2.k = t1.k)",
+            "select a from t1 where a = (select avg(b) from t2 where t2.k = t1.k group by b)",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(BustubxError::NotSupport(_))),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_unsupported_outer_references() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, k int)").unwrap();
+        db.run("create table t2 (b int, k int)").unwrap();
+        db.run("create table t3 (c int)").unwrap();
+        db.run("insert into t1 values (1, 1), (2, 2)").unwrap();
+        db.run("insert into t2 values (1, 1), (3, 2)").unwrap();
+        db.run("insert into t3 values (1), (2)").unwrap();
+
+        for sql in [
+            "select a from t1 where exists (select t1.a from t2)",
+            "select a from t1 where exists (select 1 from t2 inner join t3 on c = t1.k)",
+            "select a from t1 where a in (select max(b) from t2 group by t1.k)",
+            "select a from t1 where exists (select 1 from t2 where t1.a in (select c from t3))",
+            "select a from t1 where exists (select 1 from t2 where exists \
+             (select 1 from t3 where c = t1.k))",
+            "select a from t1 where a = (select max(b) from t2 inner join t3 on c = t1.k \
+             where t2.k = t1.k)",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(BustubxError::NotSupport(_))),
+                "{sql}"
+            );
+        }
+        // a column no query has stays a plan error
+        assert!(matches!(
+            db.run("select a from t1 where exists (select 1 from t2 where t2.d = t1.a)"),
+            Err(BustubxError::Plan(_))
+        ));
+        assert_eq!(
+            pairs(
+                &mut db,
+                "select a, k from t1 where exists (select 1 from t2 where t2.k = t1.k \
+                 and exists (select 1 from t3 where c = t2.b))"
+            ),
+            vec![(Some(1), Some(1))]
+        );
+    }
+}
@@ -19,14 +19,26 @@ impl<'a> LogicalPlanner<'a> {
                 )))
             }
         };
+        self.context.catalog.check_table_writable(&table_ref)?;
 
+        if self
+            .context
+            .catalog
+            .catalog_table(&table_ref)?
+            .is_partitioned()
+        {
+            return Err(BustubxError::NotSupport(format!(
+                "update of partitioned table {} not supported, update its partitions",
+                table_ref
+            )));
+        }
         let table_schema = self.context.catalog.table_heap(&table_ref)?.schema.clone();
 
         let mut assignment_map = HashMap::new();
         for assign in assignments {
             let column_name = assign
                 .id
-                .get(0)
+                .first()
                 .ok_or(BustubxError::Plan(format!(
                     "Assignment {} is not supported",
                     assign
@@ -38,7 +50,7 @@ impl<'a> LogicalPlanner<'a> {
         }
 
         let selection = match selection {
-            Some(e) => Some(self.bind_expr(e)?),
+            Some(e) => Some(self.bind_predicate(e, &table_schema)?),
             None => None,
         };
 
@@ -47,6 +59,8 @@ impl<'a> LogicalPlanner<'a> {
             table_schema,
             assignments: assignment_map,
             selection,
+            order_by: vec![],
+            limit: None,
         }))
     }
 }
@@ -1,6 +1,9 @@
 pub mod logical_plan;
 mod logical_planner;
 mod physical_planner;
+mod plan_cache;
 
+pub(crate) use logical_planner::{create_index_sql, create_table_sql, sql_literal};
 pub use logical_planner::{LogicalPlanner, PlannerContext};
 pub use physical_planner::PhysicalPlanner;
+pub use plan_cache::{PlanCache, PlanCacheStats};
@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod physical_planner;
 
 pub use physical_planner::PhysicalPlanner;
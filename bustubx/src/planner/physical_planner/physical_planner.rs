@@ -1,61 +1,131 @@
-use crate::catalog::{Catalog, Schema, DEFAULT_SCHEMA_NAME};
+use crate::catalog::{Catalog, DataType, Partitioning, Schema, SchemaRef, DEFAULT_SCHEMA_NAME};
+use crate::common::value_ord::Collation;
+use crate::common::{numeric, ScalarValue, TableReference};
+use crate::config::ExecutionOptions;
+use std::ops::Bound;
 use std::sync::Arc;
 
+use crate::expression::{AggregateFunction, BinaryExpr, BinaryOp, Expr, ExprTrait, Literal};
+use crate::function::AggregateFunctionKind;
 use crate::planner::logical_plan::{
-    Aggregate, CreateIndex, CreateTable, EmptyRelation, Filter, Insert, Join, Limit, LogicalPlan,
+    Aggregate, CreateIndex, CreateSchema, CreateTable, Delete, DropSchema, DropTable,
+    EmptyRelation, Filter, Insert, Join, JoinType, Limit, LogicalPlan, Maintenance, OrderByExpr,
     Project, Sort, TableScan, Update, Values,
 };
 
+use crate::execution::operator_name;
+use crate::execution::physical_plan::PhysicalCreateSchema;
+use crate::execution::physical_plan::PhysicalDropSchema;
+use crate::execution::physical_plan::PhysicalDropTable;
 use crate::execution::physical_plan::PhysicalLimit;
+use crate::execution::physical_plan::PhysicalMaintenance;
+use crate::execution::physical_plan::PhysicalMinMax;
 use crate::execution::physical_plan::PhysicalNestedLoopJoin;
+use crate::execution::physical_plan::PhysicalParallelSeqScan;
 use crate::execution::physical_plan::PhysicalPlan;
 use crate::execution::physical_plan::PhysicalProject;
 use crate::execution::physical_plan::PhysicalSeqScan;
 use crate::execution::physical_plan::PhysicalSort;
 use crate::execution::physical_plan::PhysicalValues;
-use crate::execution::physical_plan::{PhysicalAggregate, PhysicalCreateTable};
-use crate::execution::physical_plan::{PhysicalCreateIndex, PhysicalEmpty};
-use crate::execution::physical_plan::{PhysicalFilter, PhysicalIndexScan};
-use crate::execution::physical_plan::{PhysicalInsert, PhysicalUpdate};
+use crate::execution::physical_plan::{CountSource, DmlOrder, PhysicalCount};
+use crate::execution::physical_plan::{KeyRange, PhysicalInsert, PhysicalUpdate};
+use crate::execution::physical_plan::{PhysicalAggregate, PhysicalAppend, PhysicalCreateTable};
+use crate::execution::physical_plan::{PhysicalCreateIndex, PhysicalDelete, PhysicalEmpty};
+use crate::execution::physical_plan::{PhysicalFilter, PhysicalHashSemiJoin, PhysicalIndexScan};
+use crate::optimizer::{OptimizerTrace, TraceDecision, TraceReason};
+use crate::transaction::INVALID_TRANSACTION_ID;
+use crate::Tuple;
+use crate::{BustubxError, BustubxResult};
+
+// Above this estimated selectivity random index lookups cost more than a sequential heap scan
+const INDEX_SCAN_MAX_SELECTIVITY: f64 = 0.1;
+// The heap of a clustered table is in the key order of its clustered index, a range of the
+// index reads a run of consecutive pages instead of a page per row
+const CLUSTERED_INDEX_SCAN_MAX_SELECTIVITY: f64 = 0.5;
 
 pub struct PhysicalPlanner<'a> {
     pub catalog: &'a Catalog,
+    // options of the statement planned, physical plans are made for every execution
+    pub options: &'a ExecutionOptions,
+    // records the access paths considered when EXPLAIN shows an optimizer trace
+    pub trace: Option<&'a OptimizerTrace>,
 }
 
 impl PhysicalPlanner<'_> {
-    pub fn create_physical_plan(&self, logical_plan: LogicalPlan) -> PhysicalPlan {
+    pub fn create_physical_plan(&self, logical_plan: LogicalPlan) -> BustubxResult<PhysicalPlan> {
         let logical_plan = Arc::new(logical_plan);
         self.build_plan(logical_plan)
     }
 
-    fn build_plan(&self, logical_plan: Arc<LogicalPlan>) -> PhysicalPlan {
+    // Queries as of an earlier transaction read the tables with sequential scans, indexes
+    // and row counts only know the latest rows
+    fn reads_snapshot(&self) -> bool {
+        self.options.snapshot_txn != INVALID_TRANSACTION_ID
+    }
+
+    fn build_plan(&self, logical_plan: Arc<LogicalPlan>) -> BustubxResult<PhysicalPlan> {
         let plan = match logical_plan.as_ref() {
-            LogicalPlan::CreateTable(CreateTable { name, columns }) => PhysicalPlan::CreateTable(
-                PhysicalCreateTable::new(name.clone(), Schema::new(columns.clone())),
-            ),
+            LogicalPlan::CreateTable(CreateTable {
+                name,
+                columns,
+                ttl_column,
+                logical_row_ids,
+                partitioning,
+                foreign_keys,
+            }) => PhysicalPlan::CreateTable(PhysicalCreateTable::new(
+                name.clone(),
+                Schema::new(columns.clone()),
+                ttl_column.clone(),
+                *logical_row_ids,
+                partitioning.clone(),
+                foreign_keys.clone(),
+            )),
             LogicalPlan::CreateIndex(CreateIndex {
                 index_name,
                 table,
                 table_schema,
                 columns,
+                unique,
+                deferrable,
             }) => PhysicalPlan::CreateIndex(PhysicalCreateIndex::new(
                 index_name.clone(),
                 table.clone(),
                 table_schema.clone(),
                 columns.clone(),
+                *unique,
+                *deferrable,
+            )),
+            LogicalPlan::DropTable(DropTable { tables }) => {
+                PhysicalPlan::DropTable(PhysicalDropTable::new(tables.clone()))
+            }
+            LogicalPlan::CreateSchema(CreateSchema {
+                schema_name,
+                if_not_exists,
+            }) => PhysicalPlan::CreateSchema(PhysicalCreateSchema::new(
+                schema_name.clone(),
+                *if_not_exists,
+            )),
+            LogicalPlan::DropSchema(DropSchema {
+                schema_names,
+                tables,
+            }) => PhysicalPlan::DropSchema(PhysicalDropSchema::new(
+                schema_names.clone(),
+                tables.clone(),
             )),
             LogicalPlan::Insert(Insert {
                 table,
                 table_schema,
                 projected_schema,
                 input,
+                on_conflict,
             }) => {
-                let input_physical_plan = self.build_plan(input.clone());
+                let input_physical_plan = self.build_plan(input.clone())?;
                 PhysicalPlan::Insert(PhysicalInsert::new(
                     table.clone(),
                     table_schema.clone(),
                     projected_schema.clone(),
                     Arc::new(input_physical_plan),
+                    on_conflict.clone(),
                 ))
             }
             LogicalPlan::Values(Values { schema, values }) => {
@@ -66,7 +136,7 @@ impl PhysicalPlanner<'_> {
                 input,
                 schema,
             }) => {
-                let input_physical_plan = self.build_plan(input.clone());
+                let input_physical_plan = self.build_plan(input.clone())?;
                 PhysicalPlan::Project(PhysicalProject::new(
                     exprs.clone(),
                     schema.clone(),
@@ -74,59 +144,88 @@ impl PhysicalPlanner<'_> {
                 ))
             }
             LogicalPlan::Filter(Filter { predicate, input }) => {
-                let input_physical_plan = self.build_plan(input.clone());
+                let input_physical_plan = match input.as_ref() {
+                    LogicalPlan::TableScan(table_scan) => {
+                        if let Some(partition_scan) =
+                            self.build_partition_scan(table_scan, Some(predicate))
+                        {
+                            self.trace_access_paths(table_scan, predicate, &partition_scan);
+                            partition_scan
+                        } else if let Some(index_scan) = self
+                            .build_index_ranges_scan(table_scan, predicate)
+                            .or_else(|| self.build_index_prefix_scan(table_scan, predicate))
+                        {
+                            self.trace_access_paths(table_scan, predicate, &index_scan);
+                            index_scan
+                        } else {
+                            let selectivity = self
+                                .catalog
+                                .table_statistics(&table_scan.table_ref)
+                                .map(|stats| stats.selectivity(predicate));
+                            let table_scan_plan = self.build_table_scan(table_scan, selectivity);
+                            // the workers of a parallel scan filter the rows themselves
+                            if let Some(parallel_scan) =
+                                self.build_parallel_scan(&table_scan_plan, Some(predicate))
+                            {
+                                self.trace_access_paths(table_scan, predicate, &parallel_scan);
+                                return Ok(parallel_scan);
+                            }
+                            self.trace_access_paths(table_scan, predicate, &table_scan_plan);
+                            table_scan_plan
+                        }
+                    }
+                    _ => {
+                        let input_physical_plan = self.build_plan(input.clone())?;
+                        if let Some(trace) = self.trace {
+                            trace.record(
+                                TraceDecision::PredicatePushdown,
+                                predicate.to_string(),
+                                false,
+                                TraceReason::NotAboveScan {
+                                    operator: operator_name(&input_physical_plan).to_string(),
+                                },
+                            );
+                        }
+                        input_physical_plan
+                    }
+                };
                 PhysicalPlan::Filter(PhysicalFilter::new(
                     predicate.clone(),
                     Arc::new(input_physical_plan),
                 ))
             }
-            LogicalPlan::TableScan(TableScan {
-                table_ref,
-                table_schema,
-                filters: _,
-                limit: _,
-            }) => {
-                // TODO fix testing
-                if let Some(catalog_table) = self
-                    .catalog
-                    .schemas
-                    .get(table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME))
-                    .unwrap()
-                    .tables
-                    .get(table_ref.table())
-                {
-                    if !catalog_table.indexes.is_empty() {
-                        PhysicalPlan::IndexScan(PhysicalIndexScan::new(
-                            table_ref.clone(),
-                            catalog_table.indexes.keys().next().unwrap().clone(),
-                            table_schema.clone(),
-                            ..,
-                        ))
-                    } else {
-                        PhysicalPlan::SeqScan(PhysicalSeqScan::new(
-                            table_ref.clone(),
-                            table_schema.clone(),
-                        ))
-                    }
-                } else {
-                    PhysicalPlan::SeqScan(PhysicalSeqScan::new(
-                        table_ref.clone(),
-                        table_schema.clone(),
-                    ))
-                }
-            }
+            LogicalPlan::TableScan(table_scan) => self
+                .build_partition_scan(table_scan, None)
+                .unwrap_or_else(|| {
+                    let table_scan_plan = self.build_table_scan(table_scan, None);
+                    self.build_parallel_scan(&table_scan_plan, None)
+                        .unwrap_or(table_scan_plan)
+                }),
             LogicalPlan::Limit(Limit {
                 limit,
                 offset,
                 input,
             }) => {
-                let input_physical_plan = self.build_plan((*input).clone());
+                let input_physical_plan = self.build_plan((*input).clone())?;
                 PhysicalPlan::Limit(PhysicalLimit::new(
                     *limit,
                     *offset,
                     Arc::new(input_physical_plan),
                 ))
             }
+            LogicalPlan::Join(Join {
+                left,
+                right,
+                join_type,
+                condition,
+                schema,
+            }) if matches!(
+                join_type,
+                JoinType::LeftSemi | JoinType::LeftAnti | JoinType::NullAwareLeftAnti
+            ) =>
+            {
+                self.build_semi_join(left, right, *join_type, condition.as_ref(), schema)?
+            }
             LogicalPlan::Join(Join {
                 left,
                 right,
@@ -134,8 +233,16 @@ impl PhysicalPlanner<'_> {
                 condition,
                 schema,
             }) => {
-                let left_physical_plan = self.build_plan((*left).clone());
-                let right_physical_plan = self.build_plan((*right).clone());
+                let left_physical_plan = self.build_plan((*left).clone())?;
+                let right_physical_plan = self.build_plan((*right).clone())?;
+                if let Some(trace) = self.trace {
+                    trace.record(
+                        TraceDecision::JoinReordering,
+                        format!("{join_type} join"),
+                        false,
+                        TraceReason::JoinOrderAsWritten,
+                    );
+                }
                 PhysicalPlan::NestedLoopJoin(PhysicalNestedLoopJoin::new(
                     *join_type,
                     condition.clone(),
@@ -149,12 +256,17 @@ impl PhysicalPlanner<'_> {
                 ref input,
                 limit: _,
             }) => {
-                // TODO limit
-                let input_physical_plan = self.build_plan(Arc::clone(input));
-                PhysicalPlan::Sort(PhysicalSort::new(
-                    expr.clone(),
-                    Arc::new(input_physical_plan),
-                ))
+                // an input already producing the ordering makes the sort redundant
+                if let Some(ordered_plan) = self.build_ordered_plan(input, expr) {
+                    ordered_plan
+                } else {
+                    // TODO limit
+                    let input_physical_plan = self.build_plan(Arc::clone(input))?;
+                    PhysicalPlan::Sort(PhysicalSort::new(
+                        expr.clone(),
+                        Arc::new(input_physical_plan),
+                    ))
+                }
             }
             LogicalPlan::EmptyRelation(EmptyRelation {
                 produce_one_row,
@@ -167,28 +279,1642 @@ impl PhysicalPlanner<'_> {
                 input,
                 group_exprs,
                 aggr_exprs,
+                grouping_sets,
                 schema,
             }) => {
-                let input_physical_plan = self.build_plan(Arc::clone(input));
-                PhysicalPlan::Aggregate(PhysicalAggregate::new(
-                    Arc::new(input_physical_plan),
-                    group_exprs.clone(),
-                    aggr_exprs.clone(),
-                    schema.clone(),
-                ))
+                // a row count the storage already keeps, or the ends of an index, need no scan
+                if let Some(shortcut_plan) = grouping_sets
+                    .is_empty()
+                    .then(|| {
+                        self.build_count_plan(input, group_exprs, aggr_exprs, schema)
+                            .or_else(|| {
+                                self.build_min_max_plan(input, group_exprs, aggr_exprs, schema)
+                            })
+                    })
+                    .flatten()
+                {
+                    shortcut_plan
+                } else {
+                    let input_physical_plan = self.build_plan(Arc::clone(input))?;
+                    PhysicalPlan::Aggregate(
+                        PhysicalAggregate::new(
+                            Arc::new(input_physical_plan),
+                            group_exprs.clone(),
+                            aggr_exprs.clone(),
+                            schema.clone(),
+                        )
+                        .with_grouping_sets(grouping_sets.clone()),
+                    )
+                }
             }
             LogicalPlan::Update(Update {
                 table,
                 table_schema,
                 assignments,
                 selection,
-            }) => PhysicalPlan::Update(PhysicalUpdate::new(
-                table.clone(),
-                table_schema.clone(),
-                assignments.clone(),
-                selection.clone(),
+                order_by,
+                limit,
+            }) => PhysicalPlan::Update(
+                PhysicalUpdate::new(
+                    table.clone(),
+                    table_schema.clone(),
+                    assignments.clone(),
+                    selection.clone(),
+                )
+                .with_order(self.dml_order(table, order_by, *limit)),
+            ),
+            LogicalPlan::Delete(Delete {
+                table,
+                table_schema,
+                selection,
+                order_by,
+                limit,
+            }) => PhysicalPlan::Delete(
+                PhysicalDelete::new(table.clone(), table_schema.clone(), selection.clone())
+                    .with_order(self.dml_order(table, order_by, *limit)),
+            ),
+            LogicalPlan::Maintenance(Maintenance {
+                kind,
+                tables,
+                schema,
+            }) => PhysicalPlan::Maintenance(PhysicalMaintenance::new(
+                kind.clone(),
+                tables.clone(),
+                schema.clone(),
             )),
         };
-        plan
+        Ok(plan)
+    }
+
+    /// Build `logical_plan` so that it produces rows in `ordering`, the ordering is translated
+    /// through order preserving operators down to a table scan that an index can satisfy.
+    /// Returns None if no such plan exists.
+    fn build_ordered_plan(
+        &self,
+        logical_plan: &Arc<LogicalPlan>,
+        ordering: &[OrderByExpr],
+    ) -> Option<PhysicalPlan> {
+        match logical_plan.as_ref() {
+            LogicalPlan::Project(Project {
+                exprs,
+                input,
+                schema,
+            }) => {
+                let input_ordering = ordering
+                    .iter()
+                    .map(|order| {
+                        let Expr::Column(col) = order.expr.as_ref() else {
+                            return None;
+                        };
+                        let idx = schema.index_of(col.relation.as_ref(), &col.name).ok()?;
+                        let input_expr = match &exprs[idx] {
+                            Expr::Alias(alias) => alias.expr.as_ref(),
+                            expr => expr,
+                        };
+                        matches!(input_expr, Expr::Column(_)).then(|| OrderByExpr {
+                            expr: Box::new(input_expr.clone()),
+                            ..order.clone()
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                let input_physical_plan = self.build_ordered_plan(input, &input_ordering)?;
+                Some(PhysicalPlan::Project(PhysicalProject::new(
+                    exprs.clone(),
+                    schema.clone(),
+                    Arc::new(input_physical_plan),
+                )))
+            }
+            LogicalPlan::Filter(Filter { predicate, input }) => {
+                let input_physical_plan = self.build_ordered_plan(input, ordering)?;
+                Some(PhysicalPlan::Filter(PhysicalFilter::new(
+                    predicate.clone(),
+                    Arc::new(input_physical_plan),
+                )))
+            }
+            LogicalPlan::TableScan(table_scan) if !self.reads_snapshot() => {
+                let (index_name, descending) =
+                    self.ordering_index(&table_scan.table_ref, ordering)?;
+                Some(PhysicalPlan::IndexScan(
+                    PhysicalIndexScan::new(
+                        table_scan.table_ref.clone(),
+                        index_name,
+                        table_scan.table_schema.clone(),
+                        ..,
+                    )
+                    .with_descending(descending),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    // Index whose leading key columns are the ordering columns, and whether it is read
+    // backward. An index orders NULL keys first, so every column has to be ascending with
+    // NULLs first, or descending with NULLs last.
+    fn ordering_index(
+        &self,
+        table_ref: &TableReference,
+        ordering: &[OrderByExpr],
+    ) -> Option<(String, bool)> {
+        let descending = !ordering.first()?.asc;
+        if ordering
+            .iter()
+            .any(|order| order.asc == descending || order.nulls_first == descending)
+        {
+            return None;
+        }
+        let catalog_table = self.catalog.catalog_table(table_ref).ok()?;
+        let mut index_names: Vec<&String> = catalog_table.indexes.keys().collect();
+        index_names.sort();
+        index_names
+            .into_iter()
+            .find(|index_name| {
+                let key_columns = &catalog_table.indexes[*index_name].key_schema.columns;
+                ordering.len() <= key_columns.len()
+                    && ordering.iter().zip(key_columns.iter()).all(|(order, key)| {
+                        matches!(order.expr.as_ref(), Expr::Column(col)
+                            if col.name == key.name
+                                && col
+                                    .relation
+                                    .as_ref()
+                                    .is_none_or(|rel| rel.resolved_eq(table_ref)))
+                    })
+            })
+            .map(|index_name| (index_name.clone(), descending))
+    }
+
+    /// ORDER BY and LIMIT of a DELETE or UPDATE, an index in the order is read instead of
+    /// sorting the matching rows.
+    fn dml_order(
+        &self,
+        table_ref: &TableReference,
+        order_by: &[OrderByExpr],
+        limit: Option<usize>,
+    ) -> DmlOrder {
+        let index = self.ordering_index(table_ref, order_by);
+        DmlOrder {
+            order_by: order_by.to_vec(),
+            limit,
+            descending: index.as_ref().is_some_and(|(_, descending)| *descending),
+            index: index.map(|(index_name, _)| index_name),
+        }
+    }
+
+    /// Semi and anti joins of subquery predicates hash the keys of the subquery rows, the
+    /// planner only makes equality conditions for them.
+    fn build_semi_join(
+        &self,
+        left: &Arc<LogicalPlan>,
+        right: &Arc<LogicalPlan>,
+        join_type: JoinType,
+        condition: Option<&Expr>,
+        schema: &SchemaRef,
+    ) -> BustubxResult<PhysicalPlan> {
+        let (mut left_keys, mut right_keys, mut key_types) = (vec![], vec![], vec![]);
+        let mut pending = condition.into_iter().collect::<Vec<_>>();
+        while let Some(expr) = pending.pop() {
+            let (left_key, right_key) = match expr {
+                Expr::Binary(BinaryExpr {
+                    left,
+                    op: BinaryOp::And,
+                    right,
+                }) => {
+                    pending.push(right.as_ref());
+                    pending.push(left.as_ref());
+                    continue;
+                }
+                Expr::Binary(BinaryExpr {
+                    left,
+                    op: BinaryOp::Eq,
+                    right,
+                }) => (left, right),
+                _ => {
+                    return Err(BustubxError::Internal(format!(
+                        "{join_type} join condition {expr} is not a conjunction of equalities"
+                    )))
+                }
+            };
+            let key_type = match (
+                left_key.data_type(left.schema()),
+                right_key.data_type(right.schema()),
+            ) {
+                (Ok(l), Ok(r)) => numeric::coerce_types(&l, &r).ok(),
+                _ => None,
+            };
+            left_keys.push(left_key.as_ref().clone());
+            right_keys.push(right_key.as_ref().clone());
+            key_types.push(key_type);
+        }
+        Ok(PhysicalPlan::HashSemiJoin(PhysicalHashSemiJoin::new(
+            join_type,
+            left_keys,
+            right_keys,
+            key_types,
+            Arc::new(self.build_plan(left.clone())?),
+            Arc::new(self.build_plan(right.clone())?),
+            schema.clone(),
+        )))
+    }
+
+    /// Plan `COUNT(*)` without grouping over a table, or over a table filtered by a range of
+    /// one indexed column, as a count of the stored rows or index entries.
+    /// Returns None if the rows have to be aggregated one by one.
+    fn build_count_plan(
+        &self,
+        input: &Arc<LogicalPlan>,
+        group_exprs: &[Expr],
+        aggr_exprs: &[Expr],
+        schema: &SchemaRef,
+    ) -> Option<PhysicalPlan> {
+        let counts_rows = |expr: &Expr| {
+            matches!(expr, Expr::AggregateFunction(AggregateFunction {
+                func_kind: AggregateFunctionKind::Count,
+                args,
+                distinct: false,
+            }) if matches!(args.as_slice(), [Expr::Literal(Literal { value })] if !value.is_null()))
+        };
+        if self.reads_snapshot()
+            || !group_exprs.is_empty()
+            || aggr_exprs.is_empty()
+            || !aggr_exprs.iter().all(counts_rows)
+        {
+            return None;
+        }
+        let (table_scan, source) = match input.as_ref() {
+            LogicalPlan::TableScan(table_scan) => (table_scan, CountSource::Table),
+            LogicalPlan::Filter(Filter { predicate, input }) => {
+                let LogicalPlan::TableScan(table_scan) = input.as_ref() else {
+                    return None;
+                };
+                (table_scan, self.count_index_range(table_scan, predicate)?)
+            }
+            _ => return None,
+        };
+        // the rows of a partitioned table are counted in its partitions
+        if self
+            .catalog
+            .catalog_table(&table_scan.table_ref)
+            .ok()?
+            .is_partitioned()
+        {
+            return None;
+        }
+        Some(PhysicalPlan::Count(PhysicalCount::new(
+            table_scan.table_ref.clone(),
+            source,
+            schema.clone(),
+        )))
+    }
+
+    /// Plan MIN and MAX without grouping of one column over a table, or over a table
+    /// filtered by a range of that column, as a probe of the ends of an index range.
+    /// Returns None if the rows have to be aggregated one by one.
+    fn build_min_max_plan(
+        &self,
+        input: &Arc<LogicalPlan>,
+        group_exprs: &[Expr],
+        aggr_exprs: &[Expr],
+        schema: &SchemaRef,
+    ) -> Option<PhysicalPlan> {
+        if self.reads_snapshot() || !group_exprs.is_empty() {
+            return None;
+        }
+        let mut aggregates = vec![];
+        let mut column = None;
+        for expr in aggr_exprs {
+            let Expr::AggregateFunction(AggregateFunction {
+                func_kind: func_kind @ (AggregateFunctionKind::Min | AggregateFunctionKind::Max),
+                args,
+                ..
+            }) = expr
+            else {
+                return None;
+            };
+            let [Expr::Column(arg)] = args.as_slice() else {
+                return None;
+            };
+            if column.is_some_and(|column| column != arg) {
+                return None;
+            }
+            column = Some(arg);
+            aggregates.push(func_kind.clone());
+        }
+        let column = column?;
+        let (table_scan, predicate) = match input.as_ref() {
+            LogicalPlan::TableScan(table_scan) => (table_scan, None),
+            LogicalPlan::Filter(Filter { predicate, input }) => {
+                let LogicalPlan::TableScan(table_scan) = input.as_ref() else {
+                    return None;
+                };
+                (table_scan, Some(predicate))
+            }
+            _ => return None,
+        };
+        if column
+            .relation
+            .as_ref()
+            .is_some_and(|rel| !rel.resolved_eq(&table_scan.table_ref))
+        {
+            return None;
+        }
+        let catalog_table = self.catalog.catalog_table(&table_scan.table_ref).ok()?;
+        if catalog_table.is_partitioned() {
+            return None;
+        }
+
+        let (index_name, key_schema, range) = match predicate {
+            Some(predicate) => {
+                let comparisons = column_comparisons(table_scan, predicate)
+                    .into_iter()
+                    .collect::<Option<Vec<_>>>()?;
+                if comparisons.iter().any(|(name, _, _)| *name != column.name) {
+                    return None;
+                }
+                // bounds are keys of a single column
+                let (index_name, key_schema) = self
+                    .single_column_indexes(&table_scan.table_ref)
+                    .into_iter()
+                    .find(|(_, key_schema)| key_schema.columns[0].name == column.name)?;
+                let range = key_range(&key_schema, &comparisons)?;
+                (index_name, key_schema, range)
+            }
+            None => {
+                // the fewest key columns first, then by name
+                let (index_name, index) = catalog_table
+                    .indexes
+                    .iter()
+                    .filter(|(_, index)| index.key_schema.columns[0].name == column.name)
+                    .min_by_key(|(index_name, index)| {
+                        (index.key_schema.column_count(), index_name.as_str())
+                    })?;
+                let key_schema = index.key_schema.clone();
+                let key_column = &key_schema.columns[0];
+                // NULL keys sort first, a single column key starts past them
+                let start_bound = if key_schema.column_count() == 1 && key_column.nullable {
+                    Bound::Excluded(Tuple::new(
+                        key_schema.clone(),
+                        vec![ScalarValue::new_empty(key_column.data_type)],
+                    ))
+                } else {
+                    Bound::Unbounded
+                };
+                (
+                    index_name.clone(),
+                    key_schema,
+                    (start_bound, Bound::Unbounded),
+                )
+            }
+        };
+        // the index orders by the collation of the column, MIN and MAX compare binary
+        if key_schema.columns[0].collation != Collation::Binary {
+            return None;
+        }
+        Some(PhysicalPlan::MinMax(PhysicalMinMax::new(
+            table_scan.table_ref.clone(),
+            index_name,
+            range,
+            aggregates,
+            schema.clone(),
+        )))
+    }
+
+    // Range of a single column index holding exactly the rows `predicate` keeps, the predicate
+    // has to be a conjunction of comparisons between that column and constants
+    fn count_index_range(&self, table_scan: &TableScan, predicate: &Expr) -> Option<CountSource> {
+        let comparisons = column_comparisons(table_scan, predicate)
+            .into_iter()
+            .collect::<Option<Vec<_>>>()?;
+        let column_name = comparisons.first()?.0;
+        if comparisons.iter().any(|(name, _, _)| *name != column_name) {
+            return None;
+        }
+
+        let (index_name, key_schema) = self
+            .single_column_indexes(&table_scan.table_ref)
+            .into_iter()
+            .find(|(_, key_schema)| key_schema.columns[0].name == column_name)?;
+        let (start_bound, end_bound) = key_range(&key_schema, &comparisons)?;
+        // null keys sort before every value, the range must not start at them
+        if matches!(start_bound, Bound::Unbounded) && key_schema.columns[0].nullable {
+            return None;
+        }
+        Some(CountSource::IndexRange {
+            index_name,
+            start_bound,
+            end_bound,
+        })
+    }
+
+    /// Scan of the key ranges of a single column index holding the rows of `predicate`, a
+    /// comparison between the key column and a constant or an OR of them. A disjunct may
+    /// also be a conjunction holding such comparisons, its other conjuncts are left to the
+    /// filter. The ranges are sorted and overlapping or touching ones merged.
+    /// Returns None unless every disjunct translates to a range, or when the statistics
+    /// estimate too many rows for index lookups.
+    fn build_index_ranges_scan(
+        &self,
+        table_scan: &TableScan,
+        predicate: &Expr,
+    ) -> Option<PhysicalPlan> {
+        if self.reads_snapshot() {
+            return None;
+        }
+        let selectivity = self
+            .catalog
+            .table_statistics(&table_scan.table_ref)
+            .map(|stats| stats.selectivity(predicate));
+        if selectivity.is_some_and(|selectivity| selectivity > CLUSTERED_INDEX_SCAN_MAX_SELECTIVITY)
+        {
+            return None;
+        }
+        let clustered_index = self
+            .catalog
+            .catalog_table(&table_scan.table_ref)
+            .ok()
+            .and_then(|catalog_table| catalog_table.clustered_index.clone());
+        let disjuncts = disjunct_comparisons(table_scan, predicate);
+
+        let mut indexes = self.single_column_indexes(&table_scan.table_ref);
+        // the clustered index first, its ranges read the fewest heap pages
+        indexes.sort_by_key(|(index_name, _)| Some(index_name) != clustered_index.as_ref());
+        let (index_name, ranges) = indexes.into_iter().find_map(|(index_name, key_schema)| {
+            let key_name = key_schema.columns[0].name.as_str();
+            let ranges = disjuncts
+                .iter()
+                .map(|comparisons| {
+                    let comparisons = comparisons
+                        .iter()
+                        .flatten()
+                        .filter(|(name, op, _)| *name == key_name && is_range_op(*op))
+                        .copied()
+                        .collect::<Vec<_>>();
+                    if comparisons.is_empty() {
+                        return None;
+                    }
+                    key_range(&key_schema, &comparisons)
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some((index_name, coalesce_key_ranges(ranges)?))
+        })?;
+        let max_selectivity = if Some(&index_name) == clustered_index.as_ref() {
+            CLUSTERED_INDEX_SCAN_MAX_SELECTIVITY
+        } else {
+            INDEX_SCAN_MAX_SELECTIVITY
+        };
+        if selectivity.is_some_and(|selectivity| selectivity > max_selectivity) {
+            return None;
+        }
+        Some(PhysicalPlan::IndexScan(PhysicalIndexScan::with_ranges(
+            table_scan.table_ref.clone(),
+            index_name,
+            table_scan.table_schema.clone(),
+            ranges,
+        )))
+    }
+
+    /// Scan of the entries of a composite index whose leading key columns equal constants
+    /// of `predicate`, a conjunction, e.g. `a = 5` on an `(a, b)` index. The index with the
+    /// most such columns is read, its other conjuncts are left to the filter.
+    /// Returns None if no composite index has its first column compared, or when the
+    /// statistics estimate too many rows for index lookups.
+    fn build_index_prefix_scan(
+        &self,
+        table_scan: &TableScan,
+        predicate: &Expr,
+    ) -> Option<PhysicalPlan> {
+        if self.reads_snapshot() {
+            return None;
+        }
+        let catalog_table = self.catalog.catalog_table(&table_scan.table_ref).ok()?;
+        let equalities = column_comparisons(table_scan, predicate)
+            .into_iter()
+            .flatten()
+            .filter(|(_, op, _)| *op == BinaryOp::Eq)
+            .collect::<Vec<_>>();
+        let mut indexes = catalog_table
+            .indexes
+            .iter()
+            .filter(|(_, index)| index.key_schema.column_count() > 1)
+            .collect::<Vec<_>>();
+        indexes.sort_by(|a, b| a.0.cmp(b.0));
+        // the most leading columns, then by name
+        let (index_name, prefix) = indexes
+            .into_iter()
+            .filter_map(|(index_name, index)| {
+                let key_schema = &index.key_schema;
+                let values = key_schema
+                    .columns
+                    .iter()
+                    .map_while(|column| {
+                        let (_, _, value) = equalities
+                            .iter()
+                            .find(|(name, _, _)| *name == column.name)?;
+                        exact_key(value, &column.data_type)
+                    })
+                    .collect::<Vec<_>>();
+                if values.is_empty() {
+                    return None;
+                }
+                let columns = (0..values.len()).collect::<Vec<_>>();
+                let prefix_schema = Arc::new(key_schema.project(&columns).ok()?);
+                Some((index_name, Tuple::new(prefix_schema, values)))
+            })
+            .min_by_key(|(_, prefix)| std::cmp::Reverse(prefix.data.len()))?;
+
+        let max_selectivity = if catalog_table.clustered_index.as_ref() == Some(index_name) {
+            CLUSTERED_INDEX_SCAN_MAX_SELECTIVITY
+        } else {
+            INDEX_SCAN_MAX_SELECTIVITY
+        };
+        if self
+            .catalog
+            .table_statistics(&table_scan.table_ref)
+            .is_some_and(|stats| stats.selectivity(predicate) > max_selectivity)
+        {
+            return None;
+        }
+        Some(PhysicalPlan::IndexScan(PhysicalIndexScan::with_prefix(
+            table_scan.table_ref.clone(),
+            index_name.clone(),
+            table_scan.table_schema.clone(),
+            prefix,
+        )))
+    }
+
+    /// Record in the optimizer trace why every index of the table was used or skipped as
+    /// the access path of `predicate`, whether the selectivity was estimated and whether the
+    /// predicate went into `access_path`, the scan the planner built.
+    fn trace_access_paths(
+        &self,
+        table_scan: &TableScan,
+        predicate: &Expr,
+        access_path: &PhysicalPlan,
+    ) {
+        let Some(trace) = self.trace else {
+            return;
+        };
+        let table = table_scan.table_ref.to_string();
+        let statistics = self.catalog.table_statistics(&table_scan.table_ref);
+        match &statistics {
+            Some(stats) => trace.record(
+                TraceDecision::SelectivityEstimate,
+                &table,
+                true,
+                TraceReason::Estimated {
+                    selectivity: stats.selectivity(predicate),
+                },
+            ),
+            None => trace.record(
+                TraceDecision::SelectivityEstimate,
+                &table,
+                false,
+                TraceReason::MissingStatistics {
+                    table: table.clone(),
+                },
+            ),
+        }
+
+        let Ok(catalog_table) = self.catalog.catalog_table(&table_scan.table_ref) else {
+            return;
+        };
+        let mut indexes = catalog_table.indexes.iter().collect::<Vec<_>>();
+        indexes.sort_by(|a, b| a.0.cmp(b.0));
+        let disjuncts = disjunct_comparisons(table_scan, predicate);
+        for (index_name, index) in indexes {
+            let key_schema = &index.key_schema;
+            let columns = key_schema
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect::<Vec<_>>();
+            let leading = columns[0].as_str();
+            let reason = if self.reads_snapshot() {
+                TraceReason::ReadsSnapshot
+            } else if catalog_table.partitioning.is_some() {
+                TraceReason::PartitionedTable
+            } else if matches!(access_path, PhysicalPlan::IndexScan(scan)
+                if &scan.index_name == index_name && !scans_whole_index(scan))
+            {
+                TraceReason::Applied
+            } else if let Some(comparisons) = disjuncts.iter().find(|comparisons| {
+                !comparisons
+                    .iter()
+                    .flatten()
+                    .any(|(name, op, _)| *name == leading && is_range_op(*op))
+            }) {
+                if disjuncts.len() > 1 {
+                    TraceReason::DisjunctNotCompared {
+                        leading: leading.to_string(),
+                    }
+                } else {
+                    let mut compared = comparisons
+                        .iter()
+                        .flatten()
+                        .map(|(name, _, _)| name.to_string())
+                        .collect::<Vec<_>>();
+                    compared.sort();
+                    compared.dedup();
+                    TraceReason::LeadingColumnNotCompared {
+                        leading: leading.to_string(),
+                        compared,
+                    }
+                }
+            } else if columns.len() > 1 {
+                TraceReason::CompositeKey {
+                    columns: columns.clone(),
+                }
+            } else if let Some((key_type, value)) = key_schema
+                .column_with_index(0)
+                .ok()
+                .map(|column| column.data_type)
+                .and_then(|key_type| {
+                    let value = disjuncts
+                        .iter()
+                        .flatten()
+                        .flatten()
+                        .filter(|(name, op, _)| *name == leading && is_range_op(*op))
+                        .map(|(_, _, value)| *value)
+                        .find(|value| {
+                            value.is_null()
+                                || value
+                                    .cast_to(&key_type)
+                                    .ok()
+                                    .and_then(|key| key.cast_to(&value.data_type()).ok())
+                                    != Some((*value).clone())
+                        })?;
+                    Some((key_type, value))
+                })
+            {
+                TraceReason::TypeMismatch {
+                    column: leading.to_string(),
+                    key_type: key_type.to_string(),
+                    value: value.to_string(),
+                }
+            } else {
+                let max_selectivity = if catalog_table.clustered_index.as_ref() == Some(index_name)
+                {
+                    CLUSTERED_INDEX_SCAN_MAX_SELECTIVITY
+                } else {
+                    INDEX_SCAN_MAX_SELECTIVITY
+                };
+                match statistics
+                    .as_ref()
+                    .map(|stats| stats.selectivity(predicate))
+                {
+                    Some(estimated) if estimated > max_selectivity => TraceReason::TooUnselective {
+                        estimated,
+                        max: max_selectivity,
+                    },
+                    _ => TraceReason::OtherChosen {
+                        chosen: describe_access_path(access_path),
+                    },
+                }
+            };
+            trace.record(
+                TraceDecision::IndexSelection,
+                format!("{index_name} ({}) on {table}", columns.join(", ")),
+                reason == TraceReason::Applied,
+                reason,
+            );
+        }
+
+        // only scans reading fewer rows than the table have the predicate applied within
+        let pushed = match access_path {
+            PhysicalPlan::IndexScan(scan) => !scans_whole_index(scan),
+            PhysicalPlan::ParallelSeqScan(_) | PhysicalPlan::Append(_) => true,
+            _ => false,
+        };
+        let reason = if pushed {
+            TraceReason::IntoScan {
+                access_path: describe_access_path(access_path),
+            }
+        } else {
+            TraceReason::NoAccessPath {
+                access_path: describe_access_path(access_path),
+            }
+        };
+        trace.record(
+            TraceDecision::PredicatePushdown,
+            predicate.to_string(),
+            pushed,
+            reason,
+        );
+    }
+
+    // Indexes on one column of the table and their key schemas, by index name
+    fn single_column_indexes(&self, table_ref: &TableReference) -> Vec<(String, SchemaRef)> {
+        let Ok(catalog_table) = self.catalog.catalog_table(table_ref) else {
+            return vec![];
+        };
+        let mut indexes = catalog_table
+            .indexes
+            .iter()
+            .filter(|(_, index)| index.key_schema.column_count() == 1)
+            .map(|(index_name, index)| (index_name.clone(), index.key_schema.clone()))
+            .collect::<Vec<_>>();
+        indexes.sort_by(|a, b| a.0.cmp(&b.0));
+        indexes
+    }
+
+    /// Scan of the partitions of a partitioned table, leaving out the partitions whose range
+    /// provably holds no key `predicate` keeps. None if the table is not partitioned.
+    fn build_partition_scan(
+        &self,
+        table_scan: &TableScan,
+        predicate: Option<&Expr>,
+    ) -> Option<PhysicalPlan> {
+        let catalog_table = self.catalog.catalog_table(&table_scan.table_ref).ok()?;
+        let Some(Partitioning::Range { column }) = &catalog_table.partitioning else {
+            return None;
+        };
+        let table_schema = &catalog_table.table.schema;
+        let key_schema = Arc::new(
+            table_schema
+                .project(&[table_schema.index_of(None, column).ok()?])
+                .ok()?,
+        );
+        let key_type = key_schema.column_with_index(0).ok()?.data_type;
+
+        // comparisons of other columns, or not translating to a key, only keep fewer rows
+        let mut start_bound = Bound::Unbounded;
+        let mut end_bound = Bound::Unbounded;
+        let comparisons = predicate.map_or(vec![], |predicate| {
+            column_comparisons(table_scan, predicate)
+        });
+        for (name, op, value) in comparisons.into_iter().flatten() {
+            if name != column.as_str() || value.is_null() {
+                continue;
+            }
+            let Some(key) = value
+                .cast_to(&key_type)
+                .ok()
+                .filter(|key| key.cast_to(&value.data_type()).ok().as_ref() == Some(value))
+            else {
+                continue;
+            };
+            // no integer key lies between a key and its successor, where a partition may start
+            let successor =
+                integer_successor(&key).map(|key| Tuple::new(key_schema.clone(), vec![key]));
+            let key = Tuple::new(key_schema.clone(), vec![key]);
+            match op {
+                BinaryOp::Gt => match successor {
+                    Some(successor) => {
+                        tighten_bound(&mut start_bound, Bound::Included(successor), true)
+                    }
+                    None => tighten_bound(&mut start_bound, Bound::Excluded(key), true),
+                },
+                BinaryOp::GtEq => tighten_bound(&mut start_bound, Bound::Included(key), true),
+                BinaryOp::Lt => tighten_bound(&mut end_bound, Bound::Excluded(key), false),
+                BinaryOp::LtEq => tighten_bound(&mut end_bound, Bound::Included(key), false),
+                BinaryOp::Eq => {
+                    tighten_bound(&mut start_bound, Bound::Included(key.clone()), true);
+                    tighten_bound(&mut end_bound, Bound::Included(key), false);
+                }
+                _ => {}
+            }
+        }
+
+        let inputs = self
+            .catalog
+            .partitions(&table_scan.table_ref)
+            .into_iter()
+            .filter(|(_, partition)| {
+                let Some(Partitioning::Partition { from, to, .. }) = &partition.partitioning else {
+                    return false;
+                };
+                let from = Tuple::new(key_schema.clone(), vec![from.clone()]);
+                let to = Tuple::new(key_schema.clone(), vec![to.clone()]);
+                // incomparable keys keep the partition
+                let ends_before = match &end_bound {
+                    Bound::Included(end) => end < &from,
+                    Bound::Excluded(end) => end <= &from,
+                    Bound::Unbounded => false,
+                };
+                let starts_after = match &start_bound {
+                    Bound::Included(start) | Bound::Excluded(start) => start >= &to,
+                    Bound::Unbounded => false,
+                };
+                !ends_before && !starts_after
+            })
+            .map(|(partition_ref, _)| {
+                let selectivity = predicate.and_then(|predicate| {
+                    self.catalog
+                        .table_statistics(&partition_ref)
+                        .map(|stats| stats.selectivity(predicate))
+                });
+                let partition_scan = TableScan {
+                    table_ref: partition_ref,
+                    table_schema: table_scan.table_schema.clone(),
+                    filters: vec![],
+                    limit: None,
+                };
+                Arc::new(self.build_table_scan(&partition_scan, selectivity))
+            })
+            .collect();
+        Some(PhysicalPlan::Append(PhysicalAppend::new(
+            inputs,
+            table_scan.table_schema.clone(),
+        )))
+    }
+
+    // `selectivity` is the estimated fraction of rows the scan has to produce
+    // Sequential scan read by several threads in place of `plan`, when the statement asks
+    // for parallel scans and `plan` scans a table of enough pages. Rows come out in no
+    // particular order, scans of an ordering the plan relies on are built by
+    // `build_ordered_plan` instead. Ttl tables are scanned serially, the workers do not
+    // tell expired rows apart.
+    fn build_parallel_scan(
+        &self,
+        plan: &PhysicalPlan,
+        predicate: Option<&Expr>,
+    ) -> Option<PhysicalPlan> {
+        let PhysicalPlan::SeqScan(PhysicalSeqScan {
+            table,
+            table_schema,
+            ..
+        }) = plan
+        else {
+            return None;
+        };
+        if self.options.parallel_scan_workers < 2 || self.reads_snapshot() {
+            return None;
+        }
+        let catalog_table = self.catalog.catalog_table(table).ok()?;
+        if catalog_table.ttl_column.is_some() {
+            return None;
+        }
+        let (pages, _, _) = catalog_table.table.count_pages().ok()?;
+        if pages < self.options.parallel_scan_min_pages {
+            return None;
+        }
+        Some(PhysicalPlan::ParallelSeqScan(PhysicalParallelSeqScan::new(
+            table.clone(),
+            table_schema.clone(),
+            predicate.cloned(),
+            self.options.parallel_scan_workers,
+        )))
+    }
+
+    fn build_table_scan(&self, table_scan: &TableScan, selectivity: Option<f64>) -> PhysicalPlan {
+        let TableScan {
+            table_ref,
+            table_schema,
+            filters: _,
+            limit: _,
+        } = table_scan;
+        // TODO fix testing
+        if let Some(catalog_table) = self
+            .catalog
+            .schemas
+            .get(table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME))
+            .unwrap()
+            .tables
+            .get(table_ref.table())
+        {
+            let index_is_cheaper =
+                selectivity.is_none_or(|selectivity| selectivity <= INDEX_SCAN_MAX_SELECTIVITY);
+            if !catalog_table.indexes.is_empty() && index_is_cheaper && !self.reads_snapshot() {
+                PhysicalPlan::IndexScan(PhysicalIndexScan::new(
+                    table_ref.clone(),
+                    catalog_table.indexes.keys().next().unwrap().clone(),
+                    table_schema.clone(),
+                    ..,
+                ))
+            } else {
+                PhysicalPlan::SeqScan(PhysicalSeqScan::new(
+                    table_ref.clone(),
+                    table_schema.clone(),
+                ))
+            }
+        } else {
+            PhysicalPlan::SeqScan(PhysicalSeqScan::new(
+                table_ref.clone(),
+                table_schema.clone(),
+            ))
+        }
+    }
+}
+
+// `column op constant`, by column name
+type ColumnComparison<'e> = (&'e str, BinaryOp, &'e ScalarValue);
+
+// Comparisons of every disjunct of `predicate`, an OR of conjunctions, see `column_comparisons`
+fn disjunct_comparisons<'e>(
+    table_scan: &TableScan,
+    predicate: &'e Expr,
+) -> Vec<Vec<Option<ColumnComparison<'e>>>> {
+    let mut disjuncts = vec![];
+    let mut pending = vec![predicate];
+    while let Some(expr) = pending.pop() {
+        match expr {
+            Expr::Binary(BinaryExpr {
+                left,
+                op: BinaryOp::Or,
+                right,
+            }) => {
+                pending.push(right);
+                pending.push(left);
+            }
+            expr => disjuncts.push(column_comparisons(table_scan, expr)),
+        }
+    }
+    disjuncts
+}
+
+fn scans_whole_index(scan: &PhysicalIndexScan) -> bool {
+    matches!(
+        scan.ranges.as_slice(),
+        [(Bound::Unbounded, Bound::Unbounded)]
+    )
+}
+
+// How an optimizer trace names the scan of a filtered table
+fn describe_access_path(access_path: &PhysicalPlan) -> String {
+    match access_path {
+        PhysicalPlan::IndexScan(scan) if scans_whole_index(scan) => {
+            format!("full scan of index {}", scan.index_name)
+        }
+        PhysicalPlan::IndexScan(scan) => format!("range scan of index {}", scan.index_name),
+        PhysicalPlan::ParallelSeqScan(_) => "parallel sequential scan".to_string(),
+        PhysicalPlan::Append(_) => "scan of the pruned partitions".to_string(),
+        _ => "sequential scan".to_string(),
+    }
+}
+
+// Comparisons `column op constant` making up the conjunction `predicate`, on columns of the
+// scanned table, None for a conjunct that is anything else
+fn column_comparisons<'e>(
+    table_scan: &TableScan,
+    predicate: &'e Expr,
+) -> Vec<Option<ColumnComparison<'e>>> {
+    let mut comparisons = vec![];
+    let mut pending = vec![predicate];
+    while let Some(expr) = pending.pop() {
+        let Expr::Binary(BinaryExpr { left, op, right }) = expr else {
+            comparisons.push(None);
+            continue;
+        };
+        if *op == BinaryOp::And {
+            pending.push(left);
+            pending.push(right);
+            continue;
+        }
+        let comparison = match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(literal)) => Some((column, *op, &literal.value)),
+            (Expr::Literal(literal), Expr::Column(column)) => {
+                op.flip_comparison().map(|op| (column, op, &literal.value))
+            }
+            _ => None,
+        }
+        .filter(|(column, _, _)| {
+            column
+                .relation
+                .as_ref()
+                .is_none_or(|rel| rel.resolved_eq(&table_scan.table_ref))
+        })
+        .map(|(column, op, value)| (column.name.as_str(), op, value));
+        comparisons.push(comparison);
+    }
+    comparisons
+}
+
+// The smallest key above an integer `key`, None for other types and at the top of the range
+fn integer_successor(key: &ScalarValue) -> Option<ScalarValue> {
+    let successor = i64::try_from(numeric::integer_value(key)?.checked_add(1)?).ok()?;
+    numeric::cast_numeric(&successor.into(), &key.data_type()).ok()
+}
+
+fn is_range_op(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Eq | BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::Lt | BinaryOp::LtEq
+    )
+}
+
+// `value` as a key of `key_type`, None for NULL or when the cast changes the value, which
+// would move a bound, e.g. `a < 1.5` on an int column
+fn exact_key(value: &ScalarValue, key_type: &DataType) -> Option<ScalarValue> {
+    if value.is_null() {
+        return None;
+    }
+    let key = value.cast_to(key_type).ok()?;
+    (key.cast_to(&value.data_type()).ok()? == *value).then_some(key)
+}
+
+// Range of the single column `key_schema` holding exactly the keys meeting all of the
+// `comparisons` of the key column, None for a comparison not translating to a bound
+fn key_range(
+    key_schema: &SchemaRef,
+    comparisons: &[(&str, BinaryOp, &ScalarValue)],
+) -> Option<KeyRange> {
+    let key_type = key_schema.column_with_index(0).ok()?.data_type;
+    let mut start_bound = Bound::Unbounded;
+    let mut end_bound = Bound::Unbounded;
+    for (_, op, value) in comparisons {
+        let key = Tuple::new(key_schema.clone(), vec![exact_key(value, &key_type)?]);
+        match op {
+            BinaryOp::Gt => tighten_bound(&mut start_bound, Bound::Excluded(key), true),
+            BinaryOp::GtEq => tighten_bound(&mut start_bound, Bound::Included(key), true),
+            BinaryOp::Lt => tighten_bound(&mut end_bound, Bound::Excluded(key), false),
+            BinaryOp::LtEq => tighten_bound(&mut end_bound, Bound::Included(key), false),
+            BinaryOp::Eq => {
+                tighten_bound(&mut start_bound, Bound::Included(key.clone()), true);
+                tighten_bound(&mut end_bound, Bound::Included(key), false);
+            }
+            _ => return None,
+        }
+    }
+    Some((start_bound, end_bound))
+}
+
+// Sort `ranges` by their start and merge the overlapping or touching ones, empty ranges
+// are dropped. None if some keys cannot be compared.
+fn coalesce_key_ranges(ranges: Vec<KeyRange>) -> Option<Vec<KeyRange>> {
+    use std::cmp::Ordering;
+
+    fn key(bound: &Bound<Tuple>) -> Option<&Tuple> {
+        match bound {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key),
+            Bound::Unbounded => None,
+        }
+    }
+    // keys in order, on equal keys `included_first` orders an included bound first
+    fn cmp_bounded(a: &Bound<Tuple>, b: &Bound<Tuple>, included_first: bool) -> Ordering {
+        let order = key(a).partial_cmp(&key(b)).unwrap();
+        let tie = match (a, b) {
+            (Bound::Included(_), Bound::Excluded(_)) => Ordering::Less,
+            (Bound::Excluded(_), Bound::Included(_)) => Ordering::Greater,
+            _ => Ordering::Equal,
+        };
+        order.then(if included_first { tie } else { tie.reverse() })
+    }
+    // starts by the first key they admit, an unbounded start first
+    fn cmp_starts(a: &Bound<Tuple>, b: &Bound<Tuple>) -> Ordering {
+        match (a, b) {
+            (Bound::Unbounded, Bound::Unbounded) => Ordering::Equal,
+            (Bound::Unbounded, _) => Ordering::Less,
+            (_, Bound::Unbounded) => Ordering::Greater,
+            _ => cmp_bounded(a, b, true),
+        }
+    }
+    // ends by the last key they admit, an unbounded end last
+    fn cmp_ends(a: &Bound<Tuple>, b: &Bound<Tuple>) -> Ordering {
+        match (a, b) {
+            (Bound::Unbounded, Bound::Unbounded) => Ordering::Equal,
+            (Bound::Unbounded, _) => Ordering::Greater,
+            (_, Bound::Unbounded) => Ordering::Less,
+            _ => cmp_bounded(a, b, false),
+        }
+    }
+
+    // every key compares, the bounds can be ordered
+    let keys = ranges
+        .iter()
+        .flat_map(|(start, end)| key(start).into_iter().chain(key(end)))
+        .collect::<Vec<_>>();
+    if keys
+        .iter()
+        .any(|key| keys[0].partial_cmp(key).is_none() || key.partial_cmp(key).is_none())
+    {
+        return None;
+    }
+
+    let mut ranges = ranges
+        .into_iter()
+        .filter(|(start, end)| match (key(start), key(end)) {
+            // a range ending before its start holds no key
+            (Some(start_key), Some(end_key)) => match start_key.partial_cmp(end_key).unwrap() {
+                Ordering::Less => true,
+                Ordering::Equal => {
+                    matches!((start, end), (Bound::Included(_), Bound::Included(_)))
+                }
+                Ordering::Greater => false,
+            },
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by(|a, b| cmp_starts(&a.0, &b.0));
+
+    let mut coalesced: Vec<KeyRange> = vec![];
+    for (start, end) in ranges {
+        if let Some(last) = coalesced.last_mut() {
+            // no key lies between the end of the last range and the start of this one
+            let touches = match (key(&last.1), key(&start)) {
+                (Some(end_key), Some(start_key)) => match start_key.partial_cmp(end_key).unwrap() {
+                    Ordering::Less => true,
+                    Ordering::Equal => {
+                        !matches!((&last.1, &start), (Bound::Excluded(_), Bound::Excluded(_)))
+                    }
+                    Ordering::Greater => false,
+                },
+                _ => true,
+            };
+            if touches {
+                if cmp_ends(&end, &last.1) == Ordering::Greater {
+                    last.1 = end;
+                }
+                continue;
+            }
+        }
+        coalesced.push((start, end));
+    }
+    Some(coalesced)
+}
+
+// Keep the narrower of `bound` and `new`, start bounds narrow upward and end bounds downward
+fn tighten_bound(bound: &mut Bound<Tuple>, new: Bound<Tuple>, is_start: bool) {
+    let narrower = match (&*bound, &new) {
+        (Bound::Unbounded, _) => true,
+        (_, Bound::Unbounded) => false,
+        (
+            Bound::Included(old_key) | Bound::Excluded(old_key),
+            Bound::Included(new_key) | Bound::Excluded(new_key),
+        ) => match new_key.partial_cmp(old_key) {
+            Some(std::cmp::Ordering::Equal) => matches!(new, Bound::Excluded(_)),
+            Some(order) => (order == std::cmp::Ordering::Greater) == is_start,
+            None => false,
+        },
+    };
+    if narrower {
+        *bound = new;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ScalarValue;
+    use crate::config::ExecutionOptions;
+    use crate::execution::physical_plan::{PhysicalIndexScan, PhysicalPlan, PhysicalSeqScan};
+    use crate::expression::{BinaryExpr, BinaryOp, ColumnExpr, Expr};
+    use crate::optimizer::LogicalOptimizer;
+    use crate::planner::logical_plan::{build_join_schema, Join, JoinType, LogicalPlan};
+    use crate::planner::PhysicalPlanner;
+    use crate::{BustubxError, Database, DatabaseOptions};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    fn has_sort(db: &mut Database, sql: &str) -> bool {
+        fn visit(plan: &PhysicalPlan) -> bool {
+            matches!(plan, PhysicalPlan::Sort(_)) || plan.inputs().into_iter().any(visit)
+        }
+        let logical_plan = db.create_logical_plan(sql).unwrap();
+        let logical_plan = LogicalOptimizer::new().optimize(&logical_plan).unwrap();
+        let physical_plan = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(logical_plan)
+        .unwrap();
+        visit(&physical_plan)
+    }
+
+    // tables the scans of the plan read, in plan order
+    fn scanned_tables(db: &mut Database, sql: &str) -> Vec<String> {
+        fn visit(plan: &PhysicalPlan, tables: &mut Vec<String>) {
+            match plan {
+                PhysicalPlan::SeqScan(PhysicalSeqScan { table, .. }) => {
+                    tables.push(table.table().to_string())
+                }
+                PhysicalPlan::IndexScan(PhysicalIndexScan { table_ref, .. }) => {
+                    tables.push(table_ref.table().to_string())
+                }
+                _ => {}
+            }
+            for input in plan.inputs() {
+                visit(input, tables);
+            }
+        }
+        let logical_plan = db.create_logical_plan(sql).unwrap();
+        let logical_plan = LogicalOptimizer::new().optimize(&logical_plan).unwrap();
+        let physical_plan = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(logical_plan)
+        .unwrap();
+        let mut tables = vec![];
+        visit(&physical_plan, &mut tables);
+        tables
+    }
+
+    // the index scan of the plan as displayed
+    fn index_scan(db: &mut Database, sql: &str) -> Option<String> {
+        fn visit(plan: &PhysicalPlan) -> Option<String> {
+            match plan {
+                PhysicalPlan::IndexScan(index_scan) => Some(index_scan.to_string()),
+                plan => plan.inputs().into_iter().find_map(visit),
+            }
+        }
+        let logical_plan = db.create_logical_plan(sql).unwrap();
+        let logical_plan = LogicalOptimizer::new().optimize(&logical_plan).unwrap();
+        let physical_plan = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(logical_plan)
+        .unwrap();
+        visit(&physical_plan)
+    }
+
+    fn ints(db: &mut Database, sql: &str) -> Vec<Vec<i32>> {
+        db.run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| {
+                tuple
+                    .data
+                    .iter()
+                    .map(|v| match v {
+                        ScalarValue::Int32(Some(v)) => *v,
+                        v => panic!("unexpected value {v}"),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_order_by_index_prefix() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int, c int)").unwrap();
+        db.run("create index idx_ab on t1 (a, b)").unwrap();
+        // (a, b) pairs are unique and inserted out of order
+        let rows = (0..1000)
+            .map(|i| format!("({}, {}, {})", (i * 7) % 100, i / 100, i))
+            .collect::<Vec<_>>();
+        for chunk in rows.chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+
+        let mut expected = ints(&mut db, "select a, b from t1");
+        expected.sort();
+        assert!(!has_sort(&mut db, "select a, b from t1 order by a, b"));
+        assert_eq!(ints(&mut db, "select a, b from t1 order by a, b"), expected);
+        assert!(!has_sort(
+            &mut db,
+            "select c, a from t1 where c > 10 order by a"
+        ));
+
+        // descending on every column reads the index backward, NULLs come last either way
+        expected.reverse();
+        assert!(!has_sort(
+            &mut db,
+            "select a, b from t1 order by a desc, b desc"
+        ));
+        assert_eq!(
+            ints(&mut db, "select a, b from t1 order by a desc, b desc"),
+            expected
+        );
+        assert!(!has_sort(&mut db, "select a from t1 order by a desc"));
+        assert_eq!(
+            ints(&mut db, "select a from t1 order by a desc"),
+            expected.iter().map(|row| vec![row[0]]).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            index_scan(&mut db, "select a from t1 order by a desc").as_deref(),
+            Some("IndexScan: idx_ab desc")
+        );
+        assert!(has_sort(
+            &mut db,
+            "select a from t1 order by a desc nulls first"
+        ));
+        assert!(has_sort(&mut db, "select a, b from t1 order by a, b desc"));
+        assert!(has_sort(&mut db, "select a, b from t1 order by a desc, b"));
+        assert!(has_sort(&mut db, "select a, b from t1 order by b"));
+
+        // top-N stops after the first few leaf pages instead of reading the whole table
+        assert!(!has_sort(
+            &mut db,
+            "select a, b from t1 order by a, b limit 5"
+        ));
+        let before = db.buffer_pool.stats();
+        let rows = ints(&mut db, "select a, b from t1 order by a, b limit 5");
+        let fetches = db.buffer_pool.stats().fetches() - before.fetches();
+        assert_eq!(
+            rows,
+            vec![vec![0, 0], vec![0, 1], vec![0, 2], vec![0, 3], vec![0, 4]]
+        );
+        assert!(fetches < 20, "fetched {fetches} pages");
+
+        let before = db.buffer_pool.stats();
+        let rows = ints(
+            &mut db,
+            "select a, b from t1 order by a desc, b desc limit 5",
+        );
+        let fetches = db.buffer_pool.stats().fetches() - before.fetches();
+        assert_eq!(
+            rows,
+            vec![
+                vec![99, 9],
+                vec![99, 8],
+                vec![99, 7],
+                vec![99, 6],
+                vec![99, 5]
+            ]
+        );
+        assert!(fetches < 20, "fetched {fetches} pages");
+    }
+    #[test]
+    pub fn test_index_prefix_scan() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int, c int)").unwrap();
+        db.run("create index idx_ab on t1 (a, b)").unwrap();
+        let rows = (0..1000)
+            .map(|i| format!("({}, {}, {})", i % 100, i / 100, i))
+            .collect::<Vec<_>>();
+        for chunk in rows.chunks(200) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+
+        // equality on the leading column reads only the entries starting with it
+        assert_eq!(
+            index_scan(&mut db, "select b from t1 where a = 5").as_deref(),
+            Some("IndexScan: idx_ab prefix 5")
+        );
+        let mut rows = ints(&mut db, "select b from t1 where a = 5");
+        rows.sort();
+        assert_eq!(rows, (0..10).map(|b| vec![b]).collect::<Vec<_>>());
+        let before = db.buffer_pool.stats();
+        ints(&mut db, "select b from t1 where a = 5");
+        let fetches = db.buffer_pool.stats().fetches() - before.fetches();
+        let before = db.buffer_pool.stats();
+        ints(&mut db, "select b from t1 where c = 5");
+        let scan_fetches = db.buffer_pool.stats().fetches() - before.fetches();
+        assert!(
+            fetches * 2 < scan_fetches,
+            "fetched {fetches} pages, {scan_fetches} for a table scan"
+        );
+
+        // other conjuncts are left to the filter
+        assert_eq!(
+            index_scan(&mut db, "select c from t1 where a = 5 and c > 500").as_deref(),
+            Some("IndexScan: idx_ab prefix 5")
+        );
+        let mut rows = ints(&mut db, "select c from t1 where a = 5 and c > 500");
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![vec![505], vec![605], vec![705], vec![805], vec![905]]
+        );
+        assert_eq!(
+            index_scan(&mut db, "select c from t1 where b = 3 and a = 7").as_deref(),
+            Some("IndexScan: idx_ab prefix (7, 3)")
+        );
+        assert_eq!(
+            ints(&mut db, "select c from t1 where b = 3 and a = 7"),
+            vec![vec![307]]
+        );
+
+        // without the leading column every entry is read
+        assert_eq!(
+            index_scan(&mut db, "select c from t1 where b = 3").as_deref(),
+            Some("IndexScan: idx_ab")
+        );
+        assert!(ints(&mut db, "select c from t1 where a = 5.5").is_empty());
+    }
+
+    #[test]
+    pub fn test_nocase_index_probe() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (id int, name varchar(20) collate nocase, tag varchar(20))")
+            .unwrap();
+        db.run("create index idx_name on t1 (name)").unwrap();
+        let names = ["alice", "ALICE", "Bob", "aLiCe", "carol", "bob", "Alicia"];
+        let rows = (0..600)
+            .map(|i| match i % 8 {
+                7 => format!("({i}, null, 'x')"),
+                n => format!("({i}, '{}', '{}')", names[n], names[n]),
+            })
+            .collect::<Vec<_>>();
+        for chunk in rows.chunks(100) {
+            db.run(&format!("insert into t1 values {}", chunk.join(", ")))
+                .unwrap();
+        }
+
+        let sql = "select id from t1 where name = 'Alice'";
+        assert!(index_scan(&mut db, sql)
+            .unwrap()
+            .starts_with("IndexScan: idx_name"));
+        assert_eq!(ints(&mut db, sql).len(), 3 * 75);
+        assert_eq!(
+            ints(&mut db, "select id from t1 where name = 'BOB' and id < 16"),
+            vec![vec![2], vec![5], vec![10], vec![13]]
+        );
+        // the other column compares bytewise
+        assert_eq!(
+            ints(&mut db, "select id from t1 where tag = 'Alice'"),
+            Vec::<Vec<i32>>::new()
+        );
+
+        // the index is ordered by the folded names, and so is the sort
+        let names_in = |db: &mut Database, sql: &str| {
+            db.run(sql)
+                .unwrap()
+                .into_iter()
+                .map(|tuple| match &tuple.data[0] {
+                    ScalarValue::Varchar(name) => name.clone().map(|n| n.to_lowercase()),
+                    v => panic!("unexpected value {v}"),
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut expected = names_in(&mut db, "select name from t1");
+        expected.sort();
+        assert!(!has_sort(&mut db, "select name from t1 order by name"));
+        assert_eq!(
+            names_in(&mut db, "select name from t1 order by name"),
+            expected
+        );
+        // NULLs last needs a sort, the index has them first
+        assert!(has_sort(
+            &mut db,
+            "select name from t1 order by name nulls last"
+        ));
+        expected.rotate_left(75);
+        assert_eq!(
+            names_in(&mut db, "select name from t1 order by name nulls last"),
+            expected
+        );
+        assert!(db.run("check table t1 with indexes").unwrap().is_empty());
+
+        // columns of different collations do not compare
+        db.run("create table t2 (name varchar(20))").unwrap();
+        for sql in [
+            "select id from t1 where name = tag",
+            "select t1.id from t1 join t2 on t1.name = t2.name",
+            "delete from t1 where tag < name",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(crate::BustubxError::Plan(_))),
+                "{sql}"
+            );
+        }
+        assert!(db.run("create table t3 (a int collate nocase)").is_err());
+
+        // the collation is part of the stored schema
+        db.close().unwrap();
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(
+            ints(&mut db, "select id from t1 where name = 'BOB'").len(),
+            2 * 75
+        );
+        assert!(db.run("check table t1 with indexes").unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_or_of_ranges_index_scan() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        db.run("create table t2 (a int, b int)").unwrap();
+        let rows = (0..200)
+            .map(|i| format!("({}, {})", (i * 37) % 100, i % 7))
+            .chain(["(null, 1)".to_string()])
+            .collect::<Vec<_>>()
+            .join(", ");
+        db.run(&format!("insert into t1 values {rows}")).unwrap();
+        db.run(&format!("insert into t2 values {rows}")).unwrap();
+
+        let cases = [
+            (
+                "a = 3 or a = 7 or a = 20",
+                Some("IndexScan: idx_a ranges [3, 3], [7, 7], [20, 20]"),
+            ),
+            // overlapping and touching ranges are merged, equal keys kept once
+            (
+                "a >= 5 and a <= 20 or a > 15 and a < 30 or a = 30 or 50 = a or a = 50",
+                Some("IndexScan: idx_a ranges [5, 30], [50, 50]"),
+            ),
+            (
+                "a = 40 or a >= 40 and a < 42 and b = 1 or a < 3",
+                Some("IndexScan: idx_a ranges (-inf, 3), [40, 42)"),
+            ),
+            (
+                "a > 98 or a < 1 or a > 10 and a < 5",
+                Some("IndexScan: idx_a ranges (-inf, 1), (98, +inf)"),
+            ),
+            ("a = 3", Some("IndexScan: idx_a ranges [3, 3]")),
+            (
+                "a > 90 and b = 1",
+                Some("IndexScan: idx_a ranges (90, +inf)"),
+            ),
+            // a disjunct not on the key column, the whole index is read
+            ("a = 3 or b = 5", Some("IndexScan: idx_a")),
+            ("a = 3 or a < 1.5", Some("IndexScan: idx_a")),
+        ];
+        for (predicate, plan) in cases {
+            let sql = format!("select a, b from t1 where {predicate}");
+            assert_eq!(index_scan(&mut db, &sql).as_deref(), plan, "{predicate}");
+
+            let baseline_sql = format!("select a, b from t2 where {predicate}");
+            let mut rows = ints(&mut db, &sql);
+            let mut expected = ints(&mut db, &baseline_sql);
+            assert!(!expected.is_empty(), "{predicate}");
+            rows.sort();
+            expected.sort();
+            assert_eq!(rows, expected, "{predicate}");
+        }
+    }
+
+    #[test]
+    pub fn test_partition_pruning() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int) partition by range (a)")
+            .unwrap();
+        for (i, (from, to)) in [(0, 100), (100, 200), (200, 300)].into_iter().enumerate() {
+            db.run(&format!(
+                "create table t1_{i} partition of t1 for values from ({from}) to ({to})"
+            ))
+            .unwrap();
+        }
+        db.run("create index t1_2_b on t1_2 (b)").unwrap();
+        let rows = (0..300)
+            .map(|i| format!("({}, {})", i, i % 7))
+            .collect::<Vec<_>>();
+        db.run(&format!("insert into t1 values {}", rows.join(", ")))
+            .unwrap();
+
+        let all = vec!["t1_0", "t1_1", "t1_2"];
+        assert_eq!(scanned_tables(&mut db, "select a from t1"), all);
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where a >= 110 and a < 120"),
+            vec!["t1_1"]
+        );
+        assert_eq!(
+            ints(
+                &mut db,
+                "select a from t1 where a >= 110 and a < 120 and b = 0"
+            ),
+            vec![vec![112], vec![119]]
+        );
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where 100 > a"),
+            vec!["t1_0"]
+        );
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where a = 200"),
+            vec!["t1_2"]
+        );
+        assert_eq!(
+            ints(&mut db, "select b from t1 where a = 200"),
+            vec![vec![4]]
+        );
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where a > 99 and a <= 200"),
+            vec!["t1_1", "t1_2"]
+        );
+        assert!(scanned_tables(&mut db, "select a from t1 where a >= 300").is_empty());
+        assert!(ints(&mut db, "select a from t1 where a >= 300").is_empty());
+        // not provably outside a range, every partition is scanned
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where a < 50 or b = 1"),
+            all
+        );
+        assert_eq!(scanned_tables(&mut db, "select a from t1 where b = 1"), all);
+        assert_eq!(
+            scanned_tables(&mut db, "select a from t1 where a < 100.5"),
+            all
+        );
+    }
+
+    #[test]
+    pub fn test_semi_join_refuses_non_equality_condition() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("create table t2 (x int)").unwrap();
+        let left = db.create_logical_plan("select a from t1").unwrap();
+        let right = db.create_logical_plan("select x from t2").unwrap();
+        let schema =
+            Arc::new(build_join_schema(left.schema(), right.schema(), JoinType::LeftSemi).unwrap());
+        let column = |name: &str| {
+            Box::new(Expr::Column(ColumnExpr {
+                relation: None,
+                name: name.to_string(),
+            }))
+        };
+        let join = LogicalPlan::Join(Join {
+            left: Arc::new(left),
+            right: Arc::new(right),
+            join_type: JoinType::LeftSemi,
+            condition: Some(Expr::Binary(BinaryExpr {
+                left: column("a"),
+                op: BinaryOp::Gt,
+                right: column("x"),
+            })),
+            schema,
+        });
+        let result = PhysicalPlanner {
+            catalog: &db.catalog,
+            options: &ExecutionOptions::default(),
+            trace: None,
+        }
+        .create_physical_plan(join);
+        assert!(matches!(result, Err(BustubxError::Internal(_))));
     }
 }
@@ -0,0 +1,265 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::catalog::{Catalog, INFORMATION_SCHEMA_NAME};
+use crate::common::TableReference;
+use crate::planner::logical_plan::{Delete, Insert, LogicalPlan, TableScan, Update, Values};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    // statements whose plan was reused
+    pub hits: u64,
+    // statements planned from scratch, stale entries included
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct CachedPlan {
+    plan: LogicalPlan,
+    // version of every referenced table when the plan was made
+    table_versions: Vec<(TableReference, u64)>,
+}
+
+/// Optimized logical plans of recent statements keyed by their normalized text, at most
+/// `capacity` of them with the least recently used evicted first.
+///
+/// An entry is stale once a table it references changed version, see
+/// [`crate::catalog::CatalogTable::version`].
+#[derive(Debug)]
+pub struct PlanCache {
+    capacity: usize,
+    entries: HashMap<String, CachedPlan>,
+    // keys from the least to the most recently used
+    recency: VecDeque<String>,
+    stats: PlanCacheStats,
+}
+
+impl PlanCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            stats: PlanCacheStats::default(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str, catalog: &Catalog) -> Option<LogicalPlan> {
+        let fresh = self.entries.get(key).map(|entry| {
+            entry
+                .table_versions
+                .iter()
+                .all(|(table, version)| catalog.table_version(table).ok() == Some(*version))
+        });
+        match fresh {
+            Some(true) => {
+                self.touch(key);
+                self.stats.hits += 1;
+                Some(self.entries[key].plan.clone())
+            }
+            Some(false) => {
+                self.remove(key);
+                self.stats.misses += 1;
+                None
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Plans reading information_schema are not kept, some of its relations are
+    /// computed while planning.
+    pub fn insert(&mut self, key: String, plan: &LogicalPlan, catalog: &Catalog) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut tables = vec![];
+        collect_tables(plan, &mut tables);
+        if tables
+            .iter()
+            .any(|table| table.schema() == Some(INFORMATION_SCHEMA_NAME))
+        {
+            return;
+        }
+        let Ok(table_versions) = tables
+            .into_iter()
+            .map(|table| {
+                catalog
+                    .table_version(&table)
+                    .map(|version| (table, version))
+            })
+            .collect()
+        else {
+            return;
+        };
+
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let Some(oldest) = self.recency.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+        self.recency.push_back(key.clone());
+        self.entries.insert(
+            key,
+            CachedPlan {
+                plan: plan.clone(),
+                table_versions,
+            },
+        );
+    }
+
+    /// Evicts the least recently used plans above the new capacity.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            let Some(oldest) = self.recency.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn stats(&self) -> PlanCacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = PlanCacheStats::default();
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.recency.iter().position(|k| k == key) {
+            let key = self.recency.remove(pos).unwrap();
+            self.recency.push_back(key);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if self.entries.remove(key).is_some() {
+            self.recency.retain(|k| k != key);
+        }
+    }
+}
+
+fn collect_tables(plan: &LogicalPlan, tables: &mut Vec<TableReference>) {
+    match plan {
+        LogicalPlan::TableScan(TableScan { table_ref, .. }) => tables.push(table_ref.clone()),
+        LogicalPlan::Insert(Insert { table, .. })
+        | LogicalPlan::Update(Update { table, .. })
+        | LogicalPlan::Delete(Delete { table, .. }) => tables.push(table.clone()),
+        // relations computed while planning, e.g. information_schema.relation_sizes
+        LogicalPlan::Values(Values { schema, .. }) => tables.extend(
+            schema
+                .columns
+                .iter()
+                .filter_map(|column| column.relation.clone()),
+        ),
+        _ => {}
+    }
+    for input in plan.inputs() {
+        collect_tables(input, tables);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::catalog::{ANALYZE_SAMPLE_SIZE, DEFAULT_HISTOGRAM_BUCKETS};
+    use crate::common::{ScalarValue, TableReference};
+    use crate::{Database, DatabaseOptions, PlanCacheStats};
+
+    fn ints(db: &mut Database, sql: &str) -> Vec<i64> {
+        db.run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| match tuple.data[0] {
+                ScalarValue::Int32(Some(v)) => v as i64,
+                ScalarValue::Int64(Some(v)) => v,
+                ref v => panic!("unexpected value {v}"),
+            })
+            .collect()
+    }
+
+    // (hits, misses) since `before`
+    fn delta(db: &Database, before: PlanCacheStats) -> (u64, u64) {
+        let stats = db.plan_cache_stats();
+        (stats.hits - before.hits, stats.misses - before.misses)
+    }
+
+    #[test]
+    pub fn test_plan_cache() {
+        let mut db =
+            Database::new_temp_with_options(DatabaseOptions::new().plan_cache_capacity(2)).unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20)").unwrap();
+
+        let sql = "select b from t1 where a = 2";
+        let before = db.plan_cache_stats();
+        assert_eq!(ints(&mut db, sql), vec![20]);
+        assert_eq!(ints(&mut db, "SELECT b   FROM t1\n  WHERE a = 2"), vec![20]);
+        assert_eq!(delta(&db, before), (1, 1));
+
+        // a reused plan reads the current rows
+        let count = "select count(*) from t1";
+        assert_eq!(ints(&mut db, count), vec![2]);
+        db.run("insert into t1 values (3, 30)").unwrap();
+        let before = db.plan_cache_stats();
+        assert_eq!(ints(&mut db, count), vec![3]);
+        assert_eq!(delta(&db, before), (1, 0));
+
+        // new indexes and statistics make the plans of the table stale
+        db.run("create index idx_a on t1 (a)").unwrap();
+        let before = db.plan_cache_stats();
+        assert_eq!(ints(&mut db, count), vec![3]);
+        assert_eq!(ints(&mut db, count), vec![3]);
+        assert_eq!(delta(&db, before), (1, 1));
+        db.catalog
+            .analyze_table(
+                &TableReference::bare("t1"),
+                DEFAULT_HISTOGRAM_BUCKETS,
+                ANALYZE_SAMPLE_SIZE,
+            )
+            .unwrap();
+        let before = db.plan_cache_stats();
+        assert_eq!(ints(&mut db, count), vec![3]);
+        assert_eq!(delta(&db, before), (0, 1));
+
+        // least recently used plans are evicted first
+        db.run("discard plans").unwrap();
+        let (q1, q2, q3) = (
+            "select a from t1 where a = 1",
+            "select a from t1 where a = 2",
+            "select a from t1 where a = 3",
+        );
+        let before = db.plan_cache_stats();
+        for sql in [q1, q2, q1, q3] {
+            ints(&mut db, sql);
+        }
+        assert_eq!(delta(&db, before), (1, 3));
+        assert_eq!(db.plan_cache_stats().evictions - before.evictions, 1);
+        let before = db.plan_cache_stats();
+        for sql in [q1, q3, q2] {
+            ints(&mut db, sql);
+        }
+        assert_eq!(delta(&db, before), (2, 1));
+
+        // information_schema relations computed while planning are planned every time
+        let sizes = "select live_tuples from information_schema.relation_sizes \
+                     where table_name = 't1' and relation_kind = 'table'";
+        let before = db.plan_cache_stats();
+        assert_eq!(db.run(sizes).unwrap()[0].data, vec![3u64.into()]);
+        db.run("delete from t1 where a = 3").unwrap();
+        assert_eq!(db.run(sizes).unwrap()[0].data, vec![2u64.into()]);
+        assert_eq!(delta(&db, before).0, 0);
+    }
+}
@@ -0,0 +1,939 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::capture::{CaptureSource, CapturedStatement, StatementTimer};
+use crate::database::{Database, StatementOutcome};
+use crate::execution::{check_not_in_trigger, TransactionWrites};
+use crate::settings::SessionSettings;
+use crate::transaction::{IsolationLevel, TransactionId, TransactionInfo, TransactionManager};
+use crate::{BustubxError, BustubxResult, Tuple};
+
+// wait before the first retry of a transaction, doubled for every further one
+const RETRY_BACKOFF: Duration = Duration::from_millis(1);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Database shared between the threads of a pool, every worker runs its statements through
+/// its own [`Session`].
+///
+/// Threading model: the storage layer (buffer pool, table heaps, indexes, lock manager)
+/// is safe to use from any thread, while planning and executing a statement needs the
+/// database exclusively. Statements of all sessions therefore run one at a time, each in
+/// its own transaction, and a session waits for the statement running in another one.
+/// An UPDATE or DELETE takes the write lock of every row it changes, held until its
+/// transaction ends, and checks the row again on its latest version before changing it.
+/// A row written since the statement read it is skipped if it no longer matches the WHERE
+/// clause, the SET expressions see the latest version, so concurrent increments of a row
+/// are never lost.
+/// Settings changed with the setters of [`Database`] apply to every session, a `SET` only
+/// to the session running it unless the setting has [`crate::SettingScope::Database`].
+///
+/// The only thread local state is the operation name the buffer pool records page writes
+/// under, it belongs to the thread doing the writes, and the table whose trigger the thread
+/// runs, which makes the thread's statements fail instead of waiting for the lock it holds.
+pub struct SharedDatabase {
+    db: Mutex<Database>,
+    // readable while a statement or transaction holds the database
+    txn_manager: Arc<TransactionManager>,
+    // id of the next session, the statements of the database itself are session 0
+    next_session_id: AtomicU64,
+}
+
+impl SharedDatabase {
+    pub fn new(db: Database) -> Arc<Self> {
+        Arc::new(Self {
+            txn_manager: db.txn_manager.clone(),
+            db: Mutex::new(db),
+            next_session_id: AtomicU64::new(1),
+        })
+    }
+
+    pub fn session(self: &Arc<Self>) -> Session {
+        Session {
+            db: self.clone(),
+            settings: SessionSettings::default(),
+            capture: CaptureSource::new(self.next_session_id.fetch_add(1, Ordering::Relaxed)),
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// The database itself, e.g. to change settings, other sessions wait until the guard
+    /// is dropped.
+    pub fn lock(&self) -> BustubxResult<MutexGuard<'_, Database>> {
+        // the statement firing the trigger holds the lock
+        check_not_in_trigger()?;
+        self.db.lock().map_err(|_| {
+            BustubxError::Internal("a session panicked while running a statement".to_string())
+        })
+    }
+
+    /// [`Database::transactions`] without waiting for the running statement, e.g. to find
+    /// the transaction of another session keeping the others waiting.
+    pub fn transactions(&self) -> Vec<TransactionInfo> {
+        self.txn_manager.transactions()
+    }
+
+    /// [`Database::close`] once the running statement finished, the statements of every
+    /// session fail with [`BustubxError::DatabaseClosed`] afterwards.
+    pub fn close(&self) -> BustubxResult<()> {
+        self.lock()?.shutdown()
+    }
+}
+
+/// Connection of one worker to a [`SharedDatabase`]. A session can move to another thread
+/// but is not shared, statements of one worker run in order.
+pub struct Session {
+    db: Arc<SharedDatabase>,
+    // settings of the session changed with SET
+    settings: SessionSettings,
+    // id of the session and the file its statements are captured to
+    pub(crate) capture: CaptureSource,
+    // Send but not Sync
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl Session {
+    pub fn run(&mut self, sql: &str) -> BustubxResult<Vec<Tuple>> {
+        self.db
+            .lock()?
+            .run_captured(sql, Some(&mut self.settings), &mut self.capture)
+    }
+
+    /// Id of the session in capture files, unique among the sessions of the database.
+    pub fn id(&self) -> u64 {
+        self.capture.session_id
+    }
+
+    /// [`Database::execute_script`] with no statement of another session in between.
+    pub fn execute_script(&mut self, sql: &str) -> BustubxResult<Vec<StatementOutcome>> {
+        Ok(self.db.lock()?.execute_script_in_session(
+            sql,
+            Some(&mut self.settings),
+            &mut self.capture,
+        ))
+    }
+
+    /// Run the statements `transaction` runs as one transaction and return its result.
+    ///
+    /// No statement of another session runs before the closure returned. If it returns an
+    /// error, the rows its statements inserted, updated or deleted are restored, DDL is
+    /// not undone.
+    ///
+    /// The rows are restored the same way when the closure succeeded, but a key it inserted
+    /// into a deferrable unique index is held by more than one live row at commit, or a row
+    /// it wrote breaks a foreign key then.
+    ///
+    /// A [retryable](BustubxError::is_retryable) error runs the closure again after a
+    /// growing randomized backoff, up to `transaction_max_retries` times, the error of the
+    /// last attempt is returned then. Other errors are returned right away. The statements
+    /// of sessions never wait for each other's locks, as they run one at a time, such
+    /// conflicts come from table locks held outside of statements: by another thread
+    /// through [`Database::lock_manager`], or by an auto analyze of a table which a
+    /// statement dropping or rewriting it cancels and then waits for up to `lock_timeout`.
+    pub fn run_transaction<T>(
+        &mut self,
+        mut transaction: impl FnMut(&mut TransactionSession<'_>) -> BustubxResult<T>,
+    ) -> BustubxResult<T> {
+        let mut retries = 0;
+        loop {
+            let mut db = self.db.lock()?;
+            let txn_id = db
+                .txn_manager
+                .begin_tracked(IsolationLevel::SnapshotIsolation)
+                .txn_id;
+            let capturing = db.capturing(&self.capture);
+            let mut txn = TransactionSession {
+                db: &mut db,
+                settings: &mut self.settings,
+                txn_id,
+                writes: TransactionWrites {
+                    txn_id,
+                    ..Default::default()
+                },
+                session_id: self.capture.session_id,
+                captured: capturing.then(Vec::new),
+            };
+            // keys left in deferrable unique indexes and foreign keys are checked before the
+            // commit
+            let result = transaction(&mut txn)
+                .and_then(|value| txn.db.check_deferred_keys(&txn.writes).map(|_| value));
+            let writes = std::mem::take(&mut txn.writes);
+            let captured = txn.captured.take();
+            let rolled_back = match result {
+                Ok(_) => {
+                    db.txn_manager.commit_changes(txn_id, &writes.undo_log);
+                    // statements of transactions rolled back left nothing to replay
+                    if let Some(captured) = captured {
+                        db.record_statements(&captured, &mut self.capture);
+                    }
+                    Ok(())
+                }
+                Err(_) => db.rollback_transaction(writes.undo_log, Some(&self.settings)),
+            };
+            db.end_transaction(txn_id);
+            rolled_back?;
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            let max_retries = db
+                .statement_options(Some(&self.settings))
+                .transaction_max_retries;
+            if !error.is_retryable() || retries >= max_retries {
+                return Err(error);
+            }
+            // the other sessions get to run while waiting
+            drop(db);
+            thread::sleep(retry_backoff(retries));
+            retries += 1;
+        }
+    }
+}
+
+/// Statements of a transaction run by [`Session::run_transaction`]. The transaction ends
+/// with the closure, BEGIN, COMMIT, ROLLBACK and SAVEPOINT are refused.
+pub struct TransactionSession<'a> {
+    db: &'a mut Database,
+    settings: &'a mut SessionSettings,
+    txn_id: TransactionId,
+    // changes of the statements run so far and the timing of SET CONSTRAINTS
+    writes: TransactionWrites,
+    session_id: u64,
+    // statements run so far while capturing, written once the transaction committed
+    captured: Option<Vec<CapturedStatement>>,
+}
+
+impl TransactionSession<'_> {
+    /// Fails with [`BustubxError::IdleTransactionTimeout`] once the transaction went
+    /// longer than `idle_transaction_timeout` without running a statement.
+    pub fn run(&mut self, sql: &str) -> BustubxResult<Vec<Tuple>> {
+        let timer = StatementTimer::start();
+        let result = self.db.run_in_transaction(
+            sql,
+            Some(&mut *self.settings),
+            self.txn_id,
+            &mut self.writes,
+        );
+        if let Some(captured) = &mut self.captured {
+            captured.push(timer.finish(self.session_id, Some(self.txn_id), sql, &result));
+        }
+        result
+    }
+
+    /// Id of the transaction, as listed by `SHOW TRANSACTIONS`.
+    pub fn txn_id(&self) -> TransactionId {
+        self.txn_id
+    }
+}
+
+// Between half and all of the backoff of the retry, so sessions that conflicted once do
+// not retry in lockstep
+fn retry_backoff(retry: usize) -> Duration {
+    let backoff = RETRY_BACKOFF
+        .saturating_mul(1 << retry.min(16))
+        .min(MAX_RETRY_BACKOFF);
+    let random = RandomState::new().build_hasher().finish();
+    backoff / 2 + backoff.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::Catalog;
+    use crate::common::{ScalarValue, TableReference};
+    use crate::storage::index::BPlusTreeIndex;
+    use crate::storage::{DiskManager, TableHeap, TableIterator};
+    use crate::transaction::{IsolationLevel, LockManager, TableLockMode, TransactionManager};
+    use crate::{
+        BustubxError, BustubxResult, Database, Session, SharedDatabase, TransactionSession, Tuple,
+    };
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    fn assert_send<T: Send>() {}
+
+    fn count(session: &mut Session, sql: &str) -> i64 {
+        match session.run(sql).unwrap()[0].data[0] {
+            ScalarValue::Int64(Some(count)) => count,
+            ref v => panic!("unexpected count {v}"),
+        }
+    }
+
+    #[test]
+    pub fn test_thread_safety() {
+        assert_send_sync::<Database>();
+        assert_send_sync::<SharedDatabase>();
+        assert_send_sync::<Catalog>();
+        assert_send_sync::<BufferPoolManager>();
+        assert_send_sync::<DiskManager>();
+        assert_send_sync::<TableHeap>();
+        assert_send_sync::<BPlusTreeIndex>();
+        assert_send_sync::<LockManager>();
+        assert_send_sync::<TransactionManager>();
+        assert_send::<Session>();
+    }
+
+    #[test]
+    pub fn test_sessions_on_worker_threads() {
+        const WORKERS: usize = 16;
+        const ROWS: usize = 50;
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        db.session()
+            .run("create table shared (worker int, v int)")
+            .unwrap();
+
+        let handles = (0..WORKERS)
+            .map(|worker| {
+                let mut session = db.session();
+                thread::spawn(move || {
+                    session
+                        .run(&format!("create table private_{worker} (v int)"))
+                        .unwrap();
+                    for v in 0..ROWS {
+                        session
+                            .run(&format!("insert into shared values ({worker}, {v})"))
+                            .unwrap();
+                        session
+                            .run(&format!("insert into private_{worker} values ({v})"))
+                            .unwrap();
+                        if v % 10 == 0 {
+                            // every earlier insert of this worker is visible
+                            assert_eq!(
+                                count(
+                                    &mut session,
+                                    &format!("select count(*) from shared where worker = {worker}")
+                                ),
+                                v as i64 + 1
+                            );
+                        }
+                    }
+                    assert_eq!(
+                        count(
+                            &mut session,
+                            &format!("select count(v) from private_{worker}")
+                        ),
+                        ROWS as i64
+                    );
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut session = db.session();
+        assert_eq!(
+            count(&mut session, "select count(*) from shared"),
+            (WORKERS * ROWS) as i64
+        );
+        for worker in 0..WORKERS {
+            assert_eq!(
+                count(
+                    &mut session,
+                    &format!("select count(*) from private_{worker}")
+                ),
+                ROWS as i64
+            );
+        }
+        assert!(db.lock().unwrap().run("select v from private_0").is_ok());
+    }
+
+    #[test]
+    pub fn test_concurrent_updates_of_one_row() {
+        const WORKERS: usize = 4;
+        const INCREMENTS: usize = 100;
+        const LIMIT: usize = 150;
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session
+            .run("create table counters (id int, v int, capped int)")
+            .unwrap();
+        session
+            .run("insert into counters values (1, 0, 0), (2, 0, 0)")
+            .unwrap();
+
+        let handles = (0..WORKERS)
+            .map(|_| {
+                let mut session = db.session();
+                thread::spawn(move || {
+                    let mut capped_updates = 0;
+                    for _ in 0..INCREMENTS {
+                        session
+                            .run("update counters set v = v + 1 where id = 1")
+                            .unwrap();
+                        // the condition sees the increments of the other workers
+                        let updated = session
+                            .run(&format!(
+                                "update counters set capped = capped + 1 where id = 2 and capped < {LIMIT}"
+                            ))
+                            .unwrap();
+                        capped_updates += updated.len();
+                    }
+                    capped_updates
+                })
+            })
+            .collect::<Vec<_>>();
+        let capped_updates = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum::<usize>();
+
+        assert_eq!(
+            count(
+                &mut session,
+                &format!(
+                    "select count(*) from counters where id = 1 and v = {}",
+                    WORKERS * INCREMENTS
+                )
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &mut session,
+                &format!("select count(*) from counters where id = 2 and capped = {LIMIT}")
+            ),
+            1
+        );
+        assert_eq!(capped_updates, LIMIT);
+    }
+
+    #[test]
+    pub fn test_row_locks_held_until_transaction_ends() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t (id int, v int)").unwrap();
+        session.run("insert into t values (1, 0), (2, 0)").unwrap();
+        let t = TableReference::bare("t");
+        let (lock_manager, rids) = {
+            let db = db.lock().unwrap();
+            let mut iterator = TableIterator::new(db.catalog.table_heap(&t).unwrap(), ..);
+            let mut rids = vec![];
+            while let Some((rid, _)) = iterator.next().unwrap() {
+                rids.push(rid);
+            }
+            (db.lock_manager(), rids)
+        };
+        let holders = || {
+            rids.iter()
+                .map(|rid| lock_manager.row_lock_holder(&t, *rid))
+                .collect::<Vec<_>>()
+        };
+
+        // taken by the rows a statement changes and kept by the statements after it
+        session
+            .run_transaction(|txn| {
+                txn.run("update t set v = v + 1 where id = 1")?;
+                txn.run("select * from t")?;
+                assert_eq!(holders(), vec![Some(txn.txn_id()), None]);
+                txn.run("delete from t where id = 2")?;
+                assert_eq!(holders(), vec![Some(txn.txn_id()); 2]);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(holders(), vec![None, None]);
+        assert!(session
+            .run_transaction(|txn| {
+                txn.run("update t set v = v + 1")?;
+                assert_eq!(holders(), vec![Some(txn.txn_id()), None]);
+                txn.run("select * from missing")
+            })
+            .is_err());
+        assert_eq!(holders(), vec![None, None]);
+
+        // a statement run on its own holds them until it ends
+        session.run("update t set v = v + 1").unwrap();
+        assert_eq!(holders(), vec![None, None]);
+        assert_eq!(count(&mut session, "select count(*) from t where v = 2"), 1);
+    }
+
+    #[test]
+    pub fn test_run_transaction_sees_its_own_writes() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t (id int, v int)").unwrap();
+        session.run("create index t_id on t (id)").unwrap();
+        session.run("insert into t values (1, 0)").unwrap();
+        fn ints(txn: &mut TransactionSession<'_>, sql: &str) -> BustubxResult<Vec<Vec<i64>>> {
+            Ok(txn
+                .run(sql)?
+                .into_iter()
+                .map(|tuple| {
+                    tuple
+                        .data
+                        .iter()
+                        .map(|value| match *value {
+                            ScalarValue::Int32(Some(v)) => v as i64,
+                            ScalarValue::Int64(Some(v)) => v,
+                            ref v => panic!("unexpected value {v}"),
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+
+        let result = session.run_transaction(|txn| {
+            txn.run("insert into t values (2, 5)")?;
+            // by a sequential scan, an index scan and the stored count and index ends
+            assert_eq!(ints(txn, "select * from t")?, vec![vec![1, 0], vec![2, 5]]);
+            assert_eq!(ints(txn, "select v from t where id = 2")?, vec![vec![5]]);
+            assert_eq!(ints(txn, "select count(*) from t")?, vec![vec![2]]);
+            assert_eq!(ints(txn, "select max(id) from t")?, vec![vec![2]]);
+
+            txn.run("update t set v = v + 1")?;
+            txn.run("update t set v = v + 1")?;
+            assert_eq!(ints(txn, "select * from t")?, vec![vec![1, 2], vec![2, 7]]);
+            // a statement does not read the rows it inserts itself
+            txn.run("insert into t select id + 10, v from t")?;
+            assert_eq!(ints(txn, "select count(*) from t")?, vec![vec![4]]);
+            txn.run("delete from t where id = 1")?;
+            assert_eq!(
+                ints(txn, "select id from t where id = 1")?,
+                Vec::<Vec<i64>>::new()
+            );
+            Err::<(), _>(BustubxError::Execution("roll back".to_string()))
+        });
+        assert!(matches!(result, Err(BustubxError::Execution(_))));
+
+        assert_eq!(count(&mut session, "select count(*) from t"), 1);
+        assert_eq!(
+            count(&mut session, "select count(*) from t where id = 2"),
+            0
+        );
+        assert_eq!(count(&mut session, "select count(*) from t where v = 0"), 1);
+    }
+
+    #[test]
+    pub fn test_update_waits_for_the_database_lock() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session
+            .run("create table counters (id int, v int)")
+            .unwrap();
+        session.run("insert into counters values (1, 0)").unwrap();
+
+        // the guard stands in for a statement of another session still running
+        let mut held = db.lock().unwrap();
+        let (done, finished) = mpsc::channel();
+        let mut other = db.session();
+        let handle = thread::spawn(move || {
+            other
+                .run("update counters set v = v + 1 where id = 1")
+                .unwrap();
+            done.send(()).unwrap();
+        });
+        // no row lock guards the row, the update is kept out by the database lock alone
+        assert!(finished.recv_timeout(Duration::from_millis(200)).is_err());
+        held.run("update counters set v = v + 10 where id = 1")
+            .unwrap();
+        drop(held);
+        finished.recv().unwrap();
+        handle.join().unwrap();
+        assert_eq!(
+            count(
+                &mut session,
+                "select count(*) from counters where id = 1 and v = 11"
+            ),
+            1
+        );
+    }
+
+    // Run `sql` while a transaction of another client holds `table` exclusively, it fails
+    // once the lock timeout of the session passed
+    // Another thread keeping statements off `table`, e.g. while it maintains the files of
+    // the table, with the ACCESS EXCLUSIVE lock held by a transaction of its own until
+    // `release` gets a message.
+    fn hold_table_lock(
+        db: &SharedDatabase,
+        table: &str,
+    ) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+        let (lock_manager, txn_manager) = {
+            let db = db.lock().unwrap();
+            (db.lock_manager(), db.txn_manager.clone())
+        };
+        let table = TableReference::bare(table);
+        let (locked_sender, locked) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let txn_id = txn_manager
+                .begin_tracked(IsolationLevel::SnapshotIsolation)
+                .txn_id;
+            lock_manager
+                .lock_table(
+                    txn_id,
+                    TableLockMode::AccessExclusive,
+                    &table,
+                    Duration::ZERO,
+                )
+                .unwrap();
+            locked_sender.send(()).unwrap();
+            let _ = released.recv();
+            lock_manager.unlock_all(txn_id);
+            txn_manager.end(txn_id);
+        });
+        locked.recv().unwrap();
+        (release, handle)
+    }
+
+    // Statements of sessions run one at a time and never wait for each other's locks, the
+    // conflicts come from locks held outside of statements
+    #[test]
+    pub fn test_run_transaction_retries_conflicts() {
+        const TRANSACTIONS: usize = 5;
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session
+            .run("create table counters (id int, v int)")
+            .unwrap();
+        session.run("create table audit (n int)").unwrap();
+        session.run("insert into counters values (1, 0)").unwrap();
+        session.run("insert into audit values (0)").unwrap();
+        session.run("set lock_timeout = '5ms'").unwrap();
+
+        for _ in 0..TRANSACTIONS {
+            let (release, holder) = hold_table_lock(&db, "audit");
+            let mut attempts = 0;
+            session
+                .run_transaction(|txn| {
+                    attempts += 1;
+                    txn.run("update counters set v = v + 1 where id = 1")?;
+                    let result = txn.run("update audit set n = n + 1");
+                    if attempts == 1 {
+                        assert!(matches!(result, Err(BustubxError::RelationLocked { .. })));
+                        // the holder lets go while the attempt backs off
+                        release.send(()).unwrap();
+                    }
+                    result.map(|_| ())
+                })
+                .unwrap();
+            holder.join().unwrap();
+            assert!(attempts >= 2);
+        }
+        // the increments of the failed attempts were rolled back
+        assert_eq!(
+            count(
+                &mut session,
+                &format!("select count(*) from counters where v = {TRANSACTIONS}")
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                &mut session,
+                &format!("select count(*) from audit where n = {TRANSACTIONS}")
+            ),
+            1
+        );
+
+        // retries are given up on after transaction_max_retries
+        session.run("set lock_timeout = '1ms'").unwrap();
+        session.run("set transaction_max_retries = 2").unwrap();
+        let (release, holder) = hold_table_lock(&db, "audit");
+        let mut attempts = 0;
+        let result = session.run_transaction(|txn| {
+            attempts += 1;
+            txn.run("update counters set v = v + 1 where id = 1")?;
+            txn.run("update audit set n = n + 1")
+        });
+        release.send(()).unwrap();
+        holder.join().unwrap();
+        assert!(matches!(result, Err(BustubxError::RelationLocked { .. })));
+        assert_eq!(attempts, 3);
+        assert_eq!(
+            count(
+                &mut session,
+                &format!("select count(*) from counters where v = {TRANSACTIONS}")
+            ),
+            1
+        );
+    }
+
+    #[test]
+    pub fn test_run_transaction_does_not_retry_other_errors() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t1 (a int, b int)").unwrap();
+        session.run("create unique index idx_a on t1 (a)").unwrap();
+        session.run("insert into t1 values (1, 0)").unwrap();
+
+        let mut attempts = 0;
+        let result = session.run_transaction(|txn| {
+            attempts += 1;
+            txn.run("insert into t1 values (2, 0)")?;
+            txn.run("update t1 set b = 1 where a = 1")?;
+            txn.run("insert into t1 values (1, 0)")
+        });
+        assert!(
+            matches!(&result, Err(BustubxError::Execution(e)) if e.contains("unique index idx_a")),
+            "{result:?}"
+        );
+        assert_eq!(attempts, 1);
+        assert_eq!(count(&mut session, "select count(*) from t1"), 1);
+        assert_eq!(
+            count(&mut session, "select count(*) from t1 where b = 1"),
+            0
+        );
+        // the index lost the entry of the rolled back insert too
+        session.run("insert into t1 values (2, 0)").unwrap();
+
+        // the transaction ends with the closure
+        for sql in ["begin", "commit", "rollback"] {
+            let result = session.run_transaction(|txn| txn.run(sql));
+            assert!(matches!(result, Err(BustubxError::Plan(_))), "{sql}");
+        }
+        let rows = session
+            .run_transaction(|txn| {
+                txn.run("insert into t1 values (3, 0)")?;
+                txn.run("select a from t1 where a = 3")
+            })
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(count(&mut session, "select count(*) from t1"), 3);
+    }
+
+    #[test]
+    pub fn test_idle_transaction_holds_back_vacuum_until_aborted() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t1 (a int, b varchar)").unwrap();
+        let values = (0..200)
+            .map(|i| format!("({i}, '{}')", "x".repeat(100)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        session
+            .run(&format!("insert into t1 values {values}"))
+            .unwrap();
+        session
+            .run("set idle_transaction_timeout = '50ms'")
+            .unwrap();
+
+        let mut txn_id = 0;
+        let result = session.run_transaction(|txn| {
+            txn_id = txn.txn_id();
+            txn.run("delete from t1")?;
+            // the deleted tuples stay for a rollback
+            let vacuum = txn.run("vacuum t1")?;
+            assert_eq!(vacuum[0].data[1], ScalarValue::UInt64(Some(0)));
+
+            let rows = txn.run("show transactions")?;
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].data[0], ScalarValue::UInt64(Some(txn_id)));
+            assert_eq!(
+                rows[0].data[1],
+                ScalarValue::Varchar(Some("running".into()))
+            );
+            assert_eq!(rows[0].data[4], ScalarValue::UInt64(Some(200)));
+            assert_eq!(rows[0].data[5], ScalarValue::Boolean(Some(true)));
+            // readable while the transaction holds the database
+            let transactions = db.transactions();
+            assert_eq!(transactions.len(), 1);
+            assert!(transactions[0].horizon_blocker);
+            assert_eq!(
+                transactions[0].tables,
+                vec![TableReference::partial("public", "t1")]
+            );
+
+            thread::sleep(Duration::from_millis(100));
+            let result = txn.run("select count(*) from t1");
+            assert!(matches!(
+                result,
+                Err(BustubxError::IdleTransactionTimeout { .. })
+            ));
+            // aborted for good
+            txn.run("select count(*) from t1")
+        });
+        assert!(matches!(
+            result,
+            Err(BustubxError::IdleTransactionTimeout { txn_id: id, .. }) if id == txn_id
+        ));
+        assert!(db.transactions().is_empty());
+        assert_eq!(count(&mut session, "select count(*) from t1"), 200);
+
+        session.run("delete from t1").unwrap();
+        let vacuum = session.run("vacuum t1").unwrap();
+        assert!(matches!(vacuum[0].data[1], ScalarValue::UInt64(Some(pages)) if pages > 0));
+    }
+
+    #[test]
+    pub fn test_run_transaction_checks_deferred_keys_at_commit() {
+        let shared = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = shared.session();
+        session.run("create table t (id int, k int)").unwrap();
+        session
+            .run("create unique index idx_k on t (k) deferrable initially deferred")
+            .unwrap();
+        session.run("insert into t values (1, 1), (2, 2)").unwrap();
+        let keys = |session: &mut Session| {
+            session
+                .run("select k from t order by id")
+                .unwrap()
+                .iter()
+                .map(|tuple| tuple.data[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let violates = |result: BustubxResult<Vec<Tuple>>| match result {
+            Err(BustubxError::Execution(msg)) => msg.contains("idx_k"),
+            _ => false,
+        };
+
+        // both rows hold key 2 between the statements
+        session
+            .run_transaction(|txn| {
+                txn.run("update t set k = 2 where id = 1")?;
+                txn.run("update t set k = 1 where id = 2")
+            })
+            .unwrap();
+        assert_eq!(
+            keys(&mut session),
+            [2, 1].map(|k| ScalarValue::Int32(Some(k))).to_vec()
+        );
+
+        // a key still held twice at the commit rolls the transaction back
+        assert!(violates(
+            session.run_transaction(|txn| txn.run("insert into t values (3, 1)"))
+        ));
+        // checked by every statement once set immediate, the keys deferred so far too
+        assert!(violates(session.run_transaction(|txn| {
+            txn.run("set constraints all immediate")?;
+            txn.run("update t set k = 1 where id = 1")
+        })));
+        assert!(violates(session.run_transaction(|txn| {
+            txn.run("insert into t values (3, 1)")?;
+            txn.run("set constraints all immediate")
+        })));
+        assert_eq!(
+            keys(&mut session),
+            [2, 1].map(|k| ScalarValue::Int32(Some(k))).to_vec()
+        );
+        assert!(session
+            .run("check table t with indexes")
+            .unwrap()
+            .is_empty());
+
+        // an index checked at the end of every statement is deferred until the commit
+        session
+            .run("create unique index idx_id on t (id) deferrable")
+            .unwrap();
+        assert!(session
+            .run_transaction(|txn| {
+                txn.run("update t set id = 2 where k = 2")?;
+                txn.run("update t set id = 1 where k = 1")
+            })
+            .is_err());
+        session
+            .run_transaction(|txn| {
+                txn.run("set constraints all deferred")?;
+                txn.run("update t set id = 2 where k = 2")?;
+                txn.run("update t set id = 1 where k = 1")
+            })
+            .unwrap();
+        assert_eq!(
+            keys(&mut session),
+            [1, 2].map(|k| ScalarValue::Int32(Some(k))).to_vec()
+        );
+
+        // a statement run on its own checks its keys when it ends
+        assert!(violates(session.run("update t set k = 1")));
+        assert!(matches!(
+            session.run("set constraints all deferred"),
+            Err(BustubxError::Plan(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_run_transaction_checks_deferred_foreign_keys_at_commit() {
+        let shared = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = shared.session();
+        session.run("create table parent (id int)").unwrap();
+        session
+            .run("create unique index idx_parent_id on parent (id)")
+            .unwrap();
+        session
+            .run(
+                "create table child (id int, parent_id int references parent (id) \
+                 deferrable initially deferred)",
+            )
+            .unwrap();
+        session
+            .run(
+                "create table strict_child (id int, parent_id int, \
+                 constraint strict_fk foreign key (parent_id) references parent (id) \
+                 deferrable initially immediate)",
+            )
+            .unwrap();
+        let count = |session: &mut Session, table: &str| {
+            session
+                .run(&format!("select count(*) from {table}"))
+                .unwrap()[0]
+                .data[0]
+                .clone()
+        };
+        let violates = |result: BustubxResult<Vec<Tuple>>, name: &str| match result {
+            Err(BustubxError::Execution(msg)) => msg.contains(name),
+            _ => false,
+        };
+
+        // the child row references its parent once the transaction commits
+        session
+            .run_transaction(|txn| {
+                txn.run("insert into child values (1, 1)")?;
+                txn.run("insert into parent values (1)")
+            })
+            .unwrap();
+        assert_eq!(count(&mut session, "child"), ScalarValue::Int64(Some(1)));
+
+        // a parent still missing at the commit rolls the transaction back
+        assert!(violates(
+            session.run_transaction(|txn| txn.run("insert into child values (2, 2)")),
+            "child_parent_id_fkey"
+        ));
+        assert!(violates(
+            session.run_transaction(|txn| {
+                txn.run("insert into parent values (2)")?;
+                txn.run("insert into child values (2, 2)")?;
+                txn.run("delete from parent where id = 2")
+            }),
+            "child_parent_id_fkey"
+        ));
+        assert_eq!(count(&mut session, "child"), ScalarValue::Int64(Some(1)));
+        assert_eq!(count(&mut session, "parent"), ScalarValue::Int64(Some(1)));
+
+        // checked by the statement when immediate, until set deferred
+        assert!(violates(
+            session.run_transaction(|txn| {
+                txn.run("insert into strict_child values (1, 3)")?;
+                txn.run("insert into parent values (3)")
+            }),
+            "strict_fk"
+        ));
+        session
+            .run_transaction(|txn| {
+                txn.run("set constraints all deferred")?;
+                txn.run("insert into strict_child values (1, 3)")?;
+                txn.run("insert into parent values (3)")
+            })
+            .unwrap();
+
+        // a statement run on its own checks the references when it ends
+        assert!(violates(
+            session.run("delete from parent where id = 1"),
+            "child_parent_id_fkey"
+        ));
+        assert!(violates(
+            session.run("insert into child values (3, 4)"),
+            "child_parent_id_fkey"
+        ));
+        session.run("insert into child values (3, null)").unwrap();
+        session.run("delete from child where id = 1").unwrap();
+        session.run("delete from parent where id = 1").unwrap();
+    }
+}
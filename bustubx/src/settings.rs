@@ -0,0 +1,736 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use sqlparser::ast::{Expr, UnaryOperator, Value};
+
+use crate::config::{parse_timeout, ExecutionOptions};
+use crate::transaction::TransactionId;
+use crate::{BustubxError, BustubxResult};
+
+// longest statement and lock timeout accepted by SET
+const MAX_TIMEOUT_MILLIS: u64 = 24 * 60 * 60 * 1000;
+const MAX_MEMORY_BUDGET: u64 = 1 << 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingType {
+    Int,
+    Bool,
+    // written as in `statement_timeout`, e.g. '250ms', '5s' or a number of milliseconds
+    Duration,
+    // bytes, written as e.g. '64kB', '16MB' or a number of bytes
+    Size,
+    // names separated by commas, e.g. `app, public`
+    NameList,
+}
+
+impl Display for SettingType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingType::Int => write!(f, "int"),
+            SettingType::Bool => write!(f, "bool"),
+            SettingType::Duration => write!(f, "duration"),
+            SettingType::Size => write!(f, "size"),
+            SettingType::NameList => write!(f, "name list"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingScope {
+    // SET in a session applies to the statements of that session only
+    Session,
+    // SET applies to every session
+    Database,
+}
+
+impl Display for SettingScope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingScope::Session => write!(f, "session"),
+            SettingScope::Database => write!(f, "database"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingValue {
+    Int(i64),
+    Bool(bool),
+    Duration(Duration),
+    Size(u64),
+    Names(Vec<String>),
+}
+
+impl Display for SettingValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingValue::Int(v) => write!(f, "{v}"),
+            SettingValue::Bool(v) => write!(f, "{}", if *v { "on" } else { "off" }),
+            SettingValue::Duration(v) => {
+                let millis = v.as_millis();
+                if millis > 0 && millis % 1000 == 0 {
+                    write!(f, "{}s", millis / 1000)
+                } else {
+                    write!(f, "{millis}ms")
+                }
+            }
+            SettingValue::Size(v) => {
+                let unit = SIZE_UNITS
+                    .iter()
+                    .rev()
+                    .find(|(_, bytes)| *v > 0 && v % bytes == 0);
+                match unit {
+                    Some((unit, bytes)) => write!(f, "{}{unit}", v / bytes),
+                    None => write!(f, "{v}B"),
+                }
+            }
+            SettingValue::Names(v) => write!(f, "{}", v.join(", ")),
+        }
+    }
+}
+
+const SIZE_UNITS: [(&str, u64); 4] = [("B", 1), ("kB", 1 << 10), ("MB", 1 << 20), ("GB", 1 << 30)];
+
+/// Engine knob changed with `SET name = value` and listed by `SHOW name` and `SHOW ALL`.
+///
+/// Every setting is a field of [`ExecutionOptions`]. A statement takes a copy of the
+/// options when it starts, executors read its typed fields from their execution context
+/// and see the same values until the statement ends.
+#[derive(Debug)]
+pub struct Setting {
+    pub name: &'static str,
+    pub setting_type: SettingType,
+    pub scope: SettingScope,
+    pub description: &'static str,
+    // inclusive bounds of int and size values, in milliseconds for durations
+    min: u64,
+    max: u64,
+    get: fn(&ExecutionOptions) -> SettingValue,
+    set: fn(&mut ExecutionOptions, SettingValue),
+}
+
+static SETTINGS: [Setting; 23] = [
+    Setting {
+        name: "aggregate_memory_budget",
+        setting_type: SettingType::Size,
+        scope: SettingScope::Session,
+        description: "bytes of group state a hash aggregation keeps in memory",
+        min: 1,
+        max: MAX_MEMORY_BUDGET,
+        get: |options| SettingValue::Size(options.aggregate_memory_budget as u64),
+        set: |options, value| options.aggregate_memory_budget = value.size(),
+    },
+    Setting {
+        name: "analyze_sample_size",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "pages and live rows ANALYZE reads at most from a table",
+        min: 1,
+        max: 1 << 24,
+        get: |options| SettingValue::Int(options.analyze_sample_size as i64),
+        set: |options, value| options.analyze_sample_size = value.int() as usize,
+    },
+    Setting {
+        name: "auto_analyze_threshold",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Database,
+        description: "rows written to a table that start an ANALYZE of it, 0 never does",
+        min: 0,
+        max: 1 << 40,
+        get: |options| SettingValue::Int(options.auto_analyze_threshold as i64),
+        set: |options, value| options.auto_analyze_threshold = value.int() as u64,
+    },
+    Setting {
+        name: "batch_execution",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "scans, filters and projections hand rows to each other in batches",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.batch_execution),
+        set: |options, value| options.batch_execution = value.bool(),
+    },
+    Setting {
+        name: "batch_size",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "rows per batch of batch execution",
+        min: 1,
+        max: 1 << 20,
+        get: |options| SettingValue::Int(options.batch_size as i64),
+        set: |options, value| options.batch_size = value.int() as usize,
+    },
+    Setting {
+        name: "continue_script_on_error",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "a script runs the statements after a failed one",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.continue_script_on_error),
+        set: |options, value| options.continue_script_on_error = value.bool(),
+    },
+    Setting {
+        name: "deterministic_order",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "ORDER BY breaks ties of the sort keys on the remaining columns",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.deterministic_order),
+        set: |options, value| options.deterministic_order = value.bool(),
+    },
+    Setting {
+        name: "distinct_sketches",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Database,
+        description: "ANALYZE sketches the distinct values of indexed columns for later writes",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.distinct_sketches),
+        set: |options, value| options.distinct_sketches = value.bool(),
+    },
+    Setting {
+        name: "hide_expired_rows",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "scans skip expired rows of ttl tables",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.hide_expired_rows),
+        set: |options, value| options.hide_expired_rows = value.bool(),
+    },
+    Setting {
+        name: "idle_transaction_timeout",
+        setting_type: SettingType::Duration,
+        scope: SettingScope::Session,
+        description: "how long a transaction may idle between statements, 0 never aborts",
+        min: 0,
+        max: MAX_TIMEOUT_MILLIS,
+        get: |options| SettingValue::Duration(options.idle_transaction_timeout),
+        set: |options, value| options.idle_transaction_timeout = value.duration(),
+    },
+    Setting {
+        name: "index_rebuild_threshold",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "percent of a table a DELETE affects above which indexes are rebuilt",
+        min: 0,
+        max: 100,
+        get: |options| SettingValue::Int(options.index_rebuild_threshold as i64),
+        set: |options, value| options.index_rebuild_threshold = value.int() as u32,
+    },
+    Setting {
+        name: "index_scan_revalidate_interval",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "entries an index scan returns between descents, 0 descends at leaf ends",
+        min: 0,
+        max: 1 << 31,
+        get: |options| SettingValue::Int(options.index_scan_revalidate_interval as i64),
+        set: |options, value| options.index_scan_revalidate_interval = value.int() as usize,
+    },
+    Setting {
+        name: "lock_timeout",
+        setting_type: SettingType::Duration,
+        scope: SettingScope::Session,
+        description: "how long a statement waits for its table locks, 0 waits forever",
+        min: 0,
+        max: MAX_TIMEOUT_MILLIS,
+        get: |options| SettingValue::Duration(options.lock_timeout),
+        set: |options, value| options.lock_timeout = value.duration(),
+    },
+    Setting {
+        name: "optimizer_trace",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "EXPLAIN shows why rewrites and access paths were chosen or skipped",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.optimizer_trace),
+        set: |options, value| options.optimizer_trace = value.bool(),
+    },
+    Setting {
+        name: "parallel_scan_min_pages",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "pages a table needs for its sequential scans to run in parallel",
+        min: 1,
+        max: 1 << 31,
+        get: |options| SettingValue::Int(options.parallel_scan_min_pages as i64),
+        set: |options, value| options.parallel_scan_min_pages = value.int() as usize,
+    },
+    Setting {
+        name: "parallel_scan_workers",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "worker threads of a parallel sequential scan, 0 and 1 scan serially",
+        min: 0,
+        max: 64,
+        get: |options| SettingValue::Int(options.parallel_scan_workers as i64),
+        set: |options, value| options.parallel_scan_workers = value.int() as usize,
+    },
+    Setting {
+        name: "plan_cache_capacity",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Database,
+        description: "plans of recent statements kept for reuse, 0 turns the cache off",
+        min: 0,
+        max: 1 << 16,
+        get: |options| SettingValue::Int(options.plan_cache_capacity as i64),
+        set: |options, value| options.plan_cache_capacity = value.int() as usize,
+    },
+    Setting {
+        name: "search_path",
+        setting_type: SettingType::NameList,
+        scope: SettingScope::Session,
+        description: "schemas searched in order for the tables of unqualified names",
+        min: 0,
+        max: 0,
+        get: |options| SettingValue::Names(options.search_path.clone()),
+        set: |options, value| options.search_path = value.names(),
+    },
+    Setting {
+        name: "snapshot_txn",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "queries read the rows as of this committed transaction, 0 the latest",
+        min: 0,
+        max: i64::MAX as u64,
+        get: |options| SettingValue::Int(options.snapshot_txn as i64),
+        set: |options, value| options.snapshot_txn = value.int() as TransactionId,
+    },
+    Setting {
+        name: "sort_memory_budget",
+        setting_type: SettingType::Size,
+        scope: SettingScope::Session,
+        description: "bytes of rows a sort keeps in memory before spilling a sorted run",
+        min: 1,
+        max: MAX_MEMORY_BUDGET,
+        get: |options| SettingValue::Size(options.sort_memory_budget as u64),
+        set: |options, value| options.sort_memory_budget = value.size(),
+    },
+    Setting {
+        name: "statement_timeout",
+        setting_type: SettingType::Duration,
+        scope: SettingScope::Session,
+        description: "how long a statement may run before it is canceled, 0 never cancels",
+        min: 0,
+        max: MAX_TIMEOUT_MILLIS,
+        get: |options| SettingValue::Duration(options.statement_timeout),
+        set: |options, value| options.statement_timeout = value.duration(),
+    },
+    Setting {
+        name: "trace_page_accesses",
+        setting_type: SettingType::Bool,
+        scope: SettingScope::Session,
+        description: "record the pages every statement reads, see Database::last_page_trace",
+        min: 0,
+        max: 1,
+        get: |options| SettingValue::Bool(options.trace_page_accesses),
+        set: |options, value| options.trace_page_accesses = value.bool(),
+    },
+    Setting {
+        name: "transaction_max_retries",
+        setting_type: SettingType::Int,
+        scope: SettingScope::Session,
+        description: "times Session::run_transaction retries a transaction after a lock conflict",
+        min: 0,
+        max: 1000,
+        get: |options| SettingValue::Int(options.transaction_max_retries as i64),
+        set: |options, value| options.transaction_max_retries = value.int() as usize,
+    },
+];
+
+impl Setting {
+    /// Every setting, ordered by name.
+    pub fn all() -> &'static [Setting] {
+        &SETTINGS
+    }
+
+    /// Setting called `name`, ignoring case.
+    pub fn find(name: &str) -> BustubxResult<&'static Setting> {
+        SETTINGS
+            .iter()
+            .find(|setting| setting.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| BustubxError::UnknownSetting(name.to_string()))
+    }
+
+    pub fn get(&self, options: &ExecutionOptions) -> SettingValue {
+        (self.get)(options)
+    }
+
+    /// Store a value returned by [`Setting::parse`] for this setting.
+    pub fn set(&self, options: &mut ExecutionOptions, value: SettingValue) {
+        (self.set)(options, value)
+    }
+
+    pub fn default_value(&self) -> SettingValue {
+        self.get(&ExecutionOptions::default())
+    }
+
+    /// Value of `value` checked against the type and range of the setting, `default` is
+    /// its built-in default.
+    pub fn parse(&self, value: &str) -> BustubxResult<SettingValue> {
+        let value = value.trim();
+        let invalid = |reason: String| BustubxError::InvalidSetting {
+            name: self.name.to_string(),
+            value: value.to_string(),
+            reason,
+        };
+        if value.eq_ignore_ascii_case("default") {
+            return Ok(self.default_value());
+        }
+        if self.setting_type == SettingType::NameList {
+            let names = value
+                .split(',')
+                .map(|name| name.trim().trim_matches('"').to_string())
+                .collect::<Vec<_>>();
+            if names.iter().any(|name| name.is_empty()) {
+                return Err(invalid("expected names separated by commas".to_string()));
+            }
+            return Ok(SettingValue::Names(names));
+        }
+        let (parsed, number) = match self.setting_type {
+            SettingType::Bool => {
+                let parsed = match value.to_ascii_lowercase().as_str() {
+                    "on" | "true" | "yes" | "1" => true,
+                    "off" | "false" | "no" | "0" => false,
+                    _ => return Err(invalid("expected on or off".to_string())),
+                };
+                (SettingValue::Bool(parsed), parsed as i128)
+            }
+            SettingType::Int => {
+                let parsed = value
+                    .parse::<i64>()
+                    .map_err(|_| invalid("expected an integer".to_string()))?;
+                (SettingValue::Int(parsed), parsed as i128)
+            }
+            SettingType::Duration => {
+                let parsed = parse_timeout(value)
+                    .map_err(|_| invalid("expected a duration such as '5s'".to_string()))?;
+                (SettingValue::Duration(parsed), parsed.as_millis() as i128)
+            }
+            SettingType::Size => {
+                let parsed = parse_size(value)
+                    .ok_or_else(|| invalid("expected a size such as '64MB'".to_string()))?;
+                (SettingValue::Size(parsed), parsed as i128)
+            }
+            SettingType::NameList => unreachable!("name lists are not numbers"),
+        };
+        if number < self.min as i128 || number > self.max as i128 {
+            return Err(invalid(format!(
+                "out of range {} to {}",
+                self.bound(self.min),
+                self.bound(self.max)
+            )));
+        }
+        Ok(parsed)
+    }
+
+    /// Value of the right-hand side of `SET name = value`: a number, a quoted string or a
+    /// word such as `on` or `default`, or a list of words and strings for a name list.
+    pub fn parse_expr(&self, value: &[Expr]) -> BustubxResult<SettingValue> {
+        if self.setting_type == SettingType::NameList {
+            let names = value
+                .iter()
+                .map(|expr| match expr {
+                    Expr::Identifier(ident) => Ok(ident.value.clone()),
+                    Expr::Value(Value::SingleQuotedString(name)) => Ok(name.clone()),
+                    _ => Err(self.unexpected_expr(value)),
+                })
+                .collect::<BustubxResult<Vec<_>>>()?;
+            return self.parse(&names.join(","));
+        }
+        let text = match value {
+            [Expr::Value(Value::Number(value, _) | Value::SingleQuotedString(value))] => {
+                value.clone()
+            }
+            [Expr::Value(Value::Boolean(value))] => value.to_string(),
+            [Expr::Identifier(ident)] => ident.value.clone(),
+            [Expr::UnaryOp {
+                op: UnaryOperator::Minus,
+                expr,
+            }] => match expr.as_ref() {
+                Expr::Value(Value::Number(value, _)) => format!("-{value}"),
+                _ => return Err(self.unexpected_expr(value)),
+            },
+            _ => return Err(self.unexpected_expr(value)),
+        };
+        self.parse(&text)
+    }
+
+    fn unexpected_expr(&self, value: &[Expr]) -> BustubxError {
+        BustubxError::InvalidSetting {
+            name: self.name.to_string(),
+            value: value
+                .iter()
+                .map(|expr| expr.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            reason: "expected a number, a string or a word".to_string(),
+        }
+    }
+
+    fn bound(&self, bound: u64) -> SettingValue {
+        match self.setting_type {
+            SettingType::Int => SettingValue::Int(bound as i64),
+            SettingType::Bool => SettingValue::Bool(bound != 0),
+            SettingType::Duration => SettingValue::Duration(Duration::from_millis(bound)),
+            SettingType::Size => SettingValue::Size(bound),
+            SettingType::NameList => SettingValue::Names(vec![]),
+        }
+    }
+}
+
+// the accessors are called by the setters of SETTINGS with values of their own type
+impl SettingValue {
+    fn int(self) -> i64 {
+        match self {
+            SettingValue::Int(v) => v,
+            _ => unreachable!("{self:?} is not an int"),
+        }
+    }
+
+    fn bool(self) -> bool {
+        match self {
+            SettingValue::Bool(v) => v,
+            _ => unreachable!("{self:?} is not a bool"),
+        }
+    }
+
+    fn duration(self) -> Duration {
+        match self {
+            SettingValue::Duration(v) => v,
+            _ => unreachable!("{self:?} is not a duration"),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            SettingValue::Size(v) => v as usize,
+            _ => unreachable!("{self:?} is not a size"),
+        }
+    }
+
+    fn names(self) -> Vec<String> {
+        match self {
+            SettingValue::Names(v) => v,
+            _ => unreachable!("{self:?} is not a name list"),
+        }
+    }
+}
+
+// '64kB', '16MB', '1GB' or a number of bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<u64>().ok()?;
+    let unit = match unit.trim() {
+        "" => "B",
+        unit => unit,
+    };
+    let (_, bytes) = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))?;
+    number.checked_mul(*bytes)
+}
+
+/// Session scoped settings a [`crate::Session`] changed with `SET`, applied over the
+/// settings of the database for the statements of that session.
+#[derive(Debug, Clone, Default)]
+pub struct SessionSettings {
+    values: BTreeMap<&'static str, (&'static Setting, SettingValue)>,
+}
+
+impl SessionSettings {
+    pub fn set(&mut self, setting: &'static Setting, value: SettingValue) {
+        self.values.insert(setting.name, (setting, value));
+    }
+
+    /// `options` of the database with the settings of the session in place.
+    pub fn apply(&self, options: &ExecutionOptions) -> ExecutionOptions {
+        let mut options = options.clone();
+        for (setting, value) in self.values.values() {
+            setting.set(&mut options, value.clone());
+        }
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::ScalarValue;
+    use crate::settings::{Setting, SettingValue};
+    use crate::{BustubxError, Database, DatabaseOptions, SharedDatabase, Tuple};
+
+    fn show(db: &mut Database, name: &str) -> String {
+        let rows = db.run(&format!("show {name}")).unwrap();
+        assert_eq!(rows.len(), 1);
+        match &rows[0].data[..] {
+            [ScalarValue::Varchar(Some(row_name)), ScalarValue::Varchar(Some(setting))] => {
+                assert_eq!(row_name, name);
+                setting.clone()
+            }
+            data => panic!("unexpected show output {data:?}"),
+        }
+    }
+
+    #[test]
+    pub fn test_set_and_show_settings() {
+        let mut db = Database::new_temp().unwrap();
+        assert_eq!(show(&mut db, "sort_memory_budget"), "64MB");
+        assert_eq!(show(&mut db, "batch_execution"), "off");
+
+        for (sql, name, shown) in [
+            (
+                "set sort_memory_budget = '256kB'",
+                "sort_memory_budget",
+                "256kB",
+            ),
+            (
+                "set sort_memory_budget to 1000",
+                "sort_memory_budget",
+                "1000B",
+            ),
+            ("set batch_execution = on", "batch_execution", "on"),
+            ("set batch_execution = false", "batch_execution", "off"),
+            (
+                "set index_scan_revalidate_interval = 8",
+                "index_scan_revalidate_interval",
+                "8",
+            ),
+            ("set batch_size = 16", "batch_size", "16"),
+            ("set statement_timeout = '2s'", "statement_timeout", "2s"),
+            ("set lock_timeout = 250", "lock_timeout", "250ms"),
+            ("set plan_cache_capacity = 0", "plan_cache_capacity", "0"),
+            (
+                "SET Index_Rebuild_Threshold = 100",
+                "index_rebuild_threshold",
+                "100",
+            ),
+        ] {
+            db.run(sql).unwrap();
+            assert_eq!(show(&mut db, name), shown, "{sql}");
+        }
+        let options = &db.options().execution;
+        assert_eq!(options.sort_memory_budget, 1000);
+        assert_eq!(options.batch_size, 16);
+        assert_eq!(options.statement_timeout, Duration::from_secs(2));
+        assert_eq!(options.lock_timeout, Duration::from_millis(250));
+        assert_eq!(
+            db.setting("sort_memory_budget").unwrap(),
+            SettingValue::Size(1000)
+        );
+
+        db.run("set sort_memory_budget = default").unwrap();
+        assert_eq!(show(&mut db, "sort_memory_budget"), "64MB");
+
+        let all = db.run("show all").unwrap();
+        assert_eq!(all.len(), Setting::all().len());
+        let names = all
+            .iter()
+            .map(|tuple: &Tuple| tuple.data[0].to_string())
+            .collect::<Vec<_>>();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert_eq!(
+            all[names.iter().position(|n| n == "batch_size").unwrap()].data[1..4],
+            [
+                ScalarValue::Varchar(Some("16".to_string())),
+                ScalarValue::Varchar(Some("int".to_string())),
+                ScalarValue::Varchar(Some("session".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_invalid_settings() {
+        let mut db = Database::new_temp().unwrap();
+        assert!(matches!(
+            db.run("set application_name = 'public'"),
+            Err(BustubxError::UnknownSetting(name)) if name == "application_name"
+        ));
+        assert!(matches!(
+            db.run("show application_name"),
+            Err(BustubxError::UnknownSetting(_))
+        ));
+        for sql in [
+            "set index_rebuild_threshold = 101",
+            "set batch_size = 0",
+            "set batch_size = -1",
+            "set sort_memory_budget = '2048GB'",
+            "set statement_timeout = '2d'",
+            "set batch_execution = 'maybe'",
+            "set sort_memory_budget = '12 parsecs'",
+            "set batch_size = 'many'",
+        ] {
+            assert!(
+                matches!(db.run(sql), Err(BustubxError::InvalidSetting { .. })),
+                "{sql}"
+            );
+        }
+        assert!(matches!(
+            db.run("set index_rebuild_threshold = 101"),
+            Err(BustubxError::InvalidSetting { name, value, .. })
+                if name == "index_rebuild_threshold" && value == "101"
+        ));
+        // nothing changed
+        assert_eq!(db.options(), &DatabaseOptions::default());
+    }
+
+    #[test]
+    pub fn test_session_scoped_settings() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut first = db.session();
+        let mut second = db.session();
+        first.run("set sort_memory_budget = '1kB'").unwrap();
+        first.run("set plan_cache_capacity = 3").unwrap();
+
+        let setting = |session: &mut crate::Session, name: &str| {
+            session.run(&format!("show {name}")).unwrap()[0].data[1].to_string()
+        };
+        assert_eq!(setting(&mut first, "sort_memory_budget"), "1kB");
+        assert_eq!(setting(&mut second, "sort_memory_budget"), "64MB");
+        // database scoped settings apply to every session
+        assert_eq!(setting(&mut second, "plan_cache_capacity"), "3");
+        assert_eq!(
+            db.lock().unwrap().options().execution.plan_cache_capacity,
+            3
+        );
+        assert_eq!(
+            db.lock().unwrap().options().execution.sort_memory_budget,
+            DatabaseOptions::default().execution.sort_memory_budget
+        );
+    }
+
+    #[test]
+    pub fn test_set_drives_sort_spill() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (id int, k int)").unwrap();
+        let values = (0..500)
+            .map(|id| format!("({id}, {})", id % 7))
+            .collect::<Vec<_>>();
+        db.run(&format!("insert into t1 values {}", values.join(", ")))
+            .unwrap();
+        let sql = "select k, id from t1 order by k";
+
+        db.reset_runtime_stats();
+        let expected = db.run(sql).unwrap();
+        // sorted in memory
+        assert_eq!(db.runtime_stats().pages_allocated, 0);
+
+        db.run("set sort_memory_budget = '1kB'").unwrap();
+        db.reset_runtime_stats();
+        assert_eq!(db.run(sql).unwrap(), expected);
+        let stats = db.runtime_stats();
+        // sorted runs written to temp pages
+        assert!(stats.pages_allocated > 0);
+    }
+}
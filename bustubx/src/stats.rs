@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+/// Counters of the buffer pool, disk, replication log, compression, table locks and plan cache, listed
+/// by `SHOW STATS`. Every counter but `active_transactions` counts from the time the
+/// database was opened or `RESET STATS` last ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeStats {
+    // page fetches served by a resident frame
+    pub buffer_pool_hits: u64,
+    // page fetches that read the page from disk
+    pub buffer_pool_misses: u64,
+    pub buffer_pool_evictions: u64,
+    // dirty pages written back to disk
+    pub dirty_writes: u64,
+    // evictions that had to write their dirty page back on the fetch path
+    pub dirty_evictions: u64,
+    // evictions that took a clean page instead of the dirty LRU-K victim
+    pub dirty_evictions_avoided: u64,
+    pub pages_allocated: u64,
+    pub pages_freed: u64,
+    // page bytes appended to the replication log, 0 without one
+    pub wal_bytes_written: u64,
+    // bytes given to the compressor of log segments and sort runs, and what it wrote
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    pub fsyncs: u64,
+    // transactions holding or waiting for a table lock right now
+    pub active_transactions: u64,
+    // table lock requests that found a conflicting lock
+    pub lock_waits: u64,
+    pub plan_cache_hits: u64,
+    pub plan_cache_misses: u64,
+    // index entries an index scan skipped because their row was gone
+    pub dangling_index_entries: u64,
+    // ANALYZE runs started by `auto_analyze_threshold`
+    pub auto_analyze_runs: u64,
+}
+
+impl RuntimeStats {
+    /// Name and value of every counter, in the order `SHOW STATS` lists them.
+    pub fn rows(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("buffer_pool_hits", self.buffer_pool_hits),
+            ("buffer_pool_misses", self.buffer_pool_misses),
+            ("buffer_pool_evictions", self.buffer_pool_evictions),
+            ("dirty_writes", self.dirty_writes),
+            ("dirty_evictions", self.dirty_evictions),
+            ("dirty_evictions_avoided", self.dirty_evictions_avoided),
+            ("pages_allocated", self.pages_allocated),
+            ("pages_freed", self.pages_freed),
+            ("wal_bytes_written", self.wal_bytes_written),
+            ("uncompressed_bytes", self.uncompressed_bytes),
+            ("compressed_bytes", self.compressed_bytes),
+            ("fsyncs", self.fsyncs),
+            ("active_transactions", self.active_transactions),
+            ("lock_waits", self.lock_waits),
+            ("plan_cache_hits", self.plan_cache_hits),
+            ("plan_cache_misses", self.plan_cache_misses),
+            ("dangling_index_entries", self.dangling_index_entries),
+            ("auto_analyze_runs", self.auto_analyze_runs),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::common::{ScalarValue, TableReference};
+    use crate::transaction::TableLockMode;
+    use crate::{Database, DatabaseOptions, RuntimeStats, SyncPolicy};
+
+    #[test]
+    pub fn test_runtime_stats_counters() {
+        let mut db = Database::new_temp_with_options(
+            DatabaseOptions::new()
+                .buffer_pool_size(16)
+                .replication_log(true)
+                .sync_policy(SyncPolicy::OnFlush),
+        )
+        .unwrap();
+        db.run("create table t1 (a int)").unwrap();
+        db.run("insert into t1 values (1), (2), (3)").unwrap();
+        db.run("reset stats").unwrap();
+        assert_eq!(db.runtime_stats(), RuntimeStats::default());
+
+        // more new pages than frames push the first ones out of the pool
+        let page_ids = (0..20)
+            .map(|_| db.buffer_pool.new_page().unwrap().read().unwrap().page_id)
+            .collect::<Vec<_>>();
+        let stats = db.runtime_stats();
+        assert_eq!(stats.pages_allocated, 20);
+        assert_eq!((stats.buffer_pool_hits, stats.buffer_pool_misses), (0, 0));
+        assert!(stats.buffer_pool_evictions >= 4, "{stats:?}");
+        drop(db.buffer_pool.fetch_page(page_ids[19]).unwrap());
+        drop(db.buffer_pool.fetch_page(page_ids[0]).unwrap());
+        let stats = db.runtime_stats();
+        assert_eq!((stats.buffer_pool_hits, stats.buffer_pool_misses), (1, 1));
+        assert!(db.buffer_pool.delete_page(page_ids[19]).unwrap());
+        assert_eq!(db.runtime_stats().pages_freed, 1);
+
+        // a statement waiting for a lock held by another transaction
+        db.set_lock_timeout(Duration::from_millis(20));
+        let t1 = TableReference::bare("t1");
+        db.lock_manager()
+            .lock_table(999, TableLockMode::AccessExclusive, &t1, Duration::ZERO)
+            .unwrap();
+        assert!(db.run("select a from t1").is_err());
+        let stats = db.runtime_stats();
+        assert_eq!((stats.lock_waits, stats.active_transactions), (1, 1));
+        db.lock_manager().unlock_all(999);
+        assert_eq!(db.runtime_stats().active_transactions, 0);
+
+        db.reset_runtime_stats();
+        let sql = "select a from t1 where a > 1";
+        for _ in 0..3 {
+            assert_eq!(db.run(sql).unwrap().len(), 2);
+        }
+        let stats = db.runtime_stats();
+        assert_eq!((stats.plan_cache_hits, stats.plan_cache_misses), (2, 1));
+        assert_eq!(stats.lock_waits, 0);
+
+        // the rest only grows with more work
+        let before = db.runtime_stats();
+        for a in 0..200 {
+            db.run(&format!("insert into t1 values ({a})")).unwrap();
+        }
+        db.run("checkpoint").unwrap();
+        let after = db.runtime_stats();
+        for ((name, before), (_, after)) in before.rows().into_iter().zip(after.rows()) {
+            if name != "active_transactions" {
+                assert!(after >= before, "{name}: {before} -> {after}");
+            }
+        }
+        assert!(after.buffer_pool_hits > before.buffer_pool_hits);
+        assert!(after.dirty_writes > before.dirty_writes);
+        assert!(after.wal_bytes_written > before.wal_bytes_written);
+        assert!(after.fsyncs > before.fsyncs);
+
+        let rows = db.run("SHOW STATS").unwrap();
+        let expected = db.runtime_stats().rows();
+        assert_eq!(rows.len(), expected.len());
+        for (row, (name, value)) in rows.iter().zip(expected) {
+            assert_eq!(row.data, vec![name.to_string().into(), value.into()]);
+        }
+        db.run("RESET STATS;").unwrap();
+        assert!(db
+            .run("show stats")
+            .unwrap()
+            .iter()
+            .all(|row| row.data[1] == ScalarValue::UInt64(Some(0))));
+    }
+}
@@ -1,5 +1,5 @@
 use crate::buffer::BUSTUBX_PAGE_SIZE;
-use crate::storage::codec::{CommonCodec, DecodedData};
+use crate::storage::codec::{ByteReader, CommonCodec, DecodedData};
 use crate::storage::{FreelistPage, FreelistPageHeader};
 use crate::BustubxResult;
 
@@ -15,16 +15,13 @@ impl FreelistPageHeaderCodec {
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<FreelistPageHeader>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (next_page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let next_page_id = reader.read(CommonCodec::decode_u32)?;
 
-        let (current_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let current_size = reader.read(CommonCodec::decode_u32)?;
 
-        let (max_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let max_size = reader.read(CommonCodec::decode_u32)?;
 
         Ok((
             FreelistPageHeader {
@@ -32,7 +29,7 @@ impl FreelistPageHeaderCodec {
                 current_size,
                 max_size,
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
@@ -53,15 +50,13 @@ impl FreelistPageCodec {
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<FreelistPage>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (header, offset) = FreelistPageHeaderCodec::decode(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let header = reader.read(FreelistPageHeaderCodec::decode)?;
 
         let mut array = Vec::new();
         for _ in 0..header.current_size {
-            let (page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-            left_bytes = &left_bytes[offset..];
+            let page_id = reader.read(CommonCodec::decode_u32)?;
             array.push(page_id);
         }
 
@@ -1,19 +1,27 @@
 use crate::buffer::BUSTUBX_PAGE_SIZE;
 use crate::catalog::SchemaRef;
-use crate::storage::codec::{CommonCodec, DecodedData, RidCodec, TupleCodec};
+use crate::storage::codec::{ByteReader, CommonCodec, DecodedData, RidCodec, TupleCodec};
 use crate::storage::{
     BPlusTreeInternalPage, BPlusTreeInternalPageHeader, BPlusTreeLeafPage, BPlusTreeLeafPageHeader,
-    BPlusTreePage, BPlusTreePageType,
+    BPlusTreePage, BPlusTreePageType, Tuple,
 };
 use crate::{BustubxError, BustubxResult};
 
 pub struct BPlusTreePageCodec;
 
 impl BPlusTreePageCodec {
+    #[cfg(test)]
     pub fn encode(page: &BPlusTreePage) -> Vec<u8> {
+        let mut bytes = [0; BUSTUBX_PAGE_SIZE];
+        Self::encode_into(page, &mut bytes);
+        bytes.to_vec()
+    }
+
+    /// Encode over the whole of `out`, e.g. the data of the frame holding the page.
+    pub fn encode_into(page: &BPlusTreePage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
         match page {
-            BPlusTreePage::Leaf(page) => BPlusTreeLeafPageCodec::encode(page),
-            BPlusTreePage::Internal(page) => BPlusTreeInternalPageCodec::encode(page),
+            BPlusTreePage::Leaf(page) => BPlusTreeLeafPageCodec::encode_into(page, out),
+            BPlusTreePage::Internal(page) => BPlusTreeInternalPageCodec::encode_into(page, out),
         }
     }
 
@@ -26,8 +34,8 @@ impl BPlusTreePageCodec {
             )));
         }
 
-        // not consume left_bytes
-        let (page_type, _) = BPlusTreePageTypeCodec::decode(bytes)?;
+        // not consume bytes
+        let page_type = ByteReader::new(bytes).peek(BPlusTreePageTypeCodec::decode)?;
 
         match page_type {
             BPlusTreePageType::LeafPage => {
@@ -45,17 +53,26 @@ impl BPlusTreePageCodec {
 pub struct BPlusTreeLeafPageCodec;
 
 impl BPlusTreeLeafPageCodec {
+    #[cfg(test)]
     pub fn encode(page: &BPlusTreeLeafPage) -> Vec<u8> {
-        let mut bytes = vec![];
-        bytes.extend(BPlusTreeLeafPageHeaderCodec::encode(&page.header));
+        let mut bytes = [0; BUSTUBX_PAGE_SIZE];
+        Self::encode_into(page, &mut bytes);
+        bytes.to_vec()
+    }
+
+    /// Encode over the whole of `out`, e.g. the data of the frame holding the page.
+    pub fn encode_into(page: &BPlusTreeLeafPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        let mut offset = 0;
+        put_bytes(
+            out,
+            &mut offset,
+            &BPlusTreeLeafPageHeaderCodec::encode(&page.header),
+        );
         for (tuple, rid) in page.array.iter() {
-            bytes.extend(TupleCodec::encode(tuple));
-            bytes.extend(RidCodec::encode(rid));
+            put_bytes(out, &mut offset, &TupleCodec::encode(tuple));
+            put_bytes(out, &mut offset, &RidCodec::encode(rid));
         }
-        // make sure length of bytes is BUSTUBX_PAGE_SIZE
-        assert!(bytes.len() <= BUSTUBX_PAGE_SIZE);
-        bytes.extend(vec![0; BUSTUBX_PAGE_SIZE - bytes.len()]);
-        bytes
+        out[offset..].fill(0);
     }
 
     pub fn decode(
@@ -69,22 +86,21 @@ impl BPlusTreeLeafPageCodec {
                 bytes.len()
             )));
         }
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        // not consume left_bytes
-        let (page_type, _) = BPlusTreePageTypeCodec::decode(left_bytes)?;
+        // not consume bytes
+        let page_type = reader.peek(BPlusTreePageTypeCodec::decode)?;
 
         if matches!(page_type, BPlusTreePageType::LeafPage) {
-            let (header, offset) = BPlusTreeLeafPageHeaderCodec::decode(left_bytes)?;
-            left_bytes = &left_bytes[offset..];
+            let mut header = reader.read(BPlusTreeLeafPageHeaderCodec::decode)?;
+            TupleCodec::check_schema_fingerprint(&schema, header.schema_fingerprint)?;
+            header.schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
 
             let mut array = vec![];
             for _ in 0..header.current_size {
-                let (tuple, offset) = TupleCodec::decode(left_bytes, schema.clone())?;
-                left_bytes = &left_bytes[offset..];
+                let tuple = reader.read(|bytes| TupleCodec::decode(bytes, schema.clone()))?;
 
-                let (rid, offset) = RidCodec::decode(left_bytes)?;
-                left_bytes = &left_bytes[offset..];
+                let rid = reader.read(RidCodec::decode)?;
 
                 array.push((tuple, rid));
             }
@@ -103,22 +119,47 @@ impl BPlusTreeLeafPageCodec {
             ))
         }
     }
+
+    /// Header and first key of a leaf page, the other entries are left undecoded.
+    pub fn decode_first_key(
+        bytes: &[u8],
+        schema: SchemaRef,
+    ) -> BustubxResult<(BPlusTreeLeafPageHeader, Option<Tuple>)> {
+        let mut reader = ByteReader::new(bytes);
+        let page_type = reader.peek(BPlusTreePageTypeCodec::decode)?;
+        if !matches!(page_type, BPlusTreePageType::LeafPage) {
+            return Err(BustubxError::Storage(
+                "Index page type must be leaf page".to_string(),
+            ));
+        }
+        let mut header = reader.read(BPlusTreeLeafPageHeaderCodec::decode)?;
+        TupleCodec::check_schema_fingerprint(&schema, header.schema_fingerprint)?;
+        header.schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
+        let first_key = if header.current_size > 0 {
+            Some(reader.read(|bytes| TupleCodec::decode(bytes, schema))?)
+        } else {
+            None
+        };
+        Ok((header, first_key))
+    }
 }
 
 pub struct BPlusTreeInternalPageCodec;
 
 impl BPlusTreeInternalPageCodec {
-    pub fn encode(page: &BPlusTreeInternalPage) -> Vec<u8> {
-        let mut bytes = vec![];
-        bytes.extend(BPlusTreeInternalPageHeaderCodec::encode(&page.header));
+    /// Encode over the whole of `out`, e.g. the data of the frame holding the page.
+    pub fn encode_into(page: &BPlusTreeInternalPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        let mut offset = 0;
+        put_bytes(
+            out,
+            &mut offset,
+            &BPlusTreeInternalPageHeaderCodec::encode(&page.header),
+        );
         for (tuple, page_id) in page.array.iter() {
-            bytes.extend(TupleCodec::encode(tuple));
-            bytes.extend(CommonCodec::encode_u32(*page_id));
+            put_bytes(out, &mut offset, &TupleCodec::encode(tuple));
+            put_bytes(out, &mut offset, &CommonCodec::encode_u32(*page_id));
         }
-        // make sure length of bytes is BUSTUBX_PAGE_SIZE
-        assert!(bytes.len() <= BUSTUBX_PAGE_SIZE);
-        bytes.extend(vec![0; BUSTUBX_PAGE_SIZE - bytes.len()]);
-        bytes
+        out[offset..].fill(0);
     }
 
     pub fn decode(
@@ -132,22 +173,21 @@ impl BPlusTreeInternalPageCodec {
                 bytes.len()
             )));
         }
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        // not consume left_bytes
-        let (page_type, _) = BPlusTreePageTypeCodec::decode(left_bytes)?;
+        // not consume bytes
+        let page_type = reader.peek(BPlusTreePageTypeCodec::decode)?;
 
         if matches!(page_type, BPlusTreePageType::InternalPage) {
-            let (header, offset) = BPlusTreeInternalPageHeaderCodec::decode(left_bytes)?;
-            left_bytes = &left_bytes[offset..];
+            let mut header = reader.read(BPlusTreeInternalPageHeaderCodec::decode)?;
+            TupleCodec::check_schema_fingerprint(&schema, header.schema_fingerprint)?;
+            header.schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
 
             let mut array = vec![];
             for _ in 0..header.current_size {
-                let (tuple, offset) = TupleCodec::decode(left_bytes, schema.clone())?;
-                left_bytes = &left_bytes[offset..];
+                let tuple = reader.read(|bytes| TupleCodec::decode(bytes, schema.clone()))?;
 
-                let (page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-                left_bytes = &left_bytes[offset..];
+                let page_id = reader.read(CommonCodec::decode_u32)?;
 
                 array.push((tuple, page_id));
             }
@@ -168,6 +208,19 @@ impl BPlusTreeInternalPageCodec {
     }
 }
 
+// Set in the page type byte of headers ending with the fingerprint of the key schema.
+const KEY_FINGERPRINT_FLAG: u8 = 1 << 7;
+// Set in the page type byte of leaf headers with the page id of the previous leaf.
+const PREV_PAGE_ID_FLAG: u8 = 1 << 6;
+
+// copy `bytes` to `out` at `offset` and move past them
+fn put_bytes(out: &mut [u8], offset: &mut usize, bytes: &[u8]) {
+    let end = *offset + bytes.len();
+    assert!(end <= out.len(), "index page overflows {} bytes", out.len());
+    out[*offset..end].copy_from_slice(bytes);
+    *offset = end;
+}
+
 pub struct BPlusTreePageTypeCodec;
 
 impl BPlusTreePageTypeCodec {
@@ -180,12 +233,34 @@ impl BPlusTreePageTypeCodec {
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<BPlusTreePageType>> {
         let (flag, offset) = CommonCodec::decode_u8(bytes)?;
-        match flag {
+        match flag & !(KEY_FINGERPRINT_FLAG | PREV_PAGE_ID_FLAG) {
             1 => Ok((BPlusTreePageType::LeafPage, offset)),
             2 => Ok((BPlusTreePageType::InternalPage, offset)),
             _ => Err(BustubxError::Storage(format!("Invalid page type {}", flag))),
         }
     }
+
+    // the page type, flagged when a key fingerprint follows the header
+    fn encode_with_fingerprint(
+        page_type: &BPlusTreePageType,
+        schema_fingerprint: Option<u32>,
+    ) -> Vec<u8> {
+        let mut bytes = Self::encode(page_type);
+        if schema_fingerprint.is_some() {
+            bytes[0] |= KEY_FINGERPRINT_FLAG;
+        }
+        bytes
+    }
+
+    fn decode_has_fingerprint(bytes: &[u8]) -> BustubxResult<DecodedData<bool>> {
+        let (flag, offset) = CommonCodec::decode_u8(bytes)?;
+        Ok((flag & KEY_FINGERPRINT_FLAG != 0, offset))
+    }
+
+    fn decode_has_prev_page_id(bytes: &[u8]) -> BustubxResult<DecodedData<bool>> {
+        let (flag, offset) = CommonCodec::decode_u8(bytes)?;
+        Ok((flag & PREV_PAGE_ID_FLAG != 0, offset))
+    }
 }
 
 pub struct BPlusTreeLeafPageHeaderCodec;
@@ -193,27 +268,49 @@ pub struct BPlusTreeLeafPageHeaderCodec;
 impl BPlusTreeLeafPageHeaderCodec {
     pub fn encode(header: &BPlusTreeLeafPageHeader) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend(BPlusTreePageTypeCodec::encode(&header.page_type));
+        bytes.extend(BPlusTreePageTypeCodec::encode_with_fingerprint(
+            &header.page_type,
+            header.schema_fingerprint,
+        ));
+        if header.prev_page_id.is_some() {
+            bytes[0] |= PREV_PAGE_ID_FLAG;
+        }
         bytes.extend(CommonCodec::encode_u32(header.current_size));
         bytes.extend(CommonCodec::encode_u32(header.max_size));
         bytes.extend(CommonCodec::encode_u32(header.next_page_id));
+        if let Some(prev_page_id) = header.prev_page_id {
+            bytes.extend(CommonCodec::encode_u32(prev_page_id));
+        }
+        if let Some(schema_fingerprint) = header.schema_fingerprint {
+            bytes.extend(CommonCodec::encode_u32(schema_fingerprint));
+        }
         bytes
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<BPlusTreeLeafPageHeader>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (page_type, offset) = BPlusTreePageTypeCodec::decode(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let has_fingerprint = reader.peek(BPlusTreePageTypeCodec::decode_has_fingerprint)?;
+        let has_prev_page_id = reader.peek(BPlusTreePageTypeCodec::decode_has_prev_page_id)?;
+        let page_type = reader.read(BPlusTreePageTypeCodec::decode)?;
 
-        let (current_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let current_size = reader.read(CommonCodec::decode_u32)?;
 
-        let (max_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let max_size = reader.read(CommonCodec::decode_u32)?;
+
+        let next_page_id = reader.read(CommonCodec::decode_u32)?;
+
+        let prev_page_id = if has_prev_page_id {
+            Some(reader.read(CommonCodec::decode_u32)?)
+        } else {
+            None
+        };
 
-        let (next_page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let schema_fingerprint = if has_fingerprint {
+            Some(reader.read(CommonCodec::decode_u32)?)
+        } else {
+            None
+        };
 
         Ok((
             BPlusTreeLeafPageHeader {
@@ -221,8 +318,10 @@ impl BPlusTreeLeafPageHeaderCodec {
                 current_size,
                 max_size,
                 next_page_id,
+                prev_page_id,
+                schema_fingerprint,
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
@@ -232,31 +331,42 @@ pub struct BPlusTreeInternalPageHeaderCodec;
 impl BPlusTreeInternalPageHeaderCodec {
     pub fn encode(header: &BPlusTreeInternalPageHeader) -> Vec<u8> {
         let mut bytes = Vec::new();
-        bytes.extend(BPlusTreePageTypeCodec::encode(&header.page_type));
+        bytes.extend(BPlusTreePageTypeCodec::encode_with_fingerprint(
+            &header.page_type,
+            header.schema_fingerprint,
+        ));
         bytes.extend(CommonCodec::encode_u32(header.current_size));
         bytes.extend(CommonCodec::encode_u32(header.max_size));
+        if let Some(schema_fingerprint) = header.schema_fingerprint {
+            bytes.extend(CommonCodec::encode_u32(schema_fingerprint));
+        }
         bytes
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<BPlusTreeInternalPageHeader>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (page_type, offset) = BPlusTreePageTypeCodec::decode(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let has_fingerprint = reader.peek(BPlusTreePageTypeCodec::decode_has_fingerprint)?;
+        let page_type = reader.read(BPlusTreePageTypeCodec::decode)?;
 
-        let (current_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let current_size = reader.read(CommonCodec::decode_u32)?;
 
-        let (max_size, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let max_size = reader.read(CommonCodec::decode_u32)?;
+
+        let schema_fingerprint = if has_fingerprint {
+            Some(reader.read(CommonCodec::decode_u32)?)
+        } else {
+            None
+        };
 
         Ok((
             BPlusTreeInternalPageHeader {
                 page_type,
                 current_size,
                 max_size,
+                schema_fingerprint,
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
@@ -264,9 +374,9 @@ impl BPlusTreeInternalPageHeaderCodec {
 #[cfg(test)]
 mod tests {
     use crate::catalog::{Column, DataType, Schema};
-    use crate::storage::codec::index_page::BPlusTreePageCodec;
+    use crate::storage::codec::index_page::{BPlusTreeLeafPageCodec, BPlusTreePageCodec};
     use crate::storage::{BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage, RecordId};
-    use crate::Tuple;
+    use crate::{BustubxError, Tuple};
     use std::sync::Arc;
 
     #[test]
@@ -297,4 +407,102 @@ mod tests {
             BPlusTreePageCodec::decode(&BPlusTreePageCodec::encode(&page), schema.clone()).unwrap();
         assert_eq!(new_page, page);
     }
+
+    #[test]
+    fn index_page_codec_wide_keys_and_rids() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int64, false),
+            Column::new("b", DataType::UInt64, false),
+        ]));
+        let key = |a: i64, b: u64| Tuple::new(schema.clone(), vec![a.into(), b.into()]);
+        let mut leaf_page = BPlusTreeLeafPage::new(schema.clone(), 100);
+        leaf_page.insert(key(i64::MIN, u64::MAX), RecordId::new(u32::MAX, u32::MAX));
+        leaf_page.insert(key(-(1 << 32), 1 << 32), RecordId::new(0, u32::MAX));
+        leaf_page.insert(key(i64::MAX, 0), RecordId::new(u32::MAX, 0));
+        leaf_page.header.next_page_id = u32::MAX;
+        leaf_page.header.prev_page_id = Some(u32::MAX - 1);
+        let page = BPlusTreePage::Leaf(leaf_page);
+        let (new_page, _) =
+            BPlusTreePageCodec::decode(&BPlusTreePageCodec::encode(&page), schema.clone()).unwrap();
+        assert_eq!(new_page, page);
+
+        let mut internal_page = BPlusTreeInternalPage::new(schema.clone(), 100);
+        internal_page.insert(Tuple::empty(schema.clone()), u32::MAX);
+        internal_page.insert(key(i64::MIN + 1, 1 << 63), u32::MAX - 1);
+        let page = BPlusTreePage::Internal(internal_page);
+        let (new_page, _) =
+            BPlusTreePageCodec::decode(&BPlusTreePageCodec::encode(&page), schema.clone()).unwrap();
+        assert_eq!(new_page, page);
+    }
+
+    #[test]
+    fn index_page_codec_key_fingerprint() {
+        let schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, true)]));
+        let mut leaf_page = BPlusTreeLeafPage::new(schema.clone(), 100);
+        leaf_page.insert(
+            Tuple::new(schema.clone(), vec![1i32.into()]),
+            RecordId::new(1, 1),
+        );
+        let mut internal_page = BPlusTreeInternalPage::new(schema.clone(), 100);
+        internal_page.insert(Tuple::empty(schema.clone()), 1);
+        internal_page.insert(Tuple::new(schema.clone(), vec![1i32.into()]), 2);
+
+        let other_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int64, true)]));
+        let renamed_schema = Arc::new(Schema::new(vec![Column::new("b", DataType::Int32, false)]));
+        for page in [
+            BPlusTreePage::Leaf(leaf_page.clone()),
+            BPlusTreePage::Internal(internal_page),
+        ] {
+            let bytes = BPlusTreePageCodec::encode(&page);
+            assert!(matches!(
+                BPlusTreePageCodec::decode(&bytes, other_schema.clone()),
+                Err(BustubxError::SchemaMismatch { .. })
+            ));
+            assert!(BPlusTreePageCodec::decode(&bytes, renamed_schema.clone()).is_ok());
+        }
+        assert!(matches!(
+            BPlusTreeLeafPageCodec::decode_first_key(
+                &BPlusTreeLeafPageCodec::encode(&leaf_page),
+                other_schema.clone()
+            ),
+            Err(BustubxError::SchemaMismatch { .. })
+        ));
+
+        // a page written before the fingerprint existed decodes with any key schema and
+        // gets the fingerprint on its next write
+        let mut old_page = leaf_page.clone();
+        old_page.header.schema_fingerprint = None;
+        let (page, _) =
+            BPlusTreeLeafPageCodec::decode(&BPlusTreeLeafPageCodec::encode(&old_page), schema)
+                .unwrap();
+        assert_eq!(page, leaf_page);
+    }
+
+    #[test]
+    fn index_page_codec_prev_page_id() {
+        let schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, true)]));
+        let mut leaf_page = BPlusTreeLeafPage::new(schema.clone(), 100);
+        leaf_page.insert(
+            Tuple::new(schema.clone(), vec![1i32.into()]),
+            RecordId::new(1, 1),
+        );
+        leaf_page.header.next_page_id = 9;
+        leaf_page.header.prev_page_id = Some(7);
+        let bytes = BPlusTreeLeafPageCodec::encode(&leaf_page);
+        let (page, _) = BPlusTreeLeafPageCodec::decode(&bytes, schema.clone()).unwrap();
+        assert_eq!(page, leaf_page);
+        let (header, first_key) =
+            BPlusTreeLeafPageCodec::decode_first_key(&bytes, schema.clone()).unwrap();
+        assert_eq!(header.prev_page_id, Some(7));
+        assert_eq!(first_key, Some(leaf_page.array[0].0.clone()));
+
+        // a leaf written before the link existed decodes without it
+        let mut old_page = leaf_page.clone();
+        old_page.header.prev_page_id = None;
+        let bytes = BPlusTreeLeafPageCodec::encode(&old_page);
+        let (page, _) = BPlusTreeLeafPageCodec::decode(&bytes, schema).unwrap();
+        assert_eq!(page.header.prev_page_id, None);
+        assert_eq!(page.header.next_page_id, 9);
+        assert_eq!(page.array, leaf_page.array);
+    }
 }
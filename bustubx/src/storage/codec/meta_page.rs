@@ -1,12 +1,13 @@
-use crate::storage::codec::{CommonCodec, DecodedData};
-use crate::storage::MetaPage;
-use crate::BustubxResult;
+use crate::storage::codec::{ByteReader, CommonCodec, DecodedData};
+use crate::storage::{MetaPage, DB_FILE_MAGIC};
+use crate::{BustubxError, BustubxResult};
 
 pub struct MetaPageCodec;
 
 impl MetaPageCodec {
     pub fn encode(page: &MetaPage) -> Vec<u8> {
         let mut bytes = Vec::new();
+        bytes.extend(DB_FILE_MAGIC);
         bytes.extend(CommonCodec::encode_u32(page.major_version));
         bytes.extend(CommonCodec::encode_u32(page.minor_version));
         bytes.extend(CommonCodec::encode_u32(page.freelist_page_id));
@@ -22,30 +23,27 @@ impl MetaPageCodec {
         bytes.extend(CommonCodec::encode_u32(
             page.information_schema_indexes_first_page_id,
         ));
+        bytes.extend(CommonCodec::encode_bool(page.clean_shutdown));
         bytes
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<MetaPage>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (major_version, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (minor_version, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (freelist_page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (information_schema_schemas_first_page_id, offset) =
-            CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (information_schema_tables_first_page_id, offset) =
-            CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (information_schema_columns_first_page_id, offset) =
-            CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (information_schema_indexes_first_page_id, offset) =
-            CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        if reader.take(DB_FILE_MAGIC.len())? != DB_FILE_MAGIC {
+            return Err(BustubxError::Decode {
+                offset: 0,
+                message: "meta page magic mismatch".to_string(),
+            });
+        }
+        let major_version = reader.read(CommonCodec::decode_u32)?;
+        let minor_version = reader.read(CommonCodec::decode_u32)?;
+        let freelist_page_id = reader.read(CommonCodec::decode_u32)?;
+        let information_schema_schemas_first_page_id = reader.read(CommonCodec::decode_u32)?;
+        let information_schema_tables_first_page_id = reader.read(CommonCodec::decode_u32)?;
+        let information_schema_columns_first_page_id = reader.read(CommonCodec::decode_u32)?;
+        let information_schema_indexes_first_page_id = reader.read(CommonCodec::decode_u32)?;
+        let clean_shutdown = reader.read(CommonCodec::decode_bool)?;
 
         Ok((
             MetaPage {
@@ -56,8 +54,9 @@ impl MetaPageCodec {
                 information_schema_tables_first_page_id,
                 information_schema_columns_first_page_id,
                 information_schema_indexes_first_page_id,
+                clean_shutdown,
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
@@ -72,5 +71,9 @@ mod tests {
         let page = MetaPage::try_new().unwrap();
         let (new_page, _) = MetaPageCodec::decode(&MetaPageCodec::encode(&page)).unwrap();
         assert_eq!(page, new_page);
+
+        let mut bytes = MetaPageCodec::encode(&page);
+        bytes[0] = b'X';
+        assert!(MetaPageCodec::decode(&bytes).is_err());
     }
 }
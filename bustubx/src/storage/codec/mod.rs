@@ -2,6 +2,7 @@ mod common;
 mod freelist_page;
 mod index_page;
 mod meta_page;
+mod reader;
 mod scalar;
 mod table_page;
 mod tuple;
@@ -10,9 +11,338 @@ pub use common::CommonCodec;
 pub use freelist_page::{FreelistPageCodec, FreelistPageHeaderCodec};
 pub use index_page::*;
 pub use meta_page::MetaPageCodec;
+pub use reader::ByteReader;
 pub use scalar::ScalarValueCodec;
 pub use table_page::*;
 pub use tuple::TupleCodec;
 
+use crate::buffer::BUSTUBX_PAGE_SIZE;
+use crate::catalog::SchemaRef;
+use crate::storage::{
+    BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage, FreelistPage, MetaPage, TablePage,
+};
+use crate::BustubxResult;
+
 // data + consumed offset
 pub type DecodedData<T> = (T, usize);
+
+/// Codec of a whole page. Decoding must never panic on malformed bytes, it returns
+/// `BustubxError::Decode` with the offset where decoding failed instead.
+pub trait PageCodec {
+    type Page;
+    // extra information needed to decode the page, e.g. the schema of stored tuples
+    type Context;
+
+    /// Encode over the whole of `out`, e.g. the data of the frame holding the page.
+    fn encode_into(page: &Self::Page, out: &mut [u8; BUSTUBX_PAGE_SIZE]);
+
+    fn decode(bytes: &[u8], context: Self::Context) -> BustubxResult<DecodedData<Self::Page>>;
+}
+
+impl PageCodec for TablePageCodec {
+    type Page = TablePage;
+    type Context = SchemaRef;
+
+    fn encode_into(page: &TablePage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        TablePageCodec::encode_into(page, out)
+    }
+
+    fn decode(bytes: &[u8], schema: SchemaRef) -> BustubxResult<DecodedData<TablePage>> {
+        TablePageCodec::decode(bytes, schema)
+    }
+}
+
+impl PageCodec for BPlusTreePageCodec {
+    type Page = BPlusTreePage;
+    type Context = SchemaRef;
+
+    fn encode_into(page: &BPlusTreePage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        BPlusTreePageCodec::encode_into(page, out)
+    }
+
+    fn decode(bytes: &[u8], schema: SchemaRef) -> BustubxResult<DecodedData<BPlusTreePage>> {
+        BPlusTreePageCodec::decode(bytes, schema)
+    }
+}
+
+impl PageCodec for BPlusTreeLeafPageCodec {
+    type Page = BPlusTreeLeafPage;
+    type Context = SchemaRef;
+
+    fn encode_into(page: &BPlusTreeLeafPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        BPlusTreeLeafPageCodec::encode_into(page, out)
+    }
+
+    fn decode(bytes: &[u8], schema: SchemaRef) -> BustubxResult<DecodedData<BPlusTreeLeafPage>> {
+        BPlusTreeLeafPageCodec::decode(bytes, schema)
+    }
+}
+
+impl PageCodec for BPlusTreeInternalPageCodec {
+    type Page = BPlusTreeInternalPage;
+    type Context = SchemaRef;
+
+    fn encode_into(page: &BPlusTreeInternalPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        BPlusTreeInternalPageCodec::encode_into(page, out)
+    }
+
+    fn decode(
+        bytes: &[u8],
+        schema: SchemaRef,
+    ) -> BustubxResult<DecodedData<BPlusTreeInternalPage>> {
+        BPlusTreeInternalPageCodec::decode(bytes, schema)
+    }
+}
+
+impl PageCodec for FreelistPageCodec {
+    type Page = FreelistPage;
+    type Context = ();
+
+    fn encode_into(page: &FreelistPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        out.copy_from_slice(&FreelistPageCodec::encode(page))
+    }
+
+    fn decode(bytes: &[u8], _context: ()) -> BustubxResult<DecodedData<FreelistPage>> {
+        FreelistPageCodec::decode(bytes)
+    }
+}
+
+impl PageCodec for MetaPageCodec {
+    type Page = MetaPage;
+    type Context = ();
+
+    fn encode_into(page: &MetaPage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
+        let bytes = MetaPageCodec::encode(page);
+        out[..bytes.len()].copy_from_slice(&bytes);
+        out[bytes.len()..].fill(0);
+    }
+
+    fn decode(bytes: &[u8], _context: ()) -> BustubxResult<DecodedData<MetaPage>> {
+        MetaPageCodec::decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageCodec;
+    use crate::buffer::{BufferPoolManager, BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID};
+    use crate::catalog::{Column, DataType, Schema};
+    use crate::common::alloc_count::page_allocations;
+    use crate::storage::codec::{
+        BPlusTreeInternalPageCodec, BPlusTreeInternalPageHeaderCodec, BPlusTreeLeafPageCodec,
+        BPlusTreeLeafPageHeaderCodec, BPlusTreePageCodec, CommonCodec, FreelistPageCodec,
+        MetaPageCodec, RidCodec, TablePageCodec, TablePageHeaderCodec, TupleCodec,
+    };
+    use crate::storage::{
+        index::BPlusTreeIndex, BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage,
+        DiskManager, FreelistPage, MetaPage, RecordId, TableHeap, TablePage, EMPTY_TUPLE_META,
+    };
+    use crate::Tuple;
+    use std::sync::Arc;
+
+    // xorshift, good enough to generate garbage bytes
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn fuzz_decode<C: PageCodec>(valid: &[u8], context: impl Fn() -> C::Context) {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        assert!(C::decode(valid, context()).is_ok());
+
+        // bit flips
+        for _ in 0..500 {
+            let mut bytes = valid.to_vec();
+            for _ in 0..(rng.next() % 8 + 1) {
+                let bit = (rng.next() % (bytes.len() as u64 * 8)) as usize;
+                bytes[bit / 8] ^= 1 << (bit % 8);
+            }
+            let _ = C::decode(&bytes, context());
+        }
+        // truncated
+        for len in 0..valid.len().min(256) {
+            let _ = C::decode(&valid[..len], context());
+        }
+        // random
+        for _ in 0..100 {
+            let bytes = (0..valid.len())
+                .map(|_| rng.next() as u8)
+                .collect::<Vec<u8>>();
+            let _ = C::decode(&bytes, context());
+        }
+    }
+
+    #[test]
+    fn fuzz_page_codecs() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int8, true),
+            Column::new("b", DataType::Varchar(None), true),
+        ]));
+        let tuple1 = Tuple::new(schema.clone(), vec![1i8.into(), "aa".to_string().into()]);
+        let tuple2 = Tuple::new(schema.clone(), vec![2i8.into(), "bbbb".to_string().into()]);
+
+        let mut table_page = TablePage::new(schema.clone(), INVALID_PAGE_ID);
+        table_page.insert_tuple(&EMPTY_TUPLE_META, &tuple1).unwrap();
+        table_page.insert_tuple(&EMPTY_TUPLE_META, &tuple2).unwrap();
+        fuzz_decode::<TablePageCodec>(&TablePageCodec::encode(&table_page), || schema.clone());
+
+        let mut leaf_page = BPlusTreeLeafPage::new(schema.clone(), 10);
+        leaf_page.insert(tuple1.clone(), RecordId::new(1, 1));
+        leaf_page.insert(tuple2.clone(), RecordId::new(2, 2));
+        let leaf_bytes = BPlusTreeLeafPageCodec::encode(&leaf_page);
+        fuzz_decode::<BPlusTreeLeafPageCodec>(&leaf_bytes, || schema.clone());
+        fuzz_decode::<BPlusTreePageCodec>(&leaf_bytes, || schema.clone());
+
+        let mut internal_page = BPlusTreeInternalPage::new(schema.clone(), 10);
+        internal_page.insert(Tuple::empty(schema.clone()), 1);
+        internal_page.insert(tuple1, 2);
+        internal_page.insert(tuple2, 3);
+        let internal_bytes =
+            BPlusTreePageCodec::encode(&BPlusTreePage::Internal(internal_page.clone()));
+        fuzz_decode::<BPlusTreeInternalPageCodec>(&internal_bytes, || schema.clone());
+        fuzz_decode::<BPlusTreePageCodec>(&internal_bytes, || schema.clone());
+
+        let mut freelist_page = FreelistPage::new();
+        freelist_page.push(5);
+        freelist_page.push(6);
+        fuzz_decode::<FreelistPageCodec>(&FreelistPageCodec::encode(&freelist_page), || ());
+
+        let meta_page = MetaPage::try_new().unwrap();
+        fuzz_decode::<MetaPageCodec>(&MetaPageCodec::encode(&meta_page), || ());
+    }
+
+    #[test]
+    fn test_encode_into_matches_vec_encode() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int32, true),
+            Column::new("b", DataType::Varchar(None), true),
+        ]));
+        let tuples = (0..20i32)
+            .map(|i| Tuple::new(schema.clone(), vec![i.into(), format!("v{i}").into()]))
+            .collect::<Vec<_>>();
+        // a frame still holding the bytes of another page
+        let encode_into = |encode: &dyn Fn(&mut [u8; BUSTUBX_PAGE_SIZE])| {
+            let mut frame = [0xab; BUSTUBX_PAGE_SIZE];
+            encode(&mut frame);
+            frame.to_vec()
+        };
+
+        let mut table_page = TablePage::new(schema.clone(), 3);
+        for tuple in tuples.iter() {
+            table_page.insert_tuple(&EMPTY_TUPLE_META, tuple).unwrap();
+        }
+        let mut old_bytes = table_page.data.to_vec();
+        let header_bytes = TablePageHeaderCodec::encode(&table_page.header);
+        old_bytes[..header_bytes.len()].copy_from_slice(&header_bytes);
+        assert_eq!(TablePageCodec::encode(&table_page), old_bytes);
+        assert_eq!(
+            encode_into(&|frame| TablePageCodec::encode_into(&table_page, frame)),
+            old_bytes
+        );
+
+        let mut leaf_page = BPlusTreeLeafPage::new(schema.clone(), 50);
+        for (i, tuple) in tuples.iter().enumerate() {
+            leaf_page.insert(tuple.clone(), RecordId::new(i as u32, 0));
+        }
+        let mut old_bytes = BPlusTreeLeafPageHeaderCodec::encode(&leaf_page.header);
+        for (tuple, rid) in leaf_page.array.iter() {
+            old_bytes.extend(TupleCodec::encode(tuple));
+            old_bytes.extend(RidCodec::encode(rid));
+        }
+        old_bytes.resize(BUSTUBX_PAGE_SIZE, 0);
+        assert_eq!(BPlusTreeLeafPageCodec::encode(&leaf_page), old_bytes);
+        assert_eq!(
+            encode_into(&|frame| BPlusTreeLeafPageCodec::encode_into(&leaf_page, frame)),
+            old_bytes
+        );
+
+        let mut internal_page = BPlusTreeInternalPage::new(schema.clone(), 50);
+        internal_page.insert(Tuple::empty(schema.clone()), 1);
+        for (i, tuple) in tuples.iter().enumerate() {
+            internal_page.insert(tuple.clone(), i as u32 + 2);
+        }
+        let internal_page = BPlusTreePage::Internal(internal_page);
+        let BPlusTreePage::Internal(page) = &internal_page else {
+            unreachable!()
+        };
+        let mut old_bytes = BPlusTreeInternalPageHeaderCodec::encode(&page.header);
+        for (tuple, page_id) in page.array.iter() {
+            old_bytes.extend(TupleCodec::encode(tuple));
+            old_bytes.extend(CommonCodec::encode_u32(*page_id));
+        }
+        old_bytes.resize(BUSTUBX_PAGE_SIZE, 0);
+        assert_eq!(BPlusTreePageCodec::encode(&internal_page), old_bytes);
+        assert_eq!(
+            encode_into(&|frame| BPlusTreePageCodec::encode_into(&internal_page, frame)),
+            old_bytes
+        );
+
+        let mut freelist_page = FreelistPage::new();
+        freelist_page.push(7);
+        assert_eq!(
+            encode_into(&|frame| <FreelistPageCodec as PageCodec>::encode_into(
+                &freelist_page,
+                frame
+            )),
+            FreelistPageCodec::encode(&freelist_page)
+        );
+
+        let meta_page = MetaPage::try_new().unwrap();
+        let mut old_bytes = MetaPageCodec::encode(&meta_page);
+        old_bytes.resize(BUSTUBX_PAGE_SIZE, 0);
+        let bytes = encode_into(&|frame| MetaPageCodec::encode_into(&meta_page, frame));
+        assert_eq!(bytes, old_bytes);
+        assert_eq!(
+            <MetaPageCodec as PageCodec>::decode(&bytes, ()).unwrap().0,
+            meta_page
+        );
+    }
+
+    #[test]
+    fn test_inserts_encode_in_place() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int32, false),
+            Column::new("b", DataType::Varchar(None), true),
+        ]));
+        let key_schema = Arc::new(schema.project(&[0]).unwrap());
+        let table_heap = TableHeap::try_new(schema.clone(), buffer_pool.clone()).unwrap();
+        // leaves small enough that decoding one allocates less than a page
+        let index = BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 32, 32);
+        // few enough rows per page that decoding its header allocates less than a page
+        let rows = (0..1000i32)
+            .map(|i| {
+                (
+                    Tuple::new(schema.clone(), vec![i.into(), format!("{i:0>100}").into()]),
+                    Tuple::new(key_schema.clone(), vec![i.into()]),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let before = page_allocations();
+        for (row, key) in rows.iter() {
+            let rid = table_heap.insert_tuple(&EMPTY_TUPLE_META, row).unwrap();
+            index.insert(key, rid).unwrap();
+        }
+        // encoding to a Vec allocated a page for every index insert and, in debug builds,
+        // two more for every row patched into the heap
+        let in_place = page_allocations() - before;
+        assert!(in_place < 50, "{in_place} page sized allocations");
+
+        let before = page_allocations();
+        let _ = TablePageCodec::encode(&TablePage::new(schema.clone(), INVALID_PAGE_ID));
+        assert!(page_allocations() > before);
+
+        for (row, key) in rows.iter() {
+            let rid = index.get(key).unwrap().unwrap();
+            assert_eq!(table_heap.tuple(rid).unwrap().data, row.data);
+        }
+    }
+}
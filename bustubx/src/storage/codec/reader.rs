@@ -0,0 +1,106 @@
+use crate::storage::codec::DecodedData;
+use crate::{BustubxError, BustubxResult};
+
+/// Bounds-checked cursor over encoded bytes, decode errors carry the offset they happened at.
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    // consumed bytes
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.offset..]
+    }
+
+    pub fn take(&mut self, len: usize) -> BustubxResult<&'a [u8]> {
+        let remaining = self.remaining();
+        if remaining.len() < len {
+            return Err(self.error(format!(
+                "bytes length {} is less than {}",
+                remaining.len(),
+                len
+            )));
+        }
+        self.offset += len;
+        Ok(&remaining[..len])
+    }
+
+    pub fn read<T>(
+        &mut self,
+        decode: impl FnOnce(&'a [u8]) -> BustubxResult<DecodedData<T>>,
+    ) -> BustubxResult<T> {
+        let (data, consumed) = self.peek_with_len(decode)?;
+        self.offset += consumed;
+        Ok(data)
+    }
+
+    /// Decode without consuming bytes
+    pub fn peek<T>(
+        &self,
+        decode: impl FnOnce(&'a [u8]) -> BustubxResult<DecodedData<T>>,
+    ) -> BustubxResult<T> {
+        Ok(self.peek_with_len(decode)?.0)
+    }
+
+    fn peek_with_len<T>(
+        &self,
+        decode: impl FnOnce(&'a [u8]) -> BustubxResult<DecodedData<T>>,
+    ) -> BustubxResult<DecodedData<T>> {
+        let remaining = self.remaining();
+        let (data, consumed) = decode(remaining).map_err(|e| match e {
+            BustubxError::Decode { offset, message } => BustubxError::Decode {
+                offset: self.offset + offset,
+                message,
+            },
+            e => self.error(e.to_string()),
+        })?;
+        if consumed > remaining.len() {
+            return Err(self.error(format!(
+                "consumed {} bytes but only {} left",
+                consumed,
+                remaining.len()
+            )));
+        }
+        Ok((data, consumed))
+    }
+
+    fn error(&self, message: String) -> BustubxError {
+        BustubxError::Decode {
+            offset: self.offset,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::codec::{ByteReader, CommonCodec};
+    use crate::BustubxError;
+
+    #[test]
+    fn byte_reader() {
+        let bytes = [CommonCodec::encode_u32(7), CommonCodec::encode_u16(9)].concat();
+        let mut reader = ByteReader::new(&bytes);
+        assert_eq!(reader.peek(CommonCodec::decode_u32).unwrap(), 7);
+        assert_eq!(reader.read(CommonCodec::decode_u32).unwrap(), 7);
+        assert_eq!(reader.offset(), 4);
+        assert!(matches!(
+            reader.read(CommonCodec::decode_u32),
+            Err(BustubxError::Decode { offset: 4, .. })
+        ));
+        assert_eq!(reader.read(CommonCodec::decode_u16).unwrap(), 9);
+        assert!(matches!(
+            reader.take(1),
+            Err(BustubxError::Decode { offset: 6, .. })
+        ));
+    }
+}
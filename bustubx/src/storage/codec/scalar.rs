@@ -1,6 +1,6 @@
 use crate::catalog::DataType;
 use crate::common::ScalarValue;
-use crate::storage::codec::{CommonCodec, DecodedData};
+use crate::storage::codec::{ByteReader, CommonCodec, DecodedData};
 use crate::BustubxResult;
 
 pub struct ScalarValueCodec;
@@ -19,15 +19,8 @@ impl ScalarValueCodec {
             ScalarValue::UInt64(Some(v)) => CommonCodec::encode_u64(*v),
             ScalarValue::Float32(Some(v)) => CommonCodec::encode_f32(*v),
             ScalarValue::Float64(Some(v)) => CommonCodec::encode_f64(*v),
-            ScalarValue::Varchar(Some(v)) => {
-                if v.len() > u16::MAX as usize {
-                    panic!("Varchar length is greater than u16::Max")
-                }
-                let mut bytes = vec![];
-                bytes.extend(CommonCodec::encode_u16(v.len() as u16));
-                bytes.extend(CommonCodec::encode_string(v));
-                bytes
-            }
+            ScalarValue::Varchar(Some(v)) => Self::encode_varlen(&CommonCodec::encode_string(v)),
+            ScalarValue::Bytea(Some(v)) => Self::encode_varlen(v),
             // null
             ScalarValue::Boolean(None)
             | ScalarValue::Int8(None)
@@ -40,7 +33,8 @@ impl ScalarValueCodec {
             | ScalarValue::UInt64(None)
             | ScalarValue::Float32(None)
             | ScalarValue::Float64(None)
-            | ScalarValue::Varchar(None) => vec![],
+            | ScalarValue::Varchar(None)
+            | ScalarValue::Bytea(None) => vec![],
         }
     }
 
@@ -91,19 +85,32 @@ impl ScalarValueCodec {
                 Ok((ScalarValue::Float64(Some(value)), offset))
             }
             DataType::Varchar(_) => {
-                let mut left_bytes = bytes;
-
-                let (length, offset) = CommonCodec::decode_u16(left_bytes)?;
-                left_bytes = &left_bytes[offset..];
-
-                let (value, offset) = CommonCodec::decode_string(&left_bytes[0..length as usize])?;
-                left_bytes = &left_bytes[offset..];
-
-                Ok((
-                    ScalarValue::Varchar(Some(value)),
-                    bytes.len() - left_bytes.len(),
-                ))
+                let (value_bytes, offset) = Self::decode_varlen(bytes)?;
+                let (value, _) = CommonCodec::decode_string(value_bytes)?;
+                Ok((ScalarValue::Varchar(Some(value)), offset))
+            }
+            DataType::Bytea => {
+                let (value_bytes, offset) = Self::decode_varlen(bytes)?;
+                Ok((ScalarValue::Bytea(Some(value_bytes.to_vec())), offset))
             }
         }
     }
+
+    // u16 length followed by the bytes of a variable length value
+    fn encode_varlen(data: &[u8]) -> Vec<u8> {
+        if data.len() > u16::MAX as usize {
+            panic!("Variable length value is greater than u16::Max")
+        }
+        let mut bytes = vec![];
+        bytes.extend(CommonCodec::encode_u16(data.len() as u16));
+        bytes.extend(data);
+        bytes
+    }
+
+    fn decode_varlen(bytes: &[u8]) -> BustubxResult<DecodedData<&[u8]>> {
+        let mut reader = ByteReader::new(bytes);
+        let length = reader.read(CommonCodec::decode_u16)?;
+        let value_bytes = reader.take(length as usize)?;
+        Ok((value_bytes, reader.offset()))
+    }
 }
@@ -1,18 +1,25 @@
 use crate::buffer::BUSTUBX_PAGE_SIZE;
 use crate::catalog::SchemaRef;
 use crate::common::util::page_bytes_to_array;
-use crate::storage::codec::{CommonCodec, DecodedData};
+use crate::storage::codec::{ByteReader, CommonCodec, DecodedData, TupleCodec};
 use crate::storage::{RecordId, TablePage, TablePageHeader, TupleInfo, TupleMeta};
 use crate::{BustubxError, BustubxResult};
 
 pub struct TablePageCodec;
 
 impl TablePageCodec {
+    #[cfg(test)]
     pub fn encode(page: &TablePage) -> Vec<u8> {
+        let mut bytes = [0; BUSTUBX_PAGE_SIZE];
+        Self::encode_into(page, &mut bytes);
+        bytes.to_vec()
+    }
+
+    /// Encode over the whole of `out`, e.g. the data of the frame holding the page.
+    pub fn encode_into(page: &TablePage, out: &mut [u8; BUSTUBX_PAGE_SIZE]) {
         let header_bytes = TablePageHeaderCodec::encode(&page.header);
-        let mut all_bytes = page.data;
-        all_bytes[0..header_bytes.len()].copy_from_slice(&header_bytes);
-        all_bytes.to_vec()
+        out.copy_from_slice(&page.data);
+        out[0..header_bytes.len()].copy_from_slice(&header_bytes);
     }
 
     pub fn decode(bytes: &[u8], schema: SchemaRef) -> BustubxResult<DecodedData<TablePage>> {
@@ -23,7 +30,10 @@ impl TablePageCodec {
                 bytes.len()
             )));
         }
-        let (header, _) = TablePageHeaderCodec::decode(bytes)?;
+        let (mut header, _) = TablePageHeaderCodec::decode(bytes)?;
+        TupleCodec::check_schema_fingerprint(&schema, header.schema_fingerprint)?;
+        // an older page gets the fingerprint on its next write
+        header.schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
         Ok((
             TablePage {
                 schema,
@@ -35,50 +45,170 @@ impl TablePageCodec {
     }
 }
 
+// Set in the encoded tuple count of headers carrying the live tuple count. Slot counts
+// never come near it, so pages written before the field existed have it clear.
+const LIVE_TUPLES_FLAG: u16 = 1 << 15;
+// Set along with the live tuple flag in headers carrying the schema fingerprint after the
+// live tuple count.
+const SCHEMA_FINGERPRINT_FLAG: u16 = 1 << 14;
+
+/// Offset of the tuple counts in an encoded header, after the next page id.
+pub const TABLE_PAGE_COUNTS_OFFSET: usize = 4;
+
+/// Counts and tuple info positions of an encoded header carrying the live tuple count and
+/// the schema fingerprint, read by [`TablePageHeaderCodec::layout`] without decoding the
+/// tuple infos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TablePageHeaderLayout {
+    pub num_tuples: u16,
+    pub num_deleted_tuples: u16,
+    pub live_tuples: u16,
+    pub schema_fingerprint: u32,
+    // start of every tuple info, then the end of the header
+    pub tuple_info_offsets: Vec<usize>,
+}
+
 pub struct TablePageHeaderCodec;
 
 impl TablePageHeaderCodec {
     pub fn encode(header: &TablePageHeader) -> Vec<u8> {
+        let mut tuple_info_bytes = Vec::new();
+        for tuple_info in header.tuple_infos.iter() {
+            tuple_info_bytes.extend(TablePageHeaderTupleInfoCodec::encode(tuple_info));
+        }
+        // a page filled up under the old layout may have no room left for the count
+        let tuples_start = header
+            .tuple_infos
+            .iter()
+            .map(|info| info.offset as usize)
+            .min()
+            .unwrap_or(BUSTUBX_PAGE_SIZE);
+        let fits = |header_len: usize| header_len + tuple_info_bytes.len() <= tuples_start;
+        let schema_fingerprint = header.schema_fingerprint.filter(|_| fits(14));
+
         let mut bytes = Vec::new();
         bytes.extend(CommonCodec::encode_u32(header.next_page_id));
-        bytes.extend(CommonCodec::encode_u16(header.num_tuples));
-        bytes.extend(CommonCodec::encode_u16(header.num_deleted_tuples));
-        for tuple_info in header.tuple_infos.iter() {
-            bytes.extend(TablePageHeaderTupleInfoCodec::encode(tuple_info));
+        if let Some(schema_fingerprint) = schema_fingerprint {
+            bytes.extend(Self::encode_counts(
+                header.num_tuples,
+                header.num_deleted_tuples,
+                header.live_tuples,
+            ));
+            bytes.extend(CommonCodec::encode_u32(schema_fingerprint));
+        } else if fits(10) {
+            bytes.extend(CommonCodec::encode_u16(
+                header.num_tuples | LIVE_TUPLES_FLAG,
+            ));
+            bytes.extend(CommonCodec::encode_u16(header.num_deleted_tuples));
+            bytes.extend(CommonCodec::encode_u16(header.live_tuples));
+        } else {
+            bytes.extend(CommonCodec::encode_u16(header.num_tuples));
+            bytes.extend(CommonCodec::encode_u16(header.num_deleted_tuples));
         }
+        bytes.extend(tuple_info_bytes);
+        bytes
+    }
+
+    /// The tuple counts as written at [`TABLE_PAGE_COUNTS_OFFSET`] of a header carrying the
+    /// live tuple count and the schema fingerprint.
+    pub fn encode_counts(num_tuples: u16, num_deleted_tuples: u16, live_tuples: u16) -> Vec<u8> {
+        let mut bytes =
+            CommonCodec::encode_u16(num_tuples | LIVE_TUPLES_FLAG | SCHEMA_FINGERPRINT_FLAG);
+        bytes.extend(CommonCodec::encode_u16(num_deleted_tuples));
+        bytes.extend(CommonCodec::encode_u16(live_tuples));
         bytes
     }
 
+    /// None for a header without the live tuple count or the schema fingerprint, the next
+    /// write of its page changes the layout of the header.
+    pub fn layout(bytes: &[u8]) -> BustubxResult<Option<TablePageHeaderLayout>> {
+        let mut reader = ByteReader::new(bytes);
+        reader.take(TABLE_PAGE_COUNTS_OFFSET)?;
+        let encoded_num_tuples = reader.read(CommonCodec::decode_u16)?;
+        if encoded_num_tuples & LIVE_TUPLES_FLAG == 0
+            || encoded_num_tuples & SCHEMA_FINGERPRINT_FLAG == 0
+        {
+            return Ok(None);
+        }
+        let num_tuples = encoded_num_tuples & !(LIVE_TUPLES_FLAG | SCHEMA_FINGERPRINT_FLAG);
+        let num_deleted_tuples = reader.read(CommonCodec::decode_u16)?;
+        let live_tuples = reader.read(CommonCodec::decode_u16)?;
+        let schema_fingerprint = reader.read(CommonCodec::decode_u32)?;
+
+        let mut tuple_info_offsets = Vec::with_capacity(num_tuples as usize + 1);
+        for _ in 0..num_tuples {
+            tuple_info_offsets.push(reader.offset());
+            // offset, size and transaction ids ahead of the flags
+            reader.take(20)?;
+            let flags = reader.read(CommonCodec::decode_u8)?;
+            if flags & TUPLE_ROW_ID_FLAG != 0 {
+                reader.take(8)?;
+            }
+        }
+        tuple_info_offsets.push(reader.offset());
+        Ok(Some(TablePageHeaderLayout {
+            num_tuples,
+            num_deleted_tuples,
+            live_tuples,
+            schema_fingerprint,
+            tuple_info_offsets,
+        }))
+    }
+
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<TablePageHeader>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (next_page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let next_page_id = reader.read(CommonCodec::decode_u32)?;
 
-        let (num_tuples, offset) = CommonCodec::decode_u16(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let encoded_num_tuples = reader.read(CommonCodec::decode_u16)?;
+        let num_tuples = encoded_num_tuples & !(LIVE_TUPLES_FLAG | SCHEMA_FINGERPRINT_FLAG);
 
-        let (num_deleted_tuples, offset) = CommonCodec::decode_u16(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let num_deleted_tuples = reader.read(CommonCodec::decode_u16)?;
+
+        let stored_live_tuples = if encoded_num_tuples & LIVE_TUPLES_FLAG != 0 {
+            Some(reader.read(CommonCodec::decode_u16)?)
+        } else {
+            None
+        };
+        let schema_fingerprint = if encoded_num_tuples & LIVE_TUPLES_FLAG != 0
+            && encoded_num_tuples & SCHEMA_FINGERPRINT_FLAG != 0
+        {
+            Some(reader.read(CommonCodec::decode_u32)?)
+        } else {
+            None
+        };
 
         let mut tuple_infos = vec![];
         for _ in 0..num_tuples {
-            let (tuple_info, offset) = TablePageHeaderTupleInfoCodec::decode(left_bytes)?;
-            left_bytes = &left_bytes[offset..];
+            let tuple_info = reader.read(TablePageHeaderTupleInfoCodec::decode)?;
             tuple_infos.push(tuple_info);
         }
+        // older pages get the count from the tuple metas, it is stored on the next write
+        let live_tuples = stored_live_tuples.unwrap_or_else(|| {
+            tuple_infos
+                .iter()
+                .filter(|info| !info.meta.is_deleted)
+                .count() as u16
+        });
         Ok((
             TablePageHeader {
                 next_page_id,
                 num_tuples,
                 num_deleted_tuples,
+                live_tuples,
+                schema_fingerprint,
                 tuple_infos,
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
 
+// Bits of the byte ending a tuple info, it used to hold only the deleted flag. A logical
+// row id follows the byte when its bit is set.
+const TUPLE_DELETED_FLAG: u8 = 1;
+const TUPLE_ROW_ID_FLAG: u8 = 1 << 1;
+
 pub struct TablePageHeaderTupleInfoCodec;
 
 impl TablePageHeaderTupleInfoCodec {
@@ -88,22 +218,32 @@ impl TablePageHeaderTupleInfoCodec {
         bytes.extend(CommonCodec::encode_u16(tuple_info.size));
         bytes.extend(CommonCodec::encode_u64(tuple_info.meta.insert_txn_id));
         bytes.extend(CommonCodec::encode_u64(tuple_info.meta.delete_txn_id));
-        bytes.extend(CommonCodec::encode_bool(tuple_info.meta.is_deleted));
+        let mut flags = 0;
+        if tuple_info.meta.is_deleted {
+            flags |= TUPLE_DELETED_FLAG;
+        }
+        if tuple_info.meta.row_id.is_some() {
+            flags |= TUPLE_ROW_ID_FLAG;
+        }
+        bytes.extend(CommonCodec::encode_u8(flags));
+        if let Some(row_id) = tuple_info.meta.row_id {
+            bytes.extend(CommonCodec::encode_u64(row_id));
+        }
         bytes
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<TupleInfo>> {
-        let mut left_bytes = bytes;
-        let (tuple_offset, offset) = CommonCodec::decode_u16(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (size, offset) = CommonCodec::decode_u16(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (insert_txn_id, offset) = CommonCodec::decode_u64(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (delete_txn_id, offset) = CommonCodec::decode_u64(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
-        let (is_deleted, offset) = CommonCodec::decode_bool(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let mut reader = ByteReader::new(bytes);
+        let tuple_offset = reader.read(CommonCodec::decode_u16)?;
+        let size = reader.read(CommonCodec::decode_u16)?;
+        let insert_txn_id = reader.read(CommonCodec::decode_u64)?;
+        let delete_txn_id = reader.read(CommonCodec::decode_u64)?;
+        let flags = reader.read(CommonCodec::decode_u8)?;
+        let row_id = if flags & TUPLE_ROW_ID_FLAG != 0 {
+            Some(reader.read(CommonCodec::decode_u64)?)
+        } else {
+            None
+        };
         Ok((
             TupleInfo {
                 offset: tuple_offset,
@@ -111,10 +251,11 @@ impl TablePageHeaderTupleInfoCodec {
                 meta: TupleMeta {
                     insert_txn_id,
                     delete_txn_id,
-                    is_deleted,
+                    is_deleted: flags & TUPLE_DELETED_FLAG != 0,
+                    row_id,
                 },
             },
-            bytes.len() - left_bytes.len(),
+            reader.offset(),
         ))
     }
 }
@@ -130,18 +271,13 @@ impl RidCodec {
     }
 
     pub fn decode(bytes: &[u8]) -> BustubxResult<DecodedData<RecordId>> {
-        let mut left_bytes = bytes;
+        let mut reader = ByteReader::new(bytes);
 
-        let (page_id, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let page_id = reader.read(CommonCodec::decode_u32)?;
 
-        let (slot_num, offset) = CommonCodec::decode_u32(left_bytes)?;
-        left_bytes = &left_bytes[offset..];
+        let slot_num = reader.read(CommonCodec::decode_u32)?;
 
-        Ok((
-            RecordId::new(page_id, slot_num),
-            bytes.len() - left_bytes.len(),
-        ))
+        Ok((RecordId::new(page_id, slot_num), reader.offset()))
     }
 }
 
@@ -149,10 +285,10 @@ impl RidCodec {
 mod tests {
     use crate::buffer::INVALID_PAGE_ID;
     use crate::catalog::{Column, DataType, Schema};
-    use crate::storage::codec::table_page::TablePageHeaderCodec;
-    use crate::storage::codec::TablePageCodec;
+    use crate::storage::codec::table_page::{TablePageHeaderCodec, TablePageHeaderTupleInfoCodec};
+    use crate::storage::codec::{CommonCodec, TablePageCodec, TupleCodec};
     use crate::storage::{TablePage, TupleMeta};
-    use crate::Tuple;
+    use crate::{BustubxError, Tuple};
     use std::sync::Arc;
 
     #[test]
@@ -166,12 +302,14 @@ mod tests {
             insert_txn_id: 1,
             delete_txn_id: 2,
             is_deleted: false,
+            row_id: None,
         };
         let tuple2 = Tuple::new(schema.clone(), vec![2i8.into(), 2i32.into()]);
         let tuple2_meta = TupleMeta {
             insert_txn_id: 3,
             delete_txn_id: 4,
             is_deleted: true,
+            row_id: Some(u64::MAX - 1),
         };
 
         let mut table_page = TablePage::new(schema.clone(), INVALID_PAGE_ID);
@@ -185,4 +323,81 @@ mod tests {
         let header_size = TablePageHeaderCodec::encode(&table_page.header).len();
         assert_eq!(new_page.data[header_size..], table_page.data[header_size..]);
     }
+
+    #[test]
+    fn table_page_codec_old_header() {
+        let schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, true)]));
+        let mut table_page = TablePage::new(schema.clone(), 7);
+        for (i, is_deleted) in [false, true, false].into_iter().enumerate() {
+            let meta = TupleMeta {
+                insert_txn_id: 0,
+                delete_txn_id: 0,
+                is_deleted,
+                row_id: None,
+            };
+            table_page
+                .insert_tuple(&meta, &Tuple::new(schema.clone(), vec![(i as i32).into()]))
+                .unwrap();
+        }
+        assert_eq!(table_page.header.live_tuples, 2);
+
+        // header written before the live tuple count existed, tuple data stays in place
+        let mut old_header = vec![];
+        old_header.extend(CommonCodec::encode_u32(7));
+        old_header.extend(CommonCodec::encode_u16(3));
+        old_header.extend(CommonCodec::encode_u16(1));
+        for tuple_info in table_page.header.tuple_infos.iter() {
+            old_header.extend(TablePageHeaderTupleInfoCodec::encode(tuple_info));
+        }
+        let mut bytes = TablePageCodec::encode(&table_page);
+        let header_size = TablePageHeaderCodec::encode(&table_page.header).len();
+        bytes[..header_size].fill(0);
+        bytes[..old_header.len()].copy_from_slice(&old_header);
+
+        let (old_page, _) = TablePageCodec::decode(&bytes, schema.clone()).unwrap();
+        assert_eq!(old_page.header, table_page.header);
+        assert_eq!(old_page.tuple(2).unwrap().1, table_page.tuple(2).unwrap().1);
+
+        // written back in the new format
+        let (new_page, _) =
+            TablePageCodec::decode(&TablePageCodec::encode(&old_page), schema).unwrap();
+        assert_eq!(new_page.header, table_page.header);
+    }
+
+    #[test]
+    fn table_page_codec_schema_fingerprint() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int32, true),
+            Column::new("b", DataType::Varchar(Some(10)), true),
+        ]));
+        let tuple = Tuple::new(schema.clone(), vec![1i32.into(), "aa".to_string().into()]);
+        let mut table_page = TablePage::new(schema.clone(), INVALID_PAGE_ID);
+        table_page
+            .insert_tuple(&crate::storage::EMPTY_TUPLE_META, &tuple)
+            .unwrap();
+        let bytes = TablePageCodec::encode(&table_page);
+
+        // the column types changed, the stored tuples would decode to garbage
+        let other_schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int64, true),
+            Column::new("b", DataType::Varchar(Some(10)), true),
+        ]));
+        match TablePageCodec::decode(&bytes, other_schema.clone()) {
+            Err(BustubxError::SchemaMismatch { expected, found }) => {
+                assert_eq!(expected, TupleCodec::schema_fingerprint(&other_schema));
+                assert_eq!(found, TupleCodec::schema_fingerprint(&schema));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(TablePageCodec::decode(&bytes, Arc::new(Schema::empty())).is_err());
+
+        // renamed columns, another nullability and a longer varchar keep the encoding
+        let evolved_schema = Arc::new(Schema::new(vec![
+            Column::new("id", DataType::Int32, false),
+            Column::new("name", DataType::Varchar(Some(20)), false),
+        ]));
+        let (page, _) = TablePageCodec::decode(&bytes, evolved_schema.clone()).unwrap();
+        assert_eq!(page.header, table_page.header);
+        assert_eq!(page.tuple(0).unwrap().1.data, tuple.data);
+    }
 }
@@ -1,6 +1,6 @@
-use crate::catalog::SchemaRef;
+use crate::catalog::{DataType, Schema, SchemaRef};
 use crate::common::{DynamicBitmap, ScalarValue};
-use crate::storage::codec::{DecodedData, ScalarValueCodec};
+use crate::storage::codec::{ByteReader, DecodedData, ScalarValueCodec};
 use crate::{BustubxError, BustubxResult, Tuple};
 
 pub struct TupleCodec;
@@ -23,12 +23,10 @@ impl TupleCodec {
     }
 
     pub fn decode(bytes: &[u8], schema: SchemaRef) -> BustubxResult<DecodedData<Tuple>> {
-        let mut total_offset = 0;
+        let mut reader = ByteReader::new(bytes);
 
         let null_map_bytes = schema.column_count().div_ceil(8);
-        let null_map = DynamicBitmap::from_bytes(&bytes[0..null_map_bytes]);
-        total_offset += null_map_bytes;
-        let mut bytes = &bytes[null_map_bytes..];
+        let null_map = DynamicBitmap::from_bytes(reader.take(null_map_bytes)?);
 
         let mut data = vec![];
         for (idx, col) in schema.columns.iter().enumerate() {
@@ -38,14 +36,53 @@ impl TupleCodec {
             if null {
                 data.push(ScalarValue::new_empty(col.data_type));
             } else {
-                let (value, offset) = ScalarValueCodec::decode(bytes, col.data_type)?;
+                let value = reader.read(|bytes| ScalarValueCodec::decode(bytes, col.data_type))?;
                 data.push(value);
-                total_offset += offset;
-                bytes = &bytes[offset..];
             }
         }
 
-        Ok((Tuple::new(schema, data), total_offset))
+        Ok((Tuple::new(schema, data), reader.offset()))
+    }
+
+    /// Fingerprint of how tuples of `schema` are encoded, stored in page headers to catch
+    /// pages decoded with the wrong schema. Only the encodings of the columns in order go
+    /// into it, renaming a column or changing its nullability, default or varchar length
+    /// leaves the stored tuples readable and the fingerprint as it was.
+    pub fn schema_fingerprint(schema: &Schema) -> u32 {
+        // FNV-1a over one tag per column, stable across builds unlike the std hashers
+        let mut hash: u32 = 0x811c9dc5;
+        for column in schema.columns.iter() {
+            let tag: u8 = match column.data_type {
+                DataType::Boolean => 1,
+                DataType::Int8 => 2,
+                DataType::Int16 => 3,
+                DataType::Int32 => 4,
+                DataType::Int64 => 5,
+                DataType::UInt8 => 6,
+                DataType::UInt16 => 7,
+                DataType::UInt32 => 8,
+                DataType::UInt64 => 9,
+                DataType::Float32 => 10,
+                DataType::Float64 => 11,
+                DataType::Varchar(_) => 12,
+                DataType::Bytea => 13,
+            };
+            hash ^= tag as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+        hash
+    }
+
+    /// Err unless a page header written with the fingerprint `found` can be decoded with
+    /// `schema`, pages written before the fingerprint existed have none.
+    pub fn check_schema_fingerprint(schema: &Schema, found: Option<u32>) -> BustubxResult<()> {
+        let expected = Self::schema_fingerprint(schema);
+        match found {
+            Some(found) if found != expected => {
+                Err(BustubxError::SchemaMismatch { expected, found })
+            }
+            _ => Ok(()),
+        }
     }
 }
 
@@ -64,6 +101,8 @@ mod tests {
             Column::new("b", DataType::Int32, true),
             Column::new("c", DataType::UInt64, true),
             Column::new("d", DataType::Varchar(None), true),
+            Column::new("e", DataType::Bytea, true),
+            Column::new("f", DataType::Bytea, true),
         ]));
         let tuple = Tuple::new(
             schema.clone(),
@@ -72,6 +111,8 @@ mod tests {
                 ScalarValue::Int32(None),
                 1234u64.into(),
                 "aabb".to_string().into(),
+                vec![0xde, 0xad, 0x00, 0xff].into(),
+                Vec::<u8>::new().into(),
             ],
         );
         let new_tuple = TupleCodec::decode(&TupleCodec::encode(&tuple), schema)
@@ -79,4 +120,55 @@ mod tests {
             .0;
         assert_eq!(new_tuple, tuple);
     }
+
+    #[test]
+    fn tuple_codec_integer_limits() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int8, true),
+            Column::new("b", DataType::Int16, true),
+            Column::new("c", DataType::Int32, true),
+            Column::new("d", DataType::Int64, true),
+            Column::new("e", DataType::UInt8, true),
+            Column::new("f", DataType::UInt16, true),
+            Column::new("g", DataType::UInt32, true),
+            Column::new("h", DataType::UInt64, true),
+        ]));
+        for values in [
+            vec![
+                i8::MIN.into(),
+                i16::MIN.into(),
+                i32::MIN.into(),
+                i64::MIN.into(),
+                u8::MIN.into(),
+                u16::MIN.into(),
+                u32::MIN.into(),
+                u64::MIN.into(),
+            ],
+            vec![
+                i8::MAX.into(),
+                i16::MAX.into(),
+                i32::MAX.into(),
+                i64::MAX.into(),
+                u8::MAX.into(),
+                u16::MAX.into(),
+                u32::MAX.into(),
+                u64::MAX.into(),
+            ],
+            vec![
+                (-1i8).into(),
+                (-1i16).into(),
+                (-1i32).into(),
+                (1i64 << 32).into(),
+                ScalarValue::UInt8(None),
+                ScalarValue::UInt16(None),
+                (1u32 << 31).into(),
+                (1u64 << 63).into(),
+            ],
+        ] {
+            let tuple = Tuple::new(schema.clone(), values);
+            let (new_tuple, _) =
+                TupleCodec::decode(&TupleCodec::encode(&tuple), schema.clone()).unwrap();
+            assert_eq!(new_tuple.data, tuple.data);
+        }
+    }
 }
@@ -0,0 +1,269 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::storage::codec::{ByteReader, CommonCodec};
+use crate::{BustubxError, BustubxResult};
+
+// codec byte and uncompressed size in front of every compressed block
+const BLOCK_HEADER_SIZE: usize = 5;
+// shortest match the LZ codec encodes, and the longest one fitting its op byte
+const LZ_MIN_MATCH: usize = 4;
+const LZ_MAX_MATCH: usize = LZ_MIN_MATCH + 0x7f;
+const LZ_MAX_LITERALS: usize = 0x80;
+const LZ_MAX_OFFSET: usize = u16::MAX as usize;
+const LZ_HASH_BITS: u32 = 14;
+
+/// Codec of the replication log segments and the spilled sort runs. The pages of the db
+/// file are never compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionCodec {
+    #[default]
+    None,
+    // built in LZ77 style codec, no dependency needed
+    Lz,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl CompressionCodec {
+    fn tag(&self) -> u8 {
+        match self {
+            CompressionCodec::None => 0,
+            CompressionCodec::Lz => 1,
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> BustubxResult<Self> {
+        match tag {
+            0 => Ok(CompressionCodec::None),
+            1 => Ok(CompressionCodec::Lz),
+            #[cfg(feature = "zstd")]
+            2 => Ok(CompressionCodec::Zstd),
+            _ => Err(BustubxError::Storage(format!(
+                "unknown compression codec {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// Compresses blocks with the configured codec and counts the bytes going in and out.
+///
+/// Every block starts with a header naming its codec and uncompressed size, so
+/// [`Compressor::decompress`] needs no configuration and reads blocks written with any
+/// codec. A block the codec does not shrink is kept as is.
+#[derive(Debug, Default)]
+pub struct Compressor {
+    codec: CompressionCodec,
+    uncompressed_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+}
+
+impl Compressor {
+    pub fn new(codec: CompressionCodec) -> Self {
+        Self {
+            codec,
+            ..Default::default()
+        }
+    }
+
+    pub fn codec(&self) -> CompressionCodec {
+        self.codec
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.codec != CompressionCodec::None
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let compressed = match self.codec {
+            CompressionCodec::None => None,
+            CompressionCodec::Lz => Some(lz_compress(data)),
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => zstd::bulk::compress(data, 0).ok(),
+        };
+        let (codec, payload) = match compressed {
+            Some(compressed) if compressed.len() < data.len() => (self.codec, compressed),
+            _ => (CompressionCodec::None, data.to_vec()),
+        };
+        let mut block = Vec::with_capacity(BLOCK_HEADER_SIZE + payload.len());
+        block.push(codec.tag());
+        block.extend(CommonCodec::encode_u32(data.len() as u32));
+        block.extend(payload);
+        self.uncompressed_bytes
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.compressed_bytes
+            .fetch_add(block.len() as u64, Ordering::Relaxed);
+        block
+    }
+
+    pub fn decompress(block: &[u8]) -> BustubxResult<Vec<u8>> {
+        let mut reader = ByteReader::new(block);
+        let codec = CompressionCodec::from_tag(reader.read(CommonCodec::decode_u8)?)?;
+        let len = reader.read(CommonCodec::decode_u32)? as usize;
+        let payload = &block[reader.offset()..];
+        let data = match codec {
+            CompressionCodec::None => payload.to_vec(),
+            CompressionCodec::Lz => lz_decompress(payload, len)?,
+            #[cfg(feature = "zstd")]
+            CompressionCodec::Zstd => zstd::bulk::decompress(payload, len)?,
+        };
+        if data.len() != len {
+            return Err(BustubxError::Storage(format!(
+                "compressed block holds {} bytes instead of {}",
+                data.len(),
+                len
+            )));
+        }
+        Ok(data)
+    }
+
+    /// (uncompressed, compressed) bytes of the blocks written so far, headers included in
+    /// the compressed ones.
+    pub fn byte_counts(&self) -> (u64, u64) {
+        (
+            self.uncompressed_bytes.load(Ordering::Relaxed),
+            self.compressed_bytes.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn reset_stats(&self) {
+        self.uncompressed_bytes.store(0, Ordering::Relaxed);
+        self.compressed_bytes.store(0, Ordering::Relaxed);
+    }
+}
+
+// Ops of the LZ codec: 0x00..=0x7f copies the next op + 1 bytes, 0x80..=0xff repeats
+// op - 0x80 + LZ_MIN_MATCH bytes starting the big endian u16 offset that follows back
+fn lz_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() / 2);
+    // last position of every hashed 4 byte sequence, plus one
+    let mut table = vec![0usize; 1 << LZ_HASH_BITS];
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + LZ_MIN_MATCH <= data.len() {
+        let sequence = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        let slot = (sequence.wrapping_mul(2654435761) >> (32 - LZ_HASH_BITS)) as usize;
+        let candidate = table[slot];
+        table[slot] = pos + 1;
+        if candidate == 0
+            || pos - (candidate - 1) > LZ_MAX_OFFSET
+            || data[candidate - 1..candidate + 3] != data[pos..pos + 4]
+        {
+            pos += 1;
+            continue;
+        }
+        let start = candidate - 1;
+        let mut len = LZ_MIN_MATCH;
+        while len < LZ_MAX_MATCH && pos + len < data.len() && data[start + len] == data[pos + len] {
+            len += 1;
+        }
+        lz_literals(&mut out, &data[literal_start..pos]);
+        out.push(0x80 | (len - LZ_MIN_MATCH) as u8);
+        out.extend(((pos - start) as u16).to_be_bytes());
+        pos += len;
+        literal_start = pos;
+    }
+    lz_literals(&mut out, &data[literal_start..]);
+    out
+}
+
+fn lz_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(LZ_MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend(chunk);
+    }
+}
+
+fn lz_decompress(payload: &[u8], len: usize) -> BustubxResult<Vec<u8>> {
+    let corrupt = |message: &str| BustubxError::Storage(format!("corrupt lz block: {message}"));
+    // a corrupt header must not allocate more than the payload can produce
+    let mut out = Vec::with_capacity(len.min(payload.len() * LZ_MAX_MATCH));
+    let mut pos = 0;
+    while pos < payload.len() {
+        let op = payload[pos] as usize;
+        pos += 1;
+        if op < 0x80 {
+            let literals = payload
+                .get(pos..pos + op + 1)
+                .ok_or_else(|| corrupt("literals past the end"))?;
+            out.extend(literals);
+            pos += op + 1;
+        } else {
+            let offset = payload
+                .get(pos..pos + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+                .ok_or_else(|| corrupt("match offset past the end"))?;
+            pos += 2;
+            if offset == 0 || offset > out.len() {
+                return Err(corrupt("match before the start"));
+            }
+            // the match may overlap the bytes it produces
+            for _ in 0..op - 0x80 + LZ_MIN_MATCH {
+                out.push(out[out.len() - offset]);
+            }
+        }
+        if out.len() > len {
+            return Err(corrupt("longer than its header"));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{CompressionCodec, Compressor};
+
+    #[test]
+    pub fn test_compression_round_trip() {
+        let repetitive = (0..20000)
+            .map(|i| format!("row {} ", i % 50))
+            .collect::<String>()
+            .into_bytes();
+        // xorshift, nothing to find for the codec
+        let mut state = 0x2545f491u32;
+        let random = (0..5000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        let zeros = vec![0u8; 4096];
+
+        let compressor = Compressor::new(CompressionCodec::Lz);
+        for data in [
+            &repetitive,
+            &random,
+            &zeros,
+            &vec![],
+            &b"abcabcabcabcab".to_vec(),
+        ] {
+            let block = compressor.compress(data);
+            assert_eq!(&Compressor::decompress(&block).unwrap(), data);
+        }
+        assert!(compressor.compress(&repetitive).len() < repetitive.len() / 4);
+        assert!(compressor.compress(&zeros).len() < 128);
+        // kept as is instead of growing
+        assert_eq!(compressor.compress(&random).len(), random.len() + 5);
+
+        compressor.reset_stats();
+        let block = compressor.compress(&repetitive);
+        assert_eq!(
+            compressor.byte_counts(),
+            (repetitive.len() as u64, block.len() as u64)
+        );
+        // blocks tell their codec, any compressor reads them
+        let uncompressed = Compressor::new(CompressionCodec::None).compress(&repetitive);
+        assert_eq!(uncompressed.len(), repetitive.len() + 5);
+        assert_eq!(Compressor::decompress(&uncompressed).unwrap(), repetitive);
+
+        let mut corrupt = block.clone();
+        corrupt[1..5].copy_from_slice(&1u32.to_be_bytes());
+        assert!(Compressor::decompress(&corrupt).is_err());
+        assert!(Compressor::decompress(&block[..block.len() / 2]).is_err());
+        assert!(Compressor::decompress(&[9, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -1,8 +1,9 @@
 use log::debug;
+use std::collections::BTreeSet;
 use std::fs::File;
-use std::path::Path;
-use std::sync::atomic::Ordering;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::{
     io::{Read, Seek, Write},
     sync::{atomic::AtomicU32, Mutex, MutexGuard},
@@ -11,89 +12,231 @@ use std::{
 use crate::error::{BustubxError, BustubxResult};
 
 use crate::buffer::{PageId, BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID};
+use crate::config::{DiskOptions, SyncPolicy};
 use crate::storage::codec::{FreelistPageCodec, MetaPageCodec};
-use crate::storage::{FreelistPage, MetaPage, META_PAGE_SIZE};
+use crate::storage::{
+    backup_label_path, read_timeline, BackupLabel, Compressor, FreelistPage, LogArchive,
+    LogManager, LogRecord, Lsn, MetaPage, COMMIT_RECORD_PAGE_ID, DB_FILE_MAGIC, FIRST_LSN,
+    META_PAGE_SIZE,
+};
 
 static EMPTY_PAGE: [u8; BUSTUBX_PAGE_SIZE] = [0; BUSTUBX_PAGE_SIZE];
+// suffix of the file a new database is built in before it is renamed into place
+const INIT_FILE_SUFFIX: &str = ".init";
 
 #[derive(Debug)]
 pub struct DiskManager {
     next_page_id: AtomicU32,
     db_file: Mutex<File>,
+    path: PathBuf,
     pub meta: RwLock<MetaPage>,
+    options: DiskOptions,
+    // Number of fsync calls issued on the db file
+    sync_count: AtomicU64,
+    pages_allocated: AtomicU64,
+    pages_freed: AtomicU64,
+    // Page writes kept for standbys and the archive, only with
+    // `DiskOptions::replication_log` or `DiskOptions::wal_archive_dir`
+    log_manager: Option<LogManager>,
+    // Compresses log segments and spilled runs, never the pages of the db file
+    compressor: Arc<Compressor>,
+    // Allocations left before injecting a disk full error
+    #[cfg(test)]
+    allocation_budget: Mutex<Option<usize>>,
+    // Pages whose writes fail with an injected error
+    #[cfg(test)]
+    failing_writes: Mutex<std::collections::HashSet<PageId>>,
 }
 
 impl DiskManager {
     pub fn try_new(db_path: impl AsRef<Path>) -> BustubxResult<Self> {
-        let mut is_new_file = false;
-        let (db_file, meta) = if db_path.as_ref().exists() {
-            let mut db_file = std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(db_path)?;
-            let mut buf = vec![0; *META_PAGE_SIZE];
-            db_file.read_exact(&mut buf)?;
-            let (meta_page, _) = MetaPageCodec::decode(&buf)?;
-            (db_file, meta_page)
-        } else {
-            is_new_file = true;
-            let mut db_file = std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(db_path)?;
-            let meta_page = MetaPage::try_new()?;
-            #[allow(clippy::unused_io_amount)]
-            db_file.write(&MetaPageCodec::encode(&meta_page))?;
-            (db_file, meta_page)
+        Self::try_new_with_options(db_path, DiskOptions::default())
+    }
+
+    pub fn try_new_with_options(
+        db_path: impl AsRef<Path>,
+        options: DiskOptions,
+    ) -> BustubxResult<Self> {
+        let db_path = db_path.as_ref();
+        let init_sync_count = match std::fs::metadata(db_path) {
+            Ok(metadata) if metadata.len() == 0 => {
+                if !options.reinitialize_empty_file {
+                    return Err(BustubxError::CorruptDatabaseFile(format!(
+                        "{} is empty, open it with reinitialize_empty_file to initialize it",
+                        db_path.display()
+                    )));
+                }
+                Self::initialize(db_path, &options)?
+            }
+            Ok(_) => 0,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Self::initialize(db_path, &options)?
+            }
+            Err(e) => return Err(e.into()),
         };
+        let disk_manager = Self::open(db_path, options)?;
+        // the syncs of the initialization count towards the opened file
+        disk_manager
+            .sync_count
+            .store(init_sync_count, Ordering::SeqCst);
+        Ok(disk_manager)
+    }
 
+    /// Build a new database file next to `db_path` and rename it into place once it is
+    /// synced, so a crash leaves either no database file or a complete one. Returns the number
+    /// of syncs the sync policy asked for.
+    fn initialize(db_path: &Path, options: &DiskOptions) -> BustubxResult<u64> {
+        let Some(file_name) = db_path.file_name() else {
+            return Err(BustubxError::Storage(format!(
+                "db path {} has no file name",
+                db_path.display()
+            )));
+        };
+        let mut init_file_name = file_name.to_os_string();
+        init_file_name.push(INIT_FILE_SUFFIX);
+        let init_path = db_path.with_file_name(init_file_name);
+
+        // a leftover of a crashed initialization is overwritten
+        let mut db_file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&init_path)?;
+        let meta_page = MetaPage::try_new()?;
+        db_file.write_all(&MetaPageCodec::encode(&meta_page))?;
+
+        // the log of the first writes is not archived, backups are taken after them
+        let options = DiskOptions {
+            wal_archive_dir: None,
+            ..options.clone()
+        };
+        let disk_manager = Self::from_file(db_file, &init_path, meta_page, options)?;
+        let freelist_page_id = disk_manager.allocate_freelist_page()?;
+        let information_schema_schemas_first_page_id = disk_manager.allocate_page()?;
+        let information_schema_tables_first_page_id = disk_manager.allocate_page()?;
+        let information_schema_columns_first_page_id = disk_manager.allocate_page()?;
+        let information_schema_indexes_first_page_id = disk_manager.allocate_page()?;
+
+        let mut meta = disk_manager.meta.write().unwrap();
+        meta.freelist_page_id = freelist_page_id;
+        meta.information_schema_schemas_first_page_id = information_schema_schemas_first_page_id;
+        meta.information_schema_tables_first_page_id = information_schema_tables_first_page_id;
+        meta.information_schema_columns_first_page_id = information_schema_columns_first_page_id;
+        meta.information_schema_indexes_first_page_id = information_schema_indexes_first_page_id;
+        drop(meta);
+        disk_manager.write_meta_page()?;
+        disk_manager.db_file.lock().unwrap().sync_all()?;
+        let sync_count = disk_manager.sync_count();
+        drop(disk_manager);
+
+        std::fs::rename(&init_path, db_path)?;
+        sync_parent_dir(db_path)?;
+        Ok(sync_count)
+    }
+
+    fn open(db_path: &Path, options: DiskOptions) -> BustubxResult<Self> {
+        let mut db_file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(db_path)?;
+        let mut header = Vec::with_capacity(*META_PAGE_SIZE);
+        (&mut db_file)
+            .take(*META_PAGE_SIZE as u64)
+            .read_to_end(&mut header)?;
+
+        // a prefix of the magic is a bustubx file cut short
+        let magic_len = header.len().min(DB_FILE_MAGIC.len());
+        if header[..magic_len] != DB_FILE_MAGIC[..magic_len] {
+            return Err(BustubxError::NotDatabaseFile(format!(
+                "{} does not start with the bustubx magic",
+                db_path.display()
+            )));
+        }
+        let meta_page = match MetaPageCodec::decode(&header) {
+            Ok((meta_page, _)) => meta_page,
+            Err(e) => {
+                return Err(BustubxError::CorruptDatabaseFile(format!(
+                    "{} has a truncated meta page: {}",
+                    db_path.display(),
+                    e
+                )))
+            }
+        };
+
+        let disk_manager = Self::from_file(db_file, db_path, meta_page, options)?;
+        let next_page_id = disk_manager.next_page_id.load(Ordering::SeqCst);
+        let meta = disk_manager.meta.read().unwrap();
+        for page_id in [
+            meta.freelist_page_id,
+            meta.information_schema_schemas_first_page_id,
+            meta.information_schema_tables_first_page_id,
+            meta.information_schema_columns_first_page_id,
+            meta.information_schema_indexes_first_page_id,
+        ] {
+            if page_id == INVALID_PAGE_ID || page_id >= next_page_id {
+                return Err(BustubxError::CorruptDatabaseFile(format!(
+                    "{} is truncated, meta page points at page {} but the file has {} pages",
+                    db_path.display(),
+                    page_id,
+                    next_page_id - 1
+                )));
+            }
+        }
+        drop(meta);
+        debug!(
+            "disk_manager meta page: {:?}",
+            disk_manager.meta.read().unwrap()
+        );
+        Ok(disk_manager)
+    }
+
+    fn from_file(
+        db_file: File,
+        path: &Path,
+        meta: MetaPage,
+        options: DiskOptions,
+    ) -> BustubxResult<Self> {
         // calculate next page id
         let db_file_len = db_file.metadata()?.len();
-        if (db_file_len - *META_PAGE_SIZE as u64) % BUSTUBX_PAGE_SIZE as u64 != 0 {
-            return Err(BustubxError::Internal(format!(
-                "db file size not a multiple of {} + meta page size {}",
-                BUSTUBX_PAGE_SIZE, *META_PAGE_SIZE,
+        let pages_len = db_file_len.saturating_sub(*META_PAGE_SIZE as u64);
+        if db_file_len < *META_PAGE_SIZE as u64 || pages_len % BUSTUBX_PAGE_SIZE as u64 != 0 {
+            return Err(BustubxError::CorruptDatabaseFile(format!(
+                "db file size {} is not a multiple of {} + meta page size {}",
+                db_file_len, BUSTUBX_PAGE_SIZE, *META_PAGE_SIZE,
             )));
         }
-        let next_page_id =
-            (((db_file_len - *META_PAGE_SIZE as u64) / BUSTUBX_PAGE_SIZE as u64) + 1) as PageId;
+        let next_page_id = ((pages_len / BUSTUBX_PAGE_SIZE as u64) + 1) as PageId;
         debug!("Initialized disk_manager next_page_id: {}", next_page_id);
+        let compressor = Arc::new(Compressor::new(options.compression));
+        let log_manager = match &options.wal_archive_dir {
+            Some(dir) => {
+                let archive = LogArchive::try_new(dir, read_timeline(path)?)?;
+                Some(LogManager::with_compressor(compressor.clone()).with_archive(archive)?)
+            }
+            None => options
+                .replication_log
+                .then(|| LogManager::with_compressor(compressor.clone())),
+        };
 
-        let disk_manager = Self {
+        Ok(Self {
             next_page_id: AtomicU32::new(next_page_id),
             // Use a mutex to wrap the file handle to ensure that only one thread
             // can access the file at the same time among multiple threads.
             db_file: Mutex::new(db_file),
+            path: path.to_path_buf(),
             meta: RwLock::new(meta),
-        };
-
-        // new pages
-        if is_new_file {
-            let freelist_page_id = disk_manager.allocate_freelist_page()?;
-            let information_schema_schemas_first_page_id = disk_manager.allocate_page()?;
-            let information_schema_tables_first_page_id = disk_manager.allocate_page()?;
-            let information_schema_columns_first_page_id = disk_manager.allocate_page()?;
-            let information_schema_indexes_first_page_id = disk_manager.allocate_page()?;
-
-            let mut meta = disk_manager.meta.write().unwrap();
-            meta.freelist_page_id = freelist_page_id;
-            meta.information_schema_schemas_first_page_id =
-                information_schema_schemas_first_page_id;
-            meta.information_schema_tables_first_page_id = information_schema_tables_first_page_id;
-            meta.information_schema_columns_first_page_id =
-                information_schema_columns_first_page_id;
-            meta.information_schema_indexes_first_page_id =
-                information_schema_indexes_first_page_id;
-            drop(meta);
-            disk_manager.write_meta_page()?;
-        }
-        debug!(
-            "disk_manager meta page: {:?}",
-            disk_manager.meta.read().unwrap()
-        );
-
-        Ok(disk_manager)
+            log_manager,
+            compressor,
+            options,
+            sync_count: AtomicU64::new(0),
+            pages_allocated: AtomicU64::new(0),
+            pages_freed: AtomicU64::new(0),
+            #[cfg(test)]
+            allocation_budget: Mutex::new(None),
+            #[cfg(test)]
+            failing_writes: Mutex::new(Default::default()),
+        })
     }
 
     pub fn read_page(&self, page_id: PageId) -> BustubxResult<[u8; BUSTUBX_PAGE_SIZE]> {
@@ -117,26 +260,88 @@ impl DiskManager {
                 BUSTUBX_PAGE_SIZE
             )));
         }
+        #[cfg(test)]
+        if self.failing_writes.lock().unwrap().contains(&page_id) {
+            return Err(BustubxError::Storage(format!(
+                "write of page {} failed (injected)",
+                page_id
+            )));
+        }
         let mut guard = self.db_file.lock().unwrap();
-        Self::write_page_internal(&mut guard, page_id, data)
+        self.write_page_internal(&mut guard, page_id, data)
     }
 
     pub fn allocate_page(&self) -> BustubxResult<PageId> {
+        #[cfg(test)]
+        self.consume_allocation_budget()?;
+
+        let page_id = self.allocate_page_internal()?;
+        self.pages_allocated.fetch_add(1, Ordering::Relaxed);
+        Ok(page_id)
+    }
+
+    fn allocate_page_internal(&self) -> BustubxResult<PageId> {
         if let Some(page_id) = self.freelist_pop()? {
             Ok(page_id)
         } else {
             let mut guard = self.db_file.lock().unwrap();
 
-            // fetch current value and increment page id
-            let page_id = self.next_page_id.fetch_add(1, Ordering::SeqCst);
+            // Only hand out the page id once the file has been extended,
+            // a failed write must not leak the id or leave a partial page behind.
+            let page_id = self.next_page_id.load(Ordering::SeqCst);
+            // the last id stays unused, the next page id after it would not fit
+            if page_id == PageId::MAX {
+                return Err(BustubxError::DiskFull(format!(
+                    "all {} page ids are allocated",
+                    PageId::MAX - 1
+                )));
+            }
+            let file_len = guard.metadata()?.len();
 
             // Write an empty page (all zeros) to the allocated page.
-            Self::write_page_internal(&mut guard, page_id, &EMPTY_PAGE)?;
+            if let Err(e) = self.write_page_internal(&mut guard, page_id, &EMPTY_PAGE) {
+                let _ = guard.set_len(file_len);
+                return Err(e);
+            }
+            self.next_page_id.store(page_id + 1, Ordering::SeqCst);
 
             Ok(page_id)
         }
     }
 
+    /// Make allocations fail with `DiskFull` once `count` more pages have been allocated.
+    #[cfg(test)]
+    pub(crate) fn fail_allocations_after(&self, count: usize) {
+        *self.allocation_budget.lock().unwrap() = Some(count);
+    }
+
+    /// Undo `fail_allocations_after`, as if space had been freed on the device.
+    #[cfg(test)]
+    pub(crate) fn free_space(&self) {
+        *self.allocation_budget.lock().unwrap() = None;
+    }
+
+    /// Make every write of the given pages fail, the other pages are written as usual.
+    #[cfg(test)]
+    pub(crate) fn fail_writes_to(&self, page_ids: impl IntoIterator<Item = PageId>) {
+        self.failing_writes.lock().unwrap().extend(page_ids);
+    }
+
+    #[cfg(test)]
+    fn consume_allocation_budget(&self) -> BustubxResult<()> {
+        let mut budget = self.allocation_budget.lock().unwrap();
+        match budget.as_mut() {
+            Some(0) => Err(BustubxError::DiskFull(
+                "no space left on device (injected)".to_string(),
+            )),
+            Some(remaining) => {
+                *remaining -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
     pub fn allocate_freelist_page(&self) -> BustubxResult<PageId> {
         let page_id = self.allocate_page()?;
         let freelist_page = FreelistPage::new();
@@ -148,10 +353,11 @@ impl DiskManager {
         // Write an empty page (all zeros) to the deallocated page.
         // But this page is not deallocated, only data will be written with null or zeros.
         let mut guard = self.db_file.lock().unwrap();
-        Self::write_page_internal(&mut guard, page_id, &EMPTY_PAGE)?;
+        self.write_page_internal(&mut guard, page_id, &EMPTY_PAGE)?;
         drop(guard);
 
         self.freelist_push(page_id)?;
+        self.pages_freed.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
@@ -210,13 +416,21 @@ impl DiskManager {
 
     fn write_meta_page(&self) -> BustubxResult<()> {
         let mut guard = self.db_file.lock().unwrap();
+        let data = MetaPageCodec::encode(&self.meta.read().unwrap());
         guard.seek(std::io::SeekFrom::Start(0))?;
-        guard.write_all(&MetaPageCodec::encode(&self.meta.read().unwrap()))?;
+        guard.write_all(&data)?;
         guard.flush()?;
+        if let Some(log_manager) = &self.log_manager {
+            log_manager.append(INVALID_PAGE_ID, &data);
+        }
+        if self.options.sync_policy == SyncPolicy::Always {
+            self.sync_internal(&guard)?;
+        }
         Ok(())
     }
 
     fn write_page_internal(
+        &self,
         guard: &mut MutexGuard<File>,
         page_id: PageId,
         data: &[u8],
@@ -227,9 +441,188 @@ impl DiskManager {
         ))?;
         guard.write_all(data)?;
         guard.flush()?;
+        if let Some(log_manager) = &self.log_manager {
+            log_manager.append(page_id, data);
+        }
+        if self.options.sync_policy == SyncPolicy::Always {
+            self.sync_internal(guard)?;
+        }
         Ok(())
     }
 
+    /// Record in the meta page whether the database was closed cleanly. The db file is
+    /// fsynced before and after a clean marker whatever the sync policy, so the marker
+    /// never reaches the disk ahead of the pages it vouches for.
+    pub fn set_clean_shutdown(&self, clean: bool) -> BustubxResult<()> {
+        if clean {
+            self.sync_internal(&self.db_file.lock().unwrap())?;
+        }
+        self.meta.write().unwrap().clean_shutdown = clean;
+        self.write_meta_page()?;
+        if clean {
+            self.sync_internal(&self.db_file.lock().unwrap())
+        } else {
+            self.sync()
+        }
+    }
+
+    pub fn log_manager(&self) -> Option<&LogManager> {
+        self.log_manager.as_ref()
+    }
+
+    /// Path the db file was opened at.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn compressor(&self) -> &Arc<Compressor> {
+        &self.compressor
+    }
+
+    /// Log a commit record stamped with `time` and archive the segment it closes, the
+    /// caller flushed the buffer pool first. Returns the lsn of the record, None without a
+    /// log.
+    pub fn log_commit(&self, time: i64) -> BustubxResult<Option<Lsn>> {
+        let Some(log_manager) = &self.log_manager else {
+            return Ok(None);
+        };
+        let lsn = {
+            // in the order of the page writes
+            let _guard = self.db_file.lock().unwrap();
+            log_manager.append_commit(time)
+        };
+        log_manager.switch_segment()?;
+        Ok(Some(lsn))
+    }
+
+    /// Copy the db file to `backup_path` and label it with the lsn its page writes end at.
+    /// The caller flushed the buffer pool first, pages written later reach the copy by
+    /// replaying the archived log.
+    pub fn backup_to(&self, backup_path: &Path) -> BustubxResult<BackupLabel> {
+        let guard = self.db_file.lock().unwrap();
+        self.sync_internal(&guard)?;
+        std::fs::copy(&self.path, backup_path)?;
+        let label = BackupLabel {
+            start_lsn: self
+                .log_manager
+                .as_ref()
+                .map_or(FIRST_LSN, |log_manager| log_manager.next_lsn()),
+            timeline: self
+                .log_manager
+                .as_ref()
+                .and_then(|log_manager| log_manager.archive())
+                .map_or(read_timeline(&self.path)?, |archive| archive.timeline()),
+        };
+        drop(guard);
+        let json = serde_json::to_vec(&label)
+            .map_err(|e| BustubxError::Internal(format!("cannot encode backup label: {e}")))?;
+        std::fs::write(backup_label_path(backup_path), json)?;
+        File::open(backup_path)?.sync_all()?;
+        Ok(label)
+    }
+
+    /// Redo a page write logged by a primary or archived.
+    pub fn apply_log_record(&self, record: &LogRecord) -> BustubxResult<()> {
+        if record.page_id == COMMIT_RECORD_PAGE_ID {
+            return Ok(());
+        }
+        if record.page_id == INVALID_PAGE_ID {
+            let (meta, _) = MetaPageCodec::decode(&record.data)?;
+            *self.meta.write().unwrap() = meta;
+            return self.write_meta_page();
+        }
+        if record.data.len() != BUSTUBX_PAGE_SIZE {
+            return Err(BustubxError::Storage(format!(
+                "log record {} holds {} bytes instead of a page",
+                record.lsn,
+                record.data.len()
+            )));
+        }
+        let mut guard = self.db_file.lock().unwrap();
+        self.write_page_internal(&mut guard, record.page_id, &record.data)?;
+        self.next_page_id
+            .fetch_max(record.page_id.saturating_add(1), Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Called after the buffer pool has been flushed
+    pub fn sync(&self) -> BustubxResult<()> {
+        if self.options.sync_policy == SyncPolicy::Never {
+            return Ok(());
+        }
+        let guard = self.db_file.lock().unwrap();
+        self.sync_internal(&guard)
+    }
+
+    fn sync_internal(&self, guard: &MutexGuard<File>) -> BustubxResult<()> {
+        guard.sync_all()?;
+        self.sync_count.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn sync_count(&self) -> u64 {
+        self.sync_count.load(Ordering::SeqCst)
+    }
+
+    /// Pages handed out by [`Self::allocate_page`] and given back by
+    /// [`Self::deallocate_page`], freelist pages included.
+    pub fn page_counts(&self) -> (u64, u64) {
+        (
+            self.pages_allocated.load(Ordering::Relaxed),
+            self.pages_freed.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Pages in use, every page of the file but the free pages and the pages of the
+    /// freelist holding them.
+    pub fn allocated_page_count(&self) -> BustubxResult<u64> {
+        let (freelist_page_ids, free_page_ids) = self.freelist_page_ids()?;
+        let page_count = self.next_page_id.load(Ordering::SeqCst) as u64 - 1;
+        Ok(page_count - freelist_page_ids.len() as u64 - free_page_ids.len() as u64)
+    }
+
+    /// Pages on the freelist, handed out again before the file grows.
+    pub fn free_page_count(&self) -> BustubxResult<u64> {
+        Ok(self.freelist_page_ids()?.1.len() as u64)
+    }
+
+    /// Ids of the pages in use, the complement of the freelist. Walks the whole freelist,
+    /// meant for tests and debugging.
+    pub fn allocated_page_ids(&self) -> BustubxResult<BTreeSet<PageId>> {
+        let (freelist_page_ids, free_page_ids) = self.freelist_page_ids()?;
+        let mut page_ids = (1..self.next_page_id.load(Ordering::SeqCst)).collect::<BTreeSet<_>>();
+        for page_id in freelist_page_ids.iter().chain(free_page_ids.iter()) {
+            page_ids.remove(page_id);
+        }
+        Ok(page_ids)
+    }
+
+    // the pages of the freelist chain and the free pages they hold
+    fn freelist_page_ids(&self) -> BustubxResult<(Vec<PageId>, Vec<PageId>)> {
+        let mut freelist_page_ids = vec![];
+        let mut free_page_ids = vec![];
+        let mut page_id = self.meta.read().unwrap().freelist_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let (freelist_page, _) = FreelistPageCodec::decode(&self.read_page(page_id)?)?;
+            freelist_page_ids.push(page_id);
+            free_page_ids.extend(freelist_page.array);
+            page_id = freelist_page.header.next_page_id;
+        }
+        Ok((freelist_page_ids, free_page_ids))
+    }
+
+    /// Zero the sync, page and compression counters.
+    pub fn reset_stats(&self) {
+        for counter in [&self.sync_count, &self.pages_allocated, &self.pages_freed] {
+            counter.store(0, Ordering::SeqCst);
+        }
+        self.compressor.reset_stats();
+    }
+
+    pub fn options(&self) -> &DiskOptions {
+        &self.options
+    }
+
     pub fn db_file_len(&self) -> BustubxResult<u64> {
         let guard = self.db_file.lock().unwrap();
         let meta = guard.metadata()?;
@@ -237,11 +630,31 @@ impl DiskManager {
     }
 }
 
+// Make the rename of a file in the directory durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> BustubxResult<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> BustubxResult<()> {
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::buffer::BUSTUBX_PAGE_SIZE;
+    use crate::buffer::{PageId, BUSTUBX_PAGE_SIZE};
+    use crate::config::DiskOptions;
     use crate::storage::codec::MetaPageCodec;
-    use crate::storage::EMPTY_META_PAGE;
+    use crate::storage::{EMPTY_META_PAGE, FREELIST_PAGE_MAX_SIZE, META_PAGE_SIZE};
+    use crate::BustubxError;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::Ordering;
     use tempfile::TempDir;
 
     #[test]
@@ -290,4 +703,136 @@ mod tests {
         let page_id4 = disk_manager.allocate_page().unwrap();
         assert_eq!(page_id1, page_id4);
     }
+
+    #[test]
+    pub fn test_disk_manager_page_ids_exhausted() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = super::DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let file_len = std::fs::metadata(temp_dir.path().join("test.db"))
+            .unwrap()
+            .len();
+        disk_manager
+            .next_page_id
+            .store(PageId::MAX, Ordering::SeqCst);
+        assert!(matches!(
+            disk_manager.allocate_page(),
+            Err(BustubxError::DiskFull(_))
+        ));
+        assert_eq!(
+            std::fs::metadata(temp_dir.path().join("test.db"))
+                .unwrap()
+                .len(),
+            file_len
+        );
+        // freed pages are still handed out
+        disk_manager.next_page_id.store(6, Ordering::SeqCst);
+        let page_id = disk_manager.allocate_page().unwrap();
+        disk_manager.deallocate_page(page_id).unwrap();
+        disk_manager
+            .next_page_id
+            .store(PageId::MAX, Ordering::SeqCst);
+        assert_eq!(disk_manager.allocate_page().unwrap(), page_id);
+    }
+
+    #[test]
+    pub fn test_disk_manager_page_accounting() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = super::DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        // the first pages of the information_schema tables, page 1 is the freelist
+        let information_schema_page_ids = BTreeSet::from([2, 3, 4, 5]);
+        assert_eq!(
+            disk_manager.allocated_page_ids().unwrap(),
+            information_schema_page_ids
+        );
+        assert_eq!(disk_manager.free_page_count().unwrap(), 0);
+
+        let page_ids = (0..*FREELIST_PAGE_MAX_SIZE + 10)
+            .map(|_| disk_manager.allocate_page().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            disk_manager.allocated_page_count().unwrap(),
+            page_ids.len() as u64 + 4
+        );
+        disk_manager.deallocate_page(page_ids[3]).unwrap();
+        assert_eq!(disk_manager.free_page_count().unwrap(), 1);
+        assert!(!disk_manager
+            .allocated_page_ids()
+            .unwrap()
+            .contains(&page_ids[3]));
+
+        for page_id in page_ids.iter().filter(|page_id| **page_id != page_ids[3]) {
+            disk_manager.deallocate_page(*page_id).unwrap();
+        }
+        // a free page became the second page of the freelist once the first was full
+        assert_eq!(
+            disk_manager.free_page_count().unwrap(),
+            page_ids.len() as u64 - 1
+        );
+        assert_eq!(disk_manager.allocated_page_count().unwrap(), 4);
+        assert_eq!(
+            disk_manager.allocated_page_ids().unwrap(),
+            information_schema_page_ids
+        );
+    }
+
+    #[test]
+    pub fn test_disk_manager_allocation_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let disk_manager = super::DiskManager::try_new(temp_path).unwrap();
+        disk_manager.fail_allocations_after(1);
+        let page_id1 = disk_manager.allocate_page().unwrap();
+        let db_file_len = disk_manager.db_file_len().unwrap();
+        assert!(matches!(
+            disk_manager.allocate_page(),
+            Err(BustubxError::DiskFull(_))
+        ));
+        assert_eq!(disk_manager.db_file_len().unwrap(), db_file_len);
+
+        disk_manager.free_space();
+        let page_id2 = disk_manager.allocate_page().unwrap();
+        assert_eq!(page_id2, page_id1 + 1);
+    }
+
+    #[test]
+    pub fn test_disk_manager_classify_bad_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+        drop(super::DiskManager::try_new(&temp_path).unwrap());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        let db_bytes = std::fs::read(&temp_path).unwrap();
+
+        // crash in the middle of writing the meta page or the system pages
+        for len in [4, *META_PAGE_SIZE - 1, *META_PAGE_SIZE + BUSTUBX_PAGE_SIZE] {
+            std::fs::write(&temp_path, &db_bytes[..len]).unwrap();
+            assert!(
+                matches!(
+                    super::DiskManager::try_new(&temp_path),
+                    Err(BustubxError::CorruptDatabaseFile(_))
+                ),
+                "{len}"
+            );
+        }
+
+        std::fs::write(&temp_path, b"SQLite format 3\0").unwrap();
+        assert!(matches!(
+            super::DiskManager::try_new(&temp_path),
+            Err(BustubxError::NotDatabaseFile(_))
+        ));
+
+        std::fs::write(&temp_path, b"").unwrap();
+        assert!(matches!(
+            super::DiskManager::try_new(&temp_path),
+            Err(BustubxError::CorruptDatabaseFile(_))
+        ));
+        let options = DiskOptions {
+            reinitialize_empty_file: true,
+            ..Default::default()
+        };
+        drop(super::DiskManager::try_new_with_options(&temp_path, options).unwrap());
+        assert_eq!(std::fs::read(&temp_path).unwrap(), db_bytes);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        super::DiskManager::try_new(&temp_path).unwrap();
+    }
 }
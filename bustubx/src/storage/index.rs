@@ -1,15 +1,20 @@
-use std::collections::VecDeque;
+use std::collections::HashSet;
 use std::ops::{Bound, RangeBounds};
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use crate::buffer::{AtomicPageId, PageId, PageRef, INVALID_PAGE_ID};
-use crate::catalog::SchemaRef;
-use crate::common::util::page_bytes_to_array;
+use crate::buffer::{
+    operation_scope, AtomicPageId, PageId, PageRef, BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID,
+};
+use crate::catalog::{ColumnRef, SchemaRef};
+use crate::common::ScalarValue;
 use crate::storage::codec::{
-    BPlusTreeInternalPageCodec, BPlusTreeLeafPageCodec, BPlusTreePageCodec,
+    BPlusTreeInternalPageCodec, BPlusTreeLeafPageCodec, BPlusTreePageCodec, TupleCodec,
+};
+use crate::storage::{
+    BPlusTreeLeafPageHeader, Finding, InternalKV, LatchMode, LatchPath, LeafKV,
+    BPLUS_MAX_HEADER_SIZE,
 };
-use crate::storage::{InternalKV, LeafKV};
 use crate::{
     buffer::BufferPoolManager,
     storage::{BPlusTreeInternalPage, BPlusTreeLeafPage, BPlusTreePage, RecordId},
@@ -18,17 +23,22 @@ use crate::{
 
 use super::tuple::Tuple;
 
-struct Context {
-    pub root_page_id: PageId,
-    pub write_set: VecDeque<PageId>,
-    pub read_set: VecDeque<PageId>,
+/// When a deferrable unique index or foreign key checks the rows a transaction wrote,
+/// `INITIALLY` of `CREATE UNIQUE INDEX ... DEFERRABLE` or of a `REFERENCES ... DEFERRABLE`,
+/// changed for a transaction by `SET CONSTRAINTS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintTiming {
+    // at the end of every statement
+    Immediate,
+    // at the commit of the transaction
+    Deferred,
 }
-impl Context {
-    pub fn new(root_page_id: PageId) -> Self {
-        Self {
-            root_page_id,
-            write_set: VecDeque::new(),
-            read_set: VecDeque::new(),
+
+impl std::fmt::Display for ConstraintTiming {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstraintTiming::Immediate => write!(f, "IMMEDIATE"),
+            ConstraintTiming::Deferred => write!(f, "DEFERRED"),
         }
     }
 }
@@ -41,6 +51,14 @@ pub struct BPlusTreeIndex {
     pub internal_max_size: u32,
     pub leaf_max_size: u32,
     pub root_page_id: AtomicPageId,
+    // Inserting a key without NULLs that is present already fails with
+    // `BustubxError::DuplicateKey`, NULLs never conflict so such keys may repeat
+    pub unique: bool,
+    // Set for a DEFERRABLE unique index, which takes duplicate keys like an index that is
+    // not unique, the keys are checked when the statement or the transaction ends instead
+    pub deferrable: Option<ConstraintTiming>,
+    // leaves `count_range` decoded entry by entry
+    leaf_decodes: AtomicU64,
 }
 
 impl BPlusTreeIndex {
@@ -56,60 +74,115 @@ impl BPlusTreeIndex {
             internal_max_size,
             leaf_max_size,
             root_page_id: AtomicPageId::new(INVALID_PAGE_ID),
+            unique: false,
+            deferrable: None,
+            leaf_decodes: AtomicU64::new(0),
         }
     }
 
+    pub fn with_unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    pub fn with_deferrable(mut self, deferrable: Option<ConstraintTiming>) -> Self {
+        self.deferrable = deferrable;
+        self
+    }
+
+    pub fn with_root_page_id(self, root_page_id: PageId) -> Self {
+        self.root_page_id.store(root_page_id, Ordering::SeqCst);
+        self
+    }
+
     pub fn is_empty(&self) -> bool {
         self.root_page_id.load(Ordering::SeqCst) == INVALID_PAGE_ID
     }
 
+    /// Largest encoded key the index takes, a page full of such keys still fits a page.
+    pub fn max_key_size(&self) -> usize {
+        let room = BUSTUBX_PAGE_SIZE - BPLUS_MAX_HEADER_SIZE;
+        // a leaf entry ends with the rid of the row, an internal one with a child page id
+        let leaf = (room / self.leaf_max_size.max(1) as usize).saturating_sub(8);
+        let internal = (room / self.internal_max_size.max(1) as usize).saturating_sub(4);
+        leaf.min(internal)
+    }
+
+    // refused before any page is touched, the encoded page would not fit its frame
+    fn check_key_size(&self, key: &Tuple) -> BustubxResult<()> {
+        let size = TupleCodec::encode(key).len();
+        let max = self.max_key_size();
+        if size > max {
+            return Err(BustubxError::IndexKeyTooLarge { size, max });
+        }
+        Ok(())
+    }
+
     pub fn insert(&self, key: &Tuple, rid: RecordId) -> BustubxResult<()> {
+        let _op = operation_scope("index_insert");
+        self.check_key_size(key)?;
         if self.is_empty() {
             self.start_new_tree(key, rid)?;
             return Ok(());
         }
-        let mut context = Context::new(self.root_page_id.load(Ordering::SeqCst));
+        let mut path = LatchPath::new();
         // Find leaf page
-        let Some(leaf_page) = self.find_leaf_page(key, &mut context)? else {
+        if !self.find_leaf_page(key, &mut path, LatchMode::Write)? {
             return Err(BustubxError::Storage(
                 "Cannot find leaf page to insert".to_string(),
             ));
-        };
+        }
 
         let (mut leaf_tree_page, _) = BPlusTreeLeafPageCodec::decode(
-            leaf_page.read().unwrap().data(),
+            path.current()?.read().unwrap().data(),
             self.key_schema.clone(),
         )?;
-        leaf_tree_page.insert(key.clone(), rid);
+        if self.unique && self.deferrable.is_none() && !key.data.iter().any(|v| v.is_null()) {
+            // nothing was written yet, a duplicate leaves the tree as it was
+            leaf_tree_page.insert_unique(key.clone(), rid)?;
+        } else {
+            leaf_tree_page.insert(key.clone(), rid);
+        }
 
-        let mut curr_page = leaf_page;
         let mut curr_tree_page = BPlusTreePage::Leaf(leaf_tree_page);
 
+        // Allocate every page the splits need before touching the tree,
+        // so a full disk can't leave it half split.
+        let mut new_pages = self
+            .allocate_split_pages(&curr_tree_page, &path)?
+            .into_iter();
+
         // If leaf page is full, split it
         while curr_tree_page.is_full() {
+            let _op = operation_scope("index_split");
+            let Some(new_page) = new_pages.next() else {
+                return Err(BustubxError::Internal(
+                    "split page was not allocated".to_string(),
+                ));
+            };
             // Split to the right to create a new page
-            let internalkv = self.split(&mut curr_tree_page)?;
+            let internalkv = self.split(&mut curr_tree_page, path.current_page_id()?, new_page)?;
 
-            curr_page
-                .write()
-                .unwrap()
-                .set_data(page_bytes_to_array(&BPlusTreePageCodec::encode(
-                    &curr_tree_page,
-                )));
+            path.current_write()?
+                .encode_page::<BPlusTreePageCodec>(&curr_tree_page);
 
-            let curr_page_id = curr_page.read().unwrap().page_id;
-            if let Some(parent_page_id) = context.read_set.pop_back() {
+            if path.parent_of_current().is_some() {
                 // Update parent node
-                let (parent_page, mut parent_tree_page) = self
-                    .buffer_pool
-                    .fetch_tree_page(parent_page_id, self.key_schema.clone())?;
+                path.pop();
+                let (mut parent_tree_page, _) = BPlusTreePageCodec::decode(
+                    path.current()?.read().unwrap().data(),
+                    self.key_schema.clone(),
+                )?;
                 parent_tree_page.insert_internalkv(internalkv);
 
-                curr_page = parent_page;
                 curr_tree_page = parent_tree_page;
-            } else if curr_page_id == self.root_page_id.load(Ordering::SeqCst) {
+            } else if path.current_page_id()? == self.root_page_id.load(Ordering::SeqCst) {
                 // Create a new root page
-                let new_root_page = self.buffer_pool.new_page()?;
+                let Some(new_root_page) = new_pages.next() else {
+                    return Err(BustubxError::Internal(
+                        "root page was not allocated".to_string(),
+                    ));
+                };
                 let new_root_page_id = new_root_page.read().unwrap().page_id;
                 let mut new_root_internal_page =
                     BPlusTreeInternalPage::new(self.key_schema.clone(), self.internal_max_size);
@@ -121,62 +194,94 @@ impl BPlusTreeIndex {
                 );
                 new_root_internal_page.insert(internalkv.0, internalkv.1);
 
-                new_root_page.write().unwrap().set_data(page_bytes_to_array(
-                    &BPlusTreeInternalPageCodec::encode(&new_root_internal_page),
-                ));
+                new_root_page
+                    .write()
+                    .unwrap()
+                    .encode_page::<BPlusTreeInternalPageCodec>(&new_root_internal_page);
 
                 // Update root page id
                 self.root_page_id.store(new_root_page_id, Ordering::SeqCst);
 
-                curr_page = new_root_page;
+                path.pop();
+                path.push(new_root_page, LatchMode::Write);
                 curr_tree_page = BPlusTreePage::Internal(new_root_internal_page);
             }
         }
 
-        curr_page
-            .write()
-            .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreePageCodec::encode(
-                &curr_tree_page,
-            )));
+        path.current_write()?
+            .encode_page::<BPlusTreePageCodec>(&curr_tree_page);
 
         Ok(())
     }
 
-    pub fn delete(&self, key: &Tuple) -> BustubxResult<()> {
+    /// Delete an entry of `key`, returns whether there was one. A missing key leaves every
+    /// page as it was.
+    pub fn delete(&self, key: &Tuple) -> BustubxResult<bool> {
+        let _op = operation_scope("index_delete");
         if self.is_empty() {
-            return Ok(());
+            return Ok(false);
         }
-        let mut context = Context::new(self.root_page_id.load(Ordering::SeqCst));
+        let mut path = LatchPath::new();
         // Find leaf page
-        let Some(leaf_page) = self.find_leaf_page(key, &mut context)? else {
+        if !self.find_leaf_page(key, &mut path, LatchMode::Write)? {
             return Err(BustubxError::Storage(
                 "Cannot find leaf page to delete".to_string(),
             ));
-        };
+        }
         let (mut leaf_tree_page, _) = BPlusTreeLeafPageCodec::decode(
-            leaf_page.read().unwrap().data(),
+            path.current()?.read().unwrap().data(),
             self.key_schema.clone(),
         )?;
-        leaf_tree_page.delete(key);
-        leaf_page
-            .write()
-            .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreeLeafPageCodec::encode(
-                &leaf_tree_page,
-            )));
+        if !leaf_tree_page.delete(key) {
+            return Ok(false);
+        }
+        self.write_leaf_and_rebalance(path, leaf_tree_page)?;
+        Ok(true)
+    }
+
+    /// Delete the entry of `key` mapping to `rid`, returns whether there was one. Entries of
+    /// other rows with an equal key are left alone, wherever the run of equal keys starts.
+    pub fn delete_entry(&self, key: &Tuple, rid: RecordId) -> BustubxResult<bool> {
+        let _op = operation_scope("index_delete");
+        let mut path = LatchPath::new();
+        if !self.find_entry_leaf_page(key, rid, &mut path)? {
+            return Ok(false);
+        }
+        let (mut leaf_tree_page, _) = BPlusTreeLeafPageCodec::decode(
+            path.current()?.read().unwrap().data(),
+            self.key_schema.clone(),
+        )?;
+        let Some(index) = leaf_tree_page.entry_index(key, rid) else {
+            return Ok(false);
+        };
+        leaf_tree_page.delete_at(index);
+        self.write_leaf_and_rebalance(path, leaf_tree_page)?;
+        Ok(true)
+    }
+
+    // Write back the leaf at the end of `path`, then borrow or merge up the tree while
+    // pages are underflowing
+    fn write_leaf_and_rebalance(
+        &self,
+        mut path: LatchPath,
+        leaf_tree_page: BPlusTreeLeafPage,
+    ) -> BustubxResult<()> {
+        path.current_write()?
+            .encode_page::<BPlusTreeLeafPageCodec>(&leaf_tree_page);
 
         let mut curr_tree_page = BPlusTreePage::Leaf(leaf_tree_page);
-        let mut curr_page_id = leaf_page.read().unwrap().page_id;
+        let mut curr_page_id = path.current_page_id()?;
 
         // If leaf page is not half full, borrow from sibling nodes or merge
         while curr_tree_page.is_underflow(self.root_page_id.load(Ordering::SeqCst) == curr_page_id)
         {
-            let Some(parent_page_id) = context.read_set.pop_back() else {
+            let Some((parent_page_id, child_index)) = path.parent_of_current() else {
                 return Err(BustubxError::Storage("Cannot find parent page".to_string()));
             };
+            // The current page may be merged away, only its parent stays latched
+            path.pop();
             let (left_sibling_page_id, right_sibling_page_id) =
-                self.find_sibling_pages(parent_page_id, curr_page_id)?;
+                self.find_sibling_pages(&path, child_index)?;
 
             // Try to borrow one from left sibling
             if let Some(left_sibling_page_id) = left_sibling_page_id {
@@ -194,10 +299,10 @@ impl BPlusTreeIndex {
 
             let new_parent_page_id = if let Some(left_sibling_page_id) = left_sibling_page_id {
                 // Merge with left sibling
-                self.merge(parent_page_id, left_sibling_page_id, curr_page_id)?
+                self.merge(&mut path, left_sibling_page_id, curr_page_id)?
             } else if let Some(right_sibling_page_id) = right_sibling_page_id {
                 // Merge with right sibling
-                self.merge(parent_page_id, curr_page_id, right_sibling_page_id)?
+                self.merge(&mut path, curr_page_id, right_sibling_page_id)?
             } else {
                 return Err(BustubxError::Storage(
                     "Cannot process index page borrow or merge".to_string(),
@@ -224,9 +329,7 @@ impl BPlusTreeIndex {
         new_page
             .write()
             .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreeLeafPageCodec::encode(
-                &leaf_page,
-            )));
+            .encode_page::<BPlusTreeLeafPageCodec>(&leaf_page);
 
         // Update root page id
         self.root_page_id.store(new_page_id, Ordering::SeqCst);
@@ -234,6 +337,100 @@ impl BPlusTreeIndex {
         Ok(())
     }
 
+    /// Replace the tree with one bulk loaded from `entries`. The old tree stays readable
+    /// until the new root is swapped in, its pages are freed afterwards.
+    pub fn rebuild(&self, mut entries: Vec<LeafKV>) -> BustubxResult<()> {
+        let _op = operation_scope("index_rebuild");
+        for (key, _) in entries.iter() {
+            self.check_key_size(key)?;
+        }
+        // stable, equal keys keep the order of `entries`
+        entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let new_root_page_id = self.bulk_load(entries)?;
+        let old_root_page_id = self.root_page_id.swap(new_root_page_id, Ordering::SeqCst);
+        self.free_subtree(old_root_page_id)
+    }
+
+    // Build the leaves left to right from sorted entries, then every internal level
+    // over the one below it, returns the root page id.
+    fn bulk_load(&self, entries: Vec<LeafKV>) -> BustubxResult<PageId> {
+        if entries.is_empty() {
+            return Ok(INVALID_PAGE_ID);
+        }
+        // first key and page id of every page on the level being built
+        let mut level: Vec<InternalKV> = vec![];
+        // a leaf is written once the page id of its right sibling is known
+        let mut prev_leaf: Option<(PageRef, BPlusTreeLeafPage)> = None;
+        for chunk in even_chunks(entries, self.leaf_max_size as usize) {
+            let page = self.buffer_pool.new_page()?;
+            let page_id = page.read().unwrap().page_id;
+            level.push((chunk[0].0.clone(), page_id));
+            let mut leaf_page = BPlusTreeLeafPage::new(self.key_schema.clone(), self.leaf_max_size);
+            if let Some((prev_page, mut prev_leaf_page)) = prev_leaf.take() {
+                prev_leaf_page.header.next_page_id = page_id;
+                let mut prev_page = prev_page.write().unwrap();
+                leaf_page.header.prev_page_id = Some(prev_page.page_id);
+                prev_page.encode_page::<BPlusTreeLeafPageCodec>(&prev_leaf_page);
+            }
+            leaf_page.header.current_size = chunk.len() as u32;
+            leaf_page.array = chunk;
+            prev_leaf = Some((page, leaf_page));
+        }
+        if let Some((page, leaf_page)) = prev_leaf {
+            page.write()
+                .unwrap()
+                .encode_page::<BPlusTreeLeafPageCodec>(&leaf_page);
+        }
+
+        while level.len() > 1 {
+            let mut upper_level = vec![];
+            for chunk in even_chunks(level, self.internal_max_size as usize) {
+                let page = self.buffer_pool.new_page()?;
+                let page_id = page.read().unwrap().page_id;
+                upper_level.push((chunk[0].0.clone(), page_id));
+
+                let mut internal_page =
+                    BPlusTreeInternalPage::new(self.key_schema.clone(), self.internal_max_size);
+                internal_page.header.current_size = chunk.len() as u32;
+                internal_page.array = chunk;
+                // The first kv pair's key in internal page is empty
+                internal_page.array[0].0 = Tuple::empty(self.key_schema.clone());
+                page.write()
+                    .unwrap()
+                    .encode_page::<BPlusTreeInternalPageCodec>(&internal_page);
+            }
+            level = upper_level;
+        }
+        Ok(level[0].1)
+    }
+
+    /// Free every page of the tree, which is left empty.
+    pub fn destroy(&self) -> BustubxResult<()> {
+        let root_page_id = self.root_page_id.swap(INVALID_PAGE_ID, Ordering::SeqCst);
+        self.free_subtree(root_page_id)
+    }
+
+    fn free_subtree(&self, page_id: PageId) -> BustubxResult<()> {
+        if page_id == INVALID_PAGE_ID {
+            return Ok(());
+        }
+        let (_, tree_page) = self
+            .buffer_pool
+            .fetch_tree_page(page_id, self.key_schema.clone())?;
+        if let BPlusTreePage::Internal(internal_page) = tree_page {
+            for child_page_id in internal_page.values() {
+                self.free_subtree(child_page_id)?;
+            }
+        }
+        if !self.buffer_pool.delete_page(page_id)? {
+            return Err(BustubxError::Storage(format!(
+                "index page {} is still pinned",
+                page_id
+            )));
+        }
+        Ok(())
+    }
+
     // Find the value corresponding to the key on the leaf node
     pub fn get(&self, key: &Tuple) -> BustubxResult<Option<RecordId>> {
         if self.is_empty() {
@@ -241,52 +438,216 @@ impl BPlusTreeIndex {
         }
 
         // Find leaf page
-        let mut context = Context::new(self.root_page_id.load(Ordering::SeqCst));
-        let Some(leaf_page) = self.find_leaf_page(key, &mut context)? else {
+        let mut path = LatchPath::new();
+        if !self.find_leaf_page(key, &mut path, LatchMode::Read)? {
             return Ok(None);
-        };
+        }
         let (leaf_tree_page, _) = BPlusTreeLeafPageCodec::decode(
-            leaf_page.read().unwrap().data(),
+            path.current()?.read().unwrap().data(),
             self.key_schema.clone(),
         )?;
         let result = leaf_tree_page.look_up(key);
         Ok(result)
     }
 
-    fn find_leaf_page(&self, key: &Tuple, context: &mut Context) -> BustubxResult<Option<PageRef>> {
+    /// Look up many keys at once, results are in the order of `keys`.
+    ///
+    /// The keys are probed in sorted order so consecutive keys falling in the same leaf,
+    /// or in the leaf right after it, are resolved without descending from the root again.
+    pub fn get_batch(&self, keys: &[Tuple]) -> BustubxResult<Vec<Option<RecordId>>> {
+        let mut results = vec![None; keys.len()];
         if self.is_empty() {
-            return Ok(None);
+            return Ok(results);
+        }
+        let mut probes = (0..keys.len()).collect::<Vec<_>>();
+        probes.sort_by(|a, b| keys[*a].partial_cmp(&keys[*b]).unwrap());
+
+        let mut leaf: Option<BPlusTreeLeafPage> = None;
+        for idx in probes {
+            let key = &keys[idx];
+            let beyond_leaf =
+                |leaf: &BPlusTreeLeafPage| leaf.array.last().is_none_or(|last_kv| *key > last_kv.0);
+            if let Some(current) = leaf.as_ref().filter(|current| beyond_leaf(current)) {
+                let next_page_id = current.header.next_page_id;
+                leaf = None;
+                if next_page_id != INVALID_PAGE_ID {
+                    let (_, next_leaf) = self
+                        .buffer_pool
+                        .fetch_tree_leaf_page(next_page_id, self.key_schema.clone())?;
+                    if !beyond_leaf(&next_leaf) {
+                        leaf = Some(next_leaf);
+                    }
+                }
+            }
+            if leaf.is_none() {
+                let mut path = LatchPath::new();
+                if !self.find_leaf_page(key, &mut path, LatchMode::Read)? {
+                    return Ok(results);
+                }
+                leaf = Some(
+                    BPlusTreeLeafPageCodec::decode(
+                        path.current()?.read().unwrap().data(),
+                        self.key_schema.clone(),
+                    )?
+                    .0,
+                );
+            }
+            results[idx] = leaf.as_ref().unwrap().look_up(key);
         }
-        let (mut curr_page, mut curr_tree_page) = self.buffer_pool.fetch_tree_page(
+        Ok(results)
+    }
+
+    // Descend to the leaf the key belongs in, the leaf becomes the current page of `path`.
+    // Readers only keep the current page, writers keep every level for splits and merges.
+    fn find_leaf_page(
+        &self,
+        key: &Tuple,
+        path: &mut LatchPath,
+        mode: LatchMode,
+    ) -> BustubxResult<bool> {
+        if self.is_empty() {
+            return Ok(false);
+        }
+        let (root_page, mut curr_tree_page) = self.buffer_pool.fetch_tree_page(
             self.root_page_id.load(Ordering::SeqCst),
             self.key_schema.clone(),
         )?;
+        path.push(root_page, mode);
 
         // Find leaf page
         loop {
             match curr_tree_page {
                 BPlusTreePage::Internal(internal_page) => {
-                    context
-                        .read_set
-                        .push_back(curr_page.read().unwrap().page_id);
                     // Find next page
-                    let next_page_id = internal_page.look_up(key);
-                    let (next_page, next_tree_page) = self
-                        .buffer_pool
-                        .fetch_tree_page(next_page_id, self.key_schema.clone())?;
-                    curr_page = next_page;
+                    let child_index = internal_page.child_index(key);
+                    let (next_page, next_tree_page) = self.buffer_pool.fetch_tree_page(
+                        internal_page.value_at(child_index),
+                        self.key_schema.clone(),
+                    )?;
+                    path.descend(child_index, next_page, mode);
+                    if mode == LatchMode::Read {
+                        path.release_ancestors_above(path.len() - 1);
+                    }
                     curr_tree_page = next_tree_page;
                 }
                 BPlusTreePage::Leaf(_leaf_page) => {
-                    return Ok(Some(curr_page));
+                    return Ok(true);
                 }
             }
         }
     }
 
-    // Split page
-    fn split(&self, tree_page: &mut BPlusTreePage) -> BustubxResult<InternalKV> {
-        let new_page = self.buffer_pool.new_page()?;
+    // Descend with write latches to the leaf holding `key` mapping to `rid`. Equal keys may
+    // span several leaves, every child that can hold one of them is searched in key order.
+    fn find_entry_leaf_page(
+        &self,
+        key: &Tuple,
+        rid: RecordId,
+        path: &mut LatchPath,
+    ) -> BustubxResult<bool> {
+        if self.is_empty() {
+            return Ok(false);
+        }
+        let (root_page, root_tree_page) = self.buffer_pool.fetch_tree_page(
+            self.root_page_id.load(Ordering::SeqCst),
+            self.key_schema.clone(),
+        )?;
+        path.push(root_page, LatchMode::Write);
+        self.find_entry_in_subtree(key, rid, root_tree_page, path)
+    }
+
+    fn find_entry_in_subtree(
+        &self,
+        key: &Tuple,
+        rid: RecordId,
+        tree_page: BPlusTreePage,
+        path: &mut LatchPath,
+    ) -> BustubxResult<bool> {
+        match tree_page {
+            BPlusTreePage::Internal(internal_page) => {
+                let children =
+                    internal_page.lower_child_index(key)..=internal_page.child_index(key);
+                for child_index in children {
+                    let (child_page, child_tree_page) = self.buffer_pool.fetch_tree_page(
+                        internal_page.value_at(child_index),
+                        self.key_schema.clone(),
+                    )?;
+                    path.descend(child_index, child_page, LatchMode::Write);
+                    if self.find_entry_in_subtree(key, rid, child_tree_page, path)? {
+                        return Ok(true);
+                    }
+                    path.pop();
+                }
+                Ok(false)
+            }
+            BPlusTreePage::Leaf(leaf_page) => Ok(leaf_page.entry_index(key, rid).is_some()),
+        }
+    }
+
+    // Pages needed to split the leaf and every full ancestor, plus a new root if the root splits
+    fn allocate_split_pages(
+        &self,
+        leaf_tree_page: &BPlusTreePage,
+        path: &LatchPath,
+    ) -> BustubxResult<Vec<PageRef>> {
+        let mut count = 0;
+        if leaf_tree_page.is_full() {
+            count += 1;
+            let page_ids = path.page_ids();
+            // ancestors of the leaf, bottom up
+            let mut parent_page_ids = page_ids.iter().rev().skip(1);
+            loop {
+                let Some(parent_page_id) = parent_page_ids.next() else {
+                    // the root splits
+                    count += 1;
+                    break;
+                };
+                let (_, parent_tree_page) = self
+                    .buffer_pool
+                    .fetch_tree_page(*parent_page_id, self.key_schema.clone())?;
+                let BPlusTreePage::Internal(parent_internal_page) = parent_tree_page else {
+                    return Err(BustubxError::Storage(format!(
+                        "page {} is not an internal page",
+                        parent_page_id
+                    )));
+                };
+                // the parent takes one more kv from the split below
+                if parent_internal_page.header.current_size < parent_internal_page.header.max_size {
+                    break;
+                }
+                count += 1;
+            }
+        }
+
+        let mut pages = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.buffer_pool.new_page() {
+                Ok(page) => pages.push(page),
+                Err(e) => {
+                    // Nothing links to these pages yet, give them back
+                    let page_ids: Vec<PageId> = pages
+                        .iter()
+                        .map(|page| page.read().unwrap().page_id)
+                        .collect();
+                    drop(pages);
+                    for page_id in page_ids {
+                        // a failure here only leaks the page, report the original error
+                        let _ = self.buffer_pool.delete_page(page_id);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    // Split page `page_id` into the freshly allocated `new_page`
+    fn split(
+        &self,
+        tree_page: &mut BPlusTreePage,
+        page_id: PageId,
+        new_page: PageRef,
+    ) -> BustubxResult<InternalKV> {
         let new_page_id = new_page.read().unwrap().page_id;
 
         match tree_page {
@@ -297,13 +658,16 @@ impl BPlusTreeIndex {
                 new_leaf_page
                     .batch_insert(leaf_page.split_off(leaf_page.header.current_size as usize / 2));
 
-                // Update next page id
+                // Update next and previous page ids
                 new_leaf_page.header.next_page_id = leaf_page.header.next_page_id;
-                leaf_page.header.next_page_id = new_page.read().unwrap().page_id;
+                new_leaf_page.header.prev_page_id = Some(page_id);
+                leaf_page.header.next_page_id = new_page_id;
+                self.link_prev_leaf(new_leaf_page.header.next_page_id, new_page_id)?;
 
-                new_page.write().unwrap().set_data(page_bytes_to_array(
-                    &BPlusTreeLeafPageCodec::encode(&new_leaf_page),
-                ));
+                new_page
+                    .write()
+                    .unwrap()
+                    .encode_page::<BPlusTreeLeafPageCodec>(&new_leaf_page);
 
                 Ok((new_leaf_page.key_at(0).clone(), new_page_id))
             }
@@ -315,9 +679,10 @@ impl BPlusTreeIndex {
                     internal_page.split_off(internal_page.header.current_size as usize / 2),
                 );
 
-                new_page.write().unwrap().set_data(page_bytes_to_array(
-                    &BPlusTreeInternalPageCodec::encode(&new_internal_page),
-                ));
+                new_page
+                    .write()
+                    .unwrap()
+                    .encode_page::<BPlusTreeInternalPageCodec>(&new_internal_page);
 
                 let min_leafkv = self.find_subtree_min_leafkv(new_page_id)?;
                 Ok((min_leafkv.0, new_page_id))
@@ -350,6 +715,7 @@ impl BPlusTreeIndex {
         borrowed_page_id: PageId,
         min_max: bool,
     ) -> BustubxResult<bool> {
+        let _op = operation_scope("index_borrow");
         let (borrowed_page, mut borrowed_tree_page) = self
             .buffer_pool
             .fetch_tree_page(borrowed_page_id, self.key_schema.clone())?;
@@ -361,7 +727,25 @@ impl BPlusTreeIndex {
             .buffer_pool
             .fetch_tree_page(page_id, self.key_schema.clone())?;
 
-        let (old_internal_key, new_internal_key) = match borrowed_tree_page {
+        // The separator in front of the right one of the two pages changes. It is found by
+        // position, a deleted first key of a page may still be its separator.
+        let (parent_page, mut parent_internal_page) = self
+            .buffer_pool
+            .fetch_tree_internal_page(parent_page_id, self.key_schema.clone())?;
+        let right_page_id = if min_max { borrowed_page_id } else { page_id };
+        let Some(separator_index) = parent_internal_page
+            .array
+            .iter()
+            .position(|kv| kv.1 == right_page_id)
+        else {
+            return Err(BustubxError::Storage(format!(
+                "Page {} is not a child of page {}",
+                right_page_id, parent_page_id
+            )));
+        };
+        let separator = parent_internal_page.key_at(separator_index).clone();
+
+        let new_separator = match borrowed_tree_page {
             BPlusTreePage::Internal(ref mut borrowed_internal_page) => {
                 let BPlusTreePage::Internal(ref mut internal_page) = tree_page else {
                     return Err(BustubxError::Storage(
@@ -369,24 +753,25 @@ impl BPlusTreeIndex {
                     ));
                 };
                 if min_max {
-                    let kv = borrowed_internal_page.reverse_split_off(0).remove(0);
-                    internal_page.insert(kv.0.clone(), kv.1);
-                    (
-                        kv.0,
-                        self.find_subtree_min_leafkv(borrowed_internal_page.value_at(0))?
-                            .0,
+                    // the first child of the right sibling moves down under the separator,
+                    // the key of its second child moves up
+                    let (_, child_page_id) = borrowed_internal_page.reverse_split_off(0).remove(0);
+                    internal_page.insert(separator, child_page_id);
+                    std::mem::replace(
+                        &mut borrowed_internal_page.array[0].0,
+                        Tuple::empty(self.key_schema.clone()),
                     )
                 } else {
-                    let kv = borrowed_internal_page
+                    // the last child of the left sibling becomes the first child
+                    let (key, child_page_id) = borrowed_internal_page
                         .split_off(borrowed_internal_page.header.current_size as usize - 1)
                         .remove(0);
-                    let min_key = internal_page.key_at(0).clone();
-                    internal_page.insert(kv.0.clone(), kv.1);
-                    (
-                        min_key,
-                        self.find_subtree_min_leafkv(borrowed_internal_page.value_at(0))?
-                            .0,
-                    )
+                    internal_page.array[0].0 = separator;
+                    internal_page
+                        .array
+                        .insert(0, (Tuple::empty(self.key_schema.clone()), child_page_id));
+                    internal_page.header.current_size += 1;
+                    key
                 }
             }
             BPlusTreePage::Leaf(ref mut borrowed_leaf_page) => {
@@ -395,60 +780,63 @@ impl BPlusTreeIndex {
                         "Internal page can not borrow from leaf page".to_string(),
                     ));
                 };
+                // moved by position, equal keys keep their order
                 if min_max {
                     let kv = borrowed_leaf_page.reverse_split_off(0).remove(0);
-                    leaf_page.insert(kv.0.clone(), kv.1);
-                    (kv.0, borrowed_leaf_page.key_at(0).clone())
+                    leaf_page.array.push(kv);
+                    leaf_page.header.current_size += 1;
+                    borrowed_leaf_page.key_at(0).clone()
                 } else {
                     let kv = borrowed_leaf_page
                         .split_off(borrowed_leaf_page.header.current_size as usize - 1)
                         .remove(0);
-                    leaf_page.insert(kv.0.clone(), kv.1);
-                    (leaf_page.key_at(1).clone(), leaf_page.key_at(0).clone())
+                    let key = kv.0.clone();
+                    leaf_page.array.insert(0, kv);
+                    leaf_page.header.current_size += 1;
+                    key
                 }
             }
         };
 
         page.write()
             .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreePageCodec::encode(&tree_page)));
+            .encode_page::<BPlusTreePageCodec>(&tree_page);
 
         borrowed_page
             .write()
             .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreePageCodec::encode(
-                &borrowed_tree_page,
-            )));
-
-        // Update parent node
-        let (parent_page, mut parent_internal_page) = self
-            .buffer_pool
-            .fetch_tree_internal_page(parent_page_id, self.key_schema.clone())?;
-        parent_internal_page.replace_key(&old_internal_key, new_internal_key);
+            .encode_page::<BPlusTreePageCodec>(&borrowed_tree_page);
 
-        parent_page.write().unwrap().set_data(page_bytes_to_array(
-            &BPlusTreeInternalPageCodec::encode(&parent_internal_page),
-        ));
+        parent_internal_page.array[separator_index].0 = new_separator;
+        parent_page
+            .write()
+            .unwrap()
+            .encode_page::<BPlusTreeInternalPageCodec>(&parent_internal_page);
         Ok(true)
     }
 
+    // Siblings of the child at `child_index` of the current page of `path`
     fn find_sibling_pages(
         &self,
-        parent_page_id: PageId,
-        child_page_id: PageId,
+        path: &LatchPath,
+        child_index: usize,
     ) -> BustubxResult<(Option<PageId>, Option<PageId>)> {
-        let (_, parent_internal_page) = self
-            .buffer_pool
-            .fetch_tree_internal_page(parent_page_id, self.key_schema.clone())?;
-        Ok(parent_internal_page.sibling_page_ids(child_page_id))
+        let (parent_internal_page, _) = BPlusTreeInternalPageCodec::decode(
+            path.current()?.read().unwrap().data(),
+            self.key_schema.clone(),
+        )?;
+        Ok(parent_internal_page.sibling_page_ids(child_index))
     }
 
+    // Merge the right page into the left one, both children of the current page of `path`
     fn merge(
         &self,
-        parent_page_id: PageId,
+        path: &mut LatchPath,
         left_page_id: PageId,
         right_page_id: PageId,
     ) -> BustubxResult<PageId> {
+        let _op = operation_scope("index_merge");
+        let parent_page_id = path.current_page_id()?;
         let (left_page, mut left_tree_page) = self
             .buffer_pool
             .fetch_tree_page(left_page_id, self.key_schema.clone())?;
@@ -475,8 +863,9 @@ impl BPlusTreeIndex {
             BPlusTreePage::Leaf(ref mut left_leaf_page) => {
                 if let BPlusTreePage::Leaf(ref mut right_leaf_page) = right_tree_page {
                     left_leaf_page.batch_insert(right_leaf_page.array.clone());
-                    // Update next page id
+                    // Update next and previous page ids
                     left_leaf_page.header.next_page_id = right_leaf_page.header.next_page_id;
+                    self.link_prev_leaf(left_leaf_page.header.next_page_id, left_page_id)?;
                 } else {
                     return Err(BustubxError::Storage(
                         "Internal page can not merge from leaf page".to_string(),
@@ -488,17 +877,16 @@ impl BPlusTreeIndex {
         left_page
             .write()
             .unwrap()
-            .set_data(page_bytes_to_array(&BPlusTreePageCodec::encode(
-                &left_tree_page,
-            )));
+            .encode_page::<BPlusTreePageCodec>(&left_tree_page);
 
         // Delete right page
         self.buffer_pool.delete_page(right_page_id)?;
 
         // Update parent node
-        let (parent_page, mut parent_internal_page) = self
-            .buffer_pool
-            .fetch_tree_internal_page(parent_page_id, self.key_schema.clone())?;
+        let (mut parent_internal_page, _) = BPlusTreeInternalPageCodec::decode(
+            path.current()?.read().unwrap().data(),
+            self.key_schema.clone(),
+        )?;
         parent_internal_page.delete_page_id(right_page_id);
 
         // When root node has only one child (leaf), the leaf node becomes the new root
@@ -506,17 +894,59 @@ impl BPlusTreeIndex {
             && parent_internal_page.header.current_size == 1
         {
             self.root_page_id.store(left_page_id, Ordering::SeqCst);
-            // Delete old root node
+            // Delete old root node, unpinned first so its frame is freed
+            path.pop();
             self.buffer_pool.delete_page(parent_page_id)?;
             Ok(left_page_id)
         } else {
-            parent_page.write().unwrap().set_data(page_bytes_to_array(
-                &BPlusTreeInternalPageCodec::encode(&parent_internal_page),
-            ));
+            path.current_write()?
+                .encode_page::<BPlusTreeInternalPageCodec>(&parent_internal_page);
             Ok(parent_page_id)
         }
     }
 
+    // Point leaf `page_id` back at `prev_page_id`, nothing for the end of the leaf chain
+    fn link_prev_leaf(&self, page_id: PageId, prev_page_id: PageId) -> BustubxResult<()> {
+        if page_id == INVALID_PAGE_ID {
+            return Ok(());
+        }
+        let (page, mut leaf_page) = self
+            .buffer_pool
+            .fetch_tree_leaf_page(page_id, self.key_schema.clone())?;
+        leaf_page.header.prev_page_id = Some(prev_page_id);
+        page.write()
+            .unwrap()
+            .encode_page::<BPlusTreeLeafPageCodec>(&leaf_page);
+        Ok(())
+    }
+
+    /// Link every leaf back to the leaf before it, unless the first leaf is linked already.
+    /// Leaves written before the links existed lack them, and a descending scan needs them.
+    /// Returns the number of leaves linked.
+    pub fn link_leaves_back(&self) -> BustubxResult<usize> {
+        let Some(mut page_id) = self.first_leaf_page_id()? else {
+            return Ok(0);
+        };
+        let mut prev_page_id = INVALID_PAGE_ID;
+        let mut linked = 0;
+        while page_id != INVALID_PAGE_ID {
+            let (page, mut leaf_page) = self
+                .buffer_pool
+                .fetch_tree_leaf_page(page_id, self.key_schema.clone())?;
+            if linked == 0 && leaf_page.header.prev_page_id.is_some() {
+                return Ok(0);
+            }
+            leaf_page.header.prev_page_id = Some(prev_page_id);
+            page.write()
+                .unwrap()
+                .encode_page::<BPlusTreeLeafPageCodec>(&leaf_page);
+            linked += 1;
+            prev_page_id = page_id;
+            page_id = leaf_page.header.next_page_id;
+        }
+        Ok(linked)
+    }
+
     // Find the minimum leafKV of the subtree
     fn find_subtree_min_leafkv(&self, page_id: PageId) -> BustubxResult<LeafKV> {
         self.find_subtree_leafkv(page_id, true)
@@ -557,7 +987,113 @@ impl BPlusTreeIndex {
         }
     }
 
-    pub fn get_first_leaf_page(&self) -> BustubxResult<BPlusTreeLeafPage> {
+    /// Entry with the smallest key, None when the tree is empty.
+    pub fn first_entry(&self) -> BustubxResult<Option<LeafKV>> {
+        Ok(self.get_first_leaf_page()?.array.first().cloned())
+    }
+
+    /// Entry with the largest key, the last one of the rightmost leaf.
+    pub fn last_entry(&self) -> BustubxResult<Option<LeafKV>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.find_subtree_max_leafkv(self.root_page_id.load(Ordering::SeqCst))
+            .map(Some)
+    }
+
+    /// Last entry whose key is within the end bound. The descent takes the last child
+    /// the bound admits keys of and backs up to the child on its left when that subtree
+    /// has no such key, which only happens next to a separator.
+    pub fn last_entry_before(&self, end: Bound<&Tuple>) -> BustubxResult<Option<LeafKV>> {
+        if matches!(end, Bound::Unbounded) {
+            return self.last_entry();
+        }
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.find_subtree_last_leafkv(self.root_page_id.load(Ordering::SeqCst), end)
+    }
+
+    fn find_subtree_last_leafkv(
+        &self,
+        page_id: PageId,
+        end: Bound<&Tuple>,
+    ) -> BustubxResult<Option<LeafKV>> {
+        let (_, tree_page) = self
+            .buffer_pool
+            .fetch_tree_page(page_id, self.key_schema.clone())?;
+        match tree_page {
+            BPlusTreePage::Internal(internal_page) => {
+                // keys equal to an included end may continue right of a separator
+                let last_child = match end {
+                    Bound::Included(key) => internal_page.child_index(key),
+                    Bound::Excluded(key) => internal_page.lower_child_index(key),
+                    Bound::Unbounded => internal_page.header.current_size as usize - 1,
+                };
+                for index in (0..=last_child).rev() {
+                    let child_page_id = internal_page.value_at(index);
+                    if let Some(kv) = self.find_subtree_last_leafkv(child_page_id, end)? {
+                        return Ok(Some(kv));
+                    }
+                }
+                Ok(None)
+            }
+            BPlusTreePage::Leaf(leaf_page) => Ok(leaf_page
+                .array
+                .iter()
+                .rev()
+                .find(|(key, _)| match end {
+                    Bound::Included(end) => key <= end,
+                    Bound::Excluded(end) => key < end,
+                    Bound::Unbounded => true,
+                })
+                .cloned()),
+        }
+    }
+
+    // Leaf the first entry not less than the key is in, or a leaf right before it. Equal
+    // keys may span several leaves, so this is where a scan of a key starts.
+    // Smallest key starting with `prefix`, the prefix followed by NULLs, which come before
+    // every value
+    fn prefix_start(&self, prefix: &Tuple) -> BustubxResult<Tuple> {
+        let key_columns = &self.key_schema.columns;
+        let prefix_columns = &prefix.schema.columns;
+        if prefix_columns.len() > key_columns.len()
+            || prefix_columns
+                .iter()
+                .zip(key_columns.iter())
+                .any(|(prefix_column, key_column)| prefix_column.data_type != key_column.data_type)
+        {
+            let names = |columns: &[ColumnRef]| {
+                columns
+                    .iter()
+                    .map(|column| format!("{} {}", column.name, column.data_type))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            return Err(BustubxError::Storage(format!(
+                "prefix ({}) does not match the leading columns of key ({})",
+                names(prefix_columns),
+                names(key_columns)
+            )));
+        }
+        let data = prefix
+            .data
+            .iter()
+            .cloned()
+            .chain(
+                key_columns[prefix_columns.len()..]
+                    .iter()
+                    .map(|column| ScalarValue::new_empty(column.data_type)),
+            )
+            .collect();
+        Ok(Tuple::new(self.key_schema.clone(), data))
+    }
+
+    fn find_lower_leaf_page(&self, key: &Tuple) -> BustubxResult<Option<BPlusTreeLeafPage>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
         let (_, mut curr_tree_page) = self.buffer_pool.fetch_tree_page(
             self.root_page_id.load(Ordering::SeqCst),
             self.key_schema.clone(),
@@ -565,179 +1101,897 @@ impl BPlusTreeIndex {
         loop {
             match curr_tree_page {
                 BPlusTreePage::Internal(internal_page) => {
-                    let next_page_id = internal_page.value_at(0);
+                    let next_page_id = internal_page.value_at(internal_page.lower_child_index(key));
                     let (_, next_tree_page) = self
                         .buffer_pool
                         .fetch_tree_page(next_page_id, self.key_schema.clone())?;
                     curr_tree_page = next_tree_page;
                 }
                 BPlusTreePage::Leaf(leaf_page) => {
-                    return Ok(leaf_page);
+                    return Ok(Some(leaf_page));
                 }
             }
         }
     }
-}
-
-#[derive(Debug)]
-pub struct TreeIndexIterator {
-    index: Arc<BPlusTreeIndex>,
-    start_bound: Bound<Tuple>,
-    end_bound: Bound<Tuple>,
-    leaf_page: BPlusTreeLeafPage,
-    cursor: usize,
-    started: bool,
-}
 
-impl TreeIndexIterator {
-    pub fn new<R: RangeBounds<Tuple>>(index: Arc<BPlusTreeIndex>, range: R) -> Self {
-        Self {
-            index,
-            start_bound: range.start_bound().cloned(),
-            end_bound: range.end_bound().cloned(),
-            leaf_page: BPlusTreeLeafPage::empty(),
-            cursor: 0,
-            started: false,
+    // Leaf the last entry not greater than the key is in, or a leaf right after it. Where
+    // a descending scan of a key starts, the mirror of `find_lower_leaf_page`.
+    fn find_upper_leaf_page(&self, key: &Tuple) -> BustubxResult<Option<BPlusTreeLeafPage>> {
+        let mut path = LatchPath::new();
+        if !self.find_leaf_page(key, &mut path, LatchMode::Read)? {
+            return Ok(None);
         }
+        let (leaf_page, _) = BPlusTreeLeafPageCodec::decode(
+            path.current()?.read().unwrap().data(),
+            self.key_schema.clone(),
+        )?;
+        Ok(Some(leaf_page))
     }
 
-    pub fn load_next_leaf_page(&mut self) -> BustubxResult<bool> {
-        let next_page_id = self.leaf_page.header.next_page_id;
-        if next_page_id == INVALID_PAGE_ID {
-            Ok(false)
+    /// Number of entries with keys between the bounds, what a [`TreeIndexIterator`] over
+    /// the range returns. Only the leaves holding a bound are compared entry by entry, a
+    /// leaf whose successor starts within the end bound is covered by the range and counts
+    /// its `current_size`, so of the leaves in between only the header and first key are
+    /// decoded.
+    pub fn count_range(&self, start: Bound<&Tuple>, end: Bound<&Tuple>) -> BustubxResult<u64> {
+        let _op = operation_scope("index_count");
+        let after_start = |key: &Tuple| match start {
+            Bound::Included(start) => key >= start,
+            Bound::Excluded(start) => key > start,
+            Bound::Unbounded => true,
+        };
+        let before_end = |key: &Tuple| match end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+
+        let mut leaf_page = match start {
+            Bound::Included(key) | Bound::Excluded(key) => match self.find_lower_leaf_page(key)? {
+                Some(leaf_page) => leaf_page,
+                None => return Ok(0),
+            },
+            Bound::Unbounded => {
+                if self.is_empty() {
+                    return Ok(0);
+                }
+                self.get_first_leaf_page()?
+            }
+        };
+        let mut count = 0;
+        // the leaves up to the first one ending after the start bound
+        loop {
+            self.leaf_decodes.fetch_add(1, Ordering::Relaxed);
+            for (key, _) in leaf_page.array.iter() {
+                if !after_start(key) {
+                    continue;
+                }
+                if !before_end(key) {
+                    return Ok(count);
+                }
+                count += 1;
+            }
+            if leaf_page
+                .array
+                .last()
+                .is_some_and(|(key, _)| after_start(key))
+            {
+                break;
+            }
+            if leaf_page.header.next_page_id == INVALID_PAGE_ID {
+                return Ok(count);
+            }
+            leaf_page = self
+                .buffer_pool
+                .fetch_tree_leaf_page(leaf_page.header.next_page_id, self.key_schema.clone())?
+                .1;
+        }
+
+        let mut page_id = leaf_page.header.next_page_id;
+        let mut peeked = None;
+        while page_id != INVALID_PAGE_ID {
+            let (header, first_key) = match peeked.take() {
+                Some(peeked) => peeked,
+                None => self.peek_leaf_page(page_id)?,
+            };
+            let Some(first_key) = first_key else {
+                page_id = header.next_page_id;
+                continue;
+            };
+            if !before_end(&first_key) {
+                break;
+            }
+            if matches!(end, Bound::Unbounded) {
+                count += header.current_size as u64;
+                page_id = header.next_page_id;
+                continue;
+            }
+            if header.next_page_id != INVALID_PAGE_ID {
+                let next = self.peek_leaf_page(header.next_page_id)?;
+                if next.1.as_ref().is_some_and(before_end) {
+                    count += header.current_size as u64;
+                    page_id = header.next_page_id;
+                    peeked = Some(next);
+                    continue;
+                }
+            }
+            // the end bound falls into this leaf
+            let (_, leaf_page) = self
+                .buffer_pool
+                .fetch_tree_leaf_page(page_id, self.key_schema.clone())?;
+            self.leaf_decodes.fetch_add(1, Ordering::Relaxed);
+            count += leaf_page
+                .array
+                .iter()
+                .take_while(|(key, _)| before_end(key))
+                .count() as u64;
+            break;
+        }
+        Ok(count)
+    }
+
+    fn peek_leaf_page(
+        &self,
+        page_id: PageId,
+    ) -> BustubxResult<(BPlusTreeLeafPageHeader, Option<Tuple>)> {
+        let page = self.buffer_pool.fetch_page(page_id)?;
+        let guard = page.read().unwrap();
+        BPlusTreeLeafPageCodec::decode_first_key(guard.data(), self.key_schema.clone())
+    }
+
+    /// Leaves [`Self::count_range`] compared entry by entry.
+    pub fn leaf_decodes(&self) -> u64 {
+        self.leaf_decodes.load(Ordering::Relaxed)
+    }
+
+    /// Page ids of the tree level by level, the root first.
+    pub fn page_ids(&self) -> BustubxResult<Vec<PageId>> {
+        let mut page_ids = vec![];
+        let root_page_id = self.root_page_id.load(Ordering::SeqCst);
+        if root_page_id != INVALID_PAGE_ID {
+            page_ids.push(root_page_id);
+        }
+        let mut next = 0;
+        while next < page_ids.len() {
+            let (_, tree_page) = self
+                .buffer_pool
+                .fetch_tree_page(page_ids[next], self.key_schema.clone())?;
+            if let BPlusTreePage::Internal(internal_page) = tree_page {
+                page_ids.extend(internal_page.values());
+            }
+            next += 1;
+        }
+        Ok(page_ids)
+    }
+
+    /// Leftmost leaf of the tree, an empty leaf without a successor when the tree is empty.
+    pub fn get_first_leaf_page(&self) -> BustubxResult<BPlusTreeLeafPage> {
+        if self.is_empty() {
+            return Ok(BPlusTreeLeafPage::new(
+                self.key_schema.clone(),
+                self.leaf_max_size,
+            ));
+        }
+        let (_, mut curr_tree_page) = self.buffer_pool.fetch_tree_page(
+            self.root_page_id.load(Ordering::SeqCst),
+            self.key_schema.clone(),
+        )?;
+        loop {
+            match curr_tree_page {
+                BPlusTreePage::Internal(internal_page) => {
+                    let next_page_id = internal_page.value_at(0);
+                    let (_, next_tree_page) = self
+                        .buffer_pool
+                        .fetch_tree_page(next_page_id, self.key_schema.clone())?;
+                    curr_tree_page = next_tree_page;
+                }
+                BPlusTreePage::Leaf(leaf_page) => {
+                    return Ok(leaf_page);
+                }
+            }
+        }
+    }
+
+    /// Rightmost leaf of the tree, an empty leaf without a predecessor when the tree is
+    /// empty.
+    pub fn get_last_leaf_page(&self) -> BustubxResult<BPlusTreeLeafPage> {
+        if self.is_empty() {
+            return Ok(BPlusTreeLeafPage::new(
+                self.key_schema.clone(),
+                self.leaf_max_size,
+            ));
+        }
+        let (_, mut curr_tree_page) = self.buffer_pool.fetch_tree_page(
+            self.root_page_id.load(Ordering::SeqCst),
+            self.key_schema.clone(),
+        )?;
+        loop {
+            match curr_tree_page {
+                BPlusTreePage::Internal(internal_page) => {
+                    let next_page_id =
+                        internal_page.value_at(internal_page.header.current_size as usize - 1);
+                    let (_, next_tree_page) = self
+                        .buffer_pool
+                        .fetch_tree_page(next_page_id, self.key_schema.clone())?;
+                    curr_tree_page = next_tree_page;
+                }
+                BPlusTreePage::Leaf(leaf_page) => {
+                    return Ok(leaf_page);
+                }
+            }
+        }
+    }
+
+    fn first_leaf_page_id(&self) -> BustubxResult<Option<PageId>> {
+        let mut page_id = self.root_page_id.load(Ordering::SeqCst);
+        if page_id == INVALID_PAGE_ID {
+            return Ok(None);
+        }
+        loop {
+            let (_, tree_page) = self
+                .buffer_pool
+                .fetch_tree_page(page_id, self.key_schema.clone())?;
+            match tree_page {
+                BPlusTreePage::Internal(internal_page) => page_id = internal_page.value_at(0),
+                BPlusTreePage::Leaf(_) => return Ok(Some(page_id)),
+            }
+        }
+    }
+
+    /// Check the structure of the tree, `object` names the index in the findings. Returns
+    /// the findings and the entries of the leaves in key order with their leaf page id.
+    ///
+    /// Keys are sorted within every page and within the key range the parent gives the page,
+    /// every leaf is at the same depth and the leaf chain links the leaves in key order both
+    /// ways, a leaf written before the backward links existed is not checked for one. A
+    /// page that cannot be decoded or is reached twice is not descended into.
+    pub fn check(&self, object: &str) -> (Vec<Finding>, Vec<(PageId, LeafKV)>) {
+        let mut walk = TreeCheck {
+            object,
+            findings: vec![],
+            visited: HashSet::new(),
+            leaf_depth: None,
+            leaves: vec![],
+            entries: vec![],
+        };
+        let root_page_id = self.root_page_id.load(Ordering::SeqCst);
+        if root_page_id != INVALID_PAGE_ID {
+            self.check_subtree(root_page_id, 0, None, None, &mut walk);
+        }
+        let leaves = std::mem::take(&mut walk.leaves);
+        for pair in leaves.windows(2) {
+            let ((page_id, next_page_id, _), (expected, _, _)) = (pair[0], pair[1]);
+            if next_page_id != expected {
+                walk.error(
+                    page_id,
+                    format!(
+                        "next leaf is page {} instead of page {}",
+                        next_page_id, expected
+                    ),
+                );
+            }
+        }
+        let mut expected_prev = INVALID_PAGE_ID;
+        for &(page_id, _, prev_page_id) in leaves.iter() {
+            if let Some(prev_page_id) = prev_page_id {
+                if prev_page_id != expected_prev {
+                    walk.error(
+                        page_id,
+                        format!(
+                            "previous leaf is page {} instead of page {}",
+                            prev_page_id, expected_prev
+                        ),
+                    );
+                }
+            }
+            expected_prev = page_id;
+        }
+        if let Some(&(page_id, next_page_id, _)) = leaves.last() {
+            if next_page_id != INVALID_PAGE_ID {
+                walk.error(page_id, format!("last leaf links to page {}", next_page_id));
+            }
+        }
+        (walk.findings, walk.entries)
+    }
+
+    // equal keys may span several leaves, so both bounds are inclusive
+    fn check_subtree(
+        &self,
+        page_id: PageId,
+        depth: usize,
+        lower: Option<&Tuple>,
+        upper: Option<&Tuple>,
+        walk: &mut TreeCheck<'_>,
+    ) {
+        if !walk.visited.insert(page_id) {
+            walk.error(page_id, "page is reached twice".to_string());
+            return;
+        }
+        let tree_page = match self
+            .buffer_pool
+            .fetch_tree_page(page_id, self.key_schema.clone())
+        {
+            Ok((_, tree_page)) => tree_page,
+            Err(e) => {
+                walk.error(page_id, format!("page cannot be decoded: {e}"));
+                return;
+            }
+        };
+        let (current_size, keys) = match &tree_page {
+            // the first key of an internal page is empty
+            BPlusTreePage::Internal(internal_page) => (
+                internal_page.header.current_size,
+                internal_page
+                    .array
+                    .iter()
+                    .skip(1)
+                    .map(|kv| &kv.0)
+                    .collect::<Vec<_>>(),
+            ),
+            BPlusTreePage::Leaf(leaf_page) => (
+                leaf_page.header.current_size,
+                leaf_page.array.iter().map(|kv| &kv.0).collect(),
+            ),
+        };
+        let entries = match &tree_page {
+            BPlusTreePage::Internal(internal_page) => internal_page.array.len(),
+            BPlusTreePage::Leaf(leaf_page) => leaf_page.array.len(),
+        };
+        if current_size as usize != entries {
+            walk.error(
+                page_id,
+                format!(
+                    "header counts {} entries, the page holds {}",
+                    current_size, entries
+                ),
+            );
+        }
+        if let Some(i) = (1..keys.len()).find(|&i| keys[i - 1] > keys[i]) {
+            walk.error(page_id, format!("key {} sorts before key {}", i, i - 1));
+        }
+        let out_of_range = keys.iter().position(|key| {
+            lower.is_some_and(|lower| *key < lower) || upper.is_some_and(|upper| *key > upper)
+        });
+        if let Some(i) = out_of_range {
+            walk.error(
+                page_id,
+                format!("key {} is outside of the key range of the parent", i),
+            );
+        }
+
+        match tree_page {
+            BPlusTreePage::Internal(internal_page) => {
+                if internal_page.array.is_empty() {
+                    walk.error(page_id, "internal page has no children".to_string());
+                    return;
+                }
+                let array = &internal_page.array;
+                for (i, (key, child_page_id)) in array.iter().enumerate() {
+                    let child_lower = if i == 0 { lower } else { Some(key) };
+                    let child_upper = array.get(i + 1).map(|kv| &kv.0).or(upper);
+                    self.check_subtree(*child_page_id, depth + 1, child_lower, child_upper, walk);
+                }
+            }
+            BPlusTreePage::Leaf(leaf_page) => {
+                match walk.leaf_depth {
+                    Some(leaf_depth) if leaf_depth != depth => walk.error(
+                        page_id,
+                        format!(
+                            "leaf at depth {}, the first leaf at depth {}",
+                            depth, leaf_depth
+                        ),
+                    ),
+                    Some(_) => {}
+                    None => walk.leaf_depth = Some(depth),
+                }
+                walk.leaves.push((
+                    page_id,
+                    leaf_page.header.next_page_id,
+                    leaf_page.header.prev_page_id,
+                ));
+                walk.entries
+                    .extend(leaf_page.array.into_iter().map(|kv| (page_id, kv)));
+            }
+        }
+    }
+}
+
+// state of `BPlusTreeIndex::check` descending the tree
+struct TreeCheck<'a> {
+    object: &'a str,
+    findings: Vec<Finding>,
+    visited: HashSet<PageId>,
+    leaf_depth: Option<usize>,
+    // (page id, next page id, previous page id) of the leaves in key order
+    leaves: Vec<(PageId, PageId, Option<PageId>)>,
+    entries: Vec<(PageId, LeafKV)>,
+}
+
+impl TreeCheck<'_> {
+    fn error(&mut self, page_id: PageId, description: String) {
+        self.findings
+            .push(Finding::error(self.object, Some(page_id), description));
+    }
+}
+
+// Split `items` into the fewest chunks of at most `max_size` items, chunk sizes differ
+// by at most one so no chunk but a lone root falls under half full.
+fn even_chunks<T>(items: Vec<T>, max_size: usize) -> Vec<Vec<T>> {
+    let chunk_count = items.len().div_ceil(max_size);
+    let mut items = items.into_iter();
+    let mut chunks = Vec::with_capacity(chunk_count);
+    for remaining_chunks in (1..=chunk_count).rev() {
+        let size = items.len().div_ceil(remaining_chunks);
+        chunks.push(items.by_ref().take(size).collect());
+    }
+    chunks
+}
+
+/// Scan of the entries of an index in key order, or in reverse key order when made by
+/// [`TreeIndexIterator::new_desc`].
+///
+/// The iterator works on a copy of one leaf at a time and hands out the record ids of its
+/// entries without copying their keys. Inserts running between two calls of `next` may
+/// split that leaf, leaving the copied positions and next page id stale, so
+/// once the copy is used up the iterator finds its place again by descending to the last
+/// key it returned. No entry present for the whole scan is skipped and no entry is returned
+/// twice, entries inserted meanwhile may or may not be returned. A descending scan walks the
+/// previous page ids of the leaves the same way.
+#[derive(Debug)]
+pub struct TreeIndexIterator {
+    index: Arc<BPlusTreeIndex>,
+    start_bound: Bound<Tuple>,
+    end_bound: Bound<Tuple>,
+    leaf_page: BPlusTreeLeafPage,
+    cursor: usize,
+    started: bool,
+    // the leaf was read during the current call of `next`, its next page id can be trusted
+    leaf_fresh: bool,
+    // last returned key and the entries returned with it, equal keys may repeat. The key is
+    // the one at `last_slot` of the leaf copy, only copied out once the leaf is left
+    last_slot: Option<usize>,
+    last_key: Option<Tuple>,
+    last_key_rids: Vec<RecordId>,
+    // entries returned since the position was last found by a descent
+    returned_since_anchor: usize,
+    // re-descend after this many entries even within a leaf, None only at leaf ends
+    revalidate_interval: Option<usize>,
+    // leading key values every returned key starts with, the scan ends at the first key
+    // that does not
+    prefix: Option<Tuple>,
+    // largest key first, the cursor moves left and wraps past the first entry of a leaf
+    descending: bool,
+}
+
+impl TreeIndexIterator {
+    pub fn new<R: RangeBounds<Tuple>>(index: Arc<BPlusTreeIndex>, range: R) -> Self {
+        Self {
+            index,
+            start_bound: range.start_bound().cloned(),
+            end_bound: range.end_bound().cloned(),
+            leaf_page: BPlusTreeLeafPage::empty(),
+            cursor: 0,
+            started: false,
+            leaf_fresh: false,
+            last_slot: None,
+            last_key: None,
+            last_key_rids: vec![],
+            returned_since_anchor: 0,
+            revalidate_interval: None,
+            prefix: None,
+            descending: false,
+        }
+    }
+
+    /// Entries of `range` from the largest key to the smallest, equal keys in the reverse
+    /// of the order a forward scan returns them.
+    pub fn new_desc<R: RangeBounds<Tuple>>(index: Arc<BPlusTreeIndex>, range: R) -> Self {
+        let mut iterator = Self::new(index, range);
+        iterator.descending = true;
+        iterator
+    }
+
+    /// Entries whose key starts with `prefix`, whose schema is the first columns of the
+    /// key schema or all of them, e.g. every entry with `a = 5` of an `(a, b)` index.
+    pub fn new_prefix(index: Arc<BPlusTreeIndex>, prefix: Tuple) -> BustubxResult<Self> {
+        let start = index.prefix_start(&prefix)?;
+        let mut iterator = Self::new(index, (Bound::Included(start), Bound::Unbounded));
+        iterator.prefix = Some(prefix);
+        Ok(iterator)
+    }
+
+    /// Find the position again by a descent every `entries` returned entries, so a long
+    /// scan also sees entries deleted from or inserted into the leaf it is on. 0 only does
+    /// when it moves to another leaf.
+    pub fn with_revalidate_interval(mut self, entries: usize) -> Self {
+        self.revalidate_interval = (entries > 0).then_some(entries);
+        self
+    }
+
+    /// Go on with the entries of `range`, the next call of `next` descends to its start.
+    /// Entries returned already are not returned again, a range starting before the last
+    /// returned key continues after it.
+    pub fn reset_range<R: RangeBounds<Tuple>>(&mut self, range: R) {
+        self.start_bound = range.start_bound().cloned();
+        self.end_bound = range.end_bound().cloned();
+        self.prefix = None;
+        self.started = false;
+    }
+
+    pub fn load_next_leaf_page(&mut self) -> BustubxResult<bool> {
+        let next_page_id = self.leaf_page.header.next_page_id;
+        if next_page_id == INVALID_PAGE_ID {
+            Ok(false)
         } else {
             let (_, next_leaf_page) = self
                 .index
                 .buffer_pool
                 .fetch_tree_leaf_page(next_page_id, self.index.key_schema.clone())?;
-            self.leaf_page = next_leaf_page;
+            self.set_leaf_page(next_leaf_page);
             Ok(true)
         }
     }
 
+    // Previous leaf of a descending scan, false before the first leaf
+    fn load_prev_leaf_page(&mut self) -> BustubxResult<bool> {
+        let Some(prev_page_id) = self.leaf_page.header.prev_page_id else {
+            return Err(BustubxError::Storage(
+                "index leaf has no link to its previous leaf, it was written before descending scans existed"
+                    .to_string(),
+            ));
+        };
+        if prev_page_id == INVALID_PAGE_ID {
+            return Ok(false);
+        }
+        let (_, prev_leaf_page) = self
+            .index
+            .buffer_pool
+            .fetch_tree_leaf_page(prev_page_id, self.index.key_schema.clone())?;
+        self.set_leaf_page(prev_leaf_page);
+        Ok(true)
+    }
+
+    /// Same as [`TreeIndexIterator::next_rid`].
     pub fn next(&mut self) -> BustubxResult<Option<RecordId>> {
-        if self.started {
-            match self.end_bound.as_ref() {
-                Bound::Included(end_tuple) => {
-                    self.cursor += 1;
-                    let end_tuple = end_tuple.clone();
-                    let kv = if self.cursor >= self.leaf_page.header.current_size as usize {
-                        if self.load_next_leaf_page()? {
-                            self.cursor = 0;
-                            self.leaf_page.array[self.cursor].clone()
-                        } else {
-                            return Ok(None);
-                        }
-                    } else {
-                        self.leaf_page.array[self.cursor].clone()
-                    };
-                    if kv.0 <= end_tuple {
-                        Ok(Some(kv.1))
-                    } else {
-                        Ok(None)
-                    }
-                }
-                Bound::Excluded(end_tuple) => {
-                    self.cursor += 1;
-                    let end_tuple = end_tuple.clone();
-                    let kv = if self.cursor >= self.leaf_page.header.current_size as usize {
-                        if self.load_next_leaf_page()? {
-                            self.cursor = 0;
-                            self.leaf_page.array[self.cursor].clone()
-                        } else {
-                            return Ok(None);
-                        }
-                    } else {
-                        self.leaf_page.array[self.cursor].clone()
-                    };
-                    if kv.0 < end_tuple {
-                        Ok(Some(kv.1))
-                    } else {
-                        Ok(None)
+        self.next_rid()
+    }
+
+    /// Record id of the next entry, its key is only compared with the bounds.
+    pub fn next_rid(&mut self) -> BustubxResult<Option<RecordId>> {
+        Ok(self.advance()?.then(|| self.leaf_page.array[self.cursor].1))
+    }
+
+    /// Key and record id of the next entry, for a caller that needs the key, the key is
+    /// copied out of the leaf.
+    pub fn next_entry(&mut self) -> BustubxResult<Option<(Tuple, RecordId)>> {
+        Ok(self
+            .advance()?
+            .then(|| self.leaf_page.array[self.cursor].clone()))
+    }
+
+    // Move the cursor onto the next entry in range, false after the last one
+    fn advance(&mut self) -> BustubxResult<bool> {
+        if self.descending {
+            return self.advance_desc();
+        }
+        self.leaf_fresh = false;
+        if !self.started {
+            self.started = true;
+            if !self.seek_start()? {
+                return Ok(false);
+            }
+        } else if self
+            .revalidate_interval
+            .is_some_and(|interval| self.returned_since_anchor >= interval)
+        {
+            self.reanchor()?;
+        } else {
+            self.cursor += 1;
+        }
+        loop {
+            if !self.seek_entry()? {
+                return Ok(false);
+            }
+            let (key, rid) = &self.leaf_page.array[self.cursor];
+            let rid = *rid;
+            // a descent lands before the last returned entry
+            // keys equal in the order of their collations, not only bytewise
+            let same_key = match self.last_key() {
+                Some(last_key) => match key.partial_cmp(last_key) {
+                    Some(std::cmp::Ordering::Less) => {
+                        self.cursor += 1;
+                        continue;
                     }
-                }
-                Bound::Unbounded => {
-                    self.cursor += 1;
-                    if self.cursor >= self.leaf_page.header.current_size as usize {
-                        if self.load_next_leaf_page()? {
-                            self.cursor = 0;
-                            Ok(Some(self.leaf_page.array[self.cursor].1))
-                        } else {
-                            Ok(None)
-                        }
-                    } else {
-                        Ok(Some(self.leaf_page.array[self.cursor].1))
+                    Some(std::cmp::Ordering::Equal) if self.last_key_rids.contains(&rid) => {
+                        self.cursor += 1;
+                        continue;
                     }
-                }
+                    order => order == Some(std::cmp::Ordering::Equal),
+                },
+                None => false,
+            };
+            let before_end = match self.end_bound.as_ref() {
+                Bound::Included(end_tuple) => key <= end_tuple,
+                Bound::Excluded(end_tuple) => key < end_tuple,
+                Bound::Unbounded => true,
+            };
+            let in_prefix = self
+                .prefix
+                .as_ref()
+                .is_none_or(|prefix| key.compare_prefix(prefix) == Some(std::cmp::Ordering::Equal));
+            if !before_end || !in_prefix {
+                return Ok(false);
             }
-        } else {
+            if !same_key {
+                self.last_key_rids.clear();
+            }
+            self.last_key_rids.push(rid);
+            self.last_slot = Some(self.cursor);
+            self.last_key = None;
+            self.returned_since_anchor += 1;
+            return Ok(true);
+        }
+    }
+
+    // `advance` of a descending scan, from the end bound down to the start bound
+    fn advance_desc(&mut self) -> BustubxResult<bool> {
+        self.leaf_fresh = false;
+        if !self.started {
             self.started = true;
-            match self.start_bound.as_ref() {
-                Bound::Included(start_tuple) => {
-                    let mut context = Context::new(self.index.root_page_id.load(Ordering::SeqCst));
-                    let Some(leaf_page) = self.index.find_leaf_page(start_tuple, &mut context)?
-                    else {
-                        return Ok(None);
-                    };
-                    self.leaf_page = BPlusTreeLeafPageCodec::decode(
-                        leaf_page.read().unwrap().data(),
-                        self.index.key_schema.clone(),
-                    )?
-                    .0;
-                    if let Some(idx) = self.leaf_page.next_closest(start_tuple, true) {
-                        self.cursor = idx;
-                        Ok(Some(self.leaf_page.array[self.cursor].1))
-                    } else if self.load_next_leaf_page()? {
-                        self.cursor = 0;
-                        Ok(Some(self.leaf_page.array[self.cursor].1))
-                    } else {
-                        Ok(None)
+            self.seek_end()?;
+        } else if self
+            .revalidate_interval
+            .is_some_and(|interval| self.returned_since_anchor >= interval)
+        {
+            self.reanchor_desc()?;
+        } else {
+            self.cursor = self.cursor.wrapping_sub(1);
+        }
+        loop {
+            if !self.seek_entry_desc()? {
+                return Ok(false);
+            }
+            let (key, rid) = &self.leaf_page.array[self.cursor];
+            let rid = *rid;
+            // keys equal to an excluded end may continue left of the leaf it routes to
+            let before_end = match self.end_bound.as_ref() {
+                Bound::Included(end_tuple) => key <= end_tuple,
+                Bound::Excluded(end_tuple) => key < end_tuple,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                self.cursor = self.cursor.wrapping_sub(1);
+                continue;
+            }
+            // a descent lands after the last returned entry
+            let same_key = match self.last_key() {
+                Some(last_key) => match key.partial_cmp(last_key) {
+                    Some(std::cmp::Ordering::Greater) => {
+                        self.cursor = self.cursor.wrapping_sub(1);
+                        continue;
                     }
-                }
-                Bound::Excluded(start_tuple) => {
-                    let mut context = Context::new(self.index.root_page_id.load(Ordering::SeqCst));
-                    let Some(leaf_page) = self.index.find_leaf_page(start_tuple, &mut context)?
-                    else {
-                        return Ok(None);
-                    };
-                    self.leaf_page = BPlusTreeLeafPageCodec::decode(
-                        leaf_page.read().unwrap().data(),
-                        self.index.key_schema.clone(),
-                    )?
-                    .0;
-                    if let Some(idx) = self.leaf_page.next_closest(start_tuple, false) {
-                        self.cursor = idx;
-                        Ok(Some(self.leaf_page.array[self.cursor].1))
-                    } else if self.load_next_leaf_page()? {
-                        self.cursor = 0;
-                        Ok(Some(self.leaf_page.array[self.cursor].1))
-                    } else {
-                        Ok(None)
+                    Some(std::cmp::Ordering::Equal) if self.last_key_rids.contains(&rid) => {
+                        self.cursor = self.cursor.wrapping_sub(1);
+                        continue;
                     }
-                }
-                Bound::Unbounded => {
-                    self.leaf_page = self.index.get_first_leaf_page()?;
-                    self.cursor = 0;
-                    Ok(Some(self.leaf_page.array[self.cursor].1))
-                }
+                    order => order == Some(std::cmp::Ordering::Equal),
+                },
+                None => false,
+            };
+            let after_start = match self.start_bound.as_ref() {
+                Bound::Included(start_tuple) => key >= start_tuple,
+                Bound::Excluded(start_tuple) => key > start_tuple,
+                Bound::Unbounded => true,
+            };
+            if !after_start {
+                return Ok(false);
             }
+            if !same_key {
+                self.last_key_rids.clear();
+            }
+            self.last_key_rids.push(rid);
+            self.last_slot = Some(self.cursor);
+            self.last_key = None;
+            self.returned_since_anchor += 1;
+            return Ok(true);
+        }
+    }
+
+    fn last_key(&self) -> Option<&Tuple> {
+        match self.last_slot {
+            Some(slot) => Some(&self.leaf_page.array[slot].0),
+            None => self.last_key.as_ref(),
         }
     }
+
+    // Replace the leaf copy, keeping a copy of the last returned key if it came from the
+    // old one
+    fn set_leaf_page(&mut self, leaf_page: BPlusTreeLeafPage) {
+        if let Some(slot) = self.last_slot.take() {
+            self.last_key = Some(self.leaf_page.array[slot].0.clone());
+        }
+        self.leaf_page = leaf_page;
+        self.leaf_fresh = true;
+    }
+
+    // Read the leaf of the last returned key again and place the cursor on the first
+    // entry with that key, `next` skips the entries returned already.
+    fn reanchor(&mut self) -> BustubxResult<()> {
+        let Some(last_key) = self.last_key() else {
+            return Ok(());
+        };
+        let leaf_page = self
+            .index
+            .find_lower_leaf_page(last_key)?
+            .unwrap_or_else(BPlusTreeLeafPage::empty);
+        self.set_leaf_page(leaf_page);
+        let last_key = self.last_key.as_ref().unwrap();
+        self.cursor = self.leaf_page.array.partition_point(|kv| kv.0 < *last_key);
+        self.returned_since_anchor = 0;
+        Ok(())
+    }
+
+    // Read the leaf of the last returned key again and place the cursor on the last entry
+    // with that key, `next` skips the entries returned already.
+    fn reanchor_desc(&mut self) -> BustubxResult<()> {
+        let Some(last_key) = self.last_key() else {
+            return Ok(());
+        };
+        let leaf_page = self
+            .index
+            .find_upper_leaf_page(last_key)?
+            .unwrap_or_else(BPlusTreeLeafPage::empty);
+        self.set_leaf_page(leaf_page);
+        let last_key = self.last_key.as_ref().unwrap();
+        self.cursor = self
+            .leaf_page
+            .array
+            .partition_point(|kv| kv.0 <= *last_key)
+            .wrapping_sub(1);
+        self.returned_since_anchor = 0;
+        Ok(())
+    }
+
+    // Place the cursor of a descending scan on the last entry before the end bound, or
+    // past the front of its leaf when the leaf has none.
+    fn seek_end(&mut self) -> BustubxResult<()> {
+        let leaf_page = match self.end_bound.as_ref() {
+            Bound::Unbounded => Some(self.index.get_last_leaf_page()?),
+            // entries within the end continue into the leaves before the one it routes to,
+            // which are reached through the previous page ids
+            Bound::Included(end_tuple) | Bound::Excluded(end_tuple) => {
+                self.index.find_upper_leaf_page(end_tuple)?
+            }
+        };
+        self.set_leaf_page(leaf_page.unwrap_or_else(BPlusTreeLeafPage::empty));
+        let in_range = match self.end_bound.as_ref() {
+            Bound::Included(end_tuple) => self
+                .leaf_page
+                .array
+                .partition_point(|kv| kv.0 <= *end_tuple),
+            Bound::Excluded(end_tuple) => {
+                self.leaf_page.array.partition_point(|kv| kv.0 < *end_tuple)
+            }
+            Bound::Unbounded => self.leaf_page.array.len(),
+        };
+        self.cursor = in_range.wrapping_sub(1);
+        Ok(())
+    }
+
+    // Keep the cursor of a descending scan on an entry, moving to the previous leaves once
+    // the cursor passed the front of the current one. False before the first leaf.
+    fn seek_entry_desc(&mut self) -> BustubxResult<bool> {
+        while self.cursor >= self.leaf_page.header.current_size as usize {
+            if !self.leaf_fresh && self.last_key().is_some() {
+                // the copy may predate a split, its previous page id can skip the new page
+                self.reanchor_desc()?;
+                continue;
+            }
+            if !self.load_prev_leaf_page()? {
+                return Ok(false);
+            }
+            self.cursor = (self.leaf_page.header.current_size as usize).wrapping_sub(1);
+        }
+        Ok(true)
+    }
+
+    // Place the cursor on the first entry after the start bound, false if there is none.
+    fn seek_start(&mut self) -> BustubxResult<bool> {
+        let (start_tuple, included) = match self.start_bound.as_ref() {
+            Bound::Included(start_tuple) => (start_tuple.clone(), true),
+            Bound::Excluded(start_tuple) => (start_tuple.clone(), false),
+            Bound::Unbounded => {
+                let leaf_page = self.index.get_first_leaf_page()?;
+                self.set_leaf_page(leaf_page);
+                self.cursor = 0;
+                // the first leaf is empty in an empty tree, or once its entries are deleted
+                return self.seek_entry();
+            }
+        };
+        let leaf_page = if included {
+            // entries equal to the start may begin in leaves before the one the key routes to
+            match self.index.find_lower_leaf_page(&start_tuple)? {
+                Some(leaf_page) => leaf_page,
+                None => return Ok(false),
+            }
+        } else {
+            let mut path = LatchPath::new();
+            if !self
+                .index
+                .find_leaf_page(&start_tuple, &mut path, LatchMode::Read)?
+            {
+                return Ok(false);
+            }
+            let (leaf_page, _) = BPlusTreeLeafPageCodec::decode(
+                path.current()?.read().unwrap().data(),
+                self.index.key_schema.clone(),
+            )?;
+            leaf_page
+        };
+        self.set_leaf_page(leaf_page);
+        self.cursor = self
+            .leaf_page
+            .next_closest(&start_tuple, included)
+            .unwrap_or(self.leaf_page.header.current_size as usize);
+
+        // the routed leaf may hold only keys before the start, so the bound is checked on the
+        // entry finally selected whichever leaf it comes from
+        while self.seek_entry()? {
+            let key = &self.leaf_page.array[self.cursor].0;
+            let after_start = if included {
+                *key >= start_tuple
+            } else {
+                *key > start_tuple
+            };
+            if after_start {
+                return Ok(true);
+            }
+            self.cursor += 1;
+        }
+        Ok(false)
+    }
+
+    // Keep the cursor on an entry, moving to the following leaves once the cursor passed
+    // the end of the current one. False after the last leaf.
+    fn seek_entry(&mut self) -> BustubxResult<bool> {
+        while self.cursor >= self.leaf_page.header.current_size as usize {
+            if !self.leaf_fresh && self.last_key().is_some() {
+                // the copy may predate a split, its next page id can skip the new sibling
+                self.reanchor()?;
+                continue;
+            }
+            if !self.load_next_leaf_page()? {
+                return Ok(false);
+            }
+            self.cursor = 0;
+        }
+        Ok(true)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
     use std::ops::Bound;
-    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
     use tempfile::TempDir;
 
+    use crate::buffer::{PageId, INVALID_PAGE_ID};
     use crate::catalog::SchemaRef;
-    use crate::common::util::pretty_format_index_tree;
+    use crate::common::alloc_count::allocations;
+    use crate::common::index_tree::pretty_format_index_tree_with_limits;
+    use crate::common::page_leaks::assert_no_index_page_leaks;
+    use crate::common::ScalarValue;
+    use crate::storage::codec::BPlusTreeLeafPageCodec;
     use crate::storage::index::TreeIndexIterator;
     use crate::{
         buffer::BufferPoolManager,
         catalog::{Column, DataType, Schema},
-        storage::{DiskManager, RecordId, Tuple},
+        storage::{BPlusTreePage, DiskManager, RecordId, Tuple},
+        BustubxError,
     };
 
     use super::BPlusTreeIndex;
@@ -823,12 +2077,23 @@ mod tests {
         (index, key_schema)
     }
 
+    fn collect_rids(index: Arc<BPlusTreeIndex>) -> Vec<RecordId> {
+        let mut iterator = TreeIndexIterator::new(index, ..);
+        let mut rids = vec![];
+        while let Some(rid) = iterator.next().unwrap() {
+            rids.push(rid);
+        }
+        rids
+    }
+
     #[test]
     pub fn test_index_insert() {
         let (index, _) = build_index();
-        let display = pretty_format_index_tree(&index).unwrap();
+        let display = pretty_format_index_tree_with_limits(&index, 1, usize::MAX).unwrap();
         println!("{display}");
-        assert_eq!(display, "B+ Tree Level No.1:
+        assert_eq!(
+            display,
+            "B+ Tree Level No.1:
 +-----------------------+
 | page_id=13, size: 2/4 |
 +-----------------------+
@@ -838,77 +2103,161 @@ mod tests {
 | | 8          | 12   | |
 | +------------+------+ |
 +-----------------------+
-B+ Tree Level No.2:
-+-----------------------+------------------------+
-| page_id=8, size: 2/4  | page_id=12, size: 3/4  |
-+-----------------------+------------------------+
-| +------------+------+ | +------+------+------+ |
-| | NULL, NULL | 3, 3 | | | 5, 5 | 7, 7 | 9, 9 | |
-| +------------+------+ | +------+------+------+ |
-| | 6          | 7    | | | 9    | 10   | 11   | |
-| +------------+------+ | +------+------+------+ |
-+-----------------------+------------------------+
-B+ Tree Level No.3:
-+--------------------------------------+--------------------------------------+---------------------------------------+----------------------------------------+---------------------------------------+
-| page_id=6, size: 2/4, next_page_id=7 | page_id=7, size: 2/4, next_page_id=9 | page_id=9, size: 2/4, next_page_id=10 | page_id=10, size: 2/4, next_page_id=11 | page_id=11, size: 3/4, next_page_id=0 |
-+--------------------------------------+--------------------------------------+---------------------------------------+----------------------------------------+---------------------------------------+
-| +------+------+                      | +------+------+                      | +------+------+                       | +------+------+                        | +------+--------+--------+            |
-| | 1, 1 | 2, 2 |                      | | 3, 3 | 4, 4 |                      | | 5, 5 | 6, 6 |                       | | 7, 7 | 8, 8 |                        | | 9, 9 | 10, 10 | 11, 11 |            |
-| +------+------+                      | +------+------+                      | +------+------+                       | +------+------+                        | +------+--------+--------+            |
-| | 1-1  | 2-2  |                      | | 3-3  | 4-4  |                      | | 5-5  | 6-6  |                       | | 7-7  | 8-8  |                        | | 9-9  | 10-10  | 11-11  |            |
-| +------+------+                      | +------+------+                      | +------+------+                       | +------+------+                        | +------+--------+--------+            |
-+--------------------------------------+--------------------------------------+---------------------------------------+----------------------------------------+---------------------------------------+
-");
+...
+"
+        );
+
+        let display = pretty_format_index_tree_with_limits(&index, usize::MAX, usize::MAX).unwrap();
+        assert_eq!(display.matches("B+ Tree Level No.").count(), 3);
+        assert_eq!(
+            collect_rids(Arc::new(index)),
+            (1..=11).map(|i| RecordId::new(i, i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn test_index_tree_display_limits() {
+        let (index, _) = build_index();
+        let display = pretty_format_index_tree_with_limits(&index, 3, 2).unwrap();
+        println!("{display}");
+        assert_eq!(display.matches("B+ Tree Level No.").count(), 3);
+        // leaf level has 5 pages, only the first 2 are printed
+        assert!(display.contains("page_id=6,"));
+        assert!(display.contains("page_id=7,"));
+        assert!(!display.contains("page_id=9,"));
+        assert!(display.contains("..."));
+
+        let display = pretty_format_index_tree_with_limits(&index, 2, usize::MAX).unwrap();
+        assert_eq!(display.matches("B+ Tree Level No.").count(), 2);
+        assert!(display.ends_with("...\n"));
+    }
+
+    #[test]
+    pub fn test_index_delete() {
+        let (index, key_schema) = build_index();
+
+        // the pages emptied by merges are freed
+        assert_no_index_page_leaks(&index, |index| {
+            for i in [3i8, 10, 8] {
+                index
+                    .delete(&Tuple::new(
+                        key_schema.clone(),
+                        vec![i.into(), (i as i16).into()],
+                    ))
+                    .unwrap();
+                println!(
+                    "{}",
+                    pretty_format_index_tree_with_limits(index, usize::MAX, usize::MAX).unwrap()
+                );
+            }
+        });
+
+        for i in [3i8, 10, 8] {
+            assert_eq!(
+                index
+                    .get(&Tuple::new(
+                        key_schema.clone(),
+                        vec![i.into(), (i as i16).into()],
+                    ))
+                    .unwrap(),
+                None
+            );
+        }
+        let display = pretty_format_index_tree_with_limits(&index, usize::MAX, usize::MAX).unwrap();
+        assert_eq!(display.matches("B+ Tree Level No.").count(), 2);
+        // the latch path released every page, only this fetch pins the root
+        let root_page = index
+            .buffer_pool
+            .fetch_page(index.root_page_id.load(Ordering::SeqCst))
+            .unwrap();
+        assert_eq!(root_page.pin_count(), 1);
+        drop(root_page);
+        assert_eq!(
+            collect_rids(Arc::new(index)),
+            [1, 2, 4, 5, 6, 7, 9, 11]
+                .into_iter()
+                .map(|i| RecordId::new(i, i))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn test_index_delete_all_frees_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let index = BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4);
+        let key = |i: i32| Tuple::new(key_schema.clone(), vec![i.into()]);
+        for i in 0..200 {
+            index.insert(&key(i), RecordId::new(i as u32, 0)).unwrap();
+        }
+        assert!(index.page_ids().unwrap().len() > 50);
+
+        // every merge and root collapse on the way down to a single leaf frees its pages
+        assert_no_index_page_leaks(&index, |index| {
+            for i in (0..200).filter(|i| i % 3 != 0).chain((0..200).step_by(3)) {
+                assert!(index.delete(&key(i)).unwrap());
+            }
+        });
+        assert!(index.page_ids().unwrap().len() <= 1);
     }
 
     #[test]
-    pub fn test_index_delete() {
+    pub fn test_index_delete_reports_removal() {
         let (index, key_schema) = build_index();
+        let key = |i: i8| Tuple::new(key_schema.clone(), vec![i.into(), (i as i16).into()]);
+        let before = page_hashes(&index);
+        // missing keys inside the range of the tree and beyond its ends
+        for i in [0i8, 7, 12] {
+            let missing = Tuple::new(key_schema.clone(), vec![i.into(), 100i16.into()]);
+            assert!(!index.delete(&missing).unwrap());
+        }
+        assert!(!index.delete(&key(12)).unwrap());
+        assert_eq!(page_hashes(&index), before);
 
-        index
-            .delete(&Tuple::new(
-                key_schema.clone(),
-                vec![3i8.into(), 3i16.into()],
-            ))
-            .unwrap();
-        println!("{}", pretty_format_index_tree(&index).unwrap());
-        index
-            .delete(&Tuple::new(
-                key_schema.clone(),
-                vec![10i8.into(), 10i16.into()],
-            ))
-            .unwrap();
-        println!("{}", pretty_format_index_tree(&index).unwrap());
-        index
-            .delete(&Tuple::new(
-                key_schema.clone(),
-                vec![8i8.into(), 8i16.into()],
-            ))
-            .unwrap();
-        println!("{}", pretty_format_index_tree(&index).unwrap());
-
-        assert_eq!(pretty_format_index_tree(&index).unwrap(),
-                   "B+ Tree Level No.1:
-+------------------------------+
-| page_id=8, size: 3/4         |
-+------------------------------+
-| +------------+------+------+ |
-| | NULL, NULL | 5, 5 | 7, 7 | |
-| +------------+------+------+ |
-| | 6          | 9    | 10   | |
-| +------------+------+------+ |
-+------------------------------+
-B+ Tree Level No.2:
-+--------------------------------------+---------------------------------------+---------------------------------------+
-| page_id=6, size: 3/4, next_page_id=9 | page_id=9, size: 2/4, next_page_id=10 | page_id=10, size: 3/4, next_page_id=0 |
-+--------------------------------------+---------------------------------------+---------------------------------------+
-| +------+------+------+               | +------+------+                       | +------+------+--------+              |
-| | 1, 1 | 2, 2 | 4, 4 |               | | 5, 5 | 6, 6 |                       | | 7, 7 | 9, 9 | 11, 11 |              |
-| +------+------+------+               | +------+------+                       | +------+------+--------+              |
-| | 1-1  | 2-2  | 4-4  |               | | 5-5  | 6-6  |                       | | 7-7  | 9-9  | 11-11  |              |
-| +------+------+------+               | +------+------+                       | +------+------+--------+              |
-+--------------------------------------+---------------------------------------+---------------------------------------+
-");
+        let empty = BPlusTreeIndex::new(key_schema.clone(), index.buffer_pool.clone(), 4, 4);
+        assert!(!empty.delete(&key(1)).unwrap());
+        assert!(empty.is_empty());
+
+        assert_no_index_page_leaks(&index, |index| {
+            for i in 1..=11 {
+                assert!(index.delete(&key(i)).unwrap());
+                assert!(!index.delete(&key(i)).unwrap());
+            }
+        });
+        assert_eq!(collect_rids(Arc::new(index)), vec![]);
+    }
+
+    #[test]
+    pub fn test_index_delete_entry_of_duplicate_key() {
+        let (index, key_schema) = build_index();
+        let key = |i: i8| Tuple::new(key_schema.clone(), vec![i.into(), (i as i16).into()]);
+        // the run of 6s spans several leaves, around the entry of 6 built with the tree
+        for i in 100..110 {
+            index.insert(&key(6), RecordId::new(i, i)).unwrap();
+        }
+        let display = pretty_format_index_tree_with_limits(&index, usize::MAX, usize::MAX).unwrap();
+        assert!(display.matches("| 6, 6 |").count() > 1, "{display}");
+
+        assert!(!index.delete_entry(&key(6), RecordId::new(7, 7)).unwrap());
+        assert!(!index.delete_entry(&key(7), RecordId::new(6, 6)).unwrap());
+        assert_no_index_page_leaks(&index, |index| {
+            for i in [100, 6, 109, 104] {
+                assert!(index.delete_entry(&key(6), RecordId::new(i, i)).unwrap());
+                assert!(!index.delete_entry(&key(6), RecordId::new(i, i)).unwrap());
+            }
+        });
+
+        let mut expected = (1..=11)
+            .filter(|i| *i != 6)
+            .map(|i| RecordId::new(i, i))
+            .collect::<Vec<_>>();
+        expected.extend([101, 102, 103, 105, 106, 107, 108].map(|i| RecordId::new(i, i)));
+        let mut rids = collect_rids(Arc::new(index));
+        rids.sort_by_key(|rid| rid.page_id);
+        expected.sort_by_key(|rid| rid.page_id);
+        assert_eq!(rids, expected);
     }
 
     #[test]
@@ -969,4 +2318,812 @@ B+ Tree Level No.2:
         assert_eq!(iterator4.next().unwrap(), None);
         assert_eq!(iterator4.next().unwrap(), None);
     }
+
+    #[test]
+    pub fn test_index_prefix_scan() {
+        let (index, key_schema) = build_index();
+        let index = Arc::new(index);
+        let key = |a: i8, b: i16| Tuple::new(key_schema.clone(), vec![a.into(), b.into()]);
+        // the entries of a = 5 span several leaves, with a NULL b before all the others
+        for b in (100..120).rev() {
+            index
+                .insert(&key(5, b), RecordId::new(b as u32, 0))
+                .unwrap();
+        }
+        let null_b = Tuple::new(
+            key_schema.clone(),
+            vec![5i8.into(), ScalarValue::Int16(None)],
+        );
+        index.insert(&null_b, RecordId::new(99, 0)).unwrap();
+
+        let prefix_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int8, false)]));
+        let scan = |prefix: Tuple| {
+            let mut iterator = TreeIndexIterator::new_prefix(index.clone(), prefix).unwrap();
+            let mut entries = vec![];
+            while let Some(entry) = iterator.next_entry().unwrap() {
+                entries.push(entry);
+            }
+            entries
+        };
+
+        let entries = scan(Tuple::new(prefix_schema.clone(), vec![5i8.into()]));
+        let mut expected = vec![
+            (null_b, RecordId::new(99, 0)),
+            (key(5, 5), RecordId::new(5, 5)),
+        ];
+        expected.extend((100..120).map(|b| (key(5, b), RecordId::new(b as u32, 0))));
+        assert_eq!(entries, expected);
+        // more entries than two leaves hold
+        assert!(entries.len() > 2 * index.leaf_max_size as usize);
+
+        // prefixes before, between and after the keys of the tree
+        for a in [0i8, 12, 127] {
+            assert_eq!(
+                scan(Tuple::new(prefix_schema.clone(), vec![a.into()])),
+                vec![]
+            );
+        }
+        assert_eq!(
+            scan(key(5, 110)),
+            vec![(key(5, 110), RecordId::new(110, 0))]
+        );
+        assert_eq!(scan(key(5, 6)), vec![]);
+        assert_eq!(
+            scan(Tuple::new(prefix_schema.clone(), vec![11i8.into()])),
+            vec![(key(11, 11), RecordId::new(11, 11))]
+        );
+
+        let wrong_type = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        assert!(TreeIndexIterator::new_prefix(
+            index.clone(),
+            Tuple::new(wrong_type, vec![5i32.into()])
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_index_iterator_on_empty_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(100, Arc::new(disk_manager)));
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let key = Tuple::new(key_schema.clone(), vec![5i32.into()]);
+
+        let assert_empty = |index: &Arc<BPlusTreeIndex>| {
+            let leaf_page = index.get_first_leaf_page().unwrap();
+            assert_eq!(leaf_page.header.current_size, 0);
+            assert_eq!(leaf_page.header.next_page_id, INVALID_PAGE_ID);
+            // an unbounded start reads the first leaf, not the iterator's placeholder leaf
+            let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+            assert_eq!(iterator.next().unwrap(), None);
+            assert_eq!(iterator.next().unwrap(), None);
+            let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+            assert_eq!(iterator.next_entry().unwrap(), None);
+            for range in [
+                (Bound::Unbounded, Bound::Included(key.clone())),
+                (Bound::Included(key.clone()), Bound::Unbounded),
+                (Bound::Excluded(key.clone()), Bound::Excluded(key.clone())),
+            ] {
+                let mut iterator = TreeIndexIterator::new(index.clone(), range);
+                assert_eq!(iterator.next().unwrap(), None);
+            }
+            assert_eq!(
+                index
+                    .count_range(Bound::Unbounded, Bound::Unbounded)
+                    .unwrap(),
+                0
+            );
+        };
+        assert!(index.is_empty());
+        assert_empty(&index);
+
+        // a tree whose last entry was deleted
+        index.insert(&key, RecordId::new(5, 5)).unwrap();
+        assert!(index.delete(&key).unwrap());
+        assert_empty(&index);
+    }
+
+    #[test]
+    pub fn test_index_iterator_rids_without_key_copies() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 64, 64));
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+        for key in 0..12_000 {
+            index
+                .insert(&tuple(key), RecordId::new(key as u32, 0))
+                .unwrap();
+        }
+
+        let range = tuple(1000)..tuple(11_000);
+        let before = allocations();
+        let mut iterator = TreeIndexIterator::new(index.clone(), range.clone());
+        let mut rids = vec![];
+        while let Some(rid) = iterator.next_rid().unwrap() {
+            rids.push(rid);
+        }
+        let rid_allocations = allocations() - before;
+        assert_eq!(rids.len(), 10_000);
+        assert_eq!(rids[0], RecordId::new(1000, 0));
+
+        rids.clear();
+        let before = allocations();
+        let mut iterator = TreeIndexIterator::new(index.clone(), range);
+        while let Some((key, rid)) = iterator.next_entry().unwrap() {
+            assert_eq!(key.data[0], ScalarValue::Int32(Some(rid.page_id as i32)));
+            rids.push(rid);
+        }
+        let entry_allocations = allocations() - before;
+        assert_eq!(rids.len(), 10_000);
+
+        // both decode the same leaves, only the keys handed out are copied
+        assert!(
+            entry_allocations >= rid_allocations + 9_000,
+            "{rid_allocations} allocations returning rids, {entry_allocations} returning keys"
+        );
+    }
+
+    #[test]
+    pub fn test_index_iterator_concurrent_splits() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let tuple = move |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+
+        // every key present before the scan, the rid page id is the key
+        let keys = (0..100).map(|i| i * 10).collect::<Vec<i32>>();
+        for key in keys.iter() {
+            index
+                .insert(&tuple(*key), RecordId::new(*key as u32, 0))
+                .unwrap();
+        }
+
+        let (step_tx, step_rx) = mpsc::channel::<i32>();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let inserter = {
+            let index = index.clone();
+            thread::spawn(move || {
+                // keys right after the one the scan just returned land in its leaf and split it
+                while let Ok(returned) = step_rx.recv() {
+                    for offset in 1..10 {
+                        let key = returned + offset;
+                        index
+                            .insert(&tuple(key), RecordId::new(key as u32, 0))
+                            .unwrap();
+                    }
+                    done_tx.send(()).unwrap();
+                }
+            })
+        };
+
+        for revalidate_interval in [0, 3] {
+            let mut iterator = TreeIndexIterator::new(index.clone(), ..)
+                .with_revalidate_interval(revalidate_interval);
+            let mut returned = vec![];
+            while let Some(rid) = iterator.next().unwrap() {
+                let key = rid.page_id as i32;
+                // only the first pass inserts, the second one scans the grown tree
+                if revalidate_interval == 0 && key % 50 == 0 {
+                    step_tx.send(key).unwrap();
+                    done_rx.recv().unwrap();
+                }
+                returned.push(key);
+            }
+            assert!(
+                returned.windows(2).all(|pair| pair[0] < pair[1]),
+                "keys repeated or out of order: {returned:?}"
+            );
+            assert!(
+                keys.iter().all(|key| returned.contains(key)),
+                "keys skipped: {returned:?}"
+            );
+        }
+        drop(step_tx);
+        inserter.join().unwrap();
+        assert_eq!(collect_rids(index).len(), keys.len() + 20 * 9);
+    }
+
+    #[test]
+    pub fn test_index_iterator_start_at_leaf_boundaries() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+
+        assert_eq!(collect_rids(index.clone()), vec![]);
+
+        // even keys leave room for start values between two leaves, the rid page id is the key
+        let keys = (0..40).map(|i| i * 2).collect::<Vec<i32>>();
+        for key in keys.iter() {
+            index
+                .insert(&tuple(*key), RecordId::new(*key as u32, 0))
+                .unwrap();
+        }
+
+        let mut leaf_key_ranges = vec![];
+        let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+        iterator.leaf_page = index.get_first_leaf_page().unwrap();
+        loop {
+            let array = &iterator.leaf_page.array;
+            leaf_key_ranges.push((
+                array[0].1.page_id as i32,
+                array[array.len() - 1].1.page_id as i32,
+            ));
+            if !iterator.load_next_leaf_page().unwrap() {
+                break;
+            }
+        }
+        assert!(leaf_key_ranges.len() > 2, "{leaf_key_ranges:?}");
+
+        let scan = |start_bound: Bound<Tuple>, end_bound: Bound<Tuple>| {
+            let mut iterator = TreeIndexIterator::new(index.clone(), (start_bound, end_bound));
+            let mut keys = vec![];
+            while let Some(rid) = iterator.next().unwrap() {
+                keys.push(rid.page_id as i32);
+            }
+            keys
+        };
+        let keys_from = |start: i32, included: bool| {
+            keys.iter()
+                .copied()
+                .filter(|key| *key > start || (included && *key == start))
+                .collect::<Vec<_>>()
+        };
+        for (first_key, last_key) in leaf_key_ranges {
+            for start in [first_key, last_key, last_key + 1] {
+                assert_eq!(
+                    scan(Bound::Included(tuple(start)), Bound::Unbounded),
+                    keys_from(start, true),
+                    "included start {start}"
+                );
+                assert_eq!(
+                    scan(Bound::Excluded(tuple(start)), Bound::Unbounded),
+                    keys_from(start, false),
+                    "excluded start {start}"
+                );
+            }
+        }
+
+        // the first entry is checked against the end bound too
+        assert_eq!(
+            scan(Bound::Included(tuple(11)), Bound::Excluded(tuple(12))),
+            Vec::<i32>::new()
+        );
+        assert_eq!(
+            scan(Bound::Included(tuple(10)), Bound::Included(tuple(10))),
+            vec![10]
+        );
+        assert_eq!(
+            scan(Bound::Excluded(tuple(78)), Bound::Unbounded),
+            Vec::<i32>::new()
+        );
+    }
+
+    #[test]
+    pub fn test_index_descending_iterator() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+        let scan = |range: (Bound<Tuple>, Bound<Tuple>), descending: bool| {
+            let mut iterator = if descending {
+                TreeIndexIterator::new_desc(index.clone(), range)
+            } else {
+                TreeIndexIterator::new(index.clone(), range)
+            };
+            let mut rids = vec![];
+            while let Some(rid) = iterator.next().unwrap() {
+                rids.push(rid);
+            }
+            rids
+        };
+        assert_eq!(scan((Bound::Unbounded, Bound::Unbounded), true), vec![]);
+
+        // every key three times, then the splits and merges of deleting most of them
+        for i in 0..60 {
+            index
+                .insert(&tuple(i % 20 * 2), RecordId::new(i as u32, 0))
+                .unwrap();
+        }
+        for key in (0..40).step_by(6) {
+            index.delete(&tuple(key)).unwrap();
+            index.delete(&tuple(key)).unwrap();
+        }
+        let (findings, _) = index.check("t1_a");
+        assert!(findings.is_empty(), "{findings:?}");
+
+        let bounds = |key: Option<i32>, included: bool| match key {
+            None => Bound::Unbounded,
+            Some(key) if included => Bound::Included(tuple(key)),
+            Some(key) => Bound::Excluded(tuple(key)),
+        };
+        for start in [None, Some(-1), Some(0), Some(7), Some(12), Some(38)] {
+            for end in [None, Some(0), Some(13), Some(20), Some(38), Some(41)] {
+                for (start_included, end_included) in
+                    [(true, true), (true, false), (false, true), (false, false)]
+                {
+                    let range = (bounds(start, start_included), bounds(end, end_included));
+                    let mut expected = scan(range.clone(), false);
+                    expected.reverse();
+                    assert_eq!(scan(range, true), expected, "{start:?}..{end:?}");
+                }
+            }
+        }
+
+        // entries inserted between the calls may split the leaf the scan is on
+        let mut iterator = TreeIndexIterator::new_desc(index.clone(), ..);
+        let mut rids = vec![];
+        let mut inserted = 100;
+        while let Some(rid) = iterator.next().unwrap() {
+            rids.push(rid);
+            index
+                .insert(
+                    &tuple(rid.page_id as i32 % 20 * 2),
+                    RecordId::new(inserted, 0),
+                )
+                .unwrap();
+            inserted += 1;
+        }
+        let mut before = scan((Bound::Unbounded, Bound::Unbounded), false);
+        before.retain(|rid| rid.page_id < 100);
+        before.reverse();
+        rids.retain(|rid| rid.page_id < 100);
+        assert_eq!(rids, before);
+        let (findings, _) = index.check("t1_a");
+        assert!(findings.is_empty(), "{findings:?}");
+    }
+
+    #[test]
+    pub fn test_index_link_leaves_back() {
+        let (index, _) = build_index();
+        let index = Arc::new(index);
+        let mut forward = collect_rids(index.clone());
+        // leaves as written before they linked back
+        let leaf_page_ids = index
+            .page_ids()
+            .unwrap()
+            .into_iter()
+            .filter(|page_id| {
+                let (_, tree_page) = index
+                    .buffer_pool
+                    .fetch_tree_page(*page_id, index.key_schema.clone())
+                    .unwrap();
+                matches!(tree_page, BPlusTreePage::Leaf(_))
+            })
+            .collect::<Vec<_>>();
+        for page_id in leaf_page_ids.iter() {
+            let (page, mut leaf_page) = index
+                .buffer_pool
+                .fetch_tree_leaf_page(*page_id, index.key_schema.clone())
+                .unwrap();
+            leaf_page.header.prev_page_id = None;
+            page.write()
+                .unwrap()
+                .encode_page::<BPlusTreeLeafPageCodec>(&leaf_page);
+        }
+        let mut iterator = TreeIndexIterator::new_desc(index.clone(), ..);
+        let result = (0..forward.len()).try_for_each(|_| iterator.next().map(|_| ()));
+        assert!(matches!(result, Err(BustubxError::Storage(_))));
+
+        assert_eq!(index.link_leaves_back().unwrap(), leaf_page_ids.len());
+        assert_eq!(index.link_leaves_back().unwrap(), 0);
+        let (findings, _) = index.check("t1_ab");
+        assert!(findings.is_empty(), "{findings:?}");
+        let mut iterator = TreeIndexIterator::new_desc(index.clone(), ..);
+        let mut backward = vec![];
+        while let Some(rid) = iterator.next().unwrap() {
+            backward.push(rid);
+        }
+        forward.reverse();
+        assert_eq!(backward, forward);
+    }
+
+    #[test]
+    pub fn test_index_count_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+
+        assert_eq!(
+            index
+                .count_range(Bound::Unbounded, Bound::Unbounded)
+                .unwrap(),
+            0
+        );
+
+        // even keys, every third one twice
+        let mut keys = vec![];
+        for i in 0..60 {
+            keys.push(i * 2);
+            if i % 3 == 0 {
+                keys.push(i * 2);
+            }
+        }
+        for (slot, key) in keys.iter().enumerate() {
+            index
+                .insert(&tuple(*key), RecordId::new(*key as u32, slot as u32))
+                .unwrap();
+        }
+
+        let bounds = |value: Option<(i32, bool)>| match value {
+            Some((key, true)) => Bound::Included(tuple(key)),
+            Some((key, false)) => Bound::Excluded(tuple(key)),
+            None => Bound::Unbounded,
+        };
+        let scanned = |start: Bound<Tuple>, end: Bound<Tuple>| {
+            let mut iterator = TreeIndexIterator::new(index.clone(), (start, end));
+            let mut count = 0;
+            while iterator.next().unwrap().is_some() {
+                count += 1;
+            }
+            count
+        };
+        // bounds inside, at the edges of and outside the leaves
+        let mut values = vec![None];
+        for key in [-5, 0, 1, 6, 7, 30, 31, 59, 60, 61, 117, 118, 119, 200] {
+            values.push(Some((key, true)));
+            values.push(Some((key, false)));
+        }
+        for start in values.iter() {
+            for end in values.iter() {
+                assert_eq!(
+                    index
+                        .count_range(bounds(*start).as_ref(), bounds(*end).as_ref())
+                        .unwrap(),
+                    scanned(bounds(*start), bounds(*end)),
+                    "{start:?} {end:?}"
+                );
+            }
+        }
+
+        // only the leaves holding the bounds are decoded, a start routed to the leaf before
+        // its first key takes one more
+        let before = index.leaf_decodes();
+        assert_eq!(
+            index
+                .count_range(Bound::Included(&tuple(1)), Bound::Excluded(&tuple(117)))
+                .unwrap(),
+            keys.iter().filter(|key| (1..117).contains(*key)).count() as u64
+        );
+        assert!(index.leaf_decodes() - before <= 3);
+        let before = index.leaf_decodes();
+        assert_eq!(
+            index
+                .count_range(Bound::Unbounded, Bound::Unbounded)
+                .unwrap(),
+            keys.len() as u64
+        );
+        assert_eq!(index.leaf_decodes() - before, 1);
+    }
+
+    #[test]
+    pub fn test_index_first_and_last_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let index = Arc::new(BPlusTreeIndex::new(key_schema.clone(), buffer_pool, 4, 4));
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+
+        assert_eq!(index.first_entry().unwrap(), None);
+        assert_eq!(index.last_entry().unwrap(), None);
+        assert_eq!(
+            index.last_entry_before(Bound::Included(&tuple(5))).unwrap(),
+            None
+        );
+
+        // even keys, every fourth one three times so equal keys span leaves
+        let mut entries = vec![];
+        for i in 0..50 {
+            for copy in 0..if i % 4 == 0 { 3 } else { 1 } {
+                entries.push((i * 2, RecordId::new(i as u32, copy)));
+            }
+        }
+        for (key, rid) in entries.iter() {
+            index.insert(&tuple(*key), *rid).unwrap();
+        }
+        let key_of = |entry: Option<(Tuple, RecordId)>| entry.map(|(key, _)| key.data[0].clone());
+        assert_eq!(key_of(index.first_entry().unwrap()), Some(0.into()));
+        assert_eq!(key_of(index.last_entry().unwrap()), Some(98.into()));
+
+        for end in -3..103 {
+            for (bound, expected) in [
+                (
+                    Bound::Included(tuple(end)),
+                    entries.iter().rev().find(|(key, _)| *key <= end),
+                ),
+                (
+                    Bound::Excluded(tuple(end)),
+                    entries.iter().rev().find(|(key, _)| *key < end),
+                ),
+            ] {
+                assert_eq!(
+                    key_of(index.last_entry_before(bound.as_ref()).unwrap()),
+                    expected.map(|(key, _)| (*key).into()),
+                    "{bound:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_index_wide_integer_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+
+        // keys either side of the 32 bit and the type limits
+        let signed = [i64::MIN, i64::MIN + 1, -(1 << 32), -(1 << 31) - 1, -1, 0]
+            .into_iter()
+            .chain([1 << 31, u32::MAX as i64, 1 << 32, i64::MAX - 1, i64::MAX])
+            .map(ScalarValue::from)
+            .collect::<Vec<_>>();
+        let unsigned = [
+            0,
+            1,
+            u32::MAX as u64,
+            1 << 32,
+            1 << 63,
+            u64::MAX - 1,
+            u64::MAX,
+        ]
+        .into_iter()
+        .map(ScalarValue::from)
+        .collect::<Vec<_>>();
+        for (data_type, keys) in [(DataType::Int64, signed), (DataType::UInt64, unsigned)] {
+            let key_schema = Arc::new(Schema::new(vec![Column::new("a", data_type, false)]));
+            let index = Arc::new(BPlusTreeIndex::new(
+                key_schema.clone(),
+                buffer_pool.clone(),
+                4,
+                4,
+            ));
+            let tuple = |key: &ScalarValue| Tuple::new(key_schema.clone(), vec![key.clone()]);
+            // inserted from the middle outwards so the limits land in split pages
+            let mut order = (0..keys.len()).collect::<Vec<_>>();
+            order.sort_by_key(|i| (*i as i64 - keys.len() as i64 / 2).abs());
+            for i in order {
+                let rid = RecordId::new(u32::MAX - i as u32, u32::MAX);
+                index.insert(&tuple(&keys[i]), rid).unwrap();
+            }
+
+            let mut iterator = TreeIndexIterator::new(index.clone(), ..);
+            let mut scanned = vec![];
+            while let Some((key, rid)) = iterator.next_entry().unwrap() {
+                assert_eq!(rid.slot_num, u32::MAX);
+                scanned.push(key.data[0].clone());
+            }
+            assert_eq!(scanned, keys, "{data_type}");
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(
+                    index.get(&tuple(key)).unwrap(),
+                    Some(RecordId::new(u32::MAX - i as u32, u32::MAX))
+                );
+                let below = index
+                    .count_range(Bound::Unbounded, Bound::Excluded(&tuple(key)))
+                    .unwrap();
+                assert_eq!(below, i as u64, "{key}");
+            }
+            assert_eq!(
+                index
+                    .first_entry()
+                    .unwrap()
+                    .map(|(key, _)| key.data[0].clone()),
+                keys.first().cloned()
+            );
+            assert_eq!(
+                index
+                    .last_entry()
+                    .unwrap()
+                    .map(|(key, _)| key.data[0].clone()),
+                keys.last().cloned()
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_index_get_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(5000, Arc::new(disk_manager)));
+        let index = BPlusTreeIndex::new(key_schema.clone(), buffer_pool.clone(), 10, 10);
+        let tuple = |key: i32| Tuple::new(key_schema.clone(), vec![key.into()]);
+        assert_eq!(index.get_batch(&[tuple(1)]).unwrap(), vec![None]);
+
+        // even keys, odd probes miss
+        index
+            .rebuild(
+                (0..10000)
+                    .map(|i| (tuple(i * 2), RecordId::new(i as u32, 0)))
+                    .collect(),
+            )
+            .unwrap();
+        // unsorted and clustered on the first fifth of the keys, several probes share a leaf
+        let probes = (0..1000)
+            .map(|i| tuple((i * 7919) % 2000))
+            .collect::<Vec<_>>();
+
+        let before = buffer_pool.stats();
+        let expected = probes
+            .iter()
+            .map(|key| index.get(key).unwrap())
+            .collect::<Vec<_>>();
+        let single_fetches = buffer_pool.stats().fetches() - before.fetches();
+
+        let before = buffer_pool.stats();
+        let results = index.get_batch(&probes).unwrap();
+        let batch_fetches = buffer_pool.stats().fetches() - before.fetches();
+
+        assert_eq!(results, expected);
+        assert_eq!(results.iter().filter(|rid| rid.is_some()).count(), 500);
+        assert!(
+            batch_fetches * 4 < single_fetches,
+            "batch {batch_fetches} single {single_fetches}"
+        );
+    }
+
+    #[test]
+    pub fn test_index_insert_disk_full() {
+        let (index, key_schema) = build_index();
+        let index = Arc::new(index);
+        let key = |i: u32| {
+            Tuple::new(
+                key_schema.clone(),
+                vec![(i as i8).into(), (i as i16).into()],
+            )
+        };
+
+        // enough space for one more split page, the next split runs out
+        index.buffer_pool.disk_manager.fail_allocations_after(1);
+        let mut inserted = 11;
+        let err = loop {
+            let i = inserted + 1;
+            match index.insert(&key(i), RecordId::new(i, i)) {
+                Ok(()) => inserted = i,
+                Err(e) => break e,
+            }
+            assert!(inserted < 100, "disk never filled up");
+        };
+        assert!(matches!(err, BustubxError::DiskFull(_)));
+
+        // the failed insert left no trace
+        assert_eq!(
+            collect_rids(index.clone()),
+            (1..=inserted)
+                .map(|i| RecordId::new(i, i))
+                .collect::<Vec<_>>()
+        );
+        for i in 1..=inserted {
+            assert_eq!(index.get(&key(i)).unwrap(), Some(RecordId::new(i, i)));
+        }
+        assert_eq!(index.get(&key(inserted + 1)).unwrap(), None);
+
+        index.buffer_pool.disk_manager.free_space();
+        index
+            .insert(
+                &key(inserted + 1),
+                RecordId::new(inserted + 1, inserted + 1),
+            )
+            .unwrap();
+        assert_eq!(
+            collect_rids(index.clone()),
+            (1..=inserted + 1)
+                .map(|i| RecordId::new(i, i))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    // page id and hash of the bytes of every page of the tree
+    fn page_hashes(index: &BPlusTreeIndex) -> Vec<(PageId, u64)> {
+        let mut page_ids = vec![index.root_page_id.load(Ordering::SeqCst)];
+        let mut hashes = vec![];
+        while let Some(page_id) = page_ids.pop() {
+            let (page, tree_page) = index
+                .buffer_pool
+                .fetch_tree_page(page_id, index.key_schema.clone())
+                .unwrap();
+            if let BPlusTreePage::Internal(internal_page) = tree_page {
+                page_ids.extend(internal_page.array.iter().map(|kv| kv.1));
+            }
+            let mut hasher = DefaultHasher::new();
+            page.read().unwrap().data().hash(&mut hasher);
+            hashes.push((page_id, hasher.finish()));
+        }
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    pub fn test_unique_index_duplicate_insert() {
+        let (index, key_schema) = build_index();
+        let index = Arc::new(index.with_unique(true));
+        let key = |i: u32| {
+            Tuple::new(
+                key_schema.clone(),
+                vec![(i as i8).into(), (i as i16).into()],
+            )
+        };
+        let root_page_id = index.root_page_id.load(Ordering::SeqCst);
+        let before = page_hashes(&index);
+
+        // keys of the first and last leaf and the separators of the root
+        for i in [1, 5, 8, 11] {
+            let err = index.insert(&key(i), RecordId::new(100, 100)).unwrap_err();
+            assert!(
+                matches!(err, BustubxError::DuplicateKey { rid } if rid == RecordId::new(i, i)),
+                "{err}"
+            );
+        }
+        assert_eq!(page_hashes(&index), before);
+        assert_eq!(index.root_page_id.load(Ordering::SeqCst), root_page_id);
+        assert_eq!(
+            collect_rids(index.clone()),
+            (1..=11).map(|i| RecordId::new(i, i)).collect::<Vec<_>>()
+        );
+
+        // NULLs never conflict
+        let null_key = Tuple::new(
+            key_schema.clone(),
+            vec![ScalarValue::Int8(None), ScalarValue::Int16(None)],
+        );
+        index.insert(&null_key, RecordId::new(20, 20)).unwrap();
+        index.insert(&null_key, RecordId::new(21, 21)).unwrap();
+        index.insert(&key(12), RecordId::new(12, 12)).unwrap();
+        assert_eq!(collect_rids(index.clone()).len(), 14);
+    }
+
+    #[test]
+    pub fn test_unique_index_insert_same_tuple_twice() {
+        let (index, key_schema) = build_index();
+        let key = Tuple::new(key_schema.clone(), vec![3i8.into(), 30i16.into()]);
+        let unique = BPlusTreeIndex::new(key_schema.clone(), index.buffer_pool.clone(), 4, 4)
+            .with_unique(true);
+        // the first key starts the tree, the second one goes through the leaf
+        unique.insert(&key, RecordId::new(1, 1)).unwrap();
+        let err = unique.insert(&key, RecordId::new(2, 2)).unwrap_err();
+        assert!(
+            matches!(err, BustubxError::DuplicateKey { rid } if rid == RecordId::new(1, 1)),
+            "{err}"
+        );
+        assert_eq!(unique.get(&key).unwrap(), Some(RecordId::new(1, 1)));
+        assert_eq!(collect_rids(Arc::new(unique)), vec![RecordId::new(1, 1)]);
+
+        // indexes that are not unique keep every entry of a key
+        index.insert(&key, RecordId::new(1, 1)).unwrap();
+        index.insert(&key, RecordId::new(2, 2)).unwrap();
+        assert_eq!(collect_rids(Arc::new(index)).len(), 13);
+    }
+
+    #[cfg(feature = "debug-history")]
+    #[test]
+    pub fn test_index_split_page_history() {
+        let (index, _) = build_index();
+        // the first leaf keeps the lower half of its keys when it is split
+        let first_leaf = index.first_leaf_page_id().unwrap().unwrap();
+        let history = index.buffer_pool.page_history(first_leaf);
+        let operations = history.iter().map(|r| r.operation).collect::<Vec<_>>();
+        let insert_pos = operations
+            .iter()
+            .position(|op| *op == "index_insert")
+            .unwrap();
+        let split_pos = operations
+            .iter()
+            .position(|op| *op == "index_split")
+            .unwrap();
+        assert!(insert_pos < split_pos);
+        assert!(history[insert_pos].seq < history[split_pos].seq);
+    }
 }
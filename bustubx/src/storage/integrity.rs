@@ -0,0 +1,199 @@
+#[cfg(feature = "debug-history")]
+use crate::buffer::{BufferPoolManager, PageWriteRecord};
+use crate::buffer::{PageId, BUSTUBX_PAGE_SIZE};
+use crate::catalog::SchemaRef;
+use crate::storage::codec::{TablePageHeaderCodec, TupleCodec};
+
+// writes of a damaged page reported with its finding
+#[cfg(feature = "debug-history")]
+const FINDING_HISTORY_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // the data is damaged or disagrees with itself
+    Error,
+    // harmless for readers, REINDEX cleans it up
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found by the integrity checks of a heap or an index, see `CHECK TABLE`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    // table or index the finding is about
+    pub object: String,
+    pub page_id: Option<PageId>,
+    pub description: String,
+    // recent writes of the page, oldest first, see `Finding::with_page_history`
+    #[cfg(feature = "debug-history")]
+    pub history: Vec<PageWriteRecord>,
+}
+
+impl Finding {
+    pub fn new(
+        severity: Severity,
+        object: String,
+        page_id: Option<PageId>,
+        description: String,
+    ) -> Self {
+        Self {
+            severity,
+            object,
+            page_id,
+            description,
+            #[cfg(feature = "debug-history")]
+            history: vec![],
+        }
+    }
+
+    pub fn error(object: &str, page_id: Option<PageId>, description: impl Into<String>) -> Self {
+        Self::new(
+            Severity::Error,
+            object.to_string(),
+            page_id,
+            description.into(),
+        )
+    }
+
+    pub fn warning(object: &str, page_id: Option<PageId>, description: impl Into<String>) -> Self {
+        Self::new(
+            Severity::Warning,
+            object.to_string(),
+            page_id,
+            description.into(),
+        )
+    }
+
+    /// Attach the last writes the buffer pool recorded for the page of the finding, telling
+    /// which operation left it damaged.
+    #[cfg(feature = "debug-history")]
+    pub fn with_page_history(mut self, buffer_pool: &BufferPoolManager) -> Self {
+        if let Some(page_id) = self.page_id {
+            let mut history = buffer_pool.page_history(page_id);
+            history.drain(..history.len().saturating_sub(FINDING_HISTORY_LEN));
+            self.history = history;
+        }
+        self
+    }
+
+    /// The description, followed by the recent writes of the page when they were recorded.
+    pub fn report(&self) -> String {
+        #[cfg(feature = "debug-history")]
+        if !self.history.is_empty() {
+            let writes = self
+                .history
+                .iter()
+                .map(|record| format!("#{} {}", record.seq, record.operation))
+                .collect::<Vec<_>>();
+            return format!(
+                "{} (recent writes of the page: {})",
+                self.description,
+                writes.join(", ")
+            );
+        }
+        self.description.clone()
+    }
+}
+
+/// Check the raw bytes of a heap page, returns its next page id unless the header cannot
+/// be decoded.
+///
+/// Pages carry no checksum, the layout is validated instead: the slots lie between the end
+/// of the header and the end of the page without overlapping, the tuple counts agree with
+/// the slots and every live tuple decodes to exactly the bytes of its slot.
+pub fn check_table_page(
+    object: &str,
+    page_id: PageId,
+    bytes: &[u8],
+    schema: SchemaRef,
+    findings: &mut Vec<Finding>,
+) -> Option<PageId> {
+    let mut error = |description: String| {
+        findings.push(Finding::error(object, Some(page_id), description));
+    };
+    let (header, header_len) = match TablePageHeaderCodec::decode(bytes) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            error(format!("page header cannot be decoded: {e}"));
+            return None;
+        }
+    };
+    // the tuples cannot be decoded with the schema of the table
+    if let Err(e) = TupleCodec::check_schema_fingerprint(&schema, header.schema_fingerprint) {
+        error(e.to_string());
+        return Some(header.next_page_id);
+    }
+
+    let deleted = header
+        .tuple_infos
+        .iter()
+        .filter(|info| info.meta.is_deleted)
+        .count();
+    if header.num_deleted_tuples as usize != deleted {
+        error(format!(
+            "header counts {} deleted tuples, the slots {}",
+            header.num_deleted_tuples, deleted
+        ));
+    }
+    let live = header.tuple_infos.len() - deleted;
+    if header.live_tuples as usize != live {
+        error(format!(
+            "header counts {} live tuples, the slots {}",
+            header.live_tuples, live
+        ));
+    }
+
+    // slots in page order, the ones outside of the page are not decoded
+    let mut slots = vec![];
+    for (slot_num, info) in header.tuple_infos.iter().enumerate() {
+        let (start, end) = (
+            info.offset as usize,
+            info.offset as usize + info.size as usize,
+        );
+        if start < header_len || end > BUSTUBX_PAGE_SIZE {
+            error(format!(
+                "slot {} at [{}, {}) is outside of the tuple space [{}, {})",
+                slot_num, start, end, header_len, BUSTUBX_PAGE_SIZE
+            ));
+            continue;
+        }
+        slots.push((start, end, slot_num));
+    }
+    slots.sort();
+    for pair in slots.windows(2) {
+        let ((_, end, slot_num), (start, _, next_slot_num)) = (pair[0], pair[1]);
+        if end > start {
+            error(format!(
+                "slots {} and {} overlap",
+                slot_num.min(next_slot_num),
+                slot_num.max(next_slot_num)
+            ));
+        }
+    }
+
+    for (start, end, slot_num) in slots {
+        if header.tuple_infos[slot_num].meta.is_deleted {
+            continue;
+        }
+        match TupleCodec::decode(&bytes[start..end], schema.clone()) {
+            Ok((_, len)) if len == end - start => {}
+            Ok((_, len)) => error(format!(
+                "tuple of slot {} decodes from {} of its {} bytes",
+                slot_num,
+                len,
+                end - start
+            )),
+            Err(e) => error(format!("tuple of slot {} cannot be decoded: {e}", slot_num)),
+        }
+    }
+    Some(header.next_page_id)
+}
@@ -0,0 +1,234 @@
+use std::sync::RwLockWriteGuard;
+
+use crate::buffer::{Page, PageId, PageRef};
+use crate::{BustubxError, BustubxResult};
+
+/// Mode an index operation latches a page of its path in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatchMode {
+    Read,
+    Write,
+}
+
+struct PathLevel {
+    page: PageRef,
+    page_id: PageId,
+    mode: LatchMode,
+    // child the descent continued with, None for the deepest level
+    child_index: Option<usize>,
+}
+
+/// Pages from the root down to the current page of one index operation.
+///
+/// Every level keeps its page pinned together with the latch mode it was taken in and the
+/// index of the child the descent followed, so the parent of a page and its position in
+/// the parent are known without searching again. Levels are released deepest first, the
+/// remaining ones also when the path is dropped.
+#[derive(Default)]
+pub struct LatchPath {
+    // root first, bounded by the height of the tree
+    levels: Vec<PathLevel>,
+}
+
+impl LatchPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the first page of the path, usually the root.
+    pub fn push(&mut self, page: PageRef, mode: LatchMode) {
+        let page_id = page.read().unwrap().page_id;
+        self.levels.push(PathLevel {
+            page,
+            page_id,
+            mode,
+            child_index: None,
+        });
+    }
+
+    /// Descend from the current page into its child at `child_index`.
+    pub fn descend(&mut self, child_index: usize, page: PageRef, mode: LatchMode) {
+        if let Some(level) = self.levels.last_mut() {
+            level.child_index = Some(child_index);
+        }
+        self.push(page, mode);
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Page ids of the levels, root first.
+    pub fn page_ids(&self) -> Vec<PageId> {
+        self.levels.iter().map(|level| level.page_id).collect()
+    }
+
+    #[cfg(test)]
+    pub fn mode_at(&self, level: usize) -> Option<LatchMode> {
+        self.levels.get(level).map(|level| level.mode)
+    }
+
+    pub fn current(&self) -> BustubxResult<&PageRef> {
+        Ok(&self.current_level()?.page)
+    }
+
+    pub fn current_page_id(&self) -> BustubxResult<PageId> {
+        Ok(self.current_level()?.page_id)
+    }
+
+    /// Write access to the current page, which must have been taken in write mode.
+    pub fn current_write(&self) -> BustubxResult<RwLockWriteGuard<'_, Page>> {
+        let level = self.current_level()?;
+        if level.mode != LatchMode::Write {
+            return Err(BustubxError::Internal(format!(
+                "page {} is latched for reading only",
+                level.page_id
+            )));
+        }
+        Ok(level.page.write().unwrap())
+    }
+
+    /// Page id of the parent of the current page and the index of the current page in it,
+    /// None at the top of the path.
+    pub fn parent_of_current(&self) -> Option<(PageId, usize)> {
+        let parent = self.levels.len().checked_sub(2)?;
+        let level = &self.levels[parent];
+        level
+            .child_index
+            .map(|child_index| (level.page_id, child_index))
+    }
+
+    /// Release the current page, its parent becomes the current page.
+    pub fn pop(&mut self) -> Option<PageId> {
+        let level = self.levels.pop()?;
+        if let Some(parent) = self.levels.last_mut() {
+            parent.child_index = None;
+        }
+        Some(level.page_id)
+    }
+
+    /// Release every level above `level`, which becomes the top of the path. Used once
+    /// the pages below `level` are known not to change the ancestors.
+    pub fn release_ancestors_above(&mut self, level: usize) {
+        let level = level.min(self.levels.len());
+        let released = self.levels.drain(..level).collect::<Vec<_>>();
+        // deepest first
+        for level in released.into_iter().rev() {
+            drop(level);
+        }
+    }
+
+    fn current_level(&self) -> BustubxResult<&PathLevel> {
+        self.levels
+            .last()
+            .ok_or_else(|| BustubxError::Storage("latch path is empty".to_string()))
+    }
+}
+
+impl Drop for LatchPath {
+    fn drop(&mut self) {
+        // a Vec drops its elements front to back, the path is released bottom up
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    use crate::buffer::{BufferPoolManager, PageId};
+    use crate::storage::{DiskManager, LatchMode, LatchPath};
+    use crate::BustubxError;
+
+    fn pin_count(buffer_pool: &BufferPoolManager, page_id: PageId) -> u32 {
+        // the fetch pins the page once more
+        buffer_pool.fetch_page(page_id).unwrap().pin_count() - 1
+    }
+
+    #[test]
+    pub fn test_latch_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::try_new(temp_dir.path().join("test.db")).unwrap();
+        let buffer_pool = BufferPoolManager::new(10, Arc::new(disk_manager));
+        let page_ids = (0..4)
+            .map(|_| buffer_pool.new_page().unwrap().read().unwrap().page_id)
+            .collect::<Vec<_>>();
+
+        let mut path = LatchPath::new();
+        assert!(path.current().is_err());
+        assert_eq!(path.parent_of_current(), None);
+        path.push(
+            buffer_pool.fetch_page(page_ids[0]).unwrap(),
+            LatchMode::Read,
+        );
+        assert_eq!(path.parent_of_current(), None);
+        path.descend(
+            2,
+            buffer_pool.fetch_page(page_ids[1]).unwrap(),
+            LatchMode::Read,
+        );
+        path.descend(
+            0,
+            buffer_pool.fetch_page(page_ids[2]).unwrap(),
+            LatchMode::Write,
+        );
+        path.descend(
+            1,
+            buffer_pool.fetch_page(page_ids[3]).unwrap(),
+            LatchMode::Write,
+        );
+        assert_eq!(path.page_ids(), page_ids);
+        assert_eq!(path.mode_at(1), Some(LatchMode::Read));
+        assert_eq!(path.mode_at(3), Some(LatchMode::Write));
+        assert_eq!(path.current_page_id().unwrap(), page_ids[3]);
+        assert_eq!(path.parent_of_current(), Some((page_ids[2], 1)));
+        assert!(page_ids.iter().all(|id| pin_count(&buffer_pool, *id) == 1));
+
+        // moving up releases the current page only
+        assert_eq!(path.pop(), Some(page_ids[3]));
+        assert_eq!(pin_count(&buffer_pool, page_ids[3]), 0);
+        assert_eq!(path.current_page_id().unwrap(), page_ids[2]);
+        assert_eq!(path.parent_of_current(), Some((page_ids[1], 0)));
+        assert!(path.current_write().is_ok());
+        path.descend(
+            1,
+            buffer_pool.fetch_page(page_ids[3]).unwrap(),
+            LatchMode::Read,
+        );
+        assert!(matches!(
+            path.current_write(),
+            Err(BustubxError::Internal(_))
+        ));
+
+        // partial release keeps the level and the ones below it
+        path.release_ancestors_above(2);
+        assert_eq!(path.page_ids(), page_ids[2..]);
+        assert_eq!(pin_count(&buffer_pool, page_ids[0]), 0);
+        assert_eq!(pin_count(&buffer_pool, page_ids[1]), 0);
+        assert_eq!(pin_count(&buffer_pool, page_ids[2]), 1);
+        assert_eq!(path.parent_of_current(), Some((page_ids[2], 1)));
+        path.pop();
+        assert_eq!(path.parent_of_current(), None);
+
+        path.release_ancestors_above(5);
+        assert!(path.is_empty());
+        path.push(
+            buffer_pool.fetch_page(page_ids[0]).unwrap(),
+            LatchMode::Write,
+        );
+        path.descend(
+            0,
+            buffer_pool.fetch_page(page_ids[1]).unwrap(),
+            LatchMode::Write,
+        );
+        drop(path);
+        assert!(page_ids.iter().all(|id| pin_count(&buffer_pool, *id) == 0));
+        assert_eq!(buffer_pool.stats().pin_anomalies, 0);
+    }
+}
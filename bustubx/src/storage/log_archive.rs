@@ -0,0 +1,377 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{decode_records, encode_records, LogRecord, Lsn, FIRST_LSN};
+use crate::{BustubxError, BustubxResult};
+
+/// Timeline of a database never restored from a backup.
+pub const FIRST_TIMELINE: u32 = 1;
+// next to the db file, the timeline its log is archived under, none for the first one
+pub const TIMELINE_FILE_SUFFIX: &str = ".timeline";
+// next to a base backup, see `Database::backup`
+pub const BACKUP_LABEL_SUFFIX: &str = ".backup_label";
+const SEGMENT_FILE_EXTENSION: &str = "wal";
+const HISTORY_FILE_EXTENSION: &str = "history";
+
+/// Where [`crate::Database::restore`] stops replaying the archived log. The replay stops
+/// at the last commit record not past the target, so no statement is half applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryTarget {
+    /// Commit records up to this lsn.
+    Lsn(Lsn),
+    /// Commit records logged up to this time, in seconds since the unix epoch.
+    Time(i64),
+}
+
+/// Directory the closed segments of the log are copied to, one file per segment named
+/// after the timeline and the lsns it holds.
+///
+/// A database restored from a backup continues on a new timeline, so the segments it
+/// archives never mix with the ones of the history it discarded.
+#[derive(Debug)]
+pub struct LogArchive {
+    dir: PathBuf,
+    timeline: u32,
+}
+
+/// A segment file of the archive, holding the records from `first_lsn` to `end_lsn`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivedSegment {
+    pub timeline: u32,
+    pub first_lsn: Lsn,
+    pub end_lsn: Lsn,
+    pub path: PathBuf,
+}
+
+/// Written next to a base backup, the backup holds every page write before `start_lsn`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupLabel {
+    pub start_lsn: Lsn,
+    pub timeline: u32,
+}
+
+impl LogArchive {
+    pub fn try_new(dir: impl Into<PathBuf>, timeline: u32) -> BustubxResult<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, timeline })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn timeline(&self) -> u32 {
+        self.timeline
+    }
+
+    /// Write `records` as one segment file, through a temp file renamed into place so a
+    /// crash never leaves half a segment.
+    pub fn archive(&self, records: &[LogRecord]) -> BustubxResult<()> {
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            return Ok(());
+        };
+        let file_name = format!(
+            "{:08}-{:020}-{:020}.{}",
+            self.timeline,
+            first.lsn,
+            last.lsn + 1,
+            SEGMENT_FILE_EXTENSION
+        );
+        let path = self.dir.join(file_name);
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, encode_records(records))?;
+        std::fs::File::open(&temp_path)?.sync_all()?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    /// Segments of every timeline, ordered by timeline and lsn.
+    pub fn segments(dir: &Path) -> BustubxResult<Vec<ArchivedSegment>> {
+        let mut segments = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_FILE_EXTENSION) {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let parts = stem.split('-').collect::<Vec<_>>();
+            let [timeline, first_lsn, end_lsn] = parts[..] else {
+                continue;
+            };
+            let (Ok(timeline), Ok(first_lsn), Ok(end_lsn)) =
+                (timeline.parse(), first_lsn.parse(), end_lsn.parse())
+            else {
+                continue;
+            };
+            segments.push(ArchivedSegment {
+                timeline,
+                first_lsn,
+                end_lsn,
+                path,
+            });
+        }
+        segments.sort_by_key(|segment| (segment.timeline, segment.first_lsn));
+        Ok(segments)
+    }
+
+    /// Lsn following the last archived record of any timeline, lsns never repeat across
+    /// timelines.
+    pub fn end_lsn(dir: &Path) -> BustubxResult<Lsn> {
+        if !dir.exists() {
+            return Ok(FIRST_LSN);
+        }
+        Ok(Self::segments(dir)?
+            .iter()
+            .map(|segment| segment.end_lsn)
+            .max()
+            .unwrap_or(FIRST_LSN))
+    }
+
+    /// Highest timeline with a segment or a history file in the archive.
+    pub fn latest_timeline(dir: &Path) -> BustubxResult<u32> {
+        let mut latest = Self::segments(dir)?
+            .iter()
+            .map(|segment| segment.timeline)
+            .max()
+            .unwrap_or(FIRST_TIMELINE);
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(HISTORY_FILE_EXTENSION) {
+                continue;
+            }
+            if let Some(Ok(timeline)) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(|stem| stem.parse::<u32>())
+            {
+                latest = latest.max(timeline);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// Mark the start of `timeline`, branched off `parent` after the record `switch_lsn`.
+    pub fn write_history(
+        dir: &Path,
+        timeline: u32,
+        parent: u32,
+        switch_lsn: Lsn,
+    ) -> BustubxResult<()> {
+        let path = dir.join(format!("{:08}.{}", timeline, HISTORY_FILE_EXTENSION));
+        std::fs::write(path, format!("{}\t{}\n", parent, switch_lsn))?;
+        Ok(())
+    }
+
+    /// Records of `timeline` from `start_lsn` up to the last commit record not past
+    /// `target`. The replay ends early at a gap in the lsns, left by a run of the database
+    /// that crashed before archiving its last segment.
+    pub fn recovery_records(
+        dir: &Path,
+        timeline: u32,
+        start_lsn: Lsn,
+        target: RecoveryTarget,
+    ) -> BustubxResult<Vec<LogRecord>> {
+        let mut records: Vec<LogRecord> = vec![];
+        let mut next_lsn = start_lsn;
+        for segment in Self::segments(dir)?
+            .into_iter()
+            .filter(|segment| segment.timeline == timeline && segment.end_lsn > start_lsn)
+        {
+            if segment.first_lsn > next_lsn {
+                break;
+            }
+            let decoded = decode_records(&std::fs::read(&segment.path)?)?;
+            records.extend(decoded.into_iter().filter(|record| record.lsn >= next_lsn));
+            next_lsn = segment.end_lsn;
+        }
+        if let RecoveryTarget::Lsn(lsn) = target {
+            if next_lsn <= lsn {
+                return Err(BustubxError::Storage(format!(
+                    "archived log of timeline {} ends at lsn {} before the target lsn {}",
+                    timeline, next_lsn, lsn
+                )));
+            }
+        }
+        let stop = records.iter().rposition(|record| match target {
+            RecoveryTarget::Lsn(lsn) => record.commit_time().is_some() && record.lsn <= lsn,
+            RecoveryTarget::Time(time) => record.commit_time().is_some_and(|t| t <= time),
+        });
+        records.truncate(stop.map_or(0, |stop| stop + 1));
+        Ok(records)
+    }
+}
+
+/// Timeline of the database at `db_path`.
+pub fn read_timeline(db_path: &Path) -> BustubxResult<u32> {
+    match std::fs::read_to_string(sidecar_path(db_path, TIMELINE_FILE_SUFFIX)) {
+        Ok(text) => text.trim().parse().map_err(|_| {
+            BustubxError::Storage(format!("timeline file of {} is damaged", db_path.display()))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(FIRST_TIMELINE),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn write_timeline(db_path: &Path, timeline: u32) -> BustubxResult<()> {
+    std::fs::write(
+        sidecar_path(db_path, TIMELINE_FILE_SUFFIX),
+        format!("{}\n", timeline),
+    )?;
+    Ok(())
+}
+
+pub fn backup_label_path(backup_path: &Path) -> PathBuf {
+    sidecar_path(backup_path, BACKUP_LABEL_SUFFIX)
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use tempfile::TempDir;
+
+    use crate::common::ScalarValue;
+    use crate::storage::{read_timeline, LogArchive, RecoveryTarget, FIRST_TIMELINE};
+    use crate::{Database, DatabaseOptions, MockClock};
+
+    // rows of every batch, in batch order
+    fn batch_counts(db: &mut Database) -> Vec<(i32, usize)> {
+        let mut counts = std::collections::BTreeMap::new();
+        for tuple in db.run("select batch from t1").unwrap() {
+            let ScalarValue::Int32(Some(batch)) = tuple.data[0] else {
+                panic!("unexpected row {:?}", tuple);
+            };
+            *counts.entry(batch).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    fn archived_lsns(archive_dir: &Path, timeline: u32) -> Vec<(u64, u64)> {
+        LogArchive::segments(archive_dir)
+            .unwrap()
+            .into_iter()
+            .filter(|segment| segment.timeline == timeline)
+            .map(|segment| (segment.first_lsn, segment.end_lsn))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_restore_to_point_in_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let archive_dir = temp_dir.path().join("archive");
+        let backup_path = temp_dir.path().join("backup.db");
+        let second_backup_path = temp_dir.path().join("backup2.db");
+        let clock = Arc::new(MockClock::new(1000));
+        let options = DatabaseOptions::default().wal_archive_dir(&archive_dir);
+
+        let mut db = Database::open_with_clock(&db_path, options, clock.clone()).unwrap();
+        db.run("create table t1 (batch int, a int)").unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        let label = db.backup(&backup_path).unwrap();
+        db.backup(&second_backup_path).unwrap();
+        assert_eq!(label.timeline, FIRST_TIMELINE);
+
+        // batch 1 commits at 1100, batch 2 at 1200 and batch 3 at 1300
+        let mut checkpoint_lsns = vec![];
+        for batch in 1..=3 {
+            clock.advance(100);
+            let values = (0..50)
+                .map(|i| format!("({batch}, {})", batch * 100 + i))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+            let ScalarValue::UInt64(Some(lsn)) = db.run("checkpoint").unwrap()[0].data[0] else {
+                panic!("checkpoint without a commit lsn");
+            };
+            checkpoint_lsns.push(lsn);
+        }
+        assert_eq!(batch_counts(&mut db), vec![(1, 50), (2, 50), (3, 50)]);
+        db.close().unwrap();
+
+        // between batch 2 and batch 3
+        let mut restored =
+            Database::restore(&backup_path, &archive_dir, RecoveryTarget::Time(1250)).unwrap();
+        assert_eq!(batch_counts(&mut restored), vec![(1, 50), (2, 50)]);
+        assert_eq!(
+            restored
+                .run("select a from t1 where a = 249")
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(restored
+            .run("select a from t1 where a = 300")
+            .unwrap()
+            .is_empty());
+        assert_eq!(read_timeline(&backup_path).unwrap(), FIRST_TIMELINE + 1);
+        // the backup was used up
+        assert!(Database::restore(&backup_path, &archive_dir, RecoveryTarget::Time(1250)).is_err());
+
+        // the new history is archived apart from the discarded batch 3
+        restored.run("insert into t1 values (4, 400)").unwrap();
+        restored.run("checkpoint").unwrap();
+        let old_end = archived_lsns(&archive_dir, FIRST_TIMELINE)
+            .last()
+            .unwrap()
+            .1;
+        let new_segments = archived_lsns(&archive_dir, FIRST_TIMELINE + 1);
+        assert!(!new_segments.is_empty());
+        assert!(new_segments
+            .iter()
+            .all(|(first_lsn, _)| *first_lsn >= old_end));
+        restored.close().unwrap();
+
+        // up to the commit record of the checkpoint after batch 1
+        let mut restored = Database::restore(
+            &second_backup_path,
+            &archive_dir,
+            RecoveryTarget::Lsn(checkpoint_lsns[0]),
+        )
+        .unwrap();
+        assert_eq!(batch_counts(&mut restored), vec![(1, 50)]);
+        assert_eq!(
+            read_timeline(&second_backup_path).unwrap(),
+            FIRST_TIMELINE + 2
+        );
+        restored.close().unwrap();
+    }
+
+    #[test]
+    pub fn test_restore_beyond_archive_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let archive_dir = temp_dir.path().join("archive");
+        let backup_path = temp_dir.path().join("backup.db");
+        let options = DatabaseOptions::default().wal_archive_dir(&archive_dir);
+
+        let mut db = Database::open_with_options(&db_path, options).unwrap();
+        db.run("create table t1 (batch int, a int)").unwrap();
+        let label = db.backup(&backup_path).unwrap();
+        db.run("insert into t1 values (1, 1)").unwrap();
+        db.close().unwrap();
+
+        let end_lsn = LogArchive::end_lsn(&archive_dir).unwrap();
+        assert!(end_lsn > label.start_lsn);
+        assert!(
+            Database::restore(&backup_path, &archive_dir, RecoveryTarget::Lsn(end_lsn)).is_err()
+        );
+        // the close committed the insert
+        let mut restored =
+            Database::restore(&backup_path, &archive_dir, RecoveryTarget::Lsn(end_lsn - 1))
+                .unwrap();
+        assert_eq!(batch_counts(&mut restored), vec![(1, 1)]);
+        restored.close().unwrap();
+    }
+}
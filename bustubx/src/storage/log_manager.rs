@@ -0,0 +1,482 @@
+use log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use crate::buffer::PageId;
+use crate::storage::codec::{ByteReader, CommonCodec};
+use crate::storage::{Compressor, LogArchive};
+use crate::{BustubxError, BustubxResult};
+
+/// Log sequence number, the first record is 1.
+pub type Lsn = u64;
+pub const FIRST_LSN: Lsn = 1;
+// records handed out per segment by `read_segments`, and archived per segment file
+pub const LOG_SEGMENT_RECORDS: usize = 64;
+// page id of a commit record, no page is written
+pub const COMMIT_RECORD_PAGE_ID: PageId = PageId::MAX;
+
+/// After image of one page write of the db file, the meta page is logged with
+/// `INVALID_PAGE_ID`. Applying the records in lsn order redoes the writes.
+///
+/// A commit record carries the time it was logged instead of a page. Every statement that
+/// committed before it has its pages in the records before it, see
+/// [`LogManager::append_commit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    pub page_id: PageId,
+    pub data: Vec<u8>,
+}
+
+impl LogRecord {
+    /// Seconds since the unix epoch the commit record was logged at, None for a page write.
+    pub fn commit_time(&self) -> Option<i64> {
+        if self.page_id != COMMIT_RECORD_PAGE_ID {
+            return None;
+        }
+        CommonCodec::decode_i64(&self.data)
+            .ok()
+            .map(|(time, _)| time)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSegment {
+    pub records: Vec<LogRecord>,
+}
+
+impl LogSegment {
+    /// Lsn following the last record of the segment
+    pub fn end_lsn(&self) -> Option<Lsn> {
+        self.records.last().map(|record| record.lsn + 1)
+    }
+}
+
+/// Page writes of a primary kept in memory until a standby has received them.
+///
+/// With a compressor, every [`LOG_SEGMENT_RECORDS`] records are closed into a compressed
+/// segment, [`LogManager::read_segments`] decompresses them again.
+#[derive(Debug)]
+pub struct LogManager {
+    next_lsn: AtomicU64,
+    records: Mutex<LogRecords>,
+    // page bytes appended, reset by `reset_stats`
+    bytes_written: AtomicU64,
+    compressor: Option<Arc<Compressor>>,
+    // closed segments are copied there
+    archive: Option<LogArchive>,
+}
+
+#[derive(Debug)]
+struct LogRecords {
+    // records before it were truncated
+    first_lsn: Lsn,
+    // records before it were archived
+    archived_lsn: Lsn,
+    closed: Vec<ClosedSegment>,
+    // records not closed into a segment yet, all of them without a compressor
+    open: Vec<LogRecord>,
+}
+
+// LOG_SEGMENT_RECORDS records encoded and compressed as one block
+#[derive(Debug)]
+struct ClosedSegment {
+    end_lsn: Lsn,
+    block: Vec<u8>,
+}
+
+impl Default for LogManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogManager {
+    pub fn new() -> Self {
+        Self {
+            next_lsn: AtomicU64::new(FIRST_LSN),
+            records: Mutex::new(LogRecords {
+                first_lsn: FIRST_LSN,
+                archived_lsn: FIRST_LSN,
+                closed: vec![],
+                open: vec![],
+            }),
+            bytes_written: AtomicU64::new(0),
+            compressor: None,
+            archive: None,
+        }
+    }
+
+    /// Copy every segment of [`LOG_SEGMENT_RECORDS`] records to `archive` once it is full,
+    /// or earlier on [`LogManager::switch_segment`]. The lsns continue after the last
+    /// record archived by an earlier run of the database.
+    pub fn with_archive(self, archive: LogArchive) -> BustubxResult<Self> {
+        let first_lsn = LogArchive::end_lsn(archive.dir())?;
+        self.next_lsn.store(first_lsn, Ordering::SeqCst);
+        {
+            let mut records = self.records.lock().unwrap();
+            records.first_lsn = first_lsn;
+            records.archived_lsn = first_lsn;
+        }
+        Ok(Self {
+            archive: Some(archive),
+            ..self
+        })
+    }
+
+    pub fn archive(&self) -> Option<&LogArchive> {
+        self.archive.as_ref()
+    }
+
+    /// Log closing full segments into blocks of `compressor`, unless its codec is
+    /// [`crate::CompressionCodec::None`].
+    pub fn with_compressor(compressor: Arc<Compressor>) -> Self {
+        Self {
+            compressor: compressor.is_enabled().then_some(compressor),
+            ..Self::new()
+        }
+    }
+
+    // Called with the db file locked, so lsn order is the order the writes hit the file
+    pub fn append(&self, page_id: PageId, data: &[u8]) -> Lsn {
+        let mut records = self.records.lock().unwrap();
+        let lsn = self.next_lsn.fetch_add(1, Ordering::SeqCst);
+        self.bytes_written
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        records.open.push(LogRecord {
+            lsn,
+            page_id,
+            data: data.to_vec(),
+        });
+        if let Some(compressor) = &self.compressor {
+            if records.open.len() == LOG_SEGMENT_RECORDS {
+                let open = std::mem::take(&mut records.open);
+                records.closed.push(ClosedSegment {
+                    end_lsn: lsn + 1,
+                    block: compressor.compress(&encode_records(&open)),
+                });
+            }
+        }
+        if lsn + 1 - records.archived_lsn == LOG_SEGMENT_RECORDS as Lsn {
+            // the write went through, a failed copy is tried again by the next switch
+            if let Err(e) = self.archive_records(&mut records) {
+                warn!(
+                    "failed to archive the log segment ending at lsn {}: {}",
+                    lsn, e
+                );
+            }
+        }
+        lsn
+    }
+
+    /// Log a commit record stamped with `time`, see [`LogRecord::commit_time`].
+    pub fn append_commit(&self, time: i64) -> Lsn {
+        self.append(COMMIT_RECORD_PAGE_ID, &CommonCodec::encode_i64(time))
+    }
+
+    /// Close the records not archived yet into a segment of their own and archive it.
+    pub fn switch_segment(&self) -> BustubxResult<()> {
+        let mut records = self.records.lock().unwrap();
+        self.archive_records(&mut records)
+    }
+
+    fn archive_records(&self, records: &mut LogRecords) -> BustubxResult<()> {
+        let Some(archive) = &self.archive else {
+            return Ok(());
+        };
+        let pending = Self::records_from(records, records.archived_lsn)?;
+        let Some(last) = pending.last() else {
+            return Ok(());
+        };
+        let end_lsn = last.lsn + 1;
+        archive.archive(&pending)?;
+        records.archived_lsn = end_lsn;
+        Ok(())
+    }
+
+    pub fn next_lsn(&self) -> Lsn {
+        self.next_lsn.load(Ordering::SeqCst)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_stats(&self) {
+        self.bytes_written.store(0, Ordering::Relaxed);
+    }
+
+    /// Records from `from_lsn` on, split into segments of at most [`LOG_SEGMENT_RECORDS`].
+    pub fn read_segments(&self, from_lsn: Lsn) -> BustubxResult<Vec<LogSegment>> {
+        let records = self.records.lock().unwrap();
+        if from_lsn < records.first_lsn {
+            return Err(BustubxError::Storage(format!(
+                "log records before lsn {} were truncated, requested {}",
+                records.first_lsn, from_lsn
+            )));
+        }
+        let wanted = Self::records_from(&records, from_lsn)?;
+        Ok(wanted
+            .chunks(LOG_SEGMENT_RECORDS)
+            .map(|records| LogSegment {
+                records: records.to_vec(),
+            })
+            .collect())
+    }
+
+    fn records_from(records: &LogRecords, from_lsn: Lsn) -> BustubxResult<Vec<LogRecord>> {
+        let mut wanted = vec![];
+        for segment in records.closed.iter() {
+            if segment.end_lsn > from_lsn {
+                let data = Compressor::decompress(&segment.block)?;
+                wanted.extend(
+                    decode_records(&data)?
+                        .into_iter()
+                        .filter(|record| record.lsn >= from_lsn),
+                );
+            }
+        }
+        wanted.extend(
+            records
+                .open
+                .iter()
+                .filter(|record| record.lsn >= from_lsn)
+                .cloned(),
+        );
+        Ok(wanted)
+    }
+
+    /// Drop the records before `lsn` once every standby applied them.
+    pub fn truncate(&self, lsn: Lsn) {
+        let mut records = self.records.lock().unwrap();
+        // records not archived yet are kept for the archive
+        let lsn = match self.archive {
+            Some(_) => lsn.min(records.archived_lsn),
+            None => lsn,
+        };
+        records.first_lsn = records.first_lsn.max(lsn.min(self.next_lsn()));
+        // a closed segment is dropped once all of its records are
+        records.closed.retain(|segment| segment.end_lsn > lsn);
+        records.open.retain(|record| record.lsn >= lsn);
+    }
+}
+
+pub(crate) fn encode_records(records: &[LogRecord]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for record in records {
+        bytes.extend(CommonCodec::encode_u64(record.lsn));
+        bytes.extend(CommonCodec::encode_u32(record.page_id));
+        bytes.extend(CommonCodec::encode_u32(record.data.len() as u32));
+        bytes.extend(&record.data);
+    }
+    bytes
+}
+
+pub(crate) fn decode_records(bytes: &[u8]) -> BustubxResult<Vec<LogRecord>> {
+    let mut reader = ByteReader::new(bytes);
+    let mut records = vec![];
+    while reader.offset() < bytes.len() {
+        let lsn = reader.read(CommonCodec::decode_u64)?;
+        let page_id = reader.read(CommonCodec::decode_u32)?;
+        let len = reader.read(CommonCodec::decode_u32)? as usize;
+        records.push(LogRecord {
+            lsn,
+            page_id,
+            data: reader.take(len)?.to_vec(),
+        });
+    }
+    Ok(records)
+}
+
+/// Transport of log segments from a primary to a standby.
+pub trait LogShipper {
+    fn ship(&self, segment: LogSegment) -> BustubxResult<()>;
+}
+
+impl LogShipper for Sender<LogSegment> {
+    fn ship(&self, segment: LogSegment) -> BustubxResult<()> {
+        self.send(segment)
+            .map_err(|_| BustubxError::Storage("log receiver disconnected".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_util::rows;
+    use crate::common::ScalarValue;
+    use crate::storage::{Compressor, LogManager, LOG_SEGMENT_RECORDS};
+    use crate::{BustubxError, CompressionCodec, Database, DatabaseOptions};
+    use std::sync::{mpsc, Arc};
+    use tempfile::TempDir;
+
+    #[test]
+    pub fn test_log_manager_read_segments() {
+        let log_manager = LogManager::new();
+        for page_id in 0..(LOG_SEGMENT_RECORDS as u32 + 2) {
+            log_manager.append(page_id, &[page_id as u8]);
+        }
+        let segments = log_manager.read_segments(3).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].records[0].lsn, 3);
+        assert_eq!(segments[0].end_lsn(), Some(log_manager.next_lsn()));
+        assert_eq!(log_manager.read_segments(1).unwrap().len(), 2);
+        assert!(log_manager
+            .read_segments(log_manager.next_lsn())
+            .unwrap()
+            .is_empty());
+
+        log_manager.truncate(10);
+        assert_eq!(log_manager.read_segments(10).unwrap()[0].records[0].lsn, 10);
+        assert!(log_manager.read_segments(9).is_err());
+    }
+
+    #[test]
+    pub fn test_compressed_log_segments() {
+        let plain = LogManager::new();
+        let compressor = Arc::new(Compressor::new(CompressionCodec::Lz));
+        let compressed = LogManager::with_compressor(compressor.clone());
+        for page_id in 0..(LOG_SEGMENT_RECORDS as u32 * 3 + 5) {
+            // pages are mostly zeros
+            let mut data = vec![0u8; 512];
+            data[..4].copy_from_slice(&page_id.to_be_bytes());
+            plain.append(page_id, &data);
+            compressed.append(page_id, &data);
+        }
+        let (uncompressed_bytes, compressed_bytes) = compressor.byte_counts();
+        assert!(compressed_bytes * 4 < uncompressed_bytes);
+
+        for from_lsn in [1, 2, 64, 65, 100, 200, plain.next_lsn()] {
+            assert_eq!(
+                compressed.read_segments(from_lsn).unwrap(),
+                plain.read_segments(from_lsn).unwrap()
+            );
+        }
+        // closed segments go once all of their records are truncated
+        for lsn in [10, 65, 66, 190] {
+            plain.truncate(lsn);
+            compressed.truncate(lsn);
+            assert_eq!(
+                compressed.read_segments(lsn).unwrap(),
+                plain.read_segments(lsn).unwrap()
+            );
+            assert!(compressed.read_segments(lsn - 1).is_err());
+        }
+
+        // nothing to compress with the codec off
+        let compressor = Arc::new(Compressor::new(CompressionCodec::None));
+        let log_manager = LogManager::with_compressor(compressor.clone());
+        for page_id in 0..(LOG_SEGMENT_RECORDS as u32 * 2) {
+            log_manager.append(page_id, &[0u8; 64]);
+        }
+        assert_eq!(compressor.byte_counts(), (0, 0));
+        assert_eq!(log_manager.read_segments(1).unwrap().len(), 2);
+    }
+
+    fn assert_same_data(primary: &mut Database, standby: &mut Database) {
+        for sql in [
+            "select * from t1",
+            "select b from t1 where a = 42",
+            "select a from t1 where a > 250",
+        ] {
+            assert_eq!(rows(primary, sql), rows(standby, sql), "{sql}");
+        }
+    }
+
+    #[test]
+    pub fn test_replicate_compressed_log() {
+        let mut primary = Database::new_temp_with_options(
+            DatabaseOptions::new()
+                .replication_log(true)
+                .compression(CompressionCodec::Lz),
+        )
+        .unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let mut standby = Database::open_as_replica(temp_dir.path().join("standby.db")).unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        primary
+            .run("create table t1 (a int, b varchar(20))")
+            .unwrap();
+        primary.run("create index idx_a on t1 (a)").unwrap();
+        for a in 0..300 {
+            primary
+                .run(&format!("insert into t1 values ({a}, 'row {a}')"))
+                .unwrap();
+        }
+        primary.flush().unwrap();
+        let stats = primary.runtime_stats();
+        assert!(stats.compressed_bytes > 0);
+        assert!(
+            stats.compressed_bytes * 2 < stats.uncompressed_bytes,
+            "{stats:?}"
+        );
+
+        let next_lsn = primary.ship_log(1, &sender).unwrap();
+        standby.apply_received(&receiver).unwrap();
+        assert_eq!(standby.replica_next_lsn(), Some(next_lsn));
+        assert_same_data(&mut primary, &mut standby);
+        assert_eq!(rows(&mut standby, "select b from t1 where a = 42").len(), 1);
+    }
+
+    #[test]
+    pub fn test_replicate_to_standby_and_promote() {
+        let mut primary =
+            Database::new_temp_with_options(DatabaseOptions::new().replication_log(true)).unwrap();
+        let temp_dir = TempDir::new().unwrap();
+        let mut standby = Database::open_as_replica(temp_dir.path().join("standby.db")).unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        primary
+            .run("create table t1 (a int, b varchar(20))")
+            .unwrap();
+        primary.run("create index idx_a on t1 (a)").unwrap();
+        for chunk in (0..300).collect::<Vec<_>>().chunks(50) {
+            let values = chunk
+                .iter()
+                .map(|a| format!("({a}, 'row {a}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            primary
+                .run(&format!("insert into t1 values {values}"))
+                .unwrap();
+        }
+        primary
+            .run("update t1 set b = 'updated' where a < 10")
+            .unwrap();
+        primary.run("delete from t1 where a >= 280").unwrap();
+        primary.flush().unwrap();
+
+        let next_lsn = primary.ship_log(1, &sender).unwrap();
+        assert!(standby.apply_received(&receiver).unwrap() > 0);
+        assert_eq!(standby.replica_next_lsn(), Some(next_lsn));
+        assert_same_data(&mut primary, &mut standby);
+        assert_eq!(rows(&mut standby, "select b from t1 where a = 42").len(), 1);
+
+        assert!(matches!(
+            standby.run("insert into t1 values (1, 'x')"),
+            Err(BustubxError::Execution(_))
+        ));
+
+        // catch up from where the standby stopped
+        primary.run("insert into t1 values (500, 'late')").unwrap();
+        primary.flush().unwrap();
+        primary.ship_log(next_lsn, &sender).unwrap();
+        standby.apply_received(&receiver).unwrap();
+        assert_same_data(&mut primary, &mut standby);
+        assert_eq!(
+            rows(&mut standby, "select b from t1 where a = 500"),
+            vec![vec![ScalarValue::Varchar(Some("late".to_string()))]]
+        );
+
+        standby.promote().unwrap();
+        assert_eq!(standby.replica_next_lsn(), None);
+        standby
+            .run("insert into t1 values (1000, 'promoted')")
+            .unwrap();
+        assert_eq!(
+            rows(&mut standby, "select b from t1 where a = 1000"),
+            vec![vec![ScalarValue::Varchar(Some("promoted".to_string()))]]
+        );
+    }
+}
@@ -1,11 +1,23 @@
 pub mod codec;
+mod compression;
 mod disk_manager;
 pub mod index;
+mod integrity;
+mod latch_path;
+mod log_archive;
+mod log_manager;
 mod page;
+mod row_id;
 mod table_heap;
 mod tuple;
 
+pub use compression::{CompressionCodec, Compressor};
 pub use disk_manager::DiskManager;
+pub use integrity::*;
+pub use latch_path::*;
+pub use log_archive::*;
+pub use log_manager::*;
 pub use page::*;
+pub use row_id::*;
 pub use table_heap::{TableHeap, TableIterator};
 pub use tuple::*;
@@ -1,11 +1,14 @@
 use crate::buffer::{PageId, INVALID_PAGE_ID};
 use crate::catalog::{Schema, SchemaRef};
+use crate::storage::codec::TupleCodec;
 use crate::storage::RecordId;
-use crate::Tuple;
+use crate::{BustubxError, BustubxResult, Tuple};
 use std::sync::Arc;
 
 pub const BPLUS_INTERNAL_PAGE_MAX_SIZE: usize = 10;
 pub const BPLUS_LEAF_PAGE_MAX_SIZE: usize = 10;
+// largest page header, of a leaf linked to its previous leaf and with a key fingerprint
+pub const BPLUS_MAX_HEADER_SIZE: usize = 21;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum BPlusTreePage {
@@ -58,10 +61,12 @@ pub type LeafKV = (Tuple, RecordId);
  * | HEADER | KEY(1)+PAGE_ID(1) | KEY(2)+PAGE_ID(2) | ... | KEY(n)+PAGE_ID(n) |
  *  --------------------------------------------------------------------------
  *
- * Header format (size in byte, 12 bytes in total):
+ * Header format (size in byte, 16 bytes in total):
  * ----------------------------------------------------------------------------
- * | PageType (4) | CurrentSize (4) | MaxSize (4) |
+ * | PageType (4) | CurrentSize (4) | MaxSize (4) | KeyFingerprint (4) |
  * ----------------------------------------------------------------------------
+ * The high bit of PageType marks headers with the fingerprint of the key schema, older
+ * pages lack it.
  */
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BPlusTreeInternalPage {
@@ -77,16 +82,20 @@ pub struct BPlusTreeInternalPageHeader {
     pub current_size: u32,
     // max kv size can be stored
     pub max_size: u32,
+    // of the key schema, None for a page written before it was stored until its next write
+    pub schema_fingerprint: Option<u32>,
 }
 
 impl BPlusTreeInternalPage {
     pub fn new(schema: SchemaRef, max_size: u32) -> Self {
+        let schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
         Self {
             schema,
             header: BPlusTreeInternalPageHeader {
                 page_type: BPlusTreePageType::InternalPage,
                 current_size: 0,
                 max_size,
+                schema_fingerprint,
             },
             array: Vec::with_capacity(max_size as usize),
         }
@@ -104,33 +113,27 @@ impl BPlusTreeInternalPage {
         self.array.iter().map(|kv| kv.1).collect()
     }
 
-    pub fn sibling_page_ids(&self, page_id: PageId) -> (Option<PageId>, Option<PageId>) {
-        let index = self.array.iter().position(|x| x.1 == page_id);
-        if let Some(index) = index {
-            return (
-                if index == 0 {
-                    None
-                } else {
-                    Some(self.array[index - 1].1)
-                },
-                if index == self.header.current_size as usize - 1 {
-                    None
-                } else {
-                    Some(self.array[index + 1].1)
-                },
-            );
+    // Page ids of the children left and right of the child at `index`
+    pub fn sibling_page_ids(&self, index: usize) -> (Option<PageId>, Option<PageId>) {
+        if index >= self.array.len() {
+            return (None, None);
         }
-        (None, None)
+        (
+            index.checked_sub(1).map(|left| self.array[left].1),
+            self.array.get(index + 1).map(|kv| kv.1),
+        )
     }
 
-    // TODO: can be inserted using binary search
     pub fn insert(&mut self, key: Tuple, page_id: PageId) {
-        self.array.push((key, page_id));
+        if self.array.is_empty() {
+            // The first kv pair's key is empty
+            self.array.push((key, page_id));
+        } else {
+            // Skip the first empty key, equal keys keep insertion order
+            let index = 1 + self.array[1..].partition_point(|kv| kv.0 <= key);
+            self.array.insert(index, (key, page_id));
+        }
         self.header.current_size += 1;
-        // Skip the first empty key
-        let null_kv = self.array.remove(0);
-        self.array.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-        self.array.insert(0, null_kv);
     }
     pub fn batch_insert(&mut self, kvs: Vec<InternalKV>) {
         let kvs_len = kvs.len();
@@ -140,32 +143,8 @@ impl BPlusTreeInternalPage {
     }
 
     pub fn delete(&mut self, key: &Tuple) {
-        if self.header.current_size == 0 {
-            return;
-        }
-        // The first key is empty, so start from 1
-        let mut start: i32 = 1;
-        let mut end: i32 = self.header.current_size as i32 - 1;
-        while start < end {
-            let mid = (start + end) / 2;
-            let compare_res = key.partial_cmp(&self.array[mid as usize].0).unwrap();
-            if compare_res == std::cmp::Ordering::Equal {
-                self.array.remove(mid as usize);
-                self.header.current_size -= 1;
-                // After deletion, if only one empty key remains, delete it
-                if self.header.current_size == 1 {
-                    self.array.remove(0);
-                    self.header.current_size -= 1;
-                }
-                return;
-            } else if compare_res == std::cmp::Ordering::Less {
-                end = mid - 1;
-            } else {
-                start = mid + 1;
-            }
-        }
-        if key.partial_cmp(&self.array[start as usize].0).unwrap() == std::cmp::Ordering::Equal {
-            self.array.remove(start as usize);
+        if let Some(index) = self.key_index(key) {
+            self.array.remove(index);
             self.header.current_size -= 1;
             // After deletion, if only one empty key remains, delete it
             if self.header.current_size == 1 {
@@ -212,54 +191,35 @@ impl BPlusTreeInternalPage {
     }
 
     pub fn key_index(&self, key: &Tuple) -> Option<usize> {
-        if self.header.current_size == 0 {
+        if self.array.len() <= 1 {
             return None;
         }
         // The first key is empty, so start from 1
-        let mut start: i32 = 1;
-        let mut end: i32 = self.header.current_size as i32 - 1;
-        while start < end {
-            let mid = (start + end) / 2;
-            let compare_res = key.partial_cmp(&self.array[mid as usize].0).unwrap();
-            if compare_res == std::cmp::Ordering::Equal {
-                return Some(mid as usize);
-            } else if compare_res == std::cmp::Ordering::Less {
-                end = mid - 1;
-            } else {
-                start = mid + 1;
-            }
-        }
-        if key.partial_cmp(&self.array[start as usize].0).unwrap() == std::cmp::Ordering::Equal {
-            return Some(start as usize);
-        }
-        None
+        self.array[1..]
+            .binary_search_by(|kv| kv.0.partial_cmp(key).unwrap())
+            .ok()
+            .map(|index| index + 1)
     }
 
     // Find the page_id corresponding to the key
     pub fn look_up(&self, key: &Tuple) -> PageId {
-        // The first key is empty, so start from 1
-        let mut start = 1;
-        if self.header.current_size == 0 {
-            println!("look_up empty page");
-        }
-        let mut end = self.header.current_size - 1;
-        while start < end {
-            let mid = (start + end) / 2;
-            let compare_res = key.partial_cmp(&self.array[mid as usize].0).unwrap();
-            if compare_res == std::cmp::Ordering::Equal {
-                return self.array[mid as usize].1;
-            } else if compare_res == std::cmp::Ordering::Less {
-                end = mid - 1;
-            } else {
-                start = mid + 1;
-            }
-        }
-        let compare_res = key.partial_cmp(&self.array[start as usize].0).unwrap();
-        if compare_res == std::cmp::Ordering::Less {
-            self.array[start as usize - 1].1
-        } else {
-            self.array[start as usize].1
-        }
+        self.array[self.child_index(key)].1
+    }
+
+    // Index of the child holding the first entry not less than the key, or of the child
+    // before it when that entry starts the next child
+    pub fn lower_child_index(&self, key: &Tuple) -> usize {
+        debug_assert!(!self.array.is_empty(), "look_up empty internal page");
+        self.array[1..].partition_point(|kv| kv.0 < *key)
+    }
+
+    // Index of the child the key belongs in
+    pub fn child_index(&self, key: &Tuple) -> usize {
+        debug_assert!(!self.array.is_empty(), "look_up empty internal page");
+        // The first key is empty, so start from 1. The number of keys not greater than
+        // the given key is exactly the index of the child to descend into, falling back
+        // to the first slot when the key is smaller than every non-empty key.
+        self.array[1..].partition_point(|kv| kv.0 <= *key)
     }
 }
 
@@ -269,10 +229,12 @@ impl BPlusTreeInternalPage {
  * | HEADER | KEY(1) + RID(1) | KEY(2) + RID(2) | ... | KEY(n) + RID(n)
  *  ----------------------------------------------------------------------
  *
- *  Header format (size in byte, 16 bytes in total):
- *  ---------------------------------------------------------------------
- * | PageType (4) | CurrentSize (4) | MaxSize (4) | NextPageId (4)
- *  ---------------------------------------------------------------------
+ *  Header format (size in byte, 24 bytes in total):
+ *  ----------------------------------------------------------------------------------------
+ * | PageType (4) | CurrentSize (4) | MaxSize (4) | NextPageId (4) | PrevPageId (4) | KeyFingerprint (4)
+ *  ----------------------------------------------------------------------------------------
+ *  The high bit of PageType marks headers with the fingerprint as on internal pages, the
+ *  bit below it headers with PrevPageId, which leaves written before it was added lack.
  */
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BPlusTreeLeafPage {
@@ -288,10 +250,14 @@ pub struct BPlusTreeLeafPageHeader {
     // max kv size can be stored
     pub max_size: u32,
     pub next_page_id: PageId,
+    // None on a leaf written before leaves linked back to their previous leaf
+    pub prev_page_id: Option<PageId>,
+    pub schema_fingerprint: Option<u32>,
 }
 
 impl BPlusTreeLeafPage {
     pub fn new(schema: SchemaRef, max_size: u32) -> Self {
+        let schema_fingerprint = Some(TupleCodec::schema_fingerprint(&schema));
         Self {
             schema,
             header: BPlusTreeLeafPageHeader {
@@ -299,6 +265,8 @@ impl BPlusTreeLeafPage {
                 current_size: 0,
                 max_size,
                 next_page_id: INVALID_PAGE_ID,
+                prev_page_id: Some(INVALID_PAGE_ID),
+                schema_fingerprint,
             },
             array: Vec::with_capacity(max_size as usize),
         }
@@ -312,6 +280,8 @@ impl BPlusTreeLeafPage {
                 current_size: 0,
                 max_size: 0,
                 next_page_id: INVALID_PAGE_ID,
+                prev_page_id: Some(INVALID_PAGE_ID),
+                schema_fingerprint: None,
             },
             array: Vec::new(),
         }
@@ -333,11 +303,21 @@ impl BPlusTreeLeafPage {
         self.header.current_size > self.header.max_size
     }
 
-    // TODO: can be inserted using binary search
     pub fn insert(&mut self, key: Tuple, rid: RecordId) {
-        self.array.push((key, rid));
+        // Equal keys keep insertion order
+        let index = self.array.partition_point(|kv| kv.0 <= key);
+        self.array.insert(index, (key, rid));
         self.header.current_size += 1;
-        self.array.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    }
+
+    /// Insert unless the key is present already, in which case the page is left untouched
+    /// and the error carries the record id the key maps to.
+    pub fn insert_unique(&mut self, key: Tuple, rid: RecordId) -> BustubxResult<()> {
+        if let Some(existing) = self.look_up(&key) {
+            return Err(BustubxError::DuplicateKey { rid: existing });
+        }
+        self.insert(key, rid);
+        Ok(())
     }
 
     pub fn batch_insert(&mut self, kvs: Vec<LeafKV>) {
@@ -362,12 +342,25 @@ impl BPlusTreeLeafPage {
         new_array
     }
 
-    pub fn delete(&mut self, key: &Tuple) {
+    // Returns whether the page held the key
+    pub fn delete(&mut self, key: &Tuple) -> bool {
         let key_index = self.key_index(key);
         if let Some(index) = key_index {
-            self.array.remove(index);
-            self.header.current_size -= 1;
+            self.delete_at(index);
         }
+        key_index.is_some()
+    }
+
+    pub fn delete_at(&mut self, index: usize) {
+        self.array.remove(index);
+        self.header.current_size -= 1;
+    }
+
+    // Find the index of the entry of the key mapping to rid, among the entries of equal keys
+    pub fn entry_index(&self, key: &Tuple, rid: RecordId) -> Option<usize> {
+        let start = self.array.partition_point(|kv| kv.0 < *key);
+        let end = self.array.partition_point(|kv| kv.0 <= *key);
+        (start..end).find(|index| self.array[*index].1 == rid)
     }
 
     // Find the rid corresponding to the key
@@ -377,38 +370,23 @@ impl BPlusTreeLeafPage {
     }
 
     fn key_index(&self, key: &Tuple) -> Option<usize> {
-        if self.header.current_size == 0 {
-            return None;
-        }
-        let mut start: i32 = 0;
-        let mut end: i32 = self.header.current_size as i32 - 1;
-        while start < end {
-            let mid = (start + end) / 2;
-            let compare_res = key.partial_cmp(&self.array[mid as usize].0).unwrap();
-            if compare_res == std::cmp::Ordering::Equal {
-                return Some(mid as usize);
-            } else if compare_res == std::cmp::Ordering::Less {
-                end = mid - 1;
-            } else {
-                start = mid + 1;
-            }
-        }
-        if key.partial_cmp(&self.array[start as usize].0).unwrap() == std::cmp::Ordering::Equal {
-            return Some(start as usize);
-        }
-        None
+        self.array
+            .binary_search_by(|kv| kv.0.partial_cmp(key).unwrap())
+            .ok()
     }
 
+    // Find the index of the first key greater than (or equal to, if included) the tuple
     pub fn next_closest(&self, tuple: &Tuple, included: bool) -> Option<usize> {
-        for (idx, (key, _)) in self.array.iter().enumerate() {
-            if tuple == key && included {
-                return Some(idx);
-            }
-            if key > tuple {
-                return Some(idx);
-            }
+        let index = if included {
+            self.array.partition_point(|kv| kv.0 < *tuple)
+        } else {
+            self.array.partition_point(|kv| kv.0 <= *tuple)
+        };
+        if index < self.array.len() {
+            Some(index)
+        } else {
+            None
         }
-        None
     }
 }
 
@@ -419,6 +397,7 @@ mod tests {
     use crate::{
         catalog::{Column, DataType, Schema},
         storage::{RecordId, Tuple},
+        BustubxError,
     };
     use std::sync::Arc;
 
@@ -478,6 +457,35 @@ mod tests {
         assert_eq!(leaf_page.array[2].1, RecordId::new(3, 3));
     }
 
+    #[test]
+    pub fn test_leaf_page_insert_unique() {
+        let key_schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int8, false),
+            Column::new("b", DataType::Int16, false),
+        ]));
+        let key = |i: i8| Tuple::new(key_schema.clone(), vec![i.into(), (i as i16).into()]);
+        let mut leaf_page = BPlusTreeLeafPage::new(key_schema.clone(), 3);
+        leaf_page
+            .insert_unique(key(2), RecordId::new(2, 2))
+            .unwrap();
+        leaf_page
+            .insert_unique(key(1), RecordId::new(1, 1))
+            .unwrap();
+
+        let err = leaf_page
+            .insert_unique(key(2), RecordId::new(9, 9))
+            .unwrap_err();
+        assert!(
+            matches!(err, BustubxError::DuplicateKey { rid } if rid == RecordId::new(2, 2)),
+            "{err}"
+        );
+        assert_eq!(leaf_page.header.current_size, 2);
+        assert_eq!(
+            leaf_page.array,
+            vec![(key(1), RecordId::new(1, 1)), (key(2), RecordId::new(2, 2))]
+        );
+    }
+
     #[test]
     pub fn test_internal_page_look_up() {
         let key_schema = Arc::new(Schema::new(vec![
@@ -782,4 +790,91 @@ mod tests {
         ));
         assert_eq!(leaf_page.header.current_size, 0);
     }
+
+    #[test]
+    pub fn test_internal_page_key_index() {
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int8, false)]));
+        let mut internal_page = BPlusTreeInternalPage::new(key_schema.clone(), 10);
+        internal_page.insert(Tuple::empty(key_schema.clone()), 0);
+        assert_eq!(
+            internal_page.key_index(&Tuple::new(key_schema.clone(), vec![1i8.into()])),
+            None
+        );
+        for i in [5i8, 1, 9, 3, 7] {
+            internal_page.insert(Tuple::new(key_schema.clone(), vec![i.into()]), i as u32);
+        }
+        assert_eq!(internal_page.array[0].1, 0);
+        assert_eq!(internal_page.values(), vec![0, 1, 3, 5, 7, 9]);
+
+        // first / last / absent keys
+        assert_eq!(
+            internal_page.key_index(&Tuple::new(key_schema.clone(), vec![1i8.into()])),
+            Some(1)
+        );
+        assert_eq!(
+            internal_page.key_index(&Tuple::new(key_schema.clone(), vec![9i8.into()])),
+            Some(5)
+        );
+        assert_eq!(
+            internal_page.key_index(&Tuple::new(key_schema.clone(), vec![4i8.into()])),
+            None
+        );
+        // the empty key slot is never matched
+        assert_eq!(
+            internal_page.key_index(&Tuple::empty(key_schema.clone())),
+            None
+        );
+
+        // keys smaller than every non-empty key fall back to the first slot
+        assert_eq!(
+            internal_page.look_up(&Tuple::new(key_schema.clone(), vec![0i8.into()])),
+            0
+        );
+        assert_eq!(
+            internal_page.look_up(&Tuple::new(key_schema.clone(), vec![1i8.into()])),
+            1
+        );
+        assert_eq!(
+            internal_page.look_up(&Tuple::new(key_schema.clone(), vec![4i8.into()])),
+            3
+        );
+        assert_eq!(
+            internal_page.look_up(&Tuple::new(key_schema.clone(), vec![9i8.into()])),
+            9
+        );
+        assert_eq!(
+            internal_page.look_up(&Tuple::new(key_schema.clone(), vec![100i8.into()])),
+            9
+        );
+    }
+
+    #[test]
+    pub fn test_leaf_page_next_closest() {
+        let key_schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int8, false)]));
+        let mut leaf_page = BPlusTreeLeafPage::new(key_schema.clone(), 10);
+        assert_eq!(
+            leaf_page.next_closest(&Tuple::new(key_schema.clone(), vec![1i8.into()]), true),
+            None
+        );
+        for i in [5i8, 1, 9, 3, 7] {
+            leaf_page.insert(
+                Tuple::new(key_schema.clone(), vec![i.into()]),
+                RecordId::new(i as u32, i as u32),
+            );
+        }
+
+        let key = |v: i8| Tuple::new(key_schema.clone(), vec![v.into()]);
+        assert_eq!(leaf_page.next_closest(&key(0), true), Some(0));
+        assert_eq!(leaf_page.next_closest(&key(1), true), Some(0));
+        assert_eq!(leaf_page.next_closest(&key(1), false), Some(1));
+        assert_eq!(leaf_page.next_closest(&key(4), true), Some(2));
+        assert_eq!(leaf_page.next_closest(&key(4), false), Some(2));
+        assert_eq!(leaf_page.next_closest(&key(9), true), Some(4));
+        assert_eq!(leaf_page.next_closest(&key(9), false), None);
+        assert_eq!(leaf_page.next_closest(&key(10), true), None);
+
+        assert_eq!(leaf_page.look_up(&key(1)), Some(RecordId::new(1, 1)));
+        assert_eq!(leaf_page.look_up(&key(9)), Some(RecordId::new(9, 9)));
+        assert_eq!(leaf_page.look_up(&key(4)), None);
+    }
 }
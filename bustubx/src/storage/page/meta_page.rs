@@ -3,6 +3,9 @@ use crate::storage::codec::MetaPageCodec;
 use crate::{BustubxError, BustubxResult};
 use std::sync::LazyLock;
 
+// First bytes of every database file
+pub const DB_FILE_MAGIC: [u8; 8] = *b"BUSTUBX\0";
+
 pub static EMPTY_META_PAGE: MetaPage = MetaPage {
     major_version: 0,
     minor_version: 0,
@@ -11,6 +14,7 @@ pub static EMPTY_META_PAGE: MetaPage = MetaPage {
     information_schema_tables_first_page_id: 0,
     information_schema_columns_first_page_id: 0,
     information_schema_indexes_first_page_id: 0,
+    clean_shutdown: false,
 };
 
 pub static META_PAGE_SIZE: LazyLock<usize> =
@@ -25,6 +29,8 @@ pub struct MetaPage {
     pub information_schema_tables_first_page_id: PageId,
     pub information_schema_columns_first_page_id: PageId,
     pub information_schema_indexes_first_page_id: PageId,
+    // set by a clean close, cleared again once the database is opened
+    pub clean_shutdown: bool,
 }
 
 impl MetaPage {
@@ -52,6 +58,8 @@ impl MetaPage {
             information_schema_tables_first_page_id: INVALID_PAGE_ID,
             information_schema_columns_first_page_id: INVALID_PAGE_ID,
             information_schema_indexes_first_page_id: INVALID_PAGE_ID,
+            // a new file has nothing to recover
+            clean_shutdown: true,
         })
     }
 }
@@ -1,6 +1,10 @@
 use crate::buffer::{PageId, BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID};
-use crate::catalog::SchemaRef;
-use crate::storage::codec::{TablePageHeaderCodec, TablePageHeaderTupleInfoCodec, TupleCodec};
+use crate::catalog::{Schema, SchemaRef};
+use crate::storage::codec::{
+    CommonCodec, TablePageHeaderCodec, TablePageHeaderTupleInfoCodec, TupleCodec,
+    TABLE_PAGE_COUNTS_OFFSET,
+};
+use crate::storage::RowId;
 use crate::transaction::TransactionId;
 use crate::{BustubxError, BustubxResult, Tuple};
 use std::sync::LazyLock;
@@ -9,6 +13,7 @@ pub static EMPTY_TUPLE_META: TupleMeta = TupleMeta {
     insert_txn_id: 0,
     delete_txn_id: 0,
     is_deleted: false,
+    row_id: None,
 };
 
 pub static EMPTY_TUPLE_INFO: LazyLock<TupleInfo> = LazyLock::new(|| TupleInfo {
@@ -26,9 +31,11 @@ pub static EMPTY_TUPLE_INFO: LazyLock<TupleInfo> = LazyLock::new(|| TupleInfo {
  *                                free space pointer
  *
  *  Header format (size in bytes):
- *  ----------------------------------------------------------------------------
- *  | NextPageId (4)| NumTuples(2) | NumDeletedTuples(2) |
- *  ----------------------------------------------------------------------------
+ *  ----------------------------------------------------------------------------------------
+ *  | NextPageId (4)| NumTuples(2) | NumDeletedTuples(2) | LiveTuples(2) | SchemaFingerprint(4) |
+ *  ----------------------------------------------------------------------------------------
+ *  The high bit of NumTuples marks headers with LiveTuples, the next one headers with the
+ *  SchemaFingerprint, older pages lack them.
  *  ----------------------------------------------------------------
  *  | Tuple_1 offset+size + TupleMeta | Tuple_2 offset+size + TupleMeta | ... |
  *  ----------------------------------------------------------------
@@ -45,8 +52,13 @@ pub struct TablePage {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct TablePageHeader {
     pub next_page_id: PageId,
+    // slots, deleted tuples included
     pub num_tuples: u16,
     pub num_deleted_tuples: u16,
+    pub live_tuples: u16,
+    // of the schema the tuples are encoded with, None for a page written before it was
+    // stored until its next write
+    pub schema_fingerprint: Option<u32>,
     pub tuple_infos: Vec<TupleInfo>,
 }
 
@@ -62,14 +74,19 @@ pub struct TupleMeta {
     pub insert_txn_id: TransactionId,
     pub delete_txn_id: TransactionId,
     pub is_deleted: bool,
+    // logical row id of rows in a table created with logical row ids, see `RowIdMap`
+    pub row_id: Option<RowId>,
 }
 
+/// Byte ranges of an encoded page to overwrite, as (offset, bytes).
+pub type PagePatch = Vec<(usize, Vec<u8>)>;
+
 pub const INVALID_RID: RecordId = RecordId {
     page_id: INVALID_PAGE_ID,
     slot_num: 0,
 };
 
-#[derive(derive_new::new, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(derive_new::new, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct RecordId {
     pub page_id: PageId,
     pub slot_num: u32,
@@ -78,19 +95,21 @@ pub struct RecordId {
 impl TablePage {
     pub fn new(schema: SchemaRef, next_page_id: PageId) -> Self {
         Self {
-            schema,
             header: TablePageHeader {
                 next_page_id,
                 num_tuples: 0,
                 num_deleted_tuples: 0,
+                live_tuples: 0,
+                schema_fingerprint: Some(TupleCodec::schema_fingerprint(&schema)),
                 tuple_infos: Vec::new(),
             },
+            schema,
             data: [0; BUSTUBX_PAGE_SIZE],
         }
     }
 
     // Get the offset for the next tuple insertion.
-    pub fn next_tuple_offset(&self, tuple: &Tuple) -> BustubxResult<usize> {
+    pub fn next_tuple_offset(&self, meta: &TupleMeta, tuple: &Tuple) -> BustubxResult<usize> {
         // Get the ending offset of the current slot. If there are inserted tuples,
         // get the offset of the previous inserted tuple; otherwise, set it to the size of the page.
         let slot_end_offset = if self.header.num_tuples > 0 {
@@ -113,7 +132,11 @@ impl TablePage {
         // Calculate the minimum valid tuple insertion offset, including the table page header size,
         // the total size of each tuple info (existing tuple infos and newly added tuple info).
         let min_tuple_offset = TablePageHeaderCodec::encode(&self.header).len()
-            + TablePageHeaderTupleInfoCodec::encode(&EMPTY_TUPLE_INFO).len();
+            + TablePageHeaderTupleInfoCodec::encode(&TupleInfo {
+                meta: *meta,
+                ..EMPTY_TUPLE_INFO.clone()
+            })
+            .len();
         if tuple_offset < min_tuple_offset {
             return Err(BustubxError::Storage(
                 "No enough space to store tuple".to_string(),
@@ -125,30 +148,67 @@ impl TablePage {
     }
 
     pub fn insert_tuple(&mut self, meta: &TupleMeta, tuple: &Tuple) -> BustubxResult<u16> {
-        // Get the offset for the next tuple insertion.
-        let tuple_offset = self.next_tuple_offset(tuple)?;
-        let tuple_id = self.header.num_tuples;
-        let tuple_bytes = TupleCodec::encode(tuple);
-        debug_assert!(tuple_bytes.len() < u16::MAX as usize);
-
-        // Store tuple information including offset, length, and metadata.
-        self.header.tuple_infos.push(TupleInfo {
-            offset: tuple_offset as u16,
-            size: tuple_bytes.len() as u16,
-            meta: *meta,
-        });
-
-        // only check
-        assert_eq!(tuple_id, self.header.tuple_infos.len() as u16 - 1);
+        Ok(self.insert_tuples(&[(*meta, tuple)])?[0])
+    }
 
-        self.header.num_tuples += 1;
-        if meta.is_deleted {
-            self.header.num_deleted_tuples += 1;
+    /// Insert `items` into consecutive slots, returning the slots in the order of `items`.
+    ///
+    /// The space of all tuples and tuple infos is computed before the page is touched, so
+    /// either every item is inserted or none is. When they do not all fit the error is
+    /// [`BustubxError::TablePageFull`] with the number of leading items that would, for the
+    /// caller to insert those and move the rest to another page.
+    pub fn insert_tuples(&mut self, items: &[(TupleMeta, &Tuple)]) -> BustubxResult<Vec<u16>> {
+        let tuple_bytes = items
+            .iter()
+            .map(|(_, tuple)| TupleCodec::encode(tuple))
+            .collect::<Vec<_>>();
+
+        // tuples are stored from the end of the page down while the tuple infos grow the
+        // header up, the page is full where they meet
+        let mut header_end = TablePageHeaderCodec::encode(&self.header).len();
+        let mut slot_end_offset = match self.header.tuple_infos.last() {
+            Some(info) => info.offset as usize,
+            None => BUSTUBX_PAGE_SIZE,
+        };
+        let mut tuple_offsets = Vec::with_capacity(items.len());
+        for ((meta, _), bytes) in items.iter().zip(tuple_bytes.iter()) {
+            debug_assert!(bytes.len() < u16::MAX as usize);
+            header_end += TablePageHeaderTupleInfoCodec::encode(&TupleInfo {
+                meta: *meta,
+                ..EMPTY_TUPLE_INFO.clone()
+            })
+            .len();
+            match slot_end_offset.checked_sub(bytes.len()) {
+                Some(tuple_offset) if tuple_offset >= header_end => {
+                    tuple_offsets.push(tuple_offset);
+                    slot_end_offset = tuple_offset;
+                }
+                _ => {
+                    return Err(BustubxError::TablePageFull {
+                        fits: tuple_offsets.len(),
+                        tuples: items.len(),
+                    })
+                }
+            }
         }
 
-        // Copy the tuple's data into the appropriate position within the page's data buffer.
-        self.data[tuple_offset..tuple_offset + tuple_bytes.len()].copy_from_slice(&tuple_bytes);
-        Ok(tuple_id)
+        let first_slot = self.header.num_tuples;
+        self.header.tuple_infos.reserve(items.len());
+        for (((meta, _), bytes), tuple_offset) in items.iter().zip(tuple_bytes).zip(tuple_offsets) {
+            self.header.tuple_infos.push(TupleInfo {
+                offset: tuple_offset as u16,
+                size: bytes.len() as u16,
+                meta: *meta,
+            });
+            if meta.is_deleted {
+                self.header.num_deleted_tuples += 1;
+            } else {
+                self.header.live_tuples += 1;
+            }
+            self.data[tuple_offset..tuple_offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        self.header.num_tuples += items.len() as u16;
+        Ok((first_slot..self.header.num_tuples).collect())
     }
 
     pub fn update_tuple_meta(&mut self, meta: TupleMeta, slot_num: u16) -> BustubxResult<()> {
@@ -158,14 +218,132 @@ impl TablePage {
                 slot_num
             )));
         }
-        if meta.is_deleted && !self.header.tuple_infos[slot_num as usize].meta.is_deleted {
+        let was_deleted = self.header.tuple_infos[slot_num as usize].meta.is_deleted;
+        if meta.is_deleted && !was_deleted {
             self.header.num_deleted_tuples += 1;
+            self.header.live_tuples -= 1;
+        } else if !meta.is_deleted && was_deleted {
+            // a rolled back delete
+            self.header.num_deleted_tuples -= 1;
+            self.header.live_tuples += 1;
         }
 
         self.header.tuple_infos[slot_num as usize].meta = meta;
         Ok(())
     }
 
+    /// Patch of the encoded page `data` inserting `tuple`, and the slot of the tuple. The
+    /// patch writes the counts, the new tuple info and the tuple, leaving the page as
+    /// decoding it, calling [`TablePage::insert_tuple`] and encoding it again would.
+    ///
+    /// None when the tuple does not fit or the header changes layout on the next write,
+    /// the caller takes the full path then. Err when the page holds tuples of another
+    /// schema than `schema`.
+    pub fn insert_tuple_patch(
+        data: &[u8],
+        schema: &Schema,
+        meta: &TupleMeta,
+        tuple: &Tuple,
+    ) -> BustubxResult<Option<(u16, PagePatch)>> {
+        let Some(layout) = TablePageHeaderCodec::layout(data)? else {
+            return Ok(None);
+        };
+        TupleCodec::check_schema_fingerprint(schema, Some(layout.schema_fingerprint))?;
+        let offsets = &layout.tuple_info_offsets;
+        let header_end = offsets[offsets.len() - 1];
+        // tuples are stored from the end of the page down, the last one is the lowest
+        let slot_end_offset = match offsets.len() {
+            1 => BUSTUBX_PAGE_SIZE,
+            len => CommonCodec::decode_u16(&data[offsets[len - 2]..])?.0 as usize,
+        };
+        let tuple_bytes = TupleCodec::encode(tuple);
+        let Some(tuple_offset) = slot_end_offset.checked_sub(tuple_bytes.len()) else {
+            return Ok(None);
+        };
+        let info_bytes = TablePageHeaderTupleInfoCodec::encode(&TupleInfo {
+            offset: tuple_offset as u16,
+            size: tuple_bytes.len() as u16,
+            meta: *meta,
+        });
+        if tuple_offset < header_end + info_bytes.len() {
+            return Ok(None);
+        }
+
+        let (mut num_deleted_tuples, mut live_tuples) =
+            (layout.num_deleted_tuples, layout.live_tuples);
+        if meta.is_deleted {
+            num_deleted_tuples += 1;
+        } else {
+            live_tuples += 1;
+        }
+        let patch = vec![
+            (
+                TABLE_PAGE_COUNTS_OFFSET,
+                TablePageHeaderCodec::encode_counts(
+                    layout.num_tuples + 1,
+                    num_deleted_tuples,
+                    live_tuples,
+                ),
+            ),
+            (header_end, info_bytes),
+            (tuple_offset, tuple_bytes),
+        ];
+        Ok(Some((layout.num_tuples, patch)))
+    }
+
+    /// Patch of the encoded page `data` replacing the meta of slot `slot_num`, and whether
+    /// the slot was deleted before. The patch writes the counts and the tuple info, leaving
+    /// the page as [`TablePage::update_tuple_meta`] on the decoded page would.
+    ///
+    /// None when the tuple info changes size, as it does gaining or losing a row id, or the
+    /// header changes layout on the next write. Err like [`TablePage::insert_tuple_patch`].
+    pub fn update_tuple_meta_patch(
+        data: &[u8],
+        schema: &Schema,
+        meta: TupleMeta,
+        slot_num: u16,
+    ) -> BustubxResult<Option<(bool, PagePatch)>> {
+        let Some(layout) = TablePageHeaderCodec::layout(data)? else {
+            return Ok(None);
+        };
+        TupleCodec::check_schema_fingerprint(schema, Some(layout.schema_fingerprint))?;
+        if slot_num >= layout.num_tuples {
+            return Err(BustubxError::Storage(format!(
+                "tuple_id {} out of range",
+                slot_num
+            )));
+        }
+        let info_offset = layout.tuple_info_offsets[slot_num as usize];
+        let (info, info_len) = TablePageHeaderTupleInfoCodec::decode(&data[info_offset..])?;
+        let info_bytes = TablePageHeaderTupleInfoCodec::encode(&TupleInfo { meta, ..info });
+        if info_bytes.len() != info_len {
+            return Ok(None);
+        }
+
+        let was_deleted = info.meta.is_deleted;
+        let (mut num_deleted_tuples, mut live_tuples) =
+            (layout.num_deleted_tuples, layout.live_tuples);
+        if meta.is_deleted && !was_deleted {
+            num_deleted_tuples += 1;
+            live_tuples -= 1;
+        } else if !meta.is_deleted && was_deleted {
+            num_deleted_tuples -= 1;
+            live_tuples += 1;
+        }
+        let patch = vec![
+            (
+                TABLE_PAGE_COUNTS_OFFSET,
+                TablePageHeaderCodec::encode_counts(
+                    layout.num_tuples,
+                    num_deleted_tuples,
+                    live_tuples,
+                ),
+            ),
+            (info_offset, info_bytes),
+        ];
+        Ok(Some((was_deleted, patch)))
+    }
+
     pub fn update_tuple(&mut self, tuple: Tuple, slot_num: u16) -> BustubxResult<()> {
         if slot_num >= self.header.num_tuples {
             return Err(BustubxError::Storage(format!(
@@ -235,19 +413,26 @@ impl TablePage {
 
     pub fn get_next_rid(&self, rid: &RecordId) -> Option<RecordId> {
         // TODO: ignore deleted tuples
-        let tuple_id = rid.slot_num;
-        if tuple_id + 1 >= self.header.num_tuples as u32 {
+        let tuple_id = rid.slot_num.checked_add(1)?;
+        if tuple_id >= self.header.num_tuples as u32 {
             return None;
         }
 
-        Some(RecordId::new(rid.page_id, tuple_id + 1))
+        Some(RecordId::new(rid.page_id, tuple_id))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::buffer::{BUSTUBX_PAGE_SIZE, INVALID_PAGE_ID};
     use crate::catalog::{Column, DataType, Schema};
-    use crate::storage::{Tuple, EMPTY_TUPLE_META};
+    use crate::storage::codec::{
+        TablePageCodec, TablePageHeaderCodec, TablePageHeaderTupleInfoCodec, TupleCodec,
+    };
+    use crate::storage::{
+        RecordId, TablePage, Tuple, TupleMeta, EMPTY_TUPLE_INFO, EMPTY_TUPLE_META,
+    };
+    use crate::BustubxError;
     use std::sync::Arc;
 
     #[test]
@@ -288,6 +473,93 @@ mod tests {
         assert_eq!(tuple.data, vec![3i8.into(), 3i16.into()]);
     }
 
+    #[test]
+    pub fn test_table_page_next_rid() {
+        let schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int8, false)]));
+        let mut table_page = super::TablePage::new(schema.clone(), 0);
+        for i in 0..2i8 {
+            table_page
+                .insert_tuple(
+                    &EMPTY_TUPLE_META,
+                    &Tuple::new(schema.clone(), vec![i.into()]),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            table_page.get_next_rid(&RecordId::new(u32::MAX, 0)),
+            Some(RecordId::new(u32::MAX, 1))
+        );
+        assert_eq!(table_page.get_next_rid(&RecordId::new(u32::MAX, 1)), None);
+        assert_eq!(
+            table_page.get_next_rid(&RecordId::new(u32::MAX, u32::MAX)),
+            None
+        );
+    }
+
+    #[test]
+    pub fn test_table_page_insert_tuples() {
+        let schema = Arc::new(Schema::new(vec![Column::new(
+            "a",
+            DataType::Varchar(None),
+            false,
+        )]));
+        let tuple = |len: usize| Tuple::new(schema.clone(), vec!["x".repeat(len).into()]);
+        let info_len = TablePageHeaderTupleInfoCodec::encode(&EMPTY_TUPLE_INFO).len();
+        let empty_len = TupleCodec::encode(&tuple(0)).len();
+
+        let mut table_page = TablePage::new(schema.clone(), INVALID_PAGE_ID);
+        assert!(table_page.insert_tuples(&[]).unwrap().is_empty());
+        assert_eq!(table_page, TablePage::new(schema.clone(), INVALID_PAGE_ID));
+
+        // nine tuples of 100 bytes and a last one taking the rest of the page
+        let free = BUSTUBX_PAGE_SIZE - TablePageHeaderCodec::encode(&table_page.header).len();
+        let last_len = free - 10 * info_len - 9 * (empty_len + 100) - empty_len;
+        let tuples = (0..9)
+            .map(|_| tuple(100))
+            .chain([tuple(last_len)])
+            .collect::<Vec<_>>();
+        let items = tuples
+            .iter()
+            .map(|tuple| (EMPTY_TUPLE_META, tuple))
+            .collect::<Vec<_>>();
+
+        // one tuple over the page, nothing is inserted
+        let mut over = items.clone();
+        over.push((EMPTY_TUPLE_META, &tuples[0]));
+        assert!(matches!(
+            table_page.insert_tuples(&over),
+            Err(BustubxError::TablePageFull {
+                fits: 10,
+                tuples: 11
+            })
+        ));
+        assert_eq!(table_page, TablePage::new(schema.clone(), INVALID_PAGE_ID));
+
+        assert_eq!(
+            table_page.insert_tuples(&items).unwrap(),
+            (0..10).collect::<Vec<u16>>()
+        );
+        // the page is full to the last byte
+        assert_eq!(
+            TablePageHeaderCodec::encode(&table_page.header).len(),
+            table_page.header.tuple_infos[9].offset as usize
+        );
+        assert!(matches!(
+            table_page.insert_tuple(&EMPTY_TUPLE_META, &tuple(0)),
+            Err(BustubxError::TablePageFull { fits: 0, .. })
+        ));
+
+        // the same page as inserting the tuples one by one
+        let mut one_by_one = TablePage::new(schema.clone(), INVALID_PAGE_ID);
+        for (meta, tuple) in items.iter() {
+            one_by_one.insert_tuple(meta, tuple).unwrap();
+        }
+        assert_eq!(table_page, one_by_one);
+        let (decoded, _) =
+            TablePageCodec::decode(&TablePageCodec::encode(&table_page), schema.clone()).unwrap();
+        assert_eq!(decoded.tuple(9).unwrap().1.data, tuples[9].data);
+    }
+
     #[test]
     pub fn test_table_page_update_tuple_meta() {
         let schema = Arc::new(Schema::new(vec![
@@ -325,4 +597,92 @@ mod tests {
         assert_eq!(tuple_meta.delete_txn_id, 1);
         assert_eq!(tuple_meta.insert_txn_id, 2);
     }
+
+    #[test]
+    pub fn test_table_page_patches_match_full_path() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int32, false),
+            Column::new("b", DataType::Varchar(None), true),
+        ]));
+        let mut table_page = super::TablePage::new(schema.clone(), 7);
+        let mut bytes = TablePageCodec::encode(&table_page);
+        let apply = |bytes: &mut Vec<u8>, patch: super::PagePatch| {
+            for (offset, patch_bytes) in patch {
+                bytes[offset..offset + patch_bytes.len()].copy_from_slice(&patch_bytes);
+            }
+        };
+
+        // fill the page, every third row with a row id
+        let mut slots = 0;
+        loop {
+            let tuple = Tuple::new(
+                schema.clone(),
+                vec![slots.into(), format!("value {slots}").into()],
+            );
+            let meta = TupleMeta {
+                row_id: (slots % 3 == 0).then_some(slots as u64),
+                ..EMPTY_TUPLE_META
+            };
+            let Some((slot_num, patch)) =
+                super::TablePage::insert_tuple_patch(&bytes, &schema, &meta, &tuple).unwrap()
+            else {
+                assert!(table_page.next_tuple_offset(&meta, &tuple).is_err());
+                break;
+            };
+            apply(&mut bytes, patch);
+            assert_eq!(table_page.insert_tuple(&meta, &tuple).unwrap(), slot_num);
+            assert_eq!(
+                bytes,
+                TablePageCodec::encode(&table_page),
+                "slot {slot_num}"
+            );
+            slots += 1;
+        }
+        assert!(slots > 50, "{slots}");
+
+        for (slot_num, is_deleted) in [(0, true), (4, true), (5, true), (4, false), (5, true)] {
+            let meta = TupleMeta {
+                is_deleted,
+                delete_txn_id: 9,
+                ..table_page.tuple_meta(slot_num).unwrap()
+            };
+            let (was_deleted, patch) =
+                super::TablePage::update_tuple_meta_patch(&bytes, &schema, meta, slot_num)
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(
+                was_deleted,
+                table_page.tuple_meta(slot_num).unwrap().is_deleted
+            );
+            apply(&mut bytes, patch);
+            table_page.update_tuple_meta(meta, slot_num).unwrap();
+            assert_eq!(
+                bytes,
+                TablePageCodec::encode(&table_page),
+                "slot {slot_num}"
+            );
+        }
+        assert_eq!(table_page.header.live_tuples as i32, slots - 2);
+
+        // a row id changes the size of the tuple info, left to the full path
+        let meta = TupleMeta {
+            row_id: Some(1000),
+            ..table_page.tuple_meta(1).unwrap()
+        };
+        assert!(
+            super::TablePage::update_tuple_meta_patch(&bytes, &schema, meta, 1)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            super::TablePage::update_tuple_meta_patch(&bytes, &schema, meta, slots as u16).is_err()
+        );
+
+        // never patched with a schema the page was not written with
+        let other_schema = Schema::new(vec![Column::new("a", DataType::Int64, false)]);
+        assert!(matches!(
+            super::TablePage::update_tuple_meta_patch(&bytes, &other_schema, meta, 0),
+            Err(crate::BustubxError::SchemaMismatch { .. })
+        ));
+    }
 }
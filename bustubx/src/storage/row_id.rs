@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+
+use crate::catalog::{Column, DataType, Schema, SchemaRef};
+use crate::storage::index::BPlusTreeIndex;
+use crate::storage::{RecordId, TableHeap, TableIterator};
+use crate::{BustubxResult, Tuple};
+
+pub type RowId = u64;
+
+// name the mapping is recorded under in information_schema.indexes
+pub static ROW_ID_INDEX_NAME: &str = "__row_ids";
+
+pub static ROW_ID_KEY_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![Column::new(
+        "row_id",
+        DataType::UInt64,
+        false,
+    )]))
+});
+
+/// Logical row ids of a table created with `logical_row_ids`.
+///
+/// Every row gets a monotonic id stored in its tuple header, and the secondary indexes of
+/// the table store that id instead of the record id of the row. A B+ tree keyed by the id
+/// maps it to the current record id, so moving rows only rewrites the mapping.
+#[derive(Debug)]
+pub struct RowIdMap {
+    pub index: Arc<BPlusTreeIndex>,
+    next_row_id: AtomicU64,
+}
+
+impl RowIdMap {
+    pub fn new(index: Arc<BPlusTreeIndex>, next_row_id: RowId) -> Self {
+        Self {
+            index,
+            next_row_id: AtomicU64::new(next_row_id),
+        }
+    }
+
+    /// Mapping of a heap loaded from disk, ids continue after the largest one in the heap.
+    pub fn open(index: Arc<BPlusTreeIndex>, heap: Arc<TableHeap>) -> BustubxResult<Self> {
+        let mut next_row_id = 0;
+        let mut iterator = TableIterator::new(heap.clone(), ..);
+        while let Some((rid, _)) = iterator.next()? {
+            if let Some(row_id) = heap.tuple_meta(rid)?.row_id {
+                next_row_id = next_row_id.max(row_id + 1);
+            }
+        }
+        Ok(Self::new(index, next_row_id))
+    }
+
+    pub fn allocate(&self) -> RowId {
+        self.next_row_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    pub fn map(&self, row_id: RowId, rid: RecordId) -> BustubxResult<()> {
+        self.index.insert(&Self::key(row_id), rid)
+    }
+
+    pub fn unmap(&self, row_id: RowId) -> BustubxResult<()> {
+        self.index.delete(&Self::key(row_id))?;
+        Ok(())
+    }
+
+    /// Record id the row currently lives at, None once the row is gone.
+    pub fn resolve(&self, row_id: RowId) -> BustubxResult<Option<RecordId>> {
+        self.index.get(&Self::key(row_id))
+    }
+
+    /// Replace the mapping with `entries`, e.g. after the rows moved.
+    pub fn rebuild(&self, entries: Vec<(RowId, RecordId)>) -> BustubxResult<()> {
+        self.index.rebuild(
+            entries
+                .into_iter()
+                .map(|(row_id, rid)| (Self::key(row_id), rid))
+                .collect(),
+        )
+    }
+
+    /// Value a secondary index stores for the row, the id split over the record id fields.
+    pub fn index_entry(row_id: RowId) -> RecordId {
+        RecordId::new((row_id >> 32) as u32, row_id as u32)
+    }
+
+    pub fn row_id_of(entry: RecordId) -> RowId {
+        ((entry.page_id as u64) << 32) | entry.slot_num as u64
+    }
+
+    fn key(row_id: RowId) -> Tuple {
+        Tuple::new(ROW_ID_KEY_SCHEMA.clone(), vec![row_id.into()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::Ordering;
+
+    use crate::common::{ScalarValue, TableReference};
+    use crate::storage::{RecordId, RowId, RowIdMap, TableIterator};
+    use crate::{Database, DatabaseOptions};
+    use tempfile::TempDir;
+
+    fn ints(db: &mut Database, sql: &str) -> Vec<i64> {
+        let mut rows = db
+            .run(sql)
+            .unwrap()
+            .into_iter()
+            .map(|tuple| match tuple.data[0] {
+                ScalarValue::Int32(Some(v)) => v as i64,
+                ScalarValue::Int64(Some(v)) => v,
+                ref v => panic!("unexpected value {v}"),
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    // record id of every live row keyed by its row id
+    fn locations(db: &Database, table: &str) -> HashMap<RowId, RecordId> {
+        let table_heap = db.catalog.table_heap(&TableReference::bare(table)).unwrap();
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        let mut locations = HashMap::new();
+        while let Some((rid, _)) = iterator.next().unwrap() {
+            let meta = table_heap.tuple_meta(rid).unwrap();
+            if !meta.is_deleted {
+                locations.insert(meta.row_id.unwrap(), rid);
+            }
+        }
+        locations
+    }
+
+    #[test]
+    pub fn test_index_entry_round_trip() {
+        for row_id in [
+            0,
+            1,
+            u32::MAX as u64,
+            1 << 32,
+            (1 << 32) + 1,
+            u64::MAX - 1,
+            u64::MAX,
+        ] {
+            let entry = RowIdMap::index_entry(row_id);
+            assert_eq!(RowIdMap::row_id_of(entry), row_id, "{entry:?}");
+        }
+        assert_eq!(
+            RowIdMap::index_entry(u64::MAX),
+            RecordId::new(u32::MAX, u32::MAX)
+        );
+        assert_eq!(RowIdMap::index_entry(1 << 32), RecordId::new(1, 0));
+    }
+
+    #[test]
+    pub fn test_index_lookups_survive_vacuum_full() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("test.db");
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        db.run("create table t1 (a int, b varchar(100)) with (logical_row_ids = true)")
+            .unwrap();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        for chunk in (0..1000).collect::<Vec<_>>().chunks(200) {
+            let values = chunk
+                .iter()
+                .map(|i| format!("({i}, 'row {i}')"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            db.run(&format!("insert into t1 values {values}")).unwrap();
+        }
+        // every remaining row follows a deleted one, so all of them move
+        db.run("delete from t1 where a - a / 2 * 2 = 0").unwrap();
+        let before = locations(&db, "t1");
+        assert_eq!(before.len(), 500);
+        let index = db
+            .catalog
+            .index(&TableReference::bare("t1"), "idx_a")
+            .unwrap()
+            .unwrap();
+        let root_page_id = index.root_page_id.load(Ordering::SeqCst);
+
+        db.run("vacuum full t1").unwrap();
+        let after = locations(&db, "t1");
+        assert_eq!(after.len(), 500);
+        assert!(before
+            .iter()
+            .all(|(row_id, rid)| after.get(row_id).is_some_and(|moved| moved != rid)));
+        // the secondary index was not rewritten
+        assert_eq!(index.root_page_id.load(Ordering::SeqCst), root_page_id);
+
+        let odd = (0..1000).filter(|a| a % 2 == 1).collect::<Vec<_>>();
+        assert_eq!(ints(&mut db, "select a from t1"), odd);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 751"), vec![751]);
+        assert!(ints(&mut db, "select a from t1 where a = 750").is_empty());
+        assert_eq!(
+            db.run("select b from t1 where a = 999").unwrap()[0].data,
+            vec!["row 999".to_string().into()]
+        );
+        db.close().unwrap();
+
+        let mut db = Database::open_with_options(&db_path, DatabaseOptions::default()).unwrap();
+        assert_eq!(ints(&mut db, "select a from t1"), odd);
+        assert_eq!(ints(&mut db, "select a from t1 where a = 3"), vec![3]);
+        // ids keep growing after reopening
+        db.run("insert into t1 values (2000, 'new')").unwrap();
+        let locations = locations(&db, "t1");
+        assert_eq!(locations.len(), 501);
+        assert_eq!(locations.keys().max(), Some(&1000));
+        assert_eq!(ints(&mut db, "select a from t1 where a = 2000"), vec![2000]);
+    }
+
+    #[test]
+    pub fn test_logical_row_ids_insert_update_delete() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int) with (logical_row_ids = 'true')")
+            .unwrap();
+        db.run("create unique index idx_a on t1 (a)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20), (3, 30)")
+            .unwrap();
+        assert_eq!(locations(&db, "t1").len(), 3);
+
+        // conflicts are found through the row ids
+        assert!(db.run("insert into t1 values (2, 0)").is_err());
+        db.run(
+            "insert into t1 values (2, 5), (4, 40) on conflict (a) do update set b = excluded.b",
+        )
+        .unwrap();
+        assert_eq!(ints(&mut db, "select b from t1 where a = 2"), vec![5]);
+        db.run("insert into t1 values (4, 0) on conflict (a) do update set a = 5")
+            .unwrap();
+        assert_eq!(ints(&mut db, "select b from t1 where a = 5"), vec![40]);
+        assert!(ints(&mut db, "select b from t1 where a = 4").is_empty());
+
+        db.run("update t1 set b = b + 1 where a >= 3").unwrap();
+        assert_eq!(ints(&mut db, "select b from t1"), vec![5, 10, 31, 41]);
+
+        db.run("delete from t1 where a = 1").unwrap();
+        assert_eq!(ints(&mut db, "select a from t1"), vec![2, 3, 5]);
+        // a deleted row gives up its row id, the key can be inserted again
+        db.run("insert into t1 values (1, 11)").unwrap();
+        assert_eq!(ints(&mut db, "select b from t1 where a = 1"), vec![11]);
+        let locations = locations(&db, "t1");
+        assert_eq!(locations.len(), 4);
+        assert_eq!(locations.keys().max(), Some(&4));
+
+        // a failing statement leaves the mapping as it was
+        assert!(db.run("insert into t1 values (6, 60), (1, 0)").is_err());
+        assert_eq!(ints(&mut db, "select a from t1"), vec![1, 2, 3, 5]);
+        assert_eq!(ints(&mut db, "select count(*) from t1"), vec![4]);
+
+        let ddl = db.run("show create table t1").unwrap();
+        let ScalarValue::Varchar(Some(ddl)) = &ddl[0].data[1] else {
+            panic!("unexpected show create table output {ddl:?}");
+        };
+        assert!(ddl.contains("WITH (logical_row_ids = true)"), "{ddl}");
+        assert!(db
+            .run("create table t2 (a int) with (logical_row_ids = 1)")
+            .is_err());
+        assert!(db.run("create index __row_ids on t1 (b)").is_err());
+    }
+}
@@ -1,13 +1,14 @@
-use crate::buffer::{AtomicPageId, INVALID_PAGE_ID};
+use crate::buffer::{operation_scope, AtomicPageId, PageId, PageRef, INVALID_PAGE_ID};
 use crate::catalog::SchemaRef;
-use crate::common::util::page_bytes_to_array;
-use crate::storage::codec::TablePageCodec;
+use crate::storage::codec::{TablePageCodec, TupleCodec};
+use crate::storage::integrity::{check_table_page, Finding};
 use crate::storage::{RecordId, TablePage, TupleMeta, INVALID_RID};
-use crate::{buffer::BufferPoolManager, BustubxResult};
-use std::collections::Bound;
+use crate::{buffer::BufferPoolManager, BustubxError, BustubxResult};
+use std::collections::{Bound, HashSet};
 use std::ops::RangeBounds;
-use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use super::tuple::Tuple;
 
@@ -17,24 +18,70 @@ pub struct TableHeap {
     pub buffer_pool: Arc<BufferPoolManager>,
     pub first_page_id: AtomicPageId,
     pub last_page_id: AtomicPageId,
+    // live tuples of the heap, unknown for a heap loaded from disk until its pages are counted
+    live_tuples: Mutex<Option<usize>>,
+    // tuples decoded from the heap pages
+    tuple_decodes: AtomicU64,
+    // how long page requests wait for a frame of a full buffer pool, zero fails right away
+    frame_wait: Duration,
 }
 
 impl TableHeap {
+    /// Heap whose page chain already exists on disk.
+    pub fn open(
+        schema: SchemaRef,
+        buffer_pool: Arc<BufferPoolManager>,
+        first_page_id: PageId,
+        last_page_id: PageId,
+    ) -> Self {
+        Self {
+            schema,
+            buffer_pool,
+            first_page_id: AtomicPageId::new(first_page_id),
+            last_page_id: AtomicPageId::new(last_page_id),
+            live_tuples: Mutex::new(None),
+            tuple_decodes: AtomicU64::new(0),
+            frame_wait: Duration::ZERO,
+        }
+    }
+
     pub fn try_new(schema: SchemaRef, buffer_pool: Arc<BufferPoolManager>) -> BustubxResult<Self> {
+        Self::try_new_with_frame_wait(schema, buffer_pool, Duration::ZERO)
+    }
+
+    /// Heap an operator spills rows to while it runs. Its page requests wait up to
+    /// [`BufferPoolManager::frame_wait_timeout`] for another thread to unpin a frame when the
+    /// buffer pool is full, instead of failing the statement.
+    pub fn try_new_spill(
+        schema: SchemaRef,
+        buffer_pool: Arc<BufferPoolManager>,
+    ) -> BustubxResult<Self> {
+        let frame_wait = buffer_pool.frame_wait_timeout();
+        Self::try_new_with_frame_wait(schema, buffer_pool, frame_wait)
+    }
+
+    fn try_new_with_frame_wait(
+        schema: SchemaRef,
+        buffer_pool: Arc<BufferPoolManager>,
+        frame_wait: Duration,
+    ) -> BustubxResult<Self> {
         // new a page and initialize
-        let first_page = buffer_pool.new_page()?;
+        let first_page = buffer_pool.new_page_blocking(frame_wait)?;
         let first_page_id = first_page.read().unwrap().page_id;
         let table_page = TablePage::new(schema.clone(), INVALID_PAGE_ID);
         first_page
             .write()
             .unwrap()
-            .set_data(page_bytes_to_array(&TablePageCodec::encode(&table_page)));
+            .encode_page::<TablePageCodec>(&table_page);
 
         Ok(Self {
             schema,
             buffer_pool,
             first_page_id: AtomicPageId::new(first_page_id),
             last_page_id: AtomicPageId::new(first_page_id),
+            live_tuples: Mutex::new(Some(0)),
+            tuple_decodes: AtomicU64::new(0),
+            frame_wait,
         })
     }
 
@@ -51,46 +98,58 @@ impl TableHeap {
     /// Returns:
     /// An `Option` containing the `Rid` of the inserted tuple if successful, otherwise `None`.
     pub fn insert_tuple(&self, meta: &TupleMeta, tuple: &Tuple) -> BustubxResult<RecordId> {
+        let _op = operation_scope("heap_insert");
         let mut last_page_id = self.last_page_id.load(Ordering::SeqCst);
-        let (last_page, mut last_table_page) = self
-            .buffer_pool
-            .fetch_table_page(last_page_id, self.schema.clone())?;
+
+        // a tuple fitting the last page is patched in without decoding the page
+        let last_page = self.fetch_page(last_page_id)?;
+        let mut page = last_page.write().unwrap();
+        if let Some((slot_id, patch)) =
+            TablePage::insert_tuple_patch(page.data(), &self.schema, meta, tuple)?
+        {
+            #[cfg(debug_assertions)]
+            self.assert_patch_matches(page.data(), &patch, |table_page| {
+                table_page.insert_tuple(meta, tuple).map(|_| ())
+            })?;
+            page.apply_patch(&patch);
+            drop(page);
+            if !meta.is_deleted {
+                self.adjust_live_tuples(1);
+            }
+            return Ok(RecordId::new(last_page_id, slot_id as u32));
+        }
+        drop(page);
+        drop(last_page);
+        self.check_tuple_sizes(&[(*meta, tuple)])?;
+
+        let (mut last_page, mut last_table_page) = self.fetch_table_page(last_page_id)?;
 
         // Loop until a suitable page is found for inserting the tuple
         loop {
-            if last_table_page.next_tuple_offset(tuple).is_ok() {
+            if last_table_page.next_tuple_offset(meta, tuple).is_ok() {
                 break;
             }
 
-            // if there's no tuple in the page, and we can't insert the tuple,
-            // then this tuple is too large.
-            assert!(
-                last_table_page.header.num_tuples > 0,
-                "tuple is too large, cannot insert"
-            );
-
             // Allocate a new page if no more table pages are available.
-            let next_page = self.buffer_pool.new_page()?;
+            // Nothing has been modified yet, so running out of disk space leaves the chain intact.
+            let next_page = self.new_page()?;
             let next_page_id = next_page.read().unwrap().page_id;
             let next_table_page = TablePage::new(self.schema.clone(), INVALID_PAGE_ID);
             next_page
                 .write()
                 .unwrap()
-                .set_data(page_bytes_to_array(&TablePageCodec::encode(
-                    &next_table_page,
-                )));
+                .encode_page::<TablePageCodec>(&next_table_page);
 
             // Update and release the previous page
             last_table_page.header.next_page_id = next_page_id;
             last_page
                 .write()
                 .unwrap()
-                .set_data(page_bytes_to_array(&TablePageCodec::encode(
-                    &last_table_page,
-                )));
+                .encode_page::<TablePageCodec>(&last_table_page);
 
             // Update last_page_id.
             last_page_id = next_page_id;
+            last_page = next_page;
             last_table_page = next_table_page;
             self.last_page_id.store(last_page_id, Ordering::SeqCst);
         }
@@ -101,43 +160,146 @@ impl TableHeap {
         last_page
             .write()
             .unwrap()
-            .set_data(page_bytes_to_array(&TablePageCodec::encode(
-                &last_table_page,
-            )));
+            .encode_page::<TablePageCodec>(&last_table_page);
+
+        if !meta.is_deleted {
+            self.adjust_live_tuples(1);
+        }
 
         // Map the slot_id to a Rid and return
         Ok(RecordId::new(last_page_id, slot_id as u32))
     }
 
+    /// [`TableHeap::insert_tuple`] of many tuples, which fill the last page in order and go
+    /// on in new pages. Every page the tuples go to is decoded and encoded once. Returns the
+    /// record ids in the order of `items`.
+    ///
+    /// A failure leaves the tuples of the pages filled before it in the heap.
+    pub fn insert_tuple_batch(
+        &self,
+        items: &[(TupleMeta, &Tuple)],
+    ) -> BustubxResult<Vec<RecordId>> {
+        let _op = operation_scope("heap_insert");
+        let mut rids = Vec::with_capacity(items.len());
+        if items.is_empty() {
+            return Ok(rids);
+        }
+        self.check_tuple_sizes(items)?;
+        let mut last_page_id = self.last_page_id.load(Ordering::SeqCst);
+        let (mut last_page, mut last_table_page) = self.fetch_table_page(last_page_id)?;
+        let mut rest = items;
+        let mut page_live_tuples = 0;
+        loop {
+            let slots = match last_table_page.insert_tuples(rest) {
+                Ok(slots) => slots,
+                Err(BustubxError::TablePageFull { fits, .. }) => {
+                    last_table_page.insert_tuples(&rest[..fits])?
+                }
+                Err(e) => return Err(e),
+            };
+            let (inserted, left) = rest.split_at(slots.len());
+            page_live_tuples += inserted.iter().filter(|(meta, _)| !meta.is_deleted).count();
+            rids.extend(
+                slots
+                    .into_iter()
+                    .map(|slot_id| RecordId::new(last_page_id, slot_id as u32)),
+            );
+            rest = left;
+            if rest.is_empty() {
+                break;
+            }
+
+            // Nothing of the page is written before the next one exists, so running out of
+            // disk space leaves the chain intact.
+            let next_page = self.new_page()?;
+            let next_page_id = next_page.read().unwrap().page_id;
+            let next_table_page = TablePage::new(self.schema.clone(), INVALID_PAGE_ID);
+            next_page
+                .write()
+                .unwrap()
+                .encode_page::<TablePageCodec>(&next_table_page);
+
+            last_table_page.header.next_page_id = next_page_id;
+            last_page
+                .write()
+                .unwrap()
+                .encode_page::<TablePageCodec>(&last_table_page);
+            self.adjust_live_tuples(std::mem::take(&mut page_live_tuples) as isize);
+
+            last_page_id = next_page_id;
+            last_page = next_page;
+            last_table_page = next_table_page;
+            self.last_page_id.store(last_page_id, Ordering::SeqCst);
+        }
+
+        last_page
+            .write()
+            .unwrap()
+            .encode_page::<TablePageCodec>(&last_table_page);
+        self.adjust_live_tuples(page_live_tuples as isize);
+        Ok(rids)
+    }
+
+    // A tuple not fitting an empty page fits no page, refused before anything is written.
+    fn check_tuple_sizes(&self, items: &[(TupleMeta, &Tuple)]) -> BustubxResult<()> {
+        let empty_page = TablePage::new(self.schema.clone(), INVALID_PAGE_ID);
+        for (meta, tuple) in items.iter() {
+            if empty_page.next_tuple_offset(meta, tuple).is_err() {
+                return Err(BustubxError::TupleTooLarge {
+                    size: TupleCodec::encode(tuple).len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn update_tuple(&self, rid: RecordId, tuple: Tuple) -> BustubxResult<()> {
-        let (page, mut table_page) = self
-            .buffer_pool
-            .fetch_table_page(rid.page_id, self.schema.clone())?;
+        let _op = operation_scope("heap_update");
+        let (page, mut table_page) = self.fetch_table_page(rid.page_id)?;
         table_page.update_tuple(tuple, rid.slot_num as u16)?;
 
         page.write()
             .unwrap()
-            .set_data(page_bytes_to_array(&TablePageCodec::encode(&table_page)));
+            .encode_page::<TablePageCodec>(&table_page);
         Ok(())
     }
 
     pub fn update_tuple_meta(&self, meta: TupleMeta, rid: RecordId) -> BustubxResult<()> {
-        let (page, mut table_page) = self
-            .buffer_pool
-            .fetch_table_page(rid.page_id, self.schema.clone())?;
-        table_page.update_tuple_meta(meta, rid.slot_num as u16)?;
-
-        page.write()
-            .unwrap()
-            .set_data(page_bytes_to_array(&TablePageCodec::encode(&table_page)));
+        let _op = operation_scope("heap_update_meta");
+        let slot_num = rid.slot_num as u16;
+        let page_ref = self.fetch_page(rid.page_id)?;
+        let mut page = page_ref.write().unwrap();
+        let patch = TablePage::update_tuple_meta_patch(page.data(), &self.schema, meta, slot_num)?;
+        let was_deleted = match patch {
+            Some((was_deleted, patch)) => {
+                #[cfg(debug_assertions)]
+                self.assert_patch_matches(page.data(), &patch, |table_page| {
+                    table_page.update_tuple_meta(meta, slot_num)
+                })?;
+                page.apply_patch(&patch);
+                was_deleted
+            }
+            None => {
+                let (mut table_page, _) = TablePageCodec::decode(page.data(), self.schema.clone())?;
+                let was_deleted = table_page.tuple_meta(slot_num)?.is_deleted;
+                table_page.update_tuple_meta(meta, slot_num)?;
+                page.encode_page::<TablePageCodec>(&table_page);
+                was_deleted
+            }
+        };
+        drop(page);
+        match (was_deleted, meta.is_deleted) {
+            (false, true) => self.adjust_live_tuples(-1),
+            (true, false) => self.adjust_live_tuples(1),
+            _ => {}
+        }
         Ok(())
     }
 
     pub fn full_tuple(&self, rid: RecordId) -> BustubxResult<(TupleMeta, Tuple)> {
-        let (_, table_page) = self
-            .buffer_pool
-            .fetch_table_page(rid.page_id, self.schema.clone())?;
+        let (_, table_page) = self.fetch_table_page(rid.page_id)?;
         let result = table_page.tuple(rid.slot_num as u16)?;
+        self.tuple_decodes.fetch_add(1, Ordering::Relaxed);
         Ok(result)
     }
 
@@ -146,45 +308,370 @@ impl TableHeap {
         Ok(tuple)
     }
 
+    /// Tuple at `rid` unless it is deleted or `rid` no longer holds a tuple of the heap, as
+    /// for a stale index entry whose page was reclaimed.
+    pub fn live_tuple(&self, rid: RecordId) -> BustubxResult<Option<Tuple>> {
+        match self.full_tuple(rid) {
+            Ok((meta, tuple)) => Ok((!meta.is_deleted).then_some(tuple)),
+            Err(BustubxError::Storage(_) | BustubxError::Decode { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn tuple_meta(&self, rid: RecordId) -> BustubxResult<TupleMeta> {
-        let (meta, _tuple) = self.full_tuple(rid)?;
-        Ok(meta)
+        let (_, table_page) = self.fetch_table_page(rid.page_id)?;
+        table_page.tuple_meta(rid.slot_num as u16)
     }
 
-    pub fn get_first_rid(&self) -> BustubxResult<Option<RecordId>> {
+    /// Live tuples kept up to date by the heap writes, None until the heap loaded from disk
+    /// was counted by [`TableHeap::count_pages`].
+    pub fn live_tuple_count(&self) -> Option<usize> {
+        *self.live_tuples.lock().unwrap()
+    }
+
+    /// (pages, live tuples, dead tuples) read from the page headers without decoding tuples,
+    /// the live tuple counter starts from the result.
+    pub fn count_pages(&self) -> BustubxResult<(usize, usize, usize)> {
+        let (mut pages, mut live_tuples, mut dead_tuples) = (0, 0, 0);
+        let mut page_id = self.first_page_id.load(Ordering::SeqCst);
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            let header = &table_page.header;
+            pages += 1;
+            dead_tuples += header.num_deleted_tuples as usize;
+            live_tuples += header.live_tuples as usize;
+            page_id = header.next_page_id;
+        }
+        *self.live_tuples.lock().unwrap() = Some(live_tuples);
+        Ok((pages, live_tuples, dead_tuples))
+    }
+
+    /// Page ids of the heap in chain order.
+    pub fn page_ids(&self) -> BustubxResult<Vec<PageId>> {
+        let mut page_ids = vec![];
+        let mut page_id = self.first_page_id.load(Ordering::SeqCst);
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            page_ids.push(page_id);
+            page_id = table_page.header.next_page_id;
+        }
+        Ok(page_ids)
+    }
+
+    /// Page ids of the heap in chain order split into at most `partitions` runs of
+    /// consecutive pages of about the same length, each run can be read with
+    /// [`TableHeap::page_tuples`] independently of the others.
+    pub fn partition_pages(&self, partitions: usize) -> BustubxResult<Vec<Vec<PageId>>> {
+        let page_ids = self.page_ids()?;
+        let run_len = page_ids.len().div_ceil(partitions.max(1)).max(1);
+        Ok(page_ids.chunks(run_len).map(|run| run.to_vec()).collect())
+    }
+
+    /// Tuples of one page in slot order, deleted ones included.
+    pub fn page_tuples(&self, page_id: PageId) -> BustubxResult<Vec<(RecordId, TupleMeta, Tuple)>> {
+        let (_, table_page) = self.fetch_table_page(page_id)?;
+        let tuples = (0..table_page.header.num_tuples)
+            .map(|slot_num| {
+                let (meta, tuple) = table_page.tuple(slot_num)?;
+                Ok((RecordId::new(page_id, slot_num as u32), meta, tuple))
+            })
+            .collect::<BustubxResult<Vec<_>>>()?;
+        self.tuple_decodes
+            .fetch_add(tuples.len() as u64, Ordering::Relaxed);
+        Ok(tuples)
+    }
+
+    /// Walk the page chain and check every page of it, `object` names the heap in the
+    /// findings. The chain must end at the last page without visiting a page twice, a page
+    /// that cannot be read ends the walk.
+    pub fn check(&self, object: &str) -> Vec<Finding> {
+        let mut findings = vec![];
+        let mut visited = HashSet::new();
+        let mut prev_page_id = INVALID_PAGE_ID;
+        let mut page_id = self.first_page_id.load(Ordering::SeqCst);
+        while page_id != INVALID_PAGE_ID {
+            if !visited.insert(page_id) {
+                findings.push(Finding::error(
+                    object,
+                    Some(prev_page_id),
+                    format!("next page {} closes a cycle in the page chain", page_id),
+                ));
+                return findings;
+            }
+            let bytes = match self.fetch_page(page_id) {
+                Ok(page) => page.read().unwrap().data().to_vec(),
+                Err(e) => {
+                    findings.push(Finding::error(
+                        object,
+                        Some(page_id),
+                        format!("page cannot be read: {e}"),
+                    ));
+                    return findings;
+                }
+            };
+            let Some(next_page_id) =
+                check_table_page(object, page_id, &bytes, self.schema.clone(), &mut findings)
+            else {
+                return findings;
+            };
+            prev_page_id = page_id;
+            page_id = next_page_id;
+        }
+        let last_page_id = self.last_page_id.load(Ordering::SeqCst);
+        if prev_page_id != last_page_id {
+            findings.push(Finding::error(
+                object,
+                Some(prev_page_id),
+                format!(
+                    "page chain ends at page {} instead of the last page {}",
+                    prev_page_id, last_page_id
+                ),
+            ));
+        }
+        findings
+    }
+
+    pub fn tuple_decodes(&self) -> u64 {
+        self.tuple_decodes.load(Ordering::Relaxed)
+    }
+
+    fn adjust_live_tuples(&self, delta: isize) {
+        if let Some(live_tuples) = self.live_tuples.lock().unwrap().as_mut() {
+            *live_tuples = live_tuples.saturating_add_signed(delta);
+        }
+    }
+
+    /// Return every page of the heap to the disk manager, the heap must not be used afterwards.
+    fn new_page(&self) -> BustubxResult<PageRef> {
+        self.buffer_pool.new_page_blocking(self.frame_wait)
+    }
+
+    fn fetch_page(&self, page_id: PageId) -> BustubxResult<PageRef> {
+        self.buffer_pool
+            .fetch_page_blocking(page_id, self.frame_wait)
+    }
+
+    // A patch must leave the page exactly as decoding it, applying `modify` and encoding it
+    // again would.
+    #[cfg(debug_assertions)]
+    fn assert_patch_matches(
+        &self,
+        data: &[u8],
+        patch: &crate::storage::PagePatch,
+        modify: impl FnOnce(&mut TablePage) -> BustubxResult<()>,
+    ) -> BustubxResult<()> {
+        let (mut table_page, _) = TablePageCodec::decode(data, self.schema.clone())?;
+        modify(&mut table_page)?;
+        // both pages on the stack, this runs for every patched write of a debug build
+        let mut patched = crate::common::util::page_bytes_to_array(data);
+        for (offset, bytes) in patch {
+            patched[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        let mut encoded = [0; crate::buffer::BUSTUBX_PAGE_SIZE];
+        TablePageCodec::encode_into(&table_page, &mut encoded);
+        assert!(
+            patched == encoded,
+            "patch {:?} differs from re-encoding the page",
+            patch
+                .iter()
+                .map(|(offset, bytes)| (*offset, bytes.len()))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    fn fetch_table_page(&self, page_id: PageId) -> BustubxResult<(PageRef, TablePage)> {
+        let page = self.fetch_page(page_id)?;
+        let (table_page, _) =
+            TablePageCodec::decode(page.read().unwrap().data(), self.schema.clone())?;
+        Ok((page, table_page))
+    }
+
+    pub fn destroy(&self) -> BustubxResult<()> {
+        let mut page_id = self.first_page_id.load(Ordering::SeqCst);
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            if !self.buffer_pool.delete_page(page_id)? {
+                return Err(BustubxError::Storage(format!(
+                    "table page {} is still pinned",
+                    page_id
+                )));
+            }
+            page_id = table_page.header.next_page_id;
+        }
+        self.first_page_id.store(INVALID_PAGE_ID, Ordering::SeqCst);
+        self.last_page_id.store(INVALID_PAGE_ID, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Tuples of the pages [`TableHeap::vacuum`] would reclaim now, all of them deleted.
+    /// Their index entries must go before the record ids are reused by new pages.
+    pub fn reclaimable_tuples(&self) -> BustubxResult<Vec<(RecordId, TupleMeta, Tuple)>> {
+        let mut tuples = vec![];
+        let (_, first_table_page) =
+            self.fetch_table_page(self.first_page_id.load(Ordering::SeqCst))?;
+        let mut page_id = first_table_page.header.next_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            if table_page.header.live_tuples == 0 {
+                for slot_num in 0..table_page.header.num_tuples {
+                    let (meta, tuple) = table_page.tuple(slot_num)?;
+                    tuples.push((RecordId::new(page_id, slot_num as u32), meta, tuple));
+                }
+            }
+            page_id = table_page.header.next_page_id;
+        }
+        Ok(tuples)
+    }
+
+    /// Unlink every page but the first whose tuples are all deleted from the page chain and
+    /// return it to the disk manager. Returns (pages reclaimed, dead tuples removed).
+    pub fn vacuum(&self) -> BustubxResult<(usize, usize)> {
+        let _op = operation_scope("heap_vacuum");
+        let (mut pages_reclaimed, mut dead_tuples) = (0, 0);
+        let mut prev_page_id = self.first_page_id.load(Ordering::SeqCst);
+        let (_, first_table_page) = self.fetch_table_page(prev_page_id)?;
+        let mut page_id = first_table_page.header.next_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            let next_page_id = table_page.header.next_page_id;
+            if table_page.header.live_tuples > 0 {
+                prev_page_id = page_id;
+                page_id = next_page_id;
+                continue;
+            }
+
+            let (prev_page, mut prev_table_page) = self.fetch_table_page(prev_page_id)?;
+            prev_table_page.header.next_page_id = next_page_id;
+            prev_page
+                .write()
+                .unwrap()
+                .encode_page::<TablePageCodec>(&prev_table_page);
+            drop(prev_page);
+            if self.last_page_id.load(Ordering::SeqCst) == page_id {
+                self.last_page_id.store(prev_page_id, Ordering::SeqCst);
+            }
+            if !self.buffer_pool.delete_page(page_id)? {
+                return Err(BustubxError::Storage(format!(
+                    "table page {} is still pinned",
+                    page_id
+                )));
+            }
+            pages_reclaimed += 1;
+            dead_tuples += table_page.header.num_tuples as usize;
+            page_id = next_page_id;
+        }
+        Ok((pages_reclaimed, dead_tuples))
+    }
+
+    /// Rewrite the live tuples packed into as few pages as possible, dropping every deleted
+    /// tuple. The first page is kept, record ids change so the indexes must be rebuilt.
+    /// Returns (pages reclaimed, dead tuples removed).
+    pub fn vacuum_full(&self) -> BustubxResult<(usize, usize)> {
+        let _op = operation_scope("heap_vacuum_full");
         let first_page_id = self.first_page_id.load(Ordering::SeqCst);
-        let (_, table_page) = self
-            .buffer_pool
-            .fetch_table_page(first_page_id, self.schema.clone())?;
-        if table_page.header.num_tuples == 0 {
-            // TODO: ignore deleted tuples
-            Ok(None)
-        } else {
-            Ok(Some(RecordId::new(first_page_id, 0)))
+        let mut page_ids = vec![];
+        let mut live_tuples = vec![];
+        let mut dead_tuples = 0;
+        let mut page_id = first_page_id;
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            for slot_num in 0..table_page.header.num_tuples {
+                let (meta, tuple) = table_page.tuple(slot_num)?;
+                if meta.is_deleted {
+                    dead_tuples += 1;
+                } else {
+                    live_tuples.push((meta, tuple));
+                }
+            }
+            page_ids.push(page_id);
+            page_id = table_page.header.next_page_id;
         }
+
+        let first_page = self.fetch_page(first_page_id)?;
+        first_page
+            .write()
+            .unwrap()
+            .encode_page::<TablePageCodec>(&TablePage::new(self.schema.clone(), INVALID_PAGE_ID));
+        drop(first_page);
+        self.last_page_id.store(first_page_id, Ordering::SeqCst);
+        for page_id in page_ids.iter().skip(1) {
+            // only pages in the buffer pool are returned to the disk manager
+            let _ = self.fetch_table_page(*page_id)?;
+            if !self.buffer_pool.delete_page(*page_id)? {
+                return Err(BustubxError::Storage(format!(
+                    "table page {} is still pinned",
+                    page_id
+                )));
+            }
+        }
+        *self.live_tuples.lock().unwrap() = Some(0);
+        let items = live_tuples
+            .iter()
+            .map(|(meta, tuple)| (*meta, tuple))
+            .collect::<Vec<_>>();
+        self.insert_tuple_batch(&items)?;
+        let (pages, _, _) = self.count_pages()?;
+        Ok((page_ids.len() - pages, dead_tuples))
+    }
+
+    /// Replace the heap by a fresh page chain holding `tuples` in the given order, the
+    /// pages of the old chain are freed afterwards. Record ids change so the indexes must
+    /// be rebuilt. Returns the number of pages freed.
+    pub fn rewrite(&self, tuples: &[(TupleMeta, &Tuple)]) -> BustubxResult<usize> {
+        let _op = operation_scope("heap_rewrite");
+        let old_page_ids = self.page_ids()?;
+
+        let first_page = self.buffer_pool.new_page_blocking(self.frame_wait)?;
+        let first_page_id = first_page.read().unwrap().page_id;
+        first_page
+            .write()
+            .unwrap()
+            .encode_page::<TablePageCodec>(&TablePage::new(self.schema.clone(), INVALID_PAGE_ID));
+        drop(first_page);
+        self.first_page_id.store(first_page_id, Ordering::SeqCst);
+        self.last_page_id.store(first_page_id, Ordering::SeqCst);
+        *self.live_tuples.lock().unwrap() = Some(0);
+        self.insert_tuple_batch(tuples)?;
+
+        for page_id in old_page_ids.iter() {
+            // only pages in the buffer pool are returned to the disk manager
+            let _ = self.fetch_table_page(*page_id)?;
+            if !self.buffer_pool.delete_page(*page_id)? {
+                return Err(BustubxError::Storage(format!(
+                    "table page {} is still pinned",
+                    page_id
+                )));
+            }
+        }
+        Ok(old_page_ids.len())
+    }
+
+    pub fn get_first_rid(&self) -> BustubxResult<Option<RecordId>> {
+        self.first_rid_from(self.first_page_id.load(Ordering::SeqCst))
     }
 
     pub fn get_next_rid(&self, rid: RecordId) -> BustubxResult<Option<RecordId>> {
-        let (_, table_page) = self
-            .buffer_pool
-            .fetch_table_page(rid.page_id, self.schema.clone())?;
+        let (_, table_page) = self.fetch_table_page(rid.page_id)?;
         let next_rid = table_page.get_next_rid(&rid);
         if next_rid.is_some() {
             return Ok(next_rid);
         }
+        self.first_rid_from(table_page.header.next_page_id)
+    }
 
-        if table_page.header.next_page_id == INVALID_PAGE_ID {
-            return Ok(None);
-        }
-        let (_, next_table_page) = self
-            .buffer_pool
-            .fetch_table_page(table_page.header.next_page_id, self.schema.clone())?;
-        if next_table_page.header.num_tuples == 0 {
-            // TODO: ignore deleted tuples
-            Ok(None)
-        } else {
-            Ok(Some(RecordId::new(table_page.header.next_page_id, 0)))
+    // First slot of the first page from `page_id` on holding live tuples, pages whose
+    // tuples are all deleted are skipped.
+    fn first_rid_from(&self, mut page_id: PageId) -> BustubxResult<Option<RecordId>> {
+        while page_id != INVALID_PAGE_ID {
+            let (_, table_page) = self.fetch_table_page(page_id)?;
+            if table_page.header.live_tuples > 0 {
+                // TODO: ignore deleted tuples
+                return Ok(Some(RecordId::new(page_id, 0)));
+            }
+            page_id = table_page.header.next_page_id;
         }
+        Ok(None)
     }
 }
 
@@ -306,6 +793,7 @@ impl TableIterator {
 #[cfg(test)]
 mod tests {
 
+    use std::sync::atomic::Ordering;
     use std::sync::Arc;
     use tempfile::TempDir;
 
@@ -314,6 +802,7 @@ mod tests {
     use crate::{
         buffer::BufferPoolManager,
         storage::{table_heap::TableHeap, DiskManager, Tuple},
+        BustubxError,
     };
 
     #[test]
@@ -377,6 +866,7 @@ mod tests {
             insert_txn_id: 1,
             delete_txn_id: 1,
             is_deleted: false,
+            row_id: None,
         };
         let rid1 = table_heap
             .insert_tuple(
@@ -388,6 +878,7 @@ mod tests {
             insert_txn_id: 2,
             delete_txn_id: 2,
             is_deleted: false,
+            row_id: None,
         };
         let rid2 = table_heap
             .insert_tuple(
@@ -399,6 +890,7 @@ mod tests {
             insert_txn_id: 3,
             delete_txn_id: 3,
             is_deleted: false,
+            row_id: None,
         };
         let rid3 = table_heap
             .insert_tuple(
@@ -420,6 +912,56 @@ mod tests {
         assert_eq!(tuple.data, vec![3i8.into(), 3i16.into()]);
     }
 
+    #[test]
+    pub fn test_table_heap_insert_tuple_batch() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int32, false),
+            Column::new("b", DataType::Varchar(None), false),
+        ]));
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let batch_heap = Arc::new(TableHeap::try_new(schema.clone(), buffer_pool.clone()).unwrap());
+        let single_heap = Arc::new(TableHeap::try_new(schema.clone(), buffer_pool).unwrap());
+
+        let tuples = (0..500i32)
+            .map(|i| Tuple::new(schema.clone(), vec![i.into(), format!("{i:0>50}").into()]))
+            .collect::<Vec<_>>();
+        let mut items = tuples
+            .iter()
+            .map(|tuple| (EMPTY_TUPLE_META, tuple))
+            .collect::<Vec<_>>();
+        items[7].0.is_deleted = true;
+        assert!(batch_heap.insert_tuple_batch(&[]).unwrap().is_empty());
+        // the second batch starts on the last page the first one filled
+        let mut rids = batch_heap.insert_tuple_batch(&items[..100]).unwrap();
+        rids.extend(batch_heap.insert_tuple_batch(&items[100..]).unwrap());
+        for (meta, tuple) in items.iter() {
+            single_heap.insert_tuple(meta, tuple).unwrap();
+        }
+
+        let (pages, live_tuples, dead_tuples) = batch_heap.count_pages().unwrap();
+        assert!(pages > 5, "{pages} pages");
+        assert_eq!((live_tuples, dead_tuples), (499, 1));
+        assert_eq!(
+            single_heap.count_pages().unwrap(),
+            (pages, live_tuples, dead_tuples)
+        );
+        for (rid, tuple) in rids.iter().zip(tuples.iter()) {
+            assert_eq!(batch_heap.tuple(*rid).unwrap().data, tuple.data);
+        }
+        // the same slots as inserting the tuples one by one
+        let slots = |heap: &Arc<TableHeap>| {
+            let mut iterator = TableIterator::new(heap.clone(), ..);
+            std::iter::from_fn(|| iterator.next().unwrap())
+                .map(|(rid, _)| rid.slot_num)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(slots(&batch_heap), slots(&single_heap));
+    }
+
     #[test]
     pub fn test_table_heap_iterator() {
         let temp_dir = TempDir::new().unwrap();
@@ -438,6 +980,7 @@ mod tests {
             insert_txn_id: 1,
             delete_txn_id: 1,
             is_deleted: false,
+            row_id: None,
         };
         let rid1 = table_heap
             .insert_tuple(
@@ -449,6 +992,7 @@ mod tests {
             insert_txn_id: 2,
             delete_txn_id: 2,
             is_deleted: false,
+            row_id: None,
         };
         let rid2 = table_heap
             .insert_tuple(
@@ -460,6 +1004,7 @@ mod tests {
             insert_txn_id: 3,
             delete_txn_id: 3,
             is_deleted: false,
+            row_id: None,
         };
         let rid3 = table_heap
             .insert_tuple(
@@ -484,4 +1029,121 @@ mod tests {
 
         assert!(iterator.next().unwrap().is_none());
     }
+
+    #[test]
+    pub fn test_table_heap_skips_all_deleted_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let schema = Arc::new(Schema::new(vec![Column::new("a", DataType::Int32, false)]));
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let table_heap = Arc::new(TableHeap::try_new(schema.clone(), buffer_pool).unwrap());
+
+        let rids = (0..600)
+            .map(|i| {
+                table_heap
+                    .insert_tuple(
+                        &EMPTY_TUPLE_META,
+                        &Tuple::new(schema.clone(), vec![i.into()]),
+                    )
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut page_ids = rids.iter().map(|rid| rid.page_id).collect::<Vec<_>>();
+        page_ids.dedup();
+        assert!(page_ids.len() >= 3, "{page_ids:?}");
+
+        // every tuple of the first and a middle page deleted
+        let deleted_pages = [page_ids[0], page_ids[1]];
+        let mut deleted = 0;
+        for rid in rids
+            .iter()
+            .filter(|rid| deleted_pages.contains(&rid.page_id))
+        {
+            let mut meta = table_heap.tuple_meta(*rid).unwrap();
+            meta.is_deleted = true;
+            table_heap.update_tuple_meta(meta, *rid).unwrap();
+            deleted += 1;
+        }
+
+        assert_eq!(
+            table_heap.get_first_rid().unwrap().map(|rid| rid.page_id),
+            Some(page_ids[2])
+        );
+        let mut iterator = TableIterator::new(table_heap.clone(), ..);
+        let mut visited = 0;
+        while let Some((rid, _)) = iterator.next().unwrap() {
+            assert!(!deleted_pages.contains(&rid.page_id));
+            assert!(!table_heap.tuple_meta(rid).unwrap().is_deleted);
+            visited += 1;
+        }
+        assert_eq!(visited, 600 - deleted);
+
+        assert_eq!(
+            table_heap.count_pages().unwrap(),
+            (page_ids.len(), 600 - deleted, deleted)
+        );
+        assert_eq!(table_heap.live_tuple_count(), Some(600 - deleted));
+
+        // a restored tuple makes its page visible again
+        let mut meta = table_heap.tuple_meta(rids[0]).unwrap();
+        meta.is_deleted = false;
+        table_heap.update_tuple_meta(meta, rids[0]).unwrap();
+        assert_eq!(table_heap.get_first_rid().unwrap(), Some(rids[0]));
+    }
+
+    #[test]
+    pub fn test_table_heap_insert_disk_full() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path().join("test.db");
+
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("a", DataType::Int8, false),
+            Column::new("b", DataType::Int16, false),
+        ]));
+
+        let disk_manager = DiskManager::try_new(temp_path).unwrap();
+        let buffer_pool = Arc::new(BufferPoolManager::new(1000, Arc::new(disk_manager)));
+        let table_heap = Arc::new(TableHeap::try_new(schema.clone(), buffer_pool.clone()).unwrap());
+        let first_page_id = table_heap.first_page_id.load(Ordering::SeqCst);
+
+        // fill the first page, chaining the next one needs a new allocation
+        buffer_pool.disk_manager.fail_allocations_after(0);
+        let tuple =
+            |i: usize| Tuple::new(schema.clone(), vec![(i as i8).into(), (i as i16).into()]);
+        let mut inserted = 0;
+        let err = loop {
+            match table_heap.insert_tuple(&EMPTY_TUPLE_META, &tuple(inserted)) {
+                Ok(_) => inserted += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err, BustubxError::DiskFull(_)));
+        assert!(inserted > 0);
+        assert_eq!(
+            table_heap.last_page_id.load(Ordering::SeqCst),
+            first_page_id
+        );
+
+        let count_tuples = || {
+            let mut iterator = TableIterator::new(table_heap.clone(), ..);
+            let mut count = 0;
+            while let Some((_, t)) = iterator.next().unwrap() {
+                assert_eq!(t, tuple(count));
+                count += 1;
+            }
+            count
+        };
+        assert_eq!(count_tuples(), inserted);
+
+        // retry once space is available again
+        buffer_pool.disk_manager.free_space();
+        let rid = table_heap
+            .insert_tuple(&EMPTY_TUPLE_META, &tuple(inserted))
+            .unwrap();
+        assert_ne!(rid.page_id, first_page_id);
+        assert_eq!(table_heap.last_page_id.load(Ordering::SeqCst), rid.page_id);
+        assert_eq!(count_tuples(), inserted + 1);
+    }
 }
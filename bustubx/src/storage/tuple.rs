@@ -1,5 +1,5 @@
 use crate::catalog::{SchemaRef, EMPTY_SCHEMA_REF};
-use crate::common::TableReference;
+use crate::common::{value_ord, TableReference};
 use crate::{catalog::Schema, common::ScalarValue, BustubxError, BustubxResult};
 use std::cmp::Ordering;
 use std::sync::{Arc, LazyLock};
@@ -62,6 +62,18 @@ impl Tuple {
         self.data.iter().all(|x| x.is_null())
     }
 
+    /// Order of the leading values of this tuple and `prefix`, whose schema is the first
+    /// columns of this one or all of them. Equal when this tuple starts with `prefix`, the
+    /// values compare in the collations of this tuple.
+    pub fn compare_prefix(&self, prefix: &Tuple) -> Option<Ordering> {
+        let column_count = prefix.schema.column_count();
+        value_ord::compare_rows(
+            self.data.get(..column_count)?,
+            prefix.data.get(..column_count)?,
+            self.schema.columns.get(..column_count)?,
+        )
+    }
+
     pub fn value(&self, index: usize) -> BustubxResult<&ScalarValue> {
         self.data.get(index).ok_or(BustubxError::Internal(format!(
             "Not found column data at {} in tuple: {:?}",
@@ -81,15 +93,11 @@ impl Tuple {
 impl PartialOrd for Tuple {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         let column_count = self.schema.column_count();
-        for idx in 0..column_count {
-            let order = self.value(idx).ok()?.partial_cmp(other.value(idx).ok()?)?;
-            if order == Ordering::Equal {
-                continue;
-            } else {
-                return Some(order);
-            }
-        }
-        Some(Ordering::Equal)
+        value_ord::compare_rows(
+            self.data.get(..column_count)?,
+            other.data.get(..column_count)?,
+            &self.schema.columns,
+        )
     }
 }
 
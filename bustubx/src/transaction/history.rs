@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::common::TableReference;
+use crate::execution::UndoRecord;
+use crate::storage::{RecordId, TupleMeta};
+use crate::transaction::{lock_key, TransactionId, TransactionManager};
+use crate::{BustubxError, BustubxResult, Tuple};
+
+/// Position of a committed transaction in the order transactions committed in, counted
+/// from 1 since the database was opened.
+pub type CommitSeq = u64;
+
+/// Versions of the rows committed transactions inserted, updated or deleted, kept in
+/// memory until VACUUM reclaims the deleted tuples of the table.
+#[derive(Debug, Default)]
+pub(crate) struct VersionHistory {
+    latest: CommitSeq,
+    // committed transactions that changed rows, transactions run one at a time so the
+    // commit order is the order of their ids
+    commits: BTreeMap<TransactionId, CommitSeq>,
+    tables: HashMap<TableReference, TableHistory>,
+}
+
+#[derive(Debug, Default)]
+struct TableHistory {
+    // snapshots before this commit need versions that were reclaimed
+    since: CommitSeq,
+    rows: HashMap<RecordId, RowHistory>,
+}
+
+#[derive(Debug, Default)]
+struct RowHistory {
+    inserted: Option<CommitSeq>,
+    deleted: Option<CommitSeq>,
+    // tuples the row had before the commits that updated it, oldest first
+    versions: Vec<(Tuple, CommitSeq)>,
+}
+
+impl VersionHistory {
+    pub fn commit(&mut self, txn_id: TransactionId, changes: &[UndoRecord]) {
+        if changes.is_empty() {
+            return;
+        }
+        self.latest += 1;
+        let seq = self.latest;
+        self.commits.insert(txn_id, seq);
+        // a row changed several times by the transaction keeps the version before the first
+        let mut changed = HashSet::new();
+        for change in changes {
+            let table = self.tables.entry(lock_key(change.table())).or_default();
+            match change {
+                UndoRecord::Insert { rid, .. } => {
+                    table.rows.entry(*rid).or_default().inserted = Some(seq);
+                    changed.insert((change.table(), *rid));
+                }
+                UndoRecord::Update { rid, old_tuple, .. } => {
+                    if changed.insert((change.table(), *rid)) {
+                        table
+                            .rows
+                            .entry(*rid)
+                            .or_default()
+                            .versions
+                            .push((old_tuple.clone(), seq));
+                    }
+                }
+                UndoRecord::Delete { rid, .. } => {
+                    table.rows.entry(*rid).or_default().deleted = Some(seq);
+                }
+                // the versions of a row are kept in the heap
+                UndoRecord::IndexInsert { .. }
+                | UndoRecord::IndexDelete { .. }
+                | UndoRecord::IndexesRebuilt { .. } => {}
+            }
+        }
+    }
+
+    // The state after the latest commit of a transaction up to `txn_id`
+    pub fn commit_seq(&self, txn_id: TransactionId) -> CommitSeq {
+        self.commits
+            .range(..=txn_id)
+            .next_back()
+            .map_or(0, |(_, seq)| *seq)
+    }
+
+    pub fn last_commit(&self) -> Option<TransactionId> {
+        self.commits.keys().next_back().copied()
+    }
+
+    pub fn forget(&mut self, table: &TableReference) {
+        self.tables.insert(
+            lock_key(table),
+            TableHistory {
+                since: self.latest,
+                rows: HashMap::new(),
+            },
+        );
+    }
+}
+
+/// Rows of the tables as they were right after a transaction committed, read by queries
+/// `AS OF TRANSACTION` it or run with `snapshot_txn` set to it.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub txn_id: TransactionId,
+    pub commit_seq: CommitSeq,
+    txn_manager: Arc<TransactionManager>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(
+        txn_id: TransactionId,
+        commit_seq: CommitSeq,
+        txn_manager: Arc<TransactionManager>,
+    ) -> Self {
+        Self {
+            txn_id,
+            commit_seq,
+            txn_manager,
+        }
+    }
+
+    /// Fails with [`BustubxError::HistoryUnavailable`] once VACUUM reclaimed versions of
+    /// the table the snapshot needs.
+    pub fn check_table(&self, table: &TableReference) -> BustubxResult<()> {
+        let history = self.txn_manager.history();
+        match history.tables.get(&lock_key(table)) {
+            Some(table_history) if table_history.since > self.commit_seq => {
+                Err(BustubxError::HistoryUnavailable {
+                    table: table.clone(),
+                    txn_id: self.txn_id,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Version of the row at `rid` the snapshot sees, given the tuple stored there now.
+    /// None if the row did not exist yet or was deleted already.
+    pub fn version(
+        &self,
+        table: &TableReference,
+        rid: RecordId,
+        meta: &TupleMeta,
+        tuple: Tuple,
+    ) -> Option<Tuple> {
+        let history = self.txn_manager.history();
+        let row = history
+            .tables
+            .get(&lock_key(table))
+            .and_then(|table_history| table_history.rows.get(&rid));
+        let Some(row) = row else {
+            // unchanged since the history began, a deleted tuple without history never
+            // committed or was deleted before
+            return (!meta.is_deleted).then_some(tuple);
+        };
+        if row.inserted.is_some_and(|seq| seq > self.commit_seq) {
+            return None;
+        }
+        match row.deleted {
+            Some(seq) if seq <= self.commit_seq => return None,
+            None if meta.is_deleted => return None,
+            _ => {}
+        }
+        let version = row
+            .versions
+            .iter()
+            .find(|(_, replaced)| *replaced > self.commit_seq)
+            .map(|(version, _)| version.clone());
+        Some(version.unwrap_or(tuple))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::test_util::rows;
+    use crate::common::ScalarValue;
+    use crate::{BustubxError, Database, SharedDatabase};
+
+    fn row(id: i32, v: &str) -> Vec<ScalarValue> {
+        vec![id.into(), v.to_string().into()]
+    }
+
+    #[test]
+    pub fn test_query_as_of_transaction() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t (id int, v varchar(10))").unwrap();
+        db.run("create index t_id on t (id)").unwrap();
+        db.run("insert into t values (1, 'a'), (2, 'b'), (3, 'c')")
+            .unwrap();
+        let original = db.last_commit().unwrap();
+
+        db.run("update t set v = 'x' where id = 1").unwrap();
+        db.run("update t set v = 'y' where id = 1").unwrap();
+        let updated = db.last_commit().unwrap();
+        db.run("delete from t where id = 2").unwrap();
+        db.run("insert into t values (4, 'd')").unwrap();
+
+        let sql = |txn_id| format!("select id, v from t as of transaction {txn_id} order by id");
+        assert_eq!(
+            rows(&mut db, &sql(original)),
+            vec![row(1, "a"), row(2, "b"), row(3, "c")]
+        );
+        assert_eq!(
+            rows(&mut db, &sql(updated)),
+            vec![row(1, "y"), row(2, "b"), row(3, "c")]
+        );
+        // indexes only know the latest rows, the snapshot is scanned
+        assert_eq!(
+            rows(
+                &mut db,
+                &format!("select v from t as of transaction {original} where id = 1")
+            ),
+            vec![vec!["a".to_string().into()]]
+        );
+        assert_eq!(
+            rows(
+                &mut db,
+                &format!("select count(*) from t as of transaction {original}")
+            ),
+            vec![vec![3i64.into()]]
+        );
+        assert_eq!(
+            rows(&mut db, "select id, v from t order by id"),
+            vec![row(1, "y"), row(3, "c"), row(4, "d")]
+        );
+
+        db.run(&format!("set snapshot_txn = {original}")).unwrap();
+        assert_eq!(rows(&mut db, "select id from t order by id").len(), 3);
+        assert!(matches!(
+            db.run("insert into t values (5, 'e')"),
+            Err(BustubxError::Plan(_))
+        ));
+        db.run("set snapshot_txn = 0").unwrap();
+        assert!(db.run("select * from t as of transaction 1000").is_err());
+
+        // VACUUM reclaims the deleted row, older snapshots of the table are gone
+        db.run("vacuum t").unwrap();
+        assert!(matches!(
+            db.run(&sql(original)),
+            Err(BustubxError::HistoryUnavailable { txn_id, .. }) if txn_id == original
+        ));
+        let vacuumed = db.last_commit().unwrap();
+        assert_eq!(rows(&mut db, &sql(vacuumed)).len(), 3);
+    }
+
+    #[test]
+    pub fn test_rolled_back_transaction_leaves_no_versions() {
+        let db = SharedDatabase::new(Database::new_temp().unwrap());
+        let mut session = db.session();
+        session.run("create table t (id int, v int)").unwrap();
+        session.run("insert into t values (1, 10)").unwrap();
+        let committed = session
+            .run_transaction(|txn| {
+                txn.run("update t set v = 20 where id = 1")?;
+                txn.run("insert into t values (2, 20)")?;
+                Ok(txn.txn_id())
+            })
+            .unwrap();
+        let failed = session.run_transaction(|txn| {
+            txn.run("delete from t")?;
+            txn.run("select * from missing")
+        });
+        assert!(failed.is_err());
+        session.run("update t set v = 30").unwrap();
+
+        let values = |session: &mut crate::Session, sql: &str| {
+            session
+                .run(sql)
+                .unwrap()
+                .into_iter()
+                .map(|tuple| tuple.data[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let sql = |txn_id| format!("select v from t as of transaction {txn_id} order by id");
+        assert_eq!(
+            values(&mut session, &sql(committed)),
+            vec![20.into(), 20.into()]
+        );
+        assert_eq!(values(&mut session, &sql(committed - 1)), vec![10.into()]);
+        assert_eq!(
+            values(&mut session, "select v from t order by id"),
+            vec![30.into(), 30.into()]
+        );
+    }
+}
@@ -1,65 +1,598 @@
+use crate::catalog::DEFAULT_SCHEMA_NAME;
 use crate::common::TableReference;
 use crate::storage::RecordId;
-use crate::transaction::{Transaction, TransactionId};
-use std::collections::HashMap;
+use crate::transaction::TransactionId;
+use crate::{BustubxError, BustubxResult};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-pub enum LockMode {
-    Shared,
-    Exclusive,
-    IntentionShared,
-    IntentionExclusive,
-    SharedIntentionExclusive,
+/// Table lock a statement holds until it finished, ordered from weakest to strongest.
+/// Scans take ACCESS SHARE, DML ROW EXCLUSIVE, REINDEX SHARE to keep writers out while
+/// readers go on, and DDL rewriting or removing the table takes ACCESS EXCLUSIVE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TableLockMode {
+    AccessShare,
+    RowExclusive,
+    Share,
+    AccessExclusive,
+}
+
+impl TableLockMode {
+    pub fn conflicts_with(&self, other: TableLockMode) -> bool {
+        use TableLockMode::*;
+        matches!(
+            (*self, other),
+            (AccessExclusive, _)
+                | (_, AccessExclusive)
+                | (RowExclusive, Share)
+                | (Share, RowExclusive)
+        )
+    }
 }
 
-pub struct LockRequest {
+impl std::fmt::Display for TableLockMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableLockMode::AccessShare => write!(f, "ACCESS SHARE"),
+            TableLockMode::RowExclusive => write!(f, "ROW EXCLUSIVE"),
+            TableLockMode::Share => write!(f, "SHARE"),
+            TableLockMode::AccessExclusive => write!(f, "ACCESS EXCLUSIVE"),
+        }
+    }
+}
+
+/// Table lock held or waited for, see [`LockManager::table_locks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableLockInfo {
+    pub table: TableReference,
+    pub txn_id: TransactionId,
+    pub mode: TableLockMode,
+    // false while the transaction waits for it
+    pub granted: bool,
+}
+
+#[derive(Debug)]
+struct TableLockRequest {
     txn_id: TransactionId,
-    lock_mod: LockMode,
-    table_ref: TableReference,
-    rid: Option<RecordId>,
+    mode: TableLockMode,
     granted: bool,
 }
 
+#[derive(Debug, Default)]
 pub struct LockManager {
-    table_lock_map: HashMap<TableReference, Vec<LockRequest>>,
-    row_lock_map: HashMap<RecordId, Vec<LockRequest>>,
+    // granted and waiting requests per table in arrival order
+    table_lock_map: Mutex<HashMap<TableReference, Vec<TableLockRequest>>>,
+    // signalled whenever requests leave a queue
+    table_lock_released: Condvar,
+    // table lock requests that had to wait behind another transaction
+    lock_waits: AtomicU64,
+    // transaction holding the write lock of a row, until it ends
+    row_lock_map: Mutex<HashMap<(TableReference, RecordId), TransactionId>>,
+    // signalled whenever a transaction released its row locks
+    row_lock_released: Condvar,
 }
 
 impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests are granted in arrival order, a request waits for every conflicting
+    /// request of another transaction ahead of it. Gives up with
+    /// [`BustubxError::RelationLocked`] once `timeout` passed, `Duration::MAX` waits
+    /// as long as it takes.
     pub fn lock_table(
         &self,
-        _txn: Transaction,
-        _mode: LockMode,
-        _table_ref: TableReference,
-    ) -> bool {
-        todo!()
+        txn_id: TransactionId,
+        mode: TableLockMode,
+        table_ref: &TableReference,
+        timeout: Duration,
+    ) -> BustubxResult<()> {
+        let key = lock_key(table_ref);
+        let deadline = Instant::now().checked_add(timeout);
+        let mut lock_map = self.table_lock_map.lock().unwrap();
+        let queue = lock_map.entry(key.clone()).or_default();
+        if queue
+            .iter()
+            .any(|request| request.txn_id == txn_id && request.granted && request.mode >= mode)
+        {
+            return Ok(());
+        }
+        queue.push(TableLockRequest {
+            txn_id,
+            mode,
+            granted: false,
+        });
+
+        let mut waited = false;
+        loop {
+            let queue = lock_map.get_mut(&key).unwrap();
+            let pos = queue
+                .iter()
+                .position(|request| request.txn_id == txn_id && !request.granted)
+                .unwrap();
+            let blocked = queue.iter().enumerate().any(|(i, request)| {
+                request.txn_id != txn_id
+                    && (i < pos || request.granted)
+                    && request.mode.conflicts_with(mode)
+            });
+            if !blocked {
+                queue[pos].granted = true;
+                return Ok(());
+            }
+            if !waited {
+                waited = true;
+                self.lock_waits.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let Some(deadline) = deadline else {
+                lock_map = self.table_lock_released.wait(lock_map).unwrap();
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                queue.remove(pos);
+                if queue.is_empty() {
+                    lock_map.remove(&key);
+                }
+                // requests queued behind this one may be grantable now
+                self.table_lock_released.notify_all();
+                return Err(BustubxError::RelationLocked {
+                    table: key,
+                    mode,
+                    timeout,
+                });
+            }
+            lock_map = self
+                .table_lock_released
+                .wait_timeout(lock_map, deadline - now)
+                .unwrap()
+                .0;
+        }
     }
 
-    pub fn unlock_table(&self, _txn: Transaction, _table_ref: TableReference) -> bool {
-        todo!()
+    /// Release every lock of the transaction on the table, false if it held none.
+    pub fn unlock_table(&self, txn_id: TransactionId, table_ref: &TableReference) -> bool {
+        let key = lock_key(table_ref);
+        let mut lock_map = self.table_lock_map.lock().unwrap();
+        let Some(queue) = lock_map.get_mut(&key) else {
+            return false;
+        };
+        let len = queue.len();
+        queue.retain(|request| request.txn_id != txn_id);
+        let released = queue.len() != len;
+        if queue.is_empty() {
+            lock_map.remove(&key);
+        }
+        if released {
+            self.table_lock_released.notify_all();
+        }
+        released
     }
 
+    /// Take every lock of a statement, in table order so two statements locking the
+    /// same tables never wait on each other crosswise.
+    pub fn lock_tables(
+        self: &Arc<Self>,
+        txn_id: TransactionId,
+        locks: Vec<(TableReference, TableLockMode)>,
+        timeout: Duration,
+    ) -> BustubxResult<TableLocks> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut keyed = locks
+            .into_iter()
+            .map(|(table_ref, mode)| (lock_key(&table_ref), mode))
+            .collect::<Vec<_>>();
+        // the strongest mode of a table first, the weaker requests are then no-ops
+        keyed.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        keyed.dedup_by(|a, b| a.0 == b.0);
+
+        let table_locks = TableLocks {
+            lock_manager: self.clone(),
+            txn_id,
+        };
+        for (table_ref, mode) in keyed.iter() {
+            let remaining = deadline.map_or(Duration::MAX, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            // released again by dropping `table_locks` on failure
+            self.lock_table(txn_id, *mode, table_ref, remaining)
+                .map_err(|e| match e {
+                    BustubxError::RelationLocked { table, mode, .. } => {
+                        BustubxError::RelationLocked {
+                            table,
+                            mode,
+                            timeout,
+                        }
+                    }
+                    e => e,
+                })?;
+        }
+        Ok(table_locks)
+    }
+
+    /// Write lock of a row an UPDATE or DELETE is about to change, held by the transaction
+    /// until [`LockManager::unlock_rows`]. Waits for the transaction holding it to end, gives
+    /// up with [`BustubxError::RowLocked`] once `timeout` passed.
     pub fn lock_row(
         &self,
-        _txn: Transaction,
-        _mode: LockMode,
-        _table_ref: TableReference,
-        _rid: RecordId,
-    ) -> bool {
-        todo!()
+        txn_id: TransactionId,
+        table_ref: &TableReference,
+        rid: RecordId,
+        timeout: Duration,
+    ) -> BustubxResult<()> {
+        let key = (lock_key(table_ref), rid);
+        let deadline = Instant::now().checked_add(timeout);
+        let mut row_locks = self.row_lock_map.lock().unwrap();
+        loop {
+            match row_locks.get(&key) {
+                None => {
+                    row_locks.insert(key, txn_id);
+                    return Ok(());
+                }
+                Some(holder) if *holder == txn_id => return Ok(()),
+                Some(_) => {}
+            }
+            let Some(deadline) = deadline else {
+                row_locks = self.row_lock_released.wait(row_locks).unwrap();
+                continue;
+            };
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(BustubxError::RowLocked {
+                    table: key.0,
+                    rid,
+                    timeout,
+                });
+            }
+            row_locks = self
+                .row_lock_released
+                .wait_timeout(row_locks, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Release the row locks of a transaction that ended.
+    pub fn unlock_rows(&self, txn_id: TransactionId) {
+        let mut row_locks = self.row_lock_map.lock().unwrap();
+        let len = row_locks.len();
+        row_locks.retain(|_, holder| *holder != txn_id);
+        if row_locks.len() != len {
+            self.row_lock_released.notify_all();
+        }
     }
 
-    pub fn unlock_row(
+    /// Transaction holding the write lock of the row.
+    pub fn row_lock_holder(
         &self,
-        _txn: Transaction,
-        _table_ref: TableReference,
-        _rid: RecordId,
-        _force: bool,
-    ) -> bool {
-        todo!()
+        table_ref: &TableReference,
+        rid: RecordId,
+    ) -> Option<TransactionId> {
+        let row_locks = self.row_lock_map.lock().unwrap();
+        row_locks.get(&(lock_key(table_ref), rid)).copied()
+    }
+
+    /// Table lock requests that found a conflicting lock and had to wait, timed out
+    /// ones included.
+    pub fn lock_waits(&self) -> u64 {
+        self.lock_waits.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_stats(&self) {
+        self.lock_waits.store(0, Ordering::Relaxed);
+    }
+
+    /// Transactions holding or waiting for a table lock.
+    pub fn active_transactions(&self) -> usize {
+        let lock_map = self.table_lock_map.lock().unwrap();
+        lock_map
+            .values()
+            .flatten()
+            .map(|request| request.txn_id)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Snapshot of the table lock queues, sorted by table and in arrival order per table.
+    pub fn table_locks(&self) -> Vec<TableLockInfo> {
+        let lock_map = self.table_lock_map.lock().unwrap();
+        let mut locks = lock_map
+            .iter()
+            .flat_map(|(table, queue)| {
+                queue.iter().map(|request| TableLockInfo {
+                    table: table.clone(),
+                    txn_id: request.txn_id,
+                    mode: request.mode,
+                    granted: request.granted,
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(lock_map);
+        // stable, keeps the arrival order within a table
+        locks.sort_by(|a, b| a.table.cmp(&b.table));
+        locks
+    }
+
+    /// Release every table lock of the transaction.
+    pub fn unlock_all(&self, txn_id: TransactionId) {
+        let mut lock_map = self.table_lock_map.lock().unwrap();
+        for queue in lock_map.values_mut() {
+            queue.retain(|request| request.txn_id != txn_id);
+        }
+        lock_map.retain(|_, queue| !queue.is_empty());
+        self.table_lock_released.notify_all();
     }
+}
+
+/// Table locks of one statement, released when dropped.
+#[derive(Debug)]
+pub struct TableLocks {
+    lock_manager: Arc<LockManager>,
+    txn_id: TransactionId,
+}
+
+impl Drop for TableLocks {
+    fn drop(&mut self) {
+        self.lock_manager.unlock_all(self.txn_id);
+    }
+}
+
+// "t1", "public.t1" and "bustubx.public.t1" are the same table
+pub(crate) fn lock_key(table_ref: &TableReference) -> TableReference {
+    TableReference::partial(
+        table_ref.schema().unwrap_or(DEFAULT_SCHEMA_NAME),
+        table_ref.table(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::TableReference;
+    use crate::storage::RecordId;
+    use crate::transaction::{LockManager, TableLockMode};
+    use crate::{BustubxError, Database};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn is_locked(result: crate::BustubxResult<impl std::fmt::Debug>, mode: TableLockMode) -> bool {
+        matches!(result, Err(BustubxError::RelationLocked { mode: m, .. }) if m == mode)
+    }
+
+    #[test]
+    pub fn test_table_lock_conflicts() {
+        let lock_manager = LockManager::new();
+        let t1 = TableReference::bare("t1");
+        let no_wait = Duration::ZERO;
+
+        lock_manager
+            .lock_table(1, TableLockMode::AccessShare, &t1, no_wait)
+            .unwrap();
+        lock_manager
+            .lock_table(2, TableLockMode::AccessShare, &t1, no_wait)
+            .unwrap();
+        let start = Instant::now();
+        assert!(is_locked(
+            lock_manager.lock_table(
+                3,
+                TableLockMode::AccessExclusive,
+                &TableReference::full("bustubx", "public", "t1"),
+                Duration::from_millis(50)
+            ),
+            TableLockMode::AccessExclusive
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        // other tables are not affected
+        lock_manager
+            .lock_table(
+                3,
+                TableLockMode::AccessExclusive,
+                &TableReference::bare("t2"),
+                no_wait,
+            )
+            .unwrap();
+
+        assert!(lock_manager.unlock_table(1, &t1));
+        assert!(!lock_manager.unlock_table(1, &t1));
+        lock_manager.unlock_all(2);
+        lock_manager
+            .lock_table(3, TableLockMode::AccessExclusive, &t1, no_wait)
+            .unwrap();
+        assert!(is_locked(
+            lock_manager.lock_table(1, TableLockMode::AccessShare, &t1, no_wait),
+            TableLockMode::AccessShare
+        ));
+        // the holder locking again is granted at once
+        lock_manager
+            .lock_table(3, TableLockMode::AccessShare, &t1, no_wait)
+            .unwrap();
+        lock_manager.unlock_all(3);
+
+        // SHARE keeps writers out but lets readers and other SHARE holders in
+        lock_manager
+            .lock_table(1, TableLockMode::Share, &t1, no_wait)
+            .unwrap();
+        lock_manager
+            .lock_table(2, TableLockMode::AccessShare, &t1, no_wait)
+            .unwrap();
+        lock_manager
+            .lock_table(3, TableLockMode::Share, &t1, no_wait)
+            .unwrap();
+        assert!(is_locked(
+            lock_manager.lock_table(4, TableLockMode::RowExclusive, &t1, no_wait),
+            TableLockMode::RowExclusive
+        ));
+        lock_manager.unlock_all(1);
+        lock_manager.unlock_all(3);
+        lock_manager
+            .lock_table(4, TableLockMode::RowExclusive, &t1, no_wait)
+            .unwrap();
+        // writers don't conflict with each other
+        lock_manager
+            .lock_table(5, TableLockMode::RowExclusive, &t1, no_wait)
+            .unwrap();
+        assert!(is_locked(
+            lock_manager.lock_table(1, TableLockMode::Share, &t1, no_wait),
+            TableLockMode::Share
+        ));
+    }
+
+    #[test]
+    pub fn test_row_locks_held_until_unlocked() {
+        let lock_manager = Arc::new(LockManager::new());
+        let t1 = TableReference::bare("t1");
+        let rid = RecordId::new(3, 1);
+        lock_manager.lock_row(1, &t1, rid, Duration::ZERO).unwrap();
+        // the holder locking again is granted at once, other rows and tables are free
+        lock_manager
+            .lock_row(
+                1,
+                &TableReference::partial("public", "t1"),
+                rid,
+                Duration::ZERO,
+            )
+            .unwrap();
+        lock_manager
+            .lock_row(2, &t1, RecordId::new(3, 2), Duration::ZERO)
+            .unwrap();
+        lock_manager
+            .lock_row(2, &TableReference::bare("t2"), rid, Duration::ZERO)
+            .unwrap();
+        let result = lock_manager.lock_row(2, &t1, rid, Duration::from_millis(10));
+        assert!(matches!(result, Err(BustubxError::RowLocked { .. })));
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(lock_manager.row_lock_holder(&t1, rid), Some(1));
+
+        // a waiting writer gets the row once the holder ended
+        let (tx, rx) = mpsc::channel();
+        let handle = {
+            let lock_manager = lock_manager.clone();
+            let t1 = t1.clone();
+            thread::spawn(move || {
+                tx.send(()).unwrap();
+                lock_manager.lock_row(2, &t1, rid, Duration::from_secs(10))
+            })
+        };
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(20));
+        lock_manager.unlock_rows(1);
+        handle.join().unwrap().unwrap();
+        assert_eq!(lock_manager.row_lock_holder(&t1, rid), Some(2));
+        lock_manager.unlock_rows(2);
+        assert_eq!(lock_manager.row_lock_holder(&t1, rid), None);
+    }
+
+    #[test]
+    pub fn test_table_lock_waits_in_arrival_order() {
+        let lock_manager = Arc::new(LockManager::new());
+        let t1 = TableReference::bare("t1");
+        lock_manager
+            .lock_table(1, TableLockMode::AccessShare, &t1, Duration::ZERO)
+            .unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let handle = {
+            let lock_manager = lock_manager.clone();
+            let t1 = t1.clone();
+            thread::spawn(move || {
+                tx.send(()).unwrap();
+                let start = Instant::now();
+                lock_manager
+                    .lock_table(
+                        2,
+                        TableLockMode::AccessExclusive,
+                        &t1,
+                        Duration::from_secs(10),
+                    )
+                    .unwrap();
+                let waited = start.elapsed();
+                lock_manager.unlock_all(2);
+                waited
+            })
+        };
+        rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        // queued behind the waiting exclusive request although compatible with the holder
+        assert!(is_locked(
+            lock_manager.lock_table(3, TableLockMode::AccessShare, &t1, Duration::ZERO),
+            TableLockMode::AccessShare
+        ));
+        thread::sleep(Duration::from_millis(100));
+        lock_manager.unlock_all(1);
+        assert!(handle.join().unwrap() >= Duration::from_millis(200));
+        lock_manager
+            .lock_table(3, TableLockMode::AccessShare, &t1, Duration::from_secs(10))
+            .unwrap();
+    }
+
+    #[test]
+    pub fn test_statement_table_locks() {
+        let mut db = Database::new_temp().unwrap();
+        db.run("create table t1 (a int, b int)").unwrap();
+        db.run("create table t2 (a int)").unwrap();
+        db.run("insert into t1 values (1, 10), (2, 20)").unwrap();
+        db.set_lock_timeout(Duration::from_millis(100));
+        let lock_manager = db.lock_manager();
+        let t1 = TableReference::bare("t1");
+
+        // an exclusive holder, e.g. a DROP in progress, makes new scans time out
+        lock_manager
+            .lock_table(1000, TableLockMode::AccessExclusive, &t1, Duration::ZERO)
+            .unwrap();
+        let start = Instant::now();
+        let result = db.run("select a from t1");
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(is_locked(result, TableLockMode::AccessShare));
+        assert!(is_locked(
+            db.run("insert into t1 values (3, 30)"),
+            TableLockMode::RowExclusive
+        ));
+        assert_eq!(db.run("select a from t2").unwrap().len(), 0);
+        assert!(lock_manager.unlock_table(1000, &t1));
+        assert_eq!(db.run("select a from t1").unwrap().len(), 2);
+
+        // a statement failing on its second table releases the first
+        let t2 = TableReference::bare("t2");
+        lock_manager
+            .lock_table(1000, TableLockMode::AccessExclusive, &t2, Duration::ZERO)
+            .unwrap();
+        assert!(is_locked(
+            db.run("select t2.a from t1 inner join t2 on t1.a = t2.a"),
+            TableLockMode::AccessShare
+        ));
+        lock_manager
+            .lock_table(1002, TableLockMode::AccessExclusive, &t1, Duration::ZERO)
+            .unwrap();
+        lock_manager.unlock_all(1002);
+        lock_manager.unlock_all(1000);
+
+        // DDL waits for a scan running in another thread to finish
+        db.set_lock_timeout(Duration::from_secs(10));
+        let (tx, rx) = mpsc::channel();
+        let handle = {
+            let lock_manager = lock_manager.clone();
+            let t1 = t1.clone();
+            thread::spawn(move || {
+                lock_manager
+                    .lock_table(1001, TableLockMode::AccessShare, &t1, Duration::ZERO)
+                    .unwrap();
+                tx.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+                lock_manager.unlock_all(1001);
+            })
+        };
+        rx.recv().unwrap();
+        let start = Instant::now();
+        db.run("create index idx_a on t1 (a)").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        handle.join().unwrap();
 
-    pub fn unlock_all(&self) {
-        todo!()
+        // statements leave no locks behind
+        lock_manager
+            .lock_table(1002, TableLockMode::AccessExclusive, &t1, Duration::ZERO)
+            .unwrap();
     }
 }
@@ -1,5 +1,11 @@
+mod history;
 mod lock_manager;
+#[allow(clippy::module_inception)]
 mod transaction;
 mod transaction_manager;
 
+pub use history::Snapshot;
+pub(crate) use lock_manager::lock_key;
+pub use lock_manager::{LockManager, TableLockMode, TableLocks};
 pub use transaction::*;
+pub use transaction_manager::*;
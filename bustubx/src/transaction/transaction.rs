@@ -1,8 +1,11 @@
-use crate::Tuple;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 pub type TransactionId = u64;
 pub const INVALID_TRANSACTION_ID: TransactionId = 0;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransactionState {
     Running,
     Tainted,
@@ -10,24 +13,52 @@ pub enum TransactionState {
     Aborted,
 }
 
-pub struct Transaction {}
+impl std::fmt::Display for TransactionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionState::Running => write!(f, "running"),
+            TransactionState::Tainted => write!(f, "tainted"),
+            TransactionState::Committed => write!(f, "committed"),
+            TransactionState::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+pub struct Transaction {
+    pub txn_id: TransactionId,
+    // database clock time the transaction began at
+    pub start_time: i64,
+}
 
-/// Represents a link to a previous version of this tuple
-pub struct UndoLink {
-    prev_txn: TransactionId,
-    prev_log_idx: u32,
+/// Source of transaction ids, a test injects one to get predictable ids.
+pub trait TransactionIdSource: Send + Sync + Debug {
+    fn next_txn_id(&self) -> TransactionId;
 }
 
-impl UndoLink {
-    pub fn is_valid(&self) -> bool {
-        self.prev_txn != INVALID_TRANSACTION_ID
+pub type TransactionIdSourceRef = Arc<dyn TransactionIdSource>;
+
+/// Hands out increasing ids starting at `first`.
+#[derive(Debug)]
+pub struct SequentialTransactionIds {
+    next: AtomicU64,
+}
+
+impl SequentialTransactionIds {
+    pub fn new(first: TransactionId) -> Self {
+        Self {
+            next: AtomicU64::new(first),
+        }
     }
 }
 
-pub struct UndoLog {
-    is_deleted: bool,
-    modified_fields: Vec<bool>,
-    tuple: Tuple,
-    timestamp: u64,
-    prev_version: UndoLink,
+impl Default for SequentialTransactionIds {
+    fn default() -> Self {
+        Self::new(INVALID_TRANSACTION_ID + 1)
+    }
+}
+
+impl TransactionIdSource for SequentialTransactionIds {
+    fn next_txn_id(&self) -> TransactionId {
+        self.next.fetch_add(1, Ordering::SeqCst)
+    }
 }
@@ -1,4 +1,18 @@
-use crate::transaction::Transaction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::common::{ClockRef, TableReference};
+use crate::execution::UndoRecord;
+use crate::transaction::history::VersionHistory;
+use crate::transaction::{
+    lock_key, Snapshot, Transaction, TransactionId, TransactionIdSourceRef, TransactionState,
+    INVALID_TRANSACTION_ID,
+};
+use crate::{BustubxError, BustubxResult};
 
 pub enum IsolationLevel {
     ReadUncommitted,
@@ -6,11 +20,206 @@ pub enum IsolationLevel {
     Serializable,
 }
 
-pub struct TransactionManager {}
+/// Open transaction of [`crate::Session::run_transaction`], as listed by
+/// `SHOW TRANSACTIONS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionInfo {
+    pub txn_id: TransactionId,
+    pub state: TransactionState,
+    // since the transaction began and since its last statement finished
+    pub age: Duration,
+    pub idle: Duration,
+    pub rows_written: u64,
+    // tables it inserted, updated or deleted rows of
+    pub tables: Vec<TableReference>,
+    // the oldest open transaction, VACUUM leaves the tables an open transaction wrote alone
+    // since rolling it back needs the tuples it deleted
+    pub horizon_blocker: bool,
+}
+
+#[derive(Debug)]
+struct ActiveTransaction {
+    state: TransactionState,
+    started: Instant,
+    last_active: Instant,
+    rows_written: u64,
+    tables: BTreeSet<TableReference>,
+}
+
+#[derive(Debug)]
+pub struct TransactionManager {
+    clock: ClockRef,
+    txn_ids: TransactionIdSourceRef,
+    // transactions of sessions from their first statement until they committed or were
+    // rolled back, a statement run on its own is not tracked
+    active: Mutex<BTreeMap<TransactionId, ActiveTransaction>>,
+    // highest id handed out, later ids have not begun
+    latest_txn_id: AtomicU64,
+    // versions of the rows committed transactions changed, read by snapshots
+    history: Mutex<VersionHistory>,
+}
 
 impl TransactionManager {
+    pub fn new(clock: ClockRef, txn_ids: TransactionIdSourceRef) -> Self {
+        Self {
+            clock,
+            txn_ids,
+            active: Mutex::new(BTreeMap::new()),
+            latest_txn_id: AtomicU64::new(INVALID_TRANSACTION_ID),
+            history: Mutex::new(VersionHistory::default()),
+        }
+    }
+
     pub fn begin(&self, _isolation_level: IsolationLevel) -> Transaction {
-        todo!()
+        let txn = Transaction {
+            txn_id: self.txn_ids.next_txn_id(),
+            start_time: self.clock.now(),
+        };
+        self.latest_txn_id.fetch_max(txn.txn_id, Ordering::SeqCst);
+        txn
+    }
+
+    /// [`TransactionManager::begin`] of a transaction spanning statements, listed by
+    /// [`TransactionManager::transactions`] until [`TransactionManager::end`].
+    pub fn begin_tracked(&self, isolation_level: IsolationLevel) -> Transaction {
+        let txn = self.begin(isolation_level);
+        let now = Instant::now();
+        self.active.lock().unwrap().insert(
+            txn.txn_id,
+            ActiveTransaction {
+                state: TransactionState::Running,
+                started: now,
+                last_active: now,
+                rows_written: 0,
+                tables: BTreeSet::new(),
+            },
+        );
+        txn
+    }
+
+    /// Fails a transaction idle for longer than `idle_timeout` with
+    /// [`BustubxError::IdleTransactionTimeout`] and aborts it, so does every later statement
+    /// of it. A zero timeout never aborts.
+    pub fn check_idle(&self, txn_id: TransactionId, idle_timeout: Duration) -> BustubxResult<()> {
+        let mut active = self.active.lock().unwrap();
+        let Some(txn) = active.get_mut(&txn_id) else {
+            return Ok(());
+        };
+        if txn.state == TransactionState::Running
+            && !idle_timeout.is_zero()
+            && txn.last_active.elapsed() > idle_timeout
+        {
+            warn!(
+                "transaction {} aborted after being idle for {:?}",
+                txn_id,
+                txn.last_active.elapsed()
+            );
+            txn.state = TransactionState::Aborted;
+        }
+        if txn.state == TransactionState::Aborted {
+            return Err(BustubxError::IdleTransactionTimeout {
+                txn_id,
+                timeout: idle_timeout,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count a finished statement of the transaction, which wrote `rows_written` rows of
+    /// `tables`.
+    pub fn statement_done(
+        &self,
+        txn_id: TransactionId,
+        rows_written: u64,
+        tables: impl IntoIterator<Item = TableReference>,
+    ) {
+        if let Some(txn) = self.active.lock().unwrap().get_mut(&txn_id) {
+            txn.last_active = Instant::now();
+            txn.rows_written += rows_written;
+            txn.tables
+                .extend(tables.into_iter().map(|table| lock_key(&table)));
+        }
+    }
+
+    /// The transaction committed or was rolled back.
+    pub fn end(&self, txn_id: TransactionId) {
+        self.active.lock().unwrap().remove(&txn_id);
+    }
+
+    /// Open transactions by id, the oldest one is the horizon blocker.
+    pub fn transactions(&self) -> Vec<TransactionInfo> {
+        let active = self.active.lock().unwrap();
+        let oldest = active
+            .iter()
+            .min_by_key(|(txn_id, txn)| (txn.started, **txn_id))
+            .map(|(txn_id, _)| *txn_id);
+        active
+            .iter()
+            .map(|(txn_id, txn)| TransactionInfo {
+                txn_id: *txn_id,
+                state: txn.state,
+                age: txn.started.elapsed(),
+                idle: txn.last_active.elapsed(),
+                rows_written: txn.rows_written,
+                tables: txn.tables.iter().cloned().collect(),
+                horizon_blocker: oldest == Some(*txn_id),
+            })
+            .collect()
+    }
+
+    /// The oldest open transaction.
+    pub fn horizon(&self) -> Option<TransactionInfo> {
+        self.transactions()
+            .into_iter()
+            .find(|txn| txn.horizon_blocker)
+    }
+
+    /// Open transaction that wrote rows of the table, whose dead tuples VACUUM must keep.
+    pub fn writer_of(&self, table: &TableReference) -> Option<TransactionId> {
+        let table = lock_key(table);
+        self.active
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, txn)| txn.tables.contains(&table))
+            .map(|(txn_id, _)| *txn_id)
+    }
+
+    /// The transaction committed the row changes of `changes`, the versions they replaced
+    /// stay readable by snapshots until VACUUM reclaims them.
+    pub fn commit_changes(&self, txn_id: TransactionId, changes: &[UndoRecord]) {
+        self.history().commit(txn_id, changes);
+    }
+
+    /// Snapshot of the rows right after transaction `txn_id` committed, or right after the
+    /// last commit before it when it changed no rows or was rolled back. History is kept
+    /// since the database was opened, ids of an earlier open name other transactions.
+    pub fn snapshot(self: &Arc<Self>, txn_id: TransactionId) -> BustubxResult<Snapshot> {
+        if txn_id > self.latest_txn_id.load(Ordering::SeqCst)
+            || self.active.lock().unwrap().contains_key(&txn_id)
+        {
+            return Err(BustubxError::Execution(format!(
+                "transaction {} has not committed",
+                txn_id
+            )));
+        }
+        let commit_seq = self.history().commit_seq(txn_id);
+        Ok(Snapshot::new(txn_id, commit_seq, self.clone()))
+    }
+
+    /// Drop the history of the table, snapshots of commits before now fail to read it.
+    /// Called when its deleted tuples were reclaimed or it changed outside a transaction.
+    pub fn forget_history(&self, table: &TableReference) {
+        self.history().forget(table);
+    }
+
+    /// Id of the latest transaction that committed changed rows.
+    pub fn last_commit(&self) -> Option<TransactionId> {
+        self.history().last_commit()
+    }
+
+    pub(crate) fn history(&self) -> MutexGuard<'_, VersionHistory> {
+        self.history.lock().unwrap()
     }
 
     pub fn commit(&self, _txn: Transaction) -> bool {
@@ -21,3 +230,25 @@ impl TransactionManager {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::common::MockClock;
+    use crate::transaction::{IsolationLevel, SequentialTransactionIds};
+    use crate::{Database, DatabaseOptions};
+    use std::sync::Arc;
+
+    #[test]
+    pub fn test_transaction_manager_injected_sources() {
+        let clock = Arc::new(MockClock::new(500));
+        let mut db =
+            Database::new_temp_with_clock(DatabaseOptions::default(), clock.clone()).unwrap();
+        db.set_txn_id_source(Arc::new(SequentialTransactionIds::new(42)));
+
+        let txn1 = db.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        clock.advance(5);
+        let txn2 = db.txn_manager.begin(IsolationLevel::SnapshotIsolation);
+        assert_eq!((txn1.txn_id, txn1.start_time), (42, 500));
+        assert_eq!((txn2.txn_id, txn2.start_time), (43, 505));
+    }
+}
@@ -1,11 +1,15 @@
 use bustubx_sqllogictest::BustubxDB;
 use std::path::{Path, PathBuf};
 
+// Every file runs against a fresh database, a failing file does not stop the others.
+// Failures are reported together with the file and line of the failing record.
 #[test]
 fn sqllogictest() {
-    let test_files = read_dir_recursive("slt/");
+    let mut test_files = read_dir_recursive("slt/");
+    test_files.sort();
     println!("test_files: {:?}", test_files);
 
+    let mut failures = vec![];
     for file in test_files {
         let db = BustubxDB::new();
         let mut tester = sqllogictest::Runner::new(db);
@@ -13,8 +17,16 @@ fn sqllogictest() {
             "======== start to run file {} ========",
             file.to_str().unwrap()
         );
-        tester.run_file(file).unwrap();
+        if let Err(e) = tester.run_file(&file) {
+            failures.push(format!("{}: {e}", file.display()));
+        }
     }
+    assert!(
+        failures.is_empty(),
+        "{} slt file(s) failed:\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
 }
 
 fn read_dir_recursive<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {